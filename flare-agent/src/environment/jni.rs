@@ -1,8 +1,8 @@
 use super::super::native::{JavaObject, JNIEnvPtr};
 use super::super::class::ClassId;
-use native::jvmti_native::{jclass, jmethodID, jobject};
-use std::ffi::CString;
-use native::{JavaMethod, JavaClass, JavaThread, JavaLong};
+use native::jvmti_native::{jclass, jmethodID, jobject, jstring, jarray, jobjectArray, jboolean};
+use std::ffi::{CString, CStr};
+use native::{JavaMethod, JavaClass, JavaThread, JavaLong, JavaInt};
 
 ///
 /// `JNI` defines a set of operatations the JVM offers through it's JNI interface.
@@ -18,9 +18,32 @@ pub trait JNI {
 
     fn call_long_method(&self, obj: jobject, method_id: JavaMethod) -> JavaLong;
 
+    fn call_long_method_with_long(&self, obj: jobject, method_id: JavaMethod, arg: JavaLong) -> JavaLong;
+
+    fn call_int_method(&self, obj: jobject, method_id: JavaMethod) -> JavaInt;
+
+    fn call_object_method(&self, obj: jobject, method_id: JavaMethod) -> jobject;
+
+    fn call_object_method_with_bools(&self, obj: jobject, method_id: JavaMethod, arg1: bool, arg2: bool) -> jobject;
+
+    fn get_array_length(&self, array: jarray) -> JavaInt;
+
+    fn get_object_array_element(&self, array: jobjectArray, index: JavaInt) -> jobject;
+
+    fn get_static_method_id(&self, clazz: JavaClass, method_name: &str, method_sig: &str) -> JavaMethod;
+
+    fn call_static_object_method(&self, clazz: JavaClass, method_id: JavaMethod) -> jobject;
+
+    /// Clear the pending Java exception, return true if there was one.
+    fn exception_clear(&self) -> bool;
+
+    fn new_global_ref(&self, obj: jobject) -> jobject;
+
     fn delete_local_ref(&self, obj: jobject);
 
     fn delete_global_ref(&self, obj: jobject);
+
+    fn get_string_utf_chars(&self, str: jstring) -> String;
 }
 
 ///
@@ -76,6 +99,73 @@ impl JNI for JNIEnvironment {
         }
     }
 
+    fn call_long_method_with_long(&self, obj: jobject, method_id: JavaMethod, arg: JavaLong) -> JavaLong {
+        unsafe {
+            (**self.jni).CallLongMethod.unwrap()(self.jni, obj, method_id, arg)
+        }
+    }
+
+    fn call_int_method(&self, obj: jobject, method_id: JavaMethod) -> JavaInt {
+        unsafe {
+            (**self.jni).CallIntMethod.unwrap()(self.jni, obj, method_id)
+        }
+    }
+
+    fn call_object_method(&self, obj: jobject, method_id: JavaMethod) -> jobject {
+        unsafe {
+            (**self.jni).CallObjectMethod.unwrap()(self.jni, obj, method_id)
+        }
+    }
+
+    fn call_object_method_with_bools(&self, obj: jobject, method_id: JavaMethod, arg1: bool, arg2: bool) -> jobject {
+        unsafe {
+            //可变参数中的jboolean按int传递
+            (**self.jni).CallObjectMethod.unwrap()(self.jni, obj, method_id, arg1 as jboolean as u32, arg2 as jboolean as u32)
+        }
+    }
+
+    fn get_array_length(&self, array: jarray) -> JavaInt {
+        unsafe {
+            (**self.jni).GetArrayLength.unwrap()(self.jni, array)
+        }
+    }
+
+    fn get_object_array_element(&self, array: jobjectArray, index: JavaInt) -> jobject {
+        unsafe {
+            (**self.jni).GetObjectArrayElement.unwrap()(self.jni, array, index)
+        }
+    }
+
+    fn get_static_method_id(&self, clazz: JavaClass, method_name: &str, method_sig: &str) -> JavaMethod {
+        unsafe {
+            let method_name = CString::new(method_name.to_string()).expect("CString::new failed");
+            let method_sig = CString::new(method_sig.to_string()).expect("CString::new failed");
+            (**self.jni).GetStaticMethodID.unwrap()(self.jni, clazz, method_name.as_ptr() as *const i8, method_sig.as_ptr() as *const i8)
+        }
+    }
+
+    fn call_static_object_method(&self, clazz: JavaClass, method_id: JavaMethod) -> jobject {
+        unsafe {
+            (**self.jni).CallStaticObjectMethod.unwrap()(self.jni, clazz, method_id)
+        }
+    }
+
+    fn exception_clear(&self) -> bool {
+        unsafe {
+            if (**self.jni).ExceptionCheck.unwrap()(self.jni) != 0 {
+                (**self.jni).ExceptionClear.unwrap()(self.jni);
+                return true;
+            }
+            false
+        }
+    }
+
+    fn new_global_ref(&self, obj: jobject) -> jobject {
+        unsafe {
+            (**self.jni).NewGlobalRef.unwrap()(self.jni, obj)
+        }
+    }
+
     fn delete_local_ref(&self, obj: jobject) {
         unsafe {
             (**self.jni).DeleteLocalRef.unwrap()(self.jni, obj);
@@ -87,4 +177,19 @@ impl JNI for JNIEnvironment {
             (**self.jni).DeleteGlobalRef.unwrap()(self.jni, obj);
         }
     }
+
+    fn get_string_utf_chars(&self, str: jstring) -> String {
+        if str.is_null() {
+            return "".to_string();
+        }
+        unsafe {
+            let chars = (**self.jni).GetStringUTFChars.unwrap()(self.jni, str, std::ptr::null_mut());
+            if chars.is_null() {
+                return "".to_string();
+            }
+            let value = CStr::from_ptr(chars).to_string_lossy().into_owned();
+            (**self.jni).ReleaseStringUTFChars.unwrap()(self.jni, str, chars);
+            value
+        }
+    }
 }
@@ -1,175 +1,379 @@
-use self::jvmti::{JVMTI, JVMTIEnvironment};
-use self::jni::{JNI, JNIEnvironment};
-use super::capabilities::Capabilities;
-use super::class::{ClassId, ClassSignature};
-use super::error::NativeError;
-use super::event::{EventCallbacks, VMEvent};
-use super::mem::MemoryAllocation;
-use super::method::{MethodId, MethodSignature};
-use super::native::{JavaObject, JavaThread};
-use super::thread::Thread;
-use super::version::VersionNumber;
-use native::{JavaClass, JavaMethod, JavaLong, JNIEnvPtr, JavaInt};
-use thread::ThreadId;
-use native::jvmti_native::{jvmtiTimerInfo, jobject, jvmtiStackInfo};
-use std::cell::Cell;
-use std::ptr;
-use environment::jvmti::{ThreadInfo, JavaStackTrace, JavaStackFrame};
-
-pub mod jni;
-pub mod jvm;
-pub mod jvmti;
-
-/// `Environment` combines the functionality of both `JNI` and `JVMTI` by wrapping an instance of
-/// both and delegating the method calls to their corresponding recipients.
-pub struct Environment {
-    jvmti: Box<JVMTI>,
-    jni: Box<JNI>,
-    thread_get_id_method: Cell<Option<JavaMethod>>
-}
-
-impl Environment {
-
-//    pub fn new(jvmti: JVMTIEnvironment, jni: JNIEnvironment) -> Environment {
-//        Environment { jvmti: Box::new(jvmti), jni: Box::new(jni ), thread_get_id_method: Cell::new(None) }
-//    }
-
-    pub fn new(jvmti: Box<JVMTI>, jni: Box<JNI>) -> Environment {
-        Environment { jvmti: jvmti, jni: jni, thread_get_id_method: Cell::new(None) }
-    }
-
-    pub fn get_thread_id(&self, thread_id: &JavaThread) -> JavaLong {
-        //get actual java thread id
-        match self.thread_get_id_method.get() {
-            Some(method_id) => {
-                self.call_long_method(thread_id.clone(), method_id)
-            },
-            None => {
-                println!("find class: java/lang/Thread");
-                let thread_class = self.jni.find_class("java/lang/Thread");
-                let get_id_method = self.jni.get_method_id(thread_class.native_id, "getId", "()J");
-                self.thread_get_id_method.set(Some(get_id_method.clone()));
-                self.call_long_method(thread_id.clone(), get_id_method)
-            },
-        }
-    }
-
-    pub fn get_thread_cpu_time_ex(&self, thread_id: JavaLong) -> i64 {
-//        let classid_management_factory = self.jni.find_class("java/lang/management/ManagementFactory");
-//        let method_getThreadMXBean = self.jni.get_method_id(classid_management_factory.native_id, "getThreadMXBean", "()J");
-        unimplemented!()
-    }
-
-    //JNI methods
-    pub fn get_object_class(&self, object_id: &JavaObject) -> ClassId {
-        self.jni.get_object_class(object_id)
-    }
-
-    pub fn find_class(&self, class_name: &str) -> ClassId {
-        self.jni.find_class(class_name)
-    }
-
-    pub fn get_method_id(&self, clazz: JavaClass, method_name: &str, method_sig: &str) -> JavaMethod {
-        self.jni.get_method_id(clazz, method_name, method_sig)
-    }
-
-    pub fn call_long_method(&self, obj: jobject, method_id: JavaMethod) -> JavaLong {
-        self.jni.call_long_method(obj, method_id)
-    }
-
-    pub fn delete_local_ref(&self, obj: jobject) {
-        self.jni.delete_local_ref(obj);
-    }
-
-    pub fn delete_global_ref(&self, obj: jobject) {
-        self.jni.delete_global_ref(obj);
-    }
-
-    //JVMTI methods
-    pub fn get_version_number(&self) -> VersionNumber {
-        self.jvmti.get_version_number()
-    }
-
-    pub fn add_capabilities(&mut self, new_capabilities: &Capabilities) -> Result<Capabilities, NativeError> {
-        self.jvmti.add_capabilities(new_capabilities)
-    }
-
-    pub fn get_capabilities(&self) -> Capabilities {
-        self.jvmti.get_capabilities()
-    }
-
-    pub fn get_potential_capabilities(&self) -> Capabilities {
-        self.jvmti.get_potential_capabilities()
-    }
-
-    pub fn set_event_callbacks(&mut self, callbacks: EventCallbacks) -> Option<NativeError> {
-        self.jvmti.set_event_callbacks(callbacks)
-    }
-
-    pub fn set_event_notification_mode(&mut self, event: VMEvent, mode: bool) -> Option<NativeError> {
-        self.jvmti.set_event_notification_mode(event, mode)
-    }
-
-    pub fn get_thread_info(&self, thread_id: &JavaThread) -> Result<Thread, NativeError> {
-        self.jvmti.get_thread_info(&self.jni, thread_id)
-    }
-
-    pub fn get_thread_info_ex(&self, thread_id: &JavaThread) -> Result<ThreadInfo, NativeError> {
-        let java_thread_id = self.get_thread_id(&thread_id);
-        let mut thread = self.jvmti.get_thread_info(&self.jni, thread_id).unwrap();
-        let thread_info = ThreadInfo{
-            thread_id: java_thread_id,
-            name: thread.name.clone(),
-            priority: thread.priority,
-            is_daemon: thread.is_daemon,
-            cpu_time: 0
-        };
-        //release jni local ref ?
-        self.delete_local_ref(thread.thread_group);
-        self.delete_local_ref(thread.context_class_loader);
-        Ok(thread_info)
-    }
-
-    pub fn get_method_declaring_class(&self, method_id: &MethodId) -> Result<ClassId, NativeError> {
-        self.jvmti.get_method_declaring_class(method_id)
-    }
-
-    pub fn get_method_name(&self, method_id: &MethodId) -> Result<MethodSignature, NativeError> {
-        self.jvmti.get_method_name(method_id)
-    }
-
-    pub fn get_class_signature(&self, class_id: &ClassId) -> Result<ClassSignature, NativeError> {
-        self.jvmti.get_class_signature(class_id)
-    }
-
-    pub fn allocate(&self, len: usize) -> Result<MemoryAllocation, NativeError> {
-        self.jvmti.allocate(len)
-    }
-
-    pub fn deallocate(&self, ptr: *mut i8) {
-        self.jvmti.deallocate(ptr)
-    }
-
-    pub fn get_all_stacktraces(&self) -> Result<Vec<JavaStackTrace>, NativeError> {
-        self.jvmti.get_all_stacktraces(self)
-    }
-
-    pub fn get_all_threads(&self) -> Result<Vec<ThreadId>, NativeError> {
-        self.jvmti.get_all_threads()
-    }
-
-    pub fn get_thread_cpu_time(&self, thread_id: &JavaThread) -> Result<JavaLong, NativeError> {
-        self.jvmti.get_thread_cpu_time(thread_id)
-    }
-
-    pub fn get_thread_cpu_timer_info(&self) -> Result<jvmtiTimerInfo, NativeError> {
-        self.jvmti.get_thread_cpu_timer_info()
-    }
-
-    pub fn get_stack_trace(&self, thread_id: &JavaThread) -> Result<Vec<JavaStackFrame>, NativeError> {
-        self.jvmti.get_stack_trace(thread_id)
-    }
-
-}
-
-
+use self::jvmti::{JVMTI, JVMTIEnvironment};
+use self::jni::{JNI, JNIEnvironment};
+use super::capabilities::Capabilities;
+use super::class::{ClassId, ClassSignature};
+use super::error::NativeError;
+use super::event::{EventCallbacks, VMEvent};
+use super::mem::MemoryAllocation;
+use super::method::{MethodId, MethodSignature};
+use super::native::{JavaObject, JavaThread};
+use super::thread::Thread;
+use super::version::VersionNumber;
+use native::{JavaClass, JavaMethod, JavaLong, JNIEnvPtr, JavaInt};
+use thread::ThreadId;
+use native::jvmti_native::{jvmtiTimerInfo, jobject, jvmtiStackInfo};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ptr;
+use environment::jvmti::{ThreadInfo, JavaStackTrace, JavaStackFrame};
+
+pub mod jni;
+pub mod jvm;
+pub mod jvmti;
+
+/// `Environment` combines the functionality of both `JNI` and `JVMTI` by wrapping an instance of
+/// both and delegating the method calls to their corresponding recipients.
+pub struct Environment {
+    jvmti: Box<JVMTI>,
+    jni: Box<JNI>,
+    thread_get_id_method: Cell<Option<JavaMethod>>,
+    //com.sun.management.ThreadMXBean(全局引用)及getThreadAllocatedBytes方法，Some(null)表示不支持
+    thread_allocated_bytes_method: Cell<Option<(jobject, JavaMethod)>>,
+    //java.lang.management.MemoryMXBean及getObjectPendingFinalizationCount方法
+    pending_finalization_method: Cell<Option<(jobject, JavaMethod)>>
+}
+
+impl Environment {
+
+//    pub fn new(jvmti: JVMTIEnvironment, jni: JNIEnvironment) -> Environment {
+//        Environment { jvmti: Box::new(jvmti), jni: Box::new(jni ), thread_get_id_method: Cell::new(None) }
+//    }
+
+    pub fn new(jvmti: Box<JVMTI>, jni: Box<JNI>) -> Environment {
+        Environment { jvmti: jvmti, jni: jni, thread_get_id_method: Cell::new(None), thread_allocated_bytes_method: Cell::new(None), pending_finalization_method: Cell::new(None) }
+    }
+
+    pub fn get_thread_id(&self, thread_id: &JavaThread) -> JavaLong {
+        //get actual java thread id
+        match self.thread_get_id_method.get() {
+            Some(method_id) => {
+                self.call_long_method(thread_id.clone(), method_id)
+            },
+            None => {
+                println!("find class: java/lang/Thread");
+                let thread_class = self.jni.find_class("java/lang/Thread");
+                let get_id_method = self.jni.get_method_id(thread_class.native_id, "getId", "()J");
+                self.thread_get_id_method.set(Some(get_id_method.clone()));
+                self.call_long_method(thread_id.clone(), get_id_method)
+            },
+        }
+    }
+
+    //线程累计分配的字节数(HotSpot的com.sun.management.ThreadMXBean)，不支持或线程已结束时返回None
+    pub fn get_thread_allocated_bytes(&self, thread_id: JavaLong) -> Option<JavaLong> {
+        let (thread_mxbean, method_id) = match self.thread_allocated_bytes_method.get() {
+            Some(x) => x,
+            None => {
+                let x = self.find_mxbean_method("getThreadMXBean", "java/lang/management/ThreadMXBean", "com/sun/management/ThreadMXBean", "getThreadAllocatedBytes", "(J)J")
+                    .unwrap_or((ptr::null_mut(), ptr::null_mut()));
+                self.thread_allocated_bytes_method.set(Some(x));
+                x
+            }
+        };
+        if thread_mxbean.is_null() {
+            return None;
+        }
+        let bytes = self.jni.call_long_method_with_long(thread_mxbean, method_id, thread_id);
+        if self.jni.exception_clear() || bytes < 0 {
+            return None;
+        }
+        Some(bytes)
+    }
+
+    //等待执行finalize()的对象数量(MemoryMXBean.getObjectPendingFinalizationCount)
+    pub fn get_pending_finalization_count(&self) -> Option<JavaInt> {
+        let (memory_mxbean, method_id) = match self.pending_finalization_method.get() {
+            Some(x) => x,
+            None => {
+                let x = self.find_mxbean_method("getMemoryMXBean", "java/lang/management/MemoryMXBean", "java/lang/management/MemoryMXBean", "getObjectPendingFinalizationCount", "()I")
+                    .unwrap_or((ptr::null_mut(), ptr::null_mut()));
+                self.pending_finalization_method.set(Some(x));
+                x
+            }
+        };
+        if memory_mxbean.is_null() {
+            return None;
+        }
+        let count = self.jni.call_int_method(memory_mxbean, method_id);
+        if self.jni.exception_clear() {
+            return None;
+        }
+        Some(count)
+    }
+
+    //各个线程持有的ownable synchronizer(java.util.concurrent的锁): 线程id => [(类名, identity hash)]
+    //  JVMTI的监视器信息不包含这类锁，通过 ThreadMXBean.dumpAllThreads(false, true) 获取，会暂停所有线程(safepoint)
+    pub fn get_locked_synchronizers(&self) -> Option<HashMap<JavaLong, Vec<(String, JavaInt)>>> {
+        let (thread_mxbean, dump_method) = self.find_mxbean_method("getThreadMXBean", "java/lang/management/ThreadMXBean", "java/lang/management/ThreadMXBean",
+                                                                   "dumpAllThreads", "(ZZ)[Ljava/lang/management/ThreadInfo;")?;
+        let thread_infos = self.jni.call_object_method_with_bools(thread_mxbean, dump_method, false, true);
+        self.jni.delete_global_ref(thread_mxbean);
+        if self.jni.exception_clear() || thread_infos.is_null() {
+            return None;
+        }
+        let result = self.read_locked_synchronizers(thread_infos);
+        self.jni.delete_local_ref(thread_infos);
+        result
+    }
+
+    fn read_locked_synchronizers(&self, thread_infos: jobject) -> Option<HashMap<JavaLong, Vec<(String, JavaInt)>>> {
+        let thread_info_class = self.jni.find_class("java/lang/management/ThreadInfo");
+        let lock_info_class = self.jni.find_class("java/lang/management/LockInfo");
+        if self.jni.exception_clear() || thread_info_class.native_id.is_null() || lock_info_class.native_id.is_null() {
+            return None;
+        }
+        let get_thread_id = self.jni.get_method_id(thread_info_class.native_id, "getThreadId", "()J");
+        let get_synchronizers = self.jni.get_method_id(thread_info_class.native_id, "getLockedSynchronizers", "()[Ljava/lang/management/LockInfo;");
+        let get_class_name = self.jni.get_method_id(lock_info_class.native_id, "getClassName", "()Ljava/lang/String;");
+        let get_hash_code = self.jni.get_method_id(lock_info_class.native_id, "getIdentityHashCode", "()I");
+        self.jni.delete_local_ref(thread_info_class.native_id);
+        self.jni.delete_local_ref(lock_info_class.native_id);
+        if self.jni.exception_clear() {
+            return None;
+        }
+
+        let mut result = HashMap::new();
+        for i in 0..self.jni.get_array_length(thread_infos) {
+            //已结束的线程为null
+            let thread_info = self.jni.get_object_array_element(thread_infos, i);
+            if thread_info.is_null() {
+                continue;
+            }
+            let thread_id = self.jni.call_long_method(thread_info, get_thread_id);
+            let locks = self.jni.call_object_method(thread_info, get_synchronizers);
+            if !locks.is_null() {
+                let mut synchronizers = vec![];
+                for j in 0..self.jni.get_array_length(locks) {
+                    let lock = self.jni.get_object_array_element(locks, j);
+                    let class_name = self.jni.call_object_method(lock, get_class_name);
+                    synchronizers.push((self.jni.get_string_utf_chars(class_name), self.jni.call_int_method(lock, get_hash_code)));
+                    self.jni.delete_local_ref(class_name);
+                    self.jni.delete_local_ref(lock);
+                }
+                if !synchronizers.is_empty() {
+                    result.insert(thread_id, synchronizers);
+                }
+                self.jni.delete_local_ref(locks);
+            }
+            self.jni.delete_local_ref(thread_info);
+            if self.jni.exception_clear() {
+                return None;
+            }
+        }
+        Some(result)
+    }
+
+    //ManagementFactory.<getter>()返回的MXBean(全局引用)及其方法，bean_class 为声明方法的接口
+    fn find_mxbean_method(&self, getter: &str, getter_class: &str, bean_class: &str, method_name: &str, method_sig: &str) -> Option<(jobject, JavaMethod)> {
+        let factory_class = self.jni.find_class("java/lang/management/ManagementFactory");
+        if self.jni.exception_clear() || factory_class.native_id.is_null() {
+            return None;
+        }
+        let getter_sig = format!("()L{};", getter_class);
+        let get_bean_method = self.jni.get_static_method_id(factory_class.native_id, getter, &getter_sig);
+        if self.jni.exception_clear() || get_bean_method.is_null() {
+            self.jni.delete_local_ref(factory_class.native_id);
+            return None;
+        }
+        let mxbean = self.jni.call_static_object_method(factory_class.native_id, get_bean_method);
+        self.jni.delete_local_ref(factory_class.native_id);
+        if self.jni.exception_clear() || mxbean.is_null() {
+            return None;
+        }
+        let class_id = self.jni.find_class(bean_class);
+        if self.jni.exception_clear() || class_id.native_id.is_null() {
+            println!("{} is not available, {} is disabled", bean_class, method_name);
+            self.jni.delete_local_ref(mxbean);
+            return None;
+        }
+        let method_id = self.jni.get_method_id(class_id.native_id, method_name, method_sig);
+        self.jni.delete_local_ref(class_id.native_id);
+        if self.jni.exception_clear() || method_id.is_null() {
+            self.jni.delete_local_ref(mxbean);
+            return None;
+        }
+        let global_ref = self.jni.new_global_ref(mxbean);
+        self.jni.delete_local_ref(mxbean);
+        Some((global_ref, method_id))
+    }
+
+    pub fn get_thread_cpu_time_ex(&self, thread_id: JavaLong) -> i64 {
+//        let classid_management_factory = self.jni.find_class("java/lang/management/ManagementFactory");
+//        let method_getThreadMXBean = self.jni.get_method_id(classid_management_factory.native_id, "getThreadMXBean", "()J");
+        unimplemented!()
+    }
+
+    //JNI methods
+    pub fn get_object_class(&self, object_id: &JavaObject) -> ClassId {
+        self.jni.get_object_class(object_id)
+    }
+
+    pub fn find_class(&self, class_name: &str) -> ClassId {
+        self.jni.find_class(class_name)
+    }
+
+    pub fn get_method_id(&self, clazz: JavaClass, method_name: &str, method_sig: &str) -> JavaMethod {
+        self.jni.get_method_id(clazz, method_name, method_sig)
+    }
+
+    pub fn call_long_method(&self, obj: jobject, method_id: JavaMethod) -> JavaLong {
+        self.jni.call_long_method(obj, method_id)
+    }
+
+    pub fn delete_local_ref(&self, obj: jobject) {
+        self.jni.delete_local_ref(obj);
+    }
+
+    pub fn delete_global_ref(&self, obj: jobject) {
+        self.jni.delete_global_ref(obj);
+    }
+
+    //JVMTI methods
+    pub fn get_version_number(&self) -> VersionNumber {
+        self.jvmti.get_version_number()
+    }
+
+    pub fn add_capabilities(&mut self, new_capabilities: &Capabilities) -> Result<Capabilities, NativeError> {
+        self.jvmti.add_capabilities(new_capabilities)
+    }
+
+    pub fn get_capabilities(&self) -> Capabilities {
+        self.jvmti.get_capabilities()
+    }
+
+    pub fn get_potential_capabilities(&self) -> Capabilities {
+        self.jvmti.get_potential_capabilities()
+    }
+
+    pub fn set_event_callbacks(&mut self, callbacks: EventCallbacks) -> Option<NativeError> {
+        self.jvmti.set_event_callbacks(callbacks)
+    }
+
+    pub fn set_event_notification_mode(&mut self, event: VMEvent, mode: bool) -> Option<NativeError> {
+        self.jvmti.set_event_notification_mode(event, mode)
+    }
+
+    pub fn get_thread_info(&self, thread_id: &JavaThread) -> Result<Thread, NativeError> {
+        self.jvmti.get_thread_info(&self.jni, thread_id)
+    }
+
+    pub fn get_thread_info_ex(&self, thread_id: &JavaThread) -> Result<ThreadInfo, NativeError> {
+        let java_thread_id = self.get_thread_id(&thread_id);
+        let mut thread = self.jvmti.get_thread_info(&self.jni, thread_id).unwrap();
+        let thread_info = ThreadInfo{
+            thread_id: java_thread_id,
+            name: thread.name.clone(),
+            priority: thread.priority,
+            is_daemon: thread.is_daemon,
+            cpu_time: 0,
+            start_time: ::profile::clock::now_millis()
+        };
+        //release jni local ref ?
+        self.delete_local_ref(thread.thread_group);
+        self.delete_local_ref(thread.context_class_loader);
+        Ok(thread_info)
+    }
+
+    pub fn get_method_declaring_class(&self, method_id: &MethodId) -> Result<ClassId, NativeError> {
+        self.jvmti.get_method_declaring_class(method_id)
+    }
+
+    pub fn get_method_name(&self, method_id: &MethodId) -> Result<MethodSignature, NativeError> {
+        self.jvmti.get_method_name(method_id)
+    }
+
+    pub fn get_class_signature(&self, class_id: &ClassId) -> Result<ClassSignature, NativeError> {
+        self.jvmti.get_class_signature(class_id)
+    }
+
+    pub fn get_method_name_bytes(&self, method_id: &MethodId) -> Result<Vec<u8>, NativeError> {
+        self.jvmti.get_method_name_bytes(method_id)
+    }
+
+    pub fn get_class_name_bytes(&self, class_id: &ClassId) -> Result<Vec<u8>, NativeError> {
+        self.jvmti.get_class_name_bytes(class_id)
+    }
+
+    pub fn allocate(&self, len: usize) -> Result<MemoryAllocation, NativeError> {
+        self.jvmti.allocate(len)
+    }
+
+    pub fn deallocate(&self, ptr: *mut i8) {
+        self.jvmti.deallocate(ptr)
+    }
+
+    pub fn get_all_stacktraces(&self) -> Result<Vec<JavaStackTrace>, NativeError> {
+        self.jvmti.get_all_stacktraces(self)
+    }
+
+    pub fn get_all_threads(&self) -> Result<Vec<ThreadId>, NativeError> {
+        self.jvmti.get_all_threads()
+    }
+
+    pub fn get_thread_cpu_time(&self, thread_id: &JavaThread) -> Result<JavaLong, NativeError> {
+        self.jvmti.get_thread_cpu_time(thread_id)
+    }
+
+    pub fn get_thread_cpu_timer_info(&self) -> Result<jvmtiTimerInfo, NativeError> {
+        self.jvmti.get_thread_cpu_timer_info()
+    }
+
+    pub fn get_stack_trace(&self, thread_id: &JavaThread) -> Result<Vec<JavaStackFrame>, NativeError> {
+        self.jvmti.get_stack_trace(thread_id)
+    }
+
+    pub fn get_current_contended_monitor(&self, thread_id: &JavaThread) -> Result<Option<JavaObject>, NativeError> {
+        self.jvmti.get_current_contended_monitor(thread_id)
+    }
+
+    pub fn get_monitor_owner(&self, object: &JavaObject) -> Result<Option<JavaThread>, NativeError> {
+        self.jvmti.get_monitor_owner(object)
+    }
+
+    pub fn get_owned_monitors(&self, thread_id: &JavaThread) -> Result<Vec<JavaObject>, NativeError> {
+        self.jvmti.get_owned_monitors(thread_id)
+    }
+
+    pub fn get_owned_monitor_stack_depth_info(&self, thread_id: &JavaThread) -> Result<Vec<(JavaObject, JavaInt)>, NativeError> {
+        self.jvmti.get_owned_monitor_stack_depth_info(thread_id)
+    }
+
+    pub fn get_thread_state(&self, thread_id: &JavaThread) -> Result<u32, NativeError> {
+        self.jvmti.get_thread_state(thread_id)
+    }
+
+    pub fn get_loaded_classes(&self) -> Result<Vec<JavaClass>, NativeError> {
+        self.jvmti.get_loaded_classes()
+    }
+
+    pub fn get_class_loader(&self, class: &JavaClass) -> Result<Option<JavaObject>, NativeError> {
+        self.jvmti.get_class_loader(class)
+    }
+
+    pub fn get_object_hash_code(&self, object: &JavaObject) -> Result<JavaInt, NativeError> {
+        self.jvmti.get_object_hash_code(object)
+    }
+
+    pub fn set_tag(&self, object: &JavaObject, tag: JavaLong) -> Result<(), NativeError> {
+        self.jvmti.set_tag(object, tag)
+    }
+
+    pub fn get_tag(&self, object: &JavaObject) -> Result<JavaLong, NativeError> {
+        self.jvmti.get_tag(object)
+    }
+
+    pub fn force_garbage_collection(&self) -> Result<(), NativeError> {
+        self.jvmti.force_garbage_collection()
+    }
+
+    pub fn set_heap_sampling_interval(&self, sampling_interval: JavaInt) -> Result<(), NativeError> {
+        self.jvmti.set_heap_sampling_interval(sampling_interval)
+    }
+
+    pub fn iterate_heap_by_class_tag(&self) -> Result<HashMap<JavaLong, (i64, i64)>, NativeError> {
+        self.jvmti.iterate_heap_by_class_tag()
+    }
+
+}
+
+
@@ -1,4 +1,6 @@
 use super::native::jvmti_native::*;
+use super::environment::Environment;
+use super::native::{JavaClass, JavaMethod, JavaThread};
 use super::runtime::*;
 use super::thread::Thread;
 
@@ -8,7 +10,8 @@ pub type FnVMInit = fn() -> ();
 pub type FnVMDeath = fn() -> ();
 pub type FnVMStart = fn() -> ();
 pub type FnVMObjectAlloc = fn(event: ObjectAllocationEvent) -> ();
-pub type FnVMObjectFree = fn() -> ();
+pub type FnVMObjectFree = fn(tag: i64) -> ();
+pub type FnSampledObjectAlloc = fn(env: &Environment, thread: JavaThread, size: i64) -> ();
 pub type FnThreadStart = fn(thread: Thread) -> ();
 pub type FnThreadEnd = fn(thread: Thread) -> ();
 pub type FnException = fn() -> ();
@@ -23,13 +26,13 @@ pub type FnGarbageCollectionStart = fn() -> ();
 pub type FnGarbageCollectionFinish = fn() -> ();
 pub type FnClassFileLoad = fn(event: ClassFileLoadEvent) -> Option<Vec<u8>>;
 pub type FnClassLoad = fn() -> ();
-pub type FnClassPrepare = fn() -> ();
+pub type FnClassPrepare = fn(env: &Environment, thread: JavaThread, class: JavaClass) -> ();
 pub type FnSingleStep = fn() -> ();
 pub type FnFramePop = fn() -> ();
 pub type FnBreakpoint = fn() -> ();
 pub type FnNativeMethodBind = fn() -> ();
-pub type FnCompiledMethodLoad = fn() -> ();
-pub type FnCompiledMethodUnload = fn() -> ();
+pub type FnCompiledMethodLoad = fn(method: JavaMethod) -> ();
+pub type FnCompiledMethodUnload = fn(method: JavaMethod) -> ();
 pub type FnDynamicCodeGenerated = fn() -> ();
 pub type FnResourceExhausted = fn() -> ();
 pub type FnDataDumpRequest = fn() -> ();
@@ -71,7 +74,8 @@ pub enum VMEvent {
     CompiledMethodUnload = JVMTI_EVENT_COMPILED_METHOD_UNLOAD as isize,
     DynamicCodeGenerated = JVMTI_EVENT_DYNAMIC_CODE_GENERATED as isize,
     DataDumpRequest = JVMTI_EVENT_DATA_DUMP_REQUEST as isize,
-    ResourceExhausted = JVMTI_EVENT_RESOURCE_EXHAUSTED as isize
+    ResourceExhausted = JVMTI_EVENT_RESOURCE_EXHAUSTED as isize,
+    SampledObjectAlloc = JVMTI_EVENT_SAMPLED_OBJECT_ALLOC as isize
 }
 
 ///
@@ -110,7 +114,8 @@ pub struct EventCallbacks {
     pub compiled_method_unload: Option<FnCompiledMethodUnload>,
     pub dynamic_code_generated: Option<FnDynamicCodeGenerated>,
     pub data_dump_request: Option<FnDataDumpRequest>,
-    pub resource_exhausted: Option<FnResourceExhausted>
+    pub resource_exhausted: Option<FnResourceExhausted>,
+    pub sampled_object_alloc: Option<FnSampledObjectAlloc>
 }
 
 impl EventCallbacks {
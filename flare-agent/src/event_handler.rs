@@ -45,7 +45,8 @@ pub static mut CALLBACK_TABLE: EventCallbacks = EventCallbacks {
     compiled_method_unload: None,
     dynamic_code_generated: None,
     data_dump_request: None,
-    resource_exhausted: None
+    resource_exhausted: None,
+    sampled_object_alloc: None
 };
 
 pub fn register_vm_init_callback(callback: Option<FnVMInit>) {
@@ -56,10 +57,26 @@ pub fn register_vm_death_callback(callback: Option<FnVMDeath>) {
     unsafe { CALLBACK_TABLE.vm_death = callback; }
 }
 
+pub fn register_compiled_method_load_callback(callback: Option<FnCompiledMethodLoad>) {
+    unsafe { CALLBACK_TABLE.compiled_method_load = callback; }
+}
+
+pub fn register_compiled_method_unload_callback(callback: Option<FnCompiledMethodUnload>) {
+    unsafe { CALLBACK_TABLE.compiled_method_unload = callback; }
+}
+
+pub fn register_class_prepare_callback(callback: Option<FnClassPrepare>) {
+    unsafe { CALLBACK_TABLE.class_prepare = callback; }
+}
+
 pub fn register_vm_object_alloc_callback(callback: Option<FnVMObjectAlloc>) {
     unsafe { CALLBACK_TABLE.vm_object_alloc = callback; }
 }
 
+pub fn register_sampled_object_alloc_callback(callback: Option<FnSampledObjectAlloc>) {
+    unsafe { CALLBACK_TABLE.sampled_object_alloc = callback; }
+}
+
 pub fn register_vm_object_free_callback(callback: Option<FnVMObjectFree>) {
     unsafe { CALLBACK_TABLE.vm_object_free = callback; }
 }
@@ -172,7 +189,9 @@ pub fn local_event_callbacks() -> jvmtiEventCallbacks {
         GarbageCollectionStart: Some(local_cb_garbage_collection_start), //jvmtiEventGarbageCollectionStart,
         GarbageCollectionFinish: Some(local_cb_garbage_collection_finish), //jvmtiEventGarbageCollectionFinish,
         ObjectFree: Some(local_cb_object_free), //jvmtiEventObjectFree,
-        VMObjectAlloc: Some(local_cb_vm_object_alloc) //jvmtiEventVMObjectAlloc,
+        VMObjectAlloc: Some(local_cb_vm_object_alloc), //jvmtiEventVMObjectAlloc,
+        reserved85: None, //jvmtiEventReserved,
+        SampledObjectAlloc: Some(local_cb_sampled_object_alloc) //jvmtiEventSampledObjectAlloc,
     }
 }
 
@@ -505,18 +524,41 @@ unsafe extern "C" fn local_cb_class_load(jvmti_env: *mut jvmtiEnv, jni_env: *mut
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_class_prepare(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, klass: jclass) -> () {
+    match CALLBACK_TABLE.class_prepare {
+        Some(function) => {
+            let env = get_env_api(jvmti_env, jni_env);
+            function(&env, thread, klass)
+        },
+        None => ()
+    }
+}
 
+#[allow(unused_variables)]
+unsafe extern "C" fn local_cb_sampled_object_alloc(jvmti_env: *mut jvmtiEnv, jni_env: *mut JNIEnv, thread: jthread, object: jobject, object_klass: jclass, size: jlong) -> () {
+    match CALLBACK_TABLE.sampled_object_alloc {
+        Some(function) => {
+            let env = get_env_api(jvmti_env, jni_env);
+            function(&env, thread, size as i64)
+        },
+        None => ()
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_compiled_method_load(jvmti_env: *mut jvmtiEnv, method: jmethodID, code_size: jint, code_addr: *const c_void, map_length: jint,
                                                    map: *const jvmtiAddrLocationMap, compile_info: *const c_void) -> () {
-
+    match CALLBACK_TABLE.compiled_method_load {
+        Some(function) => function(method),
+        None => ()
+    }
 }
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_compiled_method_unload(jvmti_env: *mut jvmtiEnv, method: jmethodID, code_addr: *const c_void) -> () {
-
+    match CALLBACK_TABLE.compiled_method_unload {
+        Some(function) => function(method),
+        None => ()
+    }
 }
 
 #[allow(unused_variables)]
@@ -564,7 +606,10 @@ unsafe extern "C" fn local_cb_native_method_bind(jvmti_env: *mut jvmtiEnv, jni_e
 
 #[allow(unused_variables)]
 unsafe extern "C" fn local_cb_object_free(jvmti_env: *mut jvmtiEnv, tag: jlong) -> () {
-
+    match CALLBACK_TABLE.vm_object_free {
+        Some(function) => function(tag as i64),
+        None => ()
+    }
 }
 
 #[allow(unused_variables)]
@@ -14,6 +14,7 @@ extern crate serde;
 //extern crate jni;
 //extern crate jvmti_sys;
 extern crate resp;
+extern crate flare_proto;
 extern crate timer;
 extern crate chrono;
 
@@ -46,9 +47,11 @@ use bytecode::io::ClassWriter;
 use config::Config;
 use context::static_context;
 use instrumentation::asm::transformer::Transformer;
-use native::{JavaVMPtr, MutString, VoidPtr, ReturnValue, JavaLong};
+use native::{JavaVMPtr, MutString, VoidPtr, ReturnValue, JavaLong, JNIEnvPtr, JavaClass};
+use native::jvmti_native::jstring;
 use options::Options;
 use runtime::*;
+use profile::diagnostic::*;
 use std::io::{Cursor, Write};
 use thread::Thread;
 use util::stringify;
@@ -59,10 +62,13 @@ use time::{Duration,Tm};
 use environment::jvm::{JVMF, JVMAgent};
 use environment::jvmti::{JVMTI, JVMTIEnvironment, JavaStackTrace, ThreadInfo};
 use profile::sample::*;
+use profile::deopt::{on_compiled_method_load, on_compiled_method_unload};
+use profile::classloader::{on_class_prepare, on_class_loader_free};
 use environment::Environment;
-use environment::jni::JNIEnvironment;
+use environment::jni::{JNI, JNIEnvironment};
 use std::path::Path;
-use error::NativeError;
+use error::{NativeError, translate_error};
+use event::{EventCallbacks, VMEvent};
 use std::collections::HashMap;
 use std::cmp::max;
 
@@ -272,17 +278,76 @@ fn on_object_alloc(event: ObjectAllocationEvent) {
     println!("[{}] [{}] Object allocation: (size: {})", nowTime(), event.thread.name, event.size);
 }
 
-fn on_object_free() {
+fn on_object_free(tag: i64) {
     if !is_trace_running() {
         return;
     }
-    println!("[{}] Object free", nowTime());
+    println!("[{}] Object free: (tag: {})", nowTime(), tag);
 }
 
 
+//取样线程的参数，JVM启动时加载(Agent_OnLoad)及运行中加载(Agent_OnAttach)共用
+struct TraceOptions {
+    interval: u64,
+    //定期检测死锁的间隔(ms)
+    deadlock_interval: i64,
+    //统计线程分配速率的间隔(ms)
+    alloc_interval: i64,
+    //JDK11+分配取样的平均间隔(字节)
+    alloc_sampling_interval: i64,
+    //读取finalizer积压数量的间隔(ms)，0表示不读取
+    finalizer_interval: i64,
+    //上报JIT重新编译次数的间隔(ms)，大于0时开启编译事件
+    deopt_interval: i64,
+    //统计类加载器的间隔(ms)，大于0时开启ClassPrepare事件记录类加载器的创建调用栈
+    classloader_interval: i64,
+    //推送GC暂停的间隔(ms)，0表示不开启GC事件
+    gc_interval: i64,
+    bind_host: String,
+    bind_port: u16,
+    //独立录制的目录(output参数)，不为空时不监听分析服务的连接，事件写入本地文件
+    output_dir: String,
+}
+
+lazy_static! {
+    //JVM启动时加载的取样参数，在VMInit事件中启动取样线程
+    static ref PREMAIN_TRACE: Mutex<Option<(usize, TraceOptions)>> = Mutex::new(None);
+}
+
+fn parse_int_option(options: &Options, name: &str, default_value: i64) -> i64 {
+    match options.custom_args.get(name) {
+        Some(str) => match str.parse() {
+            Ok(int_val) => int_val,
+            Err(e) => {
+                println!("parse {} failed, value: {}, error: {}", name, str, e);
+                default_value
+            }
+        },
+        None => default_value
+    }
+}
+
+fn parse_trace_options(options: &Options, default_interval: u64) -> TraceOptions {
+    let (bind_host, bind_port) = parse_address(options);
+    TraceOptions {
+        interval: parse_int_option(options, "interval", default_interval as i64).max(1) as u64,
+        deadlock_interval: parse_int_option(options, "deadlock_interval", 0),
+        alloc_interval: parse_int_option(options, "alloc_interval", 0),
+        alloc_sampling_interval: parse_int_option(options, "alloc_sampling_interval", profile::allocation::DEFAULT_SAMPLING_INTERVAL as i64),
+        finalizer_interval: parse_int_option(options, "finalizer_interval", 1000),
+        deopt_interval: parse_int_option(options, "deopt_interval", 0),
+        classloader_interval: parse_int_option(options, "classloader_interval", 0),
+        gc_interval: parse_int_option(options, "gc_interval", 1000),
+        bind_host,
+        bind_port,
+        output_dir: options.custom_args.get("output").cloned().unwrap_or_default(),
+    }
+}
+
 ///
 /// `Agent_OnLoad` is the actual entry point of the agent code and it is called by the
 /// Java Virtual Machine directly.
+/// -- Load java agent at JVM startup by -agentpath:<lib>=<options>
 ///
 #[no_mangle]
 #[allow(non_snake_case, unused_variables)]
@@ -291,25 +356,48 @@ pub extern fn Agent_OnLoad(vm: JavaVMPtr, options: MutString, reserved: VoidPtr)
     env_logger::init();
 
     let options = Options::parse(stringify(options));
-    println!("Starting up as {}", options.agent_id);
+    println!("Starting up as {}, options: {:?}", options.agent_id, options);
 
     if let Some(config) = Config::read_config() {
         println!("Setting configuration");
         static_context().set_config(config);
     }
 
-    let mut interval = 20;
-    if let Some(str) = options.custom_args.get("interval") {
-        interval = str.parse().unwrap();
+    //JVM还没有初始化，不能attach线程及取样，在VMInit事件中启动取样线程
+    let trace_options = parse_trace_options(&options, 20);
+    *PREMAIN_TRACE.lock().unwrap() = Some((vm as usize, trace_options));
+    match JVMAgent::new(vm).get_environment() {
+        Ok(mut jvmti) => {
+            let mut callbacks = EventCallbacks::new();
+            callbacks.vm_init = Some(on_premain_vm_init);
+            if let Some(error) = jvmti.set_event_callbacks(callbacks) {
+                println!("Couldn't register VMInit callback: {}", translate_error(&error));
+                return 1;
+            }
+            jvmti.set_event_notification_mode(VMEvent::VMInit, true);
+        },
+        Err(error) => {
+            println!("Could not get JVMTI environment: {}", translate_error(&error));
+            return 1;
+        }
     }
 
-    let (bind_host,bind_port) = parse_address(&options);
+    return 0;
+}
 
-    let mut agent = Agent::new(vm);
-    init_agent(&mut agent);
-    start_trace(interval, &bind_host, bind_port);
+fn on_premain_vm_init() {
+    if let Some((vm_ptr, trace_options)) = PREMAIN_TRACE.lock().unwrap().take() {
+        std::thread::spawn(move || {
+            println!("Trace agent is running ...");
+            run_trace(vm_ptr, trace_options);
+        });
+    }
+}
 
-    return 0;
+//独立录制时JVM退出前停止取样，写完剩余的事件
+fn on_recording_vm_death() {
+    println!("JVM is exiting, stopping recording ..");
+    stop_trace();
 }
 
 struct JavaVMPtrVo {
@@ -345,8 +433,6 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
 //                println!("caps: {}", caps);
 //                jvmti.get_all_stacktraces();
 
-    let (bind_host,bind_port) = parse_address(&options);
-
     if let Some(val) = options.custom_args.get("trace") {
         match val.as_ref() {
             "on" => {
@@ -356,64 +442,12 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
                     return 0;
                 }
 
-                let mut interval = 5;
-                if let Some(interval_str) = options.custom_args.get("interval") {
-                    match interval_str.parse() {
-                        Ok(int_val) => {
-                            interval = int_val;
-                        },
-                        Err(e) => {
-                            println!("parse sample interval failed, value: {}, error: {}", interval_str, e);
-                        }
-                    }
-                }
-
+                let trace_options = parse_trace_options(&options, 5);
                 let vm_ptr = vm as usize;
                 //TODO how to pass vm or agent to thread safely?
                 let handle = std::thread::spawn( move||{
                     println!("Trace agent is running ...");
-                    start_trace(interval, &bind_host, bind_port);
-                    let vm = vm_ptr as JavaVMPtr;
-                    println!("create agent ..");
-                    let mut agent = Agent::new_attach(vm, "Flare-Profiler");
-                    println!("init_agent ..");
-                    init_agent(&mut agent);
-                    let jvmenv = &agent.jvm_env;
-
-                    let mut samples=0i64;
-                    let mut thread_info_map: HashMap<JavaLong, ThreadInfo> = HashMap::new();
-                    let mut last_get_cpu_time = 0i64;
-                    //let get_cpu_time_per_samples = max(1, 50/interval);
-                    while is_trace_running() {
-                        samples += 1;
-                        let t0 = Local::now().timestamp_millis();
-                        let update_cpu_time = (t0 - last_get_cpu_time) > 50;
-                        if update_cpu_time {
-                            last_get_cpu_time = t0;
-                        }
-                        match jvmenv.get_all_stacktraces() {
-//                        match get_stack_traces(jvmenv, &mut thread_info_map, update_cpu_time) {
-                            Ok(stack_traces) => {
-                                let t1 = time::now();
-                                SAMPLER.lock().unwrap().add_stack_traces(jvmenv, &stack_traces);
-                                let t2 = time::now();
-                            },
-                            Err(e) => {
-                                println!("get all stack traces failed, error: {:?}", e);
-                            }
-                        }
-
-                        //process client request
-                        SAMPLER.lock().unwrap().handle_request();
-
-                        //sample interval
-                        std::thread::sleep(std::time::Duration::from_millis(interval));
-
-                        //TODO auto close after exceed max idle time
-
-                    }
-                    stop_trace();
-                    println!("Trace agent is stopped.");
+                    run_trace(vm_ptr, trace_options);
                 });
             },
             _ => {
@@ -428,6 +462,108 @@ pub extern fn Agent_OnAttach(vm: JavaVMPtr, options: MutString, reserved: VoidPt
     return 0;
 }
 
+//在取样线程中运行，直到停止取样
+fn run_trace(vm_ptr: usize, trace_options: TraceOptions) {
+    let interval = trace_options.interval;
+    SAMPLER.lock().unwrap().set_output_dir(&trace_options.output_dir);
+    SAMPLER.lock().unwrap().set_deadlock_interval(trace_options.deadlock_interval);
+    SAMPLER.lock().unwrap().set_allocation_interval(trace_options.alloc_interval);
+    SAMPLER.lock().unwrap().set_finalizer_interval(trace_options.finalizer_interval);
+    SAMPLER.lock().unwrap().set_deopt_interval(trace_options.deopt_interval);
+    SAMPLER.lock().unwrap().set_classloader_interval(trace_options.classloader_interval);
+    SAMPLER.lock().unwrap().set_gc_interval(trace_options.gc_interval);
+    let vm = vm_ptr as JavaVMPtr;
+    println!("create agent ..");
+    let mut agent = Agent::new_attach(vm, "Flare-Profiler");
+    if !trace_options.output_dir.is_empty() {
+        agent.on_vm_death(Some(on_recording_vm_death));
+    }
+    println!("init_agent ..");
+    init_agent(&mut agent);
+    //hello事件按开启的选项及添加成功的JVMTI能力报告
+    SAMPLER.lock().unwrap().set_command_capabilities(get_command_capabilities(&agent));
+    start_trace(interval, &trace_options.bind_host, trace_options.bind_port);
+    if trace_options.deopt_interval > 0 {
+        agent.on_compiled_method_load(Some(on_compiled_method_load));
+        agent.on_compiled_method_unload(Some(on_compiled_method_unload));
+    }
+    if trace_options.classloader_interval > 0 {
+        agent.on_class_prepare(Some(on_class_prepare));
+        agent.on_vm_object_free(Some(on_class_loader_free));
+    }
+    if trace_options.alloc_interval > 0 {
+        agent.on_sampled_object_alloc(Some(profile::allocation::on_sampled_object_alloc));
+    }
+    if trace_options.gc_interval > 0 {
+        agent.on_garbage_collection_start(Some(profile::gc::on_garbage_collection_start));
+        agent.on_garbage_collection_finish(Some(profile::gc::on_garbage_collection_finish));
+    }
+    //注册的事件回调在update时才启用，独立录制需要VMDeath事件写完剩余的数据
+    if !trace_options.output_dir.is_empty() || trace_options.deopt_interval > 0 || trace_options.classloader_interval > 0 || trace_options.gc_interval > 0
+        || trace_options.alloc_interval > 0 {
+        agent.update();
+    }
+    if trace_options.alloc_interval > 0 {
+        start_allocation_sampling(&agent, trace_options.alloc_sampling_interval);
+    }
+    let jvmenv = &agent.jvm_env;
+
+    let mut samples=0i64;
+    let mut thread_info_map: HashMap<JavaLong, ThreadInfo> = HashMap::new();
+    let mut last_get_cpu_time = 0i64;
+    //let get_cpu_time_per_samples = max(1, 50/interval);
+    while is_trace_running() {
+        samples += 1;
+        let t0 = profile::clock::now_millis();
+        let update_cpu_time = (t0 - last_get_cpu_time) > 50;
+        if update_cpu_time {
+            last_get_cpu_time = t0;
+        }
+        match jvmenv.get_all_stacktraces() {
+//                        match get_stack_traces(jvmenv, &mut thread_info_map, update_cpu_time) {
+            Ok(stack_traces) => {
+                let t1 = time::now();
+                SAMPLER.lock().unwrap().add_stack_traces(jvmenv, &stack_traces);
+                SAMPLER.lock().unwrap().check_allocations(jvmenv, &stack_traces);
+                let t2 = time::now();
+            },
+            Err(e) => {
+                println!("get all stack traces failed, error: {:?}", e);
+                report_diagnostic(LEVEL_ERROR, KIND_JVMTI_ERROR, &format!("get all stack traces failed, error: {:?}", e), 1);
+            }
+        }
+        //取样耗时超过取样间隔时，实际的取样频率低于设定值
+        let elapsed = profile::clock::now_millis() - t0;
+        if elapsed > interval as i64 {
+            report_diagnostic(LEVEL_WARN, KIND_SAMPLING_OVERRUN, &format!("sampling took {}ms, exceeds interval {}ms", elapsed, interval), 1);
+        }
+
+        //process client request
+        SAMPLER.lock().unwrap().handle_request();
+        SAMPLER.lock().unwrap().check_deadlocks(jvmenv);
+        SAMPLER.lock().unwrap().check_thread_dump(jvmenv);
+        SAMPLER.lock().unwrap().check_heap_histogram(jvmenv);
+        SAMPLER.lock().unwrap().check_finalizer(jvmenv);
+        SAMPLER.lock().unwrap().check_deoptimizations(jvmenv);
+        SAMPLER.lock().unwrap().check_class_loaders(jvmenv);
+        SAMPLER.lock().unwrap().check_gc_pauses();
+        profile::clock::check_clock_jump();
+        SAMPLER.lock().unwrap().check_diagnostics();
+
+        //sample interval
+        std::thread::sleep(std::time::Duration::from_millis(interval));
+
+        //TODO auto close after exceed max idle time
+
+    }
+    if profile::allocation::is_sampling_enabled() {
+        agent.jvm_env.set_event_notification_mode(VMEvent::SampledObjectAlloc, false);
+        profile::allocation::set_sampling_interval(0);
+    }
+    stop_trace();
+    println!("Trace agent is stopped.");
+}
+
 fn parse_address(options: &Options) -> (String, u16) {
     let mut bind_host = "0.0.0.0";
     let mut bind_port = 3333;
@@ -519,6 +655,34 @@ fn get_stack_traces(jvmenv: &Box<Environment>, thread_info_map: &mut HashMap<Jav
     }
 }
 
+//控制请求需要的JVMTI能力
+fn get_command_capabilities(agent: &Agent) -> i64 {
+    let mut capabilities = flare_proto::handshake::CAP_THREAD_DUMP;
+    if agent.capabilities.can_get_current_contended_monitor && agent.capabilities.can_get_monitor_info {
+        capabilities |= flare_proto::handshake::CAP_DEADLOCK;
+    }
+    if agent.capabilities.can_tag_objects {
+        capabilities |= flare_proto::handshake::CAP_HEAP_HISTOGRAM;
+    }
+    capabilities
+}
+
+//JDK11+按TLAB取样分配的对象，不支持时回退到读取线程累计分配的字节数
+fn start_allocation_sampling(agent: &Agent, sampling_interval: i64) {
+    if !agent.capabilities.can_generate_sampled_object_alloc_events {
+        println!("SampledObjectAlloc event is not supported, fallback to thread allocated bytes");
+        return;
+    }
+    let sampling_interval = sampling_interval.max(1).min(std::i32::MAX as i64) as i32;
+    match agent.jvm_env.set_heap_sampling_interval(sampling_interval) {
+        Ok(()) => {
+            println!("start allocation sampling, sampling interval: {} bytes", sampling_interval);
+            profile::allocation::set_sampling_interval(sampling_interval);
+        },
+        Err(e) => println!("set heap sampling interval failed: {:?}, fallback to thread allocated bytes", e)
+    }
+}
+
 fn init_agent(agent: &mut Agent) {
     agent.capabilities.can_get_thread_cpu_time = true;
     agent.capabilities.can_get_current_thread_cpu_time = true;
@@ -527,9 +691,14 @@ fn init_agent(agent: &mut Agent) {
     agent.capabilities.can_get_source_file_name = true;
     agent.capabilities.can_generate_all_class_hook_events = true;
     agent.capabilities.can_get_bytecodes = true;
-
-//    agent.on_garbage_collection_start(Some(on_garbage_collection_start));
-//    agent.on_garbage_collection_finish(Some(on_garbage_collection_finish));
+    agent.capabilities.can_get_current_contended_monitor = true;
+    agent.capabilities.can_get_monitor_info = true;
+    agent.capabilities.can_get_owned_monitor_info = true;
+    agent.capabilities.can_get_owned_monitor_stack_depth_info = true;
+    agent.capabilities.can_tag_objects = true;
+
+//    agent.on_garbage_collection_start(Some(profile::gc::on_garbage_collection_start));
+//    agent.on_garbage_collection_finish(Some(profile::gc::on_garbage_collection_finish));
     //agent.on_vm_object_alloc(Some(on_object_alloc));
     //agent.on_vm_object_free(Some(on_object_free));
     //agent.on_class_file_load(Some(on_class_file_load));
@@ -545,6 +714,64 @@ fn init_agent(agent: &mut Agent) {
 }
 
 
+///
+/// Native method of `com.kylixs.flare.Flare.addMarker0(String label, String color)`,
+/// the JVM resolves it from the loaded agent library.
+///
+#[no_mangle]
+#[allow(non_snake_case, unused_variables)]
+pub extern "C" fn Java_com_kylixs_flare_Flare_addMarker0(jni_env: JNIEnvPtr, class: JavaClass, label: jstring, color: jstring) {
+    if !is_trace_running() {
+        return;
+    }
+    let jni = JNIEnvironment::new(jni_env);
+    let label = jni.get_string_utf_chars(label);
+    let color = jni.get_string_utf_chars(color);
+    add_marker(&label, &color);
+}
+
+///
+/// Native method of `com.kylixs.flare.Flare.begin0(String name)`.
+///
+#[no_mangle]
+#[allow(non_snake_case, unused_variables)]
+pub extern "C" fn Java_com_kylixs_flare_Flare_begin0(jni_env: JNIEnvPtr, class: JavaClass, name: jstring) {
+    if !is_trace_running() {
+        return;
+    }
+    let jni = JNIEnvironment::new(jni_env);
+    let name = jni.get_string_utf_chars(name);
+    begin_interval(&name, get_current_thread_id(&jni));
+}
+
+///
+/// Native method of `com.kylixs.flare.Flare.end0()`.
+///
+#[no_mangle]
+#[allow(non_snake_case, unused_variables)]
+pub extern "C" fn Java_com_kylixs_flare_Flare_end0(jni_env: JNIEnvPtr, class: JavaClass) {
+    if !is_trace_running() {
+        return;
+    }
+    let jni = JNIEnvironment::new(jni_env);
+    end_interval(get_current_thread_id(&jni));
+}
+
+//调用native方法的Java线程的id(Thread.currentThread().getId())，阶段按线程嵌套
+fn get_current_thread_id(jni: &JNIEnvironment) -> JavaLong {
+    let thread_class = jni.find_class("java/lang/Thread");
+    let current_thread_method = jni.get_static_method_id(thread_class.native_id, "currentThread", "()Ljava/lang/Thread;");
+    let get_id_method = jni.get_method_id(thread_class.native_id, "getId", "()J");
+    let thread = jni.call_static_object_method(thread_class.native_id, current_thread_method);
+    let thread_id = jni.call_long_method(thread, get_id_method);
+    if jni.exception_clear() {
+        println!("get current thread id failed");
+    }
+    jni.delete_local_ref(thread);
+    jni.delete_local_ref(thread_class.native_id);
+    thread_id
+}
+
 ///
 /// `Agent_OnUnload` is the exit point of the agent code. It is called when the JVM has finished
 /// running and the virtual machine is unloading the agent from memory before shutting down.
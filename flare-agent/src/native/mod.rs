@@ -959,7 +959,8 @@ pub mod jvmti_native {
     pub const JVMTI_EVENT_GARBAGE_COLLECTION_FINISH: c_uint = 82;
     pub const JVMTI_EVENT_OBJECT_FREE: c_uint = 83;
     pub const JVMTI_EVENT_VM_OBJECT_ALLOC: c_uint = 84;
-    pub const JVMTI_MAX_EVENT_TYPE_VAL: c_uint = 84;
+    pub const JVMTI_EVENT_SAMPLED_OBJECT_ALLOC: c_uint = 86;
+    pub const JVMTI_MAX_EVENT_TYPE_VAL: c_uint = 86;
     #[allow(non_camel_case_types)]
     pub type jvmtiEvent = Enum_Unnamed28;
     #[allow(non_camel_case_types)]
@@ -1604,6 +1605,13 @@ pub mod jvmti_native {
                                                    object: jobject,
                                                    object_klass: jclass,
                                                    size: jlong) -> ()>;
+    pub type jvmtiEventSampledObjectAlloc =
+        Option<unsafe extern "C" fn(jvmti_env: *mut jvmtiEnv,
+                                                   jni_env: *mut JNIEnv,
+                                                   thread: jthread,
+                                                   object: jobject,
+                                                   object_klass: jclass,
+                                                   size: jlong) -> ()>;
     pub type jvmtiEventVMStart =
         Option<unsafe extern "C" fn(jvmti_env: *mut jvmtiEnv,
                                                    jni_env: *mut JNIEnv) -> ()>;
@@ -1645,6 +1653,8 @@ pub mod jvmti_native {
         pub GarbageCollectionFinish: jvmtiEventGarbageCollectionFinish,
         pub ObjectFree: jvmtiEventObjectFree,
         pub VMObjectAlloc: jvmtiEventVMObjectAlloc,
+        pub reserved85: jvmtiEventReserved,
+        pub SampledObjectAlloc: jvmtiEventSampledObjectAlloc,
     }
     impl ::std::clone::Clone for Struct_Unnamed30 {
         fn clone(&self) -> Self { *self }
@@ -1812,6 +1822,8 @@ pub mod jvmti_native {
         pub GetOwnedMonitorStackDepthInfo: Option<unsafe extern "C" fn(env: *mut jvmtiEnv, thread: jthread, monitor_info_count_ptr: *mut jint, monitor_info_ptr: *mut *mut jvmtiMonitorStackDepthInfo) -> jvmtiError>,
         pub GetObjectSize: Option<unsafe extern "C" fn(env: *mut jvmtiEnv, object: jobject, size_ptr: *mut jlong) -> jvmtiError>,
         pub GetLocalInstance: Option<unsafe extern "C" fn(env: *mut jvmtiEnv, thread: jthread, depth: jint, value_ptr: *mut jobject) -> jvmtiError>,
+        //JDK11+，之前版本的函数表没有这一项，需要先确认有can_generate_sampled_object_alloc_events
+        pub SetHeapSamplingInterval: Option<unsafe extern "C" fn(env: *mut jvmtiEnv, sampling_interval: jint) -> jvmtiError>,
     }
     impl ::std::clone::Clone for Struct_jvmtiInterface_1_ {
         fn clone(&self) -> Self { *self }
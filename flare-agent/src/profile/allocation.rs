@@ -0,0 +1,73 @@
+//分配取样：JDK11+开启SampledObjectAlloc事件，JVM在线程的TLAB中平均每分配sampling_interval字节取样一个对象，
+//每次取样计为max(对象大小, sampling_interval)字节，按(线程, 调用栈)累计，由取样线程定期取出上报
+//  事件回调在分配对象的线程中执行，只记录线程id和调用栈
+//  累计的调用栈数量有上限，超过时丢弃新调用栈的取样并计数
+//  JVM不支持时回退到定期读取线程累计分配的字节数(Sampler::check_allocations)
+
+use environment::Environment;
+use native::{JavaLong, JavaMethod, JavaThread};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+//JVMTI默认的取样间隔
+pub const DEFAULT_SAMPLING_INTERVAL: i32 = 512 * 1024;
+const MAX_PENDING_STACKS: usize = 10_000;
+
+#[derive(Default)]
+struct PendingSamples {
+    //0表示未开启
+    sampling_interval: i64,
+    //(java线程id, 调用栈) => 估算的分配字节数
+    samples: HashMap<(JavaLong, Vec<usize>), i64>,
+    dropped: i64,
+}
+
+lazy_static! {
+    static ref PENDING_SAMPLES: Mutex<PendingSamples> = Mutex::new(PendingSamples::default());
+}
+
+//确认JVM已开启SampledObjectAlloc事件后调用
+pub fn set_sampling_interval(sampling_interval: i32) {
+    let mut pending = PENDING_SAMPLES.lock().unwrap();
+    pending.sampling_interval = sampling_interval as i64;
+    pending.samples.clear();
+    pending.dropped = 0;
+}
+
+pub fn is_sampling_enabled() -> bool {
+    PENDING_SAMPLES.lock().unwrap().sampling_interval > 0
+}
+
+pub fn on_sampled_object_alloc(env: &Environment, thread: JavaThread, size: i64) {
+    let thread_id = env.get_thread_id(&thread);
+    let stacktrace: Vec<usize> = match env.get_stack_trace(&thread) {
+        Ok(frames) => frames.iter().map(|x| x.method as usize).collect(),
+        Err(_) => return
+    };
+    let mut pending = PENDING_SAMPLES.lock().unwrap();
+    if pending.sampling_interval <= 0 {
+        return;
+    }
+    let bytes = size.max(pending.sampling_interval);
+    let key = (thread_id, stacktrace);
+    if let Some(total) = pending.samples.get_mut(&key) {
+        *total += bytes;
+        return;
+    }
+    if pending.samples.len() >= MAX_PENDING_STACKS {
+        pending.dropped += 1;
+        return;
+    }
+    pending.samples.insert(key, bytes);
+}
+
+//取出距上次调用累计的取样：(java线程id, 调用栈, 字节数)，及丢弃的取样次数
+pub fn take_samples() -> (Vec<(JavaLong, Vec<JavaMethod>, i64)>, i64) {
+    let mut pending = PENDING_SAMPLES.lock().unwrap();
+    let samples = pending.samples.drain()
+        .map(|((thread_id, stacktrace), bytes)| (thread_id, stacktrace.into_iter().map(|x| x as JavaMethod).collect(), bytes))
+        .collect();
+    let dropped = pending.dropped;
+    pending.dropped = 0;
+    (samples, dropped)
+}
@@ -0,0 +1,120 @@
+
+//类加载器统计：定期遍历已加载的类，按定义类的类加载器统计类的数量；
+//ClassPrepare事件中记录每个类加载器定义第一个类时的调用栈，作为类加载器的创建位置
+//  attach之后才开启事件，之前已经存在的类加载器没有调用栈
+//  类加载器的id使用JVMTI tag，第一次遇到时分配，不会像identity hash code一样重复；
+//  类加载器被回收时(ObjectFree事件)删除还没有上报的调用栈
+//  tag从 LOADER_TAG_BASE 开始分配，与堆直方图给类设置的tag(序号)不重叠
+
+use environment::Environment;
+use error::NativeError;
+use native::{JavaClass, JavaMethod, JavaObject, JavaThread};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub const BOOTSTRAP_LOADER_NAME: &str = "<bootstrap>";
+const LOADER_TAG_BASE: i64 = 1 << 48;
+
+pub struct ClassLoaderStats {
+    //JVMTI tag，引导类加载器为0
+    pub loader_id: i64,
+    pub loader_name: String,
+    pub classes: i64,
+}
+
+lazy_static! {
+    //下一个分配的tag，分配时加锁，避免多个线程给同一个类加载器设置不同的tag
+    static ref NEXT_LOADER_TAG: Mutex<i64> = Mutex::new(LOADER_TAG_BASE);
+    static ref DEFINING_STACKS: Mutex<HashMap<i64, Vec<usize>>> = Mutex::new(HashMap::new());
+    //ObjectFree回调中不能调用JNI及等待其它调用JVMTI的线程，只记录回收的tag，由取样线程清理
+    static ref FREED_LOADERS: Mutex<Vec<i64>> = Mutex::new(vec![]);
+}
+
+//不支持tag时使用identity hash code
+fn get_loader_id(env: &Environment, loader: &JavaObject) -> Option<i64> {
+    let mut next_tag = NEXT_LOADER_TAG.lock().unwrap();
+    match env.get_tag(loader) {
+        Ok(tag) if tag >= LOADER_TAG_BASE => Some(tag),
+        Ok(_) => {
+            let tag = *next_tag;
+            match env.set_tag(loader, tag) {
+                Ok(()) => {
+                    *next_tag += 1;
+                    Some(tag)
+                }
+                Err(_) => env.get_object_hash_code(loader).ok().map(|x| x as i64)
+            }
+        }
+        Err(_) => env.get_object_hash_code(loader).ok().map(|x| x as i64)
+    }
+}
+
+pub fn on_class_prepare(env: &Environment, thread: JavaThread, class: JavaClass) {
+    let loader = match env.get_class_loader(&class) {
+        Ok(Some(loader)) => loader,
+        _ => return
+    };
+    if let Some(loader_id) = get_loader_id(env, &loader) {
+        let mut stacks = DEFINING_STACKS.lock().unwrap();
+        stacks.entry(loader_id).or_insert_with(|| {
+            env.get_stack_trace(&thread).map(|frames| frames.iter().map(|x| x.method as usize).collect()).unwrap_or_default()
+        });
+    }
+    env.delete_local_ref(loader);
+}
+
+pub fn on_class_loader_free(tag: i64) {
+    if tag >= LOADER_TAG_BASE {
+        FREED_LOADERS.lock().unwrap().push(tag);
+    }
+}
+
+//删除已回收的类加载器的调用栈
+fn remove_freed_loaders() {
+    let freed: Vec<i64> = std::mem::replace(&mut *FREED_LOADERS.lock().unwrap(), vec![]);
+    if !freed.is_empty() {
+        let mut stacks = DEFINING_STACKS.lock().unwrap();
+        for tag in freed {
+            stacks.remove(&tag);
+        }
+    }
+}
+
+//取出类加载器的创建调用栈，每个类加载器只返回一次
+pub fn take_defining_stack(loader_id: i64) -> Option<Vec<JavaMethod>> {
+    DEFINING_STACKS.lock().unwrap().remove(&loader_id).map(|x| x.into_iter().map(|method| method as JavaMethod).collect())
+}
+
+//按类的数量从大到小排列
+pub fn get_class_loader_stats(jvmenv: &Box<Environment>) -> Result<Vec<ClassLoaderStats>, NativeError> {
+    remove_freed_loaders();
+    let classes = jvmenv.get_loaded_classes()?;
+    let mut loaders: HashMap<i64, ClassLoaderStats> = HashMap::new();
+    for class in &classes {
+        let loader = jvmenv.get_class_loader(class).unwrap_or(None);
+        let loader_id = match loader {
+            Some(loader) => get_loader_id(jvmenv, &loader).unwrap_or(0),
+            None => 0
+        };
+        let stats = loaders.entry(loader_id).or_insert_with(|| {
+            let loader_name = match loader {
+                Some(loader) => {
+                    let loader_class = jvmenv.get_object_class(&loader);
+                    let name = jvmenv.get_class_signature(&loader_class).map(|x| x.name).unwrap_or_default();
+                    jvmenv.delete_local_ref(loader_class.native_id);
+                    name
+                },
+                None => BOOTSTRAP_LOADER_NAME.to_string()
+            };
+            ClassLoaderStats { loader_id, loader_name, classes: 0 }
+        });
+        stats.classes += 1;
+        if let Some(loader) = loader {
+            jvmenv.delete_local_ref(loader);
+        }
+        jvmenv.delete_local_ref(*class);
+    }
+    let mut result: Vec<ClassLoaderStats> = loaders.into_iter().map(|x| x.1).collect();
+    result.sort_by(|a, b| b.classes.cmp(&a.classes).then(a.loader_id.cmp(&b.loader_id)));
+    Ok(result)
+}
@@ -0,0 +1,34 @@
+
+//事件时间：第一次使用时记录墙上时间作为锚点，之后按单调时钟(Instant)的增量计算，
+//目标主机的墙上时间跳变(如NTP step、手工修改时间)不会让事件时间倒退，各个检查的间隔也不受影响
+//  定期比较墙上时间与事件时间，差值变化超过阈值时上报诊断信息 clock_jump
+
+use chrono::Local;
+use std::sync::Mutex;
+use std::time::Instant;
+use profile::diagnostic::*;
+
+//墙上时间跳变的阈值(ms)
+const CLOCK_JUMP_THRESHOLD: i64 = 1000;
+
+lazy_static! {
+    static ref CLOCK_ANCHOR: (i64, Instant) = (Local::now().timestamp_millis(), Instant::now());
+    //上次检查时墙上时间与事件时间的差值
+    static ref LAST_WALL_CLOCK_DIFF: Mutex<i64> = Mutex::new(0);
+}
+
+pub fn now_millis() -> i64 {
+    let elapsed = CLOCK_ANCHOR.1.elapsed();
+    CLOCK_ANCHOR.0 + elapsed.as_secs() as i64 * 1000 + elapsed.subsec_millis() as i64
+}
+
+pub fn check_clock_jump() {
+    let diff = Local::now().timestamp_millis() - now_millis();
+    let mut last_diff = LAST_WALL_CLOCK_DIFF.lock().unwrap();
+    if (diff - *last_diff).abs() > CLOCK_JUMP_THRESHOLD {
+        let message = format!("wall clock jumped by {}ms, event times keep the monotonic time", diff - *last_diff);
+        println!("{}", message);
+        report_diagnostic(LEVEL_WARN, KIND_CLOCK_JUMP, &message, 1);
+        *last_diff = diff;
+    }
+}
@@ -0,0 +1,66 @@
+
+//死锁检测：每个线程等待进入的监视器及其持有者构成等待图，图中的环即为死锁
+//  只包含synchronized监视器，需要 can_get_current_contended_monitor/can_get_monitor_info 能力
+
+use environment::Environment;
+use environment::jvmti::JavaStackFrame;
+use native::JavaLong;
+use std::collections::{HashMap, HashSet};
+
+pub struct DeadlockThreadInfo {
+    pub thread_id: JavaLong,
+    pub name: String,
+    //等待的监视器类名
+    pub lock: String,
+    pub owner_id: JavaLong,
+    pub frames: Vec<JavaStackFrame>,
+}
+
+pub fn find_deadlocks(jvmenv: &Box<Environment>) -> Vec<Vec<DeadlockThreadInfo>> {
+    let threads = match jvmenv.get_all_threads() {
+        Ok(threads) => threads,
+        Err(e) => {
+            println!("get_all_threads failed: {:?}", e);
+            return vec![];
+        }
+    };
+
+    //等待图: 线程 -> 等待的监视器持有者
+    let mut waiting: HashMap<JavaLong, DeadlockThreadInfo> = HashMap::new();
+    for thread in threads {
+        if let Ok(Some(monitor)) = jvmenv.get_current_contended_monitor(&thread.native_id) {
+            if let Ok(Some(owner)) = jvmenv.get_monitor_owner(&monitor) {
+                let thread_id = jvmenv.get_thread_id(&thread.native_id);
+                let owner_id = jvmenv.get_thread_id(&owner);
+                let name = jvmenv.get_thread_info_ex(&thread.native_id).map(|x| x.name).unwrap_or_default();
+                let lock_class = jvmenv.get_object_class(&monitor);
+                let lock = jvmenv.get_class_signature(&lock_class).map(|x| x.name).unwrap_or_default();
+                let frames = jvmenv.get_stack_trace(&thread.native_id).unwrap_or_default();
+                jvmenv.delete_local_ref(lock_class.native_id);
+                jvmenv.delete_local_ref(owner);
+                waiting.insert(thread_id, DeadlockThreadInfo { thread_id, name, lock, owner_id, frames });
+            }
+            jvmenv.delete_local_ref(monitor);
+        }
+        jvmenv.delete_local_ref(thread.native_id);
+    }
+
+    //沿着持有者查找环，每个线程只访问一次
+    let mut cycles = vec![];
+    let mut visited: HashSet<JavaLong> = HashSet::new();
+    let mut thread_ids: Vec<JavaLong> = waiting.keys().cloned().collect();
+    thread_ids.sort();
+    for start in thread_ids {
+        let mut path: Vec<JavaLong> = vec![];
+        let mut current = start;
+        while !visited.contains(&current) && waiting.contains_key(&current) {
+            visited.insert(current);
+            path.push(current);
+            current = waiting[&current].owner_id;
+        }
+        if let Some(pos) = path.iter().position(|x| *x == current) {
+            cycles.push(path[pos..].to_vec());
+        }
+    }
+    cycles.into_iter().map(|cycle| cycle.iter().filter_map(|x| waiting.remove(x)).collect()).collect()
+}
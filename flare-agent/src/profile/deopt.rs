@@ -0,0 +1,50 @@
+//JIT反优化统计：JVMTI没有反优化事件，方法的编译代码被卸载(CompiledMethodUnload)后再次编译(CompiledMethodLoad)，
+//计为一次反优化后的重新编译；分层编译(C1->C2)、OSR编译不卸载原有代码，不计入
+//  JVMTI不提供反优化原因；代码卸载可能晚于重新编译(not entrant的代码等到清理时才卸载)，这时在下一次编译时计数
+//  只统计开启之后发生的编译，编译回调在JIT编译线程中执行，只做计数
+
+use native::JavaMethod;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct CompileCounter {
+    //已卸载、还没有重新编译的次数
+    unloads: i64,
+    //卸载后重新编译的次数
+    reloads: i64,
+    //已经上报的重新编译次数
+    reported: i64,
+}
+
+lazy_static! {
+    static ref COMPILE_COUNTERS: Mutex<HashMap<usize, CompileCounter>> = Mutex::new(HashMap::new());
+}
+
+pub fn on_compiled_method_load(method: JavaMethod) {
+    let mut counters = COMPILE_COUNTERS.lock().unwrap();
+    if let Some(counter) = counters.get_mut(&(method as usize)) {
+        if counter.unloads > 0 {
+            counter.unloads -= 1;
+            counter.reloads += 1;
+        }
+    }
+}
+
+pub fn on_compiled_method_unload(method: JavaMethod) {
+    let mut counters = COMPILE_COUNTERS.lock().unwrap();
+    counters.entry(method as usize).or_insert_with(CompileCounter::default).unloads += 1;
+}
+
+//返回距上次调用新增的重新编译次数
+pub fn take_recompiles() -> Vec<(JavaMethod, i64)> {
+    let mut counters = COMPILE_COUNTERS.lock().unwrap();
+    let mut result = vec![];
+    for (method, counter) in counters.iter_mut() {
+        if counter.reloads > counter.reported {
+            result.push((*method as JavaMethod, counter.reloads - counter.reported));
+            counter.reported = counter.reloads;
+        }
+    }
+    result
+}
@@ -0,0 +1,57 @@
+
+//agent自身的诊断信息：取样超时、JVMTI调用失败、发送队列丢弃数据等，由取样线程定期推送给服务端，
+//让数据质量下降可以被发现，而不是只打印在目标进程的控制台
+//  同一类型在一个上报周期内合并为一条，保留最后的消息并累计次数，避免持续的问题刷屏
+
+use profile::clock::now_millis;
+use std::sync::Mutex;
+
+pub const LEVEL_INFO: &str = "info";
+pub const LEVEL_WARN: &str = "warn";
+pub const LEVEL_ERROR: &str = "error";
+
+pub const KIND_SAMPLING_OVERRUN: &str = "sampling_overrun";
+pub const KIND_JVMTI_ERROR: &str = "jvmti_error";
+pub const KIND_DROPPED_EVENTS: &str = "dropped_events";
+pub const KIND_CLOCK_JUMP: &str = "clock_jump";
+
+//上报周期(ms)
+pub const DIAGNOSTIC_INTERVAL: i64 = 1000;
+
+//合并后的类型数量上限
+const MAX_PENDING_DIAGNOSTICS: usize = 100;
+
+pub struct Diagnostic {
+    //本周期内第一次出现的时间
+    pub time: i64,
+    pub level: String,
+    pub kind: String,
+    pub message: String,
+    pub count: i64,
+}
+
+lazy_static! {
+    static ref DIAGNOSTICS: Mutex<Vec<Diagnostic>> = Mutex::new(vec![]);
+}
+
+pub fn report_diagnostic(level: &str, kind: &str, message: &str, count: i64) {
+    let mut diagnostics = DIAGNOSTICS.lock().unwrap();
+    if let Some(x) = diagnostics.iter_mut().find(|x| x.kind == kind && x.level == level) {
+        x.message = message.to_string();
+        x.count += count;
+        return;
+    }
+    if diagnostics.len() < MAX_PENDING_DIAGNOSTICS {
+        diagnostics.push(Diagnostic {
+            time: now_millis(),
+            level: level.to_string(),
+            kind: kind.to_string(),
+            message: message.to_string(),
+            count,
+        });
+    }
+}
+
+pub fn take_diagnostics() -> Vec<Diagnostic> {
+    std::mem::replace(&mut DIAGNOSTICS.lock().unwrap(), vec![])
+}
@@ -1,54 +1,153 @@
-
-use resp::{Value, Decoder};
-use profile::sample::{ThreadData, MethodData};
-
-pub fn resp_encode_thread_data(thread_data: &ThreadData) -> Value {
-    Value::Array(vec![
-        Value::String("thread".to_string()),
-        Value::String("time".to_string()),
-        Value::Integer(thread_data.sample_time),
-        Value::String("id".to_string()),
-        Value::Integer(thread_data.id),
-        Value::String("name".to_string()),
-        Value::String(thread_data.name.clone()),
-        Value::String("cpu_time".to_string()),
-        Value::Integer(thread_data.cpu_time),
-        Value::String("cpu_time_delta".to_string()),
-        Value::Integer(thread_data.cpu_time_delta),
-        Value::String("state".to_string()),
-        Value::String(thread_data.state.clone()),
-        Value::String("stacktrace".to_string()),
-        resp_encode_stacktrace(thread_data),
-    ])
-}
-
-
-fn resp_encode_stacktrace(thread_data: &ThreadData) -> Value {
-    let mut vec = vec![];
-    for call_id in  &thread_data.stacktrace {
-        vec.push(Value::Integer(call_id.clone()));
-    }
-    Value::Array(vec)
-}
-
-pub fn resp_encode_method_data(method_data: &MethodData) -> Value {
-    Value::Array(vec![
-        Value::String("method".to_string()),
-        Value::String("id".to_string()),
-        Value::Integer(method_data.method_id),
-        Value::String("name".to_string()),
-        Value::String(method_data.full_name.clone()),
-    ])
-}
-
-pub fn resp_encode_sample_info(start_time: i64, sample_interval:u64, last_sample_time: i64) -> Value {
-    Value::Array(vec![
-        Value::String("sample_info".to_string()),
-        Value::String("start_time".to_string()),
-        Value::Integer(start_time),
-        Value::String("sample_interval".to_string()),
-        Value::Integer(sample_interval as i64),
-        Value::String("last_sample_time".to_string()),
-        Value::Integer(last_sample_time),
-    ])
-}
\ No newline at end of file
+
+//取样事件的编码格式定义在 flare-proto，与分析服务共用
+use resp::Value;
+use flare_proto::agent::*;
+use profile::sample::{ThreadData, MethodData, MarkerData, IntervalData, DeadlockThreadData, ThreadDumpData, HeapHistogramData, AllocationData, FinalizerData, DeoptimizationData, ClassLoaderData, GcData, DiagnosticData, ClockSyncData};
+
+pub fn resp_encode_thread_data(thread_data: &ThreadData) -> Value {
+    AgentEvent::Thread(ThreadEvent {
+        time: thread_data.sample_time,
+        id: thread_data.id,
+        name: thread_data.name.clone(),
+        cpu_time: thread_data.cpu_time,
+        cpu_time_delta: thread_data.cpu_time_delta,
+        state: thread_data.state.clone(),
+        stacktrace: thread_data.stacktrace.clone(),
+        start_time: thread_data.start_time,
+    }).to_resp()
+}
+
+pub fn resp_encode_method_data(method_data: &MethodData) -> Value {
+    encode_raw_method(method_data.method_id, &method_data.full_name)
+}
+
+pub fn resp_encode_sample_info(start_time: i64, sample_interval:u64, last_sample_time: i64) -> Value {
+    AgentEvent::SampleInfo(SampleInfoEvent {
+        start_time,
+        sample_interval: sample_interval as i64,
+        last_sample_time,
+    }).to_resp()
+}
+
+pub fn resp_encode_marker_data(marker_data: &MarkerData) -> Value {
+    AgentEvent::Marker(MarkerEvent {
+        time: marker_data.time,
+        label: marker_data.label.clone(),
+        color: marker_data.color.clone(),
+    }).to_resp()
+}
+
+pub fn resp_encode_interval_data(interval_data: &IntervalData) -> Value {
+    if interval_data.begin {
+        AgentEvent::IntervalBegin(IntervalBeginEvent {
+            time: interval_data.time,
+            name: interval_data.name.clone(),
+            thread_id: interval_data.thread_id,
+        }).to_resp()
+    } else {
+        AgentEvent::IntervalEnd(IntervalEndEvent {
+            time: interval_data.time,
+            thread_id: interval_data.thread_id,
+        }).to_resp()
+    }
+}
+
+pub fn resp_encode_deadlock_thread_data(deadlock_data: &DeadlockThreadData) -> Value {
+    with_event_tag(AgentEvent::DeadlockThread(DeadlockThreadEvent {
+        time: deadlock_data.time,
+        cycle: deadlock_data.cycle,
+        id: deadlock_data.id,
+        name: deadlock_data.name.clone(),
+        state: "BLOCKED".to_string(),
+        lock: deadlock_data.lock.clone(),
+        owner_id: deadlock_data.owner_id,
+        stacktrace: deadlock_data.stacktrace.clone(),
+    }).to_resp(), &deadlock_data.tag)
+}
+
+pub fn resp_encode_thread_dump_data(thread_dump_data: &ThreadDumpData) -> Value {
+    with_event_tag(AgentEvent::ThreadDump(ThreadDumpEvent {
+        time: thread_dump_data.time,
+        threads: thread_dump_data.threads,
+        content: thread_dump_data.content.clone(),
+    }).to_resp(), &thread_dump_data.tag)
+}
+
+pub fn resp_encode_heap_histogram_data(histogram_data: &HeapHistogramData) -> Value {
+    let classes: Vec<&[u8]> = histogram_data.classes.iter().map(|x| x.class_name.as_slice()).collect();
+    let counts: Vec<i64> = histogram_data.classes.iter().map(|x| x.count).collect();
+    let bytes: Vec<i64> = histogram_data.classes.iter().map(|x| x.bytes).collect();
+    let totals = &histogram_data.totals;
+    with_event_tag(encode_raw_heap_histogram(histogram_data.time, histogram_data.force_gc, &classes, &counts, &bytes,
+                                             (totals.classes, totals.count, totals.bytes)), &histogram_data.tag)
+}
+
+pub fn resp_encode_allocation_data(allocation_data: &AllocationData) -> Value {
+    AgentEvent::Allocation(AllocationEvent {
+        time: allocation_data.time,
+        id: allocation_data.id,
+        name: allocation_data.name.clone(),
+        bytes: allocation_data.bytes,
+        stacktrace: allocation_data.stacktrace.clone(),
+    }).to_resp()
+}
+
+pub fn resp_encode_finalizer_data(finalizer_data: &FinalizerData) -> Value {
+    AgentEvent::Finalizer(FinalizerEvent {
+        time: finalizer_data.time,
+        pending: finalizer_data.pending,
+    }).to_resp()
+}
+
+pub fn resp_encode_deoptimization_data(deopt_data: &DeoptimizationData) -> Value {
+    AgentEvent::Deoptimization(DeoptimizationEvent {
+        time: deopt_data.time,
+        method: deopt_data.method_id,
+        count: deopt_data.count,
+    }).to_resp()
+}
+
+pub fn resp_encode_class_loader_data(loader_data: &ClassLoaderData) -> Value {
+    AgentEvent::ClassLoader(ClassLoaderEvent {
+        time: loader_data.time,
+        id: loader_data.loader_id,
+        name: loader_data.loader_name.clone(),
+        classes: loader_data.classes,
+        stacktrace: loader_data.stacktrace.clone(),
+    }).to_resp()
+}
+
+pub fn resp_encode_gc_data(gc_data: &GcData) -> Value {
+    AgentEvent::Gc(GcEvent {
+        time: gc_data.time,
+        duration: gc_data.duration,
+    }).to_resp()
+}
+
+pub fn resp_encode_diagnostic_data(diagnostic_data: &DiagnosticData) -> Value {
+    AgentEvent::Diagnostic(DiagnosticEvent {
+        time: diagnostic_data.time,
+        level: diagnostic_data.level.clone(),
+        kind: diagnostic_data.kind.clone(),
+        message: diagnostic_data.message.clone(),
+        count: diagnostic_data.count,
+    }).to_resp()
+}
+
+pub fn resp_encode_clock_sync_data(clock_sync_data: &ClockSyncData, send_time: i64) -> Value {
+    with_event_tag(AgentEvent::ClockSync(ClockSyncEvent {
+        client_time: clock_sync_data.client_time,
+        receive_time: clock_sync_data.receive_time,
+        send_time,
+    }).to_resp(), &clock_sync_data.tag)
+}
+
+//订阅后首先发送的版本及能力
+pub fn resp_encode_hello(capabilities: i64) -> Value {
+    AgentEvent::Hello(HelloEvent {
+        proto_version: flare_proto::AGENT_PROTO_VERSION as i64,
+        min_proto_version: flare_proto::MIN_AGENT_PROTO_VERSION as i64,
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities,
+    }).to_resp()
+}
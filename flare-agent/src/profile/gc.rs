@@ -0,0 +1,39 @@
+
+//GC暂停：GarbageCollectionStart/Finish事件之间的时间，事件回调中只能做很少的操作，只记录时间，
+//由取样线程定期取出并推送
+//  回调只在stop-the-world的GC阶段触发，并发GC阶段不包含在内
+
+use profile::clock::now_millis;
+use std::sync::Mutex;
+use std::time::Instant;
+
+struct GcState {
+    start: Option<(i64, Instant)>,
+    //(开始时间ms, 暂停时间us)
+    pauses: Vec<(i64, i64)>,
+}
+
+lazy_static! {
+    static ref GC_STATE: Mutex<GcState> = Mutex::new(GcState { start: None, pauses: vec![] });
+}
+
+//积压的暂停数量上限，避免没有取出时无限增长
+const MAX_PENDING_PAUSES: usize = 10_000;
+
+pub fn on_garbage_collection_start() {
+    GC_STATE.lock().unwrap().start = Some((now_millis(), Instant::now()));
+}
+
+pub fn on_garbage_collection_finish() {
+    let mut state = GC_STATE.lock().unwrap();
+    if let Some((time, instant)) = state.start.take() {
+        if state.pauses.len() < MAX_PENDING_PAUSES {
+            let elapsed = instant.elapsed();
+            state.pauses.push((time, elapsed.as_secs() as i64 * 1_000_000 + elapsed.subsec_micros() as i64));
+        }
+    }
+}
+
+pub fn take_gc_pauses() -> Vec<(i64, i64)> {
+    std::mem::replace(&mut GC_STATE.lock().unwrap().pauses, vec![])
+}
@@ -0,0 +1,62 @@
+
+//堆直方图：给所有已加载的类设置tag(序号)，遍历堆中的对象按类的tag统计实例数量及字节数
+//  需要 can_tag_objects 能力，遍历期间JVM会暂停
+
+use environment::Environment;
+use error::NativeError;
+use class::ClassId;
+
+pub struct HeapClassStats {
+    //原始字节(modified UTF-8)
+    pub class_name: Vec<u8>,
+    pub count: i64,
+    pub bytes: i64,
+}
+
+//截断前的类数量及总数
+pub struct HeapTotals {
+    pub classes: i64,
+    pub count: i64,
+    pub bytes: i64,
+}
+
+//按字节数从大到小排列，limit为0时返回全部的类，总数按截断前计算
+pub fn build_heap_histogram(jvmenv: &Box<Environment>, force_gc: bool, limit: usize) -> Result<(Vec<HeapClassStats>, HeapTotals), NativeError> {
+    if force_gc {
+        if let Err(e) = jvmenv.force_garbage_collection() {
+            println!("force garbage collection failed: {:?}", e);
+        }
+    }
+
+    let classes = jvmenv.get_loaded_classes()?;
+    let mut class_names = vec![];
+    for (i, class) in classes.iter().enumerate() {
+        //tag从1开始，0表示没有tag的类
+        if let Err(e) = jvmenv.set_tag(class, i as i64 + 1) {
+            println!("set class tag failed: {:?}", e);
+        }
+        let name = jvmenv.get_class_name_bytes(&ClassId { native_id: *class }).unwrap_or_default();
+        class_names.push(name);
+        jvmenv.delete_local_ref(*class);
+    }
+
+    let histogram = jvmenv.iterate_heap_by_class_tag()?;
+    let mut result: Vec<HeapClassStats> = histogram.into_iter().map(|(tag, (count, bytes))| {
+        let class_name = if tag > 0 && tag as usize <= class_names.len() {
+            class_names[tag as usize - 1].clone()
+        } else {
+            b"<unknown>".to_vec()
+        };
+        HeapClassStats { class_name, count, bytes }
+    }).collect();
+    result.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.class_name.cmp(&b.class_name)));
+    let totals = HeapTotals {
+        classes: result.len() as i64,
+        count: result.iter().map(|x| x.count).sum(),
+        bytes: result.iter().map(|x| x.bytes).sum(),
+    };
+    if limit > 0 {
+        result.truncate(limit);
+    }
+    Ok((result, totals))
+}
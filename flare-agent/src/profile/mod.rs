@@ -2,4 +2,14 @@
 pub mod sample;
 mod tree;
 mod encoder;
-mod server;
\ No newline at end of file
+mod server;
+mod recorder;
+mod deadlock;
+mod thread_dump;
+mod heap_histogram;
+pub mod deopt;
+pub mod allocation;
+pub mod classloader;
+pub mod gc;
+pub mod diagnostic;
+pub mod clock;
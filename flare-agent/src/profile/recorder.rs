@@ -0,0 +1,80 @@
+//独立录制(agent参数 output=<目录>): 不监听分析服务的连接，把发送队列中的事件写入本地文件
+//  文件格式见 flare_proto::recording，之后用分析服务的 open_sample 导入
+//  JVM退出(VMDeath)时停止录制，写完队列中剩余的事件
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use chrono::Local;
+use flare_proto::recording::*;
+use profile::clock::now_millis;
+use profile::encoder::*;
+use profile::server::pop_sample_data;
+
+//定期刷新文件，进程被强制结束时最多丢失这段时间的事件
+const FLUSH_INTERVAL_MS: i64 = 1000;
+
+lazy_static! {
+    static ref RECORDER_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+//创建录制目录并启动写文件线程，返回录制目录
+pub fn start_recorder(output_dir: &str, start_time: i64, sample_interval: u64, capabilities: i64) -> io::Result<String> {
+    let dir_name = get_recording_dir_name(std::process::id(), &Local::now().format("%Y%m%dT%H%M%S").to_string());
+    let recording_dir = format!("{}/{}", output_dir, dir_name);
+    std::fs::create_dir_all(&recording_dir)?;
+    let mut writer = BufWriter::new(File::create(format!("{}/{}", recording_dir, RECORDING_EVENTS_FILE))?);
+    writer.write_all(resp_encode_hello(capabilities).encode().as_slice())?;
+    writer.write_all(resp_encode_sample_info(start_time, sample_interval, start_time).encode().as_slice())?;
+    writer.flush()?;
+
+    RECORDING.store(true, Ordering::SeqCst);
+    let handle = std::thread::Builder::new()
+        .name("flare-recorder".to_string())
+        .spawn(move || {
+            if let Err(e) = write_events(&mut writer) {
+                println!("write recording events failed: {}", e);
+            }
+        })?;
+    *RECORDER_THREAD.lock().unwrap() = Some(handle);
+    println!("Flare agent is recording to {}", recording_dir);
+    Ok(recording_dir)
+}
+
+//停止录制，等待写完剩余的事件
+pub fn stop_recorder() {
+    RECORDING.store(false, Ordering::SeqCst);
+    if let Some(handle) = RECORDER_THREAD.lock().unwrap().take() {
+        handle.join().ok();
+        println!("Flare agent recording is stopped.");
+    }
+}
+
+fn write_events(writer: &mut BufWriter<File>) -> io::Result<()> {
+    let mut last_flush_time = now_millis();
+    loop {
+        //先读取状态再取事件，停止之前加入的事件都会写入
+        let recording = RECORDING.load(Ordering::SeqCst);
+        match pop_sample_data() {
+            Some(sample_data) => writer.write_all(sample_data.encode().as_slice())?,
+            None => {
+                if !recording {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let now = now_millis();
+        if now - last_flush_time >= FLUSH_INTERVAL_MS {
+            writer.flush()?;
+            last_flush_time = now;
+        }
+    }
+    writer.flush()
+}
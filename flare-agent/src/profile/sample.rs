@@ -11,10 +11,21 @@ use profile::tree::{TreeArena, NodeId};
 use std::collections::hash_map::Entry;
 use time::Duration;
 use super::server::*;
-use chrono::Local;
+use profile::clock::now_millis;
 use profile::encoder::*;
 use std::sync::{Mutex, mpsc};
 use error::NativeError;
+use profile::deadlock::find_deadlocks;
+use profile::thread_dump::format_thread_dump;
+use profile::heap_histogram::{build_heap_histogram, HeapClassStats, HeapTotals};
+use profile::deopt::take_recompiles;
+use profile::classloader::{get_class_loader_stats, take_defining_stack};
+use profile::gc::take_gc_pauses;
+use profile::allocation;
+use profile::diagnostic::*;
+use profile::recorder::{start_recorder, stop_recorder};
+use flare_proto::names::decode_name;
+use flare_proto::handshake::{CAP_DEADLOCK, CAP_THREAD_DUMP, CAP_HEAP_HISTOGRAM, CAP_CLOCK_SYNC, CAP_ALLOCATION, CAP_CLASS_LOADER, CAP_GC, CAP_DIAGNOSTIC, CAP_SESSION_TAG};
 //use std::sync::mpsc::{Sender, Receiver};
 
 #[derive(Serialize, Deserialize)]
@@ -39,6 +50,8 @@ pub struct ThreadData {
     pub cpu_time: i64,
     pub cpu_time_delta: i64,
     pub sample_time: i64,
+    //agent第一次取样到该线程的时间，与id一起区分重用线程id的不同线程
+    pub start_time: i64,
     pub stacktrace: Vec<i64>,
     pub last_stack_frame: i64,
     pub last_stack_len: usize
@@ -55,6 +68,7 @@ impl ThreadData {
             cpu_time: 0,
             cpu_time_delta: 0,
             sample_time: 0,
+            start_time: 0,
             stacktrace: vec![],
             last_stack_frame: 0,
             last_stack_len:0
@@ -76,7 +90,8 @@ impl SampleData for ThreadData {
 #[derive(Clone)]
 pub struct MethodData {
     pub method_id: i64,
-    pub full_name: String,
+    //JVMTI返回的原始字节(modified UTF-8)，由分析服务解码
+    pub full_name: Vec<u8>,
     pub hits_count: u32
 //    pub source_file: String,
 //    pub line_num: u16
@@ -92,6 +107,256 @@ impl SampleData for MethodData {
     }
 }
 
+//应用通过agent API添加的时间轴标记
+#[derive(Clone)]
+pub struct MarkerData {
+    pub time: i64,
+    pub label: String,
+    pub color: String,
+}
+
+impl SampleData for MarkerData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_marker_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "marker".to_string()
+    }
+}
+
+pub fn add_marker(label: &str, color: &str) {
+    add_sample_data(Box::new(MarkerData {
+        time: now_millis(),
+        label: label.to_string(),
+        color: color.to_string(),
+    }));
+}
+
+//应用通过agent API标记的阶段开始/结束事件
+#[derive(Clone)]
+pub struct IntervalData {
+    pub time: i64,
+    pub name: String,
+    pub begin: bool,
+    //调用API的线程，0表示未知
+    pub thread_id: i64,
+}
+
+impl SampleData for IntervalData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_interval_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        if self.begin { "interval_begin".to_string() } else { "interval_end".to_string() }
+    }
+}
+
+pub fn begin_interval(name: &str, thread_id: i64) {
+    add_sample_data(Box::new(IntervalData {
+        time: now_millis(),
+        name: name.to_string(),
+        begin: true,
+        thread_id,
+    }));
+}
+
+pub fn end_interval(thread_id: i64) {
+    add_sample_data(Box::new(IntervalData {
+        time: now_millis(),
+        name: "".to_string(),
+        begin: false,
+        thread_id,
+    }));
+}
+
+//死锁环中的一个线程
+#[derive(Clone)]
+pub struct DeadlockThreadData {
+    pub time: i64,
+    pub cycle: i64,
+    pub id: i64,
+    pub name: String,
+    pub lock: String,
+    pub owner_id: i64,
+    pub stacktrace: Vec<i64>,
+    //请求中的会话标签，定期检测时为空
+    pub tag: String,
+}
+
+impl SampleData for DeadlockThreadData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_deadlock_thread_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "deadlock_thread".to_string()
+    }
+}
+
+//jstack格式的线程dump
+#[derive(Clone)]
+pub struct ThreadDumpData {
+    pub time: i64,
+    pub threads: i64,
+    pub content: String,
+    pub tag: String,
+}
+
+impl SampleData for ThreadDumpData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_thread_dump_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "thread_dump".to_string()
+    }
+}
+
+//堆直方图
+pub struct HeapHistogramData {
+    pub time: i64,
+    pub force_gc: bool,
+    pub classes: Vec<HeapClassStats>,
+    pub totals: HeapTotals,
+    pub tag: String,
+}
+
+impl SampleData for HeapHistogramData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_heap_histogram_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "heap_histogram".to_string()
+    }
+}
+
+//线程在两次检查之间分配的字节数及检查时的调用栈
+pub struct AllocationData {
+    pub time: i64,
+    pub id: i64,
+    pub name: String,
+    pub bytes: i64,
+    pub stacktrace: Vec<i64>,
+}
+
+impl SampleData for AllocationData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_allocation_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "allocation".to_string()
+    }
+}
+
+//等待执行finalize()的对象数量
+pub struct FinalizerData {
+    pub time: i64,
+    pub pending: i64,
+}
+
+impl SampleData for FinalizerData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_finalizer_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "finalizer".to_string()
+    }
+}
+
+//方法的反优化(卸载后重新编译)次数
+pub struct DeoptimizationData {
+    pub time: i64,
+    pub method_id: i64,
+    pub count: i64,
+}
+
+impl SampleData for DeoptimizationData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_deoptimization_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "deoptimization".to_string()
+    }
+}
+
+//类加载器定义的类数量
+pub struct ClassLoaderData {
+    pub time: i64,
+    pub loader_id: i64,
+    pub loader_name: String,
+    pub classes: i64,
+    pub stacktrace: Vec<i64>,
+}
+
+impl SampleData for ClassLoaderData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_class_loader_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "class_loader".to_string()
+    }
+}
+
+//一次GC暂停
+pub struct GcData {
+    pub time: i64,
+    //us
+    pub duration: i64,
+}
+
+impl SampleData for GcData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_gc_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "gc".to_string()
+    }
+}
+
+//agent的诊断信息，见 profile::diagnostic
+pub struct DiagnosticData {
+    pub time: i64,
+    pub level: String,
+    pub kind: String,
+    pub message: String,
+    pub count: i64,
+}
+
+impl SampleData for DiagnosticData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_diagnostic_data(self).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "diagnostic".to_string()
+    }
+}
+
+//clock-sync请求的响应，发送时间在编码时记录
+pub struct ClockSyncData {
+    pub client_time: i64,
+    pub receive_time: i64,
+    pub tag: String,
+}
+
+impl SampleData for ClockSyncData {
+    fn encode(&self) -> Vec<u8> {
+        resp_encode_clock_sync_data(self, now_millis()).encode()
+    }
+
+    fn get_type(&self) -> String {
+        "clock_sync".to_string()
+    }
+}
+
 //#[derive(Clone)]
 pub struct ResponseData {
     cmd: String,
@@ -123,9 +388,37 @@ pub struct Sampler {
     sample_interval: u64,
     bind_host: String,
     bind_port: u16,
+    //独立录制的目录，为空时监听分析服务的连接
+    output_dir: String,
     start_time: i64,
     last_sample_time: i64,
     threads_map: HashMap<JavaLong, ThreadData>,
+    //定期检测死锁的间隔(ms)，0表示只按需检测
+    deadlock_interval: i64,
+    last_deadlock_check: i64,
+    //等待处理的请求中的会话标签，在响应中带回；共享连接的多个会话同时请求时每个标签都返回结果
+    deadlock_tags: Vec<String>,
+    thread_dump_tags: Vec<String>,
+    //(force_gc, limit, tag)，按请求顺序处理
+    heap_histogram_requests: Vec<(bool, usize, String)>,
+    //检查线程分配字节数的间隔(ms)，0表示不统计
+    allocation_interval: i64,
+    last_allocation_check: i64,
+    //线程上次检查时累计分配的字节数
+    thread_allocated_bytes: HashMap<JavaLong, i64>,
+    //读取finalizer积压数量的间隔(ms)，0表示不读取
+    finalizer_interval: i64,
+    last_finalizer_check: i64,
+    //上报重新编译次数的间隔(ms)，0表示不统计
+    deopt_interval: i64,
+    last_deopt_check: i64,
+    classloader_interval: i64,
+    last_classloader_check: i64,
+    gc_interval: i64,
+    last_gc_check: i64,
+    last_diagnostic_check: i64,
+    //JVMTI能力支持的控制请求(CAP_DEADLOCK等)，agent添加能力后设置
+    command_capabilities: i64,
     sender: Option<mpsc::Sender<resp::Value>>,
     receiver: Option<mpsc::Receiver<resp::Value>>,
 }
@@ -145,18 +438,44 @@ impl Sampler {
             sample_interval: 0,
             bind_host: "0.0.0.0".to_string(),
             bind_port: 3333,
+            output_dir: String::new(),
             start_time:0,
             last_sample_time:0,
             sender: None,
             receiver: None,
-            threads_map: HashMap::new()
+            threads_map: HashMap::new(),
+            deadlock_interval: 0,
+            last_deadlock_check: 0,
+            deadlock_tags: vec![],
+            thread_dump_tags: vec![],
+            heap_histogram_requests: vec![],
+            allocation_interval: 0,
+            last_allocation_check: 0,
+            thread_allocated_bytes: HashMap::new(),
+            finalizer_interval: 0,
+            last_finalizer_check: 0,
+            deopt_interval: 0,
+            last_deopt_check: 0,
+            classloader_interval: 0,
+            last_classloader_check: 0,
+            gc_interval: 0,
+            last_gc_check: 0,
+            last_diagnostic_check: 0,
+            command_capabilities: CAP_DEADLOCK | CAP_THREAD_DUMP | CAP_HEAP_HISTOGRAM,
         }
     }
 
     pub fn start(&mut self) {
         if(!self.running) {
             self.running = true;
-            self.start_time = Local::now().timestamp_millis();
+            self.start_time = now_millis();
+            //独立录制时没有分析服务的连接及控制请求
+            if !self.output_dir.is_empty() {
+                if let Err(e) = start_recorder(&self.output_dir, self.start_time, self.sample_interval, self.get_capabilities()) {
+                    println!("start recording failed: {}, output: {}", e, self.output_dir);
+                }
+                return;
+            }
 
             // 创建一个通道
             let (tx0, rx0): (mpsc::Sender<resp::Value>, mpsc::Receiver<resp::Value>) = mpsc::channel();
@@ -164,7 +483,7 @@ impl Sampler {
             self.receiver = Some(rx0);
             self.sender = Some(tx1);
 
-            get_server().lock().unwrap().set_options(tx0, rx1, self.start_time, self.sample_interval, &self.bind_host, self.bind_port);
+            get_server().lock().unwrap().set_options(tx0, rx1, self.start_time, self.sample_interval, &self.bind_host, self.bind_port, self.get_capabilities());
             //running server in new thread
             std::thread::spawn( move || {
                 start_server();
@@ -175,7 +494,11 @@ impl Sampler {
     pub fn stop(&mut self) {
         if(self.running){
             self.running = false;
-            stop_server();
+            if self.output_dir.is_empty() {
+                stop_server();
+            } else {
+                stop_recorder();
+            }
         }
     }
 
@@ -189,6 +512,53 @@ impl Sampler {
         self.bind_port = bind_port;
     }
 
+    pub fn set_output_dir(&mut self, output_dir: &str) {
+        self.output_dir = output_dir.to_string();
+    }
+
+    pub fn set_deadlock_interval(&mut self, deadlock_interval: i64) {
+        self.deadlock_interval = deadlock_interval;
+    }
+
+    pub fn set_allocation_interval(&mut self, allocation_interval: i64) {
+        self.allocation_interval = allocation_interval;
+    }
+
+    pub fn set_finalizer_interval(&mut self, finalizer_interval: i64) {
+        self.finalizer_interval = finalizer_interval;
+    }
+
+    pub fn set_deopt_interval(&mut self, deopt_interval: i64) {
+        self.deopt_interval = deopt_interval;
+    }
+
+    pub fn set_classloader_interval(&mut self, classloader_interval: i64) {
+        self.classloader_interval = classloader_interval;
+    }
+
+    pub fn set_gc_interval(&mut self, gc_interval: i64) {
+        self.gc_interval = gc_interval;
+    }
+
+    pub fn set_command_capabilities(&mut self, command_capabilities: i64) {
+        self.command_capabilities = command_capabilities;
+    }
+
+    //hello事件中报告的能力：开启的定期统计及JVMTI能力支持的控制请求，分析服务按此降级
+    pub fn get_capabilities(&self) -> i64 {
+        let mut capabilities = self.command_capabilities | CAP_CLOCK_SYNC | CAP_DIAGNOSTIC | CAP_SESSION_TAG;
+        if self.allocation_interval > 0 {
+            capabilities |= CAP_ALLOCATION;
+        }
+        if self.classloader_interval > 0 {
+            capabilities |= CAP_CLASS_LOADER;
+        }
+        if self.gc_interval > 0 {
+            capabilities |= CAP_GC;
+        }
+        capabilities
+    }
+
     pub fn get_sample_interval(&self) -> u64 {
         self.sample_interval
     }
@@ -210,11 +580,15 @@ impl Sampler {
 
     pub fn add_stack_traces(&mut self, jvmenv: &Box<Environment>, stack_traces: &Vec<JavaStackTrace>) {
         //merge to call stack tree
-        let now_time = Local::now().timestamp_millis();
+        let now_time = now_millis();
         self.last_sample_time = now_time;
         let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
         for (i, stack_info) in stack_traces.iter().enumerate() {
             let thread_info = &stack_info.thread;
+            //线程id被新的线程重用时重新开始计算
+            if self.threads_map.get(&thread_info.thread_id).map_or(false, |x| x.start_time != thread_info.start_time) {
+                self.threads_map.remove(&thread_info.thread_id);
+            }
             let mut is_new = false;
             let mut thread_data = self.threads_map.entry(thread_info.thread_id).or_insert_with(||{
                 is_new = true;
@@ -222,6 +596,7 @@ impl Sampler {
                 thd.priority = thread_info.priority;
                 thd.daemon = thread_info.is_daemon;
                 thd.cpu_time = stack_info.cpu_time;
+                thd.start_time = thread_info.start_time;
                 thd
             });
 
@@ -268,13 +643,294 @@ impl Sampler {
         add_sample_data_batch(sample_data_vec);
     }
 
+    //定期推送GC事件回调记录的暂停
+    pub fn check_gc_pauses(&mut self) {
+        let now_time = now_millis();
+        if self.gc_interval <= 0 || now_time - self.last_gc_check < self.gc_interval {
+            return;
+        }
+        self.last_gc_check = now_time;
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        for (time, duration) in take_gc_pauses() {
+            sample_data_vec.push(Box::new(GcData { time, duration }));
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //定期推送合并后的诊断信息
+    pub fn check_diagnostics(&mut self) {
+        let now_time = now_millis();
+        if now_time - self.last_diagnostic_check < DIAGNOSTIC_INTERVAL {
+            return;
+        }
+        self.last_diagnostic_check = now_time;
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        for x in take_diagnostics() {
+            sample_data_vec.push(Box::new(DiagnosticData { time: x.time, level: x.level, kind: x.kind, message: x.message, count: x.count }));
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //定期上报各个类加载器定义的类数量，新出现的类加载器附带创建时的调用栈
+    pub fn check_class_loaders(&mut self, jvmenv: &Box<Environment>) {
+        let now_time = now_millis();
+        if self.classloader_interval <= 0 || now_time - self.last_classloader_check < self.classloader_interval {
+            return;
+        }
+        self.last_classloader_check = now_time;
+
+        let loaders = match get_class_loader_stats(jvmenv) {
+            Ok(loaders) => loaders,
+            Err(e) => {
+                println!("get class loader stats failed: {:?}", e);
+                report_diagnostic(LEVEL_ERROR, KIND_JVMTI_ERROR, &format!("get class loader stats failed: {:?}", e), 1);
+                return;
+            }
+        };
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        for loader in loaders {
+            let mut stacktrace = vec![];
+            for method in take_defining_stack(loader.loader_id).unwrap_or_default() {
+                let method_info = self.get_method_info(jvmenv, method);
+                if method_info.hits_count == 1 {
+                    sample_data_vec.push(Box::new(method_info.clone()));
+                }
+                stacktrace.push(method_info.method_id);
+            }
+            sample_data_vec.push(Box::new(ClassLoaderData {
+                time: now_time,
+                loader_id: loader.loader_id,
+                loader_name: loader.loader_name,
+                classes: loader.classes,
+                stacktrace,
+            }));
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //按需或者定期检测死锁，结果推送到发送队列
+    pub fn check_deadlocks(&mut self, jvmenv: &Box<Environment>) {
+        let now_time = now_millis();
+        let scheduled = self.deadlock_interval > 0 && now_time - self.last_deadlock_check >= self.deadlock_interval;
+        if self.deadlock_tags.is_empty() && !scheduled {
+            return;
+        }
+        self.last_deadlock_check = now_time;
+        //定期检测的结果没有标签
+        let mut tags = std::mem::replace(&mut self.deadlock_tags, vec![]);
+        if tags.is_empty() {
+            tags.push(String::new());
+        }
+
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        for (cycle, threads) in find_deadlocks(jvmenv).iter().enumerate() {
+            for thread in threads {
+                let mut stacktrace = vec![];
+                for stack_frame in &thread.frames {
+                    let method_info = self.get_method_info(jvmenv, stack_frame.method);
+                    if method_info.hits_count == 1 {
+                        sample_data_vec.push(Box::new(method_info.clone()));
+                    }
+                    stacktrace.push(method_info.method_id);
+                }
+                println!("deadlock detected: cycle: {}, thread: [{}] {}, lock: {}, owner: {}", cycle, thread.thread_id, thread.name, thread.lock, thread.owner_id);
+                for tag in &tags {
+                    sample_data_vec.push(Box::new(DeadlockThreadData {
+                        time: now_time,
+                        cycle: cycle as i64,
+                        id: thread.thread_id,
+                        name: thread.name.clone(),
+                        lock: thread.lock.clone(),
+                        owner_id: thread.owner_id,
+                        stacktrace: stacktrace.clone(),
+                        tag: tag.clone(),
+                    }));
+                }
+            }
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //定期读取取样线程的累计分配字节数，把增量连同当前调用栈推送到发送队列
+    //  首次看到的线程只记录基准值；分配量按检查时的调用栈归属到方法，是统计意义上的估算
+    pub fn check_allocations(&mut self, jvmenv: &Box<Environment>, stack_traces: &Vec<JavaStackTrace>) {
+        let now_time = now_millis();
+        if self.allocation_interval <= 0 || now_time - self.last_allocation_check < self.allocation_interval {
+            return;
+        }
+        self.last_allocation_check = now_time;
+        if allocation::is_sampling_enabled() {
+            self.report_sampled_allocations(jvmenv, stack_traces, now_time);
+            return;
+        }
+
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        let mut alive_threads = HashSet::new();
+        for stack_info in stack_traces {
+            let thread_id = stack_info.thread.thread_id;
+            let allocated_bytes = match jvmenv.get_thread_allocated_bytes(thread_id) {
+                Some(x) => x,
+                None => continue
+            };
+            alive_threads.insert(thread_id);
+            let last_bytes = self.thread_allocated_bytes.insert(thread_id, allocated_bytes);
+            let bytes = match last_bytes {
+                Some(last_bytes) if allocated_bytes > last_bytes => allocated_bytes - last_bytes,
+                _ => continue
+            };
+            let mut stacktrace = vec![];
+            for stack_frame in &stack_info.frame_buffer {
+                let method_info = self.get_method_info(jvmenv, stack_frame.method);
+                if method_info.hits_count == 1 {
+                    sample_data_vec.push(Box::new(method_info.clone()));
+                }
+                stacktrace.push(method_info.method_id);
+            }
+            sample_data_vec.push(Box::new(AllocationData {
+                time: now_time,
+                id: thread_id,
+                name: stack_info.thread.name.clone(),
+                bytes,
+                stacktrace,
+            }));
+        }
+        //清除已结束的线程
+        self.thread_allocated_bytes.retain(|thread_id, _| alive_threads.contains(thread_id));
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //上报SampledObjectAlloc事件累计的分配取样，线程名称取自当前的线程调用栈，已结束的线程名称为空
+    fn report_sampled_allocations(&mut self, jvmenv: &Box<Environment>, stack_traces: &Vec<JavaStackTrace>, now_time: i64) {
+        let (samples, dropped) = allocation::take_samples();
+        if dropped > 0 {
+            report_diagnostic(LEVEL_WARN, KIND_DROPPED_EVENTS, "too many allocation stacks, the allocation samples are dropped", dropped);
+        }
+        let thread_names: HashMap<JavaLong, &String> = stack_traces.iter().map(|x| (x.thread.thread_id, &x.thread.name)).collect();
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        for (thread_id, frames, bytes) in samples {
+            let mut stacktrace = vec![];
+            for method in frames {
+                let method_info = self.get_method_info(jvmenv, method);
+                if method_info.hits_count == 1 {
+                    sample_data_vec.push(Box::new(method_info.clone()));
+                }
+                stacktrace.push(method_info.method_id);
+            }
+            sample_data_vec.push(Box::new(AllocationData {
+                time: now_time,
+                id: thread_id,
+                name: thread_names.get(&thread_id).map(|x| x.to_string()).unwrap_or_default(),
+                bytes,
+                stacktrace,
+            }));
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //定期读取等待执行finalize()的对象数量，不支持时停止读取
+    pub fn check_finalizer(&mut self, jvmenv: &Box<Environment>) {
+        let now_time = now_millis();
+        if self.finalizer_interval <= 0 || now_time - self.last_finalizer_check < self.finalizer_interval {
+            return;
+        }
+        self.last_finalizer_check = now_time;
+        match jvmenv.get_pending_finalization_count() {
+            Some(pending) => add_sample_data(Box::new(FinalizerData {
+                time: now_time,
+                pending: pending as i64,
+            })),
+            None => {
+                println!("get pending finalization count failed, stop checking");
+                self.finalizer_interval = 0;
+            }
+        }
+    }
+
+    //定期上报各个方法新增的重新编译次数
+    pub fn check_deoptimizations(&mut self, jvmenv: &Box<Environment>) {
+        let now_time = now_millis();
+        if self.deopt_interval <= 0 || now_time - self.last_deopt_check < self.deopt_interval {
+            return;
+        }
+        self.last_deopt_check = now_time;
+
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        for (method, count) in take_recompiles() {
+            let method_info = self.get_method_info(jvmenv, method);
+            if method_info.hits_count == 1 {
+                sample_data_vec.push(Box::new(method_info.clone()));
+            }
+            sample_data_vec.push(Box::new(DeoptimizationData {
+                time: now_time,
+                method_id: method_info.method_id,
+                count,
+            }));
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //按需获取完整线程dump，结果推送到发送队列
+    pub fn check_thread_dump(&mut self, jvmenv: &Box<Environment>) {
+        if self.thread_dump_tags.is_empty() {
+            return;
+        }
+        let tags = std::mem::replace(&mut self.thread_dump_tags, vec![]);
+
+        let mut sample_data_vec :Vec<Box<SampleData+Send>> = vec![];
+        let (threads, content) = {
+            let sample_data_vec = &mut sample_data_vec;
+            format_thread_dump(jvmenv, &mut |method| {
+                let method_info = self.get_method_info(jvmenv, method);
+                if method_info.hits_count == 1 {
+                    sample_data_vec.push(Box::new(method_info.clone()));
+                }
+                decode_name(&method_info.full_name)
+            })
+        };
+        println!("thread dump: threads: {}, size: {}", threads, content.len());
+        let now_time = now_millis();
+        for tag in tags {
+            sample_data_vec.push(Box::new(ThreadDumpData {
+                time: now_time,
+                threads: threads as i64,
+                content: content.clone(),
+                tag,
+            }));
+        }
+        add_sample_data_batch(sample_data_vec);
+    }
+
+    //按需统计堆直方图，结果推送到发送队列
+    pub fn check_heap_histogram(&mut self, jvmenv: &Box<Environment>) {
+        let requests = std::mem::replace(&mut self.heap_histogram_requests, vec![]);
+        for (force_gc, limit, tag) in requests {
+            match build_heap_histogram(jvmenv, force_gc, limit) {
+                Ok((classes, totals)) => {
+                    println!("heap histogram: classes: {}/{}, force_gc: {}", classes.len(), totals.classes, force_gc);
+                    add_sample_data(Box::new(HeapHistogramData {
+                        time: now_millis(),
+                        force_gc,
+                        classes,
+                        totals,
+                        tag,
+                    }));
+                },
+                Err(e) => {
+                    println!("build heap histogram failed: {:?}", e);
+                }
+            }
+        }
+    }
+
     fn get_method_info(&mut self, jvm_env: &Box<Environment>, method: JavaMethod) -> &MethodData {
         let method_data = self.method_cache.entry(method as usize).or_insert_with(|| {
             let method_id = MethodId { native_id: method };
-            let method_sig = jvm_env.get_method_name(&method_id).unwrap();
+            let method_name = jvm_env.get_method_name_bytes(&method_id).unwrap();
             let class_id = jvm_env.get_method_declaring_class(&method_id).unwrap();
-            let class = jvm_env.get_class_signature(&class_id).unwrap();
-            let full_name =  format!("{}.{}()", class.name, method_sig.name);
+            let mut full_name = jvm_env.get_class_name_bytes(&class_id).unwrap();
+            full_name.push(b'.');
+            full_name.extend_from_slice(&method_name);
+            full_name.extend_from_slice(b"()");
             MethodData{
                 method_id: method as i64,
                 full_name,
@@ -310,6 +966,27 @@ impl Sampler {
             "get_method_cache" => {
                 self.send_method_cache();
             }
+            "detect_deadlocks" => {
+                //interval: 定期检测的间隔(ms)，0 关闭定期检测
+                if let Some(resp::Value::Integer(interval)) = options.get("interval") {
+                    self.deadlock_interval = *interval;
+                }
+                self.deadlock_tags.push(get_request_tag(options));
+            }
+            "thread_dump" => {
+                self.thread_dump_tags.push(get_request_tag(options));
+            }
+            "heap_histogram" => {
+                let force_gc = match options.get("force_gc") {
+                    Some(resp::Value::Integer(x)) => *x != 0,
+                    _ => false
+                };
+                let limit = match options.get("limit") {
+                    Some(resp::Value::Integer(x)) if *x > 0 => *x as usize,
+                    _ => 0
+                };
+                self.heap_histogram_requests.push((force_gc, limit, get_request_tag(options)));
+            }
             _ => { println!("unknown request cmd: {}, options: {:?}", cmd, options); }
         }
     }
@@ -431,8 +1108,13 @@ impl SampleQueue {
         for sample_data in data_vec {
             self.queue.push_back(sample_data);
         }
+        let mut dropped = 0;
         while(self.queue.len() > 10000){
             self.queue.pop_front();
+            dropped += 1;
+        }
+        if dropped > 0 {
+            report_diagnostic(LEVEL_WARN, KIND_DROPPED_EVENTS, "sending queue is full, the oldest events are dropped", dropped);
         }
     }
 
@@ -441,7 +1123,7 @@ impl SampleQueue {
     }
 
     pub fn stats(&mut self) {
-        let now_time = Local::now().timestamp_millis();
+        let now_time = now_millis();
         if self.last_time > 0 {
             let delta = self.total_count-self.last_count;
             let rate = delta as f64 * 1000.0 / (now_time-self.last_time) as f64;
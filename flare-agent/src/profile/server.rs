@@ -1,395 +1,493 @@
-use std::thread;
-use std::net::{TcpListener, TcpStream, Shutdown};
-use std::io::{Read, Write};
-use resp::{Value, Decoder};
-use std::io::BufReader;
-use std::collections::HashMap;
-use std::sync::{Mutex, Arc, RwLock, mpsc};
-use std::collections::VecDeque;
-use super::sample::ThreadData;
-use profile::encoder::*;
-use profile::sample::*;
-use std::time::Duration;
-
-lazy_static! {
-    static ref DATA_QUEUE: Mutex<SampleQueue>  = Mutex::new(SampleQueue::new());
-    static ref SAMPLE_SERVER: Mutex<SampleServer>  = Mutex::new(SampleServer::new());
-}
-
-pub struct SampleServer {
-    sample_interval: u64,
-    start_time: i64,
-    running: bool,
-    bind_port: u16,
-    bind_host: String,
-    sender: Option<mpsc::Sender<resp::Value>>,
-    receiver: Option<mpsc::Receiver<resp::Value>>,
-}
-
-impl SampleServer {
-    pub fn new() -> SampleServer {
-        SampleServer {
-            sample_interval: 0,
-            start_time: 0,
-            running: false,
-            bind_port: 3333,
-            bind_host: "0.0.0.0".to_string(),
-            sender: None,
-            receiver: None,
-        }
-    }
-
-    pub fn set_options(&mut self, sender: mpsc::Sender<resp::Value>, receiver: mpsc::Receiver<resp::Value>, start_time: i64, sample_interval: u64, bind_host: &str, bind_port: u16) {
-        self.start_time = start_time;
-        self.sample_interval = sample_interval;
-        self.bind_host = bind_host.to_string();
-        self.bind_port = bind_port;
-        self.sender = Some(sender);
-        self.receiver = Some(receiver);
-    }
-
-    pub fn set_running(&mut self, val: bool) {
-        self.running = val;
-    }
-
-    pub fn is_running(&self) -> bool {
-        self.running
-    }
-
-    pub fn send_request(&self, request: resp::Value) {
-        if let Some(tx) = &self.sender {
-            tx.send(request);
-        }
-    }
-
-    pub fn recv_response(&self) -> Option<resp::Value> {
-        if let Some(rx) = &self.receiver {
-            match rx.recv_timeout(Duration::from_millis(50)) {
-                Ok(val) => {
-                    return Some(val);
-                },
-                Err(e) => {
-                    //println!("recv response from sample thread failed: {:?}", e);
-                },
-            }
-        }
-        None
-    }
-
-    pub fn get_bind_addr(&self) -> String {
-        format!("{}:{}", self.bind_host, self.bind_port)
-    }
-
-    pub fn get_bind_port(&self) -> u16 {
-        self.bind_port
-    }
-
-    pub fn get_bind_host(&self) -> String {
-        self.bind_host.to_string()
-    }
-}
-
-pub fn get_server() -> &'static Mutex<SampleServer> {
-    &SAMPLE_SERVER
-}
-
-pub fn add_sample_data(sample_data: Box<SampleData + Send>) {
-    let mut data_queue = DATA_QUEUE.lock().unwrap();
-    let mut queue = &mut data_queue.queue;
-    queue.push_back(sample_data);
-    while(queue.len() > 10000){
-        queue.pop_front();
-    }
-}
-
-pub fn add_sample_data_batch(data_vec: Vec<Box<SampleData + Send>>) {
-    let mut data_queue = DATA_QUEUE.lock().unwrap();
-    data_queue.push_back(data_vec);
-}
-
-fn set_server_running(val: bool) {
-    SAMPLE_SERVER.lock().unwrap().set_running(val);
-}
-
-pub fn is_server_running() -> bool {
-    SAMPLE_SERVER.lock().unwrap().is_running()
-}
-
-pub fn stop_server() {
-    set_server_running(false);
-    //make a new connection force tcp listener exit accept() blocking
-    let bind_port = SAMPLE_SERVER.lock().unwrap().get_bind_port();
-    let bind_host = SAMPLE_SERVER.lock().unwrap().get_bind_host();
-    let mut host = if bind_host == "0.0.0.0" {
-        "127.0.0.1".to_string()
-    } else {
-        bind_host
-    };
-    match TcpStream::connect(format!("{}:{}",  host, bind_port)) {
-        Ok(_) => {
-            println!("send notify to agent server ok");
-        },
-        Err(e) => {
-            println!("send notify to agent server failed: {}", e)
-        }
-    }
-}
-
-pub fn start_server() {
-    let timer = timer::Timer::new();
-    let guard = {
-        timer.schedule_repeating(chrono::Duration::milliseconds(3000), move || {
-            DATA_QUEUE.lock().unwrap().stats();
-        })
-    };
-
-    let bind_addr = SAMPLE_SERVER.lock().unwrap().get_bind_addr();
-    let listener = TcpListener::bind(&bind_addr).unwrap();
-    // accept connections and process them, spawning a new thread for each one
-    println!("Flare agent server listening on {}", bind_addr);
-    set_server_running(true);
-    let mut last_client_stream: Option<TcpStream> = None;
-    for stream in listener.incoming() {
-        if !is_server_running() {
-            println!("Flare agent server is stopping, exiting");
-            break;
-        }
-        match stream {
-            Ok(stream) => {
-                close_connection(&mut last_client_stream);
-
-                println!("New connection: {}", stream.peer_addr().unwrap());
-                //save last connection
-                match stream.try_clone() {
-                    Ok(stream_copy) => {
-                        last_client_stream = Some(stream_copy);
-                    },
-                    Err(e) => {
-                        println!("Clone stream failed: {}", e);
-                    }
-                }
-
-                thread::spawn(move || {
-                    // connection succeeded
-                    handle_client(stream)
-                });
-            }
-            Err(e) => {
-                println!("Error: {}", e);
-                /* connection failed */
-            }
-        }
-    }
-    //close last connection
-    close_connection(&mut last_client_stream);
-
-    // close the socket server
-    drop(listener);
-    drop(guard);
-    println!("Flare agent server is shutdown.");
-}
-
-fn close_connection(last_client_stream: &mut Option<TcpStream>) -> () {
-    //close prev connectiopn
-    if let Some(last_stream) = last_client_stream {
-        let mut peer_addr = "??".to_string();
-        match last_stream.peer_addr() {
-            Err(e) => {
-                println!("Get prev connection  peer_addr failed: {}", e);
-            },
-            Ok(addr) => {
-                println!("Flare agent is already connected to collector: {} ...", addr);
-                peer_addr = addr.to_string();
-            }
-        }
-        //check prev connection error
-        match last_stream.take_error() {
-            Ok(x) => {
-                if let Some(e) = x {
-                    println!("Prev connection is error: {}", e);
-                } else {
-                    println!("Closing prev connection: {} ...", peer_addr);
-                    last_stream.shutdown(Shutdown::Both);
-                }
-            },
-            Err(e) => {
-                println!("Get prev connection status failed: {}", e);
-            }
-        }
-    } else {
-        println!("Flare agent is idle.")
-    }
-    *last_client_stream = None;
-}
-
-fn handle_client(mut stream: TcpStream) {
-    let mut data = [0 as u8; 1024]; // using 1024 byte buffer
-    while match stream.read(&mut data) {
-        Ok(size) => {
-            let clientRequest = parse_request(&data[0..size]);
-            //dispatch request
-            dispatch_request(&mut stream, &clientRequest);
-
-            true
-        },
-        Err(e) => {
-            println!("An error occurred, terminating connection with {}, error: {}", stream.peer_addr().unwrap(), e);
-            stream.shutdown(Shutdown::Both).unwrap();
-            false
-        }
-    } {}
-}
-
-fn dispatch_request(stream: &mut TcpStream, clientRequest: &Value) {
-    //extract cmd string
-    let cmd_vec_result = match clientRequest {
-        Value::Array(vec) => {
-            let first = &vec[0];
-            match first {
-                Value::String(s) => {
-                    let cmd_options = parse_request_options(vec);
-                    Some((s, cmd_options))
-                },
-                _ => {
-                    println!("invalid request array, first element must be String, but get {:?}", first);
-                    None
-                }
-            }
-        },
-        _ => {
-            println!("invalid request, must be an resp array like [String, args1, args2..], but get {:?}", clientRequest);
-            None
-        }
-    };
-
-    //dispatch by cmd str
-    if let Some((cmd, cmd_options)) = cmd_vec_result {
-        match cmd.as_str() {
-            "resume-sample" => {
-                handle_resume_sample_cmd(stream, &cmd_options);
-            },
-            "pause-sample" => {
-                handle_pause_sample_cmd(stream, &cmd_options);
-            },
-            "stop-sample" => {
-                handle_stop_sample_cmd(stream, &cmd_options);
-            },
-            "subscribe-events" => {
-                handle_subscribe_events_cmd(stream, &cmd_options);
-            },
-            _ => { println!("unknown request cmd: {}, options: {:?}", cmd, cmd_options); }
-        }
-    }
-}
-
-pub fn parse_request_options(request: &Vec<Value>) -> HashMap<String, Value> {
-    let mut result = HashMap::new();
-    let mut i = 1;
-    while i < request.len()-1 {
-        let key = &request[i];
-        let value = &request[i+1];
-        match key {
-            Value::String(str) => {
-                result.insert(str.to_string(), value.clone());
-            },
-            _ => {
-                println!("invalid cmd option key, expect String but get: {:?}", key);
-            }
-        }
-        i+=2;
-    }
-    result
-}
-
-fn handle_resume_sample_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
-    //resume
-}
-
-fn handle_pause_sample_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
-    //pause
-}
-
-fn handle_stop_sample_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
-    stop_server();
-}
-
-fn handle_subscribe_events_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
-    println!("subscribe event loop start");
-
-    //send sample info
-//    let start_time = SAMPLE_SERVER.lock().unwrap().start_time;
-//    let sample_interval = SAMPLE_SERVER.lock().unwrap().sample_interval;
-//    let buf = resp_encode_sample_info(start_time, sample_interval);
-//    if let Err(e) = stream.write_all(buf.as_slice()) {
-//        println!("send sample info failed: {}", e);
-//        return;;
-//    }
-
-    //send sample info
-    println!("sending sample info to new client ..");
-    let request = Value::Array(vec![
-        Value::String("get_sample_info".to_string()),
-    ]);
-    SAMPLE_SERVER.lock().unwrap().send_request(request);
-    if let Some(response) = SAMPLE_SERVER.lock().unwrap().recv_response() {
-        if let Err(e) = stream.write_all(response.encode().as_slice()) {
-            println!("send sample info failed: {}", e);
-            return;
-        }
-    }else {
-        println!("recv get_sample_info result failed, stopping subscribe event")
-    }
-
-    //send current method cache
-    println!("sending method cache to new client ..");
-    let request = Value::Array(vec![
-        Value::String("get_method_cache".to_string()),
-    ]);
-    SAMPLE_SERVER.lock().unwrap().send_request(request);
-    //transmit method cache
-    let mut method_count = 0;
-    while let Some(response) = SAMPLE_SERVER.lock().unwrap().recv_response() {
-        if let Err(e) = stream.write_all(response.encode().as_slice()) {
-            println!("send method cache failed: {}", e);
-            //return; //recv all message in channel
-        }
-        method_count += 1;
-    }
-    println!("total sent method cache: {}", method_count);
-
-//    println!("recv get_sample_info result failed, stopping subscribe event")
-
-    println!("loop transmit data new client ..");
-    let mut sent = false;
-    loop {
-        //auto release lock while exit guard block
-        {
-            if let Some(sample_data) = DATA_QUEUE.lock().unwrap().pop_front() {
-                sent = true;
-                //encode and send sample data
-                let buf = sample_data.encode();
-                if let Err(e) = stream.write_all(buf.as_slice()) {
-                    println!("write sample data failed: {}", e);
-                    break;
-                }
-            }else {
-                sent = false;
-            }
-        }
-        if !sent {
-            std::thread::sleep(std::time::Duration::from_millis(10));
-        }
-    }
-    println!("subscribe event loop exit")
-}
-
-fn parse_request(buf: &[u8]) -> Value {
-    // echo everything!
-    //stream.write(&data[0..size]).unwrap();
-    //println!("client: {}", String::from_utf8_lossy(&data[0..size]));
-    let mut decoder = Decoder::new(BufReader::new(buf));
-    let clientRequest = decoder.decode().unwrap();
-    println!("request: {:?}", clientRequest);
-    clientRequest
-}
-
+use std::thread;
+use std::net::{TcpListener, TcpStream, Shutdown};
+use std::io::{Read, Write};
+use resp::{Value, Decoder};
+use std::io::BufReader;
+use std::collections::HashMap;
+use std::sync::{Mutex, Arc, RwLock, mpsc};
+use std::collections::VecDeque;
+use super::sample::ThreadData;
+use profile::encoder::*;
+use profile::sample::*;
+use profile::diagnostic::*;
+use std::time::Duration;
+use profile::clock::now_millis;
+use flare_proto::agent::TAG_PROPERTY;
+
+lazy_static! {
+    static ref DATA_QUEUE: Mutex<SampleQueue>  = Mutex::new(SampleQueue::new());
+    static ref SAMPLE_SERVER: Mutex<SampleServer>  = Mutex::new(SampleServer::new());
+}
+
+pub struct SampleServer {
+    sample_interval: u64,
+    start_time: i64,
+    running: bool,
+    bind_port: u16,
+    bind_host: String,
+    //hello事件中报告的能力(CAP_*)
+    capabilities: i64,
+    sender: Option<mpsc::Sender<resp::Value>>,
+    receiver: Option<mpsc::Receiver<resp::Value>>,
+}
+
+impl SampleServer {
+    pub fn new() -> SampleServer {
+        SampleServer {
+            sample_interval: 0,
+            start_time: 0,
+            running: false,
+            bind_port: 3333,
+            bind_host: "0.0.0.0".to_string(),
+            capabilities: 0,
+            sender: None,
+            receiver: None,
+        }
+    }
+
+    pub fn set_options(&mut self, sender: mpsc::Sender<resp::Value>, receiver: mpsc::Receiver<resp::Value>, start_time: i64, sample_interval: u64, bind_host: &str, bind_port: u16, capabilities: i64) {
+        self.start_time = start_time;
+        self.capabilities = capabilities;
+        self.sample_interval = sample_interval;
+        self.bind_host = bind_host.to_string();
+        self.bind_port = bind_port;
+        self.sender = Some(sender);
+        self.receiver = Some(receiver);
+    }
+
+    pub fn set_running(&mut self, val: bool) {
+        self.running = val;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn send_request(&self, request: resp::Value) {
+        if let Some(tx) = &self.sender {
+            tx.send(request);
+        }
+    }
+
+    pub fn recv_response(&self) -> Option<resp::Value> {
+        if let Some(rx) = &self.receiver {
+            match rx.recv_timeout(Duration::from_millis(50)) {
+                Ok(val) => {
+                    return Some(val);
+                },
+                Err(e) => {
+                    //println!("recv response from sample thread failed: {:?}", e);
+                },
+            }
+        }
+        None
+    }
+
+    pub fn get_bind_addr(&self) -> String {
+        format!("{}:{}", self.bind_host, self.bind_port)
+    }
+
+    pub fn get_bind_port(&self) -> u16 {
+        self.bind_port
+    }
+
+    pub fn get_capabilities(&self) -> i64 {
+        self.capabilities
+    }
+
+    pub fn get_bind_host(&self) -> String {
+        self.bind_host.to_string()
+    }
+}
+
+pub fn get_server() -> &'static Mutex<SampleServer> {
+    &SAMPLE_SERVER
+}
+
+pub fn add_sample_data(sample_data: Box<SampleData + Send>) {
+    let mut data_queue = DATA_QUEUE.lock().unwrap();
+    let mut queue = &mut data_queue.queue;
+    queue.push_back(sample_data);
+    let mut dropped = 0;
+    while(queue.len() > 10000){
+        queue.pop_front();
+        dropped += 1;
+    }
+    if dropped > 0 {
+        report_diagnostic(LEVEL_WARN, KIND_DROPPED_EVENTS, "sending queue is full, the oldest events are dropped", dropped);
+    }
+}
+
+//插入到发送队列的最前面，用于需要尽快发送的响应
+pub fn add_priority_sample_data(sample_data: Box<SampleData + Send>) {
+    DATA_QUEUE.lock().unwrap().queue.push_front(sample_data);
+}
+
+//独立录制时由写文件线程取出待发送的事件
+pub fn pop_sample_data() -> Option<Box<SampleData + Send>> {
+    DATA_QUEUE.lock().unwrap().pop_front()
+}
+
+pub fn add_sample_data_batch(data_vec: Vec<Box<SampleData + Send>>) {
+    let mut data_queue = DATA_QUEUE.lock().unwrap();
+    data_queue.push_back(data_vec);
+}
+
+fn set_server_running(val: bool) {
+    SAMPLE_SERVER.lock().unwrap().set_running(val);
+}
+
+pub fn is_server_running() -> bool {
+    SAMPLE_SERVER.lock().unwrap().is_running()
+}
+
+pub fn stop_server() {
+    set_server_running(false);
+    //make a new connection force tcp listener exit accept() blocking
+    let bind_port = SAMPLE_SERVER.lock().unwrap().get_bind_port();
+    let bind_host = SAMPLE_SERVER.lock().unwrap().get_bind_host();
+    let mut host = if bind_host == "0.0.0.0" {
+        "127.0.0.1".to_string()
+    } else {
+        bind_host
+    };
+    match TcpStream::connect(format!("{}:{}",  host, bind_port)) {
+        Ok(_) => {
+            println!("send notify to agent server ok");
+        },
+        Err(e) => {
+            println!("send notify to agent server failed: {}", e)
+        }
+    }
+}
+
+pub fn start_server() {
+    let timer = timer::Timer::new();
+    let guard = {
+        timer.schedule_repeating(chrono::Duration::milliseconds(3000), move || {
+            DATA_QUEUE.lock().unwrap().stats();
+        })
+    };
+
+    let bind_addr = SAMPLE_SERVER.lock().unwrap().get_bind_addr();
+    let listener = TcpListener::bind(&bind_addr).unwrap();
+    // accept connections and process them, spawning a new thread for each one
+    println!("Flare agent server listening on {}", bind_addr);
+    set_server_running(true);
+    let mut last_client_stream: Option<TcpStream> = None;
+    for stream in listener.incoming() {
+        if !is_server_running() {
+            println!("Flare agent server is stopping, exiting");
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                close_connection(&mut last_client_stream);
+
+                println!("New connection: {}", stream.peer_addr().unwrap());
+                //save last connection
+                match stream.try_clone() {
+                    Ok(stream_copy) => {
+                        last_client_stream = Some(stream_copy);
+                    },
+                    Err(e) => {
+                        println!("Clone stream failed: {}", e);
+                    }
+                }
+
+                thread::spawn(move || {
+                    // connection succeeded
+                    handle_client(stream)
+                });
+            }
+            Err(e) => {
+                println!("Error: {}", e);
+                /* connection failed */
+            }
+        }
+    }
+    //close last connection
+    close_connection(&mut last_client_stream);
+
+    // close the socket server
+    drop(listener);
+    drop(guard);
+    println!("Flare agent server is shutdown.");
+}
+
+fn close_connection(last_client_stream: &mut Option<TcpStream>) -> () {
+    //close prev connectiopn
+    if let Some(last_stream) = last_client_stream {
+        let mut peer_addr = "??".to_string();
+        match last_stream.peer_addr() {
+            Err(e) => {
+                println!("Get prev connection  peer_addr failed: {}", e);
+            },
+            Ok(addr) => {
+                println!("Flare agent is already connected to collector: {} ...", addr);
+                peer_addr = addr.to_string();
+            }
+        }
+        //check prev connection error
+        match last_stream.take_error() {
+            Ok(x) => {
+                if let Some(e) = x {
+                    println!("Prev connection is error: {}", e);
+                } else {
+                    println!("Closing prev connection: {} ...", peer_addr);
+                    last_stream.shutdown(Shutdown::Both);
+                }
+            },
+            Err(e) => {
+                println!("Get prev connection status failed: {}", e);
+            }
+        }
+    } else {
+        println!("Flare agent is idle.")
+    }
+    *last_client_stream = None;
+}
+
+fn handle_client(mut stream: TcpStream) {
+    let mut data = [0 as u8; 1024]; // using 1024 byte buffer
+    while match stream.read(&mut data) {
+        Ok(size) => {
+            let clientRequest = parse_request(&data[0..size]);
+            //dispatch request
+            dispatch_request(&mut stream, &clientRequest);
+
+            true
+        },
+        Err(e) => {
+            println!("An error occurred, terminating connection with {}, error: {}", stream.peer_addr().unwrap(), e);
+            stream.shutdown(Shutdown::Both).unwrap();
+            false
+        }
+    } {}
+}
+
+fn dispatch_request(stream: &mut TcpStream, clientRequest: &Value) {
+    //extract cmd string
+    let cmd_vec_result = match clientRequest {
+        Value::Array(vec) => {
+            let first = &vec[0];
+            match first {
+                Value::String(s) => {
+                    let cmd_options = parse_request_options(vec);
+                    Some((s, cmd_options))
+                },
+                _ => {
+                    println!("invalid request array, first element must be String, but get {:?}", first);
+                    None
+                }
+            }
+        },
+        _ => {
+            println!("invalid request, must be an resp array like [String, args1, args2..], but get {:?}", clientRequest);
+            None
+        }
+    };
+
+    //dispatch by cmd str
+    if let Some((cmd, cmd_options)) = cmd_vec_result {
+        match cmd.as_str() {
+            "resume-sample" => {
+                handle_resume_sample_cmd(stream, &cmd_options);
+            },
+            "pause-sample" => {
+                handle_pause_sample_cmd(stream, &cmd_options);
+            },
+            "stop-sample" => {
+                handle_stop_sample_cmd(stream, &cmd_options);
+            },
+            "subscribe-events" => {
+                handle_subscribe_events_cmd(stream, &cmd_options);
+            },
+            _ => { println!("unknown request cmd: {}, options: {:?}", cmd, cmd_options); }
+        }
+    }
+}
+
+pub fn parse_request_options(request: &Vec<Value>) -> HashMap<String, Value> {
+    let mut result = HashMap::new();
+    let mut i = 1;
+    while i < request.len()-1 {
+        let key = &request[i];
+        let value = &request[i+1];
+        match key {
+            Value::String(str) => {
+                result.insert(str.to_string(), value.clone());
+            },
+            _ => {
+                println!("invalid cmd option key, expect String but get: {:?}", key);
+            }
+        }
+        i+=2;
+    }
+    result
+}
+
+//控制请求中的会话标签，响应事件原样带回，没有时为空
+pub fn get_request_tag(options: &HashMap<String, Value>) -> String {
+    match options.get(TAG_PROPERTY) {
+        Some(Value::String(tag)) => tag.clone(),
+        _ => String::new()
+    }
+}
+
+fn handle_resume_sample_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
+    //resume
+}
+
+fn handle_pause_sample_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
+    //pause
+}
+
+fn handle_stop_sample_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
+    stop_server();
+}
+
+fn handle_subscribe_events_cmd(stream: &mut TcpStream, cmd_options: &HashMap<String, Value>) {
+    println!("subscribe event loop start");
+
+    //首先发送版本及能力，由客户端检查是否兼容；旧版本客户端不带版本参数，会忽略hello事件
+    match (cmd_options.get("client_version"), cmd_options.get("proto_version")) {
+        (Some(Value::String(client_version)), Some(Value::Integer(proto_version))) => {
+            println!("client version: {}, agent protocol: {}", client_version, proto_version);
+        }
+        _ => println!("client does not report its version, maybe an old flare-server")
+    }
+    let capabilities = SAMPLE_SERVER.lock().unwrap().get_capabilities();
+    if let Err(e) = stream.write_all(resp_encode_hello(capabilities).encode().as_slice()) {
+        println!("send hello failed: {}", e);
+        return;
+    }
+
+    //send sample info
+//    let start_time = SAMPLE_SERVER.lock().unwrap().start_time;
+//    let sample_interval = SAMPLE_SERVER.lock().unwrap().sample_interval;
+//    let buf = resp_encode_sample_info(start_time, sample_interval);
+//    if let Err(e) = stream.write_all(buf.as_slice()) {
+//        println!("send sample info failed: {}", e);
+//        return;;
+//    }
+
+    //send sample info
+    println!("sending sample info to new client ..");
+    let request = Value::Array(vec![
+        Value::String("get_sample_info".to_string()),
+    ]);
+    SAMPLE_SERVER.lock().unwrap().send_request(request);
+    if let Some(response) = SAMPLE_SERVER.lock().unwrap().recv_response() {
+        if let Err(e) = stream.write_all(response.encode().as_slice()) {
+            println!("send sample info failed: {}", e);
+            return;
+        }
+    }else {
+        println!("recv get_sample_info result failed, stopping subscribe event")
+    }
+
+    //send current method cache
+    println!("sending method cache to new client ..");
+    let request = Value::Array(vec![
+        Value::String("get_method_cache".to_string()),
+    ]);
+    SAMPLE_SERVER.lock().unwrap().send_request(request);
+    //transmit method cache
+    let mut method_count = 0;
+    while let Some(response) = SAMPLE_SERVER.lock().unwrap().recv_response() {
+        if let Err(e) = stream.write_all(response.encode().as_slice()) {
+            println!("send method cache failed: {}", e);
+            //return; //recv all message in channel
+        }
+        method_count += 1;
+    }
+    println!("total sent method cache: {}", method_count);
+
+//    println!("recv get_sample_info result failed, stopping subscribe event")
+
+    //订阅之后继续读取客户端的控制请求
+    match stream.try_clone() {
+        Ok(request_stream) => {
+            thread::spawn(move || {
+                handle_control_requests(request_stream);
+            });
+        },
+        Err(e) => {
+            println!("Clone stream failed, control requests are disabled: {}", e);
+        }
+    }
+
+    println!("loop transmit data new client ..");
+    let mut sent = false;
+    loop {
+        //auto release lock while exit guard block
+        {
+            if let Some(sample_data) = DATA_QUEUE.lock().unwrap().pop_front() {
+                sent = true;
+                //encode and send sample data
+                let buf = sample_data.encode();
+                if let Err(e) = stream.write_all(buf.as_slice()) {
+                    println!("write sample data failed: {}", e);
+                    break;
+                }
+            }else {
+                sent = false;
+            }
+        }
+        if !sent {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+    println!("subscribe event loop exit")
+}
+
+//转发给取样线程处理的控制请求
+const CONTROL_REQUESTS: &[&str] = &["detect-deadlocks", "thread-dump", "heap-histogram"];
+const CLOCK_SYNC_REQUEST: &str = "clock-sync";
+
+fn handle_control_requests(stream: TcpStream) {
+    let mut decoder = Decoder::new(BufReader::new(stream));
+    while let Ok(request) = decoder.decode() {
+        match &request {
+            Value::Array(vec) if vec.len() > 0 => {
+                match &vec[0] {
+                    //时钟同步在接收线程中直接记录收到的时间，响应放到发送队列的最前面，发送时记录发送时间
+                    Value::String(cmd) if cmd == CLOCK_SYNC_REQUEST => {
+                        let receive_time = now_millis();
+                        let options = parse_request_options(vec);
+                        let client_time = match options.get("client_time") {
+                            Some(Value::Integer(x)) => *x,
+                            _ => 0
+                        };
+                        add_priority_sample_data(Box::new(ClockSyncData { client_time, receive_time, tag: get_request_tag(&options) }));
+                        continue;
+                    },
+                    _ => {}
+                }
+                println!("control request: {:?}", request);
+                match &vec[0] {
+                    Value::String(cmd) if CONTROL_REQUESTS.contains(&cmd.as_str()) => {
+                        let mut request_vec = vec.clone();
+                        request_vec[0] = Value::String(cmd.replace("-", "_"));
+                        SAMPLE_SERVER.lock().unwrap().send_request(Value::Array(request_vec));
+                    },
+                    _ => { println!("unknown control request: {:?}", request); }
+                }
+            },
+            _ => { println!("invalid control request, must be an resp array, but get {:?}", request); }
+        }
+    }
+    println!("control request loop exit")
+}
+
+fn parse_request(buf: &[u8]) -> Value {
+    // echo everything!
+    //stream.write(&data[0..size]).unwrap();
+    //println!("client: {}", String::from_utf8_lossy(&data[0..size]));
+    let mut decoder = Decoder::new(BufReader::new(buf));
+    let clientRequest = decoder.decode().unwrap();
+    println!("request: {:?}", clientRequest);
+    clientRequest
+}
+
@@ -0,0 +1,109 @@
+
+//jstack格式的完整线程dump：所有线程的调用栈、等待及持有的监视器(在加锁的栈帧下列出)、持有的ownable synchronizers
+//  ownable synchronizers(java.util.concurrent的锁)不在JVMTI监视器信息中，通过ThreadMXBean获取，不可用时省略
+
+use environment::Environment;
+use native::{JavaMethod, JavaObject, JavaInt};
+use native::jvmti_native::*;
+use chrono::Local;
+
+//JVMTI线程状态 => java.lang.Thread.State
+pub fn get_java_thread_state(state: u32) -> &'static str {
+    match state & JVMTI_JAVA_LANG_THREAD_STATE_MASK {
+        JVMTI_JAVA_LANG_THREAD_STATE_NEW => "NEW",
+        JVMTI_JAVA_LANG_THREAD_STATE_TERMINATED => "TERMINATED",
+        JVMTI_JAVA_LANG_THREAD_STATE_RUNNABLE => "RUNNABLE",
+        JVMTI_JAVA_LANG_THREAD_STATE_BLOCKED => "BLOCKED",
+        JVMTI_JAVA_LANG_THREAD_STATE_WAITING => "WAITING",
+        JVMTI_JAVA_LANG_THREAD_STATE_TIMED_WAITING => "TIMED_WAITING",
+        _ => "UNKNOWN"
+    }
+}
+
+fn get_object_class_name(jvmenv: &Box<Environment>, object: &JavaObject) -> String {
+    let class_id = jvmenv.get_object_class(object);
+    let name = jvmenv.get_class_signature(&class_id).map(|x| x.name).unwrap_or_default();
+    jvmenv.delete_local_ref(class_id.native_id);
+    name
+}
+
+//<identity hash> (a 类名)，同一个对象在各个线程中相同，用于对照等待及持有的锁
+fn format_lock(hash_code: JavaInt, class_name: &str) -> String {
+    format!("<0x{:08x}> (a {})", hash_code, class_name)
+}
+
+fn format_monitor(jvmenv: &Box<Environment>, monitor: &JavaObject) -> String {
+    format_lock(jvmenv.get_object_hash_code(monitor).unwrap_or(0), &get_object_class_name(jvmenv, monitor))
+}
+
+//返回线程数量及dump文本，method_name 翻译调用栈中的方法
+pub fn format_thread_dump(jvmenv: &Box<Environment>, method_name: &mut FnMut(JavaMethod) -> String) -> (usize, String) {
+    let threads = match jvmenv.get_all_threads() {
+        Ok(threads) => threads,
+        Err(e) => {
+            println!("get_all_threads failed: {:?}", e);
+            return (0, String::new());
+        }
+    };
+
+    let synchronizers = jvmenv.get_locked_synchronizers();
+    let mut content = format!("{}\nFull thread dump (flare agent):\n\n", Local::now().format("%Y-%m-%d %H:%M:%S"));
+    let mut thread_count = 0;
+    for thread in threads {
+        let thread_info = match jvmenv.get_thread_info_ex(&thread.native_id) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("get_thread_info_ex failed: {:?}, native_thread_id: {:?}", e, thread.native_id);
+                jvmenv.delete_local_ref(thread.native_id);
+                continue;
+            }
+        };
+        thread_count += 1;
+        let state = jvmenv.get_thread_state(&thread.native_id).map(get_java_thread_state).unwrap_or("UNKNOWN");
+        content.push_str(&format!("\"{}\" #{}{} prio={}\n   java.lang.Thread.State: {}\n",
+            thread_info.name, thread_info.thread_id, if thread_info.is_daemon { " daemon" } else { "" }, thread_info.priority, state));
+
+        //持有的监视器及加锁的栈帧深度，不支持时列在调用栈之后；深度为-1的是通过JNI进入的监视器
+        let (frame_monitors, other_monitors) = match jvmenv.get_owned_monitor_stack_depth_info(&thread.native_id) {
+            Ok(monitors) => monitors.into_iter().partition(|x| x.1 >= 0),
+            Err(_) => (vec![], jvmenv.get_owned_monitors(&thread.native_id).unwrap_or_default().into_iter().map(|x| (x, -1)).collect::<Vec<_>>())
+        };
+        let frames = jvmenv.get_stack_trace(&thread.native_id).unwrap_or_default();
+        for (i, frame) in frames.iter().enumerate() {
+            content.push_str(&format!("\tat {}\n", method_name(frame.method)));
+            //等待进入的监视器在栈顶
+            if i == 0 {
+                if let Ok(Some(monitor)) = jvmenv.get_current_contended_monitor(&thread.native_id) {
+                    content.push_str(&format!("\t- waiting to lock {}\n", format_monitor(jvmenv, &monitor)));
+                    jvmenv.delete_local_ref(monitor);
+                }
+            }
+            for (monitor, _) in frame_monitors.iter().filter(|x| x.1 == i as JavaInt) {
+                content.push_str(&format!("\t- locked {}\n", format_monitor(jvmenv, monitor)));
+            }
+        }
+        for (monitor, _) in frame_monitors {
+            jvmenv.delete_local_ref(monitor);
+        }
+
+        if !other_monitors.is_empty() {
+            content.push_str("\n   Locked monitors:\n");
+            for (monitor, _) in other_monitors {
+                content.push_str(&format!("\t- locked {}\n", format_monitor(jvmenv, &monitor)));
+                jvmenv.delete_local_ref(monitor);
+            }
+        }
+        if let Some(synchronizers) = &synchronizers {
+            content.push_str("\n   Locked ownable synchronizers:\n");
+            match synchronizers.get(&thread_info.thread_id) {
+                Some(locks) => for (class_name, hash_code) in locks {
+                    content.push_str(&format!("\t- {}\n", format_lock(*hash_code, class_name)));
+                },
+                None => content.push_str("\t- None\n")
+            }
+        }
+        content.push_str("\n");
+        jvmenv.delete_local_ref(thread.native_id);
+    }
+    (thread_count, content)
+}
@@ -18,3 +18,17 @@ pub fn stringify(input: RawString) -> String {
         }
     }
 }
+
+///
+/// Returns the raw bytes of a C-style string pointer without UTF-8 validation, JVMTI returns
+/// names in modified UTF-8. If the string pointer points to NULL, then "(NULL)" will be returned.
+///
+pub fn bytify(input: RawString) -> Vec<u8> {
+    unsafe {
+        if input != ptr::null_mut() {
+            CStr::from_ptr(input).to_bytes().to_vec()
+        } else {
+            b"(NULL)".to_vec()
+        }
+    }
+}
@@ -0,0 +1,61 @@
+package com.kylixs.flare;
+
+/**
+ * Application side API of flare agent, events are sent to flare server with samples.
+ * If the agent is not loaded, all methods do nothing.
+ */
+public class Flare {
+    private static volatile boolean available = true;
+
+    /**
+     * Add a marker on the timeline, e.g. deployment or load-test phase.
+     */
+    public static void addMarker(String label, String color) {
+        if (!available) {
+            return;
+        }
+        try {
+            addMarker0(label, color);
+        } catch (UnsatisfiedLinkError e) {
+            available = false;
+        }
+    }
+
+    public static void addMarker(String label) {
+        addMarker(label, "");
+    }
+
+    /**
+     * Begin a named phase, e.g. benchmark warm-up / measurement. Phases can be nested.
+     */
+    public static void begin(String name) {
+        if (!available) {
+            return;
+        }
+        try {
+            begin0(name);
+        } catch (UnsatisfiedLinkError e) {
+            available = false;
+        }
+    }
+
+    /**
+     * End the latest begun phase.
+     */
+    public static void end() {
+        if (!available) {
+            return;
+        }
+        try {
+            end0();
+        } catch (UnsatisfiedLinkError e) {
+            available = false;
+        }
+    }
+
+    private static native void addMarker0(String label, String color);
+
+    private static native void begin0(String name);
+
+    private static native void end0();
+}
@@ -0,0 +1,750 @@
+
+//agent推送的取样事件
+//  sample_info:    start_time, sample_interval(ms), last_sample_time
+//  method:         id, name(bulk string，agent发送JVMTI返回的原始字节(modified UTF-8)，解码时按 names::decode_name 规范化)
+//  thread:         time, id, name, cpu_time(ns), cpu_time_delta(ns), state, stacktrace(方法ID数组，栈顶在前)
+//  marker:         time, label, color
+//  interval_begin: time, name, thread_id(调用API的线程)
+//  interval_end:   time, thread_id(结束该线程最近开始的阶段)
+//  thread_dump:    time, threads, content(jstack格式的文本，bulk string)
+//  heap_histogram: time, force_gc(0/1), classes(类名数组，与方法名相同，原始字节), counts(实例数量数组), bytes(字节数数组),
+//                  total_classes, total_count, total_bytes(按 limit 截断前的类数量及总数)
+//  deadlock_thread: time, cycle(同一次检测中的死锁环序号), id, name, state, lock(等待的监视器类名), owner_id(持有该监视器的线程), stacktrace
+//  diagnostic:     time, level(info/warn/error), kind(如 sampling_overrun、jvmti_error、dropped_events), message, count(合并的次数)
+//  clock_sync:     client_time(请求中的客户端时间), receive_time(agent收到请求的时间), send_time(agent发送响应的时间)
+//  hello:          proto_version, min_proto_version, agent_version, capabilities(订阅后的第一个事件，见 handshake)
+//控制请求带有 tag 参数时，agent在对应的响应事件(thread_dump、heap_histogram、deadlock_thread、clock_sync)中原样带回 tag 属性，
+//  一个agent连接上的多个会话按标签分发响应，见 with_event_tag / get_event_tag
+
+use resp::Value;
+use std::io;
+use super::names::decode_name;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SampleInfoEvent {
+    pub start_time: i64,
+    pub sample_interval: i64,
+    pub last_sample_time: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct MethodEvent {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ThreadEvent {
+    pub time: i64,
+    pub id: i64,
+    pub name: String,
+    pub cpu_time: i64,
+    pub cpu_time_delta: i64,
+    pub state: String,
+    pub stacktrace: Vec<i64>,
+    //agent第一次看到该线程的时间，线程id被新的线程重用时不同，0表示未知(旧版本agent)
+    #[serde(default)]
+    pub start_time: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct MarkerEvent {
+    pub time: i64,
+    pub label: String,
+    pub color: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct IntervalBeginEvent {
+    pub time: i64,
+    pub name: String,
+    //开始阶段的线程id，0表示未知(旧版本agent)
+    #[serde(default)]
+    pub thread_id: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct IntervalEndEvent {
+    pub time: i64,
+    //结束阶段的线程id，结束该线程最近开始的阶段
+    #[serde(default)]
+    pub thread_id: i64,
+}
+
+//一次死锁检测结果中的一个线程，同一个死锁环的线程按顺序连续发送
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DeadlockThreadEvent {
+    pub time: i64,
+    pub cycle: i64,
+    pub id: i64,
+    pub name: String,
+    pub state: String,
+    pub lock: String,
+    pub owner_id: i64,
+    pub stacktrace: Vec<i64>,
+}
+
+//按需获取的完整线程dump
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ThreadDumpEvent {
+    pub time: i64,
+    pub threads: i64,
+    pub content: String,
+}
+
+//堆中各个类的实例数量及占用字节数，按字节数从大到小排列
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HeapHistogramEvent {
+    pub time: i64,
+    pub force_gc: bool,
+    pub classes: Vec<String>,
+    pub counts: Vec<i64>,
+    pub bytes: Vec<i64>,
+    //截断前的类数量及总数，旧版本agent没有(为0)
+    #[serde(default)]
+    pub total_classes: i64,
+    #[serde(default)]
+    pub total_count: i64,
+    #[serde(default)]
+    pub total_bytes: i64,
+}
+
+//线程在两次检查之间分配的字节数，stacktrace为检查时的调用栈，用于按方法估算分配量
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AllocationEvent {
+    pub time: i64,
+    pub id: i64,
+    pub name: String,
+    pub bytes: i64,
+    pub stacktrace: Vec<i64>,
+}
+
+//等待执行finalize()的对象数量，定期推送
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FinalizerEvent {
+    pub time: i64,
+    pub pending: i64,
+}
+
+//方法的反优化次数，JVMTI没有反优化原因，Java agent按编译代码卸载后重新编译计数
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DeoptimizationEvent {
+    pub time: i64,
+    pub method: i64,
+    pub count: i64,
+}
+
+//定期统计的一个类加载器定义的类数量，id 为agent给类加载器分配的JVMTI tag(引导类加载器为0，旧版本agent为identity hash code)，
+//stacktrace 为类加载器定义第一个类时的调用栈，只在第一次上报时给出，不可用时为空
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ClassLoaderEvent {
+    pub time: i64,
+    pub id: i64,
+    pub name: String,
+    pub classes: i64,
+    pub stacktrace: Vec<i64>,
+}
+
+//一次stop-the-world的GC暂停，time 为开始时间(ms)，duration 单位为微秒
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GcEvent {
+    pub time: i64,
+    pub duration: i64,
+}
+
+//agent自身的诊断信息(取样超时、JVMTI调用失败、发送队列丢弃数据等)，同一类型在一个上报周期内合并为一条
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DiagnosticEvent {
+    pub time: i64,
+    pub level: String,
+    pub kind: String,
+    pub message: String,
+    pub count: i64,
+}
+
+//clock-sync请求的响应，客户端按NTP方式估算agent与本地的时钟偏差
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ClockSyncEvent {
+    pub client_time: i64,
+    pub receive_time: i64,
+    pub send_time: i64,
+}
+
+//agent的版本及能力，旧版本agent不发送
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HelloEvent {
+    pub proto_version: i64,
+    pub min_proto_version: i64,
+    pub agent_version: String,
+    pub capabilities: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AgentEvent {
+    SampleInfo(SampleInfoEvent),
+    Method(MethodEvent),
+    Thread(ThreadEvent),
+    Marker(MarkerEvent),
+    IntervalBegin(IntervalBeginEvent),
+    IntervalEnd(IntervalEndEvent),
+    DeadlockThread(DeadlockThreadEvent),
+    ThreadDump(ThreadDumpEvent),
+    HeapHistogram(HeapHistogramEvent),
+    Allocation(AllocationEvent),
+    Finalizer(FinalizerEvent),
+    Deoptimization(DeoptimizationEvent),
+    ClassLoader(ClassLoaderEvent),
+    Gc(GcEvent),
+    Diagnostic(DiagnosticEvent),
+    ClockSync(ClockSyncEvent),
+    Hello(HelloEvent),
+}
+
+impl AgentEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AgentEvent::SampleInfo(_) => "sample_info",
+            AgentEvent::Method(_) => "method",
+            AgentEvent::Thread(_) => "thread",
+            AgentEvent::Marker(_) => "marker",
+            AgentEvent::IntervalBegin(_) => "interval_begin",
+            AgentEvent::IntervalEnd(_) => "interval_end",
+            AgentEvent::DeadlockThread(_) => "deadlock_thread",
+            AgentEvent::ThreadDump(_) => "thread_dump",
+            AgentEvent::HeapHistogram(_) => "heap_histogram",
+            AgentEvent::Allocation(_) => "allocation",
+            AgentEvent::Finalizer(_) => "finalizer",
+            AgentEvent::Deoptimization(_) => "deoptimization",
+            AgentEvent::ClassLoader(_) => "class_loader",
+            AgentEvent::Gc(_) => "gc",
+            AgentEvent::Diagnostic(_) => "diagnostic",
+            AgentEvent::ClockSync(_) => "clock_sync",
+            AgentEvent::Hello(_) => "hello",
+        }
+    }
+
+    pub fn to_resp(&self) -> Value {
+        let mut encoder = RespEncoder::new(self.name());
+        match self {
+            AgentEvent::SampleInfo(x) => {
+                encoder.int("start_time", x.start_time).int("sample_interval", x.sample_interval).int("last_sample_time", x.last_sample_time);
+            }
+            AgentEvent::Method(x) => {
+                encoder.int("id", x.id).bulk("name", &x.name);
+            }
+            AgentEvent::Thread(x) => {
+                encoder.int("time", x.time).int("id", x.id).str("name", &x.name)
+                    .int("cpu_time", x.cpu_time).int("cpu_time_delta", x.cpu_time_delta)
+                    .str("state", &x.state).int_array("stacktrace", &x.stacktrace).int("start_time", x.start_time);
+            }
+            AgentEvent::Marker(x) => {
+                encoder.int("time", x.time).str("label", &x.label).str("color", &x.color);
+            }
+            AgentEvent::IntervalBegin(x) => {
+                encoder.int("time", x.time).str("name", &x.name).int("thread_id", x.thread_id);
+            }
+            AgentEvent::IntervalEnd(x) => {
+                encoder.int("time", x.time).int("thread_id", x.thread_id);
+            }
+            AgentEvent::DeadlockThread(x) => {
+                encoder.int("time", x.time).int("cycle", x.cycle).int("id", x.id).str("name", &x.name)
+                    .str("state", &x.state).str("lock", &x.lock).int("owner_id", x.owner_id)
+                    .int_array("stacktrace", &x.stacktrace);
+            }
+            AgentEvent::ThreadDump(x) => {
+                encoder.int("time", x.time).int("threads", x.threads).bulk("content", &x.content);
+            }
+            AgentEvent::HeapHistogram(x) => {
+                encoder.int("time", x.time).int("force_gc", x.force_gc as i64).bulk_array("classes", &x.classes)
+                    .int_array("counts", &x.counts).int_array("bytes", &x.bytes)
+                    .int("total_classes", x.total_classes).int("total_count", x.total_count).int("total_bytes", x.total_bytes);
+            }
+            AgentEvent::Allocation(x) => {
+                encoder.int("time", x.time).int("id", x.id).str("name", &x.name)
+                    .int("bytes", x.bytes).int_array("stacktrace", &x.stacktrace);
+            }
+            AgentEvent::Finalizer(x) => {
+                encoder.int("time", x.time).int("pending", x.pending);
+            }
+            AgentEvent::Deoptimization(x) => {
+                encoder.int("time", x.time).int("method", x.method).int("count", x.count);
+            }
+            AgentEvent::ClassLoader(x) => {
+                encoder.int("time", x.time).int("id", x.id).str("name", &x.name)
+                    .int("classes", x.classes).int_array("stacktrace", &x.stacktrace);
+            }
+            AgentEvent::Gc(x) => {
+                encoder.int("time", x.time).int("duration", x.duration);
+            }
+            AgentEvent::Diagnostic(x) => {
+                encoder.int("time", x.time).str("level", &x.level).str("kind", &x.kind)
+                    .bulk("message", &x.message).int("count", x.count);
+            }
+            AgentEvent::ClockSync(x) => {
+                encoder.int("client_time", x.client_time).int("receive_time", x.receive_time).int("send_time", x.send_time);
+            }
+            AgentEvent::Hello(x) => {
+                encoder.int("proto_version", x.proto_version).int("min_proto_version", x.min_proto_version)
+                    .str("agent_version", &x.agent_version).int("capabilities", x.capabilities);
+            }
+        }
+        encoder.finish()
+    }
+
+    //Ok(None)表示不认识的事件(新版本agent增加的事件)，调用方可以忽略
+    pub fn from_resp(value: &Value) -> io::Result<Option<AgentEvent>> {
+        let data_vec = match value {
+            Value::Array(x) if !x.is_empty() => x,
+            _ => return Err(new_invalid_data_error("agent event is not a non-empty array"))
+        };
+        let event = match &data_vec[0] {
+            Value::String(x) => x.as_str(),
+            _ => return Err(new_invalid_data_error("agent event name is not a string"))
+        };
+        let props = RespProperties { data_vec: &data_vec[1..] };
+        let event = match event {
+            "sample_info" => AgentEvent::SampleInfo(SampleInfoEvent {
+                start_time: props.int("start_time"),
+                sample_interval: props.int("sample_interval"),
+                last_sample_time: props.int("last_sample_time"),
+            }),
+            "method" => {
+                let id = props.get("id").and_then(as_int).ok_or_else(|| new_invalid_data_error("parse method id failed"))?;
+                let name = props.get("name").and_then(as_bytes).ok_or_else(|| new_invalid_data_error("parse method name failed"))?;
+                AgentEvent::Method(MethodEvent { id, name: decode_name(name) })
+            }
+            "thread" => AgentEvent::Thread(ThreadEvent {
+                time: props.int("time"),
+                id: props.int("id"),
+                name: props.str("name"),
+                cpu_time: props.int("cpu_time"),
+                cpu_time_delta: props.int("cpu_time_delta"),
+                state: props.str("state"),
+                stacktrace: props.int_array("stacktrace"),
+                start_time: props.int("start_time"),
+            }),
+            "marker" => AgentEvent::Marker(MarkerEvent {
+                time: props.int("time"),
+                label: props.str("label"),
+                color: props.str("color"),
+            }),
+            "interval_begin" => AgentEvent::IntervalBegin(IntervalBeginEvent {
+                time: props.int("time"),
+                name: props.str("name"),
+                thread_id: props.int("thread_id"),
+            }),
+            "interval_end" => AgentEvent::IntervalEnd(IntervalEndEvent {
+                time: props.int("time"),
+                thread_id: props.int("thread_id"),
+            }),
+            "deadlock_thread" => AgentEvent::DeadlockThread(DeadlockThreadEvent {
+                time: props.int("time"),
+                cycle: props.int("cycle"),
+                id: props.int("id"),
+                name: props.str("name"),
+                state: props.str("state"),
+                lock: props.str("lock"),
+                owner_id: props.int("owner_id"),
+                stacktrace: props.int_array("stacktrace"),
+            }),
+            "thread_dump" => AgentEvent::ThreadDump(ThreadDumpEvent {
+                time: props.int("time"),
+                threads: props.int("threads"),
+                content: props.str("content"),
+            }),
+            "heap_histogram" => AgentEvent::HeapHistogram(HeapHistogramEvent {
+                time: props.int("time"),
+                force_gc: props.int("force_gc") != 0,
+                classes: props.name_array("classes"),
+                counts: props.int_array("counts"),
+                bytes: props.int_array("bytes"),
+                total_classes: props.int("total_classes"),
+                total_count: props.int("total_count"),
+                total_bytes: props.int("total_bytes"),
+            }),
+            "allocation" => AgentEvent::Allocation(AllocationEvent {
+                time: props.int("time"),
+                id: props.int("id"),
+                name: props.str("name"),
+                bytes: props.int("bytes"),
+                stacktrace: props.int_array("stacktrace"),
+            }),
+            "finalizer" => AgentEvent::Finalizer(FinalizerEvent {
+                time: props.int("time"),
+                pending: props.int("pending"),
+            }),
+            "deoptimization" => AgentEvent::Deoptimization(DeoptimizationEvent {
+                time: props.int("time"),
+                method: props.int("method"),
+                count: props.int("count"),
+            }),
+            "class_loader" => AgentEvent::ClassLoader(ClassLoaderEvent {
+                time: props.int("time"),
+                id: props.int("id"),
+                name: props.str("name"),
+                classes: props.int("classes"),
+                stacktrace: props.int_array("stacktrace"),
+            }),
+            "gc" => AgentEvent::Gc(GcEvent {
+                time: props.int("time"),
+                duration: props.int("duration"),
+            }),
+            "diagnostic" => AgentEvent::Diagnostic(DiagnosticEvent {
+                time: props.int("time"),
+                level: props.str("level"),
+                kind: props.str("kind"),
+                message: props.str("message"),
+                count: props.int("count").max(1),
+            }),
+            "clock_sync" => AgentEvent::ClockSync(ClockSyncEvent {
+                client_time: props.int("client_time"),
+                receive_time: props.int("receive_time"),
+                send_time: props.int("send_time"),
+            }),
+            "hello" => AgentEvent::Hello(HelloEvent {
+                proto_version: props.int("proto_version"),
+                min_proto_version: props.int("min_proto_version"),
+                agent_version: props.str("agent_version"),
+                capabilities: props.int("capabilities"),
+            }),
+            _ => return Ok(None)
+        };
+        Ok(Some(event))
+    }
+}
+
+//agent发送的名称为JVMTI返回的原始字节，不能保证是有效的UTF-8，由分析服务解码
+pub fn encode_raw_method(id: i64, name: &[u8]) -> Value {
+    let mut encoder = RespEncoder::new("method");
+    encoder.int("id", id).bytes("name", name);
+    encoder.finish()
+}
+
+//totals: 截断前的(类数量, 实例数量, 字节数)
+pub fn encode_raw_heap_histogram(time: i64, force_gc: bool, classes: &[&[u8]], counts: &[i64], bytes: &[i64], totals: (i64, i64, i64)) -> Value {
+    let mut encoder = RespEncoder::new("heap_histogram");
+    encoder.int("time", time).int("force_gc", force_gc as i64).bytes_array("classes", classes)
+        .int_array("counts", counts).int_array("bytes", bytes)
+        .int("total_classes", totals.0).int("total_count", totals.1).int("total_bytes", totals.2);
+    encoder.finish()
+}
+
+pub const TAG_PROPERTY: &str = "tag";
+
+//在编码后的事件中加上会话标签，空标签不添加
+pub fn with_event_tag(value: Value, tag: &str) -> Value {
+    match value {
+        Value::Array(mut values) if !tag.is_empty() => {
+            values.push(Value::String(TAG_PROPERTY.to_string()));
+            values.push(Value::String(tag.to_string()));
+            Value::Array(values)
+        }
+        value => value
+    }
+}
+
+//事件中的会话标签，没有标签时返回None
+pub fn get_event_tag(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(values) if !values.is_empty() => {
+            let tag = RespProperties { data_vec: &values[1..] }.str(TAG_PROPERTY);
+            if tag.is_empty() { None } else { Some(tag) }
+        }
+        _ => None
+    }
+}
+
+struct RespEncoder {
+    values: Vec<Value>,
+}
+
+impl RespEncoder {
+    fn new(event: &str) -> RespEncoder {
+        RespEncoder { values: vec![Value::String(event.to_string())] }
+    }
+
+    fn int(&mut self, key: &str, value: i64) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::Integer(value));
+        self
+    }
+
+    fn str(&mut self, key: &str, value: &str) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::String(value.to_string()));
+        self
+    }
+
+    //可以包含换行的文本
+    fn bulk(&mut self, key: &str, value: &str) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::Bulk(value.to_string()));
+        self
+    }
+
+    fn bulk_array(&mut self, key: &str, values: &[String]) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::Array(values.iter().map(|x| Value::Bulk(x.clone())).collect()));
+        self
+    }
+
+    fn bytes(&mut self, key: &str, value: &[u8]) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::BufBulk(value.to_vec()));
+        self
+    }
+
+    fn bytes_array(&mut self, key: &str, values: &[&[u8]]) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::Array(values.iter().map(|x| Value::BufBulk(x.to_vec())).collect()));
+        self
+    }
+
+    fn int_array(&mut self, key: &str, values: &[i64]) -> &mut RespEncoder {
+        self.values.push(Value::String(key.to_string()));
+        self.values.push(Value::Array(values.iter().map(|x| Value::Integer(*x)).collect()));
+        self
+    }
+
+    fn finish(self) -> Value {
+        Value::Array(self.values)
+    }
+}
+
+//key/value交替排列的属性
+struct RespProperties<'a> {
+    data_vec: &'a [Value],
+}
+
+impl<'a> RespProperties<'a> {
+    fn get(&self, key: &str) -> Option<&'a Value> {
+        self.data_vec.chunks(2)
+            .find(|x| x.len() == 2 && as_str(&x[0]) == Some(key))
+            .map(|x| &x[1])
+    }
+
+    fn int(&self, key: &str) -> i64 {
+        self.get(key).and_then(as_int).unwrap_or(0)
+    }
+
+    fn str(&self, key: &str) -> String {
+        self.get(key).and_then(as_str).unwrap_or("").to_string()
+    }
+
+    fn int_array(&self, key: &str) -> Vec<i64> {
+        match self.get(key) {
+            Some(Value::Array(values)) => values.iter().filter_map(as_int).collect(),
+            _ => vec![]
+        }
+    }
+
+    //类名数组，按名称规范化
+    fn name_array(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::Array(values)) => values.iter().map(|x| decode_name(as_bytes(x).unwrap_or(b""))).collect(),
+            _ => vec![]
+        }
+    }
+}
+
+fn as_int(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(x) => Some(*x),
+        _ => None
+    }
+}
+
+fn as_str(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(x) => Some(x.as_str()),
+        Value::Bulk(x) => Some(x.as_str()),
+        Value::BufBulk(x) => std::str::from_utf8(x).ok(),
+        _ => None
+    }
+}
+
+fn as_bytes(value: &Value) -> Option<&[u8]> {
+    match value {
+        Value::String(x) => Some(x.as_bytes()),
+        Value::Bulk(x) => Some(x.as_bytes()),
+        Value::BufBulk(x) => Some(x.as_slice()),
+        _ => None
+    }
+}
+
+fn new_invalid_data_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::AGENT_PROTO_VERSION;
+
+    fn sample_events() -> Vec<AgentEvent> {
+        vec![
+            AgentEvent::SampleInfo(SampleInfoEvent { start_time: 1000, sample_interval: 20, last_sample_time: 2000 }),
+            AgentEvent::Method(MethodEvent { id: 7, name: "java.lang.Thread.run()V".to_string() }),
+            AgentEvent::Thread(ThreadEvent { time: 1020, id: 1, name: "main".to_string(), cpu_time: 500, cpu_time_delta: 20,
+                state: "RUNNABLE".to_string(), stacktrace: vec![9, 8, 7], start_time: 1000 }),
+            AgentEvent::Marker(MarkerEvent { time: 1030, label: "deploy".to_string(), color: "red".to_string() }),
+            AgentEvent::IntervalBegin(IntervalBeginEvent { time: 1040, name: "warmup".to_string(), thread_id: 1 }),
+            AgentEvent::IntervalEnd(IntervalEndEvent { time: 1050, thread_id: 1 }),
+            AgentEvent::DeadlockThread(DeadlockThreadEvent { time: 1060, cycle: 0, id: 2, name: "worker-1".to_string(),
+                state: "BLOCKED".to_string(), lock: "java.lang.Object".to_string(), owner_id: 3, stacktrace: vec![9, 7] }),
+            AgentEvent::HeapHistogram(HeapHistogramEvent { time: 1080, force_gc: true, classes: vec!["[B".to_string(), "java.lang.String".to_string()],
+                counts: vec![10, 20], bytes: vec![4096, 480], total_classes: 300, total_count: 1000, total_bytes: 65536 }),
+            AgentEvent::ThreadDump(ThreadDumpEvent { time: 1070, threads: 1, content: "\"main\" #1\n\tat java.lang.Thread.run()\n".to_string() }),
+            AgentEvent::Allocation(AllocationEvent { time: 1090, id: 1, name: "main".to_string(), bytes: 1 << 20, stacktrace: vec![9, 8, 7] }),
+            AgentEvent::Finalizer(FinalizerEvent { time: 1100, pending: 5000 }),
+            AgentEvent::Deoptimization(DeoptimizationEvent { time: 1110, method: 7, count: 2 }),
+            AgentEvent::ClassLoader(ClassLoaderEvent { time: 1120, id: 123456, name: "org.apache.catalina.loader.ParallelWebappClassLoader".to_string(), classes: 800, stacktrace: vec![9, 8] }),
+            AgentEvent::Gc(GcEvent { time: 1130, duration: 15_000 }),
+            AgentEvent::Diagnostic(DiagnosticEvent { time: 1140, level: "warn".to_string(), kind: "sampling_overrun".to_string(),
+                message: "sampling took 35ms, exceeds interval 20ms".to_string(), count: 3 }),
+            AgentEvent::ClockSync(ClockSyncEvent { client_time: 1150, receive_time: 1100, send_time: 1101 }),
+            AgentEvent::Hello(HelloEvent { proto_version: 3, min_proto_version: 1, agent_version: "0.1.0".to_string(), capabilities: 0xff }),
+            AgentEvent::ClassLoader(ClassLoaderEvent { time: 1120, id: 0, name: "<bootstrap>".to_string(), classes: 2000, stacktrace: vec![] }),
+        ]
+    }
+
+    //各版本的事件格式指纹：修改事件、属性或者属性的编码类型时需要递增 AGENT_PROTO_VERSION 并在此增加一项
+    const WIRE_FORMATS: &[(i32, u64)] = &[
+        (6, 0x3e63aa9f381c4137),
+    ];
+
+    fn resp_type(value: &Value) -> String {
+        match value {
+            Value::Integer(_) => "int".to_string(),
+            Value::String(_) => "str".to_string(),
+            Value::Bulk(_) | Value::BufBulk(_) => "bulk".to_string(),
+            Value::Array(items) => format!("[{}]", items.first().map(resp_type).unwrap_or_default()),
+            _ => "other".to_string(),
+        }
+    }
+
+    //事件名称、属性名称及编码类型
+    fn wire_format_fingerprint() -> (String, u64) {
+        let mut format = String::new();
+        let mut names = vec![];
+        for event in sample_events() {
+            if names.contains(&event.name()) {
+                continue;
+            }
+            names.push(event.name());
+            format.push_str(event.name());
+            if let Value::Array(items) = event.to_resp() {
+                for pair in items[1..].chunks(2) {
+                    if let (Value::String(key), Some(value)) = (&pair[0], pair.get(1)) {
+                        format.push_str(&format!(" {}:{}", key, resp_type(value)));
+                    }
+                }
+            }
+            format.push('\n');
+        }
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in format.as_bytes() {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        (format, hash)
+    }
+
+    #[test]
+    fn test_wire_format_version() {
+        let (format, fingerprint) = wire_format_fingerprint();
+        assert!(WIRE_FORMATS.contains(&(AGENT_PROTO_VERSION, fingerprint)),
+                "agent event format changed, bump AGENT_PROTO_VERSION and add ({}, {:#x}) to WIRE_FORMATS:\n{}", AGENT_PROTO_VERSION + 1, fingerprint, format);
+        for (i, x) in WIRE_FORMATS.iter().enumerate() {
+            assert!(WIRE_FORMATS[i + 1..].iter().all(|y| y.0 > x.0 && y.1 != x.1));
+        }
+    }
+
+    #[test]
+    fn test_resp_round_trip() {
+        let events = sample_events();
+        for event in &events {
+            let value = event.to_resp();
+            let decoded = AgentEvent::from_resp(&value).unwrap();
+            assert_eq!(decoded.as_ref(), Some(event));
+            //JSON表示用于文档及非RESP的客户端
+            let json = serde_json::to_string(event).unwrap();
+            assert!(json.starts_with(&format!("{{\"event\":\"{}\"", event.name())));
+            assert_eq!(&serde_json::from_str::<AgentEvent>(&json).unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn test_resp_compatibility() {
+        //旧格式编码，属性顺序不同且带有未知属性
+        let value = Value::Array(vec![
+            Value::String("thread".to_string()),
+            Value::String("stacktrace".to_string()),
+            Value::Array(vec![Value::Integer(3), Value::Integer(2)]),
+            Value::String("id".to_string()),
+            Value::Integer(11),
+            Value::String("gc_time".to_string()),
+            Value::Integer(5),
+        ]);
+        match AgentEvent::from_resp(&value).unwrap() {
+            Some(AgentEvent::Thread(x)) => {
+                assert_eq!(x.id, 11);
+                assert_eq!(x.stacktrace, vec![3, 2]);
+                assert_eq!(x.name, "");
+            }
+            x => panic!("unexpected event: {:?}", x)
+        }
+        let unknown = Value::Array(vec![Value::String("unknown_event".to_string())]);
+        assert_eq!(AgentEvent::from_resp(&unknown).unwrap(), None);
+        assert!(AgentEvent::from_resp(&Value::Integer(1)).is_err());
+        //with_buf_bulk解码的bulk string
+        let dump = Value::Array(vec![Value::String("thread_dump".to_string()), Value::String("content".to_string()), Value::BufBulk(b"a\r\nb".to_vec())]);
+        match AgentEvent::from_resp(&dump).unwrap() {
+            Some(AgentEvent::ThreadDump(x)) => assert_eq!(x.content, "a\r\nb"),
+            x => panic!("unexpected event: {:?}", x)
+        }
+        let method = Value::Array(vec![Value::String("method".to_string()), Value::String("id".to_string()), Value::Integer(1)]);
+        assert!(AgentEvent::from_resp(&method).is_err());
+        //带会话标签的响应可以按原来的格式解码
+        let dump = with_event_tag(AgentEvent::ThreadDump(ThreadDumpEvent { time: 1, threads: 2, content: "x".to_string() }).to_resp(), "s2");
+        assert_eq!(get_event_tag(&dump), Some("s2".to_string()));
+        match AgentEvent::from_resp(&dump).unwrap() {
+            Some(AgentEvent::ThreadDump(x)) => assert_eq!(x.threads, 2),
+            x => panic!("unexpected event: {:?}", x)
+        }
+        assert_eq!(get_event_tag(&with_event_tag(dump.clone(), "")), Some("s2".to_string()));
+        assert_eq!(get_event_tag(&with_event_tag(AgentEvent::Gc(GcEvent { time: 1, duration: 2 }).to_resp(), "")), None);
+        //方法名不是有效的UTF-8时不丢弃事件
+        let method = Value::Array(vec![Value::String("method".to_string()), Value::String("id".to_string()), Value::Integer(1),
+                                       Value::String("name".to_string()), Value::BufBulk(b"Foo.\xC0\x80bar\n()V".to_vec())]);
+        match AgentEvent::from_resp(&method).unwrap() {
+            Some(AgentEvent::Method(x)) => assert_eq!(x.name, "Foo.\\u{0000}bar\\u{000A}()V"),
+            x => panic!("unexpected event: {:?}", x)
+        }
+    }
+
+    #[test]
+    fn test_raw_names() {
+        //agent发送的原始字节经过RESP编码后可以解码，包括换行及无效的UTF-8
+        let mut data = encode_raw_method(3, b"Foo.\xC0\x80line\r\nbreak()").encode();
+        data.extend(encode_raw_heap_histogram(10, true, &[b"Bad\xFFName", b"java.lang.String"], &[1, 2], &[16, 32], (5, 10, 100)).encode());
+        let mut decoder = resp::Decoder::with_buf_bulk(std::io::BufReader::new(data.as_slice()));
+        match AgentEvent::from_resp(&decoder.decode().unwrap()).unwrap() {
+            Some(AgentEvent::Method(x)) => assert_eq!((x.id, x.name.as_str()), (3, "Foo.\\u{0000}line\\u{000D}\\u{000A}break()")),
+            x => panic!("unexpected event: {:?}", x)
+        }
+        match AgentEvent::from_resp(&decoder.decode().unwrap()).unwrap() {
+            Some(AgentEvent::HeapHistogram(x)) => {
+                assert_eq!(x.classes, vec!["Bad\\xFFName".to_string(), "java.lang.String".to_string()]);
+                assert_eq!(x.bytes, vec![16, 32]);
+                assert_eq!((x.total_classes, x.total_count, x.total_bytes), (5, 10, 100));
+            }
+            x => panic!("unexpected event: {:?}", x)
+        }
+        //测试工具等使用字符串编码的方法名也使用bulk string
+        let data = AgentEvent::Method(MethodEvent { id: 4, name: "a\nb".to_string() }).to_resp().encode();
+        let mut decoder = resp::Decoder::with_buf_bulk(std::io::BufReader::new(data.as_slice()));
+        assert!(decoder.decode().is_ok());
+    }
+}
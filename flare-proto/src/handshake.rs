@@ -0,0 +1,227 @@
+//agent连接握手: 分析服务在 subscribe-events 请求中带上自己的版本及能力，agent首先回复 hello 事件
+//  请求: ["subscribe-events", "proto_version", N, "min_proto_version", N, "client_version", "x.y.z", "capabilities", 位图]
+//  hello: proto_version, min_proto_version(能够兼容的最低客户端版本), agent_version, capabilities(CAP_* 位图)
+//旧版本agent忽略请求参数，不回复hello直接发送 sample_info，按版本1处理，能力未知时不检查控制请求
+//版本不兼容时连接失败，错误信息中给出需要升级的一方，而不是之后解码失败或者控制请求没有响应
+
+use resp::Value;
+use std::io;
+use super::agent::HelloEvent;
+use super::{AGENT_PROTO_VERSION, MIN_AGENT_PROTO_VERSION};
+
+pub const SUBSCRIBE_EVENTS_REQUEST: &str = "subscribe-events";
+
+//不发送hello的agent的版本
+pub const LEGACY_PROTO_VERSION: i64 = 1;
+//从这个版本开始agent只报告开启的能力(定期统计的选项及JVM授予的JVMTI能力)，之前的版本总是报告所有能力
+pub const ACTIVE_CAPABILITIES_PROTO_VERSION: i64 = 6;
+
+//agent能力位图，控制请求及可选的事件
+pub const CAP_DEADLOCK: i64 = 1 << 0;
+pub const CAP_THREAD_DUMP: i64 = 1 << 1;
+pub const CAP_HEAP_HISTOGRAM: i64 = 1 << 2;
+pub const CAP_CLOCK_SYNC: i64 = 1 << 3;
+pub const CAP_ALLOCATION: i64 = 1 << 4;
+pub const CAP_CLASS_LOADER: i64 = 1 << 5;
+pub const CAP_GC: i64 = 1 << 6;
+pub const CAP_DIAGNOSTIC: i64 = 1 << 7;
+//控制请求的响应带回会话标签(agent::with_event_tag)
+pub const CAP_SESSION_TAG: i64 = 1 << 8;
+
+pub const CAPABILITY_NAMES: &[(i64, &str)] = &[
+    (CAP_DEADLOCK, "detect-deadlocks"),
+    (CAP_THREAD_DUMP, "thread-dump"),
+    (CAP_HEAP_HISTOGRAM, "heap-histogram"),
+    (CAP_CLOCK_SYNC, "clock-sync"),
+    (CAP_ALLOCATION, "allocation"),
+    (CAP_CLASS_LOADER, "class-loader"),
+    (CAP_GC, "gc"),
+    (CAP_DIAGNOSTIC, "diagnostic"),
+    (CAP_SESSION_TAG, "session-tag"),
+];
+
+//当前版本支持的所有能力
+pub const ALL_CAPABILITIES: i64 = CAP_DEADLOCK | CAP_THREAD_DUMP | CAP_HEAP_HISTOGRAM | CAP_CLOCK_SYNC
+    | CAP_ALLOCATION | CAP_CLASS_LOADER | CAP_GC | CAP_DIAGNOSTIC | CAP_SESSION_TAG;
+
+//控制请求需要的能力，不需要检查时返回0
+pub fn request_capability(cmd: &str) -> i64 {
+    match CAPABILITY_NAMES.iter().find(|x| x.1 == cmd) {
+        Some((cap, _)) if *cap & (CAP_DEADLOCK | CAP_THREAD_DUMP | CAP_HEAP_HISTOGRAM | CAP_CLOCK_SYNC) != 0 => *cap,
+        _ => 0
+    }
+}
+
+pub fn capability_names(capabilities: i64) -> Vec<&'static str> {
+    CAPABILITY_NAMES.iter().filter(|x| capabilities & x.0 != 0).map(|x| x.1).collect()
+}
+
+pub fn new_subscribe_request(client_version: &str) -> Value {
+    Value::Array(vec![
+        Value::String(SUBSCRIBE_EVENTS_REQUEST.to_string()),
+        Value::String("proto_version".to_string()),
+        Value::Integer(AGENT_PROTO_VERSION as i64),
+        Value::String("min_proto_version".to_string()),
+        Value::Integer(MIN_AGENT_PROTO_VERSION as i64),
+        Value::String("client_version".to_string()),
+        Value::String(client_version.to_string()),
+        Value::String("capabilities".to_string()),
+        Value::Integer(ALL_CAPABILITIES),
+    ])
+}
+
+//握手结果
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AgentCompat {
+    pub proto_version: i64,
+    //旧版本agent为空
+    pub agent_version: String,
+    //None表示agent没有报告能力(旧版本)
+    pub capabilities: Option<i64>,
+    pub client_version: String,
+    //可以使用但是部分功能不可用时的提示
+    pub warning: Option<String>,
+}
+
+impl AgentCompat {
+    pub fn supports(&self, capability: i64) -> bool {
+        match self.capabilities {
+            Some(capabilities) => capabilities & capability == capability,
+            None => true
+        }
+    }
+
+    //控制请求是否支持，不支持时返回带升级或者检查JVM提示的错误
+    pub fn check_request(&self, cmd: &str) -> io::Result<()> {
+        let capability = request_capability(cmd);
+        if capability == 0 || self.supports(capability) {
+            return Ok(());
+        }
+        let advice = if self.proto_version >= ACTIVE_CAPABILITIES_PROTO_VERSION {
+            "the JVM does not grant the required JVMTI capabilities".to_string()
+        } else {
+            format!("upgrade flare-agent to {} and attach it again", self.client_version)
+        };
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("flare-agent {} (agent protocol {}) does not support request: {}, {}",
+                                                          self.display_version(), self.proto_version, cmd, advice)))
+    }
+
+    fn display_version(&self) -> &str {
+        if self.agent_version.is_empty() { "<unknown>" } else { &self.agent_version }
+    }
+}
+
+//检查agent版本，hello为None表示旧版本agent
+//  agent版本过旧或者要求更新的客户端时返回错误；可以降级使用时warning说明缺少的功能
+pub fn negotiate(hello: Option<&HelloEvent>, client_version: &str) -> io::Result<AgentCompat> {
+    let hello = match hello {
+        Some(hello) => hello,
+        None => {
+            return Ok(AgentCompat {
+                proto_version: LEGACY_PROTO_VERSION,
+                agent_version: String::new(),
+                capabilities: None,
+                client_version: client_version.to_string(),
+                warning: Some(format!("flare-agent does not report its version (agent protocol {}), some requests may not be supported, upgrade flare-agent to {}",
+                                      LEGACY_PROTO_VERSION, client_version)),
+            });
+        }
+    };
+    let server_proto_version = AGENT_PROTO_VERSION as i64;
+    if hello.proto_version < MIN_AGENT_PROTO_VERSION as i64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("flare-agent {} (agent protocol {}) is too old, flare-server {} requires agent protocol >= {}, upgrade flare-agent to {} and attach it again",
+                                                                    hello.agent_version, hello.proto_version, client_version, MIN_AGENT_PROTO_VERSION, client_version)));
+    }
+    if hello.min_proto_version > server_proto_version {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("flare-agent {} requires agent protocol >= {}, but flare-server {} supports agent protocol {}, upgrade flare-server to {} or later",
+                                                                    hello.agent_version, hello.min_proto_version, client_version, server_proto_version, hello.agent_version)));
+    }
+    let warning = if hello.proto_version > server_proto_version {
+        Some(format!("flare-agent {} (agent protocol {}) is newer than flare-server {} (agent protocol {}), new events are ignored, upgrade flare-server to {} to use them",
+                     hello.agent_version, hello.proto_version, client_version, server_proto_version, hello.agent_version))
+    } else if ALL_CAPABILITIES & !hello.capabilities != 0 && hello.proto_version >= ACTIVE_CAPABILITIES_PROTO_VERSION {
+        Some(format!("flare-agent {} has not enabled: {}, check the agent options (e.g. gc_interval) and the JVMTI capabilities of the JVM",
+                     hello.agent_version, capability_names(ALL_CAPABILITIES & !hello.capabilities).join(", ")))
+    } else if ALL_CAPABILITIES & !hello.capabilities != 0 {
+        Some(format!("flare-agent {} (agent protocol {}) does not support: {}, upgrade flare-agent to {}",
+                     hello.agent_version, hello.proto_version, capability_names(ALL_CAPABILITIES & !hello.capabilities).join(", "), client_version))
+    } else {
+        None
+    };
+    Ok(AgentCompat {
+        proto_version: hello.proto_version,
+        agent_version: hello.agent_version.clone(),
+        capabilities: Some(hello.capabilities),
+        client_version: client_version.to_string(),
+        warning,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::agent::AgentEvent;
+
+    fn new_hello(proto_version: i64, min_proto_version: i64, capabilities: i64) -> HelloEvent {
+        HelloEvent { proto_version, min_proto_version, agent_version: "0.2.0".to_string(), capabilities }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let hello = new_hello(AGENT_PROTO_VERSION as i64, MIN_AGENT_PROTO_VERSION as i64, ALL_CAPABILITIES);
+        let compat = negotiate(Some(&hello), "0.3.0").unwrap();
+        assert_eq!(compat.warning, None);
+        assert!(compat.check_request("thread-dump").is_ok());
+
+        //旧版本agent不检查控制请求
+        let compat = negotiate(None, "0.3.0").unwrap();
+        assert_eq!(compat.proto_version, LEGACY_PROTO_VERSION);
+        assert!(compat.warning.as_ref().unwrap().contains("upgrade flare-agent to 0.3.0"));
+        assert!(compat.check_request("heap-histogram").is_ok());
+
+        //旧版本agent缺少能力时降级，请求返回升级提示
+        let hello = new_hello(ACTIVE_CAPABILITIES_PROTO_VERSION - 1, 1, CAP_DEADLOCK | CAP_CLOCK_SYNC);
+        let compat = negotiate(Some(&hello), "0.3.0").unwrap();
+        assert!(compat.warning.as_ref().unwrap().contains("thread-dump, heap-histogram"));
+        assert!(compat.check_request("detect-deadlocks").is_ok());
+        assert!(compat.check_request("unknown-request").is_ok());
+        let err = compat.check_request("thread-dump").unwrap_err();
+        assert!(err.to_string().contains("upgrade flare-agent to 0.3.0"));
+
+        //新版本agent没有开启的选项或者JVM不支持的能力，不提示升级
+        let hello = new_hello(AGENT_PROTO_VERSION as i64, 1, ALL_CAPABILITIES & !(CAP_GC | CAP_HEAP_HISTOGRAM));
+        let compat = negotiate(Some(&hello), "0.3.0").unwrap();
+        let warning = compat.warning.clone().unwrap();
+        assert!(warning.contains("has not enabled: heap-histogram, gc") && !warning.contains("upgrade"), "{}", warning);
+        let err = compat.check_request("heap-histogram").unwrap_err();
+        assert!(err.to_string().contains("does not grant the required JVMTI capabilities"));
+
+        //更新的agent可以使用，新的事件被忽略
+        let hello = new_hello(AGENT_PROTO_VERSION as i64 + 1, 1, ALL_CAPABILITIES);
+        assert!(negotiate(Some(&hello), "0.3.0").unwrap().warning.unwrap().contains("upgrade flare-server"));
+
+        //agent要求更新的客户端
+        let hello = new_hello(AGENT_PROTO_VERSION as i64 + 2, AGENT_PROTO_VERSION as i64 + 1, ALL_CAPABILITIES);
+        let err = negotiate(Some(&hello), "0.3.0").unwrap_err();
+        assert!(err.to_string().contains("upgrade flare-server to 0.2.0"));
+        let hello = new_hello(MIN_AGENT_PROTO_VERSION as i64 - 1, 0, ALL_CAPABILITIES);
+        assert!(negotiate(Some(&hello), "0.3.0").unwrap_err().to_string().contains("upgrade flare-agent"));
+    }
+
+    #[test]
+    fn test_subscribe_request() {
+        let request = new_subscribe_request("0.3.0");
+        match &request {
+            Value::Array(vec) => {
+                assert_eq!(vec[0], Value::String(SUBSCRIBE_EVENTS_REQUEST.to_string()));
+                assert_eq!(vec.len(), 9);
+            }
+            _ => unreachable!()
+        }
+        let hello = AgentEvent::Hello(new_hello(3, 1, ALL_CAPABILITIES));
+        assert_eq!(AgentEvent::from_resp(&hello.to_resp()).unwrap(), Some(hello));
+        assert_eq!(capability_names(CAP_GC | CAP_DEADLOCK), vec!["detect-deadlocks", "gc"]);
+        assert_eq!(request_capability("clock-sync"), CAP_CLOCK_SYNC);
+        assert_eq!(request_capability("gc"), 0);
+    }
+}
@@ -0,0 +1,24 @@
+
+//flare agent、分析服务及前端之间的消息类型，第三方agent(非Java运行时)及其它前端可以依赖此crate实现协议
+//  agent: agent推送到分析服务的取样事件，RESP数组编码: [event, key1, value1, key2, value2, ...]
+//  ws:    前端与分析服务之间的websocket消息，JSON编码
+//兼容规则: 只增加新的事件及属性，不修改已有属性的含义；解码时忽略不认识的属性，缺少的属性使用默认值
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod agent;
+pub mod handshake;
+pub mod names;
+pub mod recording;
+pub mod ws;
+
+//agent事件格式版本，增加事件、属性或者修改属性的编码时递增(agent::tests::WIRE_FORMATS 检查)
+//  2: allocation
+//  3: finalizer、deoptimization、class_loader、gc、diagnostic、clock_sync、hello
+//  4: thread.start_time
+//  5: heap_histogram.total_classes/total_count/total_bytes
+//  6: interval_begin/interval_end.thread_id，method/heap_histogram 的名称使用bulk string，去掉 deoptimization.reason
+pub const AGENT_PROTO_VERSION: i32 = 6;
+//能够解码的最低agent事件格式版本，更旧的agent在连接时拒绝(见 handshake)
+pub const MIN_AGENT_PROTO_VERSION: i32 = 1;
@@ -0,0 +1,184 @@
+//方法名及类名的规范化：字节码中的名称可以包含控制字符、行分隔符等，JVM还使用 modified UTF-8 编码
+//  (U+0000 编码为 C0 80，增补字符编码为两个3字节的代理项)，直接转换为字符串会失败或者破坏JSON/JS的使用方
+//  解码时按 modified UTF-8 还原字符，无法解码的字节转义为 \xNN
+//  控制字符、行/段分隔符、BOM 及双向文本控制字符转义为 \u{XXXX}，反斜杠转义为 \\，unescape_name 可以还原原始字节
+//名称只在写入(agent事件解码、导入外部数据)时规范化一次，保存的名称不再重复转义
+
+use std::borrow::Cow;
+
+//按 modified UTF-8 解码并转义
+pub fn decode_name(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match std::str::from_utf8(&bytes[pos..]) {
+            Ok(s) => {
+                push_escaped(&mut result, s);
+                break;
+            }
+            Err(e) => {
+                let valid_len = e.valid_up_to();
+                push_escaped(&mut result, std::str::from_utf8(&bytes[pos..pos + valid_len]).unwrap_or(""));
+                pos += valid_len;
+                match decode_modified_utf8(&bytes[pos..]) {
+                    Some((c, len)) => {
+                        push_escaped_char(&mut result, c);
+                        pos += len;
+                    }
+                    None => {
+                        result.push_str(&format!("\\x{:02X}", bytes[pos]));
+                        pos += 1;
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+//转义已经是UTF-8的名称，不需要转义时不复制
+pub fn escape_name(name: &str) -> Cow<'_, str> {
+    if !name.chars().any(needs_escape) {
+        return Cow::Borrowed(name);
+    }
+    let mut result = String::with_capacity(name.len() + 8);
+    push_escaped(&mut result, name);
+    Cow::Owned(result)
+}
+
+//还原转义之前的字节，modified UTF-8 的字符还原为标准的UTF-8
+pub fn unescape_name(name: &str) -> Vec<u8> {
+    let mut result = Vec::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => result.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                result.push(u8::from_str_radix(&hex, 16).unwrap_or(0));
+            }
+            Some('u') => {
+                chars.next();
+                let hex: String = chars.by_ref().take_while(|x| *x != '}').collect();
+                let c = u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32).unwrap_or('\u{FFFD}');
+                let mut buf = [0u8; 4];
+                result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            Some(other) => {
+                result.push(b'\\');
+                let mut buf = [0u8; 4];
+                result.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            }
+            None => result.push(b'\\')
+        }
+    }
+    result
+}
+
+fn needs_escape(c: char) -> bool {
+    match c {
+        '\\' => true,
+        '\u{0}'..='\u{1F}' | '\u{7F}'..='\u{9F}' => true,
+        //行/段分隔符在JS字符串中是换行
+        '\u{2028}' | '\u{2029}' | '\u{FEFF}' => true,
+        //双向文本控制字符会改变显示顺序
+        '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' => true,
+        _ => false
+    }
+}
+
+fn push_escaped(result: &mut String, s: &str) {
+    for c in s.chars() {
+        push_escaped_char(result, c);
+    }
+}
+
+fn push_escaped_char(result: &mut String, c: char) {
+    if c == '\\' {
+        result.push_str("\\\\");
+    } else if needs_escape(c) {
+        result.push_str(&format!("\\u{{{:04X}}}", c as u32));
+    } else {
+        result.push(c);
+    }
+}
+
+//modified UTF-8 中与标准UTF-8不同的编码，返回(字符, 字节数)
+fn decode_modified_utf8(bytes: &[u8]) -> Option<(char, usize)> {
+    if bytes.len() >= 2 && bytes[0] == 0xC0 && bytes[1] == 0x80 {
+        return Some(('\u{0}', 2));
+    }
+    let high = decode_surrogate(bytes, 0xA0)?;
+    let low = decode_surrogate(bytes.get(3..)?, 0xB0)?;
+    let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+    std::char::from_u32(c).map(|c| (c, 6))
+}
+
+//3字节编码的代理项，高代理项第二个字节为 A0-AF，低代理项为 B0-BF
+fn decode_surrogate(bytes: &[u8], second_min: u8) -> Option<u32> {
+    if bytes.len() < 3 || bytes[0] != 0xED || bytes[1] < second_min || bytes[1] > second_min + 0x0F || bytes[2] & 0xC0 != 0x80 {
+        return None;
+    }
+    Some(0xD000 | ((bytes[1] as u32 & 0x3F) << 6) | (bytes[2] as u32 & 0x3F))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exotic_names() {
+        let names = [
+            "java.lang.Thread.run()V",
+            //Kotlin 反引号函数名及内部访问器
+            "com.example.CalculatorTest.adds two numbers()V",
+            "com.example.Box.access$getValue$p(Lcom/example/Box;)I",
+            //Scala 符号方法及伴生对象
+            "scala.collection.immutable.List.$colon$colon(Ljava/lang/Object;)Lscala/collection/immutable/List;",
+            "com.example.Main$.main([Ljava/lang/String;)V",
+            //生成的代理及lambda
+            "com.sun.proxy.$Proxy12.invoke()V",
+            "com.example.Service$$Lambda$123/0x0000000800c0b440.apply(Ljava/lang/Object;)Ljava/lang/Object;",
+            "com.example.Repo$$EnhancerBySpringCGLIB$$5f3a.save()V",
+            "中文.服务.处理()V",
+            "emoji.Test.\u{1F680}()V",
+        ];
+        for name in names.iter() {
+            assert_eq!(escape_name(name), Cow::Borrowed(*name));
+            assert_eq!(decode_name(name.as_bytes()), *name);
+            assert_eq!(unescape_name(name), name.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_escape_and_unescape() {
+        let name = "a\\b\u{0}c\nd\u{2028}e\u{202E}f\u{7F}";
+        let escaped = escape_name(name);
+        assert_eq!(escaped, "a\\\\b\\u{0000}c\\u{000A}d\\u{2028}e\\u{202E}f\\u{007F}");
+        assert!(!escaped.chars().any(|x| x.is_control()));
+        assert_eq!(unescape_name(&escaped), name.as_bytes());
+        assert_eq!(decode_name(name.as_bytes()), escaped);
+        //转义后的名称可以原样放入JSON及JS字符串
+        let json = serde_json::to_string(&escaped).unwrap();
+        assert!(!json.contains('\u{2028}'));
+    }
+
+    #[test]
+    fn test_modified_utf8() {
+        //U+0000 及增补字符 U+1F680 的 modified UTF-8 编码
+        let bytes = [b'a', 0xC0, 0x80, b'b', 0xED, 0xA0, 0xBD, 0xED, 0xBA, 0x80, b'c'];
+        assert_eq!(decode_name(&bytes), "a\\u{0000}b\u{1F680}c");
+        assert_eq!(unescape_name(&decode_name(&bytes)), "a\u{0}b\u{1F680}c".as_bytes());
+        //单独的代理项及无效的字节按字节转义，可以还原
+        let bytes = [b'x', 0xED, 0xA0, 0xBD, 0xFF, b'y'];
+        let decoded = decode_name(&bytes);
+        assert_eq!(decoded, "x\\xED\\xA0\\xBD\\xFFy");
+        assert_eq!(unescape_name(&decoded), bytes);
+        assert_eq!(decode_name(&[0xC3]), "\\xC3");
+    }
+}
@@ -0,0 +1,35 @@
+//agent独立录制: 不连接分析服务，把事件写入本地目录(agent参数 output=<目录>)，用于录制JVM启动过程及短时间运行的任务
+//  目录: <output>/flare-agent-<pid>-<yyyymmddTHHMMSS>/
+//    agent_events.resp  与订阅连接相同的RESP事件流，hello、sample_info 在最前面
+//  进程异常退出时文件末尾可能不完整，读取时忽略最后不完整的事件
+//分析服务的 open_sample 打开此目录时重放事件流，转换为取样目录
+
+use std::path::Path;
+
+pub const RECORDING_EVENTS_FILE: &str = "agent_events.resp";
+pub const RECORDING_DIR_PREFIX: &str = "flare-agent-";
+
+//start_time 格式: %Y%m%dT%H%M%S，与分析服务的取样目录一致
+pub fn get_recording_dir_name(pid: u32, start_time: &str) -> String {
+    format!("{}{}-{}", RECORDING_DIR_PREFIX, pid, start_time)
+}
+
+pub fn is_recording_dir<P: AsRef<Path>>(dir: P) -> bool {
+    dir.as_ref().join(RECORDING_EVENTS_FILE).is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_dir() {
+        assert_eq!(get_recording_dir_name(1234, "20191002T101010"), "flare-agent-1234-20191002T101010");
+        let dir = std::env::temp_dir().join(get_recording_dir_name(std::process::id(), "test"));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(!is_recording_dir(&dir));
+        std::fs::write(dir.join(RECORDING_EVENTS_FILE), b"").unwrap();
+        assert!(is_recording_dir(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+
+//前端与分析服务之间的websocket消息
+//  请求: {"cmd": "...", "options": {...}}
+//  响应: {"result": "success"|"failure", "cmd": "...", "data": ...}，失败时data为 {"message": "..."}
+//  连接建立后服务端首先发送 hello 消息，data 为 Capabilities
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+//websocket协议版本，新增命令或消息格式变化时递增
+pub const PROTOCOL_VERSION: i32 = 1;
+//能够兼容的最低前端协议版本
+pub const MIN_PROTOCOL_VERSION: i32 = 1;
+
+pub const RESULT_SUCCESS: &str = "success";
+pub const RESULT_FAILURE: &str = "failure";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FlareRequest {
+    pub cmd: String,
+    #[serde(default)]
+    pub options: Map<String, Value>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FlareResponse<T: ?Sized> {
+    pub result: String,
+    pub cmd: String,
+    pub data: Box<T>
+}
+
+impl<T: Serialize> FlareResponse<T> {
+    pub fn success(cmd: &str, data: T) -> FlareResponse<T> {
+        FlareResponse {
+            result: RESULT_SUCCESS.to_string(),
+            cmd: cmd.to_string(),
+            data: Box::new(data),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ErrorData {
+    pub message: String,
+}
+
+impl FlareResponse<ErrorData> {
+    pub fn failure(cmd: &str, message: &str) -> FlareResponse<ErrorData> {
+        FlareResponse {
+            result: RESULT_FAILURE.to_string(),
+            cmd: cmd.to_string(),
+            data: Box::new(ErrorData { message: message.to_string() }),
+        }
+    }
+}
+
+//服务端能力，features: 可选功能名称 -> 是否支持
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Capabilities {
+    pub protocol_version: i32,
+    pub min_protocol_version: i32,
+    pub server_version: String,
+    pub commands: Vec<String>,
+    pub features: Map<String, Value>,
+}
+
+//前端的协议版本是否能够与服务端通信
+pub fn is_compatible_version(client_version: i32) -> bool {
+    client_version >= MIN_PROTOCOL_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let request: FlareRequest = serde_json::from_str(r#"{"cmd": "list_sessions"}"#).unwrap();
+        assert_eq!(request.cmd, "list_sessions");
+        assert!(request.options.is_empty());
+
+        let response = serde_json::to_value(FlareResponse::success("hello", vec![1, 2])).unwrap();
+        assert_eq!(response, serde_json::json!({"result": "success", "cmd": "hello", "data": [1, 2]}));
+        let response = serde_json::to_value(FlareResponse::failure("query", "bad query")).unwrap();
+        assert_eq!(response, serde_json::json!({"result": "failure", "cmd": "query", "data": {"message": "bad query"}}));
+        assert!(is_compatible_version(PROTOCOL_VERSION));
+        assert!(!is_compatible_version(MIN_PROTOCOL_VERSION - 1));
+    }
+}
@@ -0,0 +1,93 @@
+//使用模拟取样数据的基准测试: 写入取样(时序文件)、调用栈折叠及火焰图查询
+//  cargo bench --bench synthetic_sample [-- <filter>]
+//  查询使用的取样数据按固定种子生成在 target/bench-samples，不同提交之间的结果可以直接比较:
+//    cargo bench --bench synthetic_sample -- --save-baseline <commit>
+//    cargo bench --bench synthetic_sample -- --baseline <commit>
+
+#[macro_use]
+extern crate criterion;
+extern crate flare_server;
+extern crate inferno;
+
+use criterion::{BenchmarkId, Criterion};
+use flare_server::sample::*;
+use flare_server::sample_generator::*;
+use inferno::flamegraph;
+
+const DATA_DIR: &str = "target/bench-samples";
+//(线程数, 取样时长秒)
+const SIZES: [(usize, i64); 2] = [(4, 10), (16, 30)];
+
+fn bench_options(threads: usize, duration_secs: i64) -> GeneratorOptions {
+    let mut options = GeneratorOptions::default();
+    options.threads = threads;
+    options.duration_ms = duration_secs * 1000;
+    options
+}
+
+//每次生成前删除旧的取样目录
+fn generate(sample_data_dir: &str, options: &GeneratorOptions) -> GeneratorStats {
+    if std::fs::metadata(sample_data_dir).is_ok() {
+        std::fs::remove_dir_all(sample_data_dir).unwrap();
+    }
+    generate_sample(sample_data_dir, options).unwrap()
+}
+
+fn bench_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic/write");
+    group.sample_size(10);
+    for (threads, duration_secs) in SIZES.iter() {
+        let options = bench_options(*threads, *duration_secs);
+        let sample_data_dir = format!("{}/write-{}x{}s", DATA_DIR, threads, duration_secs);
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{}x{}s", threads, duration_secs)), &options, |b, options| {
+            b.iter(|| generate(&sample_data_dir, options))
+        });
+    }
+    group.finish();
+}
+
+fn bench_query(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic/query");
+    group.sample_size(10);
+    for (threads, duration_secs) in SIZES.iter() {
+        let options = bench_options(*threads, *duration_secs);
+        let sample_data_dir = format!("{}/query-{}x{}s", DATA_DIR, threads, duration_secs);
+        generate(&sample_data_dir, &options);
+        let collector = SampleCollector::open(&sample_data_dir).unwrap();
+        let mut collector = collector.lock().unwrap();
+        let start_time = options.start_time;
+        let end_time = options.start_time + options.duration_ms;
+        let thread_ids = collector.get_threads().unwrap().iter().map(|x| x.id).collect::<Vec<i64>>();
+        let size = format!("{}x{}s", threads, duration_secs);
+
+        group.bench_function(BenchmarkId::new("fold", &size), |b| {
+            b.iter(|| {
+                let mut lines = vec![];
+                for thread_id in &thread_ids {
+                    lines.extend(collector.get_collapsed_call_stacks(*thread_id, start_time, end_time, StatsType::SAMPLES).unwrap());
+                }
+                lines
+            })
+        });
+        group.bench_function(BenchmarkId::new("call_tree", &size), |b| {
+            b.iter(|| collector.get_call_tree(&thread_ids, start_time, end_time).unwrap().total_cpu)
+        });
+        group.bench_function(BenchmarkId::new("flame_graph", &size), |b| {
+            b.iter(|| {
+                let mut lines = vec![];
+                for thread_id in &thread_ids {
+                    lines.extend(collector.get_collapsed_call_stacks(*thread_id, start_time, end_time, StatsType::SAMPLES).unwrap());
+                }
+                let mut flame_options = flamegraph::Options::default();
+                let mut svg = vec![];
+                flamegraph::from_lines(&mut flame_options, lines.iter().map(|x| x.as_str()), &mut svg).unwrap();
+                svg.len()
+            })
+        });
+        collector.close();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_write, bench_query);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+//--features embedded 时把agent库、attacher及UI静态文件嵌入到 flare_server 程序中，部署时只需要复制一个文件
+//  嵌入的文件来自 FLARE_EMBED_DIR(默认为 build.sh 的输出目录 ../target/flare-profiler) 下的 agent/lib 及 res/static
+//没有启用时生成空的文件列表
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const EMBED_SUB_DIRS: &[&str] = &["agent/lib", "res/static"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=FLARE_EMBED_DIR");
+    let mut files = vec![];
+    if env::var("CARGO_FEATURE_EMBEDDED").is_ok() {
+        let embed_dir = match env::var("FLARE_EMBED_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("../target/flare-profiler")
+        };
+        for sub_dir in EMBED_SUB_DIRS {
+            let dir = embed_dir.join(sub_dir);
+            println!("cargo:rerun-if-changed={}", dir.display());
+            collect_files(&embed_dir, &dir, &mut files);
+        }
+        if files.is_empty() {
+            panic!("no files to embed in {}, run build.sh first or set FLARE_EMBED_DIR", embed_dir.display());
+        }
+    }
+    files.sort();
+
+    let mut code = String::from("//由 build.rs 生成: (相对路径, 文件内容)\npub static EMBEDDED_FILES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in &files {
+        println!("cargo:rerun-if-changed={}", path);
+        code.push_str(&format!("    ({:?}, include_bytes!({:?})),\n", name, path));
+    }
+    code.push_str("];\n");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("embedded_files.rs"), code).unwrap();
+}
+
+fn collect_files(base_dir: &Path, dir: &Path, files: &mut Vec<(String, String)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(base_dir, &path, files);
+            continue;
+        }
+        let name = path.strip_prefix(base_dir).unwrap().to_string_lossy().replace('\\', "/");
+        files.push((name, path.to_string_lossy().to_string()));
+    }
+}
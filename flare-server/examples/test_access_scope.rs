@@ -0,0 +1,134 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::access::*;
+use flare_server::sample_generator::{generate_sample, GeneratorOptions};
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use websocket::sender::{Sender, Writer};
+
+fn new_identity(scopes: &[&str]) -> ClientIdentity {
+    ClientIdentity {
+        name: "order-team".to_string(),
+        addr: "10.0.0.2:6000".to_string(),
+        admin: false,
+        authenticated: true,
+        scopes: scopes.iter().map(|x| x.to_string()).collect(),
+        token: String::new(),
+    }
+}
+
+//受限的令牌只能访问允许的会话及取样目录
+fn main() -> io::Result<()> {
+    assert!(match_pattern("*order-service*", "flare-samples/order-service-20261014"));
+    assert!(match_pattern("10.0.3.*", "10.0.3.7:3333"));
+    assert!(match_pattern("abc", "abc") && !match_pattern("abc", "abcd"));
+    assert!(match_pattern("a*c*e", "abcde") && !match_pattern("a*c*e", "abcd"));
+    assert!(!match_pattern("a*a", "a"));
+    let token = AccessToken { name: "order-team".to_string(), token: "t1".to_string(), admin: false, sessions: vec!["*order*".to_string()] };
+    let identity = ClientIdentity::from_token("10.0.0.2:6000", &token);
+    assert!(identity.is_scoped() && identity.authenticated);
+    assert!(identity.can_access(&["s1".to_string(), "order-service-1".to_string()]));
+    assert!(!identity.can_access(&["s1".to_string(), "payment-1".to_string()]));
+    assert!(ClientIdentity::anonymous("local").can_access(&["payment-1".to_string()]));
+
+    let samples_root = "target/testkit-samples/access-scope";
+    let _ = std::fs::remove_dir_all(samples_root);
+    let options = GeneratorOptions { threads: 2, duration_ms: 2000, ..Default::default() };
+    let order_dir = format!("{}/order-service-1", samples_root);
+    let payment_dir = format!("{}/payment-1", samples_root);
+    generate_sample(&order_dir, &options)?;
+    generate_sample(&payment_dir, &options)?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let profiler = Profiler::new();
+    let mut request = |json: String| {
+        let mut out_cmd = String::new();
+        profiler.lock().unwrap().handle_request(&mut writer, json, &mut out_cmd)
+    };
+
+    //管理员打开两个取样，max_resident_mb 过大时使用上限
+    request(format!(r#"{{"cmd": "open_sample", "options": {{"sample_data_dir": "{}", "async": false, "max_resident_mb": 9223372036854775807}}}}"#, order_dir))?;
+    request(format!(r#"{{"cmd": "open_sample", "options": {{"sample_data_dir": "{}", "async": false}}}}"#, payment_dir))?;
+    let sessions = profiler.lock().unwrap().get_sample_sessions();
+    assert_eq!(sessions.len(), 2);
+    let session_of = |dir: &str| sessions.iter()
+        .find(|(_, x)| x.lock().unwrap().get_sample_info().sample_data_dir.ends_with(dir))
+        .map(|x| x.0.clone()).unwrap();
+    let order_session = session_of("order-service-1");
+    let payment_session = session_of("payment-1");
+
+    set_client_identity(Some(new_identity(&["*order-service*"])));
+    assert!(profiler.lock().unwrap().is_session_visible(&order_session));
+    assert!(!profiler.lock().unwrap().is_session_visible(&payment_session));
+    request(format!(r#"{{"cmd": "list_threads", "options": {{"session_id": "{}"}}}}"#, order_session))?;
+    let err = request(format!(r#"{{"cmd": "list_threads", "options": {{"session_id": "{}"}}}}"#, payment_session)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(err.to_string().contains("can not access session"));
+    let err = request(format!(r#"{{"cmd": "merge_sessions", "options": {{"session_ids": ["{}", "{}"]}}}}"#, order_session, payment_session)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    let err = request(format!(r#"{{"cmd": "open_sample", "options": {{"sample_data_dir": "{}"}}}}"#, payment_dir)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    //不针对会话的命令
+    request(r#"{"cmd": "list_sessions"}"#.to_string())?;
+    let err = request(r#"{"cmd": "close_all_session"}"#.to_string()).unwrap_err();
+    assert!(err.to_string().contains("is restricted to sessions"));
+    let err = request(r#"{"cmd": "list_threads", "options": {"session_id": "unknown", "lang": "zh"}}"#.to_string()).unwrap_err();
+    assert!(err.to_string().starts_with("拒绝访问"));
+    set_client_identity(None);
+
+    //受限的连接订阅事件后，只收到允许访问的会话的事件
+    let listener2 = TcpListener::bind("127.0.0.1:0")?;
+    let mut client_stream2 = TcpStream::connect(listener2.local_addr()?)?;
+    let (server_stream2, _) = listener2.accept()?;
+    let mut writer2 = Writer { stream: SharedStream::new(server_stream2), sender: Sender::new(false) };
+    set_client_identity(Some(new_identity(&["*order-service*"])));
+    let mut out_cmd = String::new();
+    profiler.lock().unwrap().handle_request(&mut writer2, r#"{"cmd": "history_samples", "options": {"subscribe": true}}"#.to_string(), &mut out_cmd)?;
+    //没有指定 subscribe 时不订阅事件
+    let mut client_stream3 = TcpStream::connect(listener2.local_addr()?)?;
+    let (server_stream3, _) = listener2.accept()?;
+    let mut writer3 = Writer { stream: SharedStream::new(server_stream3), sender: Sender::new(false) };
+    profiler.lock().unwrap().handle_request(&mut writer3, r#"{"cmd": "history_samples"}"#.to_string(), &mut out_cmd)?;
+    set_client_identity(None);
+    let order_dir2 = format!("{}/order-service-2", samples_root);
+    let payment_dir2 = format!("{}/payment-2", samples_root);
+    generate_sample(&order_dir2, &options)?;
+    generate_sample(&payment_dir2, &options)?;
+    request(format!(r#"{{"cmd": "open_sample", "options": {{"sample_data_dir": "{}", "async": true}}}}"#, order_dir2))?;
+    request(format!(r#"{{"cmd": "open_sample", "options": {{"sample_data_dir": "{}", "async": true}}}}"#, payment_dir2))?;
+    while profiler.lock().unwrap().get_sample_sessions().len() < 4 {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let sessions = profiler.lock().unwrap().get_sample_sessions();
+    let session_of = |dir: &str| sessions.iter()
+        .find(|(_, x)| x.lock().unwrap().get_sample_info().sample_data_dir.ends_with(dir))
+        .map(|x| x.0.clone()).unwrap();
+    let read_all = |stream: &mut TcpStream| -> io::Result<String> {
+        stream.set_read_timeout(Some(std::time::Duration::from_millis(200)))?;
+        let mut received = vec![];
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = stream.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..n]);
+        }
+        Ok(String::from_utf8_lossy(&received).to_string())
+    };
+    let received = read_all(&mut client_stream2)?;
+    assert!(received.contains(&session_of("order-service-2")), "{}", received);
+    assert!(!received.contains(&session_of("payment-2")), "{}", received);
+    let received = read_all(&mut client_stream3)?;
+    assert!(received.contains("history_samples"), "{}", received);
+    assert!(!received.contains(&session_of("order-service-2")), "{}", received);
+
+    println!("access scope test passed");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+extern crate flare_server;
+extern crate flare_proto;
+
+use flare_server::testkit::*;
+use flare_server::agent_mux::*;
+use flare_server::runtime_adapter::RuntimeAdapter;
+use flare_server::sample::SampleCollector;
+use flare_proto::agent::AgentEvent;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn get_sample_count(collector: &mut SampleCollector, thread_id: i64) -> i64 {
+    collector.get_dashboard().threads.iter().find(|x| x.id == thread_id).map_or(0, |x| x.sample_count)
+}
+
+//同一个agent连接上按不同的取样间隔录制两个会话，控制请求的响应只发送给发起请求的会话
+fn main() -> io::Result<()> {
+    assert_eq!(get_session_interval(0, 20), 20);
+    assert_eq!(get_session_interval(100, 20), 100);
+    assert_eq!(get_session_interval(90, 20), 100);
+    assert_eq!(get_session_interval(50, 0), 50);
+
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 100);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000);
+    script.realtime = true;
+    //模拟agent只接受一个连接，两个会话必须共享连接
+    let mut agent = FakeAgentServer::start(script)?;
+    let samples_root = "target/testkit-samples/agent_mux";
+
+    let full = SampleCollector::new(agent.get_addr(), samples_root)?;
+    full.lock().unwrap().start_adapter(Box::new(MuxSessionAdapter::new(agent.get_addr(), 0)))?;
+    thread::sleep(Duration::from_millis(200));
+    let coarse = SampleCollector::new(agent.get_addr(), samples_root)?;
+    let adapter = MuxSessionAdapter::new(agent.get_addr(), 100);
+    coarse.lock().unwrap().start_adapter(Box::new(adapter))?;
+    assert_eq!(get_agent_session_count(agent.get_addr()), 2);
+    assert!(coarse.lock().unwrap().get_agent_compat().is_some());
+    thread::sleep(Duration::from_millis(200));
+    coarse.lock().unwrap().request_thread_dump()?;
+    agent.wait()?;
+
+    let start = Instant::now();
+    while !full.lock().unwrap().is_disconnected() || !coarse.lock().unwrap().is_disconnected() {
+        assert!(start.elapsed() < Duration::from_secs(10), "wait for recording finished timeout");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(get_agent_session_count(agent.get_addr()), 0);
+
+    let mut full = full.lock().unwrap();
+    let mut coarse = coarse.lock().unwrap();
+    assert_ne!(full.get_sample_info().sample_data_dir, coarse.get_sample_info().sample_data_dir);
+    assert_eq!(coarse.get_sample_info().sample_interval, 100);
+    let full_samples = get_sample_count(&mut full, 10);
+    let coarse_samples = get_sample_count(&mut coarse, 10);
+    println!("samples: full: {}, coarse: {}", full_samples, coarse_samples);
+    assert_eq!(full_samples, 100);
+    //后加入的会话每5次取样保留一次
+    assert!(coarse_samples > 0 && coarse_samples <= 20, "coarse samples: {}", coarse_samples);
+    //抽取后的cpu时间增量按保留的取样重新计算
+    let thread = coarse.get_dashboard().threads.into_iter().find(|x| x.id == 10).unwrap();
+    assert_eq!(thread.cpu_time_delta, 5_000_000);
+
+    assert_eq!(coarse.get_thread_dumps().len(), 1);
+    assert_eq!(full.get_thread_dumps().len(), 0);
+    full.close();
+    coarse.close();
+
+    //没有会话时连接已经断开，可以重新连接
+    let mut adapter = MuxSessionAdapter::new(agent.get_addr(), 0);
+    assert!(adapter.connect().is_err());
+
+    //会话读取跟不上时丢弃取样，恢复后收到丢弃数量的诊断信息
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 50);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000);
+    script.realtime = true;
+    let mut agent = FakeAgentServer::start(script)?;
+    let mut adapter = MuxSessionAdapter::new(agent.get_addr(), 0);
+    adapter.set_queue_size(5);
+    adapter.connect()?;
+    thread::sleep(Duration::from_millis(400));
+    let (mut samples, mut dropped, mut methods) = (0, 0, 0);
+    while let Some(event) = adapter.next_event()? {
+        match event {
+            AgentEvent::Thread(_) => samples += 1,
+            AgentEvent::Method(_) => methods += 1,
+            AgentEvent::Diagnostic(x) => {
+                assert_eq!(x.kind, "dropped_events");
+                dropped += x.count;
+            }
+            _ => {}
+        }
+    }
+    agent.wait()?;
+    println!("slow session: samples: {}, dropped: {}", samples, dropped);
+    assert_eq!(methods, 1);
+    assert!(dropped > 0);
+    assert_eq!(samples + dropped, 50);
+
+    println!("test agent mux passed");
+    Ok(())
+}
@@ -0,0 +1,96 @@
+extern crate flare_server;
+extern crate flare_proto;
+
+use flare_server::testkit::*;
+use flare_server::agent_recording::*;
+use flare_server::sample::SampleCollector;
+use flare_proto::recording::*;
+use std::io;
+use std::path::PathBuf;
+
+//agent独立录制的目录用 open_sample 导入，超过15分钟的录制不分割，末尾不完整的事件被忽略，事件文件增加后重新导入
+fn main() -> io::Result<()> {
+    let test_dir = "target/test-samples/agent_recording";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir)?;
+    }
+    let recording_dir = format!("{}/{}", test_dir, get_recording_dir_name(4242, "20191002T101010"));
+    std::fs::create_dir_all(&recording_dir)?;
+
+    //20分钟，每秒取样一次
+    let mut script = AgentScript::new(1_570_000_000_000, 1000, 1200);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_method(2, "com.example.Main.main([Ljava/lang/String;)V");
+    script.add_thread(1, "main", vec![vec![2]], 1_000_000);
+    script.add_thread_range(10, "worker-1", vec![vec![1]], 2_000_000, 100, 200);
+    let mut data = vec![];
+    for message in script.encode_messages() {
+        data.extend(message.encode());
+    }
+    //进程被强制结束时写了一半的事件
+    data.extend(b"*15\r\n$6\r\nthread\r\n$4\r\ntime\r\n:157");
+    std::fs::write(format!("{}/{}", recording_dir, RECORDING_EVENTS_FILE), &data)?;
+    assert!(is_recording_dir(&recording_dir));
+    assert_eq!(get_imported_sample_dir(&recording_dir), None);
+
+    let collector = SampleCollector::open(&recording_dir)?;
+    let sample_data_dir = {
+        let mut collector = collector.lock().unwrap();
+        let sample_info = collector.get_sample_info();
+        assert_eq!(sample_info.sample_interval, 1000);
+        assert_eq!(sample_info.record_start_time, 1_570_000_000_000);
+        assert_eq!(sample_info.last_record_time, 1_570_000_000_000 + 1199 * 1000);
+        let mut threads = collector.get_threads()?;
+        threads.sort_by_key(|x| x.id);
+        assert_eq!(threads.iter().map(|x| (x.id, x.name.as_str(), x.sample_count)).collect::<Vec<_>>(),
+                   vec![(1, "main", 1200), (10, "worker-1", 100)]);
+        assert_eq!(collector.list_methods_by_filter("com.example.Main")?.len(), 1);
+        collector.close();
+        sample_info.sample_data_dir
+    };
+    assert!(sample_data_dir.starts_with(&recording_dir), "{}", sample_data_dir);
+    assert_eq!(get_imported_sample_dir(&recording_dir), Some(PathBuf::from(&sample_data_dir)));
+
+    //再次打开时使用已转换的取样目录
+    let collector = SampleCollector::open(&recording_dir)?;
+    assert_eq!(collector.lock().unwrap().get_sample_info().sample_data_dir, sample_data_dir);
+    collector.lock().unwrap().close();
+    assert_eq!(std::fs::read_dir(&recording_dir)?.count(), 3);
+
+    //录制还在进行，事件文件增加后重新转换，删除之前的取样目录
+    let mut script = AgentScript::new(1_570_000_000_000, 1000, 1500);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(1, "main", vec![vec![1]], 1_000_000);
+    let data: Vec<u8> = script.encode_messages().iter().flat_map(|x| x.encode()).collect();
+    std::fs::write(format!("{}/{}", recording_dir, RECORDING_EVENTS_FILE), &data)?;
+    assert_eq!(get_imported_sample_dir(&recording_dir), None);
+    let collector = SampleCollector::open(&recording_dir)?;
+    let grown_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    assert_eq!(collector.lock().unwrap().get_sample_info().last_record_time, 1_570_000_000_000 + 1499 * 1000);
+    collector.lock().unwrap().close();
+    assert_ne!(grown_data_dir, sample_data_dir);
+    assert!(std::fs::metadata(&sample_data_dir).is_err());
+    assert_eq!(get_imported_sample_dir(&recording_dir), Some(PathBuf::from(&grown_data_dir)));
+    assert_eq!(std::fs::read_dir(&recording_dir)?.count(), 3);
+
+    //其他进程正在转换
+    let locked_dir = format!("{}/{}", test_dir, get_recording_dir_name(4244, "20191002T101010"));
+    std::fs::create_dir_all(&locked_dir)?;
+    std::fs::write(format!("{}/{}", locked_dir, RECORDING_EVENTS_FILE), &data)?;
+    std::fs::write(format!("{}/{}", locked_dir, IMPORT_LOCK_FILE), b"1")?;
+    let err = SampleCollector::open(&locked_dir).err().unwrap();
+    assert!(err.to_string().contains("being imported"), "{}", err);
+    std::fs::remove_file(format!("{}/{}", locked_dir, IMPORT_LOCK_FILE))?;
+    SampleCollector::open(&locked_dir)?.lock().unwrap().close();
+    assert!(std::fs::metadata(format!("{}/{}", locked_dir, IMPORT_LOCK_FILE)).is_err());
+
+    //只有hello，没有 sample_info 的录制
+    let empty_dir = format!("{}/{}", test_dir, get_recording_dir_name(4243, "20191002T101010"));
+    std::fs::create_dir_all(&empty_dir)?;
+    std::fs::write(format!("{}/{}", empty_dir, RECORDING_EVENTS_FILE), AgentScript::new(1_570_000_000_000, 20, 0).encode_messages()[0].encode())?;
+    let err = SampleCollector::open(&empty_dir).err().unwrap();
+    assert!(err.to_string().contains("agent recording is empty"), "{}", err);
+
+    println!("test agent recording passed: {}", sample_data_dir);
+    Ok(())
+}
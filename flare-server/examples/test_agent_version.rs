@@ -0,0 +1,77 @@
+extern crate flare_server;
+extern crate flare_proto;
+
+use flare_server::testkit::*;
+use flare_server::runtime_adapter::KIND_AGENT_VERSION;
+use flare_server::sample::SampleCollector;
+use flare_proto::handshake::*;
+use flare_proto::AGENT_PROTO_VERSION;
+use std::io;
+
+fn new_script() -> AgentScript {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 50);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000);
+    script
+}
+
+//连接agent时协商版本及能力，不兼容时给出升级提示，旧版本agent降级使用
+fn main() -> io::Result<()> {
+    //当前版本
+    let collector = record_script(new_script(), "target/testkit-samples/agent_version", 10_000)?;
+    {
+        let collector = collector.lock().unwrap();
+        let compat = collector.get_agent_compat().unwrap();
+        assert_eq!(compat.proto_version, AGENT_PROTO_VERSION as i64);
+        assert_eq!(compat.warning, None);
+        assert!(collector.get_session_events().iter().all(|x| x.kind != KIND_AGENT_VERSION));
+    }
+    collector.lock().unwrap().close();
+
+    //旧版本agent不发送hello，第一个事件不丢失，会话事件中提示升级
+    let mut script = new_script();
+    script.hello = None;
+    let collector = record_script(script, "target/testkit-samples/agent_version", 10_000)?;
+    {
+        let collector = collector.lock().unwrap();
+        assert_eq!(collector.get_agent_compat().unwrap().proto_version, LEGACY_PROTO_VERSION);
+        assert_eq!(collector.get_sample_info().sample_interval, 20);
+        let events: Vec<_> = collector.get_session_events().iter().filter(|x| x.kind == KIND_AGENT_VERSION).collect();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].message.contains("upgrade flare-agent"));
+        assert!(collector.supports_agent_request("thread-dump"));
+    }
+    collector.lock().unwrap().close();
+
+    //缺少能力的agent，不支持的请求直接返回错误
+    let mut script = new_script();
+    let mut hello = new_agent_hello();
+    hello.capabilities = CAP_DEADLOCK | CAP_CLOCK_SYNC;
+    script.hello = Some(hello);
+    let collector = record_script(script, "target/testkit-samples/agent_version", 10_000)?;
+    {
+        let collector = collector.lock().unwrap();
+        assert!(!collector.supports_agent_request("heap-histogram"));
+        let err = collector.request_thread_dump().unwrap_err();
+        assert!(err.to_string().contains("does not support request: thread-dump"), "{}", err);
+        assert_eq!(collector.get_session_events().iter().filter(|x| x.kind == KIND_AGENT_VERSION).count(), 1);
+    }
+    collector.lock().unwrap().close();
+
+    //agent要求更新的分析服务，连接失败
+    let mut script = new_script();
+    let mut hello = new_agent_hello();
+    hello.proto_version = AGENT_PROTO_VERSION as i64 + 1;
+    hello.min_proto_version = AGENT_PROTO_VERSION as i64 + 1;
+    hello.agent_version = "9.0.0".to_string();
+    script.hello = Some(hello);
+    let mut agent = FakeAgentServer::start(script)?;
+    let collector = SampleCollector::new(agent.get_addr(), "target/testkit-samples/agent_version")?;
+    let err = collector.lock().unwrap().subscribe_events().unwrap_err();
+    assert!(err.to_string().contains("upgrade flare-server to 9.0.0"), "{}", err);
+    let _ = agent.wait();
+    collector.lock().unwrap().close();
+
+    println!("test agent version passed");
+    Ok(())
+}
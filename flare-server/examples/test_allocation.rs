@@ -0,0 +1,69 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::allocation::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//每50次取样(1秒)检查一次分配量：worker-1 在 parse 中每秒分配 4MB，worker-2 在 encode 中每秒分配 1MB
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 300);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;")
+        .add_method(3, "com.example.Json.encode(Ljava/lang/Object;)Ljava/lang/String;")
+        .add_method(4, "java.util.Arrays.copyOf([CI)[C");
+    script.add_thread(10, "worker-1", vec![vec![4, 2, 1]], 0)
+        .add_thread(11, "worker-2", vec![vec![3, 1]], 0);
+    for sample_index in vec![50, 100, 150, 200, 250] {
+        script.add_event(ScriptedEvent::Allocation { sample_index, thread_id: 10, name: "worker-1".to_string(), bytes: 4 << 20, stacktrace: vec![4, 2, 1] });
+        script.add_event(ScriptedEvent::Allocation { sample_index, thread_id: 11, name: "worker-2".to_string(), bytes: 1 << 20, stacktrace: vec![3, 1] });
+    }
+
+    let collector = record_script(script.clone(), "target/testkit-samples/allocation", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    assert_eq!(collector.lock().unwrap().get_allocation_samples().len(), 10);
+    collector.lock().unwrap().close();
+    drop(collector);
+    assert_eq!(load_allocation_samples(&sample_data_dir)?.len(), 10);
+
+    //重新打开取样目录，检查分配速率
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let start_time = script.start_time;
+    let report = get_allocation_rate(&mut collector, start_time, start_time + 5999, 1000, 10);
+    println!("allocation rate: total: {}, bytes/sec: {}", report.total_bytes, report.bytes_per_sec);
+    assert_eq!(report.total_bytes, 25 << 20);
+    assert_eq!(report.threads.iter().map(|x| x.thread_name.as_str()).collect::<Vec<_>>(), vec!["worker-1", "worker-2"]);
+    assert_eq!(report.threads[0].series, vec![0, 4 << 20, 4 << 20, 4 << 20, 4 << 20, 4 << 20]);
+    assert_eq!(report.threads[1].total_bytes, 5 << 20);
+    //栈顶的Arrays.copyOf分配最多，parse 只在调用栈中
+    assert_eq!(report.methods[0].method_name, "java.util.Arrays.copyOf([CI)[C");
+    assert_eq!(report.methods[0].self_bytes, 20 << 20);
+    assert_eq!(report.methods[1].method_name, "com.example.Json.encode(Ljava/lang/Object;)Ljava/lang/String;");
+    assert!(report.methods.iter().all(|x| x.method_id != 2));
+
+    let report = get_allocation_rate(&mut collector, start_time, start_time + 1500, 1000, 1);
+    assert_eq!(report.threads.len(), 1);
+    assert_eq!(report.threads[0].total_bytes, 4 << 20);
+
+    //仪表盘显示最近一分钟分配最多的线程
+    let dashboard = collector.get_dashboard();
+    assert_eq!(dashboard.top_allocating_threads[0].thread_id, 10);
+    assert_eq!(dashboard.top_allocating_methods[0].method_id, 4);
+    collector.close();
+
+    //超过上限时丢弃最早的事件，乱序到达的事件按时间插入
+    let mut store = AllocationStore::new(3);
+    for time in vec![10, 30, 20, 40] {
+        store.push(AllocationSample { time, thread_id: 1, thread_name: "worker".to_string(), bytes: time, stacktrace: vec![] });
+    }
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.first_time(), Some(20));
+    assert_eq!(store.last_time(), Some(40));
+    assert_eq!(store.range(20, 30).map(|x| x.time).collect::<Vec<_>>(), vec![20, 30]);
+    assert_eq!(store.range(35, 100).map(|x| x.time).collect::<Vec<_>>(), vec![40]);
+    assert_eq!(store.range(0, 15).count(), 0);
+    assert_eq!(store.range(40, 20).count(), 0);
+    println!("allocation test passed");
+    Ok(())
+}
@@ -0,0 +1,48 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use websocket::sender::{Sender, Writer};
+
+//attach_jvm 在任务池中加载agent，立即返回，完成后回复结果；无效的端口在提交前拒绝
+fn main() -> io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let mut client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let profiler = Profiler::new();
+    let mut request = |json: &str| {
+        let mut out_cmd = String::new();
+        profiler.lock().unwrap().handle_request(&mut writer, json.to_string(), &mut out_cmd)
+    };
+
+    for port in &["70000", "0", "-1"] {
+        let err = request(&format!(r#"{{"cmd": "attach_jvm", "options": {{"target_pid": 1, "agent_port": {}}}}}"#, port)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err);
+        assert!(err.to_string().contains("agent_port"), "{}", err);
+    }
+
+    //测试环境没有agent库，任务中加载失败后回复错误
+    request(r#"{"cmd": "attach_jvm", "options": {"target_pid": 1, "agent_port": 3399}}"#)?;
+    client_stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+    let mut received = vec![];
+    let mut buf = [0u8; 4096];
+    for _ in 0..50 {
+        if let Ok(n) = client_stream.read(&mut buf) {
+            received.extend_from_slice(&buf[..n]);
+        }
+        if !received.is_empty() {
+            break;
+        }
+    }
+    let received = String::from_utf8_lossy(&received);
+    assert!(received.contains("attach_jvm"), "{}", received);
+    assert!(profiler.try_lock().is_ok());
+    println!("attach jvm test passed");
+    Ok(())
+}
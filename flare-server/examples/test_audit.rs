@@ -0,0 +1,80 @@
+#[macro_use]
+extern crate serde_json;
+extern crate flare_server;
+
+use flare_server::access::*;
+use flare_server::audit::*;
+use std::io;
+
+//访问令牌、连接身份及审计日志的写入和查询
+fn main() -> io::Result<()> {
+    let tokens = vec![
+        AccessToken { name: "ops".to_string(), token: "t-ops".to_string(), admin: true, sessions: vec![] },
+        AccessToken { name: "shop-team".to_string(), token: "t-shop".to_string(), admin: false, sessions: vec![] },
+    ];
+    assert_eq!(find_access_token(&tokens, "t-shop").unwrap().name, "shop-team");
+    assert!(find_access_token(&tokens, "").is_none());
+    assert!(find_access_token(&tokens, "t-unknown").is_none());
+
+    //没有配置令牌时不检查
+    let anonymous = ClientIdentity::anonymous("127.0.0.1:5000");
+    assert!(anonymous.is_allowed(&[]) && anonymous.is_admin(&[]));
+    assert!(!anonymous.is_allowed(&tokens) && !anonymous.is_admin(&tokens));
+    let shop = ClientIdentity { name: "shop-team".to_string(), addr: "10.0.0.2:6000".to_string(), admin: false, authenticated: true, scopes: vec![], token: "t-shop".to_string() };
+    assert!(shop.is_allowed(&tokens) && !shop.is_admin(&tokens));
+    //重新加载配置后按新的令牌配置验证
+    let mut reloaded = tokens.clone();
+    reloaded[1].admin = true;
+    reloaded[1].sessions = vec!["*shop*".to_string()];
+    assert!(shop.is_admin(&reloaded));
+    assert_eq!(shop.refresh(&reloaded).scopes, vec!["*shop*".to_string()]);
+    reloaded.remove(1);
+    assert!(!shop.is_allowed(&reloaded) && !shop.is_admin(&reloaded));
+    let revoked = shop.refresh(&reloaded);
+    assert!(!revoked.authenticated && revoked.scopes.is_empty());
+    assert!(revoked.refresh(&tokens).authenticated);
+
+    //身份保存在连接线程中
+    assert_eq!(get_client_identity().addr, "local");
+    set_client_identity(Some(shop.clone()));
+    assert_eq!(get_client_identity(), shop);
+    set_client_identity(None);
+
+    assert!(is_control_command("connect_agent") && is_control_command("close_session"));
+    assert!(!is_control_command("flame_graph"));
+
+    let path = "target/testkit-samples/audit/audit.log";
+    let _ = std::fs::remove_file(path);
+    let options = json!({"agent_addr": "10.0.0.3:3333", "token": "t-shop"}).as_object().unwrap().clone();
+    let entry = new_audit_entry(1000, &shop, "connect_agent", &options, &Ok(()));
+    assert_eq!((entry.identity.as_str(), entry.target.as_str(), entry.result.as_str()), ("shop-team", "10.0.0.3:3333", "success"));
+    //敏感属性不写入审计日志
+    assert!(!entry.options.to_string().contains("t-shop"));
+    append_audit_entry(path, &entry)?;
+    let options = json!({"session_id": "s1"}).as_object().unwrap().clone();
+    let error = io::Error::new(io::ErrorKind::NotFound, "sample session not found");
+    append_audit_entry(path, &new_audit_entry(2000, &anonymous, "close_session", &options, &Err(error)))?;
+    append_audit_entry(path, &new_audit_entry(3000, &shop, "close_session", &options, &Ok(())))?;
+
+    let entries = load_audit_entries(path, &AuditQuery { start_time: -1, end_time: -1, ..Default::default() })?;
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1].result, "failure");
+    assert_eq!(entries[1].message, "sample session not found");
+    assert_eq!(entries[1].identity, ANONYMOUS);
+    let query = AuditQuery { start_time: -1, end_time: -1, identity: "shop-team".to_string(), action: "close_session".to_string() };
+    assert_eq!(load_audit_entries(path, &query)?.iter().map(|x| x.time).collect::<Vec<_>>(), vec![3000]);
+    let query = AuditQuery { start_time: 1500, end_time: 2500, ..Default::default() };
+    assert_eq!(load_audit_entries(path, &query)?.len(), 1);
+    assert!(load_audit_entries("target/testkit-samples/audit/not-exists.log", &AuditQuery::default())?.is_empty());
+
+    //任务池中执行的命令按任务结果记录
+    let options = json!({"session_id": "s1", "export_dir": "export-1"}).as_object().unwrap().clone();
+    let pending = PendingAudit::new(path, shop.clone(), "export_sample", &options);
+    let result: io::Result<serde_json::Value> = Err(io::Error::new(io::ErrorKind::Other, "disk is full"));
+    pending.finish(4000, &result);
+    let query = AuditQuery { start_time: -1, end_time: -1, action: "export_sample".to_string(), ..Default::default() };
+    let entries = load_audit_entries(path, &query)?;
+    assert_eq!(entries.iter().map(|x| (x.time, x.result.as_str(), x.message.as_str())).collect::<Vec<_>>(), vec![(4000, "failure", "disk is full")]);
+    println!("audit test passed");
+    Ok(())
+}
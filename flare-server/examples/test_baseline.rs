@@ -0,0 +1,83 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::baseline::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+fn new_script(stacks: Vec<Vec<i64>>, cpu_per_sample: i64) -> AgentScript {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;")
+        .add_method(3, "com.example.Json.encode(Ljava/lang/Object;)Ljava/lang/String;");
+    script.add_thread(10, "worker-1", stacks, cpu_per_sample);
+    script
+}
+
+fn record(script: AgentScript) -> io::Result<String> {
+    let collector = record_script(script, "target/testkit-samples/baseline", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    Ok(sample_data_dir)
+}
+
+//基线中 parse/encode 各占一半，候选版本 parse 占3/4 并且CPU增加
+fn main() -> io::Result<()> {
+    let baseline_dir = record(new_script(vec![vec![2, 1], vec![3, 1]], 1_000_000))?;
+    let candidate_dir = record(new_script(vec![vec![2, 1], vec![2, 1], vec![2, 1], vec![3, 1]], 1_200_000))?;
+
+    let samples_root = "target/testkit-samples/baseline-registry";
+    let _ = std::fs::remove_dir_all(samples_root);
+    let baseline = Baseline { app_tag: "shop".to_string(), sample_data_dir: baseline_dir.clone(), start_time: -1, end_time: -1, description: String::new(), marked_time: 0 };
+    set_baseline(samples_root, baseline.clone())?;
+    set_baseline(samples_root, Baseline { app_tag: "order".to_string(), ..baseline.clone() })?;
+    //替换同一个标签的基线
+    set_baseline(samples_root, Baseline { description: "v1.2".to_string(), ..baseline.clone() })?;
+    let baselines = load_baselines(samples_root)?;
+    assert_eq!(baselines.iter().map(|x| x.app_tag.as_str()).collect::<Vec<_>>(), vec!["order", "shop"]);
+    assert_eq!(get_baseline(samples_root, "shop")?.description, "v1.2");
+    assert!(get_baseline(samples_root, "payment").is_err());
+    assert!(set_baseline(samples_root, Baseline { app_tag: " ".to_string(), ..baseline.clone() }).is_err());
+
+    let baseline_collector = SampleCollector::open(&baseline_dir)?;
+    let baseline_profile = get_method_profile(&mut baseline_collector.lock().unwrap(), -1, -1)?;
+    let candidate_collector = SampleCollector::open(&candidate_dir)?;
+    let candidate_profile = get_method_profile(&mut candidate_collector.lock().unwrap(), -1, -1)?;
+    assert_eq!((baseline_profile.samples, candidate_profile.samples), (200, 200));
+
+    let comparison = compare_profiles(&baseline_profile, &candidate_profile, &CompareOptions::default());
+    println!("comparison: {:?}", comparison);
+    assert!(comparison.is_regressed());
+    assert!(comparison.cpu_regressed);
+    assert_eq!(comparison.cpu_change_percent, 20.0);
+    assert_eq!(comparison.regressions.len(), 1);
+    assert_eq!(comparison.regressions[0].method_name, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;");
+    assert_eq!((comparison.regressions[0].baseline_share, comparison.regressions[0].candidate_share, comparison.regressions[0].change), (50.0, 75.0, 25.0));
+    assert_eq!(comparison.improvements[0].change, -25.0);
+
+    //阈值大于变化时没有回归
+    let options = CompareOptions { threshold_percent: 30.0, cpu_threshold_percent: 30.0, ..Default::default() };
+    assert!(!compare_profiles(&baseline_profile, &candidate_profile, &options).is_regressed());
+    //方法占比(百分点)与总CPU(百分比)的阈值分开判断
+    let options = CompareOptions { threshold_percent: 30.0, cpu_threshold_percent: 10.0, ..Default::default() };
+    let comparison = compare_profiles(&baseline_profile, &candidate_profile, &options);
+    assert!(comparison.cpu_regressed && comparison.regressions.is_empty());
+    let options = CompareOptions { threshold_percent: 10.0, cpu_threshold_percent: 30.0, ..Default::default() };
+    let comparison = compare_profiles(&baseline_profile, &candidate_profile, &options);
+    assert!(!comparison.cpu_regressed && comparison.regressions.len() == 1);
+    //与自身比较
+    assert!(!compare_profiles(&baseline_profile, &baseline_profile, &CompareOptions::default()).is_regressed());
+    baseline_collector.lock().unwrap().close();
+    candidate_collector.lock().unwrap().close();
+
+    //ci-check
+    assert_eq!(parse_percent("5%")?, 5.0);
+    assert_eq!(parse_percent(" 2.5 ")?, 2.5);
+    assert!(parse_percent("abc").is_err());
+    let comparison = compare_samples(&baseline_dir, &candidate_dir, &CompareOptions::default())?;
+    assert!(comparison.is_regressed());
+    assert_eq!(comparison.regressions.len(), 1);
+    assert!(!compare_samples(&baseline_dir, &baseline_dir, &CompareOptions::default())?.is_regressed());
+    println!("baseline test passed");
+    Ok(())
+}
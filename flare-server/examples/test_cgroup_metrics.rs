@@ -0,0 +1,101 @@
+extern crate flare_server;
+extern crate serde_json;
+extern crate flare_utils;
+
+use flare_server::cgroup_metrics::*;
+use flare_server::metric_series::*;
+use flare_utils::timeseries::TSTransform;
+use std::collections::HashMap;
+use std::io;
+
+fn write_file(path: &str, content: &str) -> io::Result<()> {
+    let path = std::path::Path::new(path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, content)
+}
+
+//模拟的/proc及cgroup目录(v1、v2)，检查指标读取及时间序列保存
+fn main() -> io::Result<()> {
+    let test_dir = "target/test-samples/cgroup_metrics";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir)?;
+    }
+    let proc_root = format!("{}/proc", test_dir);
+    let status = "Name:\tjava\nVmRSS:\t  204800 kB\nThreads:\t42\n";
+
+    //cgroup v2
+    let v2_root = format!("{}/cgroup2", test_dir);
+    write_file(&format!("{}/100/cgroup", proc_root), "0::/kubepods/pod1/app\n")?;
+    write_file(&format!("{}/100/status", proc_root), status)?;
+    write_file(&format!("{}/kubepods/pod1/app/cpu.stat", v2_root), "usage_usec 5000000\nuser_usec 4000000\nnr_periods 100\nnr_throttled 25\nthrottled_usec 750000\n")?;
+    write_file(&format!("{}/kubepods/pod1/app/cpu.max", v2_root), "150000 100000\n")?;
+    write_file(&format!("{}/kubepods/pod1/app/memory.current", v2_root), "536870912\n")?;
+    write_file(&format!("{}/kubepods/pod1/app/memory.max", v2_root), "max\n")?;
+    let metrics = read_cgroup_metrics_from(&proc_root, &v2_root, 100, 1000)?;
+    assert_eq!(metrics.cgroup_version, 2);
+    assert_eq!(metrics.cgroup_path, "/kubepods/pod1/app");
+    assert_eq!((metrics.cpu_usage_us, metrics.nr_periods, metrics.nr_throttled, metrics.throttled_us), (5_000_000, 100, 25, 750_000));
+    assert_eq!(metrics.cpu_limit_cores, 1.5);
+    assert_eq!((metrics.memory_usage_bytes, metrics.memory_limit_bytes), (536_870_912, -1));
+    assert_eq!(metrics.rss_bytes, 204_800 * 1024);
+
+    //cgroup v1
+    let v1_root = format!("{}/cgroup1", test_dir);
+    write_file(&format!("{}/200/cgroup", proc_root), "12:memory:/docker/abc\n4:cpu,cpuacct:/docker/abc\n1:name=systemd:/docker/abc\n")?;
+    write_file(&format!("{}/200/status", proc_root), status)?;
+    write_file(&format!("{}/cpu,cpuacct/docker/abc/cpu.stat", v1_root), "nr_periods 40\nnr_throttled 10\nthrottled_time 2000000000\n")?;
+    write_file(&format!("{}/cpu,cpuacct/docker/abc/cpuacct.usage", v1_root), "9000000000\n")?;
+    write_file(&format!("{}/cpu,cpuacct/docker/abc/cpu.cfs_quota_us", v1_root), "50000\n")?;
+    write_file(&format!("{}/cpu,cpuacct/docker/abc/cpu.cfs_period_us", v1_root), "100000\n")?;
+    write_file(&format!("{}/memory/docker/abc/memory.usage_in_bytes", v1_root), "1048576\n")?;
+    write_file(&format!("{}/memory/docker/abc/memory.limit_in_bytes", v1_root), "2097152\n")?;
+    let metrics = read_cgroup_metrics_from(&proc_root, &v1_root, 200, 1000)?;
+    assert_eq!(metrics.cgroup_version, 1);
+    assert_eq!((metrics.cpu_usage_us, metrics.nr_throttled, metrics.throttled_us), (9_000_000, 10, 2_000_000));
+    assert_eq!(metrics.cpu_limit_cores, 0.5);
+    assert_eq!((metrics.memory_usage_bytes, metrics.memory_limit_bytes), (1_048_576, 2_097_152));
+    assert!(read_cgroup_metrics_from(&proc_root, &v1_root, 300, 1000).is_err());
+
+    //时间序列
+    let sample_data_dir = format!("{}/sample", test_dir);
+    std::fs::create_dir_all(&sample_data_dir)?;
+    let mut series_map = HashMap::new();
+    let start_time = 1_570_000_000_000;
+    for i in 0..5 {
+        let mut metrics = metrics.clone();
+        metrics.throttled_us += i * 100_000;
+        metrics.memory_usage_bytes += i * 1000;
+        for metric in CGROUP_METRICS {
+            add_metric_value(&mut series_map, &sample_data_dir, metric, start_time + i * 1000, metrics.get_metric_value(metric.name))?;
+        }
+    }
+    drop(series_map);
+    let series_map = load_metric_series(&sample_data_dir)?;
+    assert_eq!(series_map.len(), CGROUP_METRICS.len());
+    let values = get_metric_values(&series_map, "cgroup", -1, -1, 1000, TSTransform::NONE);
+    let throttled = &values["cgroup_throttled_time"];
+    assert_eq!(throttled["metric_kind"], "COUNTER");
+    assert_eq!(throttled["data"], serde_json::json!([2_000_000, 2_100_000, 2_200_000, 2_300_000, 2_400_000]));
+    assert_eq!(values["cgroup_memory_usage"]["data"][4], 1_052_576);
+    assert!(get_metric_values(&series_map, "host", -1, -1, 1000, TSTransform::NONE).as_object().unwrap().is_empty());
+
+    //counter转换为增量和速率，gauge合并时取平均值
+    let values = get_metric_values(&series_map, "cgroup", -1, -1, 1000, TSTransform::DELTA);
+    assert_eq!(values["cgroup_throttled_time"]["transform"], "delta");
+    assert_eq!(values["cgroup_throttled_time"]["data"], serde_json::json!([null, 100_000, 100_000, 100_000, 100_000]));
+    assert_eq!(values["cgroup_memory_usage"]["transform"], "none");
+    let values = get_metric_values(&series_map, "cgroup", -1, -1, 2000, TSTransform::RATE);
+    assert_eq!(values["cgroup_throttled_time"]["data"], serde_json::json!([null, 100_000.0]));
+    assert_eq!(values["cgroup_memory_usage"]["data"], serde_json::json!([1_048_576 + 500, 1_048_576 + 2500]));
+
+    //当前进程
+    if cfg!(target_os = "linux") {
+        match read_cgroup_metrics(std::process::id() as i64, 0) {
+            Ok(metrics) => println!("current process cgroup metrics: {:?}", metrics),
+            Err(e) => println!("read current process cgroup metrics failed: {}", e)
+        }
+    }
+
+    println!("cgroup metrics test passed");
+    Ok(())
+}
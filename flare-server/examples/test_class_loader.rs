@@ -0,0 +1,76 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::classloader::*;
+use flare_server::insights::generate_insights;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+const WEBAPP_LOADER: &str = "org.apache.catalina.loader.ParallelWebappClassLoader";
+
+//每秒一次快照：每次重新部署多出一个不能回收的 WebappClassLoader，ScriptClassLoader 的类只增不减，
+//临时的 TempLoader 最后被回收，AppClassLoader 保持不变
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 300);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "org.apache.catalina.startup.HostConfig.deployWARs()V")
+        .add_method(3, "org.apache.catalina.loader.WebappLoader.createClassLoader()Lorg/apache/catalina/loader/WebappClassLoaderBase;");
+    script.add_thread(10, "main", vec![vec![1]], 0);
+    let add_loader = |script: &mut AgentScript, sample_index: usize, id: i64, name: &str, classes: i64, stacktrace: Vec<i64>| {
+        script.add_event(ScriptedEvent::ClassLoader { sample_index, id, name: name.to_string(), classes, stacktrace });
+    };
+    for (i, sample_index) in vec![50, 100, 150, 200].into_iter().enumerate() {
+        let i = i as i64;
+        add_loader(&mut script, sample_index, 0, "<bootstrap>", 2000 + i * 500, vec![]);
+        add_loader(&mut script, sample_index, 11, "sun.misc.Launcher$AppClassLoader", 500, vec![]);
+        add_loader(&mut script, sample_index, 31, "com.example.ScriptClassLoader", 100 + i * 150, vec![]);
+        for j in 0..(i.min(2) + 1) {
+            let stacktrace = if j == i { vec![3, 2, 1] } else { vec![] };
+            add_loader(&mut script, sample_index, 21 + j, WEBAPP_LOADER, 300, stacktrace);
+        }
+        if i < 3 {
+            add_loader(&mut script, sample_index, 41, "com.example.TempLoader", 100 + i * 200, vec![]);
+        }
+    }
+
+    let collector = record_script(script.clone(), "target/testkit-samples/class_loader", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    let recorded = collector.lock().unwrap().get_class_loader_samples().len();
+    assert_eq!(recorded, 4 * 3 + 9 + 3);
+    collector.lock().unwrap().close();
+    drop(collector);
+    assert_eq!(load_class_loader_samples(&sample_data_dir)?.len(), recorded);
+
+    //重新打开取样目录
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let start_time = script.start_time;
+    let loaders = get_latest_class_loaders(&collector, -1, -1);
+    assert_eq!(loaders.len(), 6);
+    assert_eq!(loaders[0].loader_name, "<bootstrap>");
+
+    let leaks = detect_class_loader_leaks(&mut collector, -1, -1, &ClassLoaderLeakOptions::default());
+    println!("class loader leaks: {:?}", leaks);
+    assert_eq!(leaks.len(), 2);
+    assert_eq!(leaks[0].kind, "loader_type");
+    assert_eq!(leaks[0].loader_name, WEBAPP_LOADER);
+    assert_eq!((leaks[0].first_instances, leaks[0].last_instances), (1, 3));
+    assert_eq!(leaks[0].growth, 600);
+    //最新的实例带有创建调用栈
+    assert_eq!(leaks[0].loader_id, 23);
+    assert_eq!(leaks[0].defining_stack[1], "org.apache.catalina.startup.HostConfig.deployWARs()V");
+    assert_eq!(leaks[1].kind, "loader");
+    assert_eq!(leaks[1].loader_id, 31);
+    assert_eq!((leaks[1].first_classes, leaks[1].last_classes), (100, 550));
+    assert_eq!(leaks[1].first_time, start_time + 1000);
+
+    //时间范围内快照不足
+    let leaks = detect_class_loader_leaks(&mut collector, start_time, start_time + 2500, &ClassLoaderLeakOptions::default());
+    assert!(leaks.is_empty());
+
+    let insights = generate_insights(&mut collector, -1, -1, "en")?;
+    assert_eq!(insights.iter().filter(|x| x.kind == "class_loader_leak").count(), 2);
+    collector.close();
+    println!("class loader test passed");
+    Ok(())
+}
@@ -0,0 +1,85 @@
+extern crate flare_server;
+extern crate flare_proto;
+extern crate chrono;
+
+use chrono::Local;
+use flare_proto::agent::*;
+use flare_server::clock_sync::*;
+use flare_server::testkit::*;
+use std::io;
+
+fn new_script(start_time: i64, skew: i64) -> AgentScript {
+    let mut script = AgentScript::new(start_time, 20, 100);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000);
+    script.add_event(ScriptedEvent::ClockSync { sample_index: 0, skew });
+    script
+}
+
+//NTP方式估算agent的时钟偏差，校正之后收到的事件时间
+fn main() -> io::Result<()> {
+    //agent时钟快500ms，往返延迟10ms
+    let event = ClockSyncEvent { client_time: 1000, receive_time: 1505, send_time: 1506 };
+    let sample = compute_clock_sample(&event, 1011);
+    assert_eq!(sample, ClockSample { offset: -500, delay: 10 });
+    let mut estimator = ClockEstimator::default();
+    assert!(estimator.get_best_sample().is_none());
+    estimator.add_sample(sample);
+    estimator.add_sample(ClockSample { offset: -480, delay: 50 });
+    assert_eq!(estimator.get_best_sample().unwrap().offset, -500);
+    //只保留最近的测量
+    for _ in 0..8 {
+        estimator.add_sample(ClockSample { offset: -490, delay: 30 });
+    }
+    assert_eq!(estimator.get_best_sample().unwrap().offset, -490);
+    //测量误差内的偏差不校正
+    assert_eq!(get_clock_correction(&ClockSample { offset: -490, delay: 30 }), -490);
+    assert_eq!(get_clock_correction(&ClockSample { offset: 1, delay: 3 }), 0);
+
+    let mut event = AgentEvent::Thread(ThreadEvent { time: 2000, id: 1, name: "main".to_string(), cpu_time: 0, cpu_time_delta: 0,
+        state: "RUNNABLE".to_string(), stacktrace: vec![], start_time: 1000 });
+    correct_event_time(&mut event, -500);
+    match event {
+        AgentEvent::Thread(x) => assert_eq!(x.time, 1500),
+        _ => unreachable!()
+    }
+
+    //agent时钟慢5秒，连接时同步之后的取样转换为本地时间
+    let skew = 5000;
+    let script = new_script(Local::now().timestamp_millis() - 10_000 - skew, skew);
+    let collector = record_script(script.clone(), "target/testkit-samples/clock_sync", 10_000)?;
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    let quality = &sample_info.data_quality;
+    println!("data quality: {:?}", quality);
+    assert_eq!(quality.clock_sync_samples, 1);
+    assert!((quality.clock_skew_ms.unwrap() - skew).abs() < 50);
+    assert_eq!(quality.clock_correction_ms, quality.clock_skew_ms.unwrap());
+    let expected_last_time = script.start_time + 99 * 20 + quality.clock_correction_ms;
+    assert_eq!(sample_info.last_record_time, expected_last_time);
+    //同步响应之前收到的 sample_info 缓冲后按相同的偏差校正
+    assert_eq!(sample_info.record_start_time, script.start_time + quality.clock_correction_ms);
+    collector.lock().unwrap().close();
+
+    //之后的同步测量到不同的偏差，会话的偏差不变，事件时间不跳动
+    let mut script = new_script(Local::now().timestamp_millis() - 10_000 - skew, skew);
+    script.add_event(ScriptedEvent::ClockSync { sample_index: 50, skew: skew + 2000 });
+    let collector = record_script(script.clone(), "target/testkit-samples/clock_sync", 10_000)?;
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    let quality = &sample_info.data_quality;
+    assert_eq!(quality.clock_sync_samples, 2);
+    assert!((quality.clock_correction_ms - skew).abs() < 50);
+    assert_eq!(sample_info.last_record_time, script.start_time + 99 * 20 + quality.clock_correction_ms);
+    collector.lock().unwrap().close();
+
+    //关闭校正时只记录偏差
+    let collector = record_script_with(new_script(Local::now().timestamp_millis() - 10_000 - skew, skew), "target/testkit-samples/clock_sync", 10_000, |collector| {
+        collector.set_clock_sync_config(ClockSyncConfig { interval_secs: 0, correction: false });
+        Ok(())
+    })?;
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    assert!((sample_info.data_quality.clock_skew_ms.unwrap() - skew).abs() < 50);
+    assert_eq!(sample_info.data_quality.clock_correction_ms, 0);
+    collector.lock().unwrap().close();
+    println!("clock sync test passed");
+    Ok(())
+}
@@ -0,0 +1,58 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::gc::*;
+use flare_server::Profiler;
+use std::io;
+
+//3个线程(CPU从高到低)，第2秒和第4秒各有一次GC暂停，第3秒一个标记
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 300);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Service.handle()V");
+    script.add_thread(10, "worker-1", vec![vec![2, 1]], 2_000_000)
+        .add_thread(11, "worker-2", vec![vec![2, 1]], 1_000_000)
+        .add_thread(12, "idle", vec![vec![1]], 0);
+    script.add_event(ScriptedEvent::Gc { sample_index: 100, duration: 15_000 });
+    script.add_event(ScriptedEvent::Gc { sample_index: 200, duration: 45_000 });
+    script.add_event(ScriptedEvent::Marker { sample_index: 150, label: "deploy".to_string(), color: "red".to_string() });
+
+    let collector = record_script(script.clone(), "target/testkit-samples/combined_view", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    assert_eq!(collector.lock().unwrap().get_gc_pauses().len(), 2);
+    collector.lock().unwrap().close();
+    drop(collector);
+    assert_eq!(load_gc_pauses(&sample_data_dir)?.len(), 2);
+
+    let profiler = Profiler::new();
+    let mut profiler = profiler.lock().unwrap();
+    let session_id = profiler.open_sample(&sample_data_dir)?;
+    let start_time = script.start_time;
+    let view = profiler.get_combined_view(&session_id, -1, -1, 1000, 900, 2)?;
+    println!("combined view: {}", view);
+    assert_eq!(view["unit_time_ms"], 1000);
+    assert_eq!(view["gc_pauses"].as_array().unwrap().len(), 2);
+    assert_eq!(view["gc_summary"]["total_duration"], 60_000);
+    assert_eq!(view["gc_summary"]["max_duration"], 45_000);
+    assert_eq!(view["markers"][0]["label"], "deploy");
+    let threads = view["top_threads"].as_array().unwrap();
+    assert_eq!(threads.len(), 2);
+    assert_eq!(threads[0]["name"], "worker-1");
+    assert_eq!(threads[1]["name"], "worker-2");
+    let steps = view["steps"].as_u64().unwrap() as usize;
+    assert_eq!(view["cpu_time"].as_array().unwrap().len(), steps);
+    assert_eq!(threads[0]["cpu_time"].as_array().unwrap().len(), steps);
+    let total: i64 = view["cpu_time"].as_array().unwrap().iter().map(|x| x.as_i64().unwrap()).sum();
+    let top_total: i64 = threads.iter().map(|x| x["total_cpu_time"].as_i64().unwrap()).sum();
+    assert_eq!(total, top_total);
+
+    //只查询前3秒：一次GC，没有标记
+    let view = profiler.get_combined_view(&session_id, start_time, start_time + 2999, 1000, 900, 10)?;
+    assert_eq!(view["gc_pauses"].as_array().unwrap().len(), 1);
+    assert_eq!(view["gc_pauses"][0]["time"], start_time + 2000);
+    assert!(view["markers"].as_array().unwrap().is_empty());
+    assert_eq!(view["top_threads"].as_array().unwrap().len(), 2);
+    assert!(profiler.get_combined_view(&session_id, start_time + 3000, start_time + 1000, 1000, 900, 10).is_err());
+    println!("combined view test passed");
+    Ok(())
+}
@@ -0,0 +1,65 @@
+extern crate flare_server;
+extern crate libc;
+
+use flare_server::daemon::*;
+use std::io;
+use std::path::Path;
+
+//常驻运行模式：pid文件、日志轮转、信号及服务定义
+fn main() -> io::Result<()> {
+    let dir = "target/testkit-samples/daemon";
+    let _ = std::fs::remove_dir_all(dir);
+
+    //运行中的进程的pid文件不能覆盖，已经退出的进程的旧文件可以覆盖
+    let pid_path = format!("{}/run/flare-server.pid", dir);
+    let pid_file = PidFile::create(&pid_path)?;
+    assert_eq!(std::fs::read_to_string(&pid_path)?.trim(), std::process::id().to_string());
+    std::fs::write(&pid_path, "1\n")?;
+    assert_eq!(PidFile::create(&pid_path).err().unwrap().kind(), io::ErrorKind::AlreadyExists);
+    drop(pid_file);
+    assert!(!Path::new(&pid_path).exists());
+    std::fs::write(&pid_path, "999999999\n")?;
+    drop(PidFile::create(&pid_path)?);
+
+    //轮转后只保留 max_files 个旧文件
+    let log_path = format!("{}/flare-server.log", dir);
+    for i in 0..4 {
+        std::fs::write(&log_path, format!("log {}", i))?;
+        rotate_log_files(&log_path, 2)?;
+    }
+    assert!(!Path::new(&log_path).exists());
+    assert_eq!(std::fs::read_to_string(format!("{}.1", log_path))?, "log 3");
+    assert_eq!(std::fs::read_to_string(format!("{}.2", log_path))?, "log 2");
+    assert!(!Path::new(&format!("{}.3", log_path)).exists());
+
+    install_signal_handlers();
+    assert_eq!(take_signal_event(), None);
+    unsafe { libc::raise(libc::SIGHUP); }
+    assert_eq!(take_signal_event(), Some(DaemonEvent::Reload));
+    unsafe { libc::raise(libc::SIGTERM); }
+    assert_eq!(take_signal_event(), Some(DaemonEvent::Shutdown));
+
+    let config = DaemonConfig::default();
+    let options = ServiceOptions {
+        exe_path: "/opt/flare profiler/bin/flare_server".to_string(),
+        work_dir: "/opt/flare profiler".to_string(),
+        user: Some("flare".to_string()),
+    };
+    let unit = generate_service_definition(SERVICE_SYSTEMD, &config, &options)?;
+    assert!(unit.contains("ExecStart=\"/opt/flare profiler/bin/flare_server\" --daemon\n"));
+    assert!(unit.contains("PIDFile=/opt/flare profiler/flare-server.pid\n"));
+    assert!(unit.contains("User=flare\n"));
+    assert!(unit.contains("WantedBy=multi-user.target"));
+    let options = ServiceOptions {
+        exe_path: r"C:\Program Files\R&D\flare_server.exe".to_string(),
+        work_dir: r"C:\Program Files\R&D".to_string(),
+        user: None,
+    };
+    let xml = generate_service_definition(SERVICE_WINDOWS, &config, &options)?;
+    assert!(xml.contains(r"<executable>C:\Program Files\R&amp;D\flare_server.exe</executable>"));
+    assert!(xml.contains("<sizeThreshold>51200</sizeThreshold>"));
+    assert!(!xml.contains("serviceaccount"));
+    assert!(generate_service_definition("launchd", &config, &options).is_err());
+    println!("daemon test passed");
+    Ok(())
+}
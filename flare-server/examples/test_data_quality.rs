@@ -0,0 +1,60 @@
+extern crate flare_server;
+extern crate serde_json;
+
+use flare_server::data_quality::*;
+use flare_server::testkit::*;
+use flare_server::Profiler;
+use std::io;
+
+//取样偏差、截断的调用栈、丢弃的事件及时钟偏差随 sample_info 返回并保存
+fn main() -> io::Result<()> {
+    let mut quality = DataQuality::default();
+    //间隔20ms，第3轮晚到5ms，第4轮跳过一轮(没有变化的线程不发送)
+    for time in &[1000, 1020, 1045, 1080, 1080] {
+        quality.on_sample_time(*time, 20);
+    }
+    assert_eq!(quality.sample_rounds, 4);
+    assert_eq!(quality.max_jitter_ms, 5);
+    assert_eq!(quality.total_jitter_ms, 10);
+    assert_eq!(quality.avg_jitter_ms, 3.33);
+    quality.on_stack(10, 10);
+    quality.on_stack(AGENT_MAX_STACK_DEPTH, AGENT_MAX_STACK_DEPTH);
+    quality.on_stack(300, 100);
+    assert_eq!(quality.truncated_stacks, 2);
+    quality.on_receive(1000, 1250);
+    quality.on_receive(1020, 1240);
+    assert_eq!(quality.clock_skew_ms, Some(220));
+
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 100);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Service.handle()V");
+    let deep_stack: Vec<i64> = (0..AGENT_MAX_STACK_DEPTH).map(|x| if x % 2 == 0 { 2 } else { 1 }).collect();
+    script.add_thread(10, "worker-1", vec![vec![2, 1]], 1_000_000)
+        .add_thread(11, "recursive", vec![deep_stack], 1_000_000);
+    script.add_event(ScriptedEvent::Diagnostic { sample_index: 50, level: "warn".to_string(), kind: KIND_DROPPED_EVENTS.to_string(),
+        message: "sending queue is full, the oldest events are dropped".to_string(), count: 42 });
+
+    let collector = record_script(script.clone(), "target/testkit-samples/data_quality", 10_000)?;
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    let quality = &sample_info.data_quality;
+    println!("data quality: {:?}", quality);
+    assert_eq!(quality.sample_rounds, 100);
+    assert_eq!(quality.max_jitter_ms, 0);
+    assert_eq!(quality.truncated_stacks, 100);
+    assert_eq!(quality.dropped_samples, 42);
+    //脚本中的时间是过去的时间，模拟agent按本机时间响应 clock-sync，使用测量的偏差而不是接收延迟
+    assert_eq!(quality.clock_sync_samples, 1);
+    assert!(quality.clock_skew_ms.unwrap().abs() < 50);
+    assert_eq!(quality.clock_correction_ms, 0);
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    //重新打开取样时保留录制时的指标
+    let profiler = Profiler::new();
+    let mut profiler = profiler.lock().unwrap();
+    let session_id = profiler.open_sample(&sample_info.sample_data_dir)?;
+    let reopened = profiler.get_sample_info(&session_id)?;
+    assert_eq!(serde_json::to_value(&reopened.data_quality)?, serde_json::to_value(quality)?);
+    println!("data quality test passed");
+    Ok(())
+}
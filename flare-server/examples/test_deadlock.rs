@@ -0,0 +1,58 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::deadlock::*;
+use flare_server::insights::generate_insights;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//worker-1 持有 Account(A) 等待 Account(B)，worker-2 相反；第50次取样时agent推送死锁检测结果
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 100);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Bank.transfer(Lcom/example/Account;Lcom/example/Account;)V")
+        .add_method(3, "com.example.Account.withdraw(J)V");
+    script.add_thread(10, "worker-1", vec![vec![3, 2, 1]], 0)
+        .add_thread(11, "worker-2", vec![vec![3, 2, 1]], 0)
+        .add_thread(12, "main", vec![vec![1]], 1000);
+    for (thread_id, owner_id, name) in vec![(10, 11, "worker-1"), (11, 10, "worker-2")] {
+        script.add_event(ScriptedEvent::DeadlockThread {
+            sample_index: 50,
+            cycle: 0,
+            thread_id,
+            name: name.to_string(),
+            lock: "com.example.Account".to_string(),
+            owner_id,
+            stacktrace: vec![3, 2, 1],
+        });
+    }
+
+    //重新打开取样目录，检查保存的死锁
+    let collector = record_script(script.clone(), "target/testkit-samples/deadlock", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    assert_eq!(collector.lock().unwrap().get_deadlocks().len(), 1);
+    collector.lock().unwrap().close();
+    drop(collector);
+    let cycles = load_deadlocks(&sample_data_dir)?;
+    println!("deadlocks: {:?}", cycles);
+    assert_eq!(cycles.len(), 1);
+    assert_eq!(cycles[0].time, script.start_time + 50 * 20);
+    assert_eq!(cycles[0].threads.iter().map(|x| (x.thread_id, x.owner_id)).collect::<Vec<_>>(), vec![(10, 11), (11, 10)]);
+    assert_eq!(cycles[0].threads[0].stacktrace, vec![3, 2, 1]);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    assert_eq!(collector.get_deadlocks(), cycles.as_slice());
+    let cycles = get_deadlocks(&mut collector, script.start_time, script.get_end_time());
+    assert_eq!(cycles[0].threads[1].frames[0], "com.example.Account.withdraw(J)V");
+    assert!(get_deadlocks(&mut collector, script.start_time, script.start_time + 500).is_empty());
+    //只支持连接agent的会话
+    assert!(collector.request_deadlock_detection(0).is_err());
+    let insights = generate_insights(&mut collector, script.start_time, script.get_end_time() + 20, "en")?;
+    assert_eq!(insights.len(), 1);
+    assert_eq!(insights[0].kind, "deadlock");
+    assert_eq!(insights[0].title, "deadlock detected between threads: worker-1, worker-2");
+    collector.close();
+    println!("deadlock test passed");
+    Ok(())
+}
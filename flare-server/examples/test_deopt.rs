@@ -0,0 +1,53 @@
+extern crate flare_server;
+extern crate serde_json;
+
+use flare_server::testkit::*;
+use flare_server::deopt::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//Json.parse 反复反优化后重新编译，Json.encode 只重新编译一次
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 300);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;")
+        .add_method(3, "com.example.Json.encode(Ljava/lang/Object;)Ljava/lang/String;");
+    script.add_thread(10, "worker-1", vec![vec![2, 1], vec![3, 1]], 0);
+    for sample_index in vec![50, 100, 150, 200] {
+        script.add_event(ScriptedEvent::Deoptimization { sample_index, method: 2, count: 2 });
+    }
+    script.add_event(ScriptedEvent::Deoptimization { sample_index: 120, method: 2, count: 1 });
+    script.add_event(ScriptedEvent::Deoptimization { sample_index: 250, method: 3, count: 1 });
+
+    let collector = record_script(script.clone(), "target/testkit-samples/deopt", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    assert_eq!(collector.lock().unwrap().get_deopt_records().len(), 6);
+    collector.lock().unwrap().close();
+    drop(collector);
+    assert_eq!(load_deopt_records(&sample_data_dir)?.len(), 6);
+
+    //重新打开取样目录，按方法汇总
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let start_time = script.start_time;
+    let stats = get_deopt_stats(&mut collector, -1, -1, 10);
+    println!("deopt stats: {:?}", stats);
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].method_name, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;");
+    assert_eq!(stats[0].count, 9);
+    assert_eq!(stats[0].first_time, start_time + 1000);
+    assert_eq!(stats[0].last_time, start_time + 4000);
+    assert_eq!(stats[1].method_id, 3);
+
+    //时间范围及数量限制
+    let stats = get_deopt_stats(&mut collector, start_time, start_time + 2500, 1);
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].count, 5);
+    collector.close();
+
+    //旧版本记录中的 reason 忽略
+    let record: DeoptRecord = serde_json::from_str(r#"{"time":1,"method_id":2,"reason":"recompile","count":3}"#)?;
+    assert_eq!(record, DeoptRecord { time: 1, method_id: 2, count: 3 });
+    println!("deopt test passed");
+    Ok(())
+}
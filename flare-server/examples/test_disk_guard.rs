@@ -0,0 +1,91 @@
+extern crate flare_server;
+
+use flare_server::disk_guard::*;
+use flare_server::sample::SampleCollector;
+use flare_server::testkit::*;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn new_script(samples: usize) -> AgentScript {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, samples);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000)
+        .add_thread(11, "worker-2", vec![vec![1]], 1_000_000);
+    script
+}
+
+//磁盘空间不足时降采样或者停止录制，处理结果保存到取样目录
+fn main() -> io::Result<()> {
+    assert!(check_disk_guard_action("downsample").is_ok() && check_disk_guard_action("drop").is_err());
+    let mut state = DiskGuardState::default();
+    state.on_downsample(1000, 100, 5);
+    let kept = (0..20).flat_map(|i| vec![1000 + i * 20; 2]).filter(|x| state.keep_sample(*x, 20)).count();
+    assert_eq!((kept, state.dropped_samples), (8, 32));
+    state.on_resume(2000);
+    assert!(state.keep_sample(2010, 20));
+
+    //低于阈值进入空间不足，恢复到阈值的120%才退出，阈值附近波动时不会反复切换
+    let config = DiskGuardConfig::default();
+    assert_eq!(config.get_resume_free_mb(1000), 1200);
+    let mut disk_low = false;
+    let states: Vec<bool> = [1100, 990, 1010, 990, 1150, 1200, 1190].iter().map(|free_mb| {
+        disk_low = config.is_disk_low(*free_mb, 1000, disk_low);
+        disk_low
+    }).collect();
+    assert_eq!(states, vec![false, true, true, true, true, false, false]);
+    let config = DiskGuardConfig { resume_free_mb: 1500, ..DiskGuardConfig::default() };
+    assert!(config.is_disk_low(1400, 1000, true) && !config.is_disk_low(1500, 1000, true));
+    //小于阈值的恢复值按阈值
+    let config = DiskGuardConfig { resume_free_mb: 500, ..DiskGuardConfig::default() };
+    assert_eq!(config.get_resume_free_mb(1000), 1000);
+
+    //每5轮保留一轮
+    let script = new_script(200);
+    let collector = record_script_with(script.clone(), "target/testkit-samples/disk_guard", 10_000, |collector| {
+        collector.set_disk_low_downsampling(100, 5)
+    })?;
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    assert_eq!(sample_info.disk_guard.action, DISK_GUARD_DOWNSAMPLE);
+    assert_eq!(sample_info.disk_guard.dropped_samples, 320);
+    collector.lock().unwrap().close();
+    drop(collector);
+    let collector = SampleCollector::open(&sample_info.sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    assert_eq!(collector.get_disk_guard_state().downsample_rate, 5);
+    assert_eq!(collector.load_thread_samples(10, script.start_time, script.get_end_time())?.len(), 40);
+    collector.close();
+
+    //录制中停止，断开agent
+    let mut script = new_script(500);
+    script.realtime = true;
+    let mut agent = FakeAgentServer::start(script.clone())?;
+    let collector = SampleCollector::new(agent.get_addr(), "target/testkit-samples/disk_guard")?;
+    collector.lock().unwrap().subscribe_events()?;
+    thread::sleep(Duration::from_millis(1000));
+    collector.lock().unwrap().stop_recording_on_disk_low(50)?;
+    let start = Instant::now();
+    while !collector.lock().unwrap().is_disconnected() {
+        assert!(start.elapsed() < Duration::from_secs(5), "wait for disconnected timeout");
+        thread::sleep(Duration::from_millis(10));
+    }
+    let _ = agent.wait();
+    assert!(collector.lock().unwrap().stop_recording_on_disk_low(50).is_err());
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    let events = collector.lock().unwrap().get_session_events().to_vec();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, KIND_DISK_LOW);
+    collector.lock().unwrap().close();
+    drop(collector);
+    assert!(sample_info.last_record_time < script.get_end_time());
+
+    let collector = SampleCollector::open(&sample_info.sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let state = collector.get_disk_guard_state().clone();
+    assert_eq!((state.action.as_str(), state.free_mb), (DISK_GUARD_STOP, 50));
+    let samples = collector.load_thread_samples(10, script.start_time, script.get_end_time())?;
+    assert!(!samples.is_empty() && samples.len() < 500);
+    collector.close();
+    println!("disk guard test passed");
+    Ok(())
+}
@@ -0,0 +1,30 @@
+extern crate flare_server;
+
+use flare_server::embedded::*;
+use flare_server::agent_attach::get_agent_lib_file_name;
+use std::io;
+
+//发布目录中的文件优先，不存在且没有嵌入时返回错误；嵌入的文件解压后内容相同
+fn main() -> io::Result<()> {
+    assert!(get_agent_lib_file_name().contains("flareagent"));
+    assert_eq!(find_dist_file("Cargo.toml")?, "Cargo.toml");
+    assert_eq!(find_dist_file("agent/lib/not-exists.jar").unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(find_static_dir(), Some("static/".to_string()));
+    assert!(get_extract_dir()?.ends_with(format!("{:016x}", get_embedded_hash())));
+
+    if has_embedded_files() {
+        let dest_dir = std::path::Path::new("target/testkit-samples/embedded");
+        let _ = std::fs::remove_dir_all(dest_dir);
+        extract_embedded_files(dest_dir)?;
+        //再次解压时跳过已经存在的文件
+        extract_embedded_files(dest_dir)?;
+        for (name, data) in EMBEDDED_FILES {
+            assert_eq!(&std::fs::read(dest_dir.join(name))?[..], *data);
+            assert_eq!(get_embedded_file(name), Some(*data));
+        }
+    } else {
+        assert_eq!(get_embedded_file("res/static/index.html"), None);
+    }
+    println!("embedded test passed");
+    Ok(())
+}
@@ -0,0 +1,43 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::sample::StatsType;
+use std::io;
+
+//使用模拟agent录制取样数据，再通过查询接口校验结果
+fn main() -> io::Result<()> {
+    let samples = 500;
+    let mut script = AgentScript::new(1_570_000_000_000, 20, samples);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Worker.process()V")
+        .add_method(3, "com.example.Worker.sleep()V")
+        .add_thread(100, "worker-1", vec![vec![2, 1], vec![3, 1]], 1_000_000)
+        .add_thread(101, "worker-2", vec![vec![2, 1]], 2_000_000)
+        .add_event(ScriptedEvent::Marker { sample_index: 100, label: "start".to_string(), color: "red".to_string() });
+    let end_time = script.get_end_time();
+
+    let collector = record_script(script.clone(), "target/testkit-samples", 10_000)?;
+    let mut collector = collector.lock().unwrap();
+
+    let dashboard = collector.get_dashboard();
+    println!("threads: {}", dashboard.threads.len());
+    assert_eq!(dashboard.threads.len(), 2);
+    assert_eq!(collector.get_markers().len(), 1);
+
+    let series = collector.list_series();
+    println!("series: {:?}", series);
+    assert_eq!(series.len(), 2);
+    assert_eq!(series[0].labels.get("thread_name").map(|x| x.as_str()), Some("worker-1"));
+
+    let call_tree = collector.get_call_tree(&[100, 101], script.start_time, end_time)?;
+    println!("call tree total duration: {}, total cpu: {}", call_tree.total_duration, call_tree.total_cpu);
+    println!("{}", call_tree.format_call_tree(true));
+
+    let stacks = collector.get_collapsed_call_stacks(100, script.start_time, end_time, StatsType::SAMPLES)?;
+    println!("collapsed stacks: {:?}", stacks);
+    assert!(!stacks.is_empty());
+
+    collector.close();
+    println!("fake agent test is done.");
+    Ok(())
+}
@@ -0,0 +1,58 @@
+extern crate flare_server;
+extern crate flare_utils;
+
+use flare_server::testkit::*;
+use flare_server::finalizer::*;
+use flare_server::insights::generate_insights;
+use flare_server::sample::SampleCollector;
+use flare_utils::timeseries::TSTransform;
+use std::io;
+
+//Finalizer线程一直在执行 NativeHandle.finalize()，每秒积压增加2000个对象
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 500);
+    script.add_method(1, "java.lang.ref.Finalizer$FinalizerThread.run()V")
+        .add_method(2, "java.lang.ref.Finalizer.runFinalizer(Ljdk/internal/misc/JavaLangAccess;)V")
+        .add_method(3, "com.example.NativeHandle.finalize()V")
+        .add_method(4, "com.example.NativeHandle.release(J)V")
+        .add_method(5, "java.lang.Thread.run()V");
+    script.add_thread(3, "Finalizer", vec![vec![4, 3, 2, 1], vec![3, 2, 1]], 1000)
+        .add_thread(10, "worker-1", vec![vec![5]], 1000);
+    for i in 0..10 {
+        script.add_event(ScriptedEvent::Finalizer { sample_index: i * 50, pending: 1000 + 2000 * i as i64 });
+    }
+
+    let collector = record_script(script.clone(), "target/testkit-samples/finalizer", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    //重新打开取样目录，积压保存在jvm分组的指标中
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let end_time = script.get_end_time() + 1000;
+    let points = collector.get_metric_points(FINALIZER_PENDING_METRIC.name, script.start_time, end_time);
+    assert_eq!(points.len(), 10);
+    assert_eq!(points[9].1, 19_000);
+    assert!(collector.get_metric_values("jvm", -1, -1, 1000, TSTransform::NONE)["finalizer_pending"].is_object());
+
+    let pressure = detect_finalizer_pressure(&mut collector, script.start_time, end_time, &FinalizerOptions::default())?.unwrap();
+    println!("finalizer pressure: {:?}", pressure);
+    assert_eq!(pressure.max_pending, 19_000);
+    assert_eq!(pressure.growth_ratio, 1.0);
+    assert!(pressure.busy_ratio >= 0.9);
+    assert_eq!(pressure.finalize_methods[0].method_name, "com.example.NativeHandle.finalize()V");
+    assert_eq!(pressure.finalize_methods[0].samples, 500);
+
+    let insights = generate_insights(&mut collector, script.start_time, end_time, "en")?;
+    let insight = insights.iter().find(|x| x.kind == "finalizer").unwrap();
+    assert_eq!(insight.severity, "critical");
+    assert_eq!(insight.title, "finalization is a bottleneck: up to 19000 objects pending finalization, Finalizer thread busy 100%");
+
+    //积压低于下限时不报告
+    let options = FinalizerOptions { min_pending: 100_000, ..FinalizerOptions::default() };
+    assert!(detect_finalizer_pressure(&mut collector, script.start_time, end_time, &options)?.is_none());
+    collector.close();
+    println!("finalizer test passed");
+    Ok(())
+}
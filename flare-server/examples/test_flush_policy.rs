@@ -0,0 +1,45 @@
+extern crate flare_server;
+extern crate serde_json;
+
+use flare_server::flush_policy::*;
+use flare_server::testkit::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//按会话配置调用栈的刷新间隔及缓冲字节数，断开连接时写入剩余的数据
+fn main() -> io::Result<()> {
+    let default_policy = FlushPolicy::default();
+    assert_eq!((default_policy.interval_ms, default_policy.max_buffer_bytes, default_policy.fsync), (1000, 100 * 1024, false));
+    let options = serde_json::json!({"interval_ms": 200, "fsync": true});
+    let policy = default_policy.merge_options(options.as_object().unwrap())?;
+    assert_eq!(policy, FlushPolicy { interval_ms: 200, max_buffer_bytes: 100 * 1024, fsync: true });
+    let options = serde_json::json!({"max_buffer_bytes": -1});
+    assert!(policy.merge_options(options.as_object().unwrap()).is_err());
+
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Service.handle()V");
+    script.add_thread(10, "worker-1", vec![vec![2, 1]], 1_000_000)
+        .add_thread(11, "worker-2", vec![vec![1]], 1_000_000);
+    let policy = FlushPolicy { interval_ms: 60_000, max_buffer_bytes: 1024 * 1024, fsync: true };
+    let collector = record_script_with(script.clone(), "target/testkit-samples/flush_policy", 10_000, |collector| {
+        collector.set_flush_policy(policy.clone())
+    })?;
+    let status = collector.lock().unwrap().get_flush_status();
+    assert_eq!(status.policy, policy);
+    assert_eq!(status.buffered_bytes, 0);
+    assert!(status.last_flush_time > 0);
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let samples = collector.load_thread_samples(10, script.start_time, script.get_end_time())?;
+    assert_eq!(samples.len(), 200);
+    //已保存的取样不能修改
+    assert!(collector.set_flush_policy(FlushPolicy::default()).is_err());
+    collector.close();
+    println!("flush policy test passed");
+    Ok(())
+}
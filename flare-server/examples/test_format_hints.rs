@@ -0,0 +1,58 @@
+extern crate flare_server;
+#[macro_use]
+extern crate serde_json;
+
+use flare_server::format_hints::*;
+use flare_server::utils::wrap_response;
+use std::io;
+
+//按字段名推断单位增加 _display 文本，原始数值不变
+fn main() -> io::Result<()> {
+    assert_eq!(format_duration(1_500_000.0, "auto", 2), "1.50 ms");
+    assert_eq!(format_duration(1_500_000.0, "us", 0), "1500 µs");
+    assert_eq!(format_bytes(3.0 * 1024.0 * 1024.0, "auto", 1), "3.0 MiB");
+    assert_eq!(format_bytes(512.0, "auto", 2), "512 B");
+
+    let mut value = json!({
+        "start_time": 1570000000000i64,
+        "unit_time_ms": 1000,
+        "total_cpu_time": 250000,
+        "cpu_time": [100000, 150000],
+        "gc_pauses": [{"time": 1570000002000i64, "duration": 15000}],
+        "gc_summary": {"count": 1, "total_duration": 15000, "max_duration": 15000, "pause_ratio": 0.0025},
+        "threads": [{"id": 10, "cpu_time": 2000000000i64, "duration": 250}],
+        "total_bytes": 5242880,
+        "bytes_per_sec": 1048576
+    });
+    add_format_hints(&mut value, &FormatOptions::default());
+    println!("formatted: {}", value);
+    assert_eq!(value["total_cpu_time"], 250000);
+    assert_eq!(value["total_cpu_time_display"], "250.00 ms");
+    assert!(value.get("start_time_display").is_none());
+    assert!(value.get("unit_time_ms_display").is_none());
+    assert!(value.get("cpu_time_display").is_none());
+    assert_eq!(value["gc_pauses"][0]["duration_display"], "15.00 ms");
+    assert_eq!(value["gc_summary"]["pause_ratio_display"], "0.25%");
+    assert_eq!(value["threads"][0]["cpu_time_display"], "2.00 s");
+    assert_eq!(value["threads"][0]["duration_display"], "250.00 ms");
+    assert_eq!(value["total_bytes_display"], "5.00 MiB");
+    assert_eq!(value["bytes_per_sec_display"], "1.00 MiB/s");
+
+    //请求参数
+    let options = json!({"format_hints": {"duration_unit": "us", "bytes_unit": "KiB", "precision": 0}});
+    let format = FormatOptions::from_request(options.as_object().unwrap())?.unwrap();
+    assert_eq!((format.duration_unit.as_str(), format.bytes_unit.as_str(), format.precision), ("us", "KiB", 0));
+    assert_eq!(FormatOptions::from_request(json!({"format_hints": true}).as_object().unwrap())?, Some(FormatOptions::default()));
+    assert_eq!(FormatOptions::from_request(json!({"format_hints": false}).as_object().unwrap())?, None);
+    assert_eq!(FormatOptions::from_request(json!({}).as_object().unwrap())?, None);
+    assert!(FormatOptions::from_request(json!({"format_hints": {"bytes_unit": "MB"}}).as_object().unwrap()).is_err());
+
+    //只有设置了当前请求的格式化选项时响应才增加提示
+    let data = json!({"duration": 1200});
+    assert!(!format!("{:?}", wrap_response("test", &data)).contains("duration_display"));
+    set_response_format(Some(format));
+    assert!(format!("{:?}", wrap_response("test", &data)).contains("1200000 µs"));
+    set_response_format(None);
+    println!("format hints test passed");
+    Ok(())
+}
@@ -0,0 +1,46 @@
+extern crate flare_server;
+extern crate flare_proto;
+
+use flare_server::testkit::*;
+use flare_server::sample::SampleCollector;
+use flare_proto::names::unescape_name;
+use std::io;
+
+//字节码中的特殊方法名在写入时规范化，保存及查询结果可以直接放入JSON
+fn main() -> io::Result<()> {
+    let names = [
+        "java.lang.Thread.run()V",
+        "com.example.CalculatorTest.adds two numbers()V",
+        "scala.collection.immutable.List.$colon$colon(Ljava/lang/Object;)Lscala/collection/immutable/List;",
+        "com.sun.proxy.$Proxy12.invoke()V",
+        "com.example.Service$$Lambda$123/0x0000000800c0b440.apply()V",
+        "com.example.Gen.line\nbreak\u{2028}()V",
+        "com.example.Gen.back\\slash()V",
+    ];
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 50);
+    for (i, name) in names.iter().enumerate() {
+        script.add_method(i as i64 + 1, name);
+    }
+    script.add_thread(1, "main", vec![(1..=names.len() as i64).rev().collect()], 1_000_000);
+    let (start_time, end_time) = (script.start_time, script.get_end_time());
+    let collector = record_script(script, "target/testkit-samples/frame_names", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    for (i, name) in names.iter().enumerate() {
+        let stored = collector.get_method_name(i as i64 + 1);
+        assert!(!stored.chars().any(|x| x.is_control() || x == '\u{2028}'), "unescaped name: {:?}", stored);
+        assert_eq!(unescape_name(&stored), name.as_bytes());
+    }
+    assert_eq!(collector.get_method_name(2), names[1]);
+    assert_eq!(collector.get_method_name(6), "com.example.Gen.line\\u{000A}break\\u{2028}()V");
+    assert_eq!(collector.get_method_name(7), "com.example.Gen.back\\\\slash()V");
+    let tree = collector.get_call_tree(&[1], start_time, end_time)?.format_call_tree(true);
+    assert_eq!(tree.lines().count(), names.len() + 1);
+    collector.close();
+    println!("frame names test passed");
+    Ok(())
+}
@@ -0,0 +1,107 @@
+extern crate flare_server;
+#[macro_use]
+extern crate serde_json;
+
+use flare_server::Profiler;
+use flare_server::access::AccessToken;
+use flare_server::grafana::*;
+use flare_server::sample_generator::*;
+use std::io;
+
+//打开模拟取样数据，按Grafana SimpleJSON协议查询指标、时间序列及标记
+fn main() -> io::Result<()> {
+    let mut options = GeneratorOptions::default();
+    options.threads = 2;
+    options.duration_ms = 10_000;
+    let sample_data_dir = "target/test-samples/grafana-datasource";
+    if std::fs::metadata(sample_data_dir).is_ok() {
+        std::fs::remove_dir_all(sample_data_dir)?;
+    }
+    generate_sample(sample_data_dir, &options)?;
+
+    let profiler = Profiler::new();
+    let session_id = profiler.lock().unwrap().open_sample(sample_data_dir)?;
+    let sessions = profiler.lock().unwrap().get_sample_sessions();
+    sessions[0].1.lock().unwrap().add_marker(options.start_time + 3000, "deploy", "red", "user")?;
+
+    let result = handle_grafana_request(&profiler, "", "/grafana/", b"")?;
+    assert_eq!(result["status"], "ok");
+
+    let result = handle_grafana_request(&profiler, "", "/grafana/search", br#"{"target": ""}"#)?;
+    println!("search: {}", result);
+    let targets = result.as_array().unwrap();
+    assert_eq!(targets.len(), 3);
+    assert_eq!(targets[0]["value"], format!("{}/cpu_time/*", session_id));
+    assert_eq!(targets[1]["value"], format!("{}/cpu_time/1000", session_id));
+    let result = handle_grafana_request(&profiler, "", "/grafana/search", br#"{"target": "synthetic-worker-1"}"#)?;
+    assert_eq!(result.as_array().unwrap().len(), 1);
+
+    let request = json!({
+        "range": {"from": "2019-10-02T07:06:40.000Z", "to": "2019-10-02T07:06:50.000Z"},
+        "intervalMs": 1000,
+        "maxDataPoints": 500,
+        "targets": [
+            {"target": format!("{}/cpu_time/1000", session_id), "type": "timeserie"},
+            {"target": format!("{}/cpu_time/1001", session_id), "type": "timeserie"},
+            {"target": format!("{}/cpu_time/*", session_id), "type": "timeserie"}
+        ]
+    });
+    assert_eq!(options.start_time, 1_570_000_000_000);
+    let result = handle_grafana_request(&profiler, "", "/grafana/query", request.to_string().as_bytes())?;
+    let series = result.as_array().unwrap();
+    assert_eq!(series.len(), 3);
+    let points = |i: usize| series[i]["datapoints"].as_array().unwrap().clone();
+    assert_eq!(points(0).len(), 10);
+    assert_eq!(points(0)[0][1], options.start_time);
+    assert_eq!(points(0)[1][1], options.start_time + 1000);
+    //所有线程之和
+    for i in 0..10 {
+        let total = points(0)[i][0].as_i64().unwrap() + points(1)[i][0].as_i64().unwrap();
+        assert_eq!(points(2)[i][0].as_i64().unwrap(), total);
+    }
+
+    let request = json!({
+        "range": {"from": options.start_time, "to": options.start_time + 10_000},
+        "annotation": {"name": "markers", "query": session_id}
+    });
+    let result = handle_grafana_request(&profiler, "", "/grafana/annotations", request.to_string().as_bytes())?;
+    println!("annotations: {}", result);
+    assert_eq!(result.as_array().unwrap().len(), 1);
+    assert_eq!(result[0]["time"], options.start_time + 3000);
+    assert_eq!(result[0]["title"], "deploy");
+
+    assert!(handle_grafana_request(&profiler, "", "/grafana/query", br#"{"range": {"from": "now", "to": "now"}}"#).is_err());
+    let request = json!({"range": {"from": 0, "to": 1}, "targets": [{"target": format!("{}/heap/1000", session_id)}]});
+    assert!(handle_grafana_request(&profiler, "", "/grafana/query", request.to_string().as_bytes()).is_err());
+    assert!(handle_grafana_request(&profiler, "", "/grafana/metrics", b"").is_err());
+
+    //配置了令牌时需要提供令牌，受限的令牌只能查询允许访问的会话
+    profiler.lock().unwrap().set_access_tokens(vec![
+        AccessToken { name: "ops".to_string(), token: "t-ops".to_string(), admin: true, sessions: vec![] },
+        AccessToken { name: "samples-team".to_string(), token: "t-samples".to_string(), admin: false, sessions: vec!["*test-samples*".to_string()] },
+    ]);
+    let err = handle_grafana_request(&profiler, "", "/grafana/search", b"").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(handle_grafana_request(&profiler, "t-unknown", "/grafana/search", b"").is_err());
+    assert_eq!(handle_grafana_request(&profiler, "t-ops", "/grafana/search", b"")?.as_array().unwrap().len(), 3);
+    //模式只匹配取样目录名称，不匹配上级路径
+    assert_eq!(handle_grafana_request(&profiler, "t-samples", "/grafana/search", b"")?.as_array().unwrap().len(), 0);
+    let request = json!({
+        "range": {"from": options.start_time, "to": options.start_time + 10_000},
+        "targets": [{"target": format!("{}/cpu_time/1000", session_id)}]
+    });
+    let err = handle_grafana_request(&profiler, "t-samples", "/grafana/query", request.to_string().as_bytes()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    profiler.lock().unwrap().set_access_tokens(vec![]);
+
+    //只允许配置的跨域来源
+    let origins = vec!["https://grafana.example.com/".to_string()];
+    assert!(is_cors_origin_allowed(&origins, "https://grafana.example.com"));
+    assert!(!is_cors_origin_allowed(&origins, "https://evil.example.com"));
+    assert!(!is_cors_origin_allowed(&[], "https://grafana.example.com"));
+    assert!(is_cors_origin_allowed(&["*".to_string()], "https://evil.example.com"));
+
+    profiler.lock().unwrap().close_all_session()?;
+    println!("grafana datasource test is done.");
+    Ok(())
+}
@@ -0,0 +1,68 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::heap_histogram::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+fn classes(entries: &[(&str, i64, i64)]) -> Vec<(String, i64, i64)> {
+    entries.iter().map(|x| (x.0.to_string(), x.1, x.2)).collect()
+}
+
+//第10、20、25次取样时agent推送堆直方图，期间 com.example.Session 持续增长
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 30);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_thread(1, "main", vec![vec![1]], 1000)
+        .add_event(ScriptedEvent::HeapHistogram { sample_index: 10, classes: classes(&[
+            ("[B", 1000, 64_000), ("com.example.Session", 100, 4_800), ("java.lang.Thread", 20, 2_400)]), limit: 0 })
+        .add_event(ScriptedEvent::HeapHistogram { sample_index: 20, classes: classes(&[
+            ("com.example.Session", 5000, 240_000), ("[B", 1100, 70_000), ("java.util.HashMap$Node", 50, 1_600)]), limit: 0 })
+        //只返回字节数最多的2个类
+        .add_event(ScriptedEvent::HeapHistogram { sample_index: 25, classes: classes(&[
+            ("com.example.Session", 6000, 288_000), ("[B", 1200, 72_000), ("java.lang.Thread", 20, 2_400)]), limit: 2 });
+
+    let collector = record_script(script.clone(), "target/testkit-samples/heap_histogram", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let collector = collector.lock().unwrap();
+    let histograms = collector.get_heap_histograms().to_vec();
+    println!("heap histograms: {:?}", histograms);
+    assert_eq!(histograms.len(), 3);
+    assert_eq!((histograms[0].classes, histograms[0].total_count, histograms[0].total_bytes), (3, 1120, 71_200));
+    //截断的直方图按截断前计算总数
+    assert_eq!((histograms[2].classes, histograms[2].total_count, histograms[2].total_bytes, histograms[2].truncated), (2, 7220, 362_400, true));
+    let before = collector.load_heap_histogram(histograms[0].time)?;
+    let after = collector.load_heap_histogram(histograms[1].time)?;
+    assert_eq!(after.classes[0], HeapClassEntry { class_name: "com.example.Session".to_string(), count: 5000, bytes: 240_000 });
+    assert!(collector.load_heap_histogram(12345).is_err());
+
+    let diffs = diff_heap_histograms(&before, &after);
+    for diff in &diffs {
+        println!("{} {:?} {:?}", diff.class_name, diff.count_delta, diff.bytes_delta);
+    }
+    assert_eq!(diffs.iter().map(|x| x.class_name.as_str()).collect::<Vec<_>>(),
+               vec!["com.example.Session", "[B", "java.util.HashMap$Node", "java.lang.Thread"]);
+    assert_eq!((diffs[0].count_delta, diffs[0].bytes_delta), (Some(4900), Some(235_200)));
+    assert_eq!((diffs[3].count_after, diffs[3].bytes_delta), (Some(0), Some(-2_400)));
+
+    //截断的直方图中没有的类变化未知，不是删除的类，排在最后
+    let truncated = collector.load_heap_histogram(histograms[2].time)?;
+    let diffs = diff_heap_histograms(&after, &truncated);
+    assert_eq!(diffs.iter().map(|x| x.class_name.as_str()).collect::<Vec<_>>(),
+               vec!["com.example.Session", "[B", "java.util.HashMap$Node"]);
+    assert_eq!((diffs[2].count_before, diffs[2].count_after, diffs[2].bytes_delta), (Some(50), None, None));
+    //截断的直方图作为比较前的一方时同样未知
+    let diffs = diff_heap_histograms(&truncated, &before);
+    let thread = diffs.iter().find(|x| x.class_name == "java.lang.Thread").unwrap();
+    assert_eq!((thread.count_before, thread.count_after), (None, Some(20)));
+    let diffs = diff_heap_histograms(&before, &truncated);
+    let thread = diffs.iter().find(|x| x.class_name == "java.lang.Thread").unwrap();
+    assert_eq!((thread.count_before, thread.count_after, thread.bytes_delta), (Some(20), None, None));
+    assert!(collector.request_heap_histogram(true, 100).is_err());
+    println!("heap histogram test passed");
+    Ok(())
+}
@@ -0,0 +1,46 @@
+extern crate flare_server;
+
+use flare_server::host_metrics::*;
+use std::io;
+
+//模拟的/proc目录，检查主机指标的解析
+fn main() -> io::Result<()> {
+    let proc_root = "target/test-samples/host_metrics/proc";
+    std::fs::create_dir_all(format!("{}/net", proc_root))?;
+    std::fs::write(format!("{}/stat", proc_root), "cpu  100 10 50 800 40 0 5 0 20 0\ncpu0 50 5 25 400 20 0 3 0 10 0\nintr 12345\n")?;
+    std::fs::write(format!("{}/loadavg", proc_root), "1.25 0.80 0.50 2/345 6789\n")?;
+    std::fs::write(format!("{}/meminfo", proc_root), "MemTotal:       16384000 kB\nMemFree:         1000000 kB\nMemAvailable:    8192000 kB\n")?;
+    std::fs::write(format!("{}/diskstats", proc_root), "\
+   8       0 sda 100 0 2000 10 50 0 4000 20 0 30 30
+   8       1 sda1 90 0 1800 9 45 0 3600 18 0 27 27
+ 259       0 nvme0n1 10 0 200 1 5 0 400 2 0 3 3
+ 259       1 nvme0n1p1 10 0 200 1 5 0 400 2 0 3 3
+   7       0 loop0 1 0 8 0 0 0 0 0 0 0 0
+")?;
+    std::fs::write(format!("{}/net/dev", proc_root), "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  999999     100    0    0    0     0          0         0   999999     100    0    0    0     0       0          0
+  eth0:    1000      10    0    0    0     0          0         0     2000      20    0    0    0     0       0          0
+  eth1:     500       5    0    0    0     0          0         0      300       3    0    0    0     0       0          0
+")?;
+
+    let metrics = read_host_metrics_from(proc_root, 1000)?;
+    assert_eq!(metrics.cpu_total, 1005);
+    assert_eq!(metrics.cpu_busy, 1005 - 840);
+    assert_eq!((metrics.load1, metrics.load5, metrics.load15), (1.25, 0.8, 0.5));
+    assert_eq!(metrics.get_metric_value("host_load1"), 125);
+    assert_eq!((metrics.memory_total_bytes, metrics.memory_available_bytes), (16_384_000 * 1024, 8_192_000 * 1024));
+    assert_eq!((metrics.disk_read_bytes, metrics.disk_written_bytes), (2200 * 512, 4400 * 512));
+    assert_eq!((metrics.net_received_bytes, metrics.net_sent_bytes), (1500, 2300));
+    for metric in HOST_METRICS {
+        assert!(metrics.get_metric_value(metric.name) >= 0, "missing metric: {}", metric.name);
+    }
+    assert!(read_host_metrics_from("target/test-samples/host_metrics/none", 1000).is_err());
+
+    if cfg!(target_os = "linux") {
+        println!("current host metrics: {:?}", read_host_metrics(0)?);
+    }
+    println!("host metrics test passed");
+    Ok(())
+}
@@ -0,0 +1,51 @@
+extern crate flare_server;
+
+use flare_server::i18n::*;
+use flare_server::gc::GcSummary;
+use flare_server::report::*;
+
+fn new_report_data(lang: &str) -> ReportData {
+    ReportData {
+        lang: lang.to_string(),
+        agent_addr: "127.0.0.1:3333".to_string(),
+        sample_data_dir: "flare-samples/test".to_string(),
+        start_time: 1_570_000_000_000,
+        end_time: 1_570_000_010_000,
+        sample_interval: 20,
+        threads: 1,
+        samples: 0,
+        cpu_time: 0,
+        top_methods: vec![],
+        gc_pauses: vec![],
+        gc_summary: GcSummary { count: 0, total_duration: 0, max_duration: 0, pause_ratio: 0.0 },
+        insights: vec![],
+        flame_graph_svg: String::new(),
+    }
+}
+
+//语言选择、参数替换、错误信息模板匹配及报告模板
+fn main() {
+    assert_eq!(normalize_lang("zh_CN"), LANG_ZH);
+    assert_eq!(normalize_lang("ZH-tw"), LANG_ZH);
+    assert_eq!(normalize_lang("fr"), LANG_EN);
+    assert_eq!(tr("zh", "report.summary"), "概要");
+    assert_eq!(tr("fr", "report.summary"), "Summary");
+    assert_eq!(tr("zh", "no.such.key"), "no.such.key");
+    assert_eq!(tr_args("zh", "insight.deadlock", &[("threads", "a, b".to_string())]), "检测到线程死锁: a, b");
+
+    assert_eq!(localize_error("zh", "sample session not found"), "取样会话不存在");
+    assert_eq!(localize_error("zh", "missing option: session_id"), "缺少选项: session_id");
+    assert_eq!(localize_error("zh", "unsupported cmd: foo, protocol version: 3"), "不支持的命令: foo，协议版本: 3");
+    assert_eq!(localize_error("en", "missing option: session_id"), "missing option: session_id");
+    //未翻译的错误及只有前缀相同的错误保持原样
+    assert_eq!(localize_error("zh", "connection refused"), "connection refused");
+    assert_eq!(localize_error("zh", "sample session not found: abc"), "sample session not found: abc");
+
+    let html = render_html_report(&new_report_data("zh"));
+    assert!(html.contains("<html lang=\"zh\">"));
+    assert!(html.contains("<h2>热点方法</h2>") && html.contains("没有取样数据。"));
+    assert!(html.contains("<th>取样间隔</th><td>20ms</td>"));
+    let md = render_markdown_report(&new_report_data("en"), "");
+    assert!(md.contains("## Top Methods") && md.contains("| Sample interval | 20ms |"));
+    println!("i18n test passed");
+}
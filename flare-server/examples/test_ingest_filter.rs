@@ -0,0 +1,55 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::ingest_filter::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//丢弃 noisy-* 线程，每2个线程保留1个，调用栈只保留3层
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 100);
+    for i in 1..6 {
+        script.add_method(i, &format!("com.example.Frame{}.call()V", i));
+    }
+    script.add_thread(10, "main", vec![vec![5, 4, 3, 2, 1]], 1000)
+        .add_thread(11, "noisy-1", vec![vec![2, 1]], 1000)
+        .add_thread(12, "noisy-2", vec![vec![2, 1]], 1000)
+        .add_thread(13, "worker-a", vec![vec![3, 1]], 1000)
+        .add_thread(14, "worker-b", vec![vec![2, 1]], 1000);
+
+    let filter = IngestFilter {
+        drop_threads: vec!["noisy-*".to_string()],
+        max_depth: 3,
+        thread_sample_rate: 2,
+    };
+    let collector = record_script_with(script.clone(), "target/testkit-samples/ingest_filter", 10_000, |collector| {
+        collector.set_ingest_filter(filter.clone())
+    })?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    {
+        let collector = collector.lock().unwrap();
+        let stats = collector.get_ingest_stats();
+        println!("ingest stats: {:?}", stats);
+        assert_eq!(stats.dropped_by_name, 200);
+        assert_eq!(stats.dropped_by_sampling, 100);
+        assert_eq!(stats.truncated_stacks, 100);
+    }
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    //过滤配置及数量保存在取样目录中
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    assert_eq!(collector.get_ingest_filter(), Some(&filter));
+    assert_eq!(collector.get_ingest_stats().dropped_by_name, 200);
+    let mut thread_ids: Vec<i64> = collector.get_threads()?.iter().map(|x| x.id).collect();
+    thread_ids.sort();
+    assert_eq!(thread_ids, vec![10, 14]);
+    let samples = collector.load_thread_samples(10, script.start_time, script.get_end_time())?;
+    assert!(!samples.is_empty());
+    assert!(samples.iter().all(|x| x.stacktrace == vec![5, 4, 3]));
+    assert!(collector.set_ingest_filter(IngestFilter::default()).is_err());
+    collector.close();
+    println!("ingest filter test passed");
+    Ok(())
+}
@@ -0,0 +1,41 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//阶段按线程配对: 不同线程同名的阶段交错结束时，各自结束本线程最近开始的阶段
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 100);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_thread(10, "worker-1", vec![vec![1]], 1_000_000)
+        .add_thread(11, "worker-2", vec![vec![1]], 1_000_000);
+    script.add_event(ScriptedEvent::IntervalBegin { sample_index: 10, name: "batch".to_string(), thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalBegin { sample_index: 20, name: "batch".to_string(), thread_id: 11 })
+        .add_event(ScriptedEvent::IntervalBegin { sample_index: 30, name: "load".to_string(), thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 40, thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 50, thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 60, thread_id: 11 })
+        //旧版本agent没有线程id，结束最近一个未结束的阶段
+        .add_event(ScriptedEvent::IntervalBegin { sample_index: 70, name: "legacy".to_string(), thread_id: 0 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 80, thread_id: 0 });
+    let start_time = script.start_time;
+    let collector = record_script(script, "target/testkit-samples/intervals", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let intervals: Vec<(String, i64, i64, i64)> = collector.get_intervals().iter()
+        .map(|x| (x.name.clone(), x.thread_id, x.start_time, x.end_time)).collect();
+    assert_eq!(intervals, vec![
+        ("batch".to_string(), 10, start_time + 10 * 20, start_time + 50 * 20),
+        ("batch".to_string(), 11, start_time + 20 * 20, start_time + 60 * 20),
+        ("load".to_string(), 10, start_time + 30 * 20, start_time + 40 * 20),
+        ("legacy".to_string(), 0, start_time + 70 * 20, start_time + 80 * 20),
+    ]);
+    collector.close();
+    println!("intervals test passed");
+    Ok(())
+}
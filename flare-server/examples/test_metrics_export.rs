@@ -0,0 +1,58 @@
+extern crate flare_server;
+
+use flare_server::sample::SampleCollector;
+use flare_server::sample_generator::*;
+use flare_server::metrics_export::*;
+use std::io;
+
+//导出CSV及Parquet文件，校验行数和文件格式
+fn main() -> io::Result<()> {
+    let mut options = GeneratorOptions::default();
+    options.threads = 2;
+    options.duration_ms = 10_000;
+    options.methods = 50;
+    let sample_data_dir = "target/test-samples/metrics-export";
+    if std::fs::metadata(sample_data_dir).is_ok() {
+        std::fs::remove_dir_all(sample_data_dir)?;
+    }
+    generate_sample(sample_data_dir, &options)?;
+    let collector = SampleCollector::open(sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+
+    let mut export_options = MetricsExportOptions {
+        format: "csv".to_string(),
+        tables: EXPORT_TABLES.iter().map(|x| x.to_string()).collect(),
+        start_time: -1,
+        end_time: -1,
+        unit_time_ms: 1000,
+        exclude_warmup: false,
+    };
+    let export_dir = "target/test-samples/metrics-export-out";
+    let tables = export_metrics(&mut collector, export_dir, &export_options)?;
+    println!("csv tables: {:?}", tables);
+    //每个线程 10 个点
+    assert_eq!(tables[0].rows, 2 * 10);
+    let csv = std::fs::read_to_string(&tables[0].path)?;
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "time,thread_id,thread_name,cpu_time_us");
+    assert_eq!(lines.len(), tables[0].rows + 1);
+    assert!(lines[1].starts_with(&format!("{},1000,synthetic-worker-0,", options.start_time)));
+    let csv = std::fs::read_to_string(&tables[1].path)?;
+    assert!(csv.lines().nth(1).unwrap().contains("\"java.lang.Thread.run()V\"") || csv.lines().nth(1).unwrap().contains(",java.lang.Thread.run()V,"));
+
+    export_options.format = "parquet".to_string();
+    let tables = export_metrics(&mut collector, export_dir, &export_options)?;
+    println!("parquet tables: {:?}", tables);
+    for table in &tables {
+        let data = std::fs::read(&table.path)?;
+        assert_eq!(&data[0..4], b"PAR1");
+        assert_eq!(&data[data.len() - 4..], b"PAR1");
+    }
+
+    export_options.format = "xlsx".to_string();
+    assert!(export_metrics(&mut collector, export_dir, &export_options).is_err());
+
+    collector.close();
+    println!("metrics export test is done.");
+    Ok(())
+}
@@ -0,0 +1,57 @@
+extern crate flare_server;
+
+use flare_server::monotonic_time::*;
+use flare_server::session_events::*;
+use flare_server::testkit::*;
+use flare_server::Profiler;
+use std::io;
+
+//取样时间倒退时平移之后的事件时间，保持递增
+fn main() -> io::Result<()> {
+    let mut normalizer = TimeNormalizer::default();
+    assert_eq!(normalizer.check(10_000, 20), None);
+    assert_eq!(normalizer.check(10_020, 20), None);
+    //小的倒退不处理
+    assert_eq!(normalizer.check(9_500, 20), None);
+    assert_eq!(normalizer.get_adjust(), 0);
+    assert_eq!(normalizer.check(5_040, 20), Some(4_980));
+    assert_eq!(normalizer.get_adjust(), 5_000);
+    assert_eq!(normalizer.check(5_060, 20), None);
+    //时钟同步的平移不是跳变
+    normalizer.shift(-3_000);
+    assert_eq!(normalizer.check(5_080 - 3_000, 20), None);
+
+    //第50次取样时墙上时间倒退1分钟，之后又前进30秒
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000);
+    script.add_event(ScriptedEvent::ClockJump { sample_index: 50, delta: -60_000 });
+    script.add_event(ScriptedEvent::Marker { sample_index: 60, label: "after-jump".to_string(), color: "red".to_string() });
+    script.add_event(ScriptedEvent::ClockJump { sample_index: 100, delta: 30_000 });
+
+    let collector = record_script(script.clone(), "target/testkit-samples/monotonic_time", 10_000)?;
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    let start_time = script.start_time;
+    assert_eq!(sample_info.data_quality.clock_jumps, 1);
+    //平移到上一次取样之后一个取样间隔
+    let expected_last_time = start_time + 199 * 20 - 60_000 + 30_000 + 60_000;
+    assert_eq!(sample_info.last_record_time, expected_last_time);
+    let events = collector.lock().unwrap().get_session_events().to_vec();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, KIND_CLOCK_JUMP);
+    assert_eq!(events[0].time, start_time + 50 * 20);
+    let markers = collector.lock().unwrap().get_markers();
+    assert_eq!(markers[0].time, start_time + 60 * 20);
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    //按时间范围查询不会得到倒退的数据
+    let profiler = Profiler::new();
+    let mut profiler = profiler.lock().unwrap();
+    let session_id = profiler.open_sample(&sample_info.sample_data_dir)?;
+    let reopened = profiler.get_sample_info(&session_id)?;
+    assert_eq!(reopened.data_quality.clock_jumps, 1);
+    assert_eq!(reopened.record_start_time, start_time);
+    println!("monotonic time test passed");
+    Ok(())
+}
@@ -0,0 +1,95 @@
+extern crate flare_server;
+
+use flare_server::offcpu::*;
+use flare_server::sample::SampleCollector;
+use flare_server::sample_generator::*;
+use std::io;
+use std::time::Duration;
+
+const FOLDED_OUTPUT: &str = "\
+java;java.lang.Thread.run()V;com.example.Dao.query()V;-;entry_SYSCALL_64;do_syscall_64;schedule 3000
+java;java.lang.Thread.run()V;java.lang.Object.wait(J)V 500
+java;java.lang.Thread.run()V;com.example.Dao.query()V;-;entry_SYSCALL_64;do_syscall_64;schedule 2000
+synthetic-worke;java.lang.Thread.run()V;-;futex_wait_[k] 700
+warning: unknown symbols
+";
+
+//用模拟的offcputime脚本执行off-CPU取样，检查保存及合并结果
+fn main() -> io::Result<()> {
+    let test_dir = "target/test-samples/offcpu";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir)?;
+    }
+    std::fs::create_dir_all(test_dir)?;
+
+    let stacks = parse_offcpu_folded(FOLDED_OUTPUT);
+    assert_eq!(stacks.len(), 4);
+    assert_eq!(stacks[0].frames, vec!["schedule_[k]", "do_syscall_64_[k]", "entry_SYSCALL_64_[k]",
+                                      "com.example.Dao.query()V", "java.lang.Thread.run()V", OFFCPU_ROOT_FRAME]);
+    assert_eq!(stacks[1].frames.last().map(|x| x.as_str()), Some(OFFCPU_ROOT_FRAME));
+
+    let sample_data_dir = format!("{}/sample", test_dir);
+    let options = GeneratorOptions { threads: 1, duration_ms: 1000, ..Default::default() };
+    generate_sample(&sample_data_dir, &options)?;
+
+    //模拟的offcputime，检查参数后输出折叠格式
+    let tool = format!("{}/offcputime.sh", test_dir);
+    std::fs::write(&tool, format!("#!/bin/sh\n[ \"$1 $2 $3 $4\" = \"-f -d -p 4242\" ] || exit 1\ncat <<'END'\n{}END\n", FOLDED_OUTPUT))?;
+    std::process::Command::new("chmod").args(&["+x", &tool]).status()?;
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    assert!(start_offcpu_sampling("", 4242, 1, collector.clone()).is_err());
+    assert!(start_offcpu_sampling(&tool, 0, 1, collector.clone()).is_err());
+    //取样时间超过上限时按上限取样，执行失败的工具不保存结果
+    assert_eq!(start_offcpu_sampling("false", 4242, 100_000, collector.clone())?, MAX_OFFCPU_DURATION_SECS);
+    start_offcpu_sampling(&tool, 4242, 1, collector.clone())?;
+    for _ in 0..100 {
+        if !collector.lock().unwrap().get_offcpu_profiles().is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let profiles = collector.get_offcpu_profiles();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].pid, 4242);
+    let merged = merge_offcpu_stacks(profiles, -1, -1, "", -1);
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged[0].off_cpu_us, 5000);
+    assert_eq!(merged[1].off_cpu_us, 700);
+    assert_eq!(merged[2].off_cpu_us, 500);
+    assert!(merge_offcpu_stacks(profiles, -1, -1, "other", -1).is_empty());
+    assert!(merge_offcpu_stacks(profiles, profiles[0].end_time + 1, -1, "", -1).is_empty());
+
+    //截断的线程名称(comm)关联到取样的线程句柄，"java"没有匹配的线程
+    let threads = collector.get_threads()?;
+    assert_eq!(threads.len(), 1);
+    let worker = merge_offcpu_stacks(profiles, -1, -1, "", threads[0].id);
+    assert_eq!(worker.len(), 1);
+    assert_eq!((worker[0].thread_name.as_str(), worker[0].off_cpu_us), ("synthetic-worke", 700));
+    assert_eq!(merged[0].thread_handle, None);
+    assert!(match_comm("synthetic-worke", "synthetic-worker-0"));
+    assert!(match_comm("main", "main"));
+    assert!(!match_comm("synthetic-worke", "synthetic-work"));
+    assert!(!match_comm("main", "main-1"));
+
+    //合并到调用树，栈底为[off-cpu]节点，阻塞时间四舍五入到ms
+    let info = collector.get_sample_info();
+    let mut call_tree = collector.get_call_tree(&[threads[0].id], info.record_start_time, info.last_record_time)?;
+    let on_cpu_children = call_tree.to_tree().children.len();
+    assert!(on_cpu_children > 0);
+    collector.add_offcpu_call_stacks(&mut call_tree, &[threads[0].id], -1, -1);
+    let tree = call_tree.to_tree();
+    assert_eq!(tree.children.len(), on_cpu_children + 1);
+    let offcpu_root = tree.children.iter().find(|x| x.label == OFFCPU_ROOT_FRAME).unwrap();
+    assert_eq!((offcpu_root.calls, offcpu_root.cpu, offcpu_root.duration), (0, 0, 1));
+    assert_eq!(offcpu_root.children[0].label, "java.lang.Thread.run()V");
+    assert_eq!(offcpu_root.children[0].children[0].label, "futex_wait_[k]");
+
+    println!("off-cpu test passed: {}", sample_data_dir);
+    Ok(())
+}
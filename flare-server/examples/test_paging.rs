@@ -0,0 +1,83 @@
+#[macro_use]
+extern crate serde_json;
+#[macro_use]
+extern crate serde_derive;
+extern crate flare_server;
+
+use flare_server::paging::*;
+use serde_json::{Map, Value};
+
+#[derive(Serialize)]
+struct Item {
+    id: i64,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu: Option<i64>,
+    calls: Vec<i64>,
+}
+
+fn new_item(id: i64, name: &str, cpu: Option<i64>, calls: usize) -> Item {
+    Item { id, name: name.to_string(), cpu, calls: vec![0; calls] }
+}
+
+fn get_ids(values: &[Value]) -> Vec<i64> {
+    values.iter().map(|x| x["id"].as_i64().unwrap()).collect()
+}
+
+fn to_options(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
+
+const SORT_FIELDS: &[SortField] = &[("id", false), ("name", false), ("cpu", true), ("calls", true)];
+
+//分页参数解析、按字段排序及分页信息
+fn main() {
+    let items = vec![
+        new_item(3, "c", Some(10), 1),
+        new_item(1, "b", None, 3),
+        new_item(2, "a", Some(30), 2),
+        new_item(4, "d", Some(20), 0),
+    ];
+
+    //默认按第一个字段升序，返回全部
+    let query = PageQuery::from_options(&Map::new(), SORT_FIELDS, 0).unwrap();
+    assert_eq!(query, PageQuery { page: 0, page_size: 0, sort_by: "id".to_string(), desc: false });
+    let (total, page) = sort_and_page(&items, &query).unwrap();
+    assert_eq!((total, get_ids(&page)), (4, vec![1, 2, 3, 4]));
+
+    //字段的默认顺序，缺少字段的排在最后
+    let query = PageQuery::from_options(&to_options(json!({"sort_by": "cpu"})), SORT_FIELDS, 0).unwrap();
+    assert!(query.desc);
+    assert_eq!(get_ids(&sort_and_page(&items, &query).unwrap().1), vec![2, 4, 3, 1]);
+    let query = PageQuery::from_options(&to_options(json!({"sort_by": "cpu", "order": "asc"})), SORT_FIELDS, 0).unwrap();
+    assert_eq!(get_ids(&sort_and_page(&items, &query).unwrap().1), vec![3, 4, 2, 1]);
+
+    //字符串及数组长度
+    let query = PageQuery::from_options(&to_options(json!({"sort_by": "name"})), SORT_FIELDS, 0).unwrap();
+    assert_eq!(get_ids(&sort_and_page(&items, &query).unwrap().1), vec![2, 1, 3, 4]);
+    let query = PageQuery::from_options(&to_options(json!({"sort_by": "calls"})), SORT_FIELDS, 0).unwrap();
+    assert_eq!(get_ids(&sort_and_page(&items, &query).unwrap().1), vec![1, 2, 3, 4]);
+
+    //分页
+    let query = PageQuery::from_options(&to_options(json!({"page": 1, "page_size": 3, "order": "desc"})), SORT_FIELDS, 0).unwrap();
+    let (total, page) = sort_and_page(&items, &query).unwrap();
+    assert_eq!((total, get_ids(&page)), (4, vec![1]));
+    let query = PageQuery::from_options(&to_options(json!({"page": 5, "page_size": 3})), SORT_FIELDS, 0).unwrap();
+    assert!(sort_and_page(&items, &query).unwrap().1.is_empty());
+    //页码很大时返回空页，不溢出
+    let query = PageQuery::from_options(&to_options(json!({"page": 100000000000000000i64, "page_size": 1000})), SORT_FIELDS, 0).unwrap();
+    assert_eq!(query.get_offset(), usize::max_value());
+    assert!(sort_and_page(&items, &query).unwrap().1.is_empty());
+    let query = PageQuery::from_options(&to_options(json!({"page": 5, "page_size": 3})), SORT_FIELDS, 0).unwrap();
+    assert_eq!(PageQuery::from_options(&to_options(json!({"page": -1, "page_size": -1})), SORT_FIELDS, 100).unwrap().page_size, 0);
+    assert_eq!(PageQuery::from_options(&Map::new(), SORT_FIELDS, 100).unwrap().page_size, 100);
+
+    let mut result = json!({"items": []});
+    query.add_page_info(&mut result, total);
+    assert_eq!(result, json!({"items": [], "total": 4, "page": 5, "page_size": 3, "sort_by": "id", "order": "asc"}));
+
+    //无效的参数
+    assert!(PageQuery::from_options(&to_options(json!({"sort_by": "size"})), SORT_FIELDS, 0).is_err());
+    assert!(PageQuery::from_options(&to_options(json!({"order": "up"})), SORT_FIELDS, 0).is_err());
+    println!("paging test passed");
+}
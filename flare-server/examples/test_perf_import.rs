@@ -0,0 +1,80 @@
+extern crate flare_server;
+
+use flare_server::perf_import::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+const PERF_SCRIPT: &str = "\
+# ========
+# captured on    : Mon Oct 14 10:00:00 2019
+# ========
+#
+app 4242/4242 [001] 1000.000100:   10101010 cpu-clock:
+\tffffffff81050a6a native_write_msr+0xa ([kernel.kallsyms])
+\t55d0c0a01234 compute+0x14 (/usr/local/bin/app)
+\t55d0c0a00100 main+0x20 (/usr/local/bin/app)
+\t7f0a1b2c3d4e __libc_start_main+0xe7 (/lib/x86_64-linux-gnu/libc-2.27.so)
+
+io worker 4242/4243 [002] 1000.000200:   10101010 cpu-clock:
+\t7f0a1b2c0000 [unknown] (/lib/x86_64-linux-gnu/libpthread-2.27.so)
+\t55d0c0a05678 worker_loop+0x8 (/usr/local/bin/app)
+
+app 4242/4242 [001] 1000.002100:   10101010 cpu-clock:
+\t55d0c0a01234 compute+0x18 (/usr/local/bin/app)
+\t55d0c0a00100 main+0x20 (/usr/local/bin/app)
+\t7f0a1b2c3d4e __libc_start_main+0xe7 (/lib/x86_64-linux-gnu/libc-2.27.so)
+
+app 4242/4242 [001] 1000.010100:   10101010 cpu-clock:
+\t55d0c0a01300 compute+0x30 (/usr/local/bin/app)
+\t55d0c0a00100 main+0x20 (/usr/local/bin/app)
+\t7f0a1b2c3d4e __libc_start_main+0xe7 (/lib/x86_64-linux-gnu/libc-2.27.so)
+
+broken header line
+\t55d0c0a00100 main+0x20 (/usr/local/bin/app)
+";
+
+//导入perf script输出，检查线程、方法名称及时间对齐
+fn main() -> io::Result<()> {
+    let test_dir = "target/test-samples/perf_import";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir)?;
+    }
+    std::fs::create_dir_all(test_dir)?;
+    let perf_file = format!("{}/out.perf", test_dir);
+    std::fs::write(&perf_file, PERF_SCRIPT)?;
+
+    let header = parse_header("io worker 4242/4243 [002] 1000.000200:   10101010 cpu-clock:").unwrap();
+    assert_eq!((header.comm.as_str(), header.pid, header.tid), ("io worker", 4242, 4243));
+    assert_eq!(parse_header("app 77 123.5: cpu-clock:").map(|x| x.tid), Some(77));
+    assert_eq!(parse_frame("\tffffffff81050a6a native_write_msr+0xa ([kernel.kallsyms])").unwrap(), "native_write_msr ([kernel.kallsyms])");
+    assert_eq!(parse_frame("\t7f0a1b2c0000 [unknown] (/lib/libpthread-2.27.so)").unwrap(), "[unknown] (libpthread-2.27.so)");
+    assert_eq!(parse_frame("\t7f0a1b2c0000 [unknown] ([unknown])").unwrap(), "[unknown]");
+
+    let sample_data_dir = format!("{}/sample", test_dir);
+    let options = PerfImportOptions { sample_interval: 10, start_time: 1_570_000_000_005 };
+    let stats = import_perf_script(&perf_file, &sample_data_dir, &options)?;
+    println!("import stats: {:?}", stats);
+    assert_eq!(stats.threads, 2);
+    assert_eq!(stats.samples, 3);
+    //同一个线程2ms内的第二次取样
+    assert_eq!(stats.dropped_samples, 1);
+    assert_eq!(stats.invalid_events, 1);
+    assert!(import_perf_script(&perf_file, &sample_data_dir, &options).is_err());
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let sample_info = collector.get_sample_info();
+    assert_eq!(sample_info.sample_interval, 10);
+    assert_eq!(sample_info.record_start_time, 1_570_000_000_000);
+    assert_eq!(sample_info.last_record_time, 1_570_000_000_010);
+    let mut threads = collector.get_threads()?;
+    threads.sort_by_key(|x| x.id);
+    assert_eq!(threads.iter().map(|x| (x.id, x.name.as_str(), x.sample_count)).collect::<Vec<_>>(),
+               vec![(4242, "app", 2), (4243, "io worker", 1)]);
+    assert_eq!(collector.list_methods_by_filter("compute (app)")?.len(), 1);
+    assert_eq!(collector.list_methods_by_filter("(libc-2.27.so)")?.len(), 1);
+    collector.close();
+
+    println!("perf import test passed: {}", sample_data_dir);
+    Ok(())
+}
@@ -0,0 +1,212 @@
+extern crate flare_server;
+extern crate serde_json;
+
+use flare_server::plugins::*;
+use flare_server::plugin_script::*;
+use flare_server::sample::SampleCollector;
+use flare_server::sample_generator::*;
+use std::collections::HashMap;
+use std::io;
+use serde_json::json;
+
+const PLUGIN: &str = r###"
+name = "example"
+description = "classify synthetic services"
+
+[[commands]]
+name = "top_services"
+query = "top {limit} methods where method like 'com.example.{service}.%' order by cpu"
+defaults = { limit = "5", service = "service1" }
+
+[[commands]]
+name = "service_summary"
+query = "top 20 methods where method like 'com.example.%' order by cpu"
+script = '''
+let services = #{};
+for row in result.rows {
+    let service = split(row.name, ".")[2];
+    if !services.contains(service) {
+        services[service] = 0;
+    }
+    services[service] += row.samples;
+}
+let rows = [];
+for name in keys(services) {
+    rows.push(#{ service: name, samples: services[name] });
+}
+return sort_by(rows, "samples", true);
+'''
+
+[[reports]]
+name = "services"
+description = "top methods of each service"
+queries = { service1 = "top 3 methods where method like 'com.example.service1.%' order by cpu", service2 = "top 3 methods where method like 'com.example.service2.%' order by cpu" }
+defaults = { title = "Services" }
+script = '''
+let text = "# " + params.title + "\n";
+for name in keys(results) {
+    text += "## " + name + "\n";
+    for row in results[name].rows {
+        text += "- " + row.name + " (" + row.category + ")\n";
+    }
+}
+return text;
+'''
+
+[[classifiers]]
+category = "service1"
+patterns = ["com.example.service1.%"]
+
+[[classifiers]]
+category = "service2"
+patterns = ["com.example.service2.%"]
+"###;
+
+//加载插件目录，执行插件命令及调用栈分类
+fn main() -> io::Result<()> {
+    let plugins_dir = "target/test-samples/plugins";
+    if std::fs::metadata(plugins_dir).is_ok() {
+        std::fs::remove_dir_all(plugins_dir)?;
+    }
+    std::fs::create_dir_all(plugins_dir)?;
+    std::fs::write(format!("{}/example.toml", plugins_dir), PLUGIN)?;
+    std::fs::write(format!("{}/broken.toml", plugins_dir), "name = \"broken\"\n[[commands]]\nname = \"x\"\nquery = \"top 5 files\"\n")?;
+    std::fs::write(format!("{}/readme.txt", plugins_dir), "not a plugin")?;
+    //脚本语法错误时不加载插件
+    std::fs::write(format!("{}/bad_script.toml", plugins_dir), "name = \"bad_script\"\n[[commands]]\nname = \"x\"\nquery = \"top 5 methods\"\nscript = \"let x = ;\"\n")?;
+    std::fs::write(format!("{}/bad_function.toml", plugins_dir), "name = \"bad_function\"\n[[reports]]\nname = \"x\"\nscript = \"return read_file(\\\"/etc/passwd\\\");\"\n")?;
+
+    let registry = PluginRegistry::load_dir(plugins_dir, &ScriptLimits::default());
+    assert_eq!(registry.get_plugins().len(), 1);
+    assert!(registry.get_plugin("bad_script").is_err());
+    assert!(registry.get_plugin("bad_function").is_err());
+    let plugin = registry.get_plugin("example")?;
+    let command = registry.get_command("example", "top_services")?;
+    assert!(registry.get_command("example", "missing").is_err());
+
+    let mut params = serde_json::Map::new();
+    assert_eq!(render_query(&command.query, &command.defaults, &params)?, "top 5 methods where method like 'com.example.service1.%' order by cpu");
+    params.insert("service".to_string(), serde_json::Value::from("service2' or method like '%"));
+    assert!(render_query(&command.query, &command.defaults, &params).is_err());
+    params.insert("service".to_string(), serde_json::Value::from("service2"));
+    params.insert("limit".to_string(), serde_json::Value::from(3));
+
+    let mut options = GeneratorOptions::default();
+    options.threads = 2;
+    options.duration_ms = 5_000;
+    let sample_data_dir = "target/test-samples/plugins-sample";
+    if std::fs::metadata(sample_data_dir).is_ok() {
+        std::fs::remove_dir_all(sample_data_dir)?;
+    }
+    let stats = generate_sample(sample_data_dir, &options)?;
+    let collector = SampleCollector::open(sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+
+    let limits = registry.get_script_limits().clone();
+    let result = run_plugin_command(&mut collector, plugin, command, &params, &limits)?;
+    println!("plugin command: {}", result);
+    let rows = result["result"]["rows"].as_array().unwrap();
+    assert!(!rows.is_empty() && rows.len() <= 3);
+    for row in rows {
+        assert!(row["name"].as_str().unwrap().starts_with("com.example.service2."));
+        assert_eq!(row["category"], "service2");
+    }
+
+    //脚本后处理: 按服务汇总取样数
+    let summary_command = registry.get_command("example", "service_summary")?;
+    let summary = run_plugin_command(&mut collector, plugin, summary_command, &serde_json::Map::new(), &limits)?;
+    println!("service summary: {}", summary);
+    let summary_rows = summary["result"].as_array().unwrap();
+    assert!(!summary_rows.is_empty());
+    assert!(summary_rows.iter().all(|x| x["service"].as_str().unwrap().starts_with("service")));
+    let samples: Vec<i64> = summary_rows.iter().map(|x| x["samples"].as_i64().unwrap()).collect();
+    assert!(samples.windows(2).all(|x| x[0] >= x[1]));
+
+    //自定义报告
+    let report = registry.get_report("example", "services")?;
+    let mut report_params = serde_json::Map::new();
+    report_params.insert("title".to_string(), serde_json::Value::from("Example services"));
+    let report_result = run_plugin_report(&mut collector, plugin, report, &report_params, &limits)?;
+    let content = report_result["content"].as_str().unwrap();
+    println!("report:\n{}", content);
+    assert!(content.starts_with("# Example services\n## service1\n"));
+    assert!(content.contains("## service2\n- com.example.service2."));
+    assert!(content.contains("(service1)") && content.contains("(service2)"));
+    assert!(registry.get_report("example", "missing").is_err());
+
+    //操作数及内存限制
+    let small_limits = ScriptLimits { max_operations: 10_000, max_memory_bytes: 1024 * 1024 };
+    let mut loop_command = summary_command.clone();
+    loop_command.script = "while true { }".to_string();
+    let err = run_plugin_command(&mut collector, plugin, &loop_command, &serde_json::Map::new(), &small_limits).unwrap_err();
+    assert!(err.to_string().contains("exceeded max operations"), "{}", err);
+    loop_command.script = "let s = \"x\"; for i in range(0, 100) { s += s; }".to_string();
+    let err = run_plugin_command(&mut collector, plugin, &loop_command, &serde_json::Map::new(), &small_limits).unwrap_err();
+    assert!(err.to_string().contains("exceeded max memory"), "{}", err);
+    loop_command.script = "let rows = range(0, 1000000000);".to_string();
+    let err = run_plugin_command(&mut collector, plugin, &loop_command, &serde_json::Map::new(), &small_limits).unwrap_err();
+    assert!(err.to_string().contains("exceeded max memory"), "{}", err);
+
+    test_script()?;
+
+    let categories = classify_samples(&mut collector, &plugin.classifiers, -1, -1)?;
+    println!("categories: {:?}", categories);
+    let total: i64 = categories.iter().map(|x| x.samples).sum();
+    assert_eq!(total as usize, stats.samples);
+    assert!(categories.iter().any(|x| x.category == "service1"));
+    assert!(categories.iter().all(|x| ["service1", "service2", OTHER_CATEGORY].contains(&x.category.as_str())));
+
+    println!("plugins test is done.");
+    Ok(())
+}
+
+fn run_script(source: &str) -> io::Result<serde_json::Value> {
+    let output = compile_script(source)?.run(HashMap::new(), &ScriptLimits::default())?;
+    Ok(output.value.unwrap_or(serde_json::Value::Null))
+}
+
+//脚本语法及运行时错误
+fn test_script() -> io::Result<()> {
+    assert_eq!(run_script("return 1 + 2 * 3 - 4 / 3 % 2;")?, json!(6));
+    assert_eq!(run_script("return 7 / 2.0;")?, json!(3.5));
+    assert_eq!(run_script("let a = [1, 2, 3]; a[-1] = 10; a.push(4); return a;")?, json!([1, 2, 10, 4]));
+    assert_eq!(run_script("let m = #{ a: #{ b: 1 } }; m.a.c = m.a.b + 1; m[\"d\"] = m.missing; return m;")?, json!({"a": {"b": 1, "c": 2}, "d": null}));
+    assert_eq!(run_script("let n = 0; for i in range(0, 10) { if i % 2 == 0 { continue; } if i > 7 { break; } n += i; } return n;")?, json!(16));
+    assert_eq!(run_script("let i = 0; while i < 5 { i += 1; } return i;")?, json!(5));
+    assert_eq!(run_script("if false { return 1; } else if 1 < 2 && !false { return 2; } else { return 3; }")?, json!(2));
+    assert_eq!(run_script("return \"a\" + 1 + 0.5 + true + null;")?, json!("a10.5truenull"));
+    assert_eq!(run_script("return [len(\"中文\"), to_int(\"42\"), round(2.346, 2), to_fixed(1.0 / 3.0, 2), sum([1, 2, null, 3])];")?, json!([2, 42, 2.35, "0.33", 6]));
+    assert_eq!(run_script("return join(slice(reverse(split(\"a,b,c,d\", \",\")), 1, -1), \"-\").upper();")?, json!("C-B"));
+    assert_eq!(run_script("return sort_by([#{ x: 2 }, #{ x: 1 }, #{ y: 3 }], \"x\");")?, json!([{"y": 3}, {"x": 1}, {"x": 2}]));
+    assert_eq!(run_script("let x = 1;")?, serde_json::Value::Null);
+
+    //语法错误包含行号
+    let err = compile_script("let a = 1;\nlet b = (a + ;").unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+    assert!(compile_script("break;").is_err());
+    assert!(compile_script("1 = 2;").is_err());
+    assert!(compile_script("return \"unclosed;").is_err());
+    assert!(compile_script("let let = 1;").is_err());
+    assert!(compile_script("return system(\"ls\");").is_err());
+    //嵌套过深
+    assert!(compile_script(&format!("return {}1{};", "(".repeat(200), ")".repeat(200))).is_err());
+    assert!(compile_script(&format!("return 1{};", " + 1".repeat(200))).is_err());
+    assert!(compile_script(&format!("return 1{};", " + 1".repeat(50))).is_ok());
+
+    //运行时错误
+    for source in &["return 1 / 0;", "return 9223372036854775807 + 1;", "let a = [1]; return a[1];", "return undefined_var;",
+        "if 1 { }", "return \"a\" - 1;", "let a = 1; a.push(2);", "let a = []; for i in range(0, 40) { a = [a]; }", "return len(1);"] {
+        let err = run_script(source).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        println!("script error: {} => {}", source, err);
+    }
+    //输入变量不计入内存，复制时计入
+    let mut variables = HashMap::new();
+    variables.insert("data".to_string(), serde_json::Value::from("x".repeat(4096)));
+    let limits = ScriptLimits { max_operations: 100_000, max_memory_bytes: 8192 };
+    let output = compile_script("let n = len(data); data += \"y\";")?.run(variables.clone(), &limits)?;
+    assert_eq!(output.variables["n"], json!(4096));
+    assert_eq!(output.variables["data"].as_str().unwrap().len(), 4097);
+    assert!(compile_script("let a = data; let b = data;")?.run(variables, &limits).is_err());
+    Ok(())
+}
@@ -0,0 +1,72 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::pool_starvation::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//模拟的线程池: exec-* 周期性地全部阻塞在同一个锁上，io-* 全部阻塞在socket读取，worker-* 只有部分线程阻塞
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 300);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "java.util.concurrent.ThreadPoolExecutor.getTask()Ljava/lang/Runnable;")
+        .add_method(3, "sun.misc.Unsafe.park(ZJ)V")
+        .add_method(4, "com.example.Cache.get(Ljava/lang/String;)Ljava/lang/Object;")
+        .add_method(5, "com.example.Handler.handle()V")
+        .add_method(6, "java.net.SocketInputStream.socketRead0(Ljava/io/FileDescriptor;[BIII)I")
+        .add_method(7, "com.example.RemoteClient.call()V");
+
+    //每100次取样: 60次阻塞在Cache.get, 40次空闲等待任务
+    let mut exec_stacks = vec![vec![4, 5, 1]; 60];
+    exec_stacks.extend(vec![vec![3, 2, 1]; 40]);
+    for i in 0..3 {
+        script.add_thread(100 + i, &format!("exec-{}", i + 1), exec_stacks.clone(), 0);
+    }
+    script.add_thread(200, "io-1", vec![vec![6, 7, 1]], 1000)
+        .add_thread(201, "io-2", vec![vec![6, 7, 1]], 1000)
+        .add_thread(300, "worker-1", vec![vec![4, 5, 1]], 0)
+        .add_thread(301, "worker-2", vec![vec![5, 1]], 1000)
+        .add_thread(400, "main", vec![vec![4, 5, 1]], 0);
+    for thread in &mut script.threads {
+        if thread.name.starts_with("exec-") || thread.name == "worker-1" || thread.name == "main" {
+            thread.state = "BLOCKED".to_string();
+        }
+    }
+
+    //重新打开取样目录，读取完整的取样数据
+    let collector = record_script(script.clone(), "target/testkit-samples/pool_starvation", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let episodes = detect_pool_starvation(&mut collector, script.start_time, script.get_end_time() + 20, &StarvationOptions::default())?;
+    for episode in &episodes {
+        println!("{} {} {}~{} {}ms {}", episode.pool, episode.state, episode.start_time, episode.end_time, episode.duration_ms, episode.blocked_at);
+    }
+    let exec_episodes: Vec<_> = episodes.iter().filter(|x| x.pool == "exec-").collect();
+    assert_eq!(exec_episodes.len(), 3);
+    assert!(exec_episodes.iter().all(|x| x.duration_ms == 1200 && x.threads.len() == 3 && x.state == "BLOCKED"));
+    assert_eq!(exec_episodes[0].top_frame, "com.example.Cache.get(Ljava/lang/String;)Ljava/lang/Object;");
+    assert_eq!(exec_episodes[0].stacktrace.len(), 3);
+    let io_episodes: Vec<_> = episodes.iter().filter(|x| x.pool == "io-").collect();
+    assert_eq!(io_episodes.len(), 1);
+    assert_eq!((io_episodes[0].state.as_str(), io_episodes[0].blocked_at.as_str()), ("RUNNABLE", "com.example.RemoteClient.call()V"));
+    assert!(episodes.iter().all(|x| x.pool != "worker-"));
+
+    //持续时间不够
+    let options = StarvationOptions { min_duration_ms: 2000, ..Default::default() };
+    let episodes = detect_pool_starvation(&mut collector, script.start_time, script.get_end_time() + 20, &options)?;
+    assert!(episodes.iter().all(|x| x.pool == "io-"));
+
+    let options = StarvationOptions { pool_patterns: vec!["exec-*".to_string(), "io-*".to_string()], ..Default::default() };
+    let episodes = detect_pool_starvation(&mut collector, script.start_time, script.get_end_time() + 20, &options)?;
+    assert_eq!(episodes.iter().filter(|x| x.pool == "exec-*").count(), 3);
+    assert_eq!(get_pool_name("pool-1-thread-12", &[]), Some("pool-1-thread-".to_string()));
+    assert_eq!(get_pool_name("main", &[]), None);
+    assert!(match_wildcard("http-nio-*-exec-*", "http-nio-8080-exec-3"));
+    assert!(!match_wildcard("http-nio-*-exec-*", "http-nio-8080-acceptor"));
+    collector.close();
+    println!("pool starvation test passed");
+    Ok(())
+}
@@ -0,0 +1,47 @@
+extern crate flare_server;
+
+use flare_server::process_tree::*;
+use std::io;
+
+fn add_process(proc_root: &str, pid: i64, ppid: i64, comm: &str, args: &[&str], environ: &[&str]) -> io::Result<()> {
+    let dir = format!("{}/{}", proc_root, pid);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(format!("{}/stat", dir), format!("{} ({}) S {} {} {} 0 -1\n", pid, comm, ppid, pid, pid))?;
+    std::fs::write(format!("{}/cmdline", dir), args.iter().map(|x| format!("{}\0", x)).collect::<String>())?;
+    std::fs::write(format!("{}/environ", dir), environ.iter().map(|x| format!("{}\0", x)).collect::<String>())?;
+    Ok(())
+}
+
+//模拟的/proc目录: mvn(100) -> surefire jvm(200) -> jvm(300)，另一个无关的进程树(500)
+fn main() -> io::Result<()> {
+    let proc_root = "target/test-samples/process_tree/proc";
+    let _ = std::fs::remove_dir_all(proc_root);
+    add_process(proc_root, 1, 0, "systemd", &["/sbin/init"], &[])?;
+    add_process(proc_root, 100, 1, "java", &["/usr/bin/java", "-agentpath:/opt/flare/libflareagent.so=address=0.0.0.0:4000", "org.codehaus.plexus.classworlds.launcher.Launcher"], &[])?;
+    add_process(proc_root, 150, 100, "sh", &["/bin/sh", "-c", "echo"], &[])?;
+    add_process(proc_root, 200, 100, "java", &["/usr/lib/jvm/bin/java", "-jar", "surefirebooter.jar"], &["JAVA_TOOL_OPTIONS=-Xmx1g -agentpath:/opt/flare/libflareagent.so=address=4001,interval=5"])?;
+    add_process(proc_root, 300, 200, "my (worker) thread", &["/usr/lib/jvm/bin/java", "Worker"], &[])?;
+    add_process(proc_root, 500, 1, "java", &["java", "-agentpath:/opt/flare/libflareagent.so"], &[])?;
+
+    let info = read_process_info(proc_root, 300)?;
+    assert_eq!((info.ppid, info.name.as_str(), info.is_jvm, info.agent_addr.clone()), (200, "my (worker) thread", true, None));
+
+    let children = list_descendants(proc_root, 100)?;
+    assert_eq!(children.iter().map(|x| x.pid).collect::<Vec<_>>(), vec![150, 200, 300]);
+    assert!(!children[0].is_jvm);
+    assert_eq!(children[1].agent_addr, Some("127.0.0.1:4001".to_string()));
+    assert!(list_descendants(proc_root, 300)?.is_empty());
+
+    assert_eq!(read_process_info(proc_root, 100)?.agent_addr, Some("127.0.0.1:4000".to_string()));
+    assert_eq!(read_process_info(proc_root, 500)?.agent_addr, Some("127.0.0.1:3333".to_string()));
+    let args = vec!["-agentpath:/opt/flare/libflareagent.so=interval=10,address=10.0.0.5:5000".to_string()];
+    assert_eq!(parse_agent_addr(&args), Some("10.0.0.5:5000".to_string()));
+    assert_eq!(parse_agent_addr(&["-agentpath:/opt/other/libother.so=address=1".to_string()]), None);
+    assert!(read_process_info(proc_root, 999).is_err());
+
+    if cfg!(target_os = "linux") {
+        println!("child jvms of current process: {:?}", list_child_jvms(std::process::id() as i64)?);
+    }
+    println!("process tree test passed");
+    Ok(())
+}
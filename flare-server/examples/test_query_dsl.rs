@@ -0,0 +1,121 @@
+extern crate flare_server;
+
+use flare_server::sample_generator::*;
+use flare_server::testkit::*;
+use flare_server::query_dsl::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//录制模拟数据，校验查询语言的解析及执行结果
+fn main() -> io::Result<()> {
+    assert!(like_match("worker-1", "worker%"));
+    assert!(like_match("worker-1", "w_rker-_"));
+    assert!(!like_match("main", "worker%"));
+    assert!(parse_query("top 10 methods where").is_err());
+    assert!(parse_query("top 10 files").is_err());
+    //相对时间溢出时返回错误
+    assert!(parse_query("top 10 methods between +0s and +9223372036854775807h").is_err());
+    assert!(parse_query("top 10 methods between +0s and +9223372036854776s").is_err());
+    //指标与取样的条件及排序不能混用
+    assert!(parse_query("top 10 metrics where thread like 'worker%'").is_err());
+    assert!(parse_query("top 10 metrics order by cpu").is_err());
+    assert!(parse_query("top 10 methods where metric like 'cgroup%'").is_err());
+    assert!(parse_query("top 10 threads order by max").is_err());
+    let query = parse_query("top 5 metrics where metric like 'finalizer%' between +1s and +1h")?;
+    assert_eq!(query.target, QueryTarget::Metrics);
+    assert_eq!(query.order_by, QueryMetric::Avg);
+    assert_eq!(query.time_range, QueryTimeRange::Between(QueryTime::Relative(1000), QueryTime::Relative(3_600_000)));
+    let query = parse_query("TOP 3 self_methods where thread like 'worker%' and state = 'runnable' between +0s and +5s order by cpu")?;
+    assert_eq!(query.limit, 3);
+    assert_eq!(query.target, QueryTarget::SelfMethods);
+    assert_eq!(query.conditions, vec![QueryCondition::ThreadLike("worker%".to_string()), QueryCondition::State("RUNNABLE".to_string())]);
+    assert_eq!(query.time_range, QueryTimeRange::Between(QueryTime::Relative(0), QueryTime::Relative(5000)));
+    assert_eq!(query.order_by, QueryMetric::Cpu);
+
+    let mut options = GeneratorOptions::default();
+    options.threads = 3;
+    options.duration_ms = 10_000;
+    options.methods = 50;
+    let sample_data_dir = "target/test-samples/query-dsl";
+    if std::fs::metadata(sample_data_dir).is_ok() {
+        std::fs::remove_dir_all(sample_data_dir)?;
+    }
+    let stats = generate_sample(sample_data_dir, &options)?;
+    let collector = SampleCollector::open(sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+
+    let result = execute_query(&mut collector, &parse_query("top 10 methods where thread like '%worker-1'")?)?;
+    println!("methods: {:?}", result.rows);
+    assert_eq!(result.total_samples as usize, stats.samples / 3);
+    //所有调用栈的栈底都是 Thread.run
+    assert_eq!(result.rows[0].name, "java.lang.Thread.run()V");
+    assert_eq!(result.rows[0].samples, result.total_samples);
+    assert!(result.rows.len() <= 10);
+
+    let result = execute_query(&mut collector, &parse_query("top 1 self_methods where state = 'waiting'")?)?;
+    println!("self methods: {:?}", result.rows);
+    assert_eq!(result.rows[0].name, "java.lang.Object.wait(J)V");
+    assert_eq!(result.rows[0].samples, result.total_samples);
+
+    let result = execute_query(&mut collector, &parse_query("top 10 threads where thread_id = 1001 between +0s and +5s")?)?;
+    println!("threads: {:?}", result.rows);
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.rows[0].name, "synthetic-worker-1 [1001]");
+    assert!(result.rows[0].samples <= 5000 / options.sample_interval + 1);
+
+    //时间溢出、开始时间大于结束时间及超出录制范围时返回错误
+    let err = execute_query(&mut collector, &parse_query("top 1 methods between +0ms and +9223372036854775807ms")?).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(execute_query(&mut collector, &parse_query("top 1 methods between +5s and +1s")?).is_err());
+    assert!(execute_query(&mut collector, &parse_query("top 1 methods between +1h and +2h")?).is_err());
+    assert!(execute_query(&mut collector, &parse_query("top 1 methods between 1000 and 2000")?).is_err());
+    //部分重叠时可以查询
+    assert!(execute_query(&mut collector, &parse_query("top 1 methods between +5s and +1h")?).is_ok());
+
+    let result = execute_query(&mut collector, &parse_query("top 10 states order by cpu")?)?;
+    println!("states: {:?}", result.rows);
+    assert_eq!(result.rows[0].name, "RUNNABLE");
+
+    collector.close();
+    drop(collector);
+    check_metric_query()?;
+    println!("query dsl test is done.");
+    Ok(())
+}
+
+//Finalizer积压保存为指标，每秒一个数据点: 1000, 3000, .. 19000
+fn check_metric_query() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 500);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_thread(10, "worker-1", vec![vec![1]], 1000);
+    for i in 0..10 {
+        script.add_event(ScriptedEvent::Finalizer { sample_index: i * 50, pending: 1000 + 2000 * i as i64 });
+    }
+    let collector = record_script(script, "target/testkit-samples/query-dsl-metrics", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+
+    let result = execute_query(&mut collector, &parse_query("top 10 metrics order by max")?)?;
+    println!("metrics: {:?}", result.rows);
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.rows[0].name, "finalizer_pending");
+    assert_eq!(result.rows[0].samples, 10);
+    assert_eq!(result.total_samples, 10);
+    let metric = result.rows[0].metric.clone().unwrap();
+    assert_eq!((metric.min, metric.max, metric.last), (1000, 19_000, 19_000));
+    assert_eq!(metric.avg, 10_000.0);
+
+    //时间范围只包含后5个数据点
+    let result = execute_query(&mut collector, &parse_query("top 10 metrics where metric = 'finalizer_pending' between +5s and +10s")?)?;
+    let metric = result.rows[0].metric.clone().unwrap();
+    assert_eq!(result.rows[0].samples, 5);
+    assert_eq!((metric.min, metric.max), (11_000, 19_000));
+
+    let result = execute_query(&mut collector, &parse_query("top 10 metrics where metric like 'cgroup%'")?)?;
+    assert!(result.rows.is_empty());
+    collector.close();
+    Ok(())
+}
@@ -0,0 +1,59 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::rate_limit::*;
+use std::sync::atomic::Ordering;
+use websocket::OwnedMessage;
+
+//令牌桶限流及并发重查询限制
+fn main() {
+    //默认不限制
+    let mut limiter = ConnectionLimiter::new(&RateLimitConfig::default(), 0);
+    for _ in 0..1000 {
+        assert!(limiter.check("flame_graph", 0).is_none());
+    }
+
+    let config = RateLimitConfig { commands_per_sec: 10.0, burst: 3, max_heavy_queries: 0 };
+    let mut limiter = ConnectionLimiter::new(&config, 0);
+    for _ in 0..3 {
+        assert!(limiter.check("dashboard", 0).is_none());
+    }
+    let throttled = limiter.check("dashboard", 0).unwrap();
+    assert!(throttled.throttled);
+    assert_eq!(throttled.retry_after_ms, 100);
+    //100ms补充一个令牌
+    assert!(limiter.check("dashboard", 100).is_none());
+    assert!(limiter.check("dashboard", 150).is_some());
+    //最多积累burst个
+    for _ in 0..3 {
+        assert!(limiter.check("dashboard", 10_000).is_none());
+    }
+    assert!(limiter.check("dashboard", 10_000).is_some());
+    assert_eq!(limiter.get_throttled_count(), 3);
+
+    //重查询在分析线程池中执行期间计数
+    let config = RateLimitConfig { commands_per_sec: 0.0, burst: 0, max_heavy_queries: 1 };
+    let mut limiter = ConnectionLimiter::new(&config, 0);
+    assert!(limiter.check("flame_graph", 0).is_none());
+    set_heavy_query_counter(Some(limiter.get_heavy_query_counter()));
+    let counter = get_heavy_query_counter().unwrap();
+    set_heavy_query_counter(None);
+    assert!(get_heavy_query_counter().is_none());
+    counter.fetch_add(1, Ordering::SeqCst);
+    assert_eq!(limiter.get_running_heavy_queries(), 1);
+    let throttled = limiter.check("export_metrics", 0).unwrap();
+    assert!(throttled.message.contains("heavy queries"));
+    //轻量命令不受影响
+    assert!(limiter.check("list_threads", 0).is_none());
+    counter.fetch_sub(1, Ordering::SeqCst);
+    assert!(limiter.check("export_metrics", 0).is_none());
+
+    let message = wrap_throttled_response("flame_graph", ThrottledData { message: "rate limit exceeded".to_string(), throttled: true, retry_after_ms: 100 });
+    match message {
+        OwnedMessage::Text(text) => {
+            assert!(text.contains("\"result\":\"failure\"") && text.contains("\"throttled\":true") && text.contains("\"retry_after_ms\":100"));
+        }
+        _ => panic!("unexpected message")
+    }
+    println!("rate limit test passed");
+}
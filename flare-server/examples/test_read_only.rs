@@ -0,0 +1,76 @@
+extern crate flare_server;
+extern crate flare_proto;
+extern crate websocket;
+
+use flare_server::access::*;
+use flare_server::agent_recording::get_imported_sample_dir;
+use flare_server::sample::SampleCollector;
+use flare_server::testkit::AgentScript;
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use flare_proto::recording::*;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use websocket::sender::{Sender, Writer};
+
+//只读模式拒绝连接、录制及写入类命令，查询类命令不受影响
+fn main() -> io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+
+    let profiler = Profiler::new();
+    assert!(!profiler.lock().unwrap().is_read_only());
+    profiler.lock().unwrap().set_read_only(true);
+    assert!(profiler.lock().unwrap().is_read_only());
+
+    for cmd in &["connect_agent", "add_samples_root", "record_group", "export_sample", "save_view", "close_session", "close_all_session"] {
+        assert!(is_write_command(cmd));
+        let mut out_cmd = String::new();
+        let request = format!(r#"{{"cmd": "{}", "options": {{"agent_addr": "127.0.0.1:1", "samples_root": "target/read-only"}}}}"#, cmd);
+        let err = profiler.lock().unwrap().handle_request(&mut writer, request, &mut out_cmd).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "{}", cmd);
+        assert_eq!(err.to_string(), format!("server is read-only, cmd is rejected: {}", cmd));
+    }
+    assert!(std::fs::metadata("target/read-only").is_err());
+    //中文错误信息
+    let mut out_cmd = String::new();
+    let err = profiler.lock().unwrap().handle_request(&mut writer, r#"{"cmd": "connect_agent", "options": {"lang": "zh"}}"#.to_string(), &mut out_cmd).unwrap_err();
+    assert_eq!(err.to_string(), "服务为只读模式，拒绝执行命令: connect_agent");
+
+    for cmd in &["hello", "list_sessions", "history_samples", "list_baselines", "schema"] {
+        assert!(!is_write_command(cmd));
+        let mut out_cmd = String::new();
+        let request = format!(r#"{{"cmd": "{}"}}"#, cmd);
+        profiler.lock().unwrap().handle_request(&mut writer, request, &mut out_cmd)?;
+    }
+
+    //只读模式打开取样不写入目录: 不转换agent录制，不修改方法索引
+    let test_dir = "target/test-samples/read_only";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir)?;
+    }
+    let recording_dir = format!("{}/{}", test_dir, get_recording_dir_name(4250, "20191002T101010"));
+    std::fs::create_dir_all(&recording_dir)?;
+    let mut script = AgentScript::new(1_570_000_000_000, 1000, 10);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(1, "main", vec![vec![1]], 1_000_000);
+    let data: Vec<u8> = script.encode_messages().iter().flat_map(|x| x.encode()).collect();
+    std::fs::write(format!("{}/{}", recording_dir, RECORDING_EVENTS_FILE), &data)?;
+    let err = SampleCollector::open_with_options(&recording_dir, true, &mut |_, _| {}).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "{}", err);
+    assert_eq!(std::fs::read_dir(&recording_dir)?.count(), 1);
+
+    SampleCollector::open(&recording_dir)?.lock().unwrap().close();
+    let sample_dir = get_imported_sample_dir(&recording_dir).unwrap();
+    let method_file = sample_dir.join("method_info.fdata");
+    let modified = std::fs::metadata(&method_file)?.modified()?;
+    let collector = SampleCollector::open_with_options(&recording_dir, true, &mut |_, _| {})?;
+    assert_eq!(collector.lock().unwrap().list_methods_by_filter("java.lang.Thread")?.len(), 1);
+    collector.lock().unwrap().close();
+    assert_eq!(std::fs::metadata(&method_file)?.modified()?, modified);
+
+    println!("read only test passed");
+    Ok(())
+}
@@ -0,0 +1,52 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::Profiler;
+use flare_server::record_group::*;
+use std::io;
+
+fn new_script(thread_name: &str) -> AgentScript {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 50);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Service.handle()V")
+        .add_thread(100, thread_name, vec![vec![2, 1]], 1_000_000);
+    script
+}
+
+//使用两个模拟agent录制一个分组，检查分组目录及成员的取样目录
+fn main() -> io::Result<()> {
+    let profiler = Profiler::new();
+    let mut profiler = profiler.lock().unwrap();
+
+    let mut agent1 = FakeAgentServer::start(new_script("order-worker"))?;
+    let mut agent2 = FakeAgentServer::start(new_script("payment-worker"))?;
+    let targets = vec![agent1.get_addr().to_string(), agent2.get_addr().to_string()];
+    let settings = GroupSettings { record_host_metrics: false, duration_secs: 0 };
+    let group = profiler.start_record_group("shop", &targets, settings.clone())?;
+    assert!(group.is_recording());
+    assert_eq!(group.members.len(), 2);
+    assert!(is_group_dir(&group.group_dir));
+    agent1.wait()?;
+    agent2.wait()?;
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    let group = profiler.stop_record_group(&group.group_id)?;
+    assert!(!group.is_recording());
+    for member in &group.members {
+        println!("member: {}, dirs: {:?}", member.agent_addr, member.sample_data_dirs);
+        assert_eq!(member.sample_data_dirs.len(), 1);
+    }
+    assert_eq!(RecordGroup::load(&group.group_dir)?, group);
+    assert!(profiler.stop_record_group(&group.group_id).is_err());
+
+    assert_eq!(list_member_dirs(&group.group_dir).len(), 2);
+
+    //任何一个成员连接失败时不创建分组
+    let _agent3 = FakeAgentServer::start(new_script("stock-worker"))?;
+    let targets = vec![_agent3.get_addr().to_string(), "127.0.0.1:1".to_string()];
+    assert!(profiler.start_record_group("broken", &targets, settings).is_err());
+    assert!(profiler.get_sample_sessions().is_empty());
+    std::fs::remove_dir_all(&group.group_dir)?;
+    println!("record group test passed");
+    Ok(())
+}
@@ -0,0 +1,61 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::sample_generator::{generate_sample, GeneratorOptions};
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use websocket::sender::{Sender, Writer};
+
+//重新加载配置文件：打开的会话不受影响，无效的配置文件不改变当前配置
+fn main() -> io::Result<()> {
+    let work_dir = "target/testkit-samples/reload-config";
+    let _ = std::fs::remove_dir_all(work_dir);
+    std::fs::create_dir_all(work_dir)?;
+    std::env::set_current_dir(work_dir)?;
+    std::fs::write("flare-server.conf", "samples_roots = [\"samples\"]\n")?;
+
+    let options = GeneratorOptions { threads: 2, duration_ms: 2000, ..Default::default() };
+    generate_sample("samples/order-1", &options)?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let profiler = Profiler::new();
+    let mut request = |json: &str| {
+        let mut out_cmd = String::new();
+        profiler.lock().unwrap().handle_request(&mut writer, json.to_string(), &mut out_cmd)
+    };
+    request(r#"{"cmd": "open_sample", "options": {"sample_data_dir": "samples/order-1", "async": false}}"#)?;
+
+    std::fs::write("flare-server.conf", "samples_roots = [\"samples\", \"archive\"]\nsession_idle_timeout_secs = 600\n\n[daemon]\nlog_file = \"logs/new.log\"\n")?;
+    request(r#"{"cmd": "reload_config", "options": {}}"#)?;
+    let mut profiler_ref = profiler.lock().unwrap();
+    assert_eq!(profiler_ref.get_sample_sessions().len(), 1);
+    assert!(std::path::Path::new("archive").is_dir());
+    assert_eq!(profiler_ref.get_daemon_config().log_file, "logs/new.log");
+    assert_eq!(profiler_ref.reload_config()?, Vec::<String>::new());
+
+    //配置了令牌后，结果中只有配置项名称
+    std::fs::write("flare-server.conf", "samples_roots = [\"samples\", \"archive\"]\nsession_idle_timeout_secs = 600\n\n[[access_tokens]]\nname = \"ops\"\ntoken = \"secret-1\"\nadmin = true\n\n[daemon]\nlog_file = \"logs/new.log\"\n")?;
+    let changed = profiler_ref.reload_config()?;
+    assert_eq!(changed, vec!["access_tokens".to_string()]);
+
+    //无效的配置文件及配置项不改变当前配置
+    std::fs::write("flare-server.conf", "samples_roots = [\"samples\"\n")?;
+    assert_eq!(profiler_ref.reload_config().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    std::fs::write("flare-server.conf", "samples_roots = [\"samples\"]\n\n[disk_guard]\naction = \"delete\"\n")?;
+    assert!(profiler_ref.reload_config().is_err());
+    assert_eq!(profiler_ref.get_daemon_config().log_file, "logs/new.log");
+
+    //--read-only 启动参数在重新加载后保持
+    profiler_ref.set_read_only(true);
+    std::fs::write("flare-server.conf", "samples_roots = [\"samples\"]\n")?;
+    let changed = profiler_ref.reload_config()?;
+    assert_eq!(changed, vec!["access_tokens".to_string(), "daemon".to_string(), "samples_roots".to_string(), "session_idle_timeout_secs".to_string()]);
+    assert!(profiler_ref.is_read_only());
+    assert_eq!(profiler_ref.get_sample_sessions().len(), 1);
+    println!("reload config test passed");
+    Ok(())
+}
@@ -0,0 +1,81 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::report::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//生成HTML报告，检查各个部分及方法名的转义
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 300);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;")
+        .add_method(3, "com.example.Cache.<init>()V");
+    script.add_thread(10, "worker-1", vec![vec![2, 1], vec![2, 1], vec![3, 1]], 1_000_000);
+    script.add_event(ScriptedEvent::Gc { sample_index: 100, duration: 12_000 });
+    script.add_event(ScriptedEvent::Gc { sample_index: 200, duration: 30_000 });
+
+    let collector = record_script(script.clone(), "target/testkit-samples/report", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let data = collect_report_data(&collector, &ReportOptions::default())?;
+    assert_eq!(data.samples, 300);
+    assert_eq!(data.threads, 1);
+    assert_eq!(data.top_methods[0].method_name, "com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;");
+    assert_eq!(data.top_methods[0].self_samples, 200);
+    assert_eq!(data.top_methods[1].self_samples, 100);
+    assert_eq!(data.gc_summary.count, 2);
+    assert!(data.flame_graph_svg.contains("<svg"));
+
+    let report = generate_report(&collector, &ReportOptions::default())?;
+    println!("report: {}, size: {}", report.path, report.size);
+    assert!(report.path.starts_with(&format!("{}/{}/report-", sample_data_dir, REPORT_DIR)));
+    let html = std::fs::read_to_string(&report.path)?;
+    for section in &["<h2>Summary</h2>", "<h2>Insights</h2>", "<h2>Top Methods</h2>", "<h2>Flame Graph</h2>", "<h2>GC Pauses</h2>", "class=\"gc-chart\""] {
+        assert!(html.contains(section), "missing section: {}", section);
+    }
+    assert!(html.contains("com.example.Cache.&lt;init&gt;()V"));
+    assert!(!html.contains("<?xml"));
+    //不引用外部资源
+    assert!(!html.contains("<script src") && !html.contains("<link "));
+
+    //Markdown报告引用单独的火焰图文件
+    let options = ReportOptions { format: "markdown".to_string(), ..Default::default() };
+    let report = generate_report(&collector, &options)?;
+    assert!(report.path.ends_with(".md"));
+    let md = std::fs::read_to_string(&report.path)?;
+    for section in &["## Summary", "## Insights", "## Top Methods", "## Flame Graph", "## GC Pauses"] {
+        assert!(md.contains(section), "missing section: {}", section);
+    }
+    assert!(md.contains("| 1 | `com.example.Json.parse(Ljava/lang/String;)Ljava/lang/Object;` | 200 |"));
+    let flame_graph_file = report.path.replace(".md", "-flamegraph.svg");
+    assert!(md.contains(&format!("![Flame Graph]({})", flame_graph_file.rsplit('/').next().unwrap())));
+    assert!(std::fs::read_to_string(&flame_graph_file)?.starts_with("<svg"));
+    //最长的暂停在前
+    assert!(md.find("| 30.000ms |").unwrap() < md.find("| 12.000ms |").unwrap());
+
+    //PDF需要配置转换工具，用cp模拟
+    let options = ReportOptions { format: "pdf".to_string(), ..Default::default() };
+    assert!(generate_report(&collector, &options).is_err());
+    let options = ReportOptions { format: "pdf".to_string(), pdf_tool: "cp".to_string(), ..Default::default() };
+    let report = generate_report(&collector, &options)?;
+    assert!(report.path.ends_with(".pdf"));
+    assert_eq!(std::fs::read_to_string(&report.path)?, std::fs::read_to_string(report.path.replace(".pdf", ".html"))?);
+    let options = ReportOptions { format: "docx".to_string(), ..Default::default() };
+    assert!(generate_report(&collector, &options).is_err());
+
+    //只包含第2秒的GC
+    let start_time = script.start_time;
+    let options = ReportOptions { start_time, end_time: start_time + 2999, ..Default::default() };
+    let data = collect_report_data(&collector, &options)?;
+    assert_eq!(data.gc_pauses.len(), 1);
+    assert_eq!(data.samples, 150);
+    let options = ReportOptions { start_time: start_time + 3000, end_time: start_time, ..Default::default() };
+    assert!(generate_report(&collector, &options).is_err());
+    collector.lock().unwrap().close();
+    println!("report test passed");
+    Ok(())
+}
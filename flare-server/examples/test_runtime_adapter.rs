@@ -0,0 +1,73 @@
+extern crate flare_server;
+extern crate serde_json;
+
+use flare_server::runtime_adapter::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+use std::time::Duration;
+
+//py-spy dump 转换得到的折叠调用栈，每次取样以空行分隔
+const COLLAPSED_STACKS: &str = "\
+MainThread;<module> (app.py:10);main (app.py:5);handle (app.py:20) 1
+worker-1;run (threading.py:870);work (worker.py:12) 1
+
+MainThread;<module> (app.py:10);main (app.py:5);handle (app.py:21) 1
+worker-1;run (threading.py:870);work (worker.py:12);compute (worker.py:30) 1
+
+MainThread;<module> (app.py:10);main (app.py:5);handle (app.py:20) 1
+";
+
+//通过collapsed适配器录制非JVM取样数据，重新打开后检查线程及方法
+fn main() -> io::Result<()> {
+    let samples_root = "target/test-samples/runtime_adapter";
+    if std::fs::metadata(samples_root).is_ok() {
+        std::fs::remove_dir_all(samples_root)?;
+    }
+    std::fs::create_dir_all(samples_root)?;
+    let stacks_file = format!("{}/stacks.txt", samples_root);
+    std::fs::write(&stacks_file, COLLAPSED_STACKS)?;
+
+    assert!(list_adapters().iter().any(|x| x == "jvm"));
+    assert!(list_adapters().iter().any(|x| x == "collapsed"));
+    assert!(create_adapter("dotnet", &stacks_file, &serde_json::Map::new()).is_err());
+    let mut options = serde_json::Map::new();
+    options.insert("sample_interval".to_string(), serde_json::Value::from(10));
+    //只能读取取样根目录下的文件
+    set_adapter_file_roots(&[samples_root.to_string()]);
+    let mut outside = create_adapter("collapsed", "Cargo.toml", &options)?;
+    assert_eq!(outside.connect().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    let mut escaped = create_adapter("collapsed", &format!("{}/../../../Cargo.toml", samples_root), &options)?;
+    assert_eq!(escaped.connect().unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    let adapter = create_adapter("collapsed", &stacks_file, &options)?;
+
+    let collector = SampleCollector::new(&format!("collapsed:{}", stacks_file), samples_root)?;
+    collector.lock().unwrap().start_adapter(adapter)?;
+    for _ in 0..100 {
+        if collector.lock().unwrap().is_disconnected() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert!(collector.lock().unwrap().is_disconnected());
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    //目录名称中的路径分隔符被替换
+    assert_eq!(std::path::Path::new(&sample_data_dir).parent(), Some(std::path::Path::new(samples_root)));
+    collector.lock().unwrap().close();
+    //释放录制的会话，保存索引文件
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let sample_info = collector.get_sample_info();
+    assert_eq!(sample_info.sample_interval, 10);
+    let mut threads = collector.get_threads()?;
+    threads.sort_by_key(|x| x.id);
+    assert_eq!(threads.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), vec!["MainThread", "worker-1"]);
+    let methods = collector.list_methods_by_filter("worker.py")?;
+    assert_eq!(methods.len(), 2);
+    assert_eq!(collector.list_methods_by_filter("handle")?.len(), 2);
+    collector.close();
+
+    println!("runtime adapter test passed: {}", sample_data_dir);
+    Ok(())
+}
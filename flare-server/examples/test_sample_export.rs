@@ -0,0 +1,44 @@
+extern crate flare_server;
+extern crate serde_json;
+
+use flare_server::sample::SampleCollector;
+use flare_server::sample_export::*;
+use flare_server::sample_generator::*;
+use std::io;
+
+//混淆导出: 类名/方法名及线程名都被替换，原始名称只写入映射文件
+fn main() -> io::Result<()> {
+    let mut options = GeneratorOptions::default();
+    options.threads = 2;
+    options.duration_ms = 2000;
+    let test_dir = "target/test-samples/sample-export";
+    let _ = std::fs::remove_dir_all(test_dir);
+    let sample_data_dir = format!("{}/origin", test_dir);
+    generate_sample(&sample_data_dir, &options)?;
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mapping_file = format!("{}/mapping.json", test_dir);
+    let export_options = ExportOptions {
+        anonymize: "map".to_string(),
+        keep_packages: DEFAULT_KEEP_PACKAGES.iter().map(|x| x.to_string()).collect(),
+        mapping_file: mapping_file.clone(),
+    };
+    let export_dir = export_sample(&mut collector.lock().unwrap(), &format!("{}/export", test_dir), &export_options)?;
+    collector.lock().unwrap().close();
+
+    let exported = SampleCollector::open(&export_dir)?;
+    let mut exported = exported.lock().unwrap();
+    let mut thread_names: Vec<String> = exported.get_threads()?.into_iter().map(|x| x.name).collect();
+    thread_names.sort();
+    assert_eq!(thread_names, vec!["t1", "t2"]);
+    assert!(exported.list_methods_by_filter("synthetic")?.is_empty());
+    exported.close();
+
+    let mapping: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&mapping_file)?)?;
+    let mut original_threads: Vec<&str> = mapping["threads"].as_object().unwrap().values().map(|x| x.as_str().unwrap()).collect();
+    original_threads.sort();
+    assert_eq!(original_threads, vec!["synthetic-worker-0", "synthetic-worker-1"]);
+    assert!(!mapping["methods"].as_object().unwrap().is_empty());
+    println!("sample export test passed: {}", export_dir);
+    Ok(())
+}
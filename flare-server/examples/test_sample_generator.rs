@@ -0,0 +1,24 @@
+extern crate flare_server;
+
+use flare_server::sample_generator::*;
+use std::io;
+
+//方法池小于3时调用栈只使用已生成名称的方法
+fn main() -> io::Result<()> {
+    for methods in 0..6 {
+        let mut options = GeneratorOptions::default();
+        options.threads = 2;
+        options.duration_ms = 1000;
+        options.methods = methods;
+        let names = generate_method_names(methods);
+        assert!(names.len() >= 3);
+        assert!(names.len() >= methods);
+        let samples = generate_thread_samples(&options, |thread_data| {
+            assert!(thread_data.stacktrace.iter().all(|x| *x >= 1 && *x <= names.len() as i64), "methods: {}, stack: {:?}", methods, thread_data.stacktrace);
+            Ok(())
+        })?;
+        assert_eq!(samples, 2 * 1000 / options.sample_interval as usize);
+    }
+    println!("sample generator test is done.");
+    Ok(())
+}
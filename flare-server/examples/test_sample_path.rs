@@ -0,0 +1,81 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::sample::SampleCollector;
+use flare_server::sample_path::*;
+use std::io;
+use std::path::Path;
+
+//非ASCII路径下的取样目录可以列出及打开，非UTF-8的路径无损转义
+fn main() -> io::Result<()> {
+    let samples_root = "target/testkit-samples/样本 数据/录制";
+    let _ = std::fs::remove_dir_all(samples_root);
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 50);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_thread(1, "主线程", vec![vec![1]], 1_000_000);
+    let collector = record_script(script, samples_root, 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let entry = std::fs::read_dir(samples_root)?.next().unwrap()?;
+    let listed = path_to_string(&entry.path());
+    assert!(listed.contains("样本 数据"));
+    assert_eq!(file_name_string(&entry.path()), file_name_string(Path::new(&sample_data_dir)));
+    let canonical = canonicalize_path(&listed);
+    assert!(Path::new(&canonical).is_absolute());
+    assert_eq!(canonicalize_path(&sample_data_dir), canonical);
+    let collector = SampleCollector::open(&canonical)?;
+    assert_eq!(collector.lock().unwrap().get_threads()?.len(), 1);
+    collector.lock().unwrap().close();
+
+    //Windows canonicalize 返回的前缀
+    assert_eq!(path_to_string(Path::new(r"\\?\C:\样本\录制")), r"C:\样本\录制");
+    assert_eq!(path_to_string(Path::new(r"\\?\UNC\server\share\录制")), r"\\server\share\录制");
+
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let mut bytes = format!("{}/", samples_root).into_bytes();
+        bytes.extend_from_slice(b"bad\xFF\\name");
+        let path = Path::new(OsStr::from_bytes(&bytes));
+        std::fs::create_dir_all(path)?;
+        let escaped = path_to_string(path);
+        assert!(escaped.ends_with("bad\\xFF\\\\name"));
+        assert_eq!(string_to_path(&escaped), path);
+        //非UTF-8目录中的取样可以按转义的路径打开
+        for entry in std::fs::read_dir(&sample_data_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                std::fs::copy(entry.path(), path.join(entry.file_name()))?;
+            }
+        }
+        let collector = SampleCollector::open(string_to_path(&escaped))?;
+        assert_eq!(collector.lock().unwrap().get_sample_info().sample_data_dir, escaped);
+        assert_eq!(collector.lock().unwrap().get_threads()?.len(), 1);
+        collector.lock().unwrap().close();
+        assert_eq!(string_to_path(&listed), Path::new(&listed));
+    }
+    //同名的目录加上序号，如同一秒内的多次合并
+    let merged_root = Path::new(samples_root).join("merged");
+    let first = create_unique_dir(&merged_root, "merged-20191002T120000")?;
+    let second = create_unique_dir(&merged_root, "merged-20191002T120000")?;
+    let third = create_unique_dir(&merged_root, "merged-20191002T120000")?;
+    assert_eq!(file_name_string(&first).unwrap(), "merged-20191002T120000");
+    assert_eq!(file_name_string(&second).unwrap(), "merged-20191002T120000-2");
+    assert_eq!(file_name_string(&third).unwrap(), "merged-20191002T120000-3");
+    assert!(third.is_dir());
+    //导出目录只能是根目录下的相对路径
+    assert_eq!(join_relative_path("flare-samples", "export-1/part")?, "flare-samples/export-1/part");
+    for bad in &["", "../export", "export/../../x", "/tmp/export"] {
+        assert_eq!(join_relative_path("flare-samples", bad).unwrap_err().kind(), io::ErrorKind::InvalidInput, "{}", bad);
+    }
+    //客户端指定的文件必须在取样根目录下
+    let roots = vec![samples_root.to_string()];
+    assert!(resolve_path_under_roots(&sample_data_dir, &roots)?.starts_with(std::fs::canonicalize(samples_root)?));
+    assert_eq!(resolve_path_under_roots("Cargo.toml", &roots).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(resolve_path_under_roots(&format!("{}/../../../../Cargo.toml", samples_root), &roots).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    println!("sample path test passed");
+    Ok(())
+}
@@ -0,0 +1,59 @@
+extern crate flare_server;
+#[macro_use]
+extern crate serde_json;
+
+use flare_server::testkit::*;
+use flare_server::saved_view::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+fn new_view(name: &str, start_time: i64, thread_ids: Vec<i64>) -> SavedView {
+    let mut filters = serde_json::Map::new();
+    filters.insert("name_filter".to_string(), json!("worker-*"));
+    SavedView {
+        name: name.to_string(),
+        description: String::new(),
+        created_time: start_time,
+        updated_time: start_time,
+        start_time,
+        end_time: start_time + 1000,
+        thread_ids,
+        filters,
+    }
+}
+
+//保存视图后重新打开取样目录，视图仍然存在，同名视图被覆盖
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 50);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 0);
+    let collector = record_script(script.clone(), "target/testkit-samples/saved_view", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    let start_time = script.start_time;
+    {
+        let mut collector = collector.lock().unwrap();
+        collector.save_view(new_view("slow requests", start_time, vec![10]))?;
+        collector.save_view(new_view("startup", start_time, vec![]))?;
+        let mut view = new_view("slow requests", start_time + 500, vec![10, 11]);
+        view.created_time = start_time + 500;
+        view.updated_time = start_time + 500;
+        let view = collector.save_view(view)?;
+        assert_eq!(view.created_time, start_time);
+        assert!(collector.save_view(new_view(" ", start_time, vec![])).is_err());
+        collector.close();
+    }
+    drop(collector);
+    assert_eq!(load_views(&sample_data_dir)?.len(), 2);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let views = collector.get_views();
+    assert_eq!(views.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), vec!["slow requests", "startup"]);
+    assert_eq!(views[0].start_time, start_time + 500);
+    assert_eq!(views[0].updated_time, start_time + 500);
+    assert_eq!(views[0].thread_ids, vec![10, 11]);
+    assert_eq!(views[0].filters["name_filter"], "worker-*");
+    collector.close();
+    println!("saved view test passed");
+    Ok(())
+}
@@ -0,0 +1,259 @@
+#[macro_use]
+extern crate serde_json;
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::schema::*;
+use flare_server::testkit::*;
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use serde_json::Value;
+use std::io;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+use websocket::OwnedMessage;
+use websocket::receiver::Receiver;
+use websocket::sender::{Sender, Writer};
+use websocket::ws::Receiver as ReceiverTrait;
+
+//按schema检查请求的必须参数及参数类型，返回错误信息
+fn check_request(schema: &Value, request: &Value) -> Vec<String> {
+    let mut errors = vec![];
+    let options_schema = &schema["request"]["properties"]["options"];
+    let options = request["options"].as_object().cloned().unwrap_or_default();
+    for name in options_schema["required"].as_array().unwrap() {
+        if !options.contains_key(name.as_str().unwrap()) {
+            errors.push(format!("missing option: {}", name));
+        }
+    }
+    for (name, value) in &options {
+        let expected = match options_schema["properties"][name]["type"].as_str() {
+            Some(x) => x,
+            None => continue
+        };
+        let matched = match expected {
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => true
+        };
+        if !matched {
+            errors.push(format!("option '{}' is not {}", name, expected));
+        }
+    }
+    errors
+}
+
+fn is_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true
+    }
+}
+
+//按schema检查响应数据的必须字段及字段类型，数组检查第一个元素的类型
+fn check_value(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let kinds: Vec<&str> = match &schema["type"] {
+        Value::String(x) => vec![x.as_str()],
+        Value::Array(x) => x.iter().filter_map(|x| x.as_str()).collect(),
+        _ => return
+    };
+    if !kinds.iter().any(|kind| is_type(value, kind)) {
+        errors.push(format!("'{}' is not {:?}: {}", path, kinds, value));
+        return;
+    }
+    if let Some(required) = schema["required"].as_array() {
+        for name in required {
+            if value.get(name.as_str().unwrap()).is_none() {
+                errors.push(format!("missing field: {}.{}", path, name.as_str().unwrap()));
+            }
+        }
+    }
+    if let (Some(properties), Some(object)) = (schema["properties"].as_object(), value.as_object()) {
+        for (name, property) in properties {
+            if let Some(field) = object.get(name) {
+                check_value(property, field, &format!("{}.{}", path, name), errors);
+            }
+        }
+    }
+    if let (Some(first), false) = (value.as_array().and_then(|x| x.first()), schema["items"].is_null()) {
+        check_value(&schema["items"], first, &format!("{}[0]", path), errors);
+    }
+}
+
+//执行命令，按 COMMAND_RESULTS 检查实际的响应数据
+fn check_responses() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Worker.process()V")
+        .add_thread(10, "worker-1", vec![vec![1, 2]], 1_000_000)
+        .add_thread(11, "worker-2", vec![vec![1]], 500_000);
+    script.add_event(ScriptedEvent::Marker { sample_index: 20, label: "deploy".to_string(), color: "red".to_string() })
+        .add_event(ScriptedEvent::IntervalBegin { sample_index: 30, name: "batch".to_string(), thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 90, thread_id: 10 })
+        .add_event(ScriptedEvent::Gc { sample_index: 50, duration: 15 })
+        .add_event(ScriptedEvent::Diagnostic { sample_index: 60, level: "warn".to_string(), kind: "sampling_overrun".to_string(), message: "sampling took 35ms".to_string(), count: 1 });
+    let (start_time, end_time) = (script.start_time, script.start_time + 200 * 20);
+    let collector = record_script(script, "target/testkit-samples/schema", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(client_stream);
+        let mut receiver = Receiver::new(false);
+        while let Ok(message) = receiver.recv_message(&mut reader) {
+            if let OwnedMessage::Text(text) = message {
+                if tx.send(serde_json::from_str::<Value>(&text).unwrap()).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let profiler = Profiler::new();
+    let session_id = profiler.lock().unwrap().open_sample(&sample_data_dir)?;
+    let requests = vec![
+        ("list_sessions", json!({})),
+        ("history_samples", json!({})),
+        ("list_runtimes", json!({})),
+        ("dashboard", json!({"session_id": session_id})),
+        ("overlay_dashboard", json!({"session_ids": [session_id]})),
+        ("combined_view", json!({"session_id": session_id})),
+        ("list_threads", json!({"session_id": session_id})),
+        ("thread_handles", json!({"session_id": session_id})),
+        ("cpu_time", json!({"session_id": session_id, "thread_ids": [10, 11]})),
+        ("call_tree", json!({"session_id": session_id, "thread_ids": [10]})),
+        ("call_tree", json!({"session_id": session_id, "thread_ids": [10], "levels": 1})),
+        ("sequenced_call_tree", json!({"session_id": session_id, "thread_id": 10, "start_time": start_time, "end_time": end_time})),
+        ("flame_graph", json!({"session_id": session_id, "thread_id": 10, "start_time": start_time, "end_time": end_time})),
+        ("list_methods_by_filter", json!({"session_id": session_id, "method_name_filter": "Worker"})),
+        ("search_slow_method_calls", json!({"session_id": session_id, "method_ids": [2]})),
+        ("database_time", json!({"session_id": session_id})),
+        ("query", json!({"session_id": session_id, "query": "top 3 methods"})),
+        ("add_marker", json!({"session_id": session_id, "label": "check"})),
+        ("save_view", json!({"session_id": session_id, "name": "all"})),
+        ("list_markers", json!({"session_id": session_id})),
+        ("list_intervals", json!({"session_id": session_id})),
+        ("list_views", json!({"session_id": session_id})),
+        ("list_series", json!({"session_id": session_id})),
+        ("session_events", json!({"session_id": session_id})),
+        ("offcpu_stacks", json!({"session_id": session_id})),
+        ("cgroup_metrics", json!({"session_id": session_id})),
+        ("host_metrics", json!({"session_id": session_id})),
+        ("list_deadlocks", json!({"session_id": session_id})),
+        ("list_thread_dumps", json!({"session_id": session_id})),
+        ("list_heap_histograms", json!({"session_id": session_id})),
+        ("allocation_rate", json!({"session_id": session_id})),
+        ("deopt_stats", json!({"session_id": session_id})),
+        ("class_loader_leaks", json!({"session_id": session_id})),
+        ("warmup_phase", json!({"session_id": session_id})),
+        ("spin_loops", json!({"session_id": session_id})),
+        ("insights", json!({"session_id": session_id})),
+        ("storage_usage", json!({})),
+        ("list_plugins", json!({})),
+        ("list_record_groups", json!({})),
+        ("list_baselines", json!({})),
+        ("self_profile", json!({})),
+        ("schema", json!({"command": "flame_graph"})),
+    ];
+    let mut errors = vec![];
+    for (cmd, options) in requests {
+        let mut out_cmd = String::new();
+        let request = json!({"cmd": cmd, "options": options}).to_string();
+        profiler.lock().unwrap().handle_request(&mut writer, request, &mut out_cmd)?;
+        let result_schema = get_result_schema(cmd).unwrap();
+        //跳过事件推送及其它命令的响应，搜索慢方法等待最后一个响应
+        loop {
+            let response = rx.recv_timeout(Duration::from_secs(30)).unwrap_or_else(|_| panic!("wait for response timeout: {}", cmd));
+            if response["cmd"] != json!(cmd) {
+                continue;
+            }
+            assert_eq!(response["result"], "success", "{}: {}", cmd, response["data"]);
+            check_value(&result_schema, &response["data"], cmd, &mut errors);
+            //没有采集的指标不返回空值字段
+            if cmd == "overlay_dashboard" {
+                let session = response["data"]["sessions"][0].as_object().unwrap();
+                assert!(session.values().all(|x| !x.is_null()), "{:?}", session.keys().collect::<Vec<_>>());
+            }
+            if cmd != "search_slow_method_calls" || response["data"]["search_finished"] == json!(true) || response["data"]["search_error"] == json!(true) {
+                break;
+            }
+        }
+    }
+    assert!(errors.is_empty(), "responses not matched schema: {:#?}", errors);
+    profiler.lock().unwrap().close_all_session()?;
+    Ok(())
+}
+
+//每个命令都有schema，并且能够作为契约检查请求及响应
+fn main() -> io::Result<()> {
+    assert!(get_undocumented_commands().is_empty(), "commands without schema: {:?}", get_undocumented_commands());
+
+    let schema = get_protocol_schema();
+    assert_eq!(schema["$schema"], JSON_SCHEMA_DRAFT);
+    let commands = schema["commands"].as_object().unwrap();
+    assert!(commands.contains_key("hello") && commands.contains_key("schema"));
+    for (cmd, command_schema) in commands {
+        assert_eq!(command_schema["request"]["properties"]["cmd"]["const"], json!(cmd));
+        assert!(command_schema["request"]["properties"]["options"]["properties"]["lang"].is_object(), "{}", cmd);
+    }
+
+    //由 flare-proto 的类型推导的消息定义
+    let definitions = &schema["definitions"];
+    assert_eq!(definitions["request"]["required"], json!(["cmd"]));
+    assert_eq!(definitions["response"]["properties"]["result"]["enum"], json!(["success", "failure"]));
+    assert_eq!(definitions["error_response"]["properties"]["data"]["properties"]["message"]["type"], "string");
+    assert_eq!(definitions["capabilities"]["properties"]["protocol_version"]["type"], "integer");
+    assert_eq!(definitions["capabilities"]["properties"]["commands"]["items"]["type"], "string");
+
+    let flame_graph = get_command_schema("flame_graph").unwrap();
+    let options = &flame_graph["request"]["properties"]["options"];
+    assert_eq!(options["required"], json!(["session_id"]));
+    assert_eq!(options["properties"]["interval"]["type"], "string");
+    assert_eq!(options["properties"]["max_depth"]["type"], "integer");
+    let cpu_time = get_command_schema("cpu_time").unwrap();
+    assert_eq!(cpu_time["request"]["properties"]["options"]["properties"]["thread_ids"], json!({"type": "array", "items": {"type": "integer"}}));
+    let hello = get_command_schema("hello").unwrap();
+    assert_eq!(hello["response"]["allOf"][1]["properties"]["data"]["$ref"], "#/definitions/capabilities");
+    //每个命令都描述了响应数据
+    for (cmd, command_schema) in commands {
+        assert_eq!(command_schema["response"]["allOf"][0]["$ref"], "#/definitions/response", "{}", cmd);
+        assert!(command_schema["response"]["allOf"][1]["properties"]["data"].is_object(), "{}", cmd);
+    }
+    let dashboard = get_result_schema("dashboard").unwrap();
+    assert_eq!(dashboard["properties"]["threads"], json!({"type": "array", "items": {"type": "object"}}));
+    assert_eq!(dashboard["properties"]["cgroup"]["type"], json!(["object", "null"]));
+    let list_threads = get_result_schema("list_threads").unwrap();
+    assert!(list_threads["required"].as_array().unwrap().contains(&json!("total")));
+    assert!(get_command_schema("unknown_cmd").is_err());
+
+    let request = json!({"cmd": "flame_graph", "options": {"session_id": "s1", "thread_id": 1, "start_time": 0}});
+    assert!(check_request(&flame_graph, &request).is_empty());
+    let request = json!({"cmd": "flame_graph", "options": {"thread_id": "1"}});
+    assert_eq!(check_request(&flame_graph, &request), vec!["missing option: \"session_id\"", "option 'thread_id' is not integer"]);
+
+    assert_eq!(infer_schema(&json!({"a": [1.5], "b": null})),
+               json!({"type": "object", "properties": {"a": {"type": "array", "items": {"type": "number"}}, "b": {}}, "required": ["a", "b"]}));
+
+    check_responses()?;
+    println!("schema test passed");
+    Ok(())
+}
@@ -0,0 +1,75 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::sample::*;
+use flare_server::self_profile::*;
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use websocket::sender::{Sender, Writer};
+
+//区段计时: 记录各线程的区段栈，写入可以打开的取样目录
+fn main() -> io::Result<()> {
+    {
+        let _span = enter_span("not-profiled");
+        assert!(get_span_stack().is_empty());
+    }
+
+    let sample_data_dir = "target/testkit-samples/self_profile/flare-server-self";
+    let _ = std::fs::remove_dir_all(sample_data_dir);
+    assert!(start_self_profiling(sample_data_dir, 0, 0).is_err());
+    let status = start_self_profiling(sample_data_dir, 5, 0)?;
+    assert!(status.running);
+    assert!(start_self_profiling(sample_data_dir, 5, 0).is_err());
+    {
+        let _span = enter_span("outer");
+        {
+            let _span = enter_spans(&["inner".to_string(), "leaf".to_string()]);
+            assert_eq!(get_span_stack(), vec!["outer", "inner", "leaf"]);
+        }
+        assert_eq!(get_span_stack(), vec!["outer"]);
+    }
+    let query_thread = thread::Builder::new().name("flare-query-test".to_string()).spawn(|| {
+        let _span = enter_span("call_tree");
+        let _span = enter_span("SampleCollector.get_call_tree");
+        thread::sleep(Duration::from_millis(300));
+    })?;
+    query_thread.join().unwrap();
+    let status = stop_self_profiling()?;
+    assert!(!status.running);
+    assert!(status.samples > 10, "samples: {}", status.samples);
+    assert!(stop_self_profiling().is_err());
+    assert_eq!(get_self_profiling_status().unwrap().sample_data_dir, sample_data_dir);
+
+    let collector = SampleCollector::open(sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let threads = collector.get_threads()?;
+    let thread = threads.iter().find(|x| x.name == "flare-query-test").expect("query thread is not sampled");
+    let sample_info = collector.get_sample_info();
+    let stacks = collector.get_collapsed_call_stacks(thread.id, sample_info.record_start_time, sample_info.last_record_time, StatsType::SAMPLES)?;
+    println!("collapsed stacks: {:?}", stacks);
+    assert!(stacks.iter().any(|x| x.starts_with("call_tree;SampleCollector.get_call_tree ")));
+    collector.close();
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let profiler = Profiler::new();
+    let mut request = |json: &str| {
+        let mut out_cmd = String::new();
+        profiler.lock().unwrap().handle_request(&mut writer, json.to_string(), &mut out_cmd)
+    };
+    request(r#"{"cmd": "self_profile", "options": {"action": "start", "interval_ms": 5, "duration_secs": 1}}"#)?;
+    request(r#"{"cmd": "self_profile", "options": {}}"#)?;
+    assert!(request(r#"{"cmd": "self_profile", "options": {"action": "pause"}}"#).is_err());
+    //到期后自动停止
+    thread::sleep(Duration::from_millis(1500));
+    assert!(!get_self_profiling_status().unwrap().running);
+    assert!(request(r#"{"cmd": "self_profile", "options": {"action": "stop"}}"#).is_err());
+    println!("self profile test passed");
+    Ok(())
+}
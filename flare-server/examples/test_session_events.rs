@@ -0,0 +1,68 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::testkit::*;
+use flare_server::session_events::*;
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use websocket::sender::{Sender, Writer};
+
+fn new_diagnostic(sample_index: usize, level: &str, kind: &str, message: &str, count: i64) -> ScriptedEvent {
+    ScriptedEvent::Diagnostic { sample_index, level: level.to_string(), kind: kind.to_string(), message: message.to_string(), count }
+}
+
+//agent推送的诊断信息保存到会话，可以按级别、类型过滤及汇总
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V");
+    script.add_thread(10, "worker-1", vec![vec![1]], 1_000_000);
+    script.add_event(new_diagnostic(50, "warn", "sampling_overrun", "sampling took 35ms, exceeds interval 20ms", 3));
+    script.add_event(new_diagnostic(100, "warn", "sampling_overrun", "sampling took 42ms, exceeds interval 20ms", 5));
+    script.add_event(new_diagnostic(120, "error", "jvmti_error", "get all stack traces failed, error: JVMTI_ERROR_OUT_OF_MEMORY", 1));
+    script.add_event(new_diagnostic(150, "warn", "dropped_events", "sending queue is full, the oldest events are dropped", 120));
+
+    let collector = record_script(script.clone(), "target/testkit-samples/session_events", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    assert_eq!(collector.lock().unwrap().get_session_events().len(), 4);
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let events = load_session_events(&sample_data_dir)?;
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0].time, script.start_time + 50 * 20);
+    assert_eq!((events[0].kind.as_str(), events[0].count), ("sampling_overrun", 3));
+    //最低级别过滤
+    let query = SessionEventQuery { start_time: -1, end_time: -1, level: LEVEL_ERROR.to_string(), ..Default::default() };
+    assert_eq!(events.iter().filter(|x| query.matches(x)).count(), 1);
+    let query = SessionEventQuery { start_time: -1, end_time: -1, level: LEVEL_INFO.to_string(), kind: "sampling_overrun".to_string() };
+    assert_eq!(events.iter().filter(|x| query.matches(x)).count(), 2);
+    let query = SessionEventQuery { start_time: script.start_time + 2100, end_time: -1, ..Default::default() };
+    assert_eq!(events.iter().filter(|x| query.matches(x)).count(), 2);
+    assert!(check_level("warn").is_ok() && check_level("").is_ok() && check_level("fatal").is_err());
+
+    //级别高的在前，同类型合并次数
+    let summary = summarize_session_events(&events);
+    assert_eq!(summary.len(), 3);
+    assert_eq!(summary[0].kind, "jvmti_error");
+    let overrun = summary.iter().find(|x| x.kind == "sampling_overrun").unwrap();
+    assert_eq!((overrun.events, overrun.count), (2, 8));
+    assert_eq!(overrun.last_message, "sampling took 42ms, exceeds interval 20ms");
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let profiler = Profiler::new();
+    let session_id = profiler.lock().unwrap().open_sample(&sample_data_dir)?;
+    let mut request = |json: String| {
+        let mut out_cmd = String::new();
+        profiler.lock().unwrap().handle_request(&mut writer, json, &mut out_cmd)
+    };
+    request(format!(r#"{{"cmd": "session_events", "options": {{"session_id": "{}", "level": "warn", "page_size": 2}}}}"#, session_id))?;
+    assert!(request(format!(r#"{{"cmd": "session_events", "options": {{"session_id": "{}", "level": "fatal"}}}}"#, session_id)).is_err());
+    assert!(request(r#"{"cmd": "session_events", "options": {}}"#.to_string()).is_err());
+    println!("session events test passed");
+    Ok(())
+}
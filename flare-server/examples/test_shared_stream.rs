@@ -0,0 +1,55 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use websocket::OwnedMessage;
+use websocket::sender::{Sender, Writer};
+
+const THREADS: usize = 4;
+const MESSAGES: usize = 20;
+const MESSAGE_SIZE: usize = 256 * 1024;
+
+//多个线程通过复制的Writer发送大消息，客户端收到的帧不交错
+fn main() -> io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let mut client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+
+    let mut handles = vec![];
+    for i in 0..THREADS {
+        let mut writer = Writer { stream: writer.stream.try_clone()?, sender: Sender::new(false) };
+        handles.push(thread::spawn(move || {
+            let text: String = std::iter::repeat((b'a' + i as u8) as char).take(MESSAGE_SIZE).collect();
+            for _ in 0..MESSAGES {
+                writer.send_message(&OwnedMessage::Text(text.clone())).unwrap();
+            }
+        }));
+    }
+
+    //服务端发送的帧没有掩码: 0x81, 127, 8字节长度, 内容
+    let mut counts = [0usize; THREADS];
+    for _ in 0..THREADS * MESSAGES {
+        let mut header = [0u8; 10];
+        client_stream.read_exact(&mut header)?;
+        assert_eq!((header[0], header[1]), (0x81, 127));
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(&header[2..]);
+        let len = u64::from_be_bytes(len_bytes) as usize;
+        assert_eq!(len, MESSAGE_SIZE);
+        let mut payload = vec![0u8; len];
+        client_stream.read_exact(&mut payload)?;
+        assert!(payload.iter().all(|x| *x == payload[0]), "frames are interleaved");
+        counts[(payload[0] - b'a') as usize] += 1;
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(counts, [MESSAGES; THREADS]);
+    println!("shared stream test passed");
+    Ok(())
+}
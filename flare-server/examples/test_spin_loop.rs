@@ -0,0 +1,55 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::spin_loop::*;
+use flare_server::insights::generate_insights;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+//spinner 在自旋锁上空转，compute 的调用栈不断变化，poller 的CPU占用很低
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "java.lang.Thread.onSpinWait()V")
+        .add_method(3, "com.example.SpinLock.lock()V")
+        .add_method(4, "com.example.Worker.process()V");
+    for i in 0..20 {
+        script.add_method(100 + i, &format!("com.example.Compute.step{}()V", i));
+    }
+    let compute_stacks = (0..20).map(|i| vec![100 + i, 4, 1]).collect();
+    script.add_thread(10, "spinner", vec![vec![2, 3, 4, 1], vec![3, 4, 1]], 20_000_000)
+        .add_thread(11, "compute", compute_stacks, 20_000_000)
+        .add_thread(12, "poller", vec![vec![3, 4, 1]], 1_000_000);
+
+    let collector = record_script(script.clone(), "target/testkit-samples/spin_loop", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let end_time = script.get_end_time() + 20;
+
+    let threads = detect_spin_loops(&mut collector, script.start_time, end_time, &SpinLoopOptions::default())?;
+    println!("spin loops: {:?}", threads);
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0].thread_name, "spinner");
+    assert_eq!((threads[0].samples, threads[0].distinct_frames, threads[0].coverage), (200, 2, 1.0));
+    assert_eq!(threads[0].frames[0], "java.lang.Thread.onSpinWait()V");
+    assert!(threads[0].cpu_ratio >= 0.99);
+
+    //低CPU阈值时 poller 也会被识别，compute 的调用栈太分散
+    let options = SpinLoopOptions { min_cpu_ratio: 0.01, ..Default::default() };
+    let threads = detect_spin_loops(&mut collector, script.start_time, end_time, &options)?;
+    assert_eq!(threads.iter().map(|x| x.thread_name.as_str()).collect::<Vec<_>>(), vec!["spinner", "poller"]);
+
+    let insights = generate_insights(&mut collector, script.start_time, end_time, "en")?;
+    println!("insights: {:?}", insights.iter().map(|x| &x.title).collect::<Vec<_>>());
+    assert_eq!(insights.len(), 1);
+    assert_eq!((insights[0].kind.as_str(), insights[0].severity.as_str()), ("spin_loop", "critical"));
+    assert_eq!(insights[0].title, "thread 'spinner' is busy spinning in java.lang.Thread.onSpinWait()V");
+    let insights = generate_insights(&mut collector, script.start_time, end_time, "zh-CN")?;
+    assert_eq!(insights[0].title, "线程 'spinner' 在 java.lang.Thread.onSpinWait()V 中忙等待");
+    collector.close();
+    println!("spin loop test passed");
+    Ok(())
+}
@@ -0,0 +1,206 @@
+extern crate flare_server;
+extern crate flare_utils;
+extern crate serde_json;
+
+use flare_server::testkit::*;
+use flare_server::sample::*;
+use flare_server::stack_record::*;
+use flare_utils::ValueType;
+use flare_utils::tuple_indexed::{TupleIndexedFile, TupleValue};
+use std::io;
+
+//调用栈按列式数据块保存，读取时引用映射的文件数据；逐条保存的二进制记录及旧版本的JSON记录仍然可以读取，查询结果相同
+fn main() -> io::Result<()> {
+    test_encode_and_parse();
+    test_chunk_encode_and_parse();
+
+    let mut script = AgentScript::new(1_570_000_000_000, 100, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Worker.process()V")
+        .add_method(3, "com.example.Worker.sleep()V")
+        .add_thread(100, "worker-1", vec![vec![2, 1], vec![3, 1]], 1_000_000)
+        .add_thread(101, "工作线程-2", vec![vec![2, 1]], 2_000_000);
+    let (start_time, end_time) = (script.start_time, script.get_end_time());
+    let collector = record_script(script, "target/testkit-samples/stack_record", 10_000)?;
+    let sample_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    //新录制的调用栈是列式数据块
+    let stack_path = format!("{}/thread_101_stack", sample_dir);
+    let mut entries = TupleIndexedFile::new_reader(&stack_path)?.get_all_entries()?;
+    entries.sort_by_key(|x| x.0);
+    assert!(!entries.is_empty());
+    assert!(entries.iter().all(|(_, bytes)| bytes[0] == STACK_CHUNK_MAGIC));
+    let chunk = StackChunk::parse(&entries[0].1)?;
+    assert!(chunk.len() > 1);
+    let record = chunk.record(0)?;
+    assert_eq!(record.name, "工作线程-2");
+    assert_eq!(record.frames().collect::<Vec<_>>(), vec![2, 1]);
+
+    //转换为逐条保存的二进制记录及旧版本的JSON记录
+    let (row_dir, chunk_bytes, row_bytes) = convert_stack_files(&sample_dir, "rows", |x| encode_stack_record(x))?;
+    let (legacy_dir, _, json_bytes) = convert_stack_files(&sample_dir, "legacy", |x| serde_json::to_vec(x).unwrap())?;
+    println!("stack records, chunks: {} bytes, binary: {} bytes, json: {} bytes", chunk_bytes, row_bytes, json_bytes);
+    assert!(chunk_bytes < row_bytes);
+    assert!(row_bytes < json_bytes);
+
+    let current = SampleCollector::open(&sample_dir)?;
+    let mut current = current.lock().unwrap();
+    let current_tree = current.get_call_tree(&[100, 101], start_time, end_time)?.format_call_tree(true);
+    println!("call tree:\n{}", current_tree);
+    assert!(current_tree.contains("com.example.Worker.sleep()V"));
+    let current_samples = current.load_thread_samples(101, start_time, end_time)?;
+    assert_eq!(current_samples.len(), 200);
+
+    for dir in &[row_dir, legacy_dir] {
+        let other = SampleCollector::open(dir)?;
+        let mut other = other.lock().unwrap();
+        assert_eq!(current_tree, other.get_call_tree(&[100, 101], start_time, end_time)?.format_call_tree(true));
+        let other_samples = other.load_thread_samples(101, start_time, end_time)?;
+        assert_eq!(serde_json::to_string(&current_samples)?, serde_json::to_string(&other_samples)?);
+        other.close();
+    }
+
+    //数据块按步数过滤行，部分范围与逐条记录的结果相同
+    let middle_time = start_time + (end_time - start_time) / 2;
+    let partial_samples = current.load_thread_samples(101, middle_time, end_time)?;
+    assert!(partial_samples.len() < current_samples.len());
+    assert!(partial_samples.iter().all(|x| x.sample_time >= middle_time - 100));
+    current.close();
+    println!("stack record test passed");
+    Ok(())
+}
+
+//将数据块展开为逐条的记录保存到新的取样目录，返回(目录, 数据块字节数, 记录字节数)
+fn convert_stack_files<F>(sample_dir: &str, suffix: &str, encode: F) -> io::Result<(String, usize, usize)>
+    where F: Fn(&ThreadData) -> Vec<u8> {
+    let dest_dir = format!("{}-{}", sample_dir, suffix);
+    let _ = std::fs::remove_dir_all(&dest_dir);
+    copy_dir(sample_dir, &dest_dir)?;
+    let mut chunk_bytes = 0;
+    let mut record_bytes = 0;
+    for thread_id in &[100, 101] {
+        let path = format!("{}/thread_{}_stack", dest_dir, thread_id);
+        let mut entries = TupleIndexedFile::new_reader(&path)?.get_all_entries()?;
+        entries.sort_by_key(|x| x.0);
+        std::fs::remove_file(format!("{}.fidx", path))?;
+        std::fs::remove_file(format!("{}.fdata", path))?;
+        let mut writer = TupleIndexedFile::new_writer(&path, ValueType::UINT32)?;
+        for (_, bytes) in &entries {
+            chunk_bytes += bytes.len();
+            let chunk = StackChunk::parse(bytes)?;
+            for row in 0..chunk.len() {
+                let data = encode(&chunk.record(row)?.to_thread_data());
+                record_bytes += data.len();
+                writer.add_value(TupleValue::uint32(chunk.step(row)), &data)?;
+            }
+        }
+    }
+    Ok((dest_dir, chunk_bytes, record_bytes))
+}
+
+fn test_encode_and_parse() {
+    let thread_data = ThreadData {
+        id: 7,
+        name: "名".repeat(30_000),
+        priority: 5,
+        daemon: true,
+        state: "RUNNABLE".to_string(),
+        cpu_time: 123_456_789,
+        cpu_time_delta: -1,
+        sample_time: 1_570_000_000_000,
+        sample_count: 3,
+        stacktrace: vec![0x7f00_0000_0001, i64::min_value(), 42],
+        duration: 0,
+        self_duration: 0,
+        self_cpu_time: 0,
+    };
+    let data = encode_stack_record(&thread_data);
+    let record = StackRecord::parse(&data).unwrap();
+    //超长的线程名称截断到字符边界
+    assert_eq!(record.name.len(), 65_535 / 3 * 3);
+    assert_eq!(record.frame_count(), 3);
+    assert_eq!(record.frames().rev().collect::<Vec<_>>(), vec![42, i64::min_value(), 0x7f00_0000_0001]);
+    let decoded = record.to_thread_data();
+    assert_eq!((decoded.id, decoded.priority, decoded.daemon, decoded.cpu_time, decoded.cpu_time_delta, decoded.sample_time, decoded.sample_count),
+               (7, 5, true, 123_456_789, -1, 1_570_000_000_000, 3));
+    assert_eq!(decoded.stacktrace, thread_data.stacktrace);
+    assert_eq!(decoded.state, "RUNNABLE");
+
+    //JSON记录
+    let json = serde_json::to_vec(&thread_data).unwrap();
+    assert_eq!(StackRecord::parse(&json).unwrap().frames().collect::<Vec<_>>(), thread_data.stacktrace);
+
+    //截断或者损坏的记录
+    assert!(StackRecord::parse(&data[..data.len() - 1]).is_err());
+    assert!(StackRecord::parse(&data[..20]).is_err());
+    assert!(StackRecord::parse(&[]).is_err());
+    let mut bad_version = data.clone();
+    bad_version[1] = 9;
+    assert!(StackRecord::parse(&bad_version).is_err());
+}
+
+fn test_chunk_encode_and_parse() {
+    let mut builder = StackChunkBuilder::new();
+    for i in 0..10 {
+        builder.add(i * 2, &ThreadData {
+            id: 7,
+            name: "worker".to_string(),
+            priority: 5,
+            daemon: i % 2 == 0,
+            state: if i < 5 { "RUNNABLE" } else { "WAITING" }.to_string(),
+            cpu_time: 1000 * i as i64,
+            cpu_time_delta: 1000,
+            sample_time: 1_570_000_000_000 + 20 * i as i64,
+            sample_count: i as i64,
+            stacktrace: (0..i as i64).collect(),
+            duration: 0,
+            self_duration: 0,
+            self_cpu_time: 0,
+        });
+    }
+    assert_eq!(builder.len(), 10);
+    let encoded_len = builder.get_encoded_len();
+    let (step, data) = builder.take();
+    assert!(builder.is_empty());
+    assert_eq!(step, 18);
+    assert_eq!(data.len(), encoded_len);
+
+    let chunk = StackChunk::parse(&data).unwrap();
+    assert_eq!(chunk.len(), 10);
+    assert_eq!(chunk.rows_in_steps(5, 12).collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+    let record = chunk.record(6).unwrap();
+    assert_eq!((record.id, record.daemon, record.cpu_time, record.sample_time, record.sample_count),
+               (7, true, 6000, 1_570_000_000_120, 6));
+    assert_eq!(record.state, "WAITING");
+    assert_eq!(record.frames().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(chunk.record(0).unwrap().frame_count(), 0);
+
+    //数据块与逐条的记录混合，数据块按步数过滤
+    let row = encode_stack_record(&chunk.record(9).unwrap().to_thread_data());
+    let records = parse_stack_records(vec![&data[..], &row[..]].into_iter(), 16, 100);
+    assert_eq!(records.iter().map(|x| x.sample_count).collect::<Vec<_>>(), vec![8, 9, 9]);
+
+    //截断或者损坏的数据块
+    assert!(StackChunk::parse(&data[..data.len() - 1]).is_err());
+    assert!(StackChunk::parse(&data[..10]).is_err());
+    let mut bad_version = data.clone();
+    bad_version[1] = 9;
+    assert!(StackChunk::parse(&bad_version).is_err());
+    assert!(parse_stack_records(vec![&bad_version[..]].into_iter(), 0, 100).is_empty());
+}
+
+fn copy_dir(src: &str, dest: &str) -> io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let dest_path = format!("{}/{}", dest, entry.file_name().to_string_lossy());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path().to_string_lossy(), &dest_path)?;
+        } else {
+            std::fs::copy(entry.path(), &dest_path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::sample::*;
+use flare_server::agg_index::{build_agg_index, has_agg_index, AGG_INDEX_DIR};
+use std::io;
+
+//同一个脚本分别以 raw 和 aggregated 模式录制，aggregated 模式不保存原始调用栈，调用树按分钟汇总
+fn main() -> io::Result<()> {
+    //3分钟，起始时间不在整分钟
+    let mut script = AgentScript::new(1_570_000_000_000, 500, 360);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Worker.process()V")
+        .add_method(3, "com.example.Worker.sleep()V")
+        .add_thread(100, "worker-1", vec![vec![2, 1], vec![3, 1]], 1_000_000)
+        .add_thread(101, "worker-2", vec![vec![2, 1]], 2_000_000);
+    let (start_time, end_time) = (script.start_time, script.get_end_time());
+
+    let raw = record_script(script.clone(), "target/testkit-samples/stack_retention", 10_000)?;
+    let aggregated = record_script_with(script.clone(), "target/testkit-samples/stack_retention", 10_000, |collector| {
+        collector.set_stack_retention(STACK_RETENTION_AGGREGATED)
+    })?;
+    let raw_dir = raw.lock().unwrap().get_sample_info().sample_data_dir;
+    let aggregated_dir = aggregated.lock().unwrap().get_sample_info().sample_data_dir;
+    assert!(raw.lock().unwrap().set_stack_retention("none").is_err());
+    raw.lock().unwrap().close();
+    aggregated.lock().unwrap().close();
+    drop(raw);
+    drop(aggregated);
+    assert!(std::fs::metadata(format!("{}/thread_100_stack.fidx", raw_dir)).is_ok());
+    assert!(std::fs::metadata(format!("{}/thread_100_stack.fidx", aggregated_dir)).is_err());
+
+    let raw = SampleCollector::open(&raw_dir)?;
+    let mut raw = raw.lock().unwrap();
+    let aggregated = SampleCollector::open(&aggregated_dir)?;
+    let mut aggregated = aggregated.lock().unwrap();
+    assert_eq!(raw.get_sample_info().stack_retention, STACK_RETENTION_RAW);
+    assert_eq!(aggregated.get_sample_info().stack_retention, STACK_RETENTION_AGGREGATED);
+    assert!(!aggregated.has_raw_stacks());
+
+    //整个录制范围的调用树相同
+    let raw_tree = raw.get_call_tree(&[100, 101], start_time, end_time)?;
+    let aggregated_tree = aggregated.get_call_tree(&[100, 101], start_time, end_time)?;
+    let sorted_lines = |text: String| {
+        let mut lines: Vec<String> = text.lines().map(|x| x.to_string()).collect();
+        lines.sort();
+        lines
+    };
+    println!("aggregated tree:\n{}", aggregated_tree.format_call_tree(true));
+    assert_eq!(sorted_lines(raw_tree.format_call_tree(true)), sorted_lines(aggregated_tree.format_call_tree(true)));
+
+    //部分时间范围按整分钟统计
+    let aggregated_tree = aggregated.get_call_tree(&[100], start_time, start_time + 1000)?;
+    assert!(aggregated_tree.format_call_tree(true).contains("1,java.lang.Thread.run()V,40,"));
+
+    //没有原始调用栈的查询返回空的结果
+    assert!(aggregated.load_thread_samples(100, start_time, end_time)?.is_empty());
+    assert!(!raw.load_thread_samples(100, start_time, end_time)?.is_empty());
+    assert!(build_agg_index(&aggregated_dir, &mut |_, _| {}).is_err());
+    //原始数据与分钟汇总混合查询，汇总之后的第一次取样不重复计算汇总的时间
+    assert!(has_agg_index(&raw_dir));
+    let (range_start, range_end) = (start_time + 5000, end_time - 5000);
+    let mixed_tree = raw.get_call_tree(&[100, 101], range_start, range_end)?;
+    raw.close();
+    aggregated.close();
+    drop(raw);
+    std::fs::rename(format!("{}/{}", raw_dir, AGG_INDEX_DIR), format!("{}/{}.bak", raw_dir, AGG_INDEX_DIR))?;
+    let raw_only = SampleCollector::open(&raw_dir)?;
+    let raw_only_tree = raw_only.lock().unwrap().get_call_tree(&[100, 101], range_start, range_end)?;
+    raw_only.lock().unwrap().close();
+    std::fs::rename(format!("{}/{}.bak", raw_dir, AGG_INDEX_DIR), format!("{}/{}", raw_dir, AGG_INDEX_DIR))?;
+    println!("mixed tree:\n{}", mixed_tree.format_call_tree(true));
+    assert_eq!(sorted_lines(raw_only_tree.format_call_tree(true)), sorted_lines(mixed_tree.format_call_tree(true)));
+    assert_eq!(mixed_tree.to_tree().children[0].duration, raw_only_tree.to_tree().children[0].duration);
+
+    //重新生成索引写入临时目录后替换旧的索引
+    let index_info = std::fs::read_to_string(format!("{}/{}/index.json", raw_dir, AGG_INDEX_DIR))?;
+    assert!(build_agg_index(&raw_dir, &mut |_, _| {})? > 0);
+    assert_eq!(std::fs::read_to_string(format!("{}/{}/index.json", raw_dir, AGG_INDEX_DIR))?, index_info);
+    assert!(std::fs::metadata(format!("{}/{}.tmp", raw_dir, AGG_INDEX_DIR)).is_err());
+    assert!(std::fs::metadata(format!("{}/{}.old", raw_dir, AGG_INDEX_DIR)).is_err());
+    //生成失败时保留旧的索引: 临时目录被同名文件占用
+    std::fs::write(format!("{}/{}.tmp", raw_dir, AGG_INDEX_DIR), b"")?;
+    assert!(build_agg_index(&raw_dir, &mut |_, _| {}).is_err());
+    assert!(has_agg_index(&raw_dir));
+    std::fs::remove_file(format!("{}/{}.tmp", raw_dir, AGG_INDEX_DIR))?;
+    println!("stack retention test passed");
+    Ok(())
+}
@@ -0,0 +1,62 @@
+extern crate flare_server;
+extern crate websocket;
+
+use flare_server::storage_usage::*;
+use flare_server::testkit::*;
+use flare_server::Profiler;
+use flare_server::shared_stream::SharedStream;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use websocket::sender::{Sender, Writer};
+
+//按数据类型统计会话取样目录的磁盘占用，估算录制中的增长速度
+fn main() -> io::Result<()> {
+    assert_eq!(get_storage_category("", "thread_10_stack.fdata"), STORAGE_STACKS);
+    assert_eq!(get_storage_category("", "thread_10_cpu_time.1000ms.fts"), STORAGE_METRICS);
+    assert_eq!(get_storage_category("", "metric_process_rss.fts"), STORAGE_METRICS);
+    assert_eq!(get_storage_category("", "method_info.fidx"), STORAGE_INDEXES);
+    assert_eq!(get_storage_category("agg_index", "minutes.json"), STORAGE_INDEXES);
+    assert_eq!(get_storage_category("thread_dumps", "1570000000000.txt"), STORAGE_SNAPSHOTS);
+    assert_eq!(get_storage_category("", "summary_info.json"), STORAGE_OTHER);
+
+    let growth = estimate_growth(Some((1000, 1000)), 3000, 5000, 10_000, Some(7_200_000));
+    assert_eq!(growth, StorageGrowth { bytes_per_sec: 2000.0, bytes_per_hour: 7_200_000, secs_until_disk_full: Some(3600) });
+    //间隔太短时按录制时长的平均值
+    let growth = estimate_growth(Some((2500, 4000)), 3000, 5000, 10_000, None);
+    assert_eq!((growth.bytes_per_sec, growth.secs_until_disk_full), (500.0, None));
+
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Service.handle()V");
+    script.add_thread(10, "worker-1", vec![vec![2, 1]], 1_000_000)
+        .add_thread(11, "worker-2", vec![vec![1]], 1_000_000);
+    let collector = record_script(script, "target/testkit-samples/storage_usage", 10_000)?;
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let usage = measure_storage_usage(&sample_data_dir)?;
+    println!("storage usage: {:?}", usage);
+    assert!(usage.stacks_bytes > 0 && usage.metrics_bytes > 0 && usage.indexes_bytes > 0 && usage.other_bytes > 0);
+    assert_eq!(usage.snapshots_bytes, 0);
+    assert_eq!(usage.total_bytes, usage.stacks_bytes + usage.metrics_bytes + usage.indexes_bytes + usage.snapshots_bytes + usage.other_bytes);
+    let stack_file_len = std::fs::metadata(format!("{}/thread_10_stack.fdata", sample_data_dir))?.len();
+    assert!(usage.stacks_bytes > stack_file_len);
+    assert!(measure_storage_usage("target/testkit-samples/storage_usage/not-exists").is_err());
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer { stream: SharedStream::new(server_stream), sender: Sender::new(false) };
+    let profiler = Profiler::new();
+    let session_id = profiler.lock().unwrap().open_sample(&sample_data_dir)?;
+    let mut request = |json: String| {
+        let mut out_cmd = String::new();
+        profiler.lock().unwrap().handle_request(&mut writer, json, &mut out_cmd)
+    };
+    request(format!(r#"{{"cmd": "storage_usage", "options": {{"session_id": "{}"}}}}"#, session_id))?;
+    request(r#"{"cmd": "storage_usage", "options": {}}"#.to_string())?;
+    assert!(request(r#"{"cmd": "storage_usage", "options": {"session_id": "not-exists"}}"#.to_string()).is_err());
+    println!("storage usage test passed");
+    Ok(())
+}
@@ -0,0 +1,57 @@
+extern crate flare_server;
+
+use flare_server::sample::MethodInfo;
+use flare_server::symbol_cache::*;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+fn methods(count: i64) -> Vec<MethodInfo> {
+    (0..count).map(|method_id| MethodInfo { method_id, full_name: format!("com.example.Foo.m{}()V", method_id), hits_count: 0 }).collect()
+}
+
+//key只计算一次并保存到取样目录，缓存超过上限时删除最久未使用的
+fn main() -> io::Result<()> {
+    let test_dir = "target/symbol-cache-test";
+    let _ = std::fs::remove_dir_all(test_dir);
+    let sample_dir = format!("{}/sample", test_dir);
+    let cache_dir = format!("{}/cache", test_dir);
+    std::fs::create_dir_all(&sample_dir)?;
+    let data_path = format!("{}/method_info.fdata", sample_dir);
+    std::fs::write(&data_path, "com.example.Foo.m0()V")?;
+
+    assert!(get_symbol_cache_key(&sample_dir, false)?.len() == 16);
+    assert!(!Path::new(&sample_dir).join(SYMBOL_CACHE_KEY_FILE).exists());
+    let key = get_symbol_cache_key(&sample_dir, true)?;
+    let saved = std::fs::read_to_string(Path::new(&sample_dir).join(SYMBOL_CACHE_KEY_FILE))?;
+    assert_eq!(saved, format!("21 {}", key));
+    //之后打开只读取保存的key
+    std::fs::write(Path::new(&sample_dir).join(SYMBOL_CACHE_KEY_FILE), "21 0123456789abcdef")?;
+    assert_eq!(get_symbol_cache_key(&sample_dir, true)?, "0123456789abcdef");
+    //方法表改变后重新计算
+    std::fs::write(&data_path, "com.example.Foo.m0()Vcom.example.Foo.m1()V")?;
+    let new_key = get_symbol_cache_key(&sample_dir, true)?;
+    assert!(new_key != key && new_key != "0123456789abcdef");
+
+    set_symbol_cache_config(&SymbolCacheConfig { dir: cache_dir.clone(), max_entries: 2 }, "unused");
+    assert_eq!(get_symbol_cache_dir(), cache_dir);
+    save_symbol_cache("a", &methods(1))?;
+    thread::sleep(Duration::from_millis(20));
+    save_symbol_cache("b", &methods(2))?;
+    thread::sleep(Duration::from_millis(20));
+    //读取后a成为最近使用的缓存
+    assert_eq!(load_symbol_cache("a").unwrap().len(), 1);
+    thread::sleep(Duration::from_millis(20));
+    save_symbol_cache("c", &methods(3))?;
+    assert!(load_symbol_cache("b").is_none());
+    assert_eq!(load_symbol_cache("a").unwrap().len(), 1);
+    assert_eq!(load_symbol_cache("c").unwrap()[2].full_name, "com.example.Foo.m2()V");
+    assert_eq!(evict_symbol_caches(&cache_dir, 1)?, 1);
+    assert!(load_symbol_cache("a").is_none());
+
+    set_symbol_cache_config(&SymbolCacheConfig::default(), "samples");
+    assert_eq!(get_symbol_cache_dir(), "samples/.symbols");
+    println!("symbol cache test is done.");
+    Ok(())
+}
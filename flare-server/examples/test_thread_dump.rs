@@ -0,0 +1,86 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::thread_dump::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+const DUMP: &str = "2019-10-02 15:06:41
+Full thread dump (flare agent):
+
+\"main\" #1 prio=5
+   java.lang.Thread.State: BLOCKED
+\tat com.example.Account.withdraw(J)V
+\t- waiting to lock <0x1b6d3586> (a com.example.Account)
+\tat java.lang.Thread.run()V
+\t- locked <0x4554617c> (a java.lang.Object)
+
+   Locked ownable synchronizers:
+\t- <0x74a14482> (a java.util.concurrent.locks.ReentrantLock$NonfairSync)
+
+\"worker-1\" #10 daemon prio=5
+   java.lang.Thread.State: RUNNABLE
+\tat java.lang.Thread.run()V
+
+   Locked ownable synchronizers:
+\t- None
+
+";
+
+//agent在第10次及第20次取样时推送线程dump
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 30);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_thread(1, "main", vec![vec![1]], 1000)
+        .add_event(ScriptedEvent::ThreadDump { sample_index: 10, content: DUMP.to_string() })
+        .add_event(ScriptedEvent::ThreadDump { sample_index: 20, content: DUMP.replace("BLOCKED", "RUNNABLE") });
+
+    //等待agent结果的回调: 收到第一个dump时完成，等待第三个dump的请求超时或者会话关闭时返回错误
+    let results: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
+    let new_waiter = |expected: usize| {
+        let results = results.clone();
+        Box::new(move |collector: &SampleCollector, error: Option<io::Error>| {
+            let result = match error {
+                Some(e) => format!("{:?}", e.kind()),
+                None if collector.get_thread_dumps().len() >= expected => format!("dumps: {}", collector.get_thread_dumps().len()),
+                None => return false
+            };
+            results.lock().unwrap().push(result);
+            true
+        })
+    };
+    let (first_waiter, timeout_waiter, close_waiter) = (new_waiter(1), new_waiter(3), new_waiter(3));
+    let collector = record_script_with(script.clone(), "target/testkit-samples/thread_dump", 10_000, |collector| {
+        collector.add_agent_result_waiter(i64::max_value(), first_waiter);
+        collector.add_agent_result_waiter(1, timeout_waiter);
+        collector.add_agent_result_waiter(i64::max_value(), close_waiter);
+        Ok(())
+    })?;
+    assert_eq!(*results.lock().unwrap(), vec!["dumps: 1"]);
+    collector.lock().unwrap().expire_agent_result_waiters(2);
+    assert_eq!(*results.lock().unwrap(), vec!["dumps: 1", "TimedOut"]);
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    assert_eq!(*results.lock().unwrap(), vec!["dumps: 1", "TimedOut", "NotConnected"]);
+    drop(collector);
+
+    //重新打开后从会话目录加载
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let collector = collector.lock().unwrap();
+    let dumps = collector.get_thread_dumps().to_vec();
+    println!("thread dumps: {:?}", dumps);
+    assert_eq!(dumps.len(), 2);
+    assert_eq!(dumps[0], ThreadDumpInfo { time: script.start_time + 10 * 20, threads: 2, size: DUMP.len() as u64 });
+    assert_eq!(collector.load_thread_dump(dumps[0].time)?, DUMP);
+    //线程的部分包含持有的锁
+    let main_thread = extract_thread(DUMP, 1).unwrap();
+    assert!(main_thread.contains("\tat java.lang.Thread.run()V\n\t- locked <0x4554617c> (a java.lang.Object)\n"));
+    assert!(main_thread.contains("ReentrantLock$NonfairSync") && !main_thread.contains("worker-1"));
+    assert!(collector.load_thread_dump(dumps[1].time)?.contains("java.lang.Thread.State: RUNNABLE\n\tat com.example.Account"));
+    assert!(collector.load_thread_dump(12345).is_err());
+    //只支持连接agent的会话
+    assert!(collector.request_thread_dump().is_err());
+    println!("thread dump test passed");
+    Ok(())
+}
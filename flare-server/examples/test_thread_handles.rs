@@ -0,0 +1,117 @@
+extern crate flare_server;
+
+use flare_server::testkit::*;
+use flare_server::thread_handles::*;
+use flare_server::sample::SampleCollector;
+use std::io;
+
+const THREAD_DUMP: &str = "\
+2019-10-02 10:10:10
+Full thread dump (flare agent):
+
+\"{name}\" #10 prio=5
+   java.lang.Thread.State: RUNNABLE
+\tat java.lang.Thread.run()V
+
+\"worker\" #11 prio=5
+   java.lang.Thread.State: WAITING
+\tat java.lang.Thread.run()V
+
+";
+
+//线程id被新的线程重用时分配新的句柄，两个线程的取样及调用栈、阶段、线程dump分开
+fn main() -> io::Result<()> {
+    let mut script = AgentScript::new(1_570_000_000_000, 20, 200);
+    script.add_method(1, "java.lang.Thread.run()V")
+        .add_method(2, "com.example.Import.load()V")
+        .add_method(3, "com.example.Export.save()V")
+        .add_thread_range(10, "import-1", vec![vec![2, 1]], 1_000_000, 0, 80)
+        .add_thread_range(10, "export-1", vec![vec![3, 1]], 1_000_000, 100, 200)
+        .add_thread(11, "worker", vec![vec![1]], 1_000_000);
+    //阶段按线程嵌套: worker结束自己的阶段，不影响import-1未结束的阶段
+    script.add_event(ScriptedEvent::IntervalBegin { sample_index: 10, name: "batch".to_string(), thread_id: 11 })
+        .add_event(ScriptedEvent::IntervalBegin { sample_index: 20, name: "import".to_string(), thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 30, thread_id: 11 })
+        .add_event(ScriptedEvent::IntervalEnd { sample_index: 60, thread_id: 10 })
+        .add_event(ScriptedEvent::IntervalBegin { sample_index: 120, name: "export".to_string(), thread_id: 10 })
+        .add_event(ScriptedEvent::ThreadDump { sample_index: 50, content: THREAD_DUMP.replace("{name}", "import-1") })
+        .add_event(ScriptedEvent::ThreadDump { sample_index: 150, content: THREAD_DUMP.replace("{name}", "export-1") });
+    let (start_time, end_time) = (script.start_time, script.get_end_time());
+    let collector = record_script(script, "target/testkit-samples/thread_handles", 10_000)?;
+    let reused_handle = 10 + THREAD_HANDLE_GENERATION;
+    {
+        let collector = collector.lock().unwrap();
+        let handles = collector.get_thread_handles();
+        assert_eq!(handles.len(), 3);
+        let handle = handles.iter().find(|x| x.handle == reused_handle).unwrap();
+        assert_eq!((handle.thread_id, handle.name.as_str()), (10, "export-1"));
+        assert_eq!((handle.start_time, handle.end_time), (start_time + 100 * 20, end_time));
+        assert_eq!(handles.iter().find(|x| x.handle == 10).unwrap().end_time, start_time + 79 * 20);
+        assert!(collector.get_session_events().iter().any(|x| x.kind == KIND_THREAD_ID_REUSED));
+    }
+    let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+    collector.lock().unwrap().close();
+    drop(collector);
+
+    let collector = SampleCollector::open(&sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    assert_eq!(collector.get_thread_handles().len(), 3);
+    let mut threads: Vec<(i64, String, i64)> = collector.get_threads()?.iter().map(|x| (x.id, x.name.clone(), x.sample_count)).collect();
+    threads.sort();
+    assert_eq!(threads, vec![(10, "import-1".to_string(), 80), (11, "worker".to_string(), 200), (reused_handle, "export-1".to_string(), 100)]);
+
+    assert_eq!(collector.load_thread_samples(10, start_time, end_time)?.len(), 80);
+    assert_eq!(collector.load_thread_samples(reused_handle, start_time, end_time)?.len(), 100);
+    let tree = collector.get_call_tree(&[reused_handle], start_time, end_time)?.format_call_tree(true);
+    assert!(tree.contains("com.example.Export.save()V"));
+    assert!(!tree.contains("com.example.Import.load()V"));
+
+    //慢方法查询按句柄读取调用栈
+    let calls = collector.search_slow_method_calls(reused_handle, &[2, 3], 0, 0)?;
+    assert!(!calls.is_empty());
+    assert!(calls.iter().all(|x| x.thread_id == reused_handle && x.method_id == 3));
+    assert!(collector.search_slow_method_calls(10, &[3], 0, 0)?.is_empty());
+
+    let intervals: Vec<(String, i64, i64, i64)> = collector.get_intervals().iter()
+        .map(|x| (x.name.clone(), x.thread_id, x.start_time, x.end_time)).collect();
+    assert_eq!(intervals, vec![
+        ("batch".to_string(), 11, start_time + 10 * 20, start_time + 30 * 20),
+        ("import".to_string(), 10, start_time + 20 * 20, start_time + 60 * 20),
+        ("export".to_string(), reused_handle, start_time + 120 * 20, -1),
+    ]);
+
+    //线程dump中的线程id按dump时间对应到句柄
+    let (dump_time1, dump_time2) = (start_time + 50 * 20, start_time + 150 * 20);
+    assert!(collector.load_thread_dump_of_thread(dump_time1, 10)?.starts_with("\"import-1\" #10"));
+    assert!(collector.load_thread_dump_of_thread(dump_time1, reused_handle).is_err());
+    let export_dump = collector.load_thread_dump_of_thread(dump_time2, reused_handle)?;
+    assert!(export_dump.starts_with("\"export-1\" #10") && !export_dump.contains("worker"), "{}", export_dump);
+    assert!(collector.load_thread_dump_of_thread(dump_time2, 10).is_err());
+    assert!(collector.load_thread_dump_of_thread(dump_time2, 11)?.contains("WAITING"));
+    collector.close();
+
+    //旧版本agent没有线程开始时间，CPU时间倒退或者长时间没有取样后改名时是新的线程
+    let mut table = ThreadHandleTable::new();
+    assert_eq!(table.resolve(5, 0, "a", 100, 1000), (5, false));
+    assert_eq!(table.resolve(5, 0, "a", 200, 1020), (5, false));
+    assert_eq!(table.resolve(5, 0, "b", 300, 1040), (5, false));
+    assert_eq!(table.resolve(5, 0, "b", 10, 1060), (5 + THREAD_HANDLE_GENERATION, true));
+    assert_eq!(table.resolve(5, 0, "c", 20, 1060 + THREAD_REUSE_MIN_GAP_MS + 1), (5 + 2 * THREAD_HANDLE_GENERATION, true));
+    assert_eq!(table.get_handle(5), 5 + 2 * THREAD_HANDLE_GENERATION);
+    assert_eq!(table.get_handle(6), 6);
+    assert!(table.has_reused());
+    assert_eq!(table.find_handle_at(5, 900), 5);
+    assert_eq!(table.find_handle_at(5, 1040), 5);
+    assert_eq!(table.find_handle_at(5, 1060), 5 + THREAD_HANDLE_GENERATION);
+    assert_eq!(table.find_handle_at(5, 5000), 5 + 2 * THREAD_HANDLE_GENERATION);
+
+    //agent报告线程开始时间时只按开始时间判断: 启动时间相同的CPU时间倒退不是新线程，开始时间改变时即使名称和CPU时间连续也是新线程
+    let mut table = ThreadHandleTable::new();
+    assert_eq!(table.resolve(7, 500, "pool-1", 100, 1000), (7, false));
+    assert_eq!(table.resolve(7, 500, "pool-1", 50, 1020), (7, false));
+    assert_eq!(table.resolve(7, 1030, "pool-1", 200, 1040), (7 + THREAD_HANDLE_GENERATION, true));
+    assert_eq!(table.resolve(7, 1030, "renamed", 300, 5000), (7 + THREAD_HANDLE_GENERATION, false));
+    assert_eq!(table.get_thread_handle(7 + THREAD_HANDLE_GENERATION).unwrap().agent_start_time, 1030);
+    println!("thread handles test passed");
+    Ok(())
+}
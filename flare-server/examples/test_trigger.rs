@@ -0,0 +1,52 @@
+extern crate flare_server;
+
+use flare_server::trigger::*;
+use std::io;
+
+fn trigger(name: &str, kind: &str, threshold: f64) -> TriggerConfig {
+    TriggerConfig { name: name.to_string(), kind: kind.to_string(), threshold }
+}
+
+fn input(cpu_usage: Option<(i64, i64)>, deadlocks: usize, error_events: usize) -> TriggerInput {
+    TriggerInput { cpu_usage, deadlocks, error_events }
+}
+
+fn names(fired: &[(String, String)]) -> Vec<&str> {
+    fired.iter().map(|x| x.0.as_str()).collect()
+}
+
+//cpu触发器超过阈值时触发一次，降到阈值以下后再次触发；死锁及错误事件有新增时触发
+fn main() -> io::Result<()> {
+    let triggers = vec![
+        trigger("high-cpu", TRIGGER_CPU, 150.0),
+        trigger("deadlock", TRIGGER_DEADLOCK, 0.0),
+        trigger("agent-error", TRIGGER_ERROR_EVENT, 0.0),
+    ];
+    validate_triggers(&triggers)?;
+    assert!(validate_triggers(&[trigger("a", "memory", 1.0)]).is_err());
+    assert!(validate_triggers(&[trigger("a", TRIGGER_CPU, 0.0)]).is_err());
+    assert!(validate_triggers(&[trigger("", TRIGGER_DEADLOCK, 0.0)]).is_err());
+    assert!(validate_triggers(&[trigger("a", TRIGGER_DEADLOCK, 0.0), trigger("a", TRIGGER_ERROR_EVENT, 0.0)]).is_err());
+
+    let mut state = TriggerState::default();
+    //第一次检查没有CPU使用率
+    assert!(evaluate_triggers(&triggers, &mut state, &input(Some((0, 0)), 0, 0)).is_empty());
+    //1秒内使用2秒CPU: 200%
+    let fired = evaluate_triggers(&triggers, &mut state, &input(Some((1000, 2_000_000)), 0, 0));
+    assert_eq!(names(&fired), vec!["high-cpu"]);
+    assert!(fired[0].1.contains("200.0%"));
+    assert!(evaluate_triggers(&triggers, &mut state, &input(Some((2000, 4_000_000)), 0, 0)).is_empty());
+    //降到阈值以下后重新触发
+    assert!(evaluate_triggers(&triggers, &mut state, &input(Some((3000, 4_500_000)), 0, 0)).is_empty());
+    assert_eq!(names(&evaluate_triggers(&triggers, &mut state, &input(Some((4000, 6_500_000)), 0, 0))), vec!["high-cpu"]);
+    //没有新的cgroup数据时保持状态
+    assert!(evaluate_triggers(&triggers, &mut state, &input(None, 0, 0)).is_empty());
+
+    let fired = evaluate_triggers(&triggers, &mut state, &input(None, 1, 2));
+    assert_eq!(names(&fired), vec!["deadlock", "agent-error"]);
+    assert_eq!(fired[1].1, "2 new error event(s) reported by agent");
+    assert!(evaluate_triggers(&triggers, &mut state, &input(None, 1, 2)).is_empty());
+    assert_eq!(names(&evaluate_triggers(&triggers, &mut state, &input(None, 1, 3))), vec!["agent-error"]);
+    println!("trigger test is done.");
+    Ok(())
+}
@@ -0,0 +1,70 @@
+extern crate flare_server;
+
+use flare_server::perf_import::*;
+use flare_server::sample::SampleCollector;
+use flare_server::warmup::*;
+use std::io;
+use std::fmt::Write;
+
+//模拟的基准测试: 前10秒热点方法每秒变化且编译线程繁忙，之后热点方法稳定
+fn generate_perf_script() -> String {
+    let mut script = String::new();
+    for step in 0..3000 {
+        let time = 1000.0 + step as f64 * 0.01;
+        let method = if step < 1000 {
+            format!("warm_{}", step / 100 * 5 + step % 5)
+        } else {
+            format!("steady_{}", step % 5)
+        };
+        writeln!(script, "bench 100/101 [000] {:.6}: 1 cpu-clock:\n\t1 {}+0x1 (bench.jar)\n\t2 main+0x1 (bench.jar)\n", time, method).unwrap();
+        if step < 1000 || step % 50 == 0 {
+            writeln!(script, "C2 CompilerThread0 100/102 [001] {:.6}: 1 cpu-clock:\n\t3 Compile::Optimize+0x1 (libjvm.so)\n", time).unwrap();
+        }
+    }
+    script
+}
+
+fn main() -> io::Result<()> {
+    let test_dir = "target/test-samples/warmup";
+    if std::fs::metadata(test_dir).is_ok() {
+        std::fs::remove_dir_all(test_dir)?;
+    }
+    std::fs::create_dir_all(test_dir)?;
+    let perf_file = format!("{}/bench.perf", test_dir);
+    std::fs::write(&perf_file, generate_perf_script())?;
+    let start_time = 1_570_000_000_000;
+    let options = PerfImportOptions { sample_interval: 10, start_time };
+    let stats = import_perf_script(&perf_file, &format!("{}/sample", test_dir), &options)?;
+
+    let collector = SampleCollector::open(&stats.sample_data_dir)?;
+    let mut collector = collector.lock().unwrap();
+    let analysis = detect_warmup(&mut collector, -1, -1, &WarmupOptions::default())?;
+    for window in &analysis.windows {
+        println!("{:?}", window);
+    }
+    assert!(analysis.has_compiler_threads);
+    assert!(analysis.steady_state);
+    assert_eq!(analysis.warmup_end_time, start_time + 10_000);
+    assert!(!analysis.windows[0].stable && analysis.windows[3].stable);
+    assert!(analysis.windows[0].compile_cpu_time_ms > analysis.windows[3].compile_cpu_time_ms);
+    assert_eq!(get_steady_start_time(&mut collector, start_time, -1)?, start_time + 10_000);
+
+    //只有预热阶段时没有稳态
+    let analysis = detect_warmup(&mut collector, start_time, start_time + 10_000, &WarmupOptions::default())?;
+    assert!(!analysis.steady_state);
+    assert_eq!(get_steady_start_time(&mut collector, start_time, start_time + 10_000)?, start_time);
+    //超出录制范围的部分不计入窗口
+    let analysis = detect_warmup(&mut collector, start_time - 3_600_000, start_time + 3_600_000, &WarmupOptions::default())?;
+    assert_eq!(analysis.start_time, start_time);
+    assert_eq!(analysis.windows.len(), 6);
+    let mut options = WarmupOptions::default();
+    options.window_ms = i64::max_value();
+    assert_eq!(detect_warmup(&mut collector, -1, -1, &options)?.windows.len(), 1);
+    //窗口过多时返回错误
+    options.window_ms = 1;
+    assert!(detect_warmup(&mut collector, -1, -1, &options).is_err());
+
+    collector.close();
+    println!("warmup test passed");
+    Ok(())
+}
@@ -0,0 +1,87 @@
+extern crate flare_server;
+#[macro_use]
+extern crate serde_json;
+
+use flare_server::webhook::*;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+//启动本地http服务接收webhook，校验事件过滤及消息格式
+fn main() -> io::Result<()> {
+    assert_eq!(parse_http_url("http://127.0.0.1:8080/hooks/flare")?, HttpUrl { tls: false, host: "127.0.0.1".to_string(), port: 8080, path: "/hooks/flare".to_string() });
+    assert_eq!(parse_http_url("http://example.com")?, HttpUrl { tls: false, host: "example.com".to_string(), port: 80, path: "/".to_string() });
+    assert_eq!(parse_http_url("https://hooks.slack.com/services/x")?, HttpUrl { tls: true, host: "hooks.slack.com".to_string(), port: 443, path: "/services/x".to_string() });
+    assert!(parse_http_url("https://").is_err());
+    assert!(parse_http_url("ftp://example.com").is_err());
+
+    //https地址连接到普通http服务时握手失败
+    let plain = TcpListener::bind("127.0.0.1:0")?;
+    let plain_port = plain.local_addr()?.port();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = plain.accept() {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        }
+    });
+    assert!(post_json(&format!("https://127.0.0.1:{}/hooks", plain_port), "{}").is_err());
+
+    let slack = build_payload("slack", NotifyEvent::AgentLost, "agent is lost: localhost:3333", &json!({"session_id": "s1"}));
+    assert_eq!(slack, json!({"text": "[flare-profiler] agent_lost: agent is lost: localhost:3333"}));
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let (tx, rx) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if line.to_lowercase().starts_with("content-length:") {
+                    content_length = line[15..].trim().parse::<usize>().unwrap();
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            tx.send(String::from_utf8(body).unwrap()).unwrap();
+        }
+    });
+
+    let webhooks = vec![
+        WebhookConfig { url: format!("http://127.0.0.1:{}/all", port), events: vec![], format: "json".to_string() },
+        WebhookConfig { url: format!("http://127.0.0.1:{}/slack", port), events: vec!["agent_lost".to_string()], format: "slack".to_string() },
+    ];
+    validate_webhooks(&webhooks)?;
+    let mut invalid = webhooks.clone();
+    invalid[1].events.push("recording_paused".to_string());
+    assert!(validate_webhooks(&invalid).is_err());
+
+    let notifier = WebhookNotifier::new(webhooks);
+    notifier.notify(NotifyEvent::RecordingStarted, "recording started", json!({"session_id": "s1"}));
+    let body: serde_json::Value = serde_json::from_str(&rx.recv_timeout(Duration::from_secs(5)).unwrap())?;
+    assert_eq!(body["event"], "recording_started");
+    assert_eq!(body["session_id"], "s1");
+    assert!(body["time"].as_i64().unwrap() > 0);
+
+    notifier.notify(NotifyEvent::AgentLost, "agent is lost", json!({"session_id": "s1"}));
+    let mut bodies = vec![];
+    for _ in 0..2 {
+        bodies.push(serde_json::from_str::<serde_json::Value>(&rx.recv_timeout(Duration::from_secs(5)).unwrap())?);
+    }
+    assert_eq!(bodies[0]["event"], "agent_lost");
+    assert_eq!(bodies[1]["text"], "[flare-profiler] agent_lost: agent is lost");
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+    assert!(get_free_disk_space(".").unwrap_or(1) > 0);
+    println!("webhook test is done.");
+    Ok(())
+}
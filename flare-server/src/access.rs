@@ -0,0 +1,219 @@
+
+//访问控制: 配置 access_tokens 后，连接需要先发送 hello 请求并在参数 token 中提供令牌，之后才能执行其它命令
+//  没有配置令牌时不检查(本机单用户使用)，所有连接都是管理员
+//连接的身份保存在处理该连接的线程中，审计日志按此记录操作者
+//  每次请求按当前配置重新验证令牌，重新加载配置后删除的令牌立即失效，权限及允许的会话按新的配置
+//令牌可以限制只能访问部分会话(sessions)，如服务团队只能查看自己应用的录制:
+//  模式匹配会话ID、agent地址或者取样目录名称(不含上级路径)，* 匹配任意文本，如 "*order-service*"
+//  事件推送按订阅时的身份过滤，受限的令牌只收到允许的会话及取样目录的事件
+//  受限的令牌只能执行指定了允许的会话/取样目录的命令，以及 SCOPED_GLOBAL_COMMANDS，会话列表只返回允许的会话
+//只读模式(配置 read_only 或者启动参数 --read-only): 拒绝 WRITE_COMMANDS，用于向更多人开放已录制的取样
+//  只读模式打开取样不写入任何文件: 未转换的agent录制拒绝打开，方法索引只读打开，不保存符号缓存
+
+use std::cell::RefCell;
+
+//控制类命令: 连接/断开目标进程、录制、修改配置等，会记录审计日志
+pub const CONTROL_COMMANDS: &[&str] = &[
+    "attach_jvm",
+    "connect_agent",
+    "connect_runtime",
+    "attach_child",
+    "close_session",
+    "close_all_session",
+    "add_samples_root",
+    "build_index",
+    "split_sample",
+    "merge_sessions",
+    "load_mapping",
+    "record_group",
+    "stop_record_group",
+    "start_offcpu_sampling",
+    "detect_deadlocks",
+    "thread_dump",
+    "heap_histogram",
+    "ingest_filter",
+    "flush_policy",
+    "self_profile",
+    "set_baseline",
+    "export_sample",
+    "plugin_command",
+    "plugin_report",
+    "reload_config",
+];
+
+//只读模式下拒绝的命令: 连接目标进程、录制、修改配置及写入取样目录或导出文件
+pub const WRITE_COMMANDS: &[&str] = &[
+    "attach_jvm",
+    "connect_agent",
+    "connect_runtime",
+    "attach_child",
+    "close_session",
+    "close_all_session",
+    "add_samples_root",
+    "build_index",
+    "split_sample",
+    "merge_sessions",
+    "add_marker",
+    "save_view",
+    "export_sample",
+    "export_metrics",
+    "generate_report",
+    "record_group",
+    "stop_record_group",
+    "start_offcpu_sampling",
+    "detect_deadlocks",
+    "thread_dump",
+    "heap_histogram",
+    "ingest_filter",
+    "flush_policy",
+    "self_profile",
+    "set_baseline",
+    "plugin_command",
+    "plugin_report",
+];
+
+//受限的令牌可以执行的、不针对某个会话的命令
+pub const SCOPED_GLOBAL_COMMANDS: &[&str] = &[
+    "hello",
+    "list_sessions",
+    "history_samples",
+    "list_runtimes",
+    "list_plugins",
+    "list_baselines",
+    "schema",
+];
+
+pub const ANONYMOUS: &str = "anonymous";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AccessToken {
+    //令牌名称，如使用者或团队名称，记录在审计日志中
+    pub name: String,
+    pub token: String,
+    //管理员可以查询审计日志
+    #[serde(default)]
+    pub admin: bool,
+    //允许访问的会话模式，为空表示不限制
+    #[serde(default)]
+    pub sessions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIdentity {
+    pub name: String,
+    pub addr: String,
+    pub admin: bool,
+    pub authenticated: bool,
+    //允许访问的会话模式，为空表示不限制
+    pub scopes: Vec<String>,
+    //hello 请求中的令牌，为空表示没有提供令牌，不写入审计日志
+    pub token: String,
+}
+
+impl ClientIdentity {
+    //没有提供令牌的连接
+    pub fn anonymous(addr: &str) -> ClientIdentity {
+        ClientIdentity {
+            name: ANONYMOUS.to_string(),
+            addr: addr.to_string(),
+            admin: false,
+            authenticated: false,
+            scopes: vec![],
+            token: String::new(),
+        }
+    }
+
+    pub fn from_token(addr: &str, token: &AccessToken) -> ClientIdentity {
+        ClientIdentity {
+            name: token.name.clone(),
+            addr: addr.to_string(),
+            admin: token.admin,
+            authenticated: true,
+            scopes: token.sessions.clone(),
+            token: token.token.clone(),
+        }
+    }
+
+    //按当前的令牌配置重新解析身份，令牌已经删除时不再是已验证的身份
+    pub fn refresh(&self, tokens: &[AccessToken]) -> ClientIdentity {
+        if self.token.is_empty() {
+            return self.clone();
+        }
+        match find_access_token(tokens, &self.token) {
+            Some(access_token) => ClientIdentity::from_token(&self.addr, access_token),
+            None => ClientIdentity {
+                name: self.name.clone(),
+                addr: self.addr.clone(),
+                admin: false,
+                authenticated: false,
+                scopes: vec![],
+                token: self.token.clone(),
+            }
+        }
+    }
+
+    pub fn is_scoped(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    //names: 会话ID、来源等，任意一个匹配即可访问
+    pub fn can_access(&self, names: &[String]) -> bool {
+        !self.is_scoped() || self.scopes.iter().any(|pattern| names.iter().any(|name| match_pattern(pattern, name)))
+    }
+
+    //是否可以执行命令，没有配置令牌时都可以执行
+    pub fn is_allowed(&self, tokens: &[AccessToken]) -> bool {
+        tokens.is_empty() || self.refresh(tokens).authenticated
+    }
+
+    pub fn is_admin(&self, tokens: &[AccessToken]) -> bool {
+        tokens.is_empty() || self.refresh(tokens).admin
+    }
+}
+
+pub fn is_control_command(cmd: &str) -> bool {
+    CONTROL_COMMANDS.contains(&cmd)
+}
+
+pub fn is_write_command(cmd: &str) -> bool {
+    WRITE_COMMANDS.contains(&cmd)
+}
+
+//* 匹配任意文本，其它字符精确匹配
+pub fn match_pattern(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    if !text.starts_with(parts[0]) {
+        return false;
+    }
+    let mut rest = &text[parts[0].len()..];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false
+        }
+    }
+    rest.ends_with(parts[parts.len() - 1])
+}
+
+pub fn find_access_token<'a>(tokens: &'a [AccessToken], token: &str) -> Option<&'a AccessToken> {
+    if token.is_empty() {
+        return None;
+    }
+    tokens.iter().find(|x| x.token == token)
+}
+
+thread_local! {
+    static CLIENT_IDENTITY: RefCell<Option<ClientIdentity>> = RefCell::new(None);
+}
+
+pub fn set_client_identity(identity: Option<ClientIdentity>) {
+    CLIENT_IDENTITY.with(|x| *x.borrow_mut() = identity);
+}
+
+//不是websocket连接(如命令回放)时返回本机的匿名身份
+pub fn get_client_identity() -> ClientIdentity {
+    CLIENT_IDENTITY.with(|x| x.borrow().clone()).unwrap_or_else(|| ClientIdentity::anonymous("local"))
+}
@@ -0,0 +1,55 @@
+//加载flare-agent到运行中的本机JVM(attach_jvm 命令)
+//  使用 flare-attacher 调用JVM的attach接口: java -jar flare-attacher.jar <agent库路径> <agent参数> <pid>
+//  agent库及attacher从发布目录的 agent/lib 查找，单文件部署(--features embedded)时使用解压的嵌入文件
+//  Java 8 需要 $JAVA_HOME/lib/tools.jar，存在时加入 bootclasspath
+
+use std::io;
+use std::io::ErrorKind;
+use std::process::Command;
+use embedded::find_dist_file;
+use process_tree::AGENT_LIB_NAME;
+use sample_path::path_to_string;
+use utils::*;
+
+pub const ATTACHER_JAR: &str = "flare-attacher-jar-with-dependencies.jar";
+const AGENT_LIB_DIR: &str = "agent/lib";
+
+//libflareagent.so / libflareagent.dylib / flareagent.dll
+pub fn get_agent_lib_file_name() -> String {
+    if cfg!(windows) {
+        format!("{}.dll", AGENT_LIB_NAME)
+    } else if cfg!(target_os = "macos") {
+        format!("lib{}.dylib", AGENT_LIB_NAME)
+    } else {
+        format!("lib{}.so", AGENT_LIB_NAME)
+    }
+}
+
+//加载agent，agent在本机的 agent_port 端口监听，返回agent地址
+pub fn attach_agent(target_pid: i64, agent_port: u16, sample_interval_ms: i64) -> io::Result<String> {
+    let agent_lib = find_dist_file(&format!("{}/{}", AGENT_LIB_DIR, get_agent_lib_file_name()))?;
+    let attacher_jar = find_dist_file(&format!("{}/{}", AGENT_LIB_DIR, ATTACHER_JAR))?;
+    //目标JVM的工作目录不同，需要绝对路径
+    let agent_lib = path_to_string(&std::fs::canonicalize(&agent_lib)?);
+    let agent_addr = format!("127.0.0.1:{}", agent_port);
+    let agent_options = format!("address={},interval={}", agent_addr, sample_interval_ms);
+
+    let java_home = std::env::var("JAVA_HOME").unwrap_or_default();
+    let mut command = if java_home.is_empty() {
+        Command::new("java")
+    } else {
+        Command::new(format!("{}/bin/java", java_home))
+    };
+    let tools_jar = format!("{}/lib/tools.jar", java_home);
+    if !java_home.is_empty() && std::path::Path::new(&tools_jar).is_file() {
+        command.arg(format!("-Xbootclasspath/a:{}", tools_jar));
+    }
+    command.arg("-jar").arg(&attacher_jar).arg(&agent_lib).arg(&agent_options).arg(target_pid.to_string());
+    println!("attach agent to jvm: {}, agent lib: {}, options: {}", target_pid, agent_lib, agent_options);
+    let output = command.output()
+        .map_err(|e| new_error(e.kind(), &format!("run flare-attacher failed, check JAVA_HOME: {}", e)))?;
+    if !output.status.success() {
+        return Err(new_error(ErrorKind::Other, &format!("attach agent to jvm {} failed: {}", target_pid, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(agent_addr)
+}
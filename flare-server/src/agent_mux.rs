@@ -0,0 +1,409 @@
+//一个agent连接上的多个录制会话(connect_agent 的 multiplex 选项)
+//  agent只接受一个订阅连接，同一个agent地址的会话共享连接，读取线程把事件分发给每个会话
+//  每个会话可以使用不同的取样间隔(agent间隔的整数倍，按取样时间抽取)及写入过滤(会话自己的 ingest_filter)
+//  控制请求带上会话标签(tag)，agent在响应中带回，响应只分发给发起请求的会话；旧版本agent的响应没有标签，分发给所有会话
+//  后加入的会话先收到缓存的 sample_info 及方法信息；最后一个会话关闭时断开agent连接
+//  每个会话的事件队列有上限，会话写入跟不上时丢弃线程取样，恢复后补发 dropped_events 诊断信息，不影响其它会话
+
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use flare_proto::agent::*;
+use flare_proto::handshake::AgentCompat;
+use resp::Value;
+use runtime_adapter::*;
+use data_quality::KIND_DROPPED_EVENTS;
+use utils::*;
+
+//每个会话缓存的事件数量
+pub const MUX_QUEUE_SIZE: usize = 10_000;
+
+lazy_static! {
+    static ref AGENT_MUXES: Mutex<HashMap<String, Arc<AgentMux>>> = Mutex::new(HashMap::new());
+}
+
+pub struct AgentMux {
+    agent_addr: String,
+    compat: Option<AgentCompat>,
+    request_hook: Mutex<Option<Box<Fn(&Value) -> io::Result<()> + Send>>>,
+    shutdown_hook: Mutex<Option<Box<Fn() + Send>>>,
+    //加入第一个会话后开始读取，之前不丢弃取样
+    adapter: Mutex<Option<JvmAgentAdapter>>,
+    state: Mutex<MuxState>,
+}
+
+struct MuxState {
+    subscribers: Vec<MuxSubscriber>,
+    //agent的取样间隔(ms)
+    agent_interval: i64,
+    sample_info: Option<SampleInfoEvent>,
+    methods: Vec<MethodEvent>,
+    next_tag: i64,
+    closed: bool,
+}
+
+struct MuxSubscriber {
+    tag: String,
+    //会话的取样间隔(ms)，0表示使用agent的间隔
+    interval: i64,
+    //当前取样轮次的时间及是否保留
+    round_time: i64,
+    keep_round: bool,
+    last_kept_time: i64,
+    //线程上次保留的取样的累计cpu时间，抽取后重新计算cpu_time_delta
+    cpu_times: HashMap<i64, i64>,
+    sender: mpsc::SyncSender<AgentEvent>,
+    //队列满时丢弃的线程取样，尚未通知会话
+    dropped: i64,
+}
+
+impl MuxSubscriber {
+    //转换为会话的事件，抽取时丢弃的取样返回None
+    fn convert(&mut self, event: &AgentEvent, agent_interval: i64) -> Option<AgentEvent> {
+        let session_interval = get_session_interval(self.interval, agent_interval);
+        match event {
+            AgentEvent::SampleInfo(x) => {
+                let mut x = x.clone();
+                x.sample_interval = session_interval;
+                Some(AgentEvent::SampleInfo(x))
+            }
+            AgentEvent::Thread(x) if session_interval > agent_interval && agent_interval > 0 => {
+                if x.time != self.round_time {
+                    self.round_time = x.time;
+                    //允许半个agent间隔的误差
+                    self.keep_round = self.last_kept_time == 0 || x.time - self.last_kept_time >= session_interval - agent_interval / 2;
+                    if self.keep_round {
+                        self.last_kept_time = x.time;
+                    }
+                }
+                if !self.keep_round {
+                    return None;
+                }
+                let mut x = x.clone();
+                if let Some(last_cpu_time) = self.cpu_times.insert(x.id, x.cpu_time) {
+                    x.cpu_time_delta = x.cpu_time - last_cpu_time;
+                }
+                Some(AgentEvent::Thread(x))
+            }
+            event => Some(event.clone())
+        }
+    }
+
+    //返回false表示会话已经结束
+    fn send(&mut self, event: AgentEvent) -> bool {
+        let time = match &event {
+            //只丢弃线程取样，方法信息及请求的响应等等待会话读取
+            AgentEvent::Thread(x) => x.time,
+            _ => return self.sender.send(event).is_ok()
+        };
+        if self.dropped > 0 {
+            let diagnostic = AgentEvent::Diagnostic(DiagnosticEvent {
+                time,
+                level: "warn".to_string(),
+                kind: KIND_DROPPED_EVENTS.to_string(),
+                message: format!("session queue of shared agent connection is full, dropped {} samples", self.dropped),
+                count: self.dropped,
+            });
+            match self.sender.try_send(diagnostic) {
+                Ok(_) => self.dropped = 0,
+                Err(mpsc::TrySendError::Full(_)) => {
+                    self.dropped += 1;
+                    return true;
+                }
+                Err(mpsc::TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        match self.sender.try_send(event) {
+            Ok(_) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                if self.dropped == 0 {
+                    println!("session queue of shared agent connection is full, drop samples, tag: {}", self.tag);
+                }
+                self.dropped += 1;
+                true
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+//会话的取样间隔，向上取整为agent间隔的整数倍
+pub fn get_session_interval(interval: i64, agent_interval: i64) -> i64 {
+    if agent_interval <= 0 {
+        return interval;
+    }
+    if interval <= agent_interval {
+        return agent_interval;
+    }
+    (interval + agent_interval - 1) / agent_interval * agent_interval
+}
+
+impl AgentMux {
+    fn connect(agent_addr: &str) -> io::Result<Arc<AgentMux>> {
+        let mut adapter = JvmAgentAdapter::new(agent_addr);
+        adapter.connect()?;
+        let mux = Arc::new(AgentMux {
+            agent_addr: agent_addr.to_string(),
+            compat: adapter.agent_compat(),
+            request_hook: Mutex::new(adapter.request_hook()),
+            shutdown_hook: Mutex::new(adapter.shutdown_hook()),
+            adapter: Mutex::new(None),
+            state: Mutex::new(MuxState {
+                subscribers: vec![],
+                agent_interval: 0,
+                sample_info: None,
+                methods: vec![],
+                next_tag: 0,
+                closed: false,
+            }),
+        });
+        *mux.adapter.lock().unwrap() = Some(adapter);
+        Ok(mux)
+    }
+
+    //启动读取线程，已经启动时忽略
+    fn start(this: &Arc<AgentMux>) -> io::Result<()> {
+        let adapter = match this.adapter.lock().unwrap().take() {
+            Some(adapter) => adapter,
+            None => return Ok(())
+        };
+        let mux = this.clone();
+        thread::Builder::new()
+            .name("flare-agent-mux".to_string())
+            .spawn(move || mux.read_events(adapter))?;
+        Ok(())
+    }
+
+    fn read_events(&self, mut adapter: JvmAgentAdapter) {
+        loop {
+            match adapter.next_tagged_event() {
+                Ok(Some((event, tag))) => self.dispatch(event, tag),
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Failed to receive data: {}, agent: {}", e, self.agent_addr);
+                    break;
+                }
+            }
+        }
+        println!("shared agent connection is stopped: {}", self.agent_addr);
+        self.close();
+    }
+
+    fn dispatch(&self, event: AgentEvent, tag: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        match &event {
+            AgentEvent::SampleInfo(x) => {
+                state.agent_interval = x.sample_interval;
+                state.sample_info = Some(x.clone());
+            }
+            AgentEvent::Method(x) => state.methods.push(x.clone()),
+            AgentEvent::Thread(x) => {
+                //后加入的会话按最新的取样时间创建目录
+                if let Some(sample_info) = state.sample_info.as_mut() {
+                    sample_info.last_sample_time = x.time;
+                }
+            }
+            _ => {}
+        }
+        let agent_interval = state.agent_interval;
+        let mut i = 0;
+        while i < state.subscribers.len() {
+            let alive = {
+                let subscriber = &mut state.subscribers[i];
+                match &tag {
+                    Some(tag) if *tag != subscriber.tag => true,
+                    _ => match subscriber.convert(&event, agent_interval) {
+                        Some(event) => subscriber.send(event),
+                        None => true
+                    }
+                }
+            };
+            //发送失败表示会话已经结束
+            if alive {
+                i += 1;
+            } else {
+                state.subscribers.remove(i);
+            }
+        }
+    }
+
+    fn add_subscriber(&self, interval: i64, queue_size: usize) -> io::Result<(String, mpsc::Receiver<AgentEvent>)> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(new_error(ErrorKind::NotConnected, &format!("agent connection is closed: {}", self.agent_addr)));
+        }
+        state.next_tag += 1;
+        let tag = format!("s{}", state.next_tag);
+        //先放入缓存的 sample_info 及方法信息，不占用取样的队列
+        let (sender, receiver) = mpsc::sync_channel(queue_size + state.methods.len() + 1);
+        let mut subscriber = MuxSubscriber {
+            tag: tag.clone(),
+            interval,
+            round_time: 0,
+            keep_round: true,
+            last_kept_time: 0,
+            cpu_times: HashMap::new(),
+            sender,
+            dropped: 0,
+        };
+        if let Some(sample_info) = state.sample_info.clone() {
+            if let Some(event) = subscriber.convert(&AgentEvent::SampleInfo(sample_info), state.agent_interval) {
+                subscriber.sender.send(event).ok();
+            }
+        }
+        for method in &state.methods {
+            subscriber.sender.send(AgentEvent::Method(method.clone())).ok();
+        }
+        state.subscribers.push(subscriber);
+        println!("add session to shared agent connection: {}, tag: {}, sessions: {}", self.agent_addr, tag, state.subscribers.len());
+        Ok((tag, receiver))
+    }
+
+    fn remove_subscriber(&self, tag: &str) {
+        let empty = {
+            let mut state = self.state.lock().unwrap();
+            state.subscribers.retain(|x| x.tag != tag);
+            state.subscribers.is_empty()
+        };
+        if empty {
+            self.close();
+        }
+    }
+
+    //控制请求带上会话标签，不支持标签的旧版本agent忽略此参数
+    fn send_request(&self, tag: &str, request: &Value) -> io::Result<()> {
+        let request = with_event_tag(request.clone(), tag);
+        match self.request_hook.lock().unwrap().as_ref() {
+            Some(hook) => hook(&request),
+            None => Err(new_error(ErrorKind::NotConnected, &format!("agent connection is closed: {}", self.agent_addr)))
+        }
+    }
+
+    //断开agent连接，结束所有会话
+    fn close(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            state.closed = true;
+            state.subscribers.clear();
+        }
+        {
+            let mut muxes = AGENT_MUXES.lock().unwrap();
+            if muxes.get(&self.agent_addr).map_or(false, |x| std::ptr::eq(&**x, self)) {
+                muxes.remove(&self.agent_addr);
+            }
+        }
+        *self.request_hook.lock().unwrap() = None;
+        *self.adapter.lock().unwrap() = None;
+        if let Some(shutdown_hook) = self.shutdown_hook.lock().unwrap().take() {
+            shutdown_hook();
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    pub fn get_session_count(&self) -> usize {
+        self.state.lock().unwrap().subscribers.len()
+    }
+}
+
+//共享连接的会话数量，没有连接时为0
+pub fn get_agent_session_count(agent_addr: &str) -> usize {
+    let mux = AGENT_MUXES.lock().unwrap().get(agent_addr).cloned();
+    mux.map_or(0, |x| x.get_session_count())
+}
+
+fn get_or_connect_mux(agent_addr: &str) -> io::Result<Arc<AgentMux>> {
+    let mut muxes = AGENT_MUXES.lock().unwrap();
+    if let Some(mux) = muxes.get(agent_addr) {
+        if !mux.is_closed() {
+            return Ok(mux.clone());
+        }
+    }
+    let mux = AgentMux::connect(agent_addr)?;
+    muxes.insert(agent_addr.to_string(), mux.clone());
+    Ok(mux)
+}
+
+//共享agent连接的会话
+pub struct MuxSessionAdapter {
+    agent_addr: String,
+    interval: i64,
+    queue_size: usize,
+    mux: Option<Arc<AgentMux>>,
+    tag: String,
+    receiver: Option<mpsc::Receiver<AgentEvent>>,
+}
+
+impl MuxSessionAdapter {
+    pub fn new(agent_addr: &str, interval: i64) -> MuxSessionAdapter {
+        MuxSessionAdapter {
+            agent_addr: agent_addr.to_string(),
+            interval,
+            queue_size: MUX_QUEUE_SIZE,
+            mux: None,
+            tag: String::new(),
+            receiver: None,
+        }
+    }
+
+    //连接之前设置
+    pub fn set_queue_size(&mut self, queue_size: usize) {
+        self.queue_size = queue_size;
+    }
+
+    pub fn get_tag(&self) -> &str {
+        &self.tag
+    }
+}
+
+impl RuntimeAdapter for MuxSessionAdapter {
+    fn runtime(&self) -> &str {
+        "jvm"
+    }
+
+    fn target(&self) -> &str {
+        &self.agent_addr
+    }
+
+    fn connect(&mut self) -> io::Result<()> {
+        let mux = get_or_connect_mux(&self.agent_addr)?;
+        let (tag, receiver) = mux.add_subscriber(self.interval, self.queue_size)?;
+        AgentMux::start(&mux)?;
+        self.tag = tag;
+        self.receiver = Some(receiver);
+        self.mux = Some(mux);
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<AgentEvent>> {
+        let receiver = self.receiver.as_ref().ok_or_else(|| new_error(ErrorKind::NotConnected, "agent is not connected"))?;
+        //共享连接断开或者会话被移除时结束
+        Ok(receiver.recv().ok())
+    }
+
+    fn shutdown_hook(&self) -> Option<Box<Fn() + Send>> {
+        let mux = self.mux.as_ref()?.clone();
+        let tag = self.tag.clone();
+        Some(Box::new(move || {
+            println!("remove session from shared agent connection: {}, tag: {}", mux.agent_addr, tag);
+            mux.remove_subscriber(&tag);
+        }))
+    }
+
+    fn request_hook(&self) -> Option<Box<Fn(&Value) -> io::Result<()> + Send>> {
+        let mux = self.mux.as_ref()?.clone();
+        let tag = self.tag.clone();
+        Some(Box::new(move |request| mux.send_request(&tag, request)))
+    }
+
+    fn agent_compat(&self) -> Option<AgentCompat> {
+        self.mux.as_ref().and_then(|x| x.compat.clone())
+    }
+}
@@ -0,0 +1,222 @@
+//导入agent独立录制(agent参数 output=<目录>，格式见 flare_proto::recording)
+//  open_sample 打开录制目录时重放事件流，转换为录制目录下的取样目录，不按15分钟分割
+//  转换完成后在 imported_sample 文件中记录取样目录名称及事件文件的大小、修改时间，之后打开时直接使用；
+//  事件文件变化(录制还在进行)或者转换中断时下次重新转换，转换期间用 import.lock 防止重复转换
+//  进程被强制结束时事件流末尾可能不完整，读取到不完整的事件时结束
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use flare_proto::agent::AgentEvent;
+use flare_proto::handshake::AgentCompat;
+use flare_proto::recording::RECORDING_EVENTS_FILE;
+use resp::{Decoder, Value};
+use runtime_adapter::*;
+use sample::SampleCollector;
+use sample_path::{path_to_string, string_to_path};
+use utils::*;
+
+pub const IMPORTED_SAMPLE_FILE: &str = "imported_sample";
+pub const IMPORT_LOCK_FILE: &str = "import.lock";
+
+//重放录制的事件流
+pub struct RecordingAdapter {
+    //转义后的事件文件路径，作为会话的目标
+    path: String,
+    events_path: PathBuf,
+    decoder: Option<Decoder<File>>,
+    compat: Option<AgentCompat>,
+    //旧版本agent没有hello，握手时读取的第一个事件
+    pending: Option<AgentEvent>,
+}
+
+impl RecordingAdapter {
+    pub fn new<P: AsRef<Path>>(events_path: P) -> RecordingAdapter {
+        RecordingAdapter {
+            path: path_to_string(events_path.as_ref()),
+            events_path: events_path.as_ref().to_path_buf(),
+            decoder: None,
+            compat: None,
+            pending: None,
+        }
+    }
+}
+
+impl RuntimeAdapter for RecordingAdapter {
+    fn runtime(&self) -> &str {
+        "recording"
+    }
+
+    fn target(&self) -> &str {
+        &self.path
+    }
+
+    fn connect(&mut self) -> io::Result<()> {
+        let file = File::open(&self.events_path)?;
+        let mut decoder = Decoder::with_buf_bulk(BufReader::new(file));
+        let (compat, pending) = read_handshake(&mut decoder, &self.path)?;
+        self.decoder = Some(decoder);
+        self.compat = Some(compat);
+        self.pending = pending;
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<AgentEvent>> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some(event));
+        }
+        let decoder = self.decoder.as_mut().ok_or_else(|| new_error(ErrorKind::NotConnected, "agent recording is not opened"))?;
+        loop {
+            //文件结束或者末尾不完整
+            let value = match decoder.decode() {
+                Ok(value) => value,
+                Err(e) => {
+                    println!("agent recording is finished: {}, {}", self.path, e);
+                    return Ok(None);
+                }
+            };
+            if let Some(event) = AgentEvent::from_resp(&value)? {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    fn shutdown_hook(&self) -> Option<Box<Fn() + Send>> {
+        None
+    }
+
+    fn request_hook(&self) -> Option<Box<Fn(&Value) -> io::Result<()> + Send>> {
+        None
+    }
+
+    fn agent_compat(&self) -> Option<AgentCompat> {
+        self.compat.clone()
+    }
+
+    fn is_realtime(&self) -> bool {
+        false
+    }
+}
+
+//转换录制目录，返回取样目录
+pub fn import_agent_recording<P: AsRef<Path>>(recording_dir: P) -> io::Result<PathBuf> {
+    let dir_path = recording_dir.as_ref();
+    let recording_dir = &path_to_string(dir_path);
+    let events_path = dir_path.join(RECORDING_EVENTS_FILE);
+    let events_stat = get_events_stat(&events_path)?;
+    if let Some(sample_dir) = get_imported_sample_dir(dir_path) {
+        return Ok(sample_dir);
+    }
+    //其他会话或者进程正在转换时不等待，避免两次转换写入同一个录制目录
+    let _lock = ImportLock::acquire(dir_path)?;
+    if let Some(sample_dir) = get_imported_sample_dir(dir_path) {
+        return Ok(sample_dir);
+    }
+    let dir_name = match dir_path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => "agent-recording".to_string()
+    };
+    println!("import agent recording: {} ..", recording_dir);
+
+    let collector = SampleCollector::new(&dir_name, dir_path)?;
+    collector.lock().unwrap().set_roll_data_dir(false);
+    collector.lock().unwrap().start_adapter(Box::new(RecordingAdapter::new(&events_path)))?;
+    while !collector.lock().unwrap().is_disconnected() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let sample_dir = {
+        let mut collector = collector.lock().unwrap();
+        let sample_dir = collector.get_sample_data_path().to_path_buf();
+        collector.close();
+        sample_dir
+    };
+    if sample_dir.as_os_str().is_empty() {
+        return Err(new_error(ErrorKind::InvalidData, &format!("agent recording is empty: {}", recording_dir)));
+    }
+
+    //录制还在进行时再次打开，重新转换后删除之前的取样目录
+    let previous = read_imported_sample(dir_path);
+    let imported = ImportedSample {
+        sample_dir: sample_dir.file_name().map_or(String::new(), |x| path_to_string(Path::new(x))),
+        events_size: events_stat.0,
+        events_mtime: events_stat.1,
+    };
+    std::fs::write(dir_path.join(IMPORTED_SAMPLE_FILE), serde_json::to_string_pretty(&imported)?)?;
+    if let Some(previous) = previous {
+        if !previous.sample_dir.is_empty() && previous.sample_dir != imported.sample_dir {
+            if let Err(e) = std::fs::remove_dir_all(dir_path.join(string_to_path(&previous.sample_dir))) {
+                println!("remove previous imported sample dir failed: {}, err: {}", previous.sample_dir, e);
+            }
+        }
+    }
+    println!("import agent recording is done: {}, sample data dir: {}", recording_dir, path_to_string(&sample_dir));
+    Ok(sample_dir)
+}
+
+//已经转换的取样目录，事件文件在转换后增加了(录制还在进行)时返回None
+pub fn get_imported_sample_dir<P: AsRef<Path>>(recording_dir: P) -> Option<PathBuf> {
+    let dir_path = recording_dir.as_ref();
+    let imported = read_imported_sample(dir_path)?;
+    let sample_dir = dir_path.join(string_to_path(&imported.sample_dir));
+    if imported.sample_dir.is_empty() || !sample_dir.is_dir() {
+        return None;
+    }
+    if get_events_stat(&dir_path.join(RECORDING_EVENTS_FILE)).ok()? != (imported.events_size, imported.events_mtime) {
+        return None;
+    }
+    Some(sample_dir)
+}
+
+//转换结果，保存在 imported_sample 文件中
+#[derive(Serialize, Deserialize)]
+struct ImportedSample {
+    //转义后的目录名称
+    sample_dir: String,
+    //转换时事件文件的大小及修改时间
+    events_size: u64,
+    events_mtime: i64,
+}
+
+fn read_imported_sample(dir_path: &Path) -> Option<ImportedSample> {
+    let json = std::fs::read_to_string(dir_path.join(IMPORTED_SAMPLE_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+//(文件大小, 修改时间毫秒数)
+fn get_events_stat(events_path: &Path) -> io::Result<(u64, i64)> {
+    let meta = std::fs::metadata(events_path)?;
+    let mtime = meta.modified()?.duration_since(UNIX_EPOCH).map(|x| x.as_millis() as i64).unwrap_or(0);
+    Ok((meta.len(), mtime))
+}
+
+//转换期间在录制目录中创建锁文件，转换结束(包括失败)时删除
+struct ImportLock {
+    path: PathBuf,
+}
+
+impl ImportLock {
+    fn acquire(dir_path: &Path) -> io::Result<ImportLock> {
+        let path = dir_path.join(IMPORT_LOCK_FILE);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id())?;
+                Ok(ImportLock { path })
+            }
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(new_error(ErrorKind::WouldBlock, &format!("agent recording is being imported, try again later (remove {} if no import is running)", path_to_string(&path))))
+            }
+            Err(e) => Err(e)
+        }
+    }
+}
+
+impl Drop for ImportLock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            println!("remove import lock file failed: {}, err: {}", path_to_string(&self.path), e);
+        }
+    }
+}
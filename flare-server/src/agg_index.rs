@@ -0,0 +1,411 @@
+
+use std::collections::HashMap;
+use std::cmp::{max, min};
+use std::io;
+use std::io::{Write, BufRead, BufReader, ErrorKind};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use ::sample::{ThreadData, SummaryInfo, STACK_RETENTION_AGGREGATED};
+use flare_utils::tuple_indexed::{TupleIndexedFile, TupleValue};
+use utils::*;
+use stack_record::*;
+
+type JavaLong = i64;
+type JavaMethod = i64;
+
+//聚合索引保存在取样目录的子目录中，与原始数据一起写入
+pub const AGG_INDEX_DIR: &str = "agg_index";
+//重新生成时先写入临时目录，完成后替换旧的索引
+const AGG_INDEX_TMP_DIR: &str = "agg_index.tmp";
+const AGG_INDEX_OLD_DIR: &str = "agg_index.old";
+pub const AGG_INDEX_VERSION: i32 = 1;
+//聚合的时间粒度：每分钟
+pub const AGG_MINUTE_MS: i64 = 60_000;
+
+//线程汇总数据
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ThreadTotals {
+    pub thread_id: JavaLong,
+    pub samples: i64,
+    //nanos
+    pub cpu_time: i64,
+    //millis
+    pub duration: i64,
+    pub first_sample_time: i64,
+    pub last_sample_time: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AggIndexInfo {
+    pub version: i32,
+    pub minute_ms: i64,
+    pub threads: Vec<ThreadTotals>,
+}
+
+//相同调用栈的累计值，frames与ThreadData.stacktrace一致，栈顶在前
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StackSummary {
+    pub frames: Vec<JavaMethod>,
+    pub samples: i64,
+    pub cpu_time: i64,
+    pub duration: i64,
+}
+
+//一个线程一分钟内的调用栈汇总
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MinuteSummary {
+    pub minute_time: i64,
+    pub samples: i64,
+    pub cpu_time: i64,
+    pub duration: i64,
+    pub stacks: Vec<StackSummary>,
+    //本分钟调用栈中出现过的方法，排序去重，旧版本的索引没有此字段
+    #[serde(default)]
+    pub methods: Option<Vec<JavaMethod>>,
+}
+
+impl MinuteSummary {
+    //是否可能包含任意一个方法，没有方法索引时总是返回true
+    pub fn may_contain_any(&self, method_ids: &[JavaMethod]) -> bool {
+        match &self.methods {
+            Some(methods) => method_ids.iter().any(|x| methods.binary_search(x).is_ok()),
+            None => true
+        }
+    }
+}
+
+struct ThreadAggState {
+    totals: ThreadTotals,
+    minute_time: i64,
+    stacks: HashMap<Vec<JavaMethod>, StackSummary>,
+}
+
+pub fn get_minute_time(sample_time: i64) -> i64 {
+    sample_time - sample_time % AGG_MINUTE_MS
+}
+
+fn get_index_info_path(index_dir: &Path) -> PathBuf {
+    index_dir.join("index.json")
+}
+
+fn get_thread_minutes_path(index_dir: &Path, thread_id: JavaLong) -> PathBuf {
+    index_dir.join(format!("thread_{}_minutes.json", thread_id))
+}
+
+pub fn has_agg_index<P: AsRef<Path>>(sample_data_dir: P) -> bool {
+    std::fs::metadata(get_index_info_path(&sample_data_dir.as_ref().join(AGG_INDEX_DIR))).is_ok()
+}
+
+//录制时累计每分钟的调用栈，每满一分钟追加写入线程的分钟汇总文件
+pub struct AggIndexBuilder {
+    index_dir: PathBuf,
+    threads: HashMap<JavaLong, ThreadAggState>,
+}
+
+impl AggIndexBuilder {
+
+    pub fn new<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<AggIndexBuilder> {
+        AggIndexBuilder::new_in_dir(sample_data_dir.as_ref().join(AGG_INDEX_DIR))
+    }
+
+    fn new_in_dir(index_dir: PathBuf) -> io::Result<AggIndexBuilder> {
+        //清除旧的索引，避免追加写入重复的数据
+        if std::fs::metadata(&index_dir).is_ok() {
+            std::fs::remove_dir_all(&index_dir)?;
+        }
+        std::fs::create_dir_all(&index_dir)?;
+        Ok(AggIndexBuilder {
+            index_dir,
+            threads: HashMap::new(),
+        })
+    }
+
+    //同一个线程的取样必须按时间顺序添加
+    pub fn add_sample(&mut self, thread_data: &ThreadData) -> io::Result<()> {
+        let thread_id = thread_data.id;
+        let sample_time = thread_data.sample_time;
+        let minute_time = get_minute_time(sample_time);
+        let mut is_new = false;
+        let state = self.threads.entry(thread_id).or_insert_with(|| {
+            is_new = true;
+            ThreadAggState {
+                totals: ThreadTotals {
+                    thread_id,
+                    first_sample_time: sample_time,
+                    ..Default::default()
+                },
+                minute_time,
+                stacks: HashMap::new(),
+            }
+        });
+        let duration = if is_new { 0 } else { sample_time - state.totals.last_sample_time };
+        if state.minute_time != minute_time {
+            let summary = take_minute_summary(state);
+            append_minute_summary(&self.index_dir, thread_id, &summary)?;
+            state.minute_time = minute_time;
+        }
+
+        let totals = &mut state.totals;
+        totals.samples += 1;
+        totals.cpu_time += thread_data.cpu_time_delta;
+        totals.duration += duration;
+        totals.last_sample_time = sample_time;
+
+        let stack = state.stacks.entry(thread_data.stacktrace.clone()).or_insert_with(|| StackSummary {
+            frames: thread_data.stacktrace.clone(),
+            samples: 0,
+            cpu_time: 0,
+            duration: 0,
+        });
+        stack.samples += 1;
+        stack.cpu_time += thread_data.cpu_time_delta;
+        stack.duration += duration;
+        Ok(())
+    }
+
+    //保存线程汇总数据，未满一分钟的数据暂不写入
+    pub fn save_info(&self) -> io::Result<()> {
+        let mut threads: Vec<ThreadTotals> = self.threads.values().map(|x| x.totals.clone()).collect();
+        threads.sort_by(|a, b| a.thread_id.cmp(&b.thread_id));
+        let info = AggIndexInfo {
+            version: AGG_INDEX_VERSION,
+            minute_ms: AGG_MINUTE_MS,
+            threads,
+        };
+        let json = serde_json::to_string_pretty(&info)?;
+        std::fs::write(get_index_info_path(&self.index_dir), json.as_bytes())
+    }
+
+    //写入所有未满一分钟的数据
+    pub fn finish(&mut self) -> io::Result<()> {
+        for (thread_id, state) in self.threads.iter_mut() {
+            if !state.stacks.is_empty() {
+                let summary = take_minute_summary(state);
+                append_minute_summary(&self.index_dir, *thread_id, &summary)?;
+            }
+        }
+        self.save_info()
+    }
+}
+
+fn take_minute_summary(state: &mut ThreadAggState) -> MinuteSummary {
+    let mut summary = MinuteSummary {
+        minute_time: state.minute_time,
+        samples: 0,
+        cpu_time: 0,
+        duration: 0,
+        stacks: Vec::with_capacity(state.stacks.len()),
+        methods: None,
+    };
+    let mut methods = vec![];
+    for (_, stack) in state.stacks.drain() {
+        summary.samples += stack.samples;
+        summary.cpu_time += stack.cpu_time;
+        summary.duration += stack.duration;
+        methods.extend_from_slice(&stack.frames);
+        summary.stacks.push(stack);
+    }
+    methods.sort();
+    methods.dedup();
+    summary.methods = Some(methods);
+    summary
+}
+
+fn append_minute_summary(index_dir: &Path, thread_id: JavaLong, summary: &MinuteSummary) -> io::Result<()> {
+    let path = get_thread_minutes_path(index_dir, thread_id);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    //one summary per line
+    let mut data = serde_json::to_vec(summary)?;
+    data.push(b'\n');
+    file.write_all(&data)
+}
+
+//读取聚合索引，线程的分钟汇总在首次使用时加载
+pub struct AggIndex {
+    index_dir: PathBuf,
+    threads: HashMap<JavaLong, ThreadTotals>,
+    minutes: HashMap<JavaLong, Vec<MinuteSummary>>,
+}
+
+impl AggIndex {
+
+    pub fn open<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<AggIndex> {
+        let index_dir = sample_data_dir.as_ref().join(AGG_INDEX_DIR);
+        let json = std::fs::read_to_string(get_index_info_path(&index_dir))?;
+        let info: AggIndexInfo = serde_json::from_str(&json)?;
+        if info.version != AGG_INDEX_VERSION || info.minute_ms != AGG_MINUTE_MS {
+            return Err(new_error(ErrorKind::InvalidData, &format!("unsupported aggregation index version: {}", info.version)));
+        }
+        let mut threads = HashMap::new();
+        for totals in info.threads {
+            threads.insert(totals.thread_id, totals);
+        }
+        Ok(AggIndex {
+            index_dir,
+            threads,
+            minutes: HashMap::new(),
+        })
+    }
+
+    pub fn get_thread_totals(&self, thread_id: JavaLong) -> Option<&ThreadTotals> {
+        self.threads.get(&thread_id)
+    }
+
+    //返回完全落在[start_time, end_time]范围内的分钟汇总
+    pub fn get_minutes(&mut self, thread_id: JavaLong, start_time: i64, end_time: i64) -> io::Result<Vec<&MinuteSummary>> {
+        Ok(self.load_minutes(thread_id)?.iter()
+            .filter(|x| x.minute_time >= start_time && x.minute_time + AGG_MINUTE_MS - 1 <= end_time)
+            .collect())
+    }
+
+    //返回与[start_time, end_time]相交的分钟汇总
+    pub fn get_overlapping_minutes(&mut self, thread_id: JavaLong, start_time: i64, end_time: i64) -> io::Result<Vec<&MinuteSummary>> {
+        Ok(self.load_minutes(thread_id)?.iter()
+            .filter(|x| x.minute_time <= end_time && x.minute_time + AGG_MINUTE_MS - 1 >= start_time)
+            .collect())
+    }
+
+    //返回可能包含指定方法的时间范围，连续命中的分钟合并为一个范围
+    //最后一个分钟汇总之后尚未写入索引的时间（如录制中的当前分钟）总是保留
+    pub fn get_method_ranges(&mut self, thread_id: JavaLong, method_ids: &[JavaMethod], start_time: i64, end_time: i64) -> io::Result<Vec<(i64, i64)>> {
+        let mut ranges: Vec<(i64, i64)> = vec![];
+        let mut indexed_end = i64::min_value();
+        let mut last_matched = false;
+        for minute in self.load_minutes(thread_id)? {
+            let minute_end = minute.minute_time + AGG_MINUTE_MS - 1;
+            indexed_end = max(indexed_end, minute_end);
+            if minute.minute_time > end_time || minute_end < start_time {
+                continue;
+            }
+            if !minute.may_contain_any(method_ids) {
+                last_matched = false;
+                continue;
+            }
+            add_range(&mut ranges, last_matched, max(start_time, minute.minute_time), min(end_time, minute_end));
+            last_matched = true;
+        }
+        if indexed_end < end_time {
+            add_range(&mut ranges, last_matched, max(start_time, indexed_end.saturating_add(1)), end_time);
+        }
+        Ok(ranges)
+    }
+
+    fn load_minutes(&mut self, thread_id: JavaLong) -> io::Result<&Vec<MinuteSummary>> {
+        if !self.minutes.contains_key(&thread_id) {
+            let minutes = load_minute_summaries(&self.index_dir, thread_id)?;
+            self.minutes.insert(thread_id, minutes);
+        }
+        Ok(self.minutes.get(&thread_id).unwrap())
+    }
+}
+
+//与上一个命中的范围相邻时合并
+fn add_range(ranges: &mut Vec<(i64, i64)>, merge: bool, start_time: i64, end_time: i64) {
+    match ranges.last_mut() {
+        Some(last) if merge => last.1 = end_time,
+        _ => ranges.push((start_time, end_time))
+    }
+}
+
+fn load_minute_summaries(index_dir: &Path, thread_id: JavaLong) -> io::Result<Vec<MinuteSummary>> {
+    let path = get_thread_minutes_path(index_dir, thread_id);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut minutes = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        minutes.push(serde_json::from_str::<MinuteSummary>(&line)?);
+    }
+    Ok(minutes)
+}
+
+//每次读取的取样数量
+const BUILD_INDEX_BATCH_SIZE: usize = 10_000;
+
+//扫描已保存的取样数据，重新生成聚合索引，progress(已处理的索引数, 索引总数)，返回取样数
+//  索引写入临时目录，完成后才替换旧的索引，生成失败时保留旧的索引
+pub fn build_agg_index<P: AsRef<Path>>(sample_data_dir: P, progress: &mut FnMut(usize, usize)) -> io::Result<usize> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    let path = sample_data_dir.join("summary_info.json");
+    let json = std::fs::read_to_string(path)?;
+    let summary: SummaryInfo = serde_json::from_str(&json)?;
+    if summary.sample_info.stack_retention == STACK_RETENTION_AGGREGATED {
+        return Err(new_invalid_input_error("raw stacks are not retained, aggregation index can not be rebuilt"));
+    }
+
+    let mut stack_files = vec![];
+    let mut total = 0;
+    for thread in &summary.threads {
+        let thread_stack_file = sample_data_dir.join(format!("thread_{}_stack", thread.id));
+        match TupleIndexedFile::new_reader(&thread_stack_file) {
+            Ok(file) => {
+                let steps: Vec<i64> = file.get_index_pairs(0, usize::max_value()).iter().map(|x| x.0).collect();
+                total += steps.len();
+                stack_files.push((file, steps));
+            }
+            Err(e) => println!("load thread stacktrace file failed: {}, err: {}", thread_stack_file.display(), e)
+        }
+    }
+
+    let tmp_dir = sample_data_dir.join(AGG_INDEX_TMP_DIR);
+    let result = write_agg_index(&tmp_dir, &mut stack_files, total, progress)
+        .and_then(|samples| replace_index_dir(sample_data_dir, &tmp_dir).map(|_| samples));
+    if result.is_err() && std::fs::metadata(&tmp_dir).is_ok() {
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+    }
+    result
+}
+
+fn write_agg_index(index_dir: &Path, stack_files: &mut Vec<(TupleIndexedFile, Vec<i64>)>, total: usize, progress: &mut FnMut(usize, usize)) -> io::Result<usize> {
+    let mut builder = AggIndexBuilder::new_in_dir(index_dir.to_path_buf())?;
+    let mut done = 0;
+    let mut samples = 0;
+    progress(done, total);
+    for (file, steps) in stack_files.iter_mut() {
+        for batch in steps.chunks(BUILD_INDEX_BATCH_SIZE) {
+            let start_step = TupleValue::uint32(batch[0] as u32);
+            let end_step = TupleValue::uint32(batch[batch.len() - 1] as u32);
+            let range = file.map_range(&start_step, &end_step)?;
+            //批次的索引是连续的，数据块中的行全部读取
+            for record in parse_stack_records(range.iter(), 0, u32::max_value()) {
+                builder.add_sample(&record.to_thread_data())?;
+                samples += 1;
+            }
+            done += batch.len();
+            progress(done, total);
+        }
+    }
+    builder.finish()?;
+    Ok(samples)
+}
+
+//旧的索引先改名再删除，Windows上不能直接改名覆盖已经存在的目录
+fn replace_index_dir(sample_data_dir: &Path, tmp_dir: &Path) -> io::Result<()> {
+    let index_dir = sample_data_dir.join(AGG_INDEX_DIR);
+    let old_dir = sample_data_dir.join(AGG_INDEX_OLD_DIR);
+    if std::fs::metadata(&old_dir).is_ok() {
+        std::fs::remove_dir_all(&old_dir)?;
+    }
+    let has_old = std::fs::metadata(&index_dir).is_ok();
+    if has_old {
+        std::fs::rename(&index_dir, &old_dir)?;
+    }
+    if let Err(e) = std::fs::rename(tmp_dir, &index_dir) {
+        if has_old {
+            let _ = std::fs::rename(&old_dir, &index_dir);
+        }
+        return Err(e);
+    }
+    if has_old {
+        if let Err(e) = std::fs::remove_dir_all(&old_dir) {
+            println!("remove old aggregation index failed: {}, err: {}", old_dir.display(), e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+
+//线程分配速率：agent参数 alloc_interval=<ms> 开启，每个间隔推送一批allocation事件，每个事件追加一行到会话目录下的文件
+//  allocations.json
+//  JDK11+使用JVMTI SampledObjectAlloc在TLAB中取样(agent参数 alloc_sampling_interval=<bytes>)，事件为间隔内
+//  同一线程、同一分配调用栈的取样估算的字节数
+//  之前的JDK读取线程累计分配的字节数(ThreadMXBean.getThreadAllocatedBytes)，事件为两次读取之间的增量及读取时的调用栈，
+//  按调用栈归属到方法的分配量是统计意义上的估算，检查间隔越短越接近真实的分配点
+//内存中按时间顺序保留最近的 MAX_ALLOCATION_SAMPLES 个事件，按时间范围二分查找
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use flare_proto::agent::AllocationEvent;
+use ::sample::SampleCollector;
+
+pub const ALLOCATION_FILE: &str = "allocations.json";
+//仪表盘统计最近一段时间的分配情况
+pub const DASHBOARD_ALLOCATION_WINDOW: i64 = 60_000;
+pub const DASHBOARD_TOP_ALLOCATORS: usize = 10;
+pub const MAX_ALLOCATION_SAMPLES: usize = 100_000;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AllocationSample {
+    pub time: i64,
+    pub thread_id: i64,
+    pub thread_name: String,
+    //距上次读取分配的字节数
+    pub bytes: i64,
+    //方法ID，栈顶在前
+    pub stacktrace: Vec<i64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ThreadAllocationRate {
+    pub thread_id: i64,
+    pub thread_name: String,
+    pub total_bytes: i64,
+    pub bytes_per_sec: i64,
+    //每个unit_time分配的字节数
+    pub series: Vec<i64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MethodAllocation {
+    pub method_id: i64,
+    pub method_name: String,
+    //在栈顶时的分配量
+    pub self_bytes: i64,
+    //在调用栈中时的分配量
+    pub total_bytes: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AllocationRateReport {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub unit_time: i64,
+    pub total_bytes: i64,
+    pub bytes_per_sec: i64,
+    //按分配字节数从大到小排列
+    pub threads: Vec<ThreadAllocationRate>,
+    //按栈顶分配字节数从大到小排列
+    pub methods: Vec<MethodAllocation>,
+}
+
+//按时间排序的分配事件，超过上限时丢弃最早的事件
+pub struct AllocationStore {
+    samples: VecDeque<AllocationSample>,
+    max_samples: usize,
+}
+
+impl AllocationStore {
+    pub fn new(max_samples: usize) -> AllocationStore {
+        AllocationStore {
+            samples: VecDeque::new(),
+            max_samples: max_samples.max(1),
+        }
+    }
+
+    //同一批事件的时间相同，乱序到达的事件插入到相同时间的事件之后
+    pub fn push(&mut self, sample: AllocationSample) {
+        match self.samples.back() {
+            Some(last) if last.time > sample.time => {
+                let index = self.samples.partition_point(|x| x.time <= sample.time);
+                self.samples.insert(index, sample);
+            }
+            _ => self.samples.push_back(sample)
+        }
+        while self.samples.len() > self.max_samples {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn first_time(&self) -> Option<i64> {
+        self.samples.front().map(|x| x.time)
+    }
+
+    pub fn last_time(&self) -> Option<i64> {
+        self.samples.back().map(|x| x.time)
+    }
+
+    //时间在[start_time, end_time]之间的事件
+    pub fn range(&self, start_time: i64, end_time: i64) -> impl Iterator<Item=&AllocationSample> {
+        let start = self.samples.partition_point(|x| x.time < start_time);
+        let end = self.samples.partition_point(|x| x.time <= end_time).max(start);
+        self.samples.range(start..end)
+    }
+}
+
+pub fn new_allocation_sample(event: &AllocationEvent) -> AllocationSample {
+    AllocationSample {
+        time: event.time,
+        thread_id: event.id,
+        thread_name: event.name.clone(),
+        bytes: event.bytes,
+        stacktrace: event.stacktrace.clone(),
+    }
+}
+
+pub fn append_allocation_sample<P: AsRef<Path>>(sample_data_dir: P, sample: &AllocationSample) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(ALLOCATION_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    //one sample per line
+    let mut data = serde_json::to_vec(sample)?;
+    data.push(b'\n');
+    file.write_all(&data)
+}
+
+pub fn load_allocation_samples<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<AllocationSample>> {
+    let path = sample_data_dir.as_ref().join(ALLOCATION_FILE);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut samples = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        samples.push(serde_json::from_str::<AllocationSample>(&line)?);
+    }
+    Ok(samples)
+}
+
+//统计时间范围内各线程的分配速率序列及分配最多的方法，limit 为返回的线程/方法数量
+pub fn get_allocation_rate(collector: &mut SampleCollector, start_time: i64, end_time: i64, unit_time: i64, limit: usize) -> AllocationRateReport {
+    let unit_time = unit_time.max(1);
+    let buckets = ((end_time - start_time).max(0) / unit_time + 1) as usize;
+    let mut threads: HashMap<i64, ThreadAllocationRate> = HashMap::new();
+    let mut methods: HashMap<i64, (i64, i64)> = HashMap::new();
+    let mut total_bytes = 0;
+    for sample in collector.get_allocation_samples().range(start_time, end_time) {
+        total_bytes += sample.bytes;
+        let thread = threads.entry(sample.thread_id).or_insert_with(|| ThreadAllocationRate {
+            thread_id: sample.thread_id,
+            thread_name: sample.thread_name.clone(),
+            total_bytes: 0,
+            bytes_per_sec: 0,
+            series: vec![0; buckets],
+        });
+        thread.total_bytes += sample.bytes;
+        thread.series[((sample.time - start_time) / unit_time) as usize] += sample.bytes;
+
+        //递归调用的方法只计算一次
+        let mut counted = vec![];
+        for (i, method_id) in sample.stacktrace.iter().enumerate() {
+            let entry = methods.entry(*method_id).or_insert((0, 0));
+            if i == 0 {
+                entry.0 += sample.bytes;
+            }
+            if !counted.contains(method_id) {
+                entry.1 += sample.bytes;
+                counted.push(*method_id);
+            }
+        }
+    }
+
+    let duration = (end_time - start_time).max(1);
+    let mut threads: Vec<ThreadAllocationRate> = threads.into_iter().map(|(_, mut thread)| {
+        thread.bytes_per_sec = thread.total_bytes * 1000 / duration;
+        thread
+    }).collect();
+    threads.sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes).then(a.thread_id.cmp(&b.thread_id)));
+    threads.truncate(limit);
+
+    let mut methods: Vec<(i64, (i64, i64))> = methods.into_iter().filter(|x| (x.1).0 > 0).collect();
+    methods.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then(a.0.cmp(&b.0)));
+    methods.truncate(limit);
+    let methods = methods.into_iter().map(|(method_id, (self_bytes, total_bytes))| MethodAllocation {
+        method_id,
+        method_name: collector.get_method_name(method_id),
+        self_bytes,
+        total_bytes,
+    }).collect();
+
+    AllocationRateReport {
+        start_time,
+        end_time,
+        unit_time,
+        total_bytes,
+        bytes_per_sec: total_bytes * 1000 / duration,
+        threads,
+        methods,
+    }
+}
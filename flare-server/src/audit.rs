@@ -0,0 +1,146 @@
+
+//控制类操作的审计日志(access::CONTROL_COMMANDS)，只追加写入，每行一个json
+//  默认保存在录制输出目录下 <samples_root>/audit.log，可以通过配置 audit_log_file 修改
+//  记录操作者(令牌名称及地址)、命令、目标(会话、取样目录或进程)及结果，请求参数中的敏感属性会被隐藏
+//  在任务池中执行的命令(如 export_sample)在任务完成时按任务结果记录
+//管理员可以通过 audit_log 命令查询
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+use serde_json::{Map, Value};
+use access::ClientIdentity;
+use command_recorder::sanitize_value;
+
+pub const AUDIT_FILE: &str = "audit.log";
+
+//依次查找的目标参数
+const TARGET_KEYS: &[&str] = &["session_id", "session_ids", "sample_data_dir", "samples_root", "target_pid", "pid", "agent_addr", "target", "group_id", "name"];
+
+lazy_static! {
+    //多个连接同时写入时保证每行完整
+    static ref AUDIT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    pub time: i64,
+    pub identity: String,
+    pub addr: String,
+    pub action: String,
+    #[serde(default)]
+    pub target: String,
+    //success, failure
+    pub result: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub options: Value,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AuditQuery {
+    //-1表示不限制
+    pub start_time: i64,
+    pub end_time: i64,
+    pub identity: String,
+    pub action: String,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        (self.start_time < 0 || entry.time >= self.start_time)
+            && (self.end_time < 0 || entry.time <= self.end_time)
+            && (self.identity.is_empty() || entry.identity == self.identity)
+            && (self.action.is_empty() || entry.action == self.action)
+    }
+}
+
+pub fn get_audit_target(options: &Map<String, Value>) -> String {
+    for key in TARGET_KEYS {
+        match options.get(*key) {
+            Some(Value::String(x)) => return x.clone(),
+            Some(Value::Null) | None => {}
+            Some(x) => return x.to_string(),
+        }
+    }
+    String::new()
+}
+
+pub fn new_audit_entry(time: i64, identity: &ClientIdentity, cmd: &str, options: &Map<String, Value>, result: &io::Result<()>) -> AuditEntry {
+    let mut recorded_options = Value::Object(options.clone());
+    sanitize_value(&mut recorded_options, true);
+    AuditEntry {
+        time,
+        identity: identity.name.clone(),
+        addr: identity.addr.clone(),
+        action: cmd.to_string(),
+        target: get_audit_target(options),
+        result: if result.is_ok() { "success".to_string() } else { "failure".to_string() },
+        message: match result {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string()
+        },
+        options: recorded_options,
+    }
+}
+
+//提交到任务池的命令，任务完成时记录
+pub struct PendingAudit {
+    path: String,
+    identity: ClientIdentity,
+    cmd: String,
+    options: Map<String, Value>,
+}
+
+impl PendingAudit {
+    pub fn new(path: &str, identity: ClientIdentity, cmd: &str, options: &Map<String, Value>) -> PendingAudit {
+        PendingAudit {
+            path: path.to_string(),
+            identity,
+            cmd: cmd.to_string(),
+            options: options.clone(),
+        }
+    }
+
+    pub fn finish<T>(self, time: i64, result: &io::Result<T>) {
+        let result = result.as_ref().map(|_| ()).map_err(|e| io::Error::new(e.kind(), e.to_string()));
+        let entry = new_audit_entry(time, &self.identity, &self.cmd, &self.options, &result);
+        if let Err(e) = append_audit_entry(&self.path, &entry) {
+            println!("write audit log failed: {}, err: {}", self.path, e);
+        }
+    }
+}
+
+pub fn append_audit_entry(path: &str, entry: &AuditEntry) -> io::Result<()> {
+    let _lock = AUDIT_LOCK.lock().unwrap();
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())
+}
+
+//跳过无法解析的行(如写入中断的最后一行)
+pub fn load_audit_entries(path: &str, query: &AuditQuery) -> io::Result<Vec<AuditEntry>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) {
+            if query.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,236 @@
+
+//基线：把一个取样(及时间范围)登记为某个应用标签的基线，之后的会话可以与基线比较，找出变慢的热点方法
+//登记表保存在录制输出目录下
+//  <samples_root>/baselines.json
+//不同取样的方法ID不同，按方法名称比较栈顶取样占比(百分点)，总CPU按每秒CPU时间比较(百分比)
+
+use ::sample::*;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use metrics_export::get_method_stats;
+use utils::new_invalid_input_error;
+
+pub const BASELINES_FILE: &str = "baselines.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Baseline {
+    pub app_tag: String,
+    pub sample_data_dir: String,
+    //-1表示整个取样
+    pub start_time: i64,
+    pub end_time: i64,
+    #[serde(default)]
+    pub description: String,
+    pub marked_time: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompareOptions {
+    //热点方法栈顶占比增加的百分点
+    pub threshold_percent: f64,
+    //总CPU(每秒CPU时间)增加的百分比，与方法占比的单位不同，分开设置
+    pub cpu_threshold_percent: f64,
+    //栈顶取样数少于此值的方法不比较
+    pub min_samples: i64,
+    pub limit: usize,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        CompareOptions {
+            threshold_percent: 5.0,
+            cpu_threshold_percent: 5.0,
+            min_samples: 10,
+            limit: 50,
+        }
+    }
+}
+
+//一个取样的栈顶方法分布
+#[derive(Clone, Debug, Default)]
+pub struct MethodProfile {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub samples: i64,
+    //ns
+    pub cpu_time: i64,
+    pub self_samples: HashMap<String, i64>,
+}
+
+impl MethodProfile {
+    //每秒的CPU时间(ms)
+    pub fn cpu_ms_per_sec(&self) -> f64 {
+        let seconds = (self.end_time - self.start_time).max(1) as f64 / 1000.0;
+        self.cpu_time as f64 / 1_000_000.0 / seconds
+    }
+
+    pub fn get_share(&self, method_name: &str) -> f64 {
+        let samples = self.self_samples.get(method_name).cloned().unwrap_or(0);
+        samples as f64 * 100.0 / self.samples.max(1) as f64
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct MethodChange {
+    pub method_name: String,
+    pub baseline_samples: i64,
+    pub candidate_samples: i64,
+    //栈顶取样占比(%)
+    pub baseline_share: f64,
+    pub candidate_share: f64,
+    //百分点
+    pub change: f64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SampleComparison {
+    pub threshold_percent: f64,
+    pub cpu_threshold_percent: f64,
+    pub baseline_samples: i64,
+    pub candidate_samples: i64,
+    pub baseline_cpu_ms_per_sec: f64,
+    pub candidate_cpu_ms_per_sec: f64,
+    //总CPU变化的百分比
+    pub cpu_change_percent: f64,
+    pub cpu_regressed: bool,
+    //按变化从大到小排列
+    pub regressions: Vec<MethodChange>,
+    pub improvements: Vec<MethodChange>,
+}
+
+impl SampleComparison {
+    pub fn is_regressed(&self) -> bool {
+        self.cpu_regressed || !self.regressions.is_empty()
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+pub fn load_baselines(samples_root: &str) -> io::Result<Vec<Baseline>> {
+    let path = format!("{}/{}", samples_root, BASELINES_FILE);
+    if std::fs::metadata(&path).is_err() {
+        return Ok(vec![]);
+    }
+    let json = std::fs::read_to_string(path)?;
+    let baselines = serde_json::from_str::<Vec<Baseline>>(&json)?;
+    Ok(baselines)
+}
+
+pub fn save_baselines(samples_root: &str, baselines: &[Baseline]) -> io::Result<()> {
+    std::fs::create_dir_all(samples_root)?;
+    let path = format!("{}/{}", samples_root, BASELINES_FILE);
+    let json = serde_json::to_string_pretty(baselines)?;
+    std::fs::write(path, json.as_bytes())
+}
+
+//每个应用标签只有一个基线，重新登记时替换
+pub fn set_baseline(samples_root: &str, baseline: Baseline) -> io::Result<Baseline> {
+    if baseline.app_tag.trim().is_empty() {
+        return Err(new_invalid_input_error("app tag is empty"));
+    }
+    let mut baselines = load_baselines(samples_root)?;
+    baselines.retain(|x| x.app_tag != baseline.app_tag);
+    baselines.push(baseline.clone());
+    baselines.sort_by(|a, b| a.app_tag.cmp(&b.app_tag));
+    save_baselines(samples_root, &baselines)?;
+    Ok(baseline)
+}
+
+pub fn get_baseline(samples_root: &str, app_tag: &str) -> io::Result<Baseline> {
+    match load_baselines(samples_root)?.into_iter().find(|x| x.app_tag == app_tag) {
+        Some(x) => Ok(x),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, format!("baseline not found: {}", app_tag)))
+    }
+}
+
+pub fn get_method_profile(collector: &mut SampleCollector, start_time: i64, end_time: i64) -> io::Result<MethodProfile> {
+    let sample_info = collector.get_sample_info();
+    let start_time = if start_time < 0 { sample_info.record_start_time } else { start_time };
+    let end_time = if end_time < 0 { sample_info.last_record_time + sample_info.sample_interval } else { end_time };
+    let mut profile = MethodProfile { start_time, end_time, ..Default::default() };
+    for (method_id, stats) in get_method_stats(collector, start_time, end_time)? {
+        if stats.self_samples == 0 {
+            continue;
+        }
+        profile.samples += stats.self_samples;
+        profile.cpu_time += stats.self_cpu_time;
+        *profile.self_samples.entry(collector.get_method_name(method_id)).or_insert(0) += stats.self_samples;
+    }
+    Ok(profile)
+}
+
+pub fn compare_profiles(baseline: &MethodProfile, candidate: &MethodProfile, options: &CompareOptions) -> SampleComparison {
+    let mut changes = vec![];
+    let mut names: Vec<&String> = baseline.self_samples.keys().chain(candidate.self_samples.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let baseline_samples = baseline.self_samples.get(name).cloned().unwrap_or(0);
+        let candidate_samples = candidate.self_samples.get(name).cloned().unwrap_or(0);
+        if baseline_samples.max(candidate_samples) < options.min_samples {
+            continue;
+        }
+        let baseline_share = baseline.get_share(name);
+        let candidate_share = candidate.get_share(name);
+        changes.push(MethodChange {
+            method_name: name.clone(),
+            baseline_samples,
+            candidate_samples,
+            baseline_share: round(baseline_share),
+            candidate_share: round(candidate_share),
+            change: round(candidate_share - baseline_share),
+        });
+    }
+    let mut regressions: Vec<MethodChange> = changes.iter().filter(|x| x.change > options.threshold_percent).cloned().collect();
+    regressions.sort_by(|a, b| b.change.partial_cmp(&a.change).unwrap().then(a.method_name.cmp(&b.method_name)));
+    regressions.truncate(options.limit);
+    let mut improvements: Vec<MethodChange> = changes.into_iter().filter(|x| x.change < -options.threshold_percent).collect();
+    improvements.sort_by(|a, b| a.change.partial_cmp(&b.change).unwrap().then(a.method_name.cmp(&b.method_name)));
+    improvements.truncate(options.limit);
+
+    let baseline_cpu = baseline.cpu_ms_per_sec();
+    let candidate_cpu = candidate.cpu_ms_per_sec();
+    let cpu_change_percent = if baseline_cpu > 0.0 { (candidate_cpu - baseline_cpu) * 100.0 / baseline_cpu } else { 0.0 };
+    SampleComparison {
+        threshold_percent: options.threshold_percent,
+        cpu_threshold_percent: options.cpu_threshold_percent,
+        baseline_samples: baseline.samples,
+        candidate_samples: candidate.samples,
+        baseline_cpu_ms_per_sec: round(baseline_cpu),
+        candidate_cpu_ms_per_sec: round(candidate_cpu),
+        cpu_change_percent: round(cpu_change_percent),
+        cpu_regressed: cpu_change_percent > options.cpu_threshold_percent,
+        regressions,
+        improvements,
+    }
+}
+
+//比较两个取样的全部时间范围，用于离线的CI检查
+pub fn compare_samples<P: AsRef<Path>>(baseline_dir: P, candidate_dir: P, options: &CompareOptions) -> io::Result<SampleComparison> {
+    let baseline_profile = {
+        let collector = SampleCollector::open(baseline_dir)?;
+        let mut collector = collector.lock().unwrap();
+        let profile = get_method_profile(&mut collector, -1, -1);
+        collector.close();
+        profile?
+    };
+    let candidate_profile = {
+        let collector = SampleCollector::open(candidate_dir)?;
+        let mut collector = collector.lock().unwrap();
+        let profile = get_method_profile(&mut collector, -1, -1);
+        collector.close();
+        profile?
+    };
+    Ok(compare_profiles(&baseline_profile, &candidate_profile, options))
+}
+
+//"5%" 或者 "5"
+pub fn parse_percent(value: &str) -> io::Result<f64> {
+    match value.trim().trim_end_matches('%').trim().parse::<f64>() {
+        Ok(x) if x >= 0.0 => Ok(x),
+        _ => Err(new_invalid_input_error(&format!("invalid percent: {}", value)))
+    }
+}
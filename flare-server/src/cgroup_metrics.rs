@@ -0,0 +1,223 @@
+
+//目标进程所在cgroup(容器)的资源指标(Linux)，用于发现CFS限流导致的CPU不足
+//  cgroup v2: <root>/<path>/cpu.stat, memory.current, memory.max, cpu.max
+//  cgroup v1: <root>/cpu,cpuacct/<path>/cpu.stat, cpuacct.usage, cpu.cfs_quota_us ...; <root>/memory/<path>/memory.usage_in_bytes ...
+//进程RSS来自 /proc/<pid>/status 的 VmRSS
+
+use std::io;
+use std::io::ErrorKind;
+use flare_utils::timeseries::MetricKind;
+use metric_series::MetricDef;
+use utils::*;
+
+pub const PROC_ROOT: &str = "/proc";
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+pub const CGROUP_METRICS: &[MetricDef] = &[
+    MetricDef { name: "cgroup_cpu_usage", unit: "us", kind: MetricKind::COUNTER, group: "cgroup" },
+    MetricDef { name: "cgroup_nr_throttled", unit: "count", kind: MetricKind::COUNTER, group: "cgroup" },
+    MetricDef { name: "cgroup_throttled_time", unit: "us", kind: MetricKind::COUNTER, group: "cgroup" },
+    MetricDef { name: "cgroup_memory_usage", unit: "bytes", kind: MetricKind::GAUGE, group: "cgroup" },
+    MetricDef { name: "cgroup_memory_limit", unit: "bytes", kind: MetricKind::GAUGE, group: "cgroup" },
+    MetricDef { name: "process_rss", unit: "bytes", kind: MetricKind::GAUGE, group: "cgroup" },
+];
+
+//累计值: cpu_usage_us, nr_periods, nr_throttled, throttled_us; -1表示不限制或者无法读取
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct CgroupMetrics {
+    pub time: i64,
+    pub pid: i64,
+    pub cgroup_version: i32,
+    pub cgroup_path: String,
+    pub cpu_usage_us: i64,
+    pub nr_periods: i64,
+    pub nr_throttled: i64,
+    pub throttled_us: i64,
+    //CFS配额对应的CPU核数
+    pub cpu_limit_cores: f64,
+    pub memory_usage_bytes: i64,
+    pub memory_limit_bytes: i64,
+    pub rss_bytes: i64,
+}
+
+impl CgroupMetrics {
+    pub fn get_metric_value(&self, name: &str) -> i64 {
+        match name {
+            "cgroup_cpu_usage" => self.cpu_usage_us,
+            "cgroup_nr_throttled" => self.nr_throttled,
+            "cgroup_throttled_time" => self.throttled_us,
+            "cgroup_memory_usage" => self.memory_usage_bytes,
+            "cgroup_memory_limit" => self.memory_limit_bytes,
+            "process_rss" => self.rss_bytes,
+            _ => -1
+        }
+    }
+}
+
+pub fn read_cgroup_metrics(pid: i64, time: i64) -> io::Result<CgroupMetrics> {
+    read_cgroup_metrics_from(PROC_ROOT, CGROUP_ROOT, pid, time)
+}
+
+pub fn read_cgroup_metrics_from(proc_root: &str, cgroup_root: &str, pid: i64, time: i64) -> io::Result<CgroupMetrics> {
+    let cgroup_file = std::fs::read_to_string(format!("{}/{}/cgroup", proc_root, pid))?;
+    let mut metrics = CgroupMetrics {
+        time,
+        pid,
+        cpu_limit_cores: -1.0,
+        memory_limit_bytes: -1,
+        rss_bytes: read_process_rss(proc_root, pid).unwrap_or(-1),
+        ..Default::default()
+    };
+
+    //hierarchy-ID:controller-list:cgroup-path
+    let mut v1_cpu_path = None;
+    let mut v1_memory_path = None;
+    let mut v2_path = None;
+    for line in cgroup_file.lines() {
+        let parts: Vec<&str> = line.splitn(3, ':').collect();
+        if parts.len() != 3 {
+            continue;
+        }
+        if parts[0] == "0" && parts[1] == "" {
+            v2_path = Some(parts[2].to_string());
+        }
+        let controllers: Vec<&str> = parts[1].split(',').collect();
+        if controllers.contains(&"cpu") || controllers.contains(&"cpuacct") {
+            v1_cpu_path = Some((parts[1].to_string(), parts[2].to_string()));
+        }
+        if controllers.contains(&"memory") {
+            v1_memory_path = Some(parts[2].to_string());
+        }
+    }
+
+    if let (Some((cpu_controller, cpu_path)), Some(memory_path)) = (&v1_cpu_path, &v1_memory_path) {
+        let cpu_dir = format!("{}/{}{}", cgroup_root, cpu_controller, cpu_path);
+        let memory_dir = format!("{}/memory{}", cgroup_root, memory_path);
+        metrics.cgroup_version = 1;
+        metrics.cgroup_path = cpu_path.clone();
+        let cpu_stat = read_key_values(&format!("{}/cpu.stat", cpu_dir));
+        metrics.nr_periods = get_key_value(&cpu_stat, "nr_periods");
+        metrics.nr_throttled = get_key_value(&cpu_stat, "nr_throttled");
+        metrics.throttled_us = get_key_value(&cpu_stat, "throttled_time") / 1000;
+        metrics.cpu_usage_us = read_int(&format!("{}/cpuacct.usage", cpu_dir)).map(|x| x / 1000).unwrap_or(-1);
+        let quota = read_int(&format!("{}/cpu.cfs_quota_us", cpu_dir)).unwrap_or(-1);
+        let period = read_int(&format!("{}/cpu.cfs_period_us", cpu_dir)).unwrap_or(-1);
+        if quota > 0 && period > 0 {
+            metrics.cpu_limit_cores = quota as f64 / period as f64;
+        }
+        metrics.memory_usage_bytes = read_int(&format!("{}/memory.usage_in_bytes", memory_dir)).unwrap_or(-1);
+        //没有限制时是一个接近i64最大值的数
+        let limit = read_int(&format!("{}/memory.limit_in_bytes", memory_dir)).unwrap_or(-1);
+        metrics.memory_limit_bytes = if limit <= 0 || limit >= 0x7FFF_FFFF_FFFF_F000 { -1 } else { limit };
+    } else if let Some(path) = &v2_path {
+        let dir = format!("{}{}", cgroup_root, path);
+        metrics.cgroup_version = 2;
+        metrics.cgroup_path = path.clone();
+        let cpu_stat = read_key_values(&format!("{}/cpu.stat", dir));
+        metrics.cpu_usage_us = get_key_value(&cpu_stat, "usage_usec");
+        metrics.nr_periods = get_key_value(&cpu_stat, "nr_periods");
+        metrics.nr_throttled = get_key_value(&cpu_stat, "nr_throttled");
+        metrics.throttled_us = get_key_value(&cpu_stat, "throttled_usec");
+        //<quota|max> <period>
+        if let Ok(cpu_max) = std::fs::read_to_string(format!("{}/cpu.max", dir)) {
+            let parts: Vec<&str> = cpu_max.split_whitespace().collect();
+            if parts.len() == 2 {
+                if let (Ok(quota), Ok(period)) = (parts[0].parse::<f64>(), parts[1].parse::<f64>()) {
+                    metrics.cpu_limit_cores = quota / period;
+                }
+            }
+        }
+        metrics.memory_usage_bytes = read_int(&format!("{}/memory.current", dir)).unwrap_or(-1);
+        metrics.memory_limit_bytes = read_int(&format!("{}/memory.max", dir)).unwrap_or(-1);
+    } else {
+        return Err(new_error(ErrorKind::NotFound, &format!("cgroup of process not found: {}", pid)));
+    }
+    Ok(metrics)
+}
+
+fn read_process_rss(proc_root: &str, pid: i64) -> Option<i64> {
+    let status = std::fs::read_to_string(format!("{}/{}/status", proc_root, pid)).ok()?;
+    //VmRSS:     12345 kB
+    let line = status.lines().find(|x| x.starts_with("VmRSS:"))?;
+    let kb = line["VmRSS:".len()..].trim().trim_end_matches("kB").trim().parse::<i64>().ok()?;
+    Some(kb * 1024)
+}
+
+fn read_int(path: &str) -> Option<i64> {
+    std::fs::read_to_string(path).ok()?.trim().parse::<i64>().ok()
+}
+
+fn read_key_values(path: &str) -> Vec<(String, i64)> {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    content.lines().filter_map(|line| {
+        let mut parts = line.split_whitespace();
+        let key = parts.next()?;
+        let value = parts.next()?.parse::<i64>().ok()?;
+        Some((key.to_string(), value))
+    }).collect()
+}
+
+fn get_key_value(values: &[(String, i64)], key: &str) -> i64 {
+    values.iter().find(|x| x.0 == key).map(|x| x.1).unwrap_or(-1)
+}
+
+//查找监听本机端口的进程，用于确定agent所在的目标进程
+pub fn find_pid_by_listen_port(port: u16) -> Option<i64> {
+    let mut inodes = vec![];
+    for file in &["tcp", "tcp6"] {
+        let content = match std::fs::read_to_string(format!("{}/net/{}", PROC_ROOT, file)) {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        //sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != "0A" {
+                continue;
+            }
+            let local_port = fields[1].rsplit(':').next().and_then(|x| u16::from_str_radix(x, 16).ok());
+            if local_port == Some(port) {
+                inodes.push(format!("socket:[{}]", fields[9]));
+            }
+        }
+    }
+    if inodes.is_empty() {
+        return None;
+    }
+    for entry in std::fs::read_dir(PROC_ROOT).ok()? {
+        let entry = match entry {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        let pid = match entry.file_name().to_string_lossy().parse::<i64>() {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        let fds = match std::fs::read_dir(entry.path().join("fd")) {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        for fd in fds.filter_map(|x| x.ok()) {
+            if let Ok(link) = std::fs::read_link(fd.path()) {
+                if inodes.iter().any(|x| link.to_string_lossy() == x.as_str()) {
+                    return Some(pid);
+                }
+            }
+        }
+    }
+    None
+}
+
+//本机的agent地址才能确定目标进程
+pub fn find_agent_pid(agent_addr: &str) -> Option<i64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let pos = agent_addr.rfind(':')?;
+    let host = &agent_addr[..pos];
+    let port = agent_addr[pos + 1..].parse::<u16>().ok()?;
+    match host {
+        "localhost" | "127.0.0.1" | "0.0.0.0" | "[::1]" | "::1" => find_pid_by_listen_port(port),
+        _ => None
+    }
+}
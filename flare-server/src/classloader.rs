@@ -0,0 +1,226 @@
+
+//类加载器泄漏分析：agent定期(agent参数 classloader_interval=<ms>)推送各个类加载器定义的类数量，
+//每个事件追加一行到会话目录下的文件，同一时间的事件组成一次快照
+//  class_loaders.json
+//类数量只增不减的类加载器，以及实例越来越多、类总数只增不减的类加载器类型(重新部署后旧的类加载器无法回收)认为可能泄漏
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use flare_proto::agent::ClassLoaderEvent;
+use ::sample::SampleCollector;
+
+pub const CLASS_LOADER_FILE: &str = "class_loaders.json";
+pub const BOOTSTRAP_LOADER_ID: i64 = 0;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClassLoaderSample {
+    pub time: i64,
+    pub loader_id: i64,
+    pub loader_name: String,
+    pub classes: i64,
+    //类加载器定义第一个类时的调用栈，只在第一次出现时有
+    #[serde(default)]
+    pub stacktrace: Vec<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClassLoaderLeakOptions {
+    //至少出现在几次快照中
+    pub min_snapshots: usize,
+    //类数量的最小增长
+    pub min_growth: i64,
+}
+
+impl Default for ClassLoaderLeakOptions {
+    fn default() -> Self {
+        ClassLoaderLeakOptions {
+            min_snapshots: 3,
+            min_growth: 100,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClassLoaderInfo {
+    pub loader_id: i64,
+    pub loader_name: String,
+    pub classes: i64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClassLoaderLeak {
+    //loader: 单个类加载器，loader_type: 同一类型的所有类加载器
+    pub kind: String,
+    pub loader_name: String,
+    //kind 为 loader_type 时是最新的实例
+    pub loader_id: i64,
+    pub first_time: i64,
+    pub last_time: i64,
+    pub first_instances: usize,
+    pub last_instances: usize,
+    pub first_classes: i64,
+    pub last_classes: i64,
+    pub growth: i64,
+    //类加载器的创建调用栈，栈顶在前，agent没有记录时为空
+    pub defining_stack: Vec<String>,
+}
+
+pub fn new_class_loader_sample(event: &ClassLoaderEvent) -> ClassLoaderSample {
+    ClassLoaderSample {
+        time: event.time,
+        loader_id: event.id,
+        loader_name: event.name.clone(),
+        classes: event.classes,
+        stacktrace: event.stacktrace.clone(),
+    }
+}
+
+pub fn append_class_loader_sample<P: AsRef<Path>>(sample_data_dir: P, sample: &ClassLoaderSample) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(CLASS_LOADER_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    //one sample per line
+    let mut data = serde_json::to_vec(sample)?;
+    data.push(b'\n');
+    file.write_all(&data)
+}
+
+pub fn load_class_loader_samples<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<ClassLoaderSample>> {
+    let path = sample_data_dir.as_ref().join(CLASS_LOADER_FILE);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut samples = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        samples.push(serde_json::from_str::<ClassLoaderSample>(&line)?);
+    }
+    Ok(samples)
+}
+
+//按时间分组的快照，时间从小到大
+fn get_snapshots(collector: &SampleCollector, start_time: i64, end_time: i64) -> BTreeMap<i64, Vec<&ClassLoaderSample>> {
+    let mut snapshots: BTreeMap<i64, Vec<&ClassLoaderSample>> = BTreeMap::new();
+    for sample in collector.get_class_loader_samples() {
+        if sample.time < start_time || (end_time >= 0 && sample.time > end_time) {
+            continue;
+        }
+        snapshots.entry(sample.time).or_insert_with(Vec::new).push(sample);
+    }
+    snapshots
+}
+
+//时间范围内最后一次快照，按类的数量从大到小排列
+pub fn get_latest_class_loaders(collector: &SampleCollector, start_time: i64, end_time: i64) -> Vec<ClassLoaderInfo> {
+    let snapshots = get_snapshots(collector, start_time, end_time);
+    let mut loaders: Vec<ClassLoaderInfo> = match snapshots.values().next_back() {
+        Some(snapshot) => snapshot.iter().map(|x| ClassLoaderInfo {
+            loader_id: x.loader_id,
+            loader_name: x.loader_name.clone(),
+            classes: x.classes,
+        }).collect(),
+        None => vec![]
+    };
+    loaders.sort_by(|a, b| b.classes.cmp(&a.classes).then(a.loader_id.cmp(&b.loader_id)));
+    loaders
+}
+
+fn only_grows(series: &[i64]) -> bool {
+    series.windows(2).all(|x| x[1] >= x[0])
+}
+
+pub fn detect_class_loader_leaks(collector: &mut SampleCollector, start_time: i64, end_time: i64, options: &ClassLoaderLeakOptions) -> Vec<ClassLoaderLeak> {
+    let mut leaks = vec![];
+    let mut defining_stacks: HashMap<i64, Vec<i64>> = HashMap::new();
+    {
+        let snapshots = get_snapshots(collector, start_time, end_time);
+        let last_time = match snapshots.keys().next_back() {
+            Some(time) => *time,
+            None => return vec![]
+        };
+        //创建调用栈可能在时间范围之前上报
+        for sample in collector.get_class_loader_samples() {
+            if !sample.stacktrace.is_empty() {
+                defining_stacks.insert(sample.loader_id, sample.stacktrace.clone());
+            }
+        }
+
+        //单个类加载器：(时间, 类数量)
+        let mut loader_series: HashMap<i64, (String, Vec<(i64, i64)>)> = HashMap::new();
+        //类加载器类型：时间 -> (实例数量, 类数量, 最新的实例)
+        let mut type_series: HashMap<String, BTreeMap<i64, (usize, i64, i64)>> = HashMap::new();
+        for (time, snapshot) in &snapshots {
+            for sample in snapshot {
+                if sample.loader_id == BOOTSTRAP_LOADER_ID {
+                    continue;
+                }
+                loader_series.entry(sample.loader_id).or_insert_with(|| (sample.loader_name.clone(), vec![]))
+                    .1.push((*time, sample.classes));
+                let entry = type_series.entry(sample.loader_name.clone()).or_insert_with(BTreeMap::new)
+                    .entry(*time).or_insert((0, 0, sample.loader_id));
+                entry.0 += 1;
+                entry.1 += sample.classes;
+                if defining_stacks.contains_key(&sample.loader_id) || !defining_stacks.contains_key(&entry.2) {
+                    entry.2 = sample.loader_id;
+                }
+            }
+        }
+
+        for (loader_id, (loader_name, series)) in &loader_series {
+            let (first, last) = (series[0], series[series.len() - 1]);
+            let classes: Vec<i64> = series.iter().map(|x| x.1).collect();
+            //已经回收的类加载器不算泄漏
+            if series.len() < options.min_snapshots || last.0 != last_time || !only_grows(&classes) || last.1 - first.1 < options.min_growth {
+                continue;
+            }
+            leaks.push(ClassLoaderLeak {
+                kind: "loader".to_string(),
+                loader_name: loader_name.clone(),
+                loader_id: *loader_id,
+                first_time: first.0,
+                last_time: last.0,
+                first_instances: 1,
+                last_instances: 1,
+                first_classes: first.1,
+                last_classes: last.1,
+                growth: last.1 - first.1,
+                defining_stack: vec![],
+            });
+        }
+        for (loader_name, series) in &type_series {
+            let (first_time, first) = series.iter().next().map(|(k, v)| (*k, *v)).unwrap();
+            let (last_time, last) = series.iter().next_back().map(|(k, v)| (*k, *v)).unwrap();
+            let classes: Vec<i64> = series.values().map(|x| x.1).collect();
+            if series.len() < options.min_snapshots || last.0 <= first.0 || !only_grows(&classes) || last.1 - first.1 < options.min_growth {
+                continue;
+            }
+            leaks.push(ClassLoaderLeak {
+                kind: "loader_type".to_string(),
+                loader_name: loader_name.clone(),
+                loader_id: last.2,
+                first_time,
+                last_time,
+                first_instances: first.0,
+                last_instances: last.0,
+                first_classes: first.1,
+                last_classes: last.1,
+                growth: last.1 - first.1,
+                defining_stack: vec![],
+            });
+        }
+    }
+    leaks.sort_by(|a, b| b.growth.cmp(&a.growth).then(a.loader_name.cmp(&b.loader_name)).then(a.kind.cmp(&b.kind)));
+    for leak in &mut leaks {
+        if let Some(stacktrace) = defining_stacks.get(&leak.loader_id) {
+            leak.defining_stack = stacktrace.iter().map(|x| collector.get_method_name(*x)).collect();
+        }
+    }
+    leaks
+}
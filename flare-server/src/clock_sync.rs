@@ -0,0 +1,120 @@
+
+//agent与本地的时钟偏差：取样事件的时间来自agent所在主机，连接时及之后定期发送 clock-sync 请求，按NTP方式估算偏差
+//  t0: 请求中的本地时间(client_time)  t1: agent收到请求的时间  t2: agent发送响应的时间  t3: 本地收到响应的时间
+//  offset = ((t0 - t1) + (t3 - t2)) / 2 (本地减去agent)，delay = (t3 - t0) - (t2 - t1)
+//保留最近的几次测量，使用往返延迟最小的一次；开启校正时，事件时间加上偏差转换为本地时间，
+//多个主机的会话合并查看及与trace关联时时间可以对齐。旧版本agent不响应请求，不做校正
+//  第一次同步的响应之前收到的事件先缓冲，确定偏差后再处理，整个会话使用同一个偏差，
+//  之后定期同步只记录当前的偏差(数据质量中的 clock_skew_ms)，事件时间不会随测量跳动
+
+use std::collections::VecDeque;
+use flare_proto::agent::*;
+use resp::Value;
+
+pub const CLOCK_SYNC_REQUEST: &str = "clock-sync";
+
+//保留的测量次数
+const MAX_CLOCK_SAMPLES: usize = 8;
+//等待第一次同步响应的最长时间(ms)及最多缓冲的事件数，超过后不校正
+pub const CLOCK_SYNC_WAIT_MS: i64 = 3000;
+pub const MAX_PENDING_CLOCK_EVENTS: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClockSyncConfig {
+    //定期同步的间隔，0表示只在连接时同步
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: i64,
+    //按估算的偏差校正事件时间
+    #[serde(default = "default_correction")]
+    pub correction: bool,
+}
+
+fn default_interval_secs() -> i64 {
+    60
+}
+
+fn default_correction() -> bool {
+    true
+}
+
+impl Default for ClockSyncConfig {
+    fn default() -> Self {
+        ClockSyncConfig {
+            interval_secs: default_interval_secs(),
+            correction: default_correction(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockSample {
+    //本地减去agent(ms)
+    pub offset: i64,
+    //往返延迟(ms)
+    pub delay: i64,
+}
+
+pub fn new_clock_sync_args(client_time: i64) -> Vec<Value> {
+    vec![Value::String("client_time".to_string()), Value::Integer(client_time)]
+}
+
+pub fn compute_clock_sample(event: &ClockSyncEvent, local_time: i64) -> ClockSample {
+    let offset = ((event.client_time - event.receive_time) + (local_time - event.send_time)) / 2;
+    let delay = (local_time - event.client_time) - (event.send_time - event.receive_time);
+    ClockSample { offset, delay: delay.max(0) }
+}
+
+#[derive(Default)]
+pub struct ClockEstimator {
+    samples: VecDeque<ClockSample>,
+}
+
+impl ClockEstimator {
+    pub fn add_sample(&mut self, sample: ClockSample) {
+        self.samples.push_back(sample);
+        while self.samples.len() > MAX_CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    //最近的测量中往返延迟最小的一次，没有测量时返回None
+    pub fn get_best_sample(&self) -> Option<ClockSample> {
+        self.samples.iter().min_by_key(|x| x.delay).cloned()
+    }
+}
+
+//校正事件时间使用的偏差，偏差在往返延迟的一半以内时无法与测量误差区分(如同一台主机)，不校正
+pub fn get_clock_correction(sample: &ClockSample) -> i64 {
+    if sample.offset.abs() * 2 <= sample.delay {
+        0
+    } else {
+        sample.offset
+    }
+}
+
+//事件中的时间加上偏差
+pub fn correct_event_time(event: &mut AgentEvent, offset: i64) {
+    if offset == 0 {
+        return;
+    }
+    match event {
+        AgentEvent::SampleInfo(x) => {
+            x.start_time += offset;
+            x.last_sample_time += offset;
+        }
+        AgentEvent::Method(_) | AgentEvent::ClockSync(_) | AgentEvent::Hello(_) => {}
+        AgentEvent::Thread(x) => x.time += offset,
+        AgentEvent::Marker(x) => x.time += offset,
+        AgentEvent::IntervalBegin(x) => x.time += offset,
+        AgentEvent::IntervalEnd(x) => x.time += offset,
+        AgentEvent::DeadlockThread(x) => x.time += offset,
+        AgentEvent::ThreadDump(x) => x.time += offset,
+        AgentEvent::HeapHistogram(x) => x.time += offset,
+        AgentEvent::Allocation(x) => x.time += offset,
+        AgentEvent::Finalizer(x) => x.time += offset,
+        AgentEvent::Deoptimization(x) => x.time += offset,
+        AgentEvent::ClassLoader(x) => x.time += offset,
+        AgentEvent::Gc(x) => x.time += offset,
+        AgentEvent::Diagnostic(x) => x.time += offset,
+    }
+}
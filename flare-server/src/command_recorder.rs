@@ -0,0 +1,222 @@
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Write, BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+use websocket::OwnedMessage;
+use websocket::sender::{Writer, Sender};
+use websocket::receiver::Receiver;
+use websocket::ws::Receiver as ReceiverTrait;
+use chrono::Local;
+use profiler::Profiler;
+use shared_stream::SharedStream;
+use utils::*;
+
+//记录的字符串最大长度，超过时截断（如火焰图svg）
+const MAX_RECORDED_STRING_LEN: usize = 256;
+//记录的数组最大长度
+const MAX_RECORDED_ARRAY_LEN: usize = 100;
+//需要隐藏的敏感属性
+const SENSITIVE_KEYS: &[&str] = &["token", "password", "secret", "authorization"];
+
+struct CommandRecorder {
+    file: File,
+    path: String,
+    seq: u64,
+}
+
+lazy_static! {
+    static ref COMMAND_RECORDER: Mutex<Option<CommandRecorder>> = Mutex::new(None);
+}
+
+//开始记录所有收到的命令及发送的响应，每行一个json
+pub fn start_recording(path: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut recorder = COMMAND_RECORDER.lock().unwrap();
+    *recorder = Some(CommandRecorder {
+        file,
+        path: path.to_string(),
+        seq: 0,
+    });
+    println!("recording websocket commands to: {}", path);
+    Ok(())
+}
+
+pub fn stop_recording() {
+    let mut recorder = COMMAND_RECORDER.lock().unwrap();
+    if let Some(recorder) = recorder.take() {
+        println!("stop recording websocket commands: {}, records: {}", recorder.path, recorder.seq);
+    }
+}
+
+pub fn get_recording_file() -> Option<String> {
+    COMMAND_RECORDER.lock().unwrap().as_ref().map(|x| x.path.clone())
+}
+
+//请求只隐藏敏感属性，保证可以回放
+pub fn record_request(client: &str, json_str: &str) {
+    let mut recorder = COMMAND_RECORDER.lock().unwrap();
+    if let Some(recorder) = recorder.as_mut() {
+        let mut data: Value = serde_json::from_str(json_str).unwrap_or_else(|_| json!(json_str));
+        sanitize_value(&mut data, false);
+        write_record(recorder, json!({"type": "request", "client": client, "data": data}));
+    }
+}
+
+//响应数据可能很大，截断长字符串和数组
+pub fn record_response(response_str: &str) {
+    let mut recorder = COMMAND_RECORDER.lock().unwrap();
+    if let Some(recorder) = recorder.as_mut() {
+        let mut data: Value = serde_json::from_str(response_str).unwrap_or_else(|_| json!(response_str));
+        sanitize_value(&mut data, true);
+        write_record(recorder, json!({"type": "response", "cmd": data["cmd"].clone(), "result": data["result"].clone(), "data": data["data"].take()}));
+    }
+}
+
+fn write_record(recorder: &mut CommandRecorder, mut record: Value) {
+    recorder.seq += 1;
+    record["seq"] = json!(recorder.seq);
+    record["time"] = json!(Local::now().timestamp_millis());
+    let mut line = record.to_string();
+    line.push('\n');
+    if let Err(e) = recorder.file.write_all(line.as_bytes()) {
+        println!("write command record failed: {}, err: {}", recorder.path, e);
+    }
+}
+
+pub fn sanitize_value(value: &mut Value, truncate: bool) {
+    match value {
+        Value::Object(map) => {
+            for (key, val) in map.iter_mut() {
+                let lower_key = key.to_lowercase();
+                if SENSITIVE_KEYS.iter().any(|x| lower_key.contains(x)) {
+                    *val = json!("***");
+                } else {
+                    sanitize_value(val, truncate);
+                }
+            }
+        }
+        Value::Array(vec) => {
+            if truncate && vec.len() > MAX_RECORDED_ARRAY_LEN {
+                let total = vec.len();
+                vec.truncate(MAX_RECORDED_ARRAY_LEN);
+                vec.push(json!(format!("...({} items)", total)));
+            }
+            for val in vec.iter_mut() {
+                sanitize_value(val, truncate);
+            }
+        }
+        Value::String(str) => {
+            if truncate && str.len() > MAX_RECORDED_STRING_LEN {
+                let mut end = MAX_RECORDED_STRING_LEN;
+                while !str.is_char_boundary(end) {
+                    end -= 1;
+                }
+                *str = format!("{}...({} bytes)", &str[0..end], str.len());
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ReplayStats {
+    pub requests: usize,
+    pub responses: usize,
+    pub failures: usize,
+    //与记录的响应结果不一致的请求
+    pub mismatches: Vec<String>,
+}
+
+//读取记录文件，将请求重新发送给handle_request，比较响应结果是否与记录一致
+pub fn replay_commands(profiler: &Arc<Mutex<Profiler>>, record_file: &str, wait_ms: u64) -> io::Result<ReplayStats> {
+    //回放时不再记录，避免写入正在读取的文件
+    stop_recording();
+    let file = File::open(record_file)?;
+    let mut records = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str::<Value>(&line)?);
+    }
+
+    //本地回环连接，模拟websocket客户端接收响应
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let client_stream = TcpStream::connect(listener.local_addr()?)?;
+    let (server_stream, _) = listener.accept()?;
+    let mut writer = Writer {
+        stream: SharedStream::new(server_stream),
+        sender: Sender::new(false),
+    };
+    let responses: Arc<Mutex<Vec<(String, String)>>> = Arc::new(Mutex::new(vec![]));
+    let responses_ref = responses.clone();
+    let reader_stream = client_stream.try_clone()?;
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader_stream);
+        let mut receiver = Receiver::new(false);
+        while let Ok(message) = receiver.recv_message(&mut reader) {
+            if let OwnedMessage::Text(text) = message {
+                if let Ok(response) = serde_json::from_str::<Value>(&text) {
+                    let cmd = response["cmd"].as_str().unwrap_or("").to_string();
+                    let result = response["result"].as_str().unwrap_or("").to_string();
+                    println!("replay response: {}, result: {}", cmd, result);
+                    responses_ref.lock().unwrap().push((cmd, result));
+                }
+            }
+        }
+    });
+
+    let mut stats = ReplayStats::default();
+    //按记录顺序，每个请求对应其后第一个相同cmd的响应
+    let mut expected_results = vec![];
+    for (i, record) in records.iter().enumerate() {
+        if record["type"].as_str() != Some("request") {
+            continue;
+        }
+        let request = &record["data"];
+        let cmd = request["cmd"].as_str().unwrap_or("").to_string();
+        let expected = records[i+1..].iter()
+            .find(|x| x["type"].as_str() == Some("response") && x["cmd"].as_str() == Some(&cmd))
+            .and_then(|x| x["result"].as_str())
+            .map(|x| x.to_string());
+        expected_results.push((cmd.clone(), expected));
+
+        stats.requests += 1;
+        let mut out_cmd = String::new();
+        let result = profiler.lock().unwrap().handle_request(&mut writer, request.to_string(), &mut out_cmd);
+        if let Err(e) = result {
+            println!("replay request failed: {}, cmd: {}", e, out_cmd);
+            writer.send_message(&wrap_error_response(&out_cmd, &e.to_string()))
+                .map_err(|e| new_error(io::ErrorKind::Other, &e.to_string()))?;
+        }
+    }
+
+    //等待后台任务的响应
+    thread::sleep(Duration::from_millis(wait_ms));
+    let _ = client_stream.shutdown(std::net::Shutdown::Both);
+
+    let responses = responses.lock().unwrap();
+    stats.responses = responses.len();
+    stats.failures = responses.iter().filter(|x| x.1 != "success").count();
+    let mut used = vec![false; responses.len()];
+    for (cmd, expected) in &expected_results {
+        let found = responses.iter().enumerate().position(|(i, x)| !used[i] && &x.0 == cmd);
+        let actual = match found {
+            Some(pos) => {
+                used[pos] = true;
+                Some(responses[pos].1.clone())
+            }
+            None => None
+        };
+        if expected.is_some() && &actual != expected {
+            stats.mismatches.push(format!("{}: expected {:?}, actual {:?}", cmd, expected, actual));
+        }
+    }
+    Ok(stats)
+}
@@ -0,0 +1,233 @@
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use sample::FLARE_SAMPLES_DIR;
+use webhook::WebhookConfig;
+use plugins::DEFAULT_PLUGINS_DIR;
+use i18n::LANG_EN;
+use rate_limit::RateLimitConfig;
+use access::AccessToken;
+use audit::AUDIT_FILE;
+use clock_sync::ClockSyncConfig;
+use flush_policy::FlushPolicy;
+use disk_guard::DiskGuardConfig;
+use daemon::DaemonConfig;
+use webhook::validate_webhooks;
+use trigger::{TriggerConfig, validate_triggers};
+use symbol_cache::SymbolCacheConfig;
+use plugin_script::ScriptLimits;
+use disk_guard::check_disk_guard_action;
+use utils::new_invalid_input_error;
+
+pub const DEFAULT_CONFIG_FILE: &str = "flare-server.conf";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerConfig {
+    //取样数据保存的根目录，第一个为录制输出目录
+    #[serde(default = "default_samples_roots")]
+    pub samples_roots: Vec<String>,
+    //空闲的取样文件会话自动关闭的时间，0表示不自动关闭
+    #[serde(default = "default_session_idle_timeout_secs")]
+    pub session_idle_timeout_secs: i64,
+    //记录websocket命令及响应的文件，用于回放调试
+    #[serde(default)]
+    pub record_commands_file: Option<String>,
+    //事件通知的webhook列表
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    //录制中的会话满足条件时发送 trigger_fired 通知
+    #[serde(default)]
+    pub triggers: Vec<TriggerConfig>,
+    //录制输出目录所在磁盘可用空间低于此值(MB)时发送通知，0表示不检查
+    #[serde(default)]
+    pub disk_free_threshold_mb: i64,
+    //可用空间低于阈值时对录制中的会话的处理: notify, stop, downsample
+    #[serde(default)]
+    pub disk_guard: DiskGuardConfig,
+    //分析插件目录
+    #[serde(default = "default_plugins_dir")]
+    pub plugins_dir: String,
+    //插件脚本的操作数及内存限制
+    #[serde(default)]
+    pub plugin_script_limits: ScriptLimits,
+    //bcc offcputime 工具路径(如 /usr/share/bcc/tools/offcputime)，为空时不支持off-CPU取样
+    #[serde(default)]
+    pub offcpu_tool: String,
+    //录制期间记录主机CPU、负载、内存、磁盘及网络IO
+    #[serde(default)]
+    pub record_host_metrics: bool,
+    //自动连接目标进程启动的、加载了flare-agent的子JVM
+    #[serde(default)]
+    pub auto_attach_children: bool,
+    //HTML转PDF工具路径(如 wkhtmltopdf)，以 <tool> <input.html> <output.pdf> 方式调用，为空时不支持PDF报告
+    #[serde(default)]
+    pub pdf_tool: String,
+    //问题洞察、报告及错误信息的默认语言: en, zh，请求可以通过参数 lang 指定
+    #[serde(default = "default_language")]
+    pub language: String,
+    //每个连接的命令限流，默认不限制
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    //访问令牌，为空时不检查
+    #[serde(default)]
+    pub access_tokens: Vec<AccessToken>,
+    //审计日志文件，为空时保存在录制输出目录下
+    #[serde(default)]
+    pub audit_log_file: String,
+    //只读模式，拒绝连接目标进程、录制、修改配置及写入文件的命令
+    #[serde(default)]
+    pub read_only: bool,
+    //与agent的时钟同步及事件时间校正
+    #[serde(default)]
+    pub clock_sync: ClockSyncConfig,
+    //录制数据的默认刷新策略，可以按会话修改
+    #[serde(default)]
+    pub flush_policy: FlushPolicy,
+    //常驻运行模式(--daemon)的pid文件及日志
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    //允许跨域访问Grafana数据源接口的来源(如 "https://grafana.example.com")，为空时不允许跨域访问，"*" 允许任意来源
+    #[serde(default)]
+    pub grafana_cors_origins: Vec<String>,
+    //打开取样时使用的方法名称缓存
+    #[serde(default)]
+    pub symbol_cache: SymbolCacheConfig,
+}
+
+fn default_samples_roots() -> Vec<String> {
+    vec![FLARE_SAMPLES_DIR.to_string()]
+}
+
+fn default_plugins_dir() -> String {
+    DEFAULT_PLUGINS_DIR.to_string()
+}
+
+fn default_language() -> String {
+    LANG_EN.to_string()
+}
+
+fn default_session_idle_timeout_secs() -> i64 {
+    1800
+}
+
+impl ServerConfig {
+
+    pub fn read_config() -> ServerConfig {
+        match ServerConfig::read_from_file(DEFAULT_CONFIG_FILE) {
+            Ok(Some(config)) => config,
+            Ok(None) => ServerConfig::default(),
+            Err(e) => {
+                println!("read config file failed: {}, err: {}", DEFAULT_CONFIG_FILE, e);
+                ServerConfig::default()
+            }
+        }
+    }
+
+    pub fn read_from_file<T: AsRef<Path>>(file_name: T) -> io::Result<Option<ServerConfig>> {
+        match File::open(file_name) {
+            Ok(mut file) => {
+                let mut contents = String::new();
+                file.read_to_string(&mut contents)?;
+                match toml::from_str::<ServerConfig>(contents.as_str()) {
+                    Ok(mut config) => {
+                        if config.samples_roots.is_empty() {
+                            config.samples_roots = default_samples_roots();
+                        }
+                        Ok(Some(config))
+                    },
+                    Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    //重新加载时读取配置文件，文件无效或者配置项无效时返回错误，保持当前的配置不变
+    pub fn read_for_reload() -> io::Result<ServerConfig> {
+        let config = ServerConfig::read_from_file(DEFAULT_CONFIG_FILE)?.unwrap_or_default();
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        validate_webhooks(&self.webhooks)?;
+        validate_triggers(&self.triggers)?;
+        check_disk_guard_action(&self.disk_guard.action)?;
+        self.plugin_script_limits.validate()?;
+        if self.symbol_cache.max_entries == 0 {
+            return Err(new_invalid_input_error("symbol_cache.max_entries must be greater than 0"));
+        }
+        Ok(())
+    }
+
+    pub fn save_to_file<T: AsRef<Path>>(&self, file_name: T) -> io::Result<()> {
+        match toml::to_string(self) {
+            Ok(contents) => std::fs::write(file_name, contents.as_bytes()),
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
+        }
+    }
+
+    //录制输出目录
+    pub fn get_primary_samples_root(&self) -> &str {
+        &self.samples_roots[0]
+    }
+
+    pub fn get_audit_log_file(&self) -> String {
+        if self.audit_log_file.is_empty() {
+            format!("{}/{}", self.get_primary_samples_root(), AUDIT_FILE)
+        } else {
+            self.audit_log_file.clone()
+        }
+    }
+}
+
+impl Default for ServerConfig {
+
+    fn default() -> Self {
+        ServerConfig {
+            samples_roots: default_samples_roots(),
+            session_idle_timeout_secs: default_session_idle_timeout_secs(),
+            record_commands_file: None,
+            webhooks: vec![],
+            triggers: vec![],
+            disk_free_threshold_mb: 0,
+            disk_guard: DiskGuardConfig::default(),
+            plugins_dir: default_plugins_dir(),
+            plugin_script_limits: ScriptLimits::default(),
+            offcpu_tool: String::new(),
+            record_host_metrics: false,
+            auto_attach_children: false,
+            pdf_tool: String::new(),
+            language: default_language(),
+            rate_limit: RateLimitConfig::default(),
+            access_tokens: vec![],
+            audit_log_file: String::new(),
+            read_only: false,
+            clock_sync: ClockSyncConfig::default(),
+            flush_policy: FlushPolicy::default(),
+            daemon: DaemonConfig::default(),
+            grafana_cors_origins: vec![],
+            symbol_cache: SymbolCacheConfig::default(),
+        }
+    }
+}
+
+//两个配置中不同的顶层配置项，如 samples_roots、access_tokens、daemon，不包含配置的值(令牌不出现在结果中)
+pub fn diff_config(old: &ServerConfig, new: &ServerConfig) -> Vec<String> {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    let (old, new) = match (old.as_object(), new.as_object()) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return vec![]
+    };
+    let mut keys: Vec<String> = old.keys().chain(new.keys())
+        .filter(|x| old.get(*x) != new.get(*x))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
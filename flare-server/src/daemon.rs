@@ -0,0 +1,325 @@
+//常驻运行模式，用于长时间的持续取样部署
+//  flare_server --daemon: 写入pid文件，输出重定向到日志文件并按大小轮转；
+//    收到 SIGTERM/SIGINT 时关闭所有会话(写入的数据保存完整)后退出，SIGHUP 重新加载配置文件并重新打开日志文件(配合 logrotate)
+//  flare_server service <systemd|windows> [--user <user>] [--output <file>]: 生成服务定义
+//    systemd: unit 文件，放到 /etc/systemd/system/flare-server.service 后 systemctl enable --now flare-server
+//    windows: WinSW 服务定义，与改名为 flare-server.exe 的 WinSW 放在同一目录后执行 flare-server.exe install
+//      服务控制管理器的停止请求由 WinSW 转换为 Ctrl+C，日志由 WinSW 按大小轮转
+//配置保存在 flare-server.conf 的 [daemon] 中
+
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use utils::*;
+use config::ServerConfig;
+
+pub const SERVICE_SYSTEMD: &str = "systemd";
+pub const SERVICE_WINDOWS: &str = "windows";
+pub const SERVICE_NAME: &str = "flare-server";
+
+//收到的信号，0表示没有
+static RECEIVED_SIGNAL: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DaemonConfig {
+    #[serde(default = "default_pid_file")]
+    pub pid_file: String,
+    #[serde(default = "default_log_file")]
+    pub log_file: String,
+    //日志文件超过此大小(MB)时轮转，0表示不轮转
+    #[serde(default = "default_log_max_size_mb")]
+    pub log_max_size_mb: i64,
+    //保留的轮转日志文件数量: flare-server.log.1 ... flare-server.log.N
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: i64,
+}
+
+fn default_pid_file() -> String {
+    "flare-server.pid".to_string()
+}
+
+fn default_log_file() -> String {
+    "logs/flare-server.log".to_string()
+}
+
+fn default_log_max_size_mb() -> i64 {
+    50
+}
+
+fn default_log_max_files() -> i64 {
+    5
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            pid_file: default_pid_file(),
+            log_file: default_log_file(),
+            log_max_size_mb: default_log_max_size_mb(),
+            log_max_files: default_log_max_files(),
+        }
+    }
+}
+
+//flare-server.conf 中的 [daemon]
+pub fn read_daemon_config() -> DaemonConfig {
+    ServerConfig::read_config().daemon
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DaemonEvent {
+    Shutdown,
+    Reload,
+}
+
+//pid文件，drop时删除
+pub struct PidFile {
+    path: String,
+}
+
+impl PidFile {
+    //pid文件中的进程还在运行时返回 AlreadyExists，进程已经退出的旧文件直接覆盖
+    pub fn create(path: &str) -> io::Result<PidFile> {
+        if let Ok(content) = std::fs::read_to_string(path) {
+            if let Ok(pid) = content.trim().parse::<u32>() {
+                if pid != std::process::id() && is_process_alive(pid) {
+                    return Err(new_error(ErrorKind::AlreadyExists, &format!("flare server is already running, pid: {}, pid file: {}", pid, path)));
+                }
+            }
+        }
+        create_parent_dir(path)?;
+        std::fs::write(path, format!("{}\n", std::process::id()))?;
+        Ok(PidFile { path: path.to_string() })
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+//按大小轮转的日志文件，标准输出及标准错误重定向到此文件
+pub struct LogFile {
+    path: String,
+    max_size: u64,
+    max_files: i64,
+}
+
+impl LogFile {
+    pub fn open(path: &str, max_size_mb: i64, max_files: i64) -> io::Result<LogFile> {
+        create_parent_dir(path)?;
+        let log_file = LogFile {
+            path: path.to_string(),
+            max_size: max_size_mb.max(0) as u64 * 1024 * 1024,
+            max_files,
+        };
+        log_file.reopen()?;
+        Ok(log_file)
+    }
+
+    //超过大小时轮转，返回是否轮转了
+    pub fn rotate_if_needed(&self) -> io::Result<bool> {
+        if self.max_size == 0 {
+            return Ok(false);
+        }
+        let size = std::fs::metadata(&self.path).map(|x| x.len()).unwrap_or(0);
+        if size < self.max_size {
+            return Ok(false);
+        }
+        rotate_log_files(&self.path, self.max_files)?;
+        self.reopen()?;
+        Ok(true)
+    }
+
+    //重新打开日志文件，如被 logrotate 移走之后
+    pub fn reopen(&self) -> io::Result<()> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        redirect_output(&file)
+    }
+}
+
+//path.N-1 -> path.N ... path -> path.1，超过 max_files 的删除
+pub fn rotate_log_files(path: &str, max_files: i64) -> io::Result<()> {
+    if max_files <= 0 {
+        return match std::fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(())
+        };
+    }
+    let _ = std::fs::remove_file(format!("{}.{}", path, max_files));
+    for i in (1..max_files).rev() {
+        let from = format!("{}.{}", path, i);
+        if Path::new(&from).exists() {
+            std::fs::rename(&from, format!("{}.{}", path, i + 1))?;
+        }
+    }
+    if Path::new(path).exists() {
+        std::fs::rename(path, format!("{}.1", path))?;
+    }
+    Ok(())
+}
+
+//注册退出及重新加载配置的信号
+pub fn install_signal_handlers() {
+    unsafe {
+        libc::signal(libc::SIGTERM, signal_handler());
+        libc::signal(libc::SIGINT, signal_handler());
+        #[cfg(unix)]
+        libc::signal(libc::SIGHUP, signal_handler());
+    }
+}
+
+//前台运行时也可以用 SIGHUP 重新加载配置，退出信号保持默认处理
+pub fn install_reload_handler() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGHUP, signal_handler());
+    }
+}
+
+fn signal_handler() -> libc::sighandler_t {
+    on_signal as *const () as libc::sighandler_t
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    //Windows上处理信号之后恢复为默认处理，需要重新注册
+    #[cfg(windows)]
+    unsafe {
+        libc::signal(signal, signal_handler());
+    }
+    RECEIVED_SIGNAL.store(signal as usize, Ordering::SeqCst);
+}
+
+//取出收到的信号对应的事件
+pub fn take_signal_event() -> Option<DaemonEvent> {
+    match RECEIVED_SIGNAL.swap(0, Ordering::SeqCst) {
+        0 => None,
+        #[cfg(unix)]
+        x if x == libc::SIGHUP as usize => Some(DaemonEvent::Reload),
+        _ => Some(DaemonEvent::Shutdown)
+    }
+}
+
+pub struct ServiceOptions {
+    pub exe_path: String,
+    pub work_dir: String,
+    pub user: Option<String>,
+}
+
+pub fn generate_service_definition(service_type: &str, config: &DaemonConfig, options: &ServiceOptions) -> io::Result<String> {
+    match service_type {
+        SERVICE_SYSTEMD => Ok(generate_systemd_unit(config, options)),
+        SERVICE_WINDOWS => Ok(generate_winsw_service(config, options)),
+        _ => Err(new_invalid_input_error(&format!("unknown service type: {}, expect one of {}, {}", service_type, SERVICE_SYSTEMD, SERVICE_WINDOWS)))
+    }
+}
+
+//前台运行(Type=simple)，由 systemd 负责重启，logrotate 轮转时发送 SIGHUP
+fn generate_systemd_unit(config: &DaemonConfig, options: &ServiceOptions) -> String {
+    let mut unit = String::new();
+    unit.push_str("[Unit]\n");
+    unit.push_str("Description=Flare profiler analysis server\n");
+    unit.push_str("After=network.target\n\n");
+    unit.push_str("[Service]\n");
+    unit.push_str("Type=simple\n");
+    if let Some(user) = &options.user {
+        unit.push_str(&format!("User={}\n", user));
+    }
+    unit.push_str(&format!("WorkingDirectory={}\n", options.work_dir));
+    unit.push_str(&format!("ExecStart={} --daemon\n", quote_systemd_arg(&options.exe_path)));
+    unit.push_str("ExecReload=/bin/kill -HUP $MAINPID\n");
+    unit.push_str(&format!("PIDFile={}\n", resolve_path(&options.work_dir, &config.pid_file)));
+    unit.push_str("KillSignal=SIGTERM\n");
+    unit.push_str("TimeoutStopSec=30\n");
+    unit.push_str("Restart=on-failure\n");
+    unit.push_str("RestartSec=5\n");
+    unit.push_str("LimitNOFILE=65536\n\n");
+    unit.push_str("[Install]\n");
+    unit.push_str("WantedBy=multi-user.target\n");
+    unit
+}
+
+fn generate_winsw_service(config: &DaemonConfig, options: &ServiceOptions) -> String {
+    let mut xml = String::new();
+    xml.push_str("<service>\n");
+    xml.push_str(&format!("  <id>{}</id>\n", SERVICE_NAME));
+    xml.push_str("  <name>Flare Profiler Server</name>\n");
+    xml.push_str("  <description>Flare profiler analysis server</description>\n");
+    xml.push_str(&format!("  <executable>{}</executable>\n", escape_xml(&options.exe_path)));
+    xml.push_str("  <arguments>--daemon</arguments>\n");
+    xml.push_str(&format!("  <workingdirectory>{}</workingdirectory>\n", escape_xml(&options.work_dir)));
+    if let Some(user) = &options.user {
+        xml.push_str(&format!("  <serviceaccount>\n    <username>{}</username>\n    <allowservicelogon>true</allowservicelogon>\n  </serviceaccount>\n", escape_xml(user)));
+    }
+    xml.push_str("  <startmode>Automatic</startmode>\n");
+    xml.push_str("  <stoptimeout>30 sec</stoptimeout>\n");
+    xml.push_str("  <onfailure action=\"restart\" delay=\"5 sec\"/>\n");
+    xml.push_str("  <log mode=\"roll-by-size\">\n");
+    xml.push_str(&format!("    <sizeThreshold>{}</sizeThreshold>\n", config.log_max_size_mb.max(1) * 1024));
+    xml.push_str(&format!("    <keepFiles>{}</keepFiles>\n", config.log_max_files.max(1)));
+    xml.push_str("  </log>\n");
+    xml.push_str("</service>\n");
+    xml
+}
+
+fn resolve_path(work_dir: &str, path: &str) -> String {
+    if Path::new(path).is_absolute() {
+        path.to_string()
+    } else {
+        format!("{}/{}", work_dir.trim_end_matches('/'), path)
+    }
+}
+
+fn quote_systemd_arg(arg: &str) -> String {
+    if arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn create_parent_dir(path: &str) -> io::Result<()> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir),
+        _ => Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn is_process_alive(pid: u32) -> bool {
+    //EPERM 表示进程存在但属于其它用户
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM) }
+}
+
+#[cfg(not(unix))]
+fn is_process_alive(_pid: u32) -> bool {
+    //Windows上由服务控制管理器保证只有一个实例，旧的pid文件直接覆盖
+    false
+}
+
+#[cfg(unix)]
+fn redirect_output(file: &std::fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    unsafe {
+        if libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO) < 0 || libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn redirect_output(_file: &std::fs::File) -> io::Result<()> {
+    //Windows服务的输出由 WinSW 保存
+    Ok(())
+}
@@ -0,0 +1,88 @@
+
+//取样的数据质量指标，录制时累计并随 sample_info 保存，用户据此判断火焰图等结果的可信程度
+//  sample_rounds:    不同的取样时间点数量(agent每轮取样的线程使用同一个时间)
+//  avg/max_jitter_ms: 相邻两轮取样的间隔与取样间隔整数倍的偏差，agent不发送没有变化的线程，间隔可能是取样间隔的整数倍
+//  dropped_samples:  agent发送队列满时丢弃的事件数量(诊断信息 dropped_events)
+//  truncated_stacks: 达到agent最大栈深度或者被写入过滤(max_depth)截断的调用栈数量
+//  clock_skew_ms:    本地时间减去agent时间的估算，有clock-sync测量(clock_sync_samples>0)时使用测量的结果，
+//                    否则取接收时差值的最小值，包含网络延迟；没有实时接收数据时为null
+//  clock_correction_ms: 最近的事件时间校正的偏差，0表示没有校正
+//  clock_jumps:      写入时检测到的时间倒退次数，之后的事件时间被平移
+
+pub const KIND_DROPPED_EVENTS: &str = "dropped_events";
+
+//agent GetAllStackTraces 的最大栈帧数
+pub const AGENT_MAX_STACK_DEPTH: usize = 2000;
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct DataQuality {
+    pub sample_rounds: i64,
+    pub avg_jitter_ms: f64,
+    pub max_jitter_ms: i64,
+    pub total_jitter_ms: i64,
+    pub dropped_samples: i64,
+    pub truncated_stacks: i64,
+    #[serde(default)]
+    pub clock_skew_ms: Option<i64>,
+    #[serde(default)]
+    pub clock_sync_samples: i64,
+    #[serde(default)]
+    pub clock_correction_ms: i64,
+    #[serde(default)]
+    pub clock_jumps: i64,
+    //上一轮取样的时间，不保存
+    #[serde(skip)]
+    last_round_time: i64,
+}
+
+impl DataQuality {
+    pub fn on_sample_time(&mut self, time: i64, sample_interval: i64) {
+        if time == self.last_round_time {
+            return;
+        }
+        //乱序的取样不计算偏差
+        if self.last_round_time > 0 && time > self.last_round_time && sample_interval > 0 {
+            let delta = time - self.last_round_time;
+            let rounds = ((delta as f64 / sample_interval as f64).round() as i64).max(1);
+            let jitter = (delta - rounds * sample_interval).abs();
+            self.total_jitter_ms += jitter;
+            self.max_jitter_ms = self.max_jitter_ms.max(jitter);
+        }
+        if time > self.last_round_time {
+            self.last_round_time = time;
+        }
+        self.sample_rounds += 1;
+        if self.sample_rounds > 1 {
+            self.avg_jitter_ms = (self.total_jitter_ms as f64 / (self.sample_rounds - 1) as f64 * 100.0).round() / 100.0;
+        }
+    }
+
+    //stack_len 为写入过滤之前的栈深度
+    pub fn on_stack(&mut self, stack_len: usize, filtered_len: usize) {
+        if stack_len >= AGENT_MAX_STACK_DEPTH || filtered_len < stack_len {
+            self.truncated_stacks += 1;
+        }
+    }
+
+    //实时接收agent数据时估算时钟偏差
+    pub fn on_receive(&mut self, agent_time: i64, local_time: i64) {
+        if self.clock_sync_samples > 0 {
+            return;
+        }
+        let skew = local_time - agent_time;
+        self.clock_skew_ms = Some(match self.clock_skew_ms {
+            Some(x) => x.min(skew),
+            None => skew
+        });
+    }
+
+    pub fn on_clock_sync(&mut self, skew: i64, correction: i64) {
+        self.clock_skew_ms = Some(skew);
+        self.clock_sync_samples += 1;
+        self.clock_correction_ms = correction;
+    }
+
+    pub fn on_dropped(&mut self, count: i64) {
+        self.dropped_samples += count;
+    }
+}
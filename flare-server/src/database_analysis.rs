@@ -0,0 +1,147 @@
+
+use ::sample::*;
+use std::collections::HashMap;
+
+//常见的JDBC驱动及连接池的类名前缀
+pub const JDBC_FRAME_PATTERNS: &[&str] = &[
+    "java.sql.",
+    "javax.sql.",
+    "com.mysql.",
+    "org.mariadb.jdbc.",
+    "org.postgresql.",
+    "oracle.jdbc.",
+    "com.microsoft.sqlserver.jdbc.",
+    "org.h2.",
+    "org.hsqldb.",
+    "org.sqlite.",
+    "com.ibm.db2.",
+    "com.zaxxer.hikari.",
+    "com.alibaba.druid.",
+    "org.apache.commons.dbcp",
+    "com.mchange.v2.c3p0.",
+];
+
+pub fn is_jdbc_frame(method_name: &str) -> bool {
+    JDBC_FRAME_PATTERNS.iter().any(|pattern| method_name.starts_with(pattern))
+}
+
+#[derive(Clone, Serialize)]
+pub struct DatabaseCallerStats {
+    pub method_id: i64,
+    pub full_name: String,
+    pub duration: i64,
+    pub cpu_time: i64,
+    pub samples: i64,
+    //调用的第一个驱动方法
+    pub driver_methods: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DatabaseTimeResult {
+    pub total_duration: i64,
+    pub total_cpu_time: i64,
+    pub total_samples: i64,
+    pub database_duration: i64,
+    pub database_cpu_time: i64,
+    pub database_samples: i64,
+    pub callers: Vec<DatabaseCallerStats>,
+}
+
+pub struct DatabaseAnalysis {
+    //method_id -> 是否为JDBC方法
+    jdbc_method_cache: HashMap<i64, bool>,
+    callers: HashMap<i64, DatabaseCallerStats>,
+    total_duration: i64,
+    total_cpu_time: i64,
+    total_samples: i64,
+    database_duration: i64,
+    database_cpu_time: i64,
+    database_samples: i64,
+}
+
+impl DatabaseAnalysis {
+
+    pub fn new() -> DatabaseAnalysis {
+        DatabaseAnalysis {
+            jdbc_method_cache: HashMap::new(),
+            callers: HashMap::new(),
+            total_duration: 0,
+            total_cpu_time: 0,
+            total_samples: 0,
+            database_duration: 0,
+            database_cpu_time: 0,
+            database_samples: 0,
+        }
+    }
+
+    pub fn add_samples(&mut self, collector: &mut SampleCollector, thread_data_vec: &Vec<ThreadData>) {
+        for thread_data in thread_data_vec {
+            //cpu_time_delta: nanos -> micros
+            let cpu_time = thread_data.cpu_time_delta / 1000;
+            self.total_duration += thread_data.self_duration;
+            self.total_cpu_time += cpu_time;
+            self.total_samples += 1;
+
+            //从栈底(root)向栈顶查找第一个JDBC方法，其上一层即为调用的业务方法
+            let mut caller: Option<i64> = None;
+            let mut driver_method: Option<i64> = None;
+            for method_id in thread_data.stacktrace.iter().rev() {
+                if self.is_jdbc_method(collector, *method_id) {
+                    driver_method = Some(*method_id);
+                    break;
+                }
+                caller = Some(*method_id);
+            }
+            if driver_method.is_none() {
+                continue;
+            }
+            self.database_duration += thread_data.self_duration;
+            self.database_cpu_time += cpu_time;
+            self.database_samples += 1;
+
+            //JDBC方法在栈底时没有业务调用方，使用0表示
+            let caller_id = caller.unwrap_or(0);
+            let driver_name = collector.get_method_name(driver_method.unwrap());
+            let stats = self.callers.entry(caller_id).or_insert_with(|| {
+                let full_name = if caller_id == 0 { "<root>".to_string() } else { collector.get_method_name(caller_id) };
+                DatabaseCallerStats {
+                    method_id: caller_id,
+                    full_name,
+                    duration: 0,
+                    cpu_time: 0,
+                    samples: 0,
+                    driver_methods: vec![],
+                }
+            });
+            stats.duration += thread_data.self_duration;
+            stats.cpu_time += cpu_time;
+            stats.samples += 1;
+            if !stats.driver_methods.contains(&driver_name) {
+                stats.driver_methods.push(driver_name);
+            }
+        }
+    }
+
+    fn is_jdbc_method(&mut self, collector: &mut SampleCollector, method_id: i64) -> bool {
+        if let Some(val) = self.jdbc_method_cache.get(&method_id) {
+            return *val;
+        }
+        let val = is_jdbc_frame(&collector.get_method_name(method_id));
+        self.jdbc_method_cache.insert(method_id, val);
+        val
+    }
+
+    pub fn get_result(&self) -> DatabaseTimeResult {
+        let mut callers: Vec<DatabaseCallerStats> = self.callers.values().cloned().collect();
+        callers.sort_by(|a, b| b.duration.cmp(&a.duration));
+        DatabaseTimeResult {
+            total_duration: self.total_duration,
+            total_cpu_time: self.total_cpu_time,
+            total_samples: self.total_samples,
+            database_duration: self.database_duration,
+            database_cpu_time: self.database_cpu_time,
+            database_samples: self.database_samples,
+            callers,
+        }
+    }
+}
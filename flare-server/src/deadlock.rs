@@ -0,0 +1,83 @@
+
+//死锁检测快照：agent通过JVMTI(GetCurrentContendedMonitor/GetObjectMonitorUsage)构造线程等待图，
+//定期(agent参数 deadlock_interval=<ms>)或者按需(detect-deadlocks请求)检测环路，
+//每个死锁线程作为一个deadlock_thread事件推送，在会话中按检测时间及环序号合并后保存
+//  只能检测synchronized监视器的死锁，java.util.concurrent的锁不在JVMTI监视器信息中
+
+use std::io;
+use std::path::Path;
+use flare_proto::agent::DeadlockThreadEvent;
+use ::sample::SampleCollector;
+
+pub const DEADLOCK_FILE: &str = "deadlocks.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeadlockThread {
+    pub thread_id: i64,
+    pub thread_name: String,
+    pub state: String,
+    //等待的监视器类名
+    pub lock: String,
+    //持有该监视器的线程
+    pub owner_id: i64,
+    //方法ID，栈顶在前
+    pub stacktrace: Vec<i64>,
+    //查询时填充的方法名称
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<String>,
+}
+
+//一次检测到的死锁环
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeadlockCycle {
+    pub time: i64,
+    pub cycle: i64,
+    pub threads: Vec<DeadlockThread>,
+}
+
+impl DeadlockCycle {
+    //同一个环的线程事件连续发送
+    pub fn accept(&self, event: &DeadlockThreadEvent) -> bool {
+        self.time == event.time && self.cycle == event.cycle
+    }
+}
+
+pub fn new_deadlock_thread(event: &DeadlockThreadEvent) -> DeadlockThread {
+    DeadlockThread {
+        thread_id: event.id,
+        thread_name: event.name.clone(),
+        state: event.state.clone(),
+        lock: event.lock.clone(),
+        owner_id: event.owner_id,
+        stacktrace: event.stacktrace.clone(),
+        frames: vec![],
+    }
+}
+
+//时间范围内检测到的死锁，填充调用栈的方法名称
+pub fn get_deadlocks(collector: &mut SampleCollector, start_time: i64, end_time: i64) -> Vec<DeadlockCycle> {
+    let mut cycles: Vec<DeadlockCycle> = collector.get_deadlocks().iter()
+        .filter(|x| x.time >= start_time && x.time <= end_time).cloned().collect();
+    for cycle in &mut cycles {
+        for thread in &mut cycle.threads {
+            thread.frames = thread.stacktrace.iter().map(|x| collector.get_method_name(*x)).collect();
+        }
+    }
+    cycles
+}
+
+pub fn load_deadlocks<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<DeadlockCycle>> {
+    let path = sample_data_dir.as_ref().join(DEADLOCK_FILE);
+    if std::fs::metadata(&path).is_err() {
+        return Ok(vec![]);
+    }
+    let json = std::fs::read_to_string(path)?;
+    let cycles = serde_json::from_str::<Vec<DeadlockCycle>>(&json)?;
+    Ok(cycles)
+}
+
+pub fn save_deadlocks<P: AsRef<Path>>(sample_data_dir: P, cycles: &[DeadlockCycle]) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(DEADLOCK_FILE);
+    let json = serde_json::to_string_pretty(cycles)?;
+    std::fs::write(path, json.as_bytes())
+}
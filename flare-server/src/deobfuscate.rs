@@ -0,0 +1,90 @@
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader};
+
+//ProGuard/R8 mapping.txt
+//com.example.Original -> a.b:
+//    void method(int) -> c
+//    1:3:void inlined():10:12 -> d
+pub struct ProguardMapping {
+    //obfuscated class -> original class
+    classes: HashMap<String, String>,
+    //(obfuscated class, obfuscated method) -> original method names
+    methods: HashMap<(String, String), Vec<String>>,
+}
+
+impl ProguardMapping {
+
+    pub fn load(path: &str) -> io::Result<ProguardMapping> {
+        let file = std::fs::File::open(path)?;
+        let mut mapping = ProguardMapping {
+            classes: HashMap::new(),
+            methods: HashMap::new(),
+        };
+        let mut current_class: Option<(String, String)> = None;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.trim().splitn(2, " -> ").collect();
+            if parts.len() != 2 {
+                continue;
+            }
+            if !line.starts_with(' ') && !line.starts_with('\t') {
+                //class line
+                let original = parts[0].trim().to_string();
+                let obfuscated = parts[1].trim().trim_end_matches(':').to_string();
+                mapping.classes.insert(obfuscated.clone(), original.clone());
+                current_class = Some((original, obfuscated));
+            } else if let Some((original_class, obfuscated_class)) = &current_class {
+                //member line, ignore fields
+                let member = parts[0].trim();
+                let pos = match member.find('(') {
+                    Some(pos) => pos,
+                    None => continue
+                };
+                let head = &member[0..pos];
+                let method_name = match head.rfind(|c| c == ' ' || c == ':') {
+                    Some(x) => &head[x+1..],
+                    None => head
+                };
+                //内联自其他类的方法带有完整类名
+                let original_name = if method_name.contains('.') {
+                    method_name.to_string()
+                } else {
+                    format!("{}.{}", original_class, method_name)
+                };
+                let names = mapping.methods.entry((obfuscated_class.clone(), parts[1].trim().to_string())).or_insert_with(|| vec![]);
+                if !names.contains(&original_name) {
+                    names.push(original_name);
+                }
+            }
+        }
+        Ok(mapping)
+    }
+
+    pub fn get_class_count(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn get_method_count(&self) -> usize {
+        self.methods.len()
+    }
+
+    //full name: a.b.c() -> com.example.Original.method()，多个候选方法时用 | 分隔
+    pub fn translate(&self, full_name: &str) -> Option<String> {
+        let name = full_name.trim_end_matches("()");
+        let pos = name.rfind('.')?;
+        let class_name = &name[0..pos];
+        let method_name = &name[pos+1..];
+        if let Some(names) = self.methods.get(&(class_name.to_string(), method_name.to_string())) {
+            return Some(format!("{}()", names.join("|")));
+        }
+        match self.classes.get(class_name) {
+            Some(original_class) => Some(format!("{}.{}()", original_class, method_name)),
+            None => None
+        }
+    }
+}
@@ -0,0 +1,93 @@
+
+//JIT反优化统计：agent(参数 deopt_interval=<ms>)定期推送各个方法新增的反优化/重新编译次数，
+//每个事件追加一行到会话目录下的文件，按方法汇总后找出反复反优化、重新编译的方法(性能悬崖)
+//  deopts.json
+//  JVMTI没有反优化事件和原因，Java agent按编译代码卸载后的重新编译计数；旧版本记录中的 reason 读取时忽略
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use flare_proto::agent::DeoptimizationEvent;
+use ::sample::SampleCollector;
+
+pub const DEOPT_FILE: &str = "deopts.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeoptRecord {
+    pub time: i64,
+    pub method_id: i64,
+    pub count: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeoptStats {
+    pub method_id: i64,
+    pub method_name: String,
+    pub count: i64,
+    pub first_time: i64,
+    pub last_time: i64,
+}
+
+pub fn new_deopt_record(event: &DeoptimizationEvent) -> DeoptRecord {
+    DeoptRecord {
+        time: event.time,
+        method_id: event.method,
+        count: event.count,
+    }
+}
+
+pub fn append_deopt_record<P: AsRef<Path>>(sample_data_dir: P, record: &DeoptRecord) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(DEOPT_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    //one record per line
+    let mut data = serde_json::to_vec(record)?;
+    data.push(b'\n');
+    file.write_all(&data)
+}
+
+pub fn load_deopt_records<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<DeoptRecord>> {
+    let path = sample_data_dir.as_ref().join(DEOPT_FILE);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut records = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str::<DeoptRecord>(&line)?);
+    }
+    Ok(records)
+}
+
+//按方法汇总时间范围内的反优化次数，按次数从大到小排列
+pub fn get_deopt_stats(collector: &mut SampleCollector, start_time: i64, end_time: i64, limit: usize) -> Vec<DeoptStats> {
+    let mut stats_map: HashMap<i64, DeoptStats> = HashMap::new();
+    for record in collector.get_deopt_records() {
+        if record.time < start_time || (end_time >= 0 && record.time > end_time) {
+            continue;
+        }
+        let stats = stats_map.entry(record.method_id).or_insert_with(|| DeoptStats {
+            method_id: record.method_id,
+            method_name: String::new(),
+            count: 0,
+            first_time: record.time,
+            last_time: record.time,
+        });
+        stats.count += record.count;
+        stats.first_time = stats.first_time.min(record.time);
+        stats.last_time = stats.last_time.max(record.time);
+    }
+    let mut stats: Vec<DeoptStats> = stats_map.into_iter().map(|x| x.1).collect();
+    stats.sort_by(|a, b| b.count.cmp(&a.count).then(a.method_id.cmp(&b.method_id)));
+    stats.truncate(limit);
+    for x in &mut stats {
+        x.method_name = collector.get_method_name(x.method_id);
+    }
+    stats
+}
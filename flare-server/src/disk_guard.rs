@@ -0,0 +1,137 @@
+
+//录制输出目录的磁盘空间保护：可用空间低于 disk_free_threshold_mb 时按配置的动作处理录制中的会话，
+//避免写满磁盘后各个文件写入失败、取样目录不完整
+//  notify:     只发送 disk_threshold_exceeded 通知(默认)
+//  stop:       停止录制并断开agent，已写入的数据保存完整
+//  downsample: 每 downsample_rate 轮取样只保留一轮，空间恢复后恢复正常取样
+//空间不足与恢复使用不同的阈值，避免可用空间在阈值附近波动时反复切换:
+//  低于 disk_free_threshold_mb 时进入空间不足状态，可用空间达到 resume_free_mb 后才恢复
+//  resume_free_mb 为0时为阈值的 120%，小于阈值时按阈值
+//处理结果保存在 summary_info.json 的 disk_guard 中，并记录会话事件 disk_low，分析时可以知道数据为什么不完整
+
+use std::io;
+use utils::*;
+
+pub const DISK_GUARD_NOTIFY: &str = "notify";
+pub const DISK_GUARD_STOP: &str = "stop";
+pub const DISK_GUARD_DOWNSAMPLE: &str = "downsample";
+const DISK_GUARD_ACTIONS: &[&str] = &[DISK_GUARD_NOTIFY, DISK_GUARD_STOP, DISK_GUARD_DOWNSAMPLE];
+
+pub const KIND_DISK_LOW: &str = "disk_low";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiskGuardConfig {
+    #[serde(default = "default_action")]
+    pub action: String,
+    #[serde(default = "default_downsample_rate")]
+    pub downsample_rate: i64,
+    //空间不足后恢复需要的可用空间(MB)
+    #[serde(default)]
+    pub resume_free_mb: i64,
+}
+
+fn default_action() -> String {
+    DISK_GUARD_NOTIFY.to_string()
+}
+
+fn default_downsample_rate() -> i64 {
+    5
+}
+
+impl Default for DiskGuardConfig {
+    fn default() -> Self {
+        DiskGuardConfig {
+            action: default_action(),
+            downsample_rate: default_downsample_rate(),
+            resume_free_mb: 0,
+        }
+    }
+}
+
+impl DiskGuardConfig {
+    pub fn get_resume_free_mb(&self, threshold_mb: i64) -> i64 {
+        if self.resume_free_mb > 0 {
+            self.resume_free_mb.max(threshold_mb)
+        } else {
+            threshold_mb + threshold_mb / 5
+        }
+    }
+
+    //was_low: 上一次检查时是否空间不足
+    pub fn is_disk_low(&self, free_mb: i64, threshold_mb: i64, was_low: bool) -> bool {
+        if was_low {
+            free_mb < self.get_resume_free_mb(threshold_mb)
+        } else {
+            free_mb < threshold_mb
+        }
+    }
+}
+
+pub fn check_disk_guard_action(action: &str) -> io::Result<()> {
+    if !DISK_GUARD_ACTIONS.contains(&action) {
+        return Err(new_invalid_input_error(&format!("unknown disk guard action: {}, expect one of {}", action, DISK_GUARD_ACTIONS.join(", "))));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DiskGuardState {
+    //stop 或者 downsample，为空表示没有处理过
+    pub action: String,
+    //处理的时间及当时的可用空间
+    pub time: i64,
+    pub free_mb: i64,
+    //当前的降采样比例，0表示没有降采样
+    pub downsample_rate: i64,
+    //降采样结束的时间
+    pub resume_time: i64,
+    //降采样丢弃的线程取样数量
+    pub dropped_samples: i64,
+    //上一次保留的取样时间，不保存
+    #[serde(skip)]
+    last_kept_time: i64,
+}
+
+impl DiskGuardState {
+    pub fn is_empty(&self) -> bool {
+        self.action.is_empty()
+    }
+
+    pub fn is_downsampling(&self) -> bool {
+        self.downsample_rate > 1
+    }
+
+    pub fn on_stop(&mut self, time: i64, free_mb: i64) {
+        self.action = DISK_GUARD_STOP.to_string();
+        self.time = time;
+        self.free_mb = free_mb;
+        self.downsample_rate = 0;
+    }
+
+    pub fn on_downsample(&mut self, time: i64, free_mb: i64, rate: i64) {
+        self.action = DISK_GUARD_DOWNSAMPLE.to_string();
+        self.time = time;
+        self.free_mb = free_mb;
+        self.downsample_rate = rate;
+        self.resume_time = 0;
+        self.last_kept_time = 0;
+    }
+
+    pub fn on_resume(&mut self, time: i64) {
+        self.downsample_rate = 0;
+        self.resume_time = time;
+    }
+
+    //同一轮的线程使用同一个时间，按时间保留整轮的取样
+    pub fn keep_sample(&mut self, time: i64, sample_interval: i64) -> bool {
+        if !self.is_downsampling() {
+            return true;
+        }
+        if self.last_kept_time == 0 || time == self.last_kept_time || time - self.last_kept_time >= self.downsample_rate * sample_interval.max(1) {
+            self.last_kept_time = time;
+            return true;
+        }
+        self.dropped_samples += 1;
+        false
+    }
+}
@@ -0,0 +1,105 @@
+//嵌入的agent库、attacher及UI静态文件(--features embedded，见 build.rs)
+//  发布目录中存在的文件优先使用(如 agent/lib/libflareagent.so、res/static/)，找不到时才解压嵌入的文件
+//  解压目录: 程序所在目录下的 .flare-embedded/<内容hash>，不同版本的文件互不覆盖；
+//    /tmp 可能以 noexec 挂载，JVM 不能从中加载agent库，所以不使用临时目录；可以通过环境变量 FLARE_EXTRACT_DIR 指定
+//  先写入临时文件再改名，多个进程同时解压时不会读到不完整的文件
+
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use utils::*;
+use sample_path::path_to_string;
+
+include!(concat!(env!("OUT_DIR"), "/embedded_files.rs"));
+
+pub const EXTRACT_DIR_ENV: &str = "FLARE_EXTRACT_DIR";
+const EXTRACT_DIR_NAME: &str = ".flare-embedded";
+
+pub fn has_embedded_files() -> bool {
+    !EMBEDDED_FILES.is_empty()
+}
+
+pub fn get_embedded_file(name: &str) -> Option<&'static [u8]> {
+    EMBEDDED_FILES.iter().find(|x| x.0 == name).map(|x| x.1)
+}
+
+//所有嵌入文件内容的hash，作为解压目录名称
+pub fn get_embedded_hash() -> u64 {
+    let mut hash = fnv1a_hash(b"");
+    for (name, data) in EMBEDDED_FILES {
+        hash = fnv1a_hash_update(hash, name.as_bytes());
+        hash = fnv1a_hash_update(hash, data);
+    }
+    hash
+}
+
+pub fn get_extract_dir() -> io::Result<PathBuf> {
+    let base_dir = match std::env::var(EXTRACT_DIR_ENV) {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            let exe_path = std::env::current_exe()?;
+            exe_path.parent().map(|x| x.join(EXTRACT_DIR_NAME)).unwrap_or_else(|| PathBuf::from(EXTRACT_DIR_NAME))
+        }
+    };
+    Ok(base_dir.join(format!("{:016x}", get_embedded_hash())))
+}
+
+//解压所有嵌入的文件，已经存在且大小相同的文件不重复写入，返回解压目录
+pub fn extract_embedded_files(dest_dir: &Path) -> io::Result<PathBuf> {
+    for (name, data) in EMBEDDED_FILES {
+        let path = dest_dir.join(name);
+        if std::fs::metadata(&path).map(|x| x.len() == data.len() as u64).unwrap_or(false) {
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = dest_dir.join(format!("{}.{}.tmp", name, std::process::id()));
+        std::fs::write(&tmp_path, data)?;
+        set_executable(&tmp_path)?;
+        std::fs::rename(&tmp_path, &path)?;
+    }
+    Ok(dest_dir.to_path_buf())
+}
+
+//查找发布目录中的文件(相对于工作目录)，不存在时使用嵌入的文件
+pub fn find_dist_file(name: &str) -> io::Result<String> {
+    if Path::new(name).exists() {
+        return Ok(name.to_string());
+    }
+    if get_embedded_file(name).is_none() {
+        return Err(new_error(ErrorKind::NotFound, &format!("file not found: {}, and it is not embedded in this program", name)));
+    }
+    let dir = extract_embedded_files(&get_extract_dir()?)?;
+    Ok(path_to_string(&dir.join(name)))
+}
+
+//UI静态文件目录，发布目录中不存在时使用嵌入的文件
+pub fn find_static_dir() -> Option<String> {
+    for dir in &["res/static/", "static/"] {
+        if Path::new(dir).is_dir() {
+            return Some(dir.to_string());
+        }
+    }
+    if !EMBEDDED_FILES.iter().any(|x| x.0.starts_with("res/static/")) {
+        return None;
+    }
+    match get_extract_dir().and_then(|x| extract_embedded_files(&x)) {
+        Ok(dir) => Some(format!("{}/", path_to_string(&dir.join("res/static")))),
+        Err(e) => {
+            println!("extract embedded static files failed: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
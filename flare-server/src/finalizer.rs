@@ -0,0 +1,120 @@
+
+//finalizer积压：agent定期(agent参数 finalizer_interval=<ms>，默认1000)推送等待执行finalize()的对象数量，
+//保存为jvm分组的指标序列；积压持续增长或者Finalizer线程一直在执行finalize()时认为finalization是瓶颈
+//  finalize()由单个Finalizer线程串行执行，慢的finalize()会让待回收的对象堆积，最终导致频繁GC或OOM
+
+use ::sample::*;
+use ::metric_series::{MetricDef, METRIC_UNIT_TIME};
+use flare_utils::timeseries::MetricKind;
+use std::collections::HashMap;
+use std::io;
+
+pub const FINALIZER_PENDING_METRIC: &MetricDef = &MetricDef { name: "finalizer_pending", unit: "count", kind: MetricKind::GAUGE, group: "jvm" };
+pub const FINALIZER_THREAD_NAME: &str = "Finalizer";
+
+#[derive(Clone, Debug)]
+pub struct FinalizerOptions {
+    //积压数量的下限
+    pub min_pending: i64,
+    //积压增长的时间点占比
+    pub min_growth_ratio: f64,
+    //Finalizer线程RUNNABLE的时间占比
+    pub min_busy_ratio: f64,
+}
+
+impl Default for FinalizerOptions {
+    fn default() -> Self {
+        FinalizerOptions {
+            min_pending: 1000,
+            min_growth_ratio: 0.6,
+            min_busy_ratio: 0.8,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FinalizeMethod {
+    pub method_name: String,
+    pub samples: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FinalizerPressure {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub first_pending: i64,
+    pub last_pending: i64,
+    pub max_pending: i64,
+    //积压数量增长的时间点占比
+    pub growth_ratio: f64,
+    //Finalizer线程RUNNABLE的时间占比
+    pub busy_ratio: f64,
+    //Finalizer线程取样中最多的finalize()方法
+    pub finalize_methods: Vec<FinalizeMethod>,
+}
+
+impl FinalizerPressure {
+    pub fn is_growing(&self, options: &FinalizerOptions) -> bool {
+        self.last_pending > self.first_pending && self.growth_ratio >= options.min_growth_ratio
+    }
+
+    pub fn is_busy(&self, options: &FinalizerOptions) -> bool {
+        self.busy_ratio >= options.min_busy_ratio
+    }
+}
+
+//统计时间范围内的finalizer积压及Finalizer线程的忙碌程度，没有积压数据时返回None
+pub fn get_finalizer_pressure(collector: &mut SampleCollector, start_time: i64, end_time: i64) -> io::Result<Option<FinalizerPressure>> {
+    let points = collector.get_metric_points(FINALIZER_PENDING_METRIC.name, start_time, end_time);
+    if points.is_empty() {
+        return Ok(None);
+    }
+    let rising = points.windows(2).filter(|x| x[1].1 > x[0].1).count();
+    let growth_ratio = if points.len() > 1 { rising as f64 / (points.len() - 1) as f64 } else { 0.0 };
+    let range_start = points[0].0;
+    let range_end = points[points.len() - 1].0 + METRIC_UNIT_TIME as i64;
+
+    let sample_interval = collector.get_sample_info().sample_interval.max(1);
+    let mut busy_ratio = 0.0;
+    let mut method_counts: HashMap<i64, usize> = HashMap::new();
+    if let Some(thread) = collector.get_threads()?.into_iter().find(|x| x.name == FINALIZER_THREAD_NAME) {
+        let samples = collector.load_thread_samples(thread.id, range_start, range_end)?;
+        let runnable: Vec<&ThreadData> = samples.iter().filter(|x| x.state == "RUNNABLE").collect();
+        busy_ratio = (runnable.len() as i64 * sample_interval) as f64 / (range_end - range_start).max(1) as f64;
+        for sample in runnable {
+            //栈中最靠近栈顶的finalize()
+            if let Some(method) = sample.stacktrace.iter().find(|x| collector.get_method_name(**x).contains(".finalize(")) {
+                *method_counts.entry(*method).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut finalize_methods: Vec<(i64, usize)> = method_counts.into_iter().collect();
+    finalize_methods.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let finalize_methods = finalize_methods.into_iter().take(5).map(|(method, samples)| FinalizeMethod {
+        method_name: collector.get_method_name(method),
+        samples,
+    }).collect();
+
+    Ok(Some(FinalizerPressure {
+        start_time: range_start,
+        end_time: range_end,
+        first_pending: points[0].1,
+        last_pending: points[points.len() - 1].1,
+        max_pending: points.iter().map(|x| x.1).max().unwrap_or(0),
+        growth_ratio: (growth_ratio * 1000.0).round() / 1000.0,
+        busy_ratio: (busy_ratio.min(1.0) * 1000.0).round() / 1000.0,
+        finalize_methods,
+    }))
+}
+
+//积压超过下限，并且持续增长或者Finalizer线程一直忙碌
+pub fn detect_finalizer_pressure(collector: &mut SampleCollector, start_time: i64, end_time: i64, options: &FinalizerOptions) -> io::Result<Option<FinalizerPressure>> {
+    let pressure = match get_finalizer_pressure(collector, start_time, end_time)? {
+        Some(x) => x,
+        None => return Ok(None)
+    };
+    if pressure.max_pending < options.min_pending || !(pressure.is_growing(options) || pressure.is_busy(options)) {
+        return Ok(None);
+    }
+    Ok(Some(pressure))
+}
@@ -0,0 +1,63 @@
+
+//录制数据的刷新策略：线程调用栈在内存中缓冲，超过时间间隔或者字节数时写入文件，
+//  服务端进程异常退出时最多丢失 interval_ms 内或者 max_buffer_bytes 的调用栈(恢复点目标，RPO)，
+//  开启 fsync 时每次写入后同步到磁盘，主机掉电时也不会丢失已写入的数据，但是写入开销更大
+//  CPU时间等时序数据不缓冲，summary_info.json 每秒保存一次
+//默认值来自服务端配置 flush_policy，连接agent时及录制过程中可以按会话修改
+
+use std::io;
+use utils::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlushPolicy {
+    //缓冲数据的最长保留时间(ms)，0表示每个取样立即写入
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: i64,
+    //每个线程缓冲的字节数上限
+    #[serde(default = "default_max_buffer_bytes")]
+    pub max_buffer_bytes: i64,
+    #[serde(default)]
+    pub fsync: bool,
+}
+
+fn default_interval_ms() -> i64 {
+    1000
+}
+
+fn default_max_buffer_bytes() -> i64 {
+    100 * 1024
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy {
+            interval_ms: default_interval_ms(),
+            max_buffer_bytes: default_max_buffer_bytes(),
+            fsync: false,
+        }
+    }
+}
+
+impl FlushPolicy {
+    //没有指定的选项保留当前的值
+    pub fn merge_options(&self, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<FlushPolicy> {
+        let policy = FlushPolicy {
+            interval_ms: get_option_as_int(options, "interval_ms", self.interval_ms),
+            max_buffer_bytes: get_option_as_int(options, "max_buffer_bytes", self.max_buffer_bytes),
+            fsync: get_option_as_bool(options, "fsync", self.fsync),
+        };
+        if policy.interval_ms < 0 || policy.max_buffer_bytes < 0 {
+            return Err(new_invalid_input_error("interval_ms and max_buffer_bytes must not be negative"));
+        }
+        Ok(policy)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FlushStatus {
+    pub policy: FlushPolicy,
+    //还没有写入文件的调用栈数据
+    pub buffered_bytes: usize,
+    //最近一次全部写入的时间
+    pub last_flush_time: i64,
+}
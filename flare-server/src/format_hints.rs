@@ -0,0 +1,211 @@
+
+//响应的格式化提示：请求参数 format_hints 为true或者对象时，在响应中数值字段的旁边增加 <name>_display 格式化后的文本，
+//原始数值保持不变，前端不需要各自实现单位换算
+//  format_hints: {"duration_unit": "auto|ns|us|ms|s", "bytes_unit": "auto|B|KiB|MiB|GiB", "precision": 2}
+//字段的单位按名称推断:
+//  cpu_time, cpu_time_delta, self_cpu_time: ns;  total_cpu_time(时间序列汇总): us
+//  duration, self_duration, total_duration, *_ms: ms;  gc_pauses/gc_summary 中的 duration: us
+//  bytes, size, *_bytes: bytes;  bytes_per_sec: bytes/s
+//  *_ratio, coverage: 比例，格式化为百分比
+//数组中的数值(如时间序列)不增加提示
+
+use std::cell::RefCell;
+use std::io;
+use serde_json::{Map, Value};
+use utils::new_invalid_input_error;
+
+const DURATION_UNITS: &[&str] = &["auto", "ns", "us", "ms", "s"];
+const BYTES_UNITS: &[&str] = &["auto", "B", "KiB", "MiB", "GiB"];
+const DISPLAY_SUFFIX: &str = "_display";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatOptions {
+    pub duration_unit: String,
+    pub bytes_unit: String,
+    //小数位数
+    pub precision: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            duration_unit: "auto".to_string(),
+            bytes_unit: "auto".to_string(),
+            precision: 2,
+        }
+    }
+}
+
+impl FormatOptions {
+    //没有开启格式化提示时返回None
+    pub fn from_request(options: &Map<String, Value>) -> io::Result<Option<FormatOptions>> {
+        let hints = match options.get("format_hints") {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+        if let Some(enabled) = hints.as_bool() {
+            return Ok(if enabled { Some(FormatOptions::default()) } else { None });
+        }
+        let hints = match hints.as_object() {
+            Some(x) => x,
+            None => return Err(new_invalid_input_error("option 'format_hints' is not bool or object"))
+        };
+        let mut format = FormatOptions::default();
+        if let Some(unit) = hints.get("duration_unit").and_then(|x| x.as_str()) {
+            if !DURATION_UNITS.contains(&unit) {
+                return Err(new_invalid_input_error(&format!("invalid duration_unit: {}, expect one of {:?}", unit, DURATION_UNITS)));
+            }
+            format.duration_unit = unit.to_string();
+        }
+        if let Some(unit) = hints.get("bytes_unit").and_then(|x| x.as_str()) {
+            if !BYTES_UNITS.contains(&unit) {
+                return Err(new_invalid_input_error(&format!("invalid bytes_unit: {}, expect one of {:?}", unit, BYTES_UNITS)));
+            }
+            format.bytes_unit = unit.to_string();
+        }
+        if let Some(precision) = hints.get("precision").and_then(|x| x.as_u64()) {
+            format.precision = precision.min(6) as usize;
+        }
+        Ok(Some(format))
+    }
+}
+
+//当前线程正在处理的请求的格式化选项，wrap_response 按此增加提示
+thread_local! {
+    static RESPONSE_FORMAT: RefCell<Option<FormatOptions>> = RefCell::new(None);
+}
+
+pub fn set_response_format(format: Option<FormatOptions>) {
+    RESPONSE_FORMAT.with(|x| *x.borrow_mut() = format);
+}
+
+pub fn get_response_format() -> Option<FormatOptions> {
+    RESPONSE_FORMAT.with(|x| x.borrow().clone())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FieldUnit {
+    Nanos,
+    Micros,
+    Millis,
+    Bytes,
+    BytesPerSec,
+    Ratio,
+}
+
+fn get_field_unit(parent: &str, key: &str) -> Option<FieldUnit> {
+    if parent == "gc_pauses" || parent == "gc_summary" {
+        if key == "duration" || key == "total_duration" || key == "max_duration" {
+            return Some(FieldUnit::Micros);
+        }
+    }
+    match key {
+        "cpu_time" | "cpu_time_delta" | "self_cpu_time" => Some(FieldUnit::Nanos),
+        "total_cpu_time" => Some(FieldUnit::Micros),
+        "duration" | "self_duration" | "total_duration" | "max_duration" => Some(FieldUnit::Millis),
+        "bytes" | "size" => Some(FieldUnit::Bytes),
+        "bytes_per_sec" => Some(FieldUnit::BytesPerSec),
+        "coverage" => Some(FieldUnit::Ratio),
+        //时间点(如 start_time_ms)不是时长
+        _ if key.ends_with("_ms") && !key.contains("time") => Some(FieldUnit::Millis),
+        _ if key.ends_with("_bytes") => Some(FieldUnit::Bytes),
+        _ if key.ends_with("_ratio") => Some(FieldUnit::Ratio),
+        _ => None
+    }
+}
+
+fn format_number(value: f64, precision: usize) -> String {
+    format!("{:.*}", precision, value)
+}
+
+//value 单位为ns
+pub fn format_duration(nanos: f64, unit: &str, precision: usize) -> String {
+    let unit = if unit == "auto" {
+        let abs = nanos.abs();
+        if abs >= 1e9 { "s" } else if abs >= 1e6 { "ms" } else if abs >= 1e3 { "us" } else { "ns" }
+    } else {
+        unit
+    };
+    let value = match unit {
+        "s" => nanos / 1e9,
+        "ms" => nanos / 1e6,
+        "us" => nanos / 1e3,
+        _ => nanos
+    };
+    let unit = if unit == "us" { "µs" } else { unit };
+    format!("{} {}", format_number(value, precision), unit)
+}
+
+pub fn format_bytes(bytes: f64, unit: &str, precision: usize) -> String {
+    let unit = if unit == "auto" {
+        let abs = bytes.abs();
+        if abs >= 1024.0 * 1024.0 * 1024.0 { "GiB" } else if abs >= 1024.0 * 1024.0 { "MiB" } else if abs >= 1024.0 { "KiB" } else { "B" }
+    } else {
+        unit
+    };
+    let value = match unit {
+        "GiB" => bytes / (1024.0 * 1024.0 * 1024.0),
+        "MiB" => bytes / (1024.0 * 1024.0),
+        "KiB" => bytes / 1024.0,
+        _ => bytes
+    };
+    if unit == "B" {
+        format!("{} B", bytes)
+    } else {
+        format!("{} {}", format_number(value, precision), unit)
+    }
+}
+
+fn format_field(unit: FieldUnit, value: f64, format: &FormatOptions) -> String {
+    match unit {
+        FieldUnit::Nanos => format_duration(value, &format.duration_unit, format.precision),
+        FieldUnit::Micros => format_duration(value * 1e3, &format.duration_unit, format.precision),
+        FieldUnit::Millis => format_duration(value * 1e6, &format.duration_unit, format.precision),
+        FieldUnit::Bytes => format_bytes(value, &format.bytes_unit, format.precision),
+        FieldUnit::BytesPerSec => format!("{}/s", format_bytes(value, &format.bytes_unit, format.precision)),
+        FieldUnit::Ratio => format!("{}%", format_number(value * 100.0, format.precision)),
+    }
+}
+
+fn add_hints_to_object(parent: &str, object: &mut Map<String, Value>, format: &FormatOptions) {
+    let mut hints = vec![];
+    for (key, value) in object.iter_mut() {
+        match value {
+            Value::Number(number) => {
+                if key.ends_with(DISPLAY_SUFFIX) {
+                    continue;
+                }
+                if let (Some(unit), Some(x)) = (get_field_unit(parent, key), number.as_f64()) {
+                    hints.push((format!("{}{}", key, DISPLAY_SUFFIX), format_field(unit, x, format)));
+                }
+            }
+            Value::Object(child) => add_hints_to_object(key, child, format),
+            Value::Array(items) => {
+                for item in items.iter_mut() {
+                    if let Value::Object(child) = item {
+                        add_hints_to_object(key, child, format);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for (key, text) in hints {
+        //不覆盖已有的字段
+        if !object.contains_key(&key) {
+            object.insert(key, Value::String(text));
+        }
+    }
+}
+
+pub fn add_format_hints(value: &mut Value, format: &FormatOptions) {
+    match value {
+        Value::Object(object) => add_hints_to_object("", object, format),
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                add_format_hints(item, format);
+            }
+        }
+        _ => {}
+    }
+}
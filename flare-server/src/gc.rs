@@ -0,0 +1,80 @@
+
+//GC暂停：agent定期(agent参数 gc_interval=<ms>，默认1000)推送GarbageCollectionStart/Finish之间的暂停，
+//每个暂停追加一行到会话目录下的文件
+//  gc_events.json
+//JVMTI的GC事件只覆盖stop-the-world阶段，不区分young/full GC
+
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use flare_proto::agent::GcEvent;
+
+pub const GC_FILE: &str = "gc_events.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct GcPause {
+    //暂停开始时间
+    pub time: i64,
+    //us
+    pub duration: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct GcSummary {
+    pub count: usize,
+    pub total_duration: i64,
+    pub max_duration: i64,
+    //暂停时间占时间范围的比例
+    pub pause_ratio: f64,
+}
+
+pub fn new_gc_pause(event: &GcEvent) -> GcPause {
+    GcPause {
+        time: event.time,
+        duration: event.duration,
+    }
+}
+
+pub fn append_gc_pause<P: AsRef<Path>>(sample_data_dir: P, pause: &GcPause) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(GC_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    //one pause per line
+    let mut data = serde_json::to_vec(pause)?;
+    data.push(b'\n');
+    file.write_all(&data)
+}
+
+pub fn load_gc_pauses<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<GcPause>> {
+    let path = sample_data_dir.as_ref().join(GC_FILE);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut pauses = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        pauses.push(serde_json::from_str::<GcPause>(&line)?);
+    }
+    Ok(pauses)
+}
+
+//时间范围内开始的暂停，end_time 为-1时不限制结束时间
+pub fn get_gc_pauses_in_range(pauses: &[GcPause], start_time: i64, end_time: i64) -> Vec<GcPause> {
+    pauses.iter().filter(|x| x.time >= start_time && (end_time < 0 || x.time <= end_time)).cloned().collect()
+}
+
+pub fn summarize_gc_pauses(pauses: &[GcPause], start_time: i64, end_time: i64) -> GcSummary {
+    let total_duration: i64 = pauses.iter().map(|x| x.duration).sum();
+    let range = (end_time - start_time).max(1) * 1000;
+    GcSummary {
+        count: pauses.len(),
+        total_duration,
+        max_duration: pauses.iter().map(|x| x.duration).max().unwrap_or(0),
+        pause_ratio: ((total_duration as f64 / range as f64).min(1.0) * 10000.0).round() / 10000.0,
+    }
+}
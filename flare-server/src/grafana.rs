@@ -0,0 +1,179 @@
+
+//兼容 Grafana SimpleJSON / Infinity 数据源协议，将会话的时间序列接入现有的Grafana面板
+//  GET  /grafana/             测试连接
+//  POST /grafana/search       列出可查询的指标: <session_id>/cpu_time/<thread_id>，<thread_id>为*表示所有线程之和
+//  POST /grafana/query        查询时间序列，返回 [{target, datapoints: [[value, time_ms], ...]}]
+//  POST /grafana/annotations  返回会话的标记及阶段，annotation.query 为会话ID(为空表示所有会话)
+//跨域访问只允许配置的来源(grafana_cors_origins)，查询在线程池中执行
+//配置了 access_tokens 时请求需要提供令牌(Authorization: Bearer <token> 或者参数 token)，受限的令牌只能查询允许访问的会话
+
+use ::sample::*;
+use profiler::Profiler;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use chrono::DateTime;
+use serde_json::{json, Value};
+use utils::*;
+
+pub const GRAFANA_PATH_PREFIX: &str = "/grafana";
+const ALL_THREADS: &str = "*";
+//单个指标最多返回的数据点，避免请求过大的时间范围
+const MAX_DATA_POINTS: i64 = 10_000;
+
+//浏览器直连模式的跨域来源，配置 "*" 时允许任意来源
+pub fn is_cors_origin_allowed(allowed_origins: &[String], origin: &str) -> bool {
+    allowed_origins.iter().any(|x| x == "*" || x.trim_end_matches('/') == origin)
+}
+
+pub fn handle_grafana_request(profiler: &Arc<Mutex<Profiler>>, token: &str, path: &str, body: &[u8]) -> io::Result<Value> {
+    let sessions = {
+        let profiler = profiler.lock().unwrap();
+        let identity = profiler.authenticate_token("grafana", token)?;
+        profiler.get_visible_sample_sessions(&identity)
+    };
+    let request: Value = if body.is_empty() {
+        json!({})
+    } else {
+        serde_json::from_slice(body)?
+    };
+    match path.trim_start_matches(GRAFANA_PATH_PREFIX).trim_end_matches('/') {
+        "" => Ok(json!({"status": "ok"})),
+        "/search" => Ok(search_targets(&sessions, request["target"].as_str().unwrap_or(""))),
+        "/query" => query_targets(&sessions, &request),
+        "/annotations" => query_annotations(&sessions, &request),
+        _ => Err(new_error(io::ErrorKind::NotFound, &format!("unknown grafana path: {}", path)))
+    }
+}
+
+fn search_targets(sessions: &[(String, Arc<Mutex<SampleCollector>>)], filter: &str) -> Value {
+    let mut targets = vec![];
+    for (session_id, collector) in sessions {
+        let collector = collector.lock().unwrap();
+        let mut session_targets = vec![(format!("{}/cpu_time/{}", session_id, ALL_THREADS), format!("{} cpu_time (all threads)", session_id))];
+        for series in collector.list_series() {
+            if series.labels.get("metric").map(|x| x.as_str()) != Some("cpu_time") {
+                continue;
+            }
+            if let Some(thread_id) = series.labels.get("thread_id") {
+                let thread_name = series.labels.get("thread_name").cloned().unwrap_or_default();
+                session_targets.push((format!("{}/cpu_time/{}", session_id, thread_id), format!("{} cpu_time {} [{}]", session_id, thread_name, thread_id)));
+            }
+        }
+        for (value, text) in session_targets {
+            if filter.is_empty() || text.contains(filter) {
+                targets.push(json!({"text": text, "value": value}));
+            }
+        }
+    }
+    json!(targets)
+}
+
+fn query_targets(sessions: &[(String, Arc<Mutex<SampleCollector>>)], request: &Value) -> io::Result<Value> {
+    let (start_time, end_time) = parse_range(&request["range"])?;
+    let max_data_points = request["maxDataPoints"].as_i64().unwrap_or(MAX_DATA_POINTS).max(1).min(MAX_DATA_POINTS);
+    let interval_ms = request["intervalMs"].as_i64().unwrap_or(0);
+    let mut result = vec![];
+    for target in request["targets"].as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+        if target["hide"].as_bool() == Some(true) {
+            continue;
+        }
+        let target = match target["target"].as_str() {
+            Some(x) if !x.is_empty() => x,
+            _ => continue
+        };
+        let (session_id, thread_id) = parse_target(target)?;
+        let collector = sessions.iter().find(|x| x.0 == session_id)
+            .ok_or_else(|| new_error(io::ErrorKind::NotFound, &format!("sample session not found: {}", session_id)))?;
+        let mut collector = collector.1.lock().unwrap();
+        let sample_interval = collector.get_sample_info().sample_interval.max(1);
+        let unit_time_ms = interval_ms.max((end_time - start_time) / max_data_points).max(sample_interval);
+        let thread_ids = match thread_id {
+            Some(thread_id) => vec![thread_id],
+            None => collector.get_threads()?.iter().map(|x| x.id).collect()
+        };
+        //按时间累加，多个线程求和
+        let mut points: BTreeMap<i64, Option<i64>> = BTreeMap::new();
+        for thread_id in &thread_ids {
+            if let Some(ts_result) = collector.get_thread_cpu_time(thread_id, start_time, end_time, unit_time_ms) {
+                let data = ts_result.data.as_opt_int64().unwrap_or_default();
+                for (i, value) in data.iter().enumerate() {
+                    let time = ts_result.begin_time + i as i64 * ts_result.unit_time as i64;
+                    let point = points.entry(time).or_insert(None);
+                    if let Some(value) = value {
+                        *point = Some(point.unwrap_or(0) + value);
+                    }
+                }
+            }
+        }
+        let datapoints: Vec<Value> = points.iter().map(|(time, value)| json!([value, time])).collect();
+        result.push(json!({"target": target, "datapoints": datapoints}));
+    }
+    Ok(json!(result))
+}
+
+fn query_annotations(sessions: &[(String, Arc<Mutex<SampleCollector>>)], request: &Value) -> io::Result<Value> {
+    let (start_time, end_time) = parse_range(&request["range"])?;
+    let annotation = &request["annotation"];
+    let session_filter = annotation["query"].as_str().unwrap_or("").trim();
+    let mut result = vec![];
+    for (session_id, collector) in sessions {
+        if !session_filter.is_empty() && session_filter != session_id {
+            continue;
+        }
+        let collector = collector.lock().unwrap();
+        for marker in collector.get_markers() {
+            if marker.time >= start_time && marker.time <= end_time {
+                result.push(json!({"annotation": annotation, "time": marker.time, "title": marker.label,
+                    "text": marker.label, "tags": [session_id, marker.source]}));
+            }
+        }
+        for interval in collector.get_intervals() {
+            let interval_end = if interval.end_time < 0 { end_time } else { interval.end_time };
+            if interval.start_time <= end_time && interval_end >= start_time {
+                result.push(json!({"annotation": annotation, "time": interval.start_time, "timeEnd": interval_end, "isRegion": true,
+                    "title": interval.name, "text": interval.name, "tags": [session_id, "interval"]}));
+            }
+        }
+    }
+    Ok(json!(result))
+}
+
+//target: <session_id>/cpu_time/<thread_id|*>，会话ID可能包含'/'，从右边解析
+fn parse_target(target: &str) -> io::Result<(String, Option<i64>)> {
+    let mut parts = target.rsplitn(3, '/');
+    let thread = parts.next().unwrap_or("");
+    let metric = parts.next().unwrap_or("");
+    let session_id = parts.next().unwrap_or("");
+    if metric != "cpu_time" || session_id.is_empty() {
+        return Err(new_invalid_input_error(&format!("invalid target: {}, expect <session_id>/cpu_time/<thread_id|*>", target)));
+    }
+    if thread == ALL_THREADS {
+        return Ok((session_id.to_string(), None));
+    }
+    match thread.parse::<i64>() {
+        Ok(thread_id) => Ok((session_id.to_string(), Some(thread_id))),
+        Err(_) => Err(new_invalid_input_error(&format!("invalid thread id of target: {}", target)))
+    }
+}
+
+//range.from/to 为 RFC 3339 时间字符串，也接受毫秒时间戳
+fn parse_range(range: &Value) -> io::Result<(i64, i64)> {
+    let start_time = parse_time(&range["from"])?;
+    let end_time = parse_time(&range["to"])?;
+    if end_time < start_time {
+        return Err(new_invalid_input_error(&format!("invalid time range: {} - {}", start_time, end_time)));
+    }
+    Ok((start_time, end_time))
+}
+
+fn parse_time(value: &Value) -> io::Result<i64> {
+    if let Some(time) = value.as_i64() {
+        return Ok(time);
+    }
+    let str = value.as_str().ok_or_else(|| new_invalid_input_error("missing time range"))?;
+    match DateTime::parse_from_rfc3339(str) {
+        Ok(time) => Ok(time.timestamp_millis()),
+        Err(_) => str.parse::<i64>().map_err(|_| new_invalid_input_error(&format!("invalid time: {}", str)))
+    }
+}
@@ -0,0 +1,166 @@
+
+//堆直方图：按需获取堆中各个类的实例数量及字节数(可选先强制GC)，每次保存为会话目录下的一个文件，
+//比较两次直方图找出增长的类，用于排查内存泄漏
+//  heap_histograms/<time>.json
+//请求指定 limit 时agent只返回字节数最多的类，总数按截断前计算；截断的直方图中没有的类数量未知，比较时不作为新增或删除
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use flare_proto::agent::HeapHistogramEvent;
+
+pub const HEAP_HISTOGRAM_DIR: &str = "heap_histograms";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct HeapClassEntry {
+    pub class_name: String,
+    pub count: i64,
+    pub bytes: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct HeapHistogram {
+    pub time: i64,
+    pub force_gc: bool,
+    pub total_count: i64,
+    pub total_bytes: i64,
+    //classes 只有字节数最多的部分类
+    #[serde(default)]
+    pub truncated: bool,
+    //按字节数从大到小排列
+    pub classes: Vec<HeapClassEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct HeapHistogramInfo {
+    pub time: i64,
+    pub force_gc: bool,
+    pub classes: usize,
+    pub total_count: i64,
+    pub total_bytes: i64,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+//截断的直方图中没有的类，该次的数量及变化为 null
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct HeapClassDiff {
+    pub class_name: String,
+    pub count_before: Option<i64>,
+    pub count_after: Option<i64>,
+    pub count_delta: Option<i64>,
+    pub bytes_before: Option<i64>,
+    pub bytes_after: Option<i64>,
+    pub bytes_delta: Option<i64>,
+}
+
+impl HeapHistogram {
+    pub fn from_event(event: &HeapHistogramEvent) -> HeapHistogram {
+        let classes: Vec<HeapClassEntry> = event.classes.iter().enumerate().map(|(i, class_name)| HeapClassEntry {
+            class_name: class_name.clone(),
+            count: event.counts.get(i).cloned().unwrap_or(0),
+            bytes: event.bytes.get(i).cloned().unwrap_or(0),
+        }).collect();
+        //旧版本agent没有截断前的总数
+        let (total_count, total_bytes, truncated) = if event.total_classes > 0 {
+            (event.total_count, event.total_bytes, (classes.len() as i64) < event.total_classes)
+        } else {
+            (classes.iter().map(|x| x.count).sum(), classes.iter().map(|x| x.bytes).sum(), false)
+        };
+        HeapHistogram {
+            time: event.time,
+            force_gc: event.force_gc,
+            total_count,
+            total_bytes,
+            truncated,
+            classes,
+        }
+    }
+
+    pub fn get_info(&self) -> HeapHistogramInfo {
+        HeapHistogramInfo {
+            time: self.time,
+            force_gc: self.force_gc,
+            classes: self.classes.len(),
+            total_count: self.total_count,
+            total_bytes: self.total_bytes,
+            truncated: self.truncated,
+        }
+    }
+
+    //不在直方图中的类: 完整的直方图中为0，截断的直方图中未知
+    fn get_missing_class_value(&self) -> Option<i64> {
+        if self.truncated { None } else { Some(0) }
+    }
+}
+
+fn get_heap_histogram_path(sample_data_dir: &Path, time: i64) -> PathBuf {
+    sample_data_dir.join(HEAP_HISTOGRAM_DIR).join(format!("{}.json", time))
+}
+
+pub fn save_heap_histogram<P: AsRef<Path>>(sample_data_dir: P, histogram: &HeapHistogram) -> io::Result<()> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    std::fs::create_dir_all(sample_data_dir.join(HEAP_HISTOGRAM_DIR))?;
+    let json = serde_json::to_string(histogram)?;
+    std::fs::write(get_heap_histogram_path(sample_data_dir, histogram.time), json.as_bytes())
+}
+
+pub fn load_heap_histogram<P: AsRef<Path>>(sample_data_dir: P, time: i64) -> io::Result<HeapHistogram> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    let json = std::fs::read_to_string(get_heap_histogram_path(sample_data_dir, time))?;
+    let histogram = serde_json::from_str::<HeapHistogram>(&json)?;
+    Ok(histogram)
+}
+
+//按时间排序
+pub fn list_heap_histograms<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<HeapHistogramInfo>> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    let dir = sample_data_dir.join(HEAP_HISTOGRAM_DIR);
+    if std::fs::metadata(&dir).is_err() {
+        return Ok(vec![]);
+    }
+    let mut histograms = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let time = match path.file_stem().and_then(|x| x.to_str()).and_then(|x| x.parse::<i64>().ok()) {
+            Some(time) => time,
+            None => continue
+        };
+        histograms.push(load_heap_histogram(sample_data_dir, time)?.get_info());
+    }
+    histograms.sort_by_key(|x| x.time);
+    Ok(histograms)
+}
+
+//比较两次直方图，按字节数增长从大到小排列，变化未知的类排在最后
+pub fn diff_heap_histograms(before: &HeapHistogram, after: &HeapHistogram) -> Vec<HeapClassDiff> {
+    let mut before_map: HashMap<&str, &HeapClassEntry> = before.classes.iter().map(|x| (x.class_name.as_str(), x)).collect();
+    let mut diffs = vec![];
+    for entry in &after.classes {
+        let (count_before, bytes_before) = match before_map.remove(entry.class_name.as_str()) {
+            Some(x) => (Some(x.count), Some(x.bytes)),
+            None => (before.get_missing_class_value(), before.get_missing_class_value())
+        };
+        diffs.push(new_class_diff(&entry.class_name, (count_before, bytes_before), (Some(entry.count), Some(entry.bytes))));
+    }
+    for entry in before_map.values() {
+        let missing = after.get_missing_class_value();
+        diffs.push(new_class_diff(&entry.class_name, (Some(entry.count), Some(entry.bytes)), (missing, missing)));
+    }
+    //None 小于任何值，倒序时排在最后
+    diffs.sort_by(|a, b| b.bytes_delta.cmp(&a.bytes_delta).then(a.class_name.cmp(&b.class_name)));
+    diffs
+}
+
+//(count, bytes)
+fn new_class_diff(class_name: &str, before: (Option<i64>, Option<i64>), after: (Option<i64>, Option<i64>)) -> HeapClassDiff {
+    HeapClassDiff {
+        class_name: class_name.to_string(),
+        count_before: before.0,
+        count_after: after.0,
+        count_delta: after.0.and_then(|x| before.0.map(|y| x - y)),
+        bytes_before: before.1,
+        bytes_after: after.1,
+        bytes_delta: after.1.and_then(|x| before.1.map(|y| x - y)),
+    }
+}
@@ -0,0 +1,163 @@
+
+//录制期间的主机资源指标(Linux /proc)，用于将JVM的表现与主机负载关联
+//  /proc/stat: cpu 累计时间(jiffies), /proc/loadavg, /proc/meminfo,
+//  /proc/diskstats: 读写扇区数, /proc/net/dev: 收发字节数(不含lo)
+
+use std::io;
+use flare_utils::timeseries::MetricKind;
+use metric_series::MetricDef;
+
+pub const HOST_METRICS: &[MetricDef] = &[
+    MetricDef { name: "host_cpu_busy", unit: "jiffies", kind: MetricKind::COUNTER, group: "host" },
+    MetricDef { name: "host_cpu_total", unit: "jiffies", kind: MetricKind::COUNTER, group: "host" },
+    MetricDef { name: "host_load1", unit: "1/100", kind: MetricKind::GAUGE, group: "host" },
+    MetricDef { name: "host_memory_total", unit: "bytes", kind: MetricKind::GAUGE, group: "host" },
+    MetricDef { name: "host_memory_available", unit: "bytes", kind: MetricKind::GAUGE, group: "host" },
+    MetricDef { name: "host_disk_read", unit: "bytes", kind: MetricKind::COUNTER, group: "host" },
+    MetricDef { name: "host_disk_written", unit: "bytes", kind: MetricKind::COUNTER, group: "host" },
+    MetricDef { name: "host_net_received", unit: "bytes", kind: MetricKind::COUNTER, group: "host" },
+    MetricDef { name: "host_net_sent", unit: "bytes", kind: MetricKind::COUNTER, group: "host" },
+];
+
+//磁盘扇区固定为512字节
+const SECTOR_BYTES: i64 = 512;
+
+//累计值: cpu、disk、net；-1表示无法读取
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct HostMetrics {
+    pub time: i64,
+    pub cpu_busy: i64,
+    pub cpu_total: i64,
+    pub load1: f64,
+    pub load5: f64,
+    pub load15: f64,
+    pub memory_total_bytes: i64,
+    pub memory_available_bytes: i64,
+    pub disk_read_bytes: i64,
+    pub disk_written_bytes: i64,
+    pub net_received_bytes: i64,
+    pub net_sent_bytes: i64,
+}
+
+impl HostMetrics {
+    pub fn get_metric_value(&self, name: &str) -> i64 {
+        match name {
+            "host_cpu_busy" => self.cpu_busy,
+            "host_cpu_total" => self.cpu_total,
+            "host_load1" => if self.load1 < 0.0 { -1 } else { (self.load1 * 100.0).round() as i64 },
+            "host_memory_total" => self.memory_total_bytes,
+            "host_memory_available" => self.memory_available_bytes,
+            "host_disk_read" => self.disk_read_bytes,
+            "host_disk_written" => self.disk_written_bytes,
+            "host_net_received" => self.net_received_bytes,
+            "host_net_sent" => self.net_sent_bytes,
+            _ => -1
+        }
+    }
+}
+
+pub fn read_host_metrics(time: i64) -> io::Result<HostMetrics> {
+    read_host_metrics_from(::cgroup_metrics::PROC_ROOT, time)
+}
+
+pub fn read_host_metrics_from(proc_root: &str, time: i64) -> io::Result<HostMetrics> {
+    let mut metrics = HostMetrics {
+        time,
+        cpu_busy: -1,
+        cpu_total: -1,
+        load1: -1.0,
+        load5: -1.0,
+        load15: -1.0,
+        memory_total_bytes: -1,
+        memory_available_bytes: -1,
+        disk_read_bytes: -1,
+        disk_written_bytes: -1,
+        net_received_bytes: -1,
+        net_sent_bytes: -1,
+    };
+
+    //cpu user nice system idle iowait irq softirq steal ...
+    let stat = std::fs::read_to_string(format!("{}/stat", proc_root))?;
+    if let Some(line) = stat.lines().find(|x| x.starts_with("cpu ")) {
+        let values: Vec<i64> = line.split_whitespace().skip(1).filter_map(|x| x.parse::<i64>().ok()).collect();
+        //guest时间已经包含在user中
+        let total: i64 = values.iter().take(8).sum();
+        let idle = values.get(3).cloned().unwrap_or(0) + values.get(4).cloned().unwrap_or(0);
+        metrics.cpu_total = total;
+        metrics.cpu_busy = total - idle;
+    }
+
+    if let Ok(loadavg) = std::fs::read_to_string(format!("{}/loadavg", proc_root)) {
+        let values: Vec<f64> = loadavg.split_whitespace().take(3).filter_map(|x| x.parse::<f64>().ok()).collect();
+        if values.len() == 3 {
+            metrics.load1 = values[0];
+            metrics.load5 = values[1];
+            metrics.load15 = values[2];
+        }
+    }
+
+    if let Ok(meminfo) = std::fs::read_to_string(format!("{}/meminfo", proc_root)) {
+        for line in meminfo.lines() {
+            let mut parts = line.split_whitespace();
+            let key = parts.next().unwrap_or("");
+            let kb = parts.next().and_then(|x| x.parse::<i64>().ok()).unwrap_or(-1);
+            match key {
+                "MemTotal:" => metrics.memory_total_bytes = kb * 1024,
+                "MemAvailable:" => metrics.memory_available_bytes = kb * 1024,
+                _ => {}
+            }
+        }
+    }
+
+    //major minor name reads merged sectors_read ms writes merged sectors_written ...
+    if let Ok(diskstats) = std::fs::read_to_string(format!("{}/diskstats", proc_root)) {
+        let mut read = 0;
+        let mut written = 0;
+        for line in diskstats.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || !is_physical_disk(fields[2]) {
+                continue;
+            }
+            read += fields[5].parse::<i64>().unwrap_or(0);
+            written += fields[9].parse::<i64>().unwrap_or(0);
+        }
+        metrics.disk_read_bytes = read * SECTOR_BYTES;
+        metrics.disk_written_bytes = written * SECTOR_BYTES;
+    }
+
+    //iface: rx_bytes packets errs drop fifo frame compressed multicast tx_bytes ...
+    if let Ok(netdev) = std::fs::read_to_string(format!("{}/net/dev", proc_root)) {
+        let mut received = 0;
+        let mut sent = 0;
+        for line in netdev.lines().skip(2) {
+            let pos = match line.find(':') {
+                Some(x) => x,
+                None => continue
+            };
+            if line[..pos].trim() == "lo" {
+                continue;
+            }
+            let fields: Vec<i64> = line[pos + 1..].split_whitespace().filter_map(|x| x.parse::<i64>().ok()).collect();
+            if fields.len() >= 9 {
+                received += fields[0];
+                sent += fields[8];
+            }
+        }
+        metrics.net_received_bytes = received;
+        metrics.net_sent_bytes = sent;
+    }
+    Ok(metrics)
+}
+
+//只统计整块磁盘，避免分区重复计算
+fn is_physical_disk(name: &str) -> bool {
+    if name.starts_with("loop") || name.starts_with("ram") || name.starts_with("dm-") {
+        return false;
+    }
+    if name.starts_with("nvme") || name.starts_with("mmcblk") {
+        //nvme0n1 / nvme0n1p1, mmcblk0 / mmcblk0p1
+        return !name.trim_start_matches("nvme").trim_start_matches("mmcblk").contains('p');
+    }
+    //sda / sda1, vda / vda1
+    !name.ends_with(|c: char| c.is_ascii_digit())
+}
@@ -1,109 +1,193 @@
-// This example serves the docs from `target/doc/`.
-//
-// Run `cargo doc && cargo run --example doc_server`, then
-// point your browser to http://localhost:3000/
-
-use futures::{future, Async::*, Future, Poll};
-use http::response::Builder as ResponseBuilder;
-use http::{header, Request, Response, StatusCode};
-use hyper::Body;
-use hyper_staticfile::{Static, StaticFuture};
-use std::io::Error;
-use std::path::Path;
-
-/// Future returned from `MainService`.
-enum MainFuture {
-    Root,
-    Static(StaticFuture<Body>),
-}
-
-impl Future for MainFuture {
-    type Item = Response<Body>;
-    type Error = Error;
-
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        match *self {
-            MainFuture::Root => {
-                let res = ResponseBuilder::new()
-                    .status(StatusCode::FOUND)
-                    .header(header::LOCATION, "/index.html")
-                    .body(Body::empty())
-                    .expect("unable to build response");
-                Ok(Ready(res))
-            }
-            MainFuture::Static(ref mut future) => future.poll(),
-        }
-    }
-}
-
-/// Hyper `Service` implementation that serves all requests.
-struct MainService {
-    static_: Static,
-}
-
-impl MainService {
-    fn new(static_dir: &str) -> MainService {
-        MainService {
-            static_: Static::new(Path::new(static_dir)),
-        }
-    }
-}
-
-impl hyper::service::Service for MainService {
-    type ReqBody = Body;
-    type ResBody = Body;
-    type Error = Error;
-    type Future = MainFuture;
-
-    fn call(&mut self, req: Request<Body>) -> MainFuture {
-//        if req.uri().path() == "/" {
-//            MainFuture::Root
-//        } else {
-//            MainFuture::Static(self.static_.serve(req))
-//        }
-        MainFuture::Static(self.static_.serve(req))
-    }
-}
-
-pub struct SimpleHttpServer {
-
-}
-
-impl SimpleHttpServer {
-    pub fn start_server(){
-
-        let mut static_dir = "static/";
-        if let Ok(r) = std::fs::read_dir("res/static/") {
-            static_dir = "res/static/";
-        }
-        println!("http static dir: {}", static_dir);
-
-        let addr = ([0, 0, 0, 0], 3890).into();
-        match hyper::Server::try_bind(&addr) {
-            Ok(builder) => {
-                let server = builder
-                    .serve(move || future::ok::<_, Error>(MainService::new(static_dir)))
-                    .map_err(|e| eprintln!("server error: {}", e));
-                println!("Http server running on http://127.0.0.1:{}/", addr.port());
-                //println!("Simpleui: http://127.0.0.1:{}/simpleui/", addr.port());
-                hyper::rt::run(server);
-            },
-            Err(e) => {
-                println!("Start flare web server failed, bind addr: {}, error: {}", addr, e);
-            }
-        }
-
-    }
-
-}
-
-
-// Application entry point.
-//fn main() {
-//    let addr = ([127, 0, 0, 1], 3000).into();
-//    let server = hyper::Server::bind(&addr)
-//        .serve(|| future::ok::<_, Error>(MainService::new()))
-//        .map_err(|e| eprintln!("server error: {}", e));
-//    eprintln!("Doc server running on http://{}/", addr);
-//    hyper::rt::run(server);
+// This example serves the docs from `target/doc/`.
+//
+// Run `cargo doc && cargo run --example doc_server`, then
+// point your browser to http://localhost:3000/
+
+use futures::{future, Async::*, Future, Poll, Stream};
+use futures::sync::oneshot;
+use http::response::Builder as ResponseBuilder;
+use http::{header, Request, Response, StatusCode};
+use hyper::Body;
+use hyper_staticfile::{Static, StaticFuture};
+use std::io::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use profiler::Profiler;
+use grafana::*;
+use embedded::find_static_dir;
+use serde_json::json;
+use task_pool::{TaskPool, TaskPriority};
+
+//Grafana查询的线程数，所有查询共用一个任务键
+const GRAFANA_WORKERS: usize = 2;
+const GRAFANA_TASK_KEY: &str = "grafana";
+
+/// Future returned from `MainService`.
+enum MainFuture {
+    Root,
+    Static(StaticFuture<Body>),
+    Api(Box<Future<Item=Response<Body>, Error=Error> + Send>),
+}
+
+impl Future for MainFuture {
+    type Item = Response<Body>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            MainFuture::Root => {
+                let res = ResponseBuilder::new()
+                    .status(StatusCode::FOUND)
+                    .header(header::LOCATION, "/index.html")
+                    .body(Body::empty())
+                    .expect("unable to build response");
+                Ok(Ready(res))
+            }
+            MainFuture::Static(ref mut future) => future.poll(),
+            MainFuture::Api(ref mut future) => future.poll(),
+        }
+    }
+}
+
+/// Hyper `Service` implementation that serves all requests.
+struct MainService {
+    static_: Static,
+    profiler: Arc<Mutex<Profiler>>,
+    grafana_pool: Arc<TaskPool>,
+}
+
+impl MainService {
+    fn new(static_dir: &str, profiler: Arc<Mutex<Profiler>>, grafana_pool: Arc<TaskPool>) -> MainService {
+        MainService {
+            static_: Static::new(Path::new(static_dir)),
+            profiler,
+            grafana_pool,
+        }
+    }
+
+    //Grafana数据源接口，浏览器直连模式需要跨域访问，只允许配置的来源(grafana_cors_origins)
+    //查询读取取样文件，在线程池中执行，不阻塞http服务的事件循环
+    fn serve_grafana(&self, req: Request<Body>) -> MainFuture {
+        let profiler = self.profiler.clone();
+        let grafana_pool = self.grafana_pool.clone();
+        let path = req.uri().path().to_string();
+        let token = get_request_token(&req);
+        let origin = req.headers().get(header::ORIGIN).and_then(|x| x.to_str().ok()).map(|x| x.to_string());
+        let is_options = req.method() == http::Method::OPTIONS;
+        let future = req.into_body().concat2()
+            .map_err(|e| Error::new(std::io::ErrorKind::Other, e))
+            .and_then(move |body| {
+                let (tx, rx) = oneshot::channel();
+                grafana_pool.submit(GRAFANA_TASK_KEY, TaskPriority::INTERACTIVE, move || {
+                    let response = build_grafana_response(&profiler, &token, &path, &body, origin, is_options);
+                    let _ = tx.send(response);
+                });
+                rx.map_err(|_| Error::new(std::io::ErrorKind::Other, "grafana request task is cancelled"))
+            });
+        MainFuture::Api(Box::new(future))
+    }
+}
+
+fn build_grafana_response(profiler: &Arc<Mutex<Profiler>>, token: &str, path: &str, body: &[u8], origin: Option<String>, is_options: bool) -> Response<Body> {
+    let (status, content) = if is_options {
+        (StatusCode::OK, String::new())
+    } else {
+        match handle_grafana_request(profiler, token, path, body) {
+            Ok(data) => (StatusCode::OK, data.to_string()),
+            Err(e) => {
+                let status = match e.kind() {
+                    std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                    std::io::ErrorKind::PermissionDenied if token.is_empty() => StatusCode::UNAUTHORIZED,
+                    std::io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                (status, json!({"message": e.to_string()}).to_string())
+            }
+        }
+    };
+    let mut builder = ResponseBuilder::new();
+    builder.status(status).header(header::CONTENT_TYPE, "application/json");
+    //没有配置允许的来源时不返回跨域头，浏览器拒绝跨域访问(Grafana服务端代理模式不受影响)
+    let allowed_origins = profiler.lock().unwrap().get_grafana_cors_origins();
+    if let Some(origin) = origin.filter(|x| is_cors_origin_allowed(&allowed_origins, x)) {
+        builder.header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.as_str())
+            .header(header::VARY, "Origin")
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
+            .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "accept, authorization, content-type");
+    }
+    builder.body(Body::from(content)).expect("unable to build response")
+}
+
+//请求头 Authorization: Bearer <token>，或者参数 token
+fn get_request_token(req: &Request<Body>) -> String {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION).and_then(|x| x.to_str().ok()) {
+        if value.starts_with("Bearer ") {
+            return value["Bearer ".len()..].trim().to_string();
+        }
+    }
+    req.uri().query().unwrap_or("").split('&')
+        .find(|x| x.starts_with("token="))
+        .map(|x| x["token=".len()..].to_string())
+        .unwrap_or_default()
+}
+
+impl hyper::service::Service for MainService {
+    type ReqBody = Body;
+    type ResBody = Body;
+    type Error = Error;
+    type Future = MainFuture;
+
+    fn call(&mut self, req: Request<Body>) -> MainFuture {
+//        if req.uri().path() == "/" {
+//            MainFuture::Root
+//        } else {
+//            MainFuture::Static(self.static_.serve(req))
+//        }
+        if req.uri().path().starts_with(GRAFANA_PATH_PREFIX) {
+            return self.serve_grafana(req);
+        }
+        MainFuture::Static(self.static_.serve(req))
+    }
+}
+
+pub struct SimpleHttpServer {
+
+}
+
+impl SimpleHttpServer {
+    pub fn start_server(profiler: Arc<Mutex<Profiler>>){
+
+        //发布目录中的 res/static/，单文件部署时使用解压的嵌入文件
+        let static_dir = find_static_dir().unwrap_or_else(|| "static/".to_string());
+        println!("http static dir: {}", static_dir);
+
+        let grafana_pool = Arc::new(TaskPool::new(GRAFANA_WORKERS, GRAFANA_WORKERS));
+        let addr = ([0, 0, 0, 0], 3890).into();
+        match hyper::Server::try_bind(&addr) {
+            Ok(builder) => {
+                let server = builder
+                    .serve(move || future::ok::<_, Error>(MainService::new(&static_dir, profiler.clone(), grafana_pool.clone())))
+                    .map_err(|e| eprintln!("server error: {}", e));
+                println!("Http server running on http://127.0.0.1:{}/", addr.port());
+                //println!("Simpleui: http://127.0.0.1:{}/simpleui/", addr.port());
+                hyper::rt::run(server);
+            },
+            Err(e) => {
+                println!("Start flare web server failed, bind addr: {}, error: {}", addr, e);
+            }
+        }
+
+    }
+
+}
+
+
+// Application entry point.
+//fn main() {
+//    let addr = ([127, 0, 0, 1], 3000).into();
+//    let server = hyper::Server::bind(&addr)
+//        .serve(|| future::ok::<_, Error>(MainService::new()))
+//        .map_err(|e| eprintln!("server error: {}", e));
+//    eprintln!("Doc server running on http://{}/", addr);
+//    hyper::rt::run(server);
 //}
\ No newline at end of file
@@ -0,0 +1,170 @@
+
+//服务端面向用户的文本的本地化：问题洞察、报告模板及错误信息，目前支持英文(en)和中文(zh)
+//语言由请求参数 lang 指定，没有指定时使用配置文件的 language，默认英文
+//  翻译缺失时使用英文文本，错误信息只翻译常见的固定格式，其它保持原样
+
+pub const LANG_EN: &str = "en";
+pub const LANG_ZH: &str = "zh";
+pub const SUPPORTED_LANGS: &[&str] = &[LANG_EN, LANG_ZH];
+
+//(key, en, zh)，文本中的 {name} 为参数
+const MESSAGES: &[(&str, &str, &str)] = &[
+    //insights
+    ("insight.spin_loop", "thread '{thread}' is busy spinning in {frame}", "线程 '{thread}' 在 {frame} 中忙等待"),
+    ("insight.pool_starvation", "all {threads} threads of pool '{pool}' are blocked at {blocked_at} for {duration}ms",
+     "线程池 '{pool}' 的全部 {threads} 个线程阻塞在 {blocked_at}，持续 {duration}ms"),
+    ("insight.deadlock", "deadlock detected between threads: {threads}", "检测到线程死锁: {threads}"),
+    ("insight.finalizer", "finalization is a bottleneck: up to {pending} objects pending finalization, Finalizer thread busy {busy}%",
+     "finalization 成为瓶颈: 最多 {pending} 个对象等待执行 finalize()，Finalizer 线程忙碌 {busy}%"),
+    ("insight.class_loader_leak.loader_type", "possible class loader leak: {instances} instances of {loader} hold {classes} classes (+{growth})",
+     "可能的类加载器泄漏: {loader} 的 {instances} 个实例持有 {classes} 个类 (+{growth})"),
+    ("insight.class_loader_leak.loader", "possible class loader leak: {loader} classes only grow, {first} -> {last}",
+     "可能的类加载器泄漏: {loader} 的类数量只增不减，{first} -> {last}"),
+    ("severity.critical", "critical", "严重"),
+    ("severity.warning", "warning", "警告"),
+    ("severity.info", "info", "提示"),
+    //report
+    ("report.title", "Flare Profiler Report", "Flare Profiler 报告"),
+    ("report.summary", "Summary", "概要"),
+    ("report.insights", "Insights", "问题洞察"),
+    ("report.top_methods", "Top Methods", "热点方法"),
+    ("report.flame_graph", "Flame Graph", "火焰图"),
+    ("report.gc_pauses", "GC Pauses", "GC暂停"),
+    ("report.no_issues", "No issues found.", "没有发现问题。"),
+    ("report.no_samples", "No samples.", "没有取样数据。"),
+    ("report.no_gc_pauses", "No GC pauses recorded (agent option gc_interval).", "没有记录GC暂停 (agent参数 gc_interval)。"),
+    ("report.agent", "Agent", "Agent"),
+    ("report.sample_dir", "Sample directory", "取样目录"),
+    ("report.time_range", "Time range", "时间范围"),
+    ("report.duration", "Duration", "时长"),
+    ("report.sample_interval", "Sample interval", "取样间隔"),
+    ("report.threads", "Threads", "线程数"),
+    ("report.samples", "Samples", "取样数"),
+    ("report.cpu_time", "CPU time", "CPU时间"),
+    ("report.gc_pause_summary", "{count} ({total}ms total, {max}ms max, {ratio}% of time)", "{count} 次 (共 {total}ms，最长 {max}ms，占 {ratio}% 时间)"),
+    ("report.generated_at", "Generated at", "生成时间"),
+    ("report.severity", "Severity", "严重程度"),
+    ("report.kind", "Kind", "类型"),
+    ("report.insight_title", "Title", "描述"),
+    ("report.method", "Method", "方法"),
+    ("report.self_samples", "Self samples", "自身取样数"),
+    ("report.self_percent", "Self %", "自身占比"),
+    ("report.total_samples", "Total samples", "总取样数"),
+    ("report.self_cpu_time", "Self CPU time", "自身CPU时间"),
+    ("report.time", "Time", "时间"),
+    ("report.pause", "Pause", "暂停时间"),
+];
+
+//(en, zh)，{} 匹配任意文本，按顺序替换
+const ERROR_MESSAGES: &[(&str, &str)] = &[
+    ("sample session not found", "取样会话不存在"),
+    ("sample data dir is not created", "取样目录尚未创建"),
+    ("time period error, end_time must be greater than start_time", "时间范围错误，end_time 必须大于 start_time"),
+    ("time period error, sessions have no recorded data", "时间范围错误，会话没有录制数据"),
+    ("missing attribute 'cmd'", "缺少属性 'cmd'"),
+    ("missing time range", "缺少时间范围"),
+    ("missing option: {}", "缺少选项: {}"),
+    ("missing option '{}'", "缺少选项 '{}'"),
+    ("option '{}' is not int array ", "选项 '{}' 不是整数数组"),
+    ("option '{}' is not string array ", "选项 '{}' 不是字符串数组"),
+    ("unsupported cmd: {}, protocol version: {}", "不支持的命令: {}，协议版本: {}"),
+    ("invalid stats_type: {}", "无效的统计类型: {}"),
+    ("invalid report format: {}, expect one of {}", "无效的报告格式: {}，可选值: {}"),
+    ("pdf report is disabled, set 'pdf_tool' in config file", "未开启PDF报告，请在配置文件中设置 'pdf_tool'"),
+    ("thread dump requires an attach session", "线程dump需要连接中的会话"),
+    ("heap histogram requires an attach session", "堆直方图需要连接中的会话"),
+    ("deadlock detection requires an attach session", "死锁检测需要连接中的会话"),
+    ("view name is empty", "视图名称为空"),
+    ("authentication required, send token in hello request", "需要认证，请在 hello 请求中提供 token"),
+    ("invalid access token", "无效的访问令牌"),
+    ("access denied, token '{}' is restricted to sessions: {}", "拒绝访问，令牌 '{}' 只能访问指定的会话: {}"),
+    ("access denied, token '{}' can not access session: {}", "拒绝访问，令牌 '{}' 不能访问会话: {}"),
+    ("server is read-only, cmd is rejected: {}", "服务为只读模式，拒绝执行命令: {}"),
+    ("audit log requires an admin token", "查询审计日志需要管理员令牌"),
+    ("invalid sort_by: {}, expect one of {}", "无效的排序字段: {}，可选值: {}"),
+    ("invalid order: {}, expect asc or desc", "无效的排序顺序: {}，可选值: asc, desc"),
+    ("invalid level: {}, expect one of {}", "无效的级别: {}，可选值: {}"),
+    ("interval_ms and max_buffer_bytes must not be negative", "interval_ms 及 max_buffer_bytes 不能为负数"),
+    ("can not set flush policy of a saved sample", "不能修改已保存取样的刷新策略"),
+    ("sample session is not recording", "取样会话没有在录制"),
+    ("unknown disk guard action: {}, expect one of {}", "未知的磁盘空间保护动作: {}，可选值: {}"),
+    ("span timing is already running", "区段计时已经在运行"),
+    ("span timing is not running", "区段计时没有运行"),
+    ("interval_ms must be positive and duration_secs must not be negative", "interval_ms 必须为正数，duration_secs 不能为负数"),
+    ("unknown span timing action: {}", "未知的区段计时操作: {}"),
+];
+
+//zh-CN、zh_TW 等都使用中文，不支持的语言使用英文
+pub fn normalize_lang(lang: &str) -> &'static str {
+    let lang = lang.trim().to_lowercase();
+    if lang == LANG_ZH || lang.starts_with("zh-") || lang.starts_with("zh_") {
+        LANG_ZH
+    } else {
+        LANG_EN
+    }
+}
+
+//没有翻译时返回key
+pub fn tr(lang: &str, key: &str) -> String {
+    match MESSAGES.iter().find(|x| x.0 == key) {
+        Some((_, en, zh)) => if normalize_lang(lang) == LANG_ZH { zh.to_string() } else { en.to_string() },
+        None => key.to_string()
+    }
+}
+
+pub fn tr_args(lang: &str, key: &str, args: &[(&str, String)]) -> String {
+    let mut text = tr(lang, key);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+//按顺序匹配模板中的固定文本，返回 {} 对应的文本
+fn match_template<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let parts: Vec<&str> = template.split("{}").collect();
+    if !message.starts_with(parts[0]) {
+        return None;
+    }
+    let mut rest = &message[parts[0].len()..];
+    let mut values = vec![];
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        //最后一段必须匹配到结尾
+        let pos = if i == parts.len() - 1 {
+            if !rest.ends_with(part) {
+                return None;
+            }
+            rest.len() - part.len()
+        } else {
+            match rest.find(part) {
+                Some(pos) if !part.is_empty() => pos,
+                _ => return None
+            }
+        };
+        values.push(&rest[..pos]);
+        rest = &rest[pos + part.len()..];
+    }
+    if parts.len() == 1 && !rest.is_empty() {
+        return None;
+    }
+    Some(values)
+}
+
+pub fn localize_error(lang: &str, message: &str) -> String {
+    if normalize_lang(lang) == LANG_EN {
+        return message.to_string();
+    }
+    for (en, zh) in ERROR_MESSAGES {
+        if let Some(values) = match_template(en, message) {
+            let mut text = String::new();
+            for (i, part) in zh.split("{}").enumerate() {
+                if i > 0 {
+                    text.push_str(values.get(i - 1).cloned().unwrap_or(""));
+                }
+                text.push_str(part);
+            }
+            return text;
+        }
+    }
+    message.to_string()
+}
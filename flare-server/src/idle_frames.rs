@@ -0,0 +1,77 @@
+
+use std::io;
+use std::str::FromStr;
+use utils::new_invalid_input_error;
+
+//wall-clock模式下常见的空闲(park/wait/sleep/poll)方法
+pub const IDLE_FRAME_PATTERNS: &[&str] = &[
+    "sun.misc.Unsafe.park",
+    "jdk.internal.misc.Unsafe.park",
+    "java.util.concurrent.locks.LockSupport.park",
+    "java.lang.Object.wait",
+    "java.lang.Thread.sleep",
+    "java.lang.Thread.yield",
+    "java.lang.ref.Reference.waitForReferencePendingList",
+    "sun.nio.ch.EPollArrayWrapper.epollWait",
+    "sun.nio.ch.EPollArrayWrapper.poll",
+    "sun.nio.ch.EPoll.wait",
+    "sun.nio.ch.EPollSelectorImpl.doSelect",
+    "sun.nio.ch.KQueue.poll",
+    "sun.nio.ch.KQueueArrayWrapper.kevent0",
+    "sun.nio.ch.KQueueArrayWrapper.poll",
+    "sun.nio.ch.WindowsSelectorImpl$SubSelector.poll0",
+    "sun.nio.ch.WindowsSelectorImpl$SubSelector.poll",
+    "sun.nio.ch.ServerSocketChannelImpl.accept0",
+    "java.net.PlainSocketImpl.socketAccept",
+    "java.net.DualStackPlainSocketImpl.accept0",
+];
+
+pub fn is_idle_frame(method_name: &str) -> bool {
+    IDLE_FRAME_PATTERNS.iter().any(|pattern| method_name.starts_with(pattern))
+}
+
+// 空闲方法的处理方式
+#[derive(Eq, PartialEq, Debug, Clone, Copy, EnumString)]
+pub enum IdleMode {
+    //保留原始调用栈
+    #[strum(serialize="keep")]
+    KEEP,
+
+    //将栈顶连续的空闲方法合并为一个 <idle> 节点
+    #[strum(serialize="collapse")]
+    COLLAPSE,
+
+    //丢弃空闲的取样
+    #[strum(serialize="trim")]
+    TRIM,
+}
+
+#[derive(Clone, Serialize, Default)]
+pub struct IdleStats {
+    pub total_samples: i64,
+    pub idle_samples: i64,
+    pub total_duration: i64,
+    pub idle_duration: i64,
+    pub idle_percent: f64,
+}
+
+impl IdleStats {
+    pub fn add_sample(&mut self, duration: i64, idle: bool) {
+        self.total_samples += 1;
+        self.total_duration += duration;
+        if idle {
+            self.idle_samples += 1;
+            self.idle_duration += duration;
+        }
+        if self.total_duration > 0 {
+            self.idle_percent = self.idle_duration as f64 * 100.0 / self.total_duration as f64;
+        }
+    }
+}
+
+pub fn parse_idle_mode(idle_mode: &str) -> io::Result<IdleMode> {
+    match IdleMode::from_str(idle_mode) {
+        Ok(x) => Ok(x),
+        Err(_) => Err(new_invalid_input_error(&format!("invalid idle_mode: {}", idle_mode)))
+    }
+}
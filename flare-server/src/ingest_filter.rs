@@ -0,0 +1,71 @@
+
+//写入时的过滤：按会话配置丢弃部分线程取样，减少噪声多的目标进程占用的存储
+//  drop_threads: 丢弃线程名称匹配的线程(通配符 *)
+//  max_depth: 只保留靠近栈顶的N个栈帧，0表示不限制
+//  thread_sample_rate: 每N个线程只保留一个(按线程ID取模)，0或1表示不抽样
+//配置及过滤的数量保存在 summary_info.json 中，分析时可以知道数据是否完整
+
+use std::io;
+use flare_proto::agent::ThreadEvent;
+use pool_starvation::match_wildcard;
+use utils::*;
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct IngestFilter {
+    #[serde(default)]
+    pub drop_threads: Vec<String>,
+    #[serde(default)]
+    pub max_depth: usize,
+    #[serde(default)]
+    pub thread_sample_rate: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct IngestStats {
+    //按线程名称丢弃的取样数量
+    pub dropped_by_name: i64,
+    //抽样丢弃的取样数量
+    pub dropped_by_sampling: i64,
+    //截断调用栈的取样数量
+    pub truncated_stacks: i64,
+}
+
+impl IngestFilter {
+    pub fn from_options(options: &serde_json::Map<String, serde_json::Value>) -> io::Result<IngestFilter> {
+        let drop_threads = match options.get("drop_threads") {
+            Some(_) => get_option_as_str_array(options, "drop_threads")?,
+            None => vec![]
+        };
+        let max_depth = get_option_as_int(options, "max_depth", 0);
+        let thread_sample_rate = get_option_as_int(options, "thread_sample_rate", 0);
+        if max_depth < 0 || thread_sample_rate < 0 {
+            return Err(new_invalid_input_error("max_depth and thread_sample_rate must not be negative"));
+        }
+        Ok(IngestFilter {
+            drop_threads,
+            max_depth: max_depth as usize,
+            thread_sample_rate,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drop_threads.is_empty() && self.max_depth == 0 && self.thread_sample_rate <= 1
+    }
+
+    //返回false表示丢弃这个取样，保留时可能截断调用栈
+    pub fn apply(&self, event: &mut ThreadEvent, stats: &mut IngestStats) -> bool {
+        if self.drop_threads.iter().any(|x| match_wildcard(x, &event.name)) {
+            stats.dropped_by_name += 1;
+            return false;
+        }
+        if self.thread_sample_rate > 1 && event.id % self.thread_sample_rate != 0 {
+            stats.dropped_by_sampling += 1;
+            return false;
+        }
+        if self.max_depth > 0 && event.stacktrace.len() > self.max_depth {
+            event.stacktrace.truncate(self.max_depth);
+            stats.truncated_stacks += 1;
+        }
+        true
+    }
+}
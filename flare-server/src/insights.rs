@@ -0,0 +1,109 @@
+
+//问题洞察报告：汇总各个启发式分析的结果，按严重程度排序
+//  spin_loop: 忙等待/自旋的线程
+//  pool_starvation: 线程池饥饿
+//  deadlock: agent检测到的死锁
+//  finalizer: finalizer积压
+//  class_loader_leak: 类数量只增不减的类加载器
+
+use ::sample::*;
+use std::io;
+use serde_json::json;
+use spin_loop::*;
+use pool_starvation::*;
+use deadlock::get_deadlocks;
+use finalizer::*;
+use classloader::*;
+use i18n::tr_args;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Insight {
+    pub kind: String,
+    //critical, warning, info
+    pub severity: String,
+    pub title: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    //分析结果的详细数据，包含代表性的调用栈
+    pub detail: serde_json::Value,
+}
+
+fn severity_order(severity: &str) -> i32 {
+    match severity {
+        "critical" => 0,
+        "warning" => 1,
+        _ => 2
+    }
+}
+
+//lang: 标题的语言
+pub fn generate_insights(collector: &mut SampleCollector, start_time: i64, end_time: i64, lang: &str) -> io::Result<Vec<Insight>> {
+    let mut insights = vec![];
+    for thread in detect_spin_loops(collector, start_time, end_time, &SpinLoopOptions::default())? {
+        insights.push(Insight {
+            kind: "spin_loop".to_string(),
+            severity: if thread.cpu_ratio >= 0.95 { "critical" } else { "warning" }.to_string(),
+            title: tr_args(lang, "insight.spin_loop", &[("thread", thread.thread_name.clone()),
+                ("frame", thread.frames.first().cloned().unwrap_or_default())]),
+            start_time: thread.start_time,
+            end_time: thread.end_time,
+            detail: json!(thread),
+        });
+    }
+    for episode in detect_pool_starvation(collector, start_time, end_time, &StarvationOptions::default())? {
+        insights.push(Insight {
+            kind: "pool_starvation".to_string(),
+            severity: "critical".to_string(),
+            title: tr_args(lang, "insight.pool_starvation", &[("threads", episode.threads.len().to_string()), ("pool", episode.pool.clone()),
+                ("blocked_at", episode.blocked_at.clone()), ("duration", episode.duration_ms.to_string())]),
+            start_time: episode.start_time,
+            end_time: episode.end_time,
+            detail: json!(episode),
+        });
+    }
+    for cycle in get_deadlocks(collector, start_time, end_time) {
+        let names: Vec<&str> = cycle.threads.iter().map(|x| x.thread_name.as_str()).collect();
+        insights.push(Insight {
+            kind: "deadlock".to_string(),
+            severity: "critical".to_string(),
+            title: tr_args(lang, "insight.deadlock", &[("threads", names.join(", "))]),
+            start_time: cycle.time,
+            end_time: cycle.time,
+            detail: json!(cycle),
+        });
+    }
+    let finalizer_options = FinalizerOptions::default();
+    if let Some(pressure) = detect_finalizer_pressure(collector, start_time, end_time, &finalizer_options)? {
+        let growing = pressure.is_growing(&finalizer_options);
+        let busy = pressure.is_busy(&finalizer_options);
+        insights.push(Insight {
+            kind: "finalizer".to_string(),
+            severity: if growing && busy { "critical" } else { "warning" }.to_string(),
+            title: tr_args(lang, "insight.finalizer", &[("pending", pressure.max_pending.to_string()),
+                ("busy", format!("{:.0}", pressure.busy_ratio * 100.0))]),
+            start_time: pressure.start_time,
+            end_time: pressure.end_time,
+            detail: json!(pressure),
+        });
+    }
+    for leak in detect_class_loader_leaks(collector, start_time, end_time, &ClassLoaderLeakOptions::default()) {
+        let title = if leak.kind == "loader_type" {
+            tr_args(lang, "insight.class_loader_leak.loader_type", &[("instances", leak.last_instances.to_string()), ("loader", leak.loader_name.clone()),
+                ("classes", leak.last_classes.to_string()), ("growth", leak.growth.to_string())])
+        } else {
+            tr_args(lang, "insight.class_loader_leak.loader", &[("loader", leak.loader_name.clone()),
+                ("first", leak.first_classes.to_string()), ("last", leak.last_classes.to_string())])
+        };
+        insights.push(Insight {
+            kind: "class_loader_leak".to_string(),
+            severity: "warning".to_string(),
+            title,
+            start_time: leak.first_time,
+            end_time: leak.last_time,
+            detail: json!(leak),
+        });
+    }
+    insights.sort_by(|a, b| severity_order(&a.severity).cmp(&severity_order(&b.severity))
+        .then((b.end_time - b.start_time).cmp(&(a.end_time - a.start_time))));
+    Ok(insights)
+}
@@ -1,41 +1,115 @@
-//extern crate libc;
-#[macro_use]
-extern crate lazy_static;
-extern crate time;
-extern crate toml;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde_json;
-extern crate serde;
-#[macro_use] extern crate log;
-extern crate env_logger;
-extern crate resp;
-extern crate chrono;
-extern crate websocket;
-extern crate timer;
-extern crate flare_utils;
-extern crate inferno;
-// Strum contains all the trait definitions
-extern crate strum;
-#[macro_use]
-extern crate strum_macros;
-
-extern crate futures;
-extern crate http;
-extern crate hyper;
-extern crate hyper_staticfile;
-
-
-//re-export
-pub use profiler::*;
-
-pub mod sample;
-mod profiler;
-mod tree;
-mod call_tree;
-mod http_server;
-pub mod utils;
-pub mod sample_encoder;
-mod method_analysis;
-
-
+extern crate libc;
+#[macro_use]
+extern crate lazy_static;
+extern crate time;
+extern crate toml;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde;
+#[macro_use] extern crate log;
+extern crate env_logger;
+extern crate resp;
+extern crate chrono;
+extern crate websocket;
+extern crate native_tls;
+extern crate timer;
+extern crate flare_utils;
+extern crate flare_proto;
+extern crate inferno;
+// Strum contains all the trait definitions
+extern crate strum;
+#[macro_use]
+extern crate strum_macros;
+
+extern crate futures;
+extern crate http;
+extern crate hyper;
+extern crate hyper_staticfile;
+
+
+//re-export
+pub use profiler::*;
+
+pub mod sample;
+mod profiler;
+mod tree;
+mod call_tree;
+mod http_server;
+pub mod utils;
+pub mod sample_encoder;
+mod method_analysis;
+mod database_analysis;
+mod idle_frames;
+mod sample_writer;
+pub mod sample_split;
+mod marker;
+pub mod sample_export;
+mod deobfuscate;
+pub mod symbol_cache;
+pub mod agg_index;
+mod task_pool;
+mod config;
+mod samples_watcher;
+mod protocol;
+pub mod command_recorder;
+pub mod testkit;
+pub mod sample_generator;
+pub mod sample_migrate;
+pub mod query_dsl;
+pub mod metrics_export;
+pub mod grafana;
+pub mod webhook;
+pub mod trigger;
+pub mod plugins;
+pub mod plugin_script;
+pub mod runtime_adapter;
+pub mod perf_import;
+pub mod offcpu;
+pub mod metric_series;
+pub mod cgroup_metrics;
+pub mod host_metrics;
+pub mod process_tree;
+pub mod record_group;
+pub mod warmup;
+pub mod pool_starvation;
+pub mod spin_loop;
+pub mod insights;
+pub mod deadlock;
+pub mod thread_dump;
+pub mod heap_histogram;
+pub mod allocation;
+pub mod finalizer;
+pub mod deopt;
+pub mod classloader;
+pub mod ingest_filter;
+pub mod gc;
+pub mod saved_view;
+pub mod report;
+pub mod i18n;
+pub mod format_hints;
+pub mod baseline;
+pub mod schema;
+pub mod paging;
+pub mod rate_limit;
+pub mod access;
+pub mod audit;
+pub mod session_events;
+pub mod data_quality;
+pub mod clock_sync;
+pub mod monotonic_time;
+pub mod flush_policy;
+pub mod disk_guard;
+pub mod storage_usage;
+pub mod self_profile;
+pub mod thread_handles;
+pub mod sample_path;
+pub mod daemon;
+pub mod embedded;
+pub mod agent_attach;
+pub mod agent_mux;
+pub mod agent_recording;
+pub mod shared_stream;
+
+
+pub mod stack_record;
@@ -1,62 +1,560 @@
-extern crate flare_server;
-
-use flare_server::sample::*;
-use flare_server::*;
-use std::sync::{Mutex, Arc};
-use std::path::Path;
-
-fn main() {
-
-    init();
-
-//    match SampleCollector::new("localhost:3333") {
-//        Ok(mut collector) => {
-//            collector.subscribe_events();
-//        }
-//        Err(e) => {
-//            println!("start sample collector failed: {:?}", e);
-//        }
-//    }
-
-    let mut profiler = Profiler::new();
-//    profiler.lock().unwrap().connect_agent("localhost:3333");
-
-    //start websocket server
-    profiler.lock().unwrap().startup();
-
-
-//    let timer = timer::Timer::new();
-//    let profiler_ref = profiler.clone();
-//    let guard = {
-//        timer.schedule_repeating(chrono::Duration::milliseconds(3000), move || {
-//            println!("=======================================================");
-//            profiler_ref.lock().unwrap().get_dashboard();
-//        })
-//    };
-
-    //wait for closing
-    loop {
-        if !profiler.lock().unwrap().is_running() {
-            break;
-        }
-        std::thread::sleep(std::time::Duration::from_millis(100));
-    }
-
-//    drop(guard);
-}
-
-fn init() {
-    if let Ok(exe_path) = std::env::current_exe() {
-        let dir = exe_path.parent().unwrap();
-        let path = dir.to_str().unwrap_or("");
-        if path.ends_with("bin") {
-            let new_path = &path[0..path.len()-4];
-            std::env::set_current_dir(Path::new(new_path));
-            println!("set_current_dir: {}", new_path);
-        }
-    }
-
-    //TEST
-    //std::env::set_current_dir(Path::new("D:\\projects\\arch\\flare-profiler\\target\\flare-profiler"));
-
-}
\ No newline at end of file
+extern crate flare_server;
+extern crate serde_json;
+extern crate libc;
+
+use flare_server::sample::*;
+use flare_server::self_profile::DEFAULT_SELF_PROFILE_INTERVAL_MS;
+use flare_server::*;
+use std::io::Write;
+use std::sync::{Mutex, Arc};
+use std::path::Path;
+
+fn main() {
+    //ci-check 的标准输出只有比较结果的JSON，在输出任何日志之前把日志改到stderr
+    let ci_check_output = if std::env::args_os().nth(1).map_or(false, |x| x == "ci-check") {
+        Some(redirect_stdout_to_stderr())
+    } else {
+        None
+    };
+
+    init();
+
+    //离线工具命令
+    //非Unicode的参数(如路径)转义，不会导致panic
+    let args: Vec<String> = std::env::args_os().map(|x| sample_path::path_to_string(Path::new(&x))).collect();
+    if args.len() > 1 && args[1] == "split" {
+        split(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "build_index" {
+        build_index(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "generate" {
+        generate(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "replay" {
+        replay(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "migrate" {
+        migrate(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "query" {
+        query(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "extract" {
+        extract(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "import_perf" {
+        import_perf(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "export_metrics" {
+        export_metrics(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "service" {
+        let code = service(&args[2..]);
+        std::process::exit(code);
+    }
+    if let Some(mut output) = ci_check_output {
+        let code = ci_check(&args[2..], &mut output);
+        std::process::exit(code);
+    }
+
+//    match SampleCollector::new("localhost:3333") {
+//        Ok(mut collector) => {
+//            collector.subscribe_events();
+//        }
+//        Err(e) => {
+//            println!("start sample collector failed: {:?}", e);
+//        }
+//    }
+
+    let mut profiler = Profiler::new();
+    //flare_server --read-only
+    if args.iter().any(|x| x == "--read-only") {
+        profiler.lock().unwrap().set_read_only(true);
+    }
+    //flare_server --self-profile: 记录服务端的区段计时直到停止(self_profile 命令)
+    if args.iter().any(|x| x == "--self-profile") {
+        if let Err(e) = profiler.lock().unwrap().start_self_profiling(DEFAULT_SELF_PROFILE_INTERVAL_MS, 0) {
+            println!("start span timing failed: {}", e);
+        }
+    }
+//    profiler.lock().unwrap().connect_agent("localhost:3333");
+
+    daemon::install_reload_handler();
+    //flare_server --daemon: 写入pid文件，输出到轮转的日志文件，收到信号时关闭会话后退出
+    let mut daemon = if args.iter().any(|x| x == "--daemon") {
+        match start_daemon(&profiler.lock().unwrap().get_daemon_config()) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                println!("start daemon mode failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    //start websocket server
+    profiler.lock().unwrap().startup();
+
+
+//    let timer = timer::Timer::new();
+//    let profiler_ref = profiler.clone();
+//    let guard = {
+//        timer.schedule_repeating(chrono::Duration::milliseconds(3000), move || {
+//            println!("=======================================================");
+//            profiler_ref.lock().unwrap().get_dashboard();
+//        })
+//    };
+
+    //wait for closing
+    loop {
+        if !profiler.lock().unwrap().is_running() {
+            break;
+        }
+        //退出信号只在daemon模式下注册，SIGHUP 前台运行时也重新加载配置
+        match daemon::take_signal_event() {
+            Some(daemon::DaemonEvent::Shutdown) => {
+                println!("received shutdown signal, closing all sessions ...");
+                let mut profiler = profiler.lock().unwrap();
+                if let Err(e) = profiler.close_all_session() {
+                    println!("close sessions failed: {}", e);
+                }
+                profiler.shutdown();
+                break;
+            }
+            Some(daemon::DaemonEvent::Reload) => {
+                if let Err(e) = profiler.lock().unwrap().reload_config() {
+                    println!("reload config failed, keep current config: {}", e);
+                }
+                if let Some((_, log_file, _)) = &mut daemon {
+                    if let Err(e) = log_file.reopen() {
+                        eprintln!("reopen log file failed: {}", e);
+                    }
+                }
+            }
+            None => {}
+        }
+        if let Some((_, log_file, log_config)) = &mut daemon {
+            //日志配置改变(SIGHUP 或者 reload_config 命令)时使用新的日志文件
+            let new_log_config = profiler.lock().unwrap().get_daemon_config();
+            if new_log_config != *log_config {
+                match daemon::LogFile::open(&new_log_config.log_file, new_log_config.log_max_size_mb, new_log_config.log_max_files) {
+                    Ok(x) => {
+                        *log_file = x;
+                        *log_config = new_log_config;
+                    }
+                    Err(e) => eprintln!("open log file failed: {}, err: {}", new_log_config.log_file, e)
+                }
+            }
+            if let Err(e) = log_file.rotate_if_needed() {
+                eprintln!("rotate log file failed: {}", e);
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    if daemon.is_some() {
+        println!("flare server stopped");
+    }
+
+//    drop(guard);
+}
+
+fn init() {
+    if let Ok(exe_path) = std::env::current_exe() {
+        let dir = exe_path.parent().unwrap();
+        if dir.ends_with("bin") {
+            let new_path = dir.parent().unwrap_or(dir);
+            std::env::set_current_dir(new_path);
+            println!("set_current_dir: {}", new_path.display());
+        }
+    }
+
+    //TEST
+    //std::env::set_current_dir(Path::new("D:\\projects\\arch\\flare-profiler\\target\\flare-profiler"));
+
+}
+
+//flare_server split <sample_data_dir> [interval_minutes|markers]
+fn split(args: &[String]) {
+    if args.is_empty() {
+        println!("usage: flare_server split <sample_data_dir> [interval_minutes|markers]");
+        return;
+    }
+    let by_markers = args.get(1).map(|x| x == "markers").unwrap_or(false);
+    let interval_minutes = args.get(1).and_then(|x| x.parse::<i64>().ok()).unwrap_or(60);
+    match SampleCollector::open(sample_path::string_to_path(&args[0])) {
+        Ok(collector) => {
+            let mut collector = collector.lock().unwrap();
+            let sample_info = collector.get_sample_info();
+            let split_times = if by_markers {
+                collector.get_markers().iter().map(|x| x.time).collect()
+            } else {
+                sample_split::get_interval_split_times(sample_info.record_start_time, sample_info.last_record_time, interval_minutes * 60_000)
+            };
+            match sample_split::split_sample(&mut collector, &split_times) {
+                Ok(dirs) => {
+                    for dir in &dirs {
+                        println!("{}", dir);
+                    }
+                }
+                Err(e) => {
+                    println!("split sample failed: {}", e);
+                }
+            }
+            collector.close();
+        }
+        Err(e) => {
+            println!("open sample failed: {}", e);
+        }
+    }
+}
+
+//flare_server build_index <sample_data_dir>
+fn build_index(args: &[String]) {
+    if args.is_empty() {
+        println!("usage: flare_server build_index <sample_data_dir>");
+        return;
+    }
+    let mut last_percent = 0;
+    let result = agg_index::build_agg_index(sample_path::string_to_path(&args[0]), &mut |done, total| {
+        let percent = if total > 0 { done * 100 / total } else { 100 };
+        if percent >= last_percent + 10 || done == total {
+            last_percent = percent;
+            println!("build index: {}% ({}/{})", percent, done, total);
+        }
+    });
+    match result {
+        Ok(samples) => println!("build index is done: {}, samples: {}", args[0], samples),
+        Err(e) => println!("build index failed: {}", e)
+    }
+}
+
+//flare_server migrate <sample_data_dir>
+fn migrate(args: &[String]) {
+    if args.is_empty() {
+        println!("usage: flare_server migrate <sample_data_dir>");
+        return;
+    }
+    match sample_migrate::migrate_sample(&args[0]) {
+        Ok(stats) => match stats.backup_dir {
+            Some(backup_dir) => println!("migrate sample is done: {}, ts files: {}, indexed files: {}, backup: {}", stats.sample_data_dir, stats.ts_files, stats.tuple_files, backup_dir),
+            None => println!("sample is already the latest format: {}", stats.sample_data_dir)
+        },
+        Err(e) => println!("migrate sample failed: {}", e)
+    }
+}
+
+//flare_server export_metrics <sample_data_dir> <csv|parquet> [export_dir] [unit_time_ms]
+fn export_metrics(args: &[String]) {
+    if args.len() < 2 {
+        println!("usage: flare_server export_metrics <sample_data_dir> <csv|parquet> [export_dir] [unit_time_ms]");
+        return;
+    }
+    let export_dir = args.get(2).cloned().unwrap_or(format!("{}-metrics", args[0].trim_end_matches(|c| c == '/' || c == '\\')));
+    let options = metrics_export::MetricsExportOptions {
+        format: args[1].clone(),
+        tables: metrics_export::EXPORT_TABLES.iter().map(|x| x.to_string()).collect(),
+        start_time: -1,
+        end_time: -1,
+        unit_time_ms: args.get(3).and_then(|x| x.parse::<i64>().ok()).unwrap_or(1000),
+        exclude_warmup: false,
+    };
+    match SampleCollector::open(sample_path::string_to_path(&args[0])) {
+        Ok(collector) => {
+            let mut collector = collector.lock().unwrap();
+            match metrics_export::export_metrics(&mut collector, &export_dir, &options) {
+                Ok(tables) => {
+                    for table in &tables {
+                        println!("{}: {} rows", table.path, table.rows);
+                    }
+                }
+                Err(e) => println!("export metrics failed: {}", e)
+            }
+            collector.close();
+        }
+        Err(e) => println!("open sample failed: {}", e)
+    }
+}
+
+//flare_server ci-check --baseline <sample_data_dir> --candidate <sample_data_dir> [--max-regression 5%] [--max-cpu-regression 5%] [--min-samples 10] [--limit 50] [--output <json_file>]
+//  --max-regression: 热点方法栈顶占比增加的百分点，如方法占比从 20% 增加到 26% 为 6 个百分点
+//  --max-cpu-regression: 总CPU(每秒CPU时间)增加的百分比，如从 200ms/s 增加到 220ms/s 为 10%，默认与 --max-regression 相同
+//标准输出只有JSON格式的比较结果，日志输出到stderr，--output 同时写入文件
+//退出码: 0 没有回归，1 有回归，2 参数或者取样错误
+fn ci_check(args: &[String], output: &mut Write) -> i32 {
+    let usage = "usage: flare_server ci-check --baseline <sample_data_dir> --candidate <sample_data_dir> [--max-regression 5%] [--max-cpu-regression 5%] [--min-samples 10] [--limit 50] [--output <json_file>]";
+    let mut baseline_dir = None;
+    let mut candidate_dir = None;
+    let mut output_file = None;
+    let mut options = baseline::CompareOptions::default();
+    let mut cpu_threshold = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = match args.get(i + 1) {
+            Some(x) => x,
+            None => {
+                eprintln!("{}", usage);
+                return 2;
+            }
+        };
+        let result = match args[i].as_str() {
+            "--baseline" => {
+                baseline_dir = Some(value.clone());
+                Ok(())
+            }
+            "--candidate" => {
+                candidate_dir = Some(value.clone());
+                Ok(())
+            }
+            "--max-regression" => baseline::parse_percent(value).map(|x| options.threshold_percent = x).map_err(|e| e.to_string()),
+            "--max-cpu-regression" => baseline::parse_percent(value).map(|x| cpu_threshold = Some(x)).map_err(|e| e.to_string()),
+            "--min-samples" => value.parse::<i64>().map(|x| options.min_samples = x).map_err(|_| format!("invalid min samples: {}", value)),
+            "--limit" => value.parse::<usize>().map(|x| options.limit = x).map_err(|_| format!("invalid limit: {}", value)),
+            "--output" => {
+                output_file = Some(value.clone());
+                Ok(())
+            }
+            _ => Err(usage.to_string())
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+        i += 2;
+    }
+    options.cpu_threshold_percent = cpu_threshold.unwrap_or(options.threshold_percent);
+    let (baseline_dir, candidate_dir) = match (baseline_dir, candidate_dir) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            eprintln!("{}", usage);
+            return 2;
+        }
+    };
+    match baseline::compare_samples(sample_path::string_to_path(&baseline_dir), sample_path::string_to_path(&candidate_dir), &options) {
+        Ok(comparison) => {
+            let json = serde_json::to_string_pretty(&comparison).unwrap_or_default();
+            if let Err(e) = writeln!(output, "{}", json).and_then(|_| output.flush()) {
+                eprintln!("write ci check result failed: {}", e);
+                return 2;
+            }
+            if let Some(output_file) = output_file {
+                if let Err(e) = std::fs::write(&output_file, json.as_bytes()) {
+                    eprintln!("write ci check result failed: {}, {}", output_file, e);
+                    return 2;
+                }
+            }
+            if comparison.is_regressed() { 1 } else { 0 }
+        }
+        Err(e) => {
+            eprintln!("ci check failed: {}", e);
+            2
+        }
+    }
+}
+
+//把标准输出(fd 1)指向stderr，返回原来的标准输出
+#[cfg(unix)]
+fn redirect_stdout_to_stderr() -> Box<Write> {
+    use std::os::unix::io::FromRawFd;
+    let _ = std::io::stdout().flush();
+    unsafe {
+        let stdout_fd = libc::dup(libc::STDOUT_FILENO);
+        if stdout_fd < 0 {
+            return Box::new(std::io::stdout());
+        }
+        if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            libc::close(stdout_fd);
+            return Box::new(std::io::stdout());
+        }
+        Box::new(std::fs::File::from_raw_fd(stdout_fd))
+    }
+}
+
+//其它平台日志仍然输出到标准输出，使用 --output 获取比较结果
+#[cfg(not(unix))]
+fn redirect_stdout_to_stderr() -> Box<Write> {
+    Box::new(std::io::stdout())
+}
+
+//flare_server extract [dest_dir]
+//解压嵌入的agent库、attacher及UI静态文件(--features embedded 编译的程序)，默认解压到程序目录下的 .flare-embedded
+fn extract(args: &[String]) {
+    if !embedded::has_embedded_files() {
+        println!("no embedded files, build with: cargo build --release --features embedded");
+        return;
+    }
+    let dest_dir = match args.get(0) {
+        Some(dir) => Ok(std::path::PathBuf::from(dir)),
+        None => embedded::get_extract_dir()
+    };
+    match dest_dir.and_then(|x| embedded::extract_embedded_files(&x)) {
+        Ok(dir) => println!("extracted {} files to: {}", embedded::EMBEDDED_FILES.len(), dir.display()),
+        Err(e) => println!("extract embedded files failed: {}", e)
+    }
+}
+
+//flare_server import_perf <perf_script_file> <sample_data_dir> [sample_interval_ms]
+fn import_perf(args: &[String]) {
+    if args.len() < 2 {
+        println!("usage: flare_server import_perf <perf_script_file> <sample_data_dir> [sample_interval_ms]");
+        return;
+    }
+    let mut options = perf_import::PerfImportOptions::default();
+    if let Some(sample_interval) = args.get(2).and_then(|x| x.parse::<i64>().ok()) {
+        options.sample_interval = sample_interval;
+    }
+    match perf_import::import_perf_script(&args[0], &args[1], &options) {
+        Ok(stats) => println!("import perf script is done: {}, threads: {}, samples: {}, methods: {}, dropped samples: {}, invalid events: {}",
+                              stats.sample_data_dir, stats.threads, stats.samples, stats.methods, stats.dropped_samples, stats.invalid_events),
+        Err(e) => println!("import perf script failed: {}", e)
+    }
+}
+
+//flare_server query <sample_data_dir> <query>
+fn query(args: &[String]) {
+    if args.len() < 2 {
+        println!("usage: flare_server query <sample_data_dir> \"top 10 methods where thread like 'worker%' between +0s and +60s\"");
+        return;
+    }
+    let query = match query_dsl::parse_query(&args[1..].join(" ")) {
+        Ok(query) => query,
+        Err(e) => {
+            println!("parse query failed: {}", e);
+            return;
+        }
+    };
+    match SampleCollector::open(sample_path::string_to_path(&args[0])) {
+        Ok(collector) => {
+            let mut collector = collector.lock().unwrap();
+            match query_dsl::execute_query(&mut collector, &query) {
+                Ok(result) => {
+                    println!("time range: [{}, {}], total samples: {}", result.start_time, result.end_time, result.total_samples);
+                    if query.target == query_dsl::QueryTarget::Metrics {
+                        println!("{:>10} {:>14} {:>12} {:>12} {:>12}  {}", "points", "avg", "min", "max", "last", "name");
+                        for row in &result.rows {
+                            if let Some(metric) = &row.metric {
+                                println!("{:>10} {:>14.2} {:>12} {:>12} {:>12}  {}", row.samples, metric.avg, metric.min, metric.max, metric.last, row.name);
+                            }
+                        }
+                    } else {
+                        println!("{:>10} {:>8} {:>12} {:>12}  {}", "samples", "ratio", "cpu_time(ms)", "duration(ms)", "name");
+                        for row in &result.rows {
+                            println!("{:>10} {:>7.2}% {:>12} {:>12}  {}", row.samples, row.ratio * 100.0, row.cpu_time, row.duration, row.name);
+                        }
+                    }
+                }
+                Err(e) => println!("execute query failed: {}", e)
+            }
+            collector.close();
+        }
+        Err(e) => println!("open sample failed: {}", e)
+    }
+}
+
+//flare_server replay <record_file> [wait_ms]
+fn replay(args: &[String]) {
+    if args.is_empty() {
+        println!("usage: flare_server replay <record_file> [wait_ms]");
+        return;
+    }
+    let wait_ms = args.get(1).and_then(|x| x.parse::<u64>().ok()).unwrap_or(3000);
+    let profiler = Profiler::new();
+    match command_recorder::replay_commands(&profiler, &args[0], wait_ms) {
+        Ok(stats) => {
+            println!("replay is done: {}, requests: {}, responses: {}, failures: {}", args[0], stats.requests, stats.responses, stats.failures);
+            for mismatch in &stats.mismatches {
+                println!("mismatch: {}", mismatch);
+            }
+        }
+        Err(e) => println!("replay failed: {}", e)
+    }
+}
+
+//flare_server generate <sample_data_dir> [threads] [duration_secs] [max_depth] [seed]
+fn generate(args: &[String]) {
+    if args.is_empty() {
+        println!("usage: flare_server generate <sample_data_dir> [threads] [duration_secs] [max_depth] [seed]");
+        return;
+    }
+    let mut options = sample_generator::GeneratorOptions::default();
+    if let Some(threads) = args.get(1).and_then(|x| x.parse::<usize>().ok()) {
+        options.threads = threads;
+    }
+    if let Some(duration_secs) = args.get(2).and_then(|x| x.parse::<i64>().ok()) {
+        options.duration_ms = duration_secs * 1000;
+    }
+    if let Some(max_depth) = args.get(3).and_then(|x| x.parse::<usize>().ok()) {
+        options.max_depth = max_depth;
+        options.mean_depth = std::cmp::min(options.mean_depth, max_depth);
+        options.min_depth = std::cmp::min(options.min_depth, max_depth);
+    }
+    if let Some(seed) = args.get(4).and_then(|x| x.parse::<u64>().ok()) {
+        options.seed = seed;
+    }
+    match sample_generator::generate_sample(&args[0], &options) {
+        Ok(stats) => println!("generate sample is done: {}, threads: {}, samples: {}, frames: {}", stats.sample_data_dir, stats.threads, stats.samples, stats.frames),
+        Err(e) => println!("generate sample failed: {}", e)
+    }
+}
+
+fn start_daemon(config: &daemon::DaemonConfig) -> std::io::Result<(daemon::PidFile, daemon::LogFile, daemon::DaemonConfig)> {
+    let pid_file = daemon::PidFile::create(&config.pid_file)?;
+    println!("redirect output to log file: {}", config.log_file);
+    let log_file = daemon::LogFile::open(&config.log_file, config.log_max_size_mb, config.log_max_files)?;
+    daemon::install_signal_handlers();
+    println!("flare server started in daemon mode, pid: {}, pid file: {}", std::process::id(), pid_file.get_path());
+    Ok((pid_file, log_file, config.clone()))
+}
+
+//flare_server service <systemd|windows> [--user <user>] [--output <file>]
+//输出服务定义，默认使用当前的程序路径及工作目录
+fn service(args: &[String]) -> i32 {
+    if args.is_empty() {
+        println!("usage: flare_server service <systemd|windows> [--user <user>] [--output <file>]");
+        return 2;
+    }
+    let get_arg = |name: &str| args.iter().position(|x| x == name).and_then(|i| args.get(i + 1)).cloned();
+    let exe_path = match std::env::current_exe() {
+        Ok(path) => sample_path::path_to_string(&path),
+        Err(e) => {
+            println!("get program path failed: {}", e);
+            return 2;
+        }
+    };
+    let work_dir = match std::env::current_dir() {
+        Ok(path) => sample_path::path_to_string(&path),
+        Err(e) => {
+            println!("get working dir failed: {}", e);
+            return 2;
+        }
+    };
+    let options = daemon::ServiceOptions { exe_path, work_dir, user: get_arg("--user") };
+    let definition = match daemon::generate_service_definition(&args[0], &daemon::read_daemon_config(), &options) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{}", e);
+            return 2;
+        }
+    };
+    match get_arg("--output") {
+        Some(output) => {
+            if let Err(e) = std::fs::write(&output, definition.as_bytes()) {
+                println!("write service definition failed: {}, err: {}", output, e);
+                return 2;
+            }
+            println!("service definition saved to: {}", output);
+        }
+        None => print!("{}", definition)
+    }
+    0
+}
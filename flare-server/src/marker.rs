@@ -0,0 +1,60 @@
+
+use std::io;
+use std::path::Path;
+use serde_json;
+
+pub const MARKERS_FILE: &str = "markers.json";
+pub const INTERVALS_FILE: &str = "intervals.json";
+
+//时间轴上的标记，来源: user(界面添加) / agent(应用通过agent API推送)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Marker {
+    pub time: i64,
+    pub label: String,
+    pub color: String,
+    #[serde(default)]
+    pub source: String,
+}
+
+//应用通过agent API (Flare.begin/end) 标记的阶段，end_time为-1表示尚未结束
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Interval {
+    pub name: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    //开始阶段的线程句柄，0表示未知(旧版本agent)
+    #[serde(default)]
+    pub thread_id: i64,
+}
+
+pub fn load_markers<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<Marker>> {
+    let path = sample_data_dir.as_ref().join(MARKERS_FILE);
+    if std::fs::metadata(&path).is_err() {
+        return Ok(vec![]);
+    }
+    let json = std::fs::read_to_string(path)?;
+    let markers = serde_json::from_str::<Vec<Marker>>(&json)?;
+    Ok(markers)
+}
+
+pub fn save_markers<P: AsRef<Path>>(sample_data_dir: P, markers: &[Marker]) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(MARKERS_FILE);
+    let json = serde_json::to_string_pretty(markers)?;
+    std::fs::write(path, json.as_bytes())
+}
+
+pub fn load_intervals<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<Interval>> {
+    let path = sample_data_dir.as_ref().join(INTERVALS_FILE);
+    if std::fs::metadata(&path).is_err() {
+        return Ok(vec![]);
+    }
+    let json = std::fs::read_to_string(path)?;
+    let intervals = serde_json::from_str::<Vec<Interval>>(&json)?;
+    Ok(intervals)
+}
+
+pub fn save_intervals<P: AsRef<Path>>(sample_data_dir: P, intervals: &[Interval]) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(INTERVALS_FILE);
+    let json = serde_json::to_string_pretty(intervals)?;
+    std::fs::write(path, json.as_bytes())
+}
@@ -0,0 +1,122 @@
+
+//会话录制期间的进程/系统资源指标，每个指标保存为一个时间序列文件 metric_<name>.fts
+//录制时写入，打开取样目录时按文件名加载
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use flare_utils::ValueType;
+use flare_utils::timeseries::*;
+use serde_json::json;
+
+pub const METRIC_FILE_PREFIX: &str = "metric_";
+//资源指标的取样间隔
+pub const METRIC_UNIT_TIME: i32 = 1000;
+
+pub struct MetricDef {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub kind: MetricKind,
+    //指标分组: cgroup, host, jvm
+    pub group: &'static str,
+}
+
+pub fn get_metric_ts_labels(metric: &MetricDef) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    labels.insert("metric".to_string(), metric.name.to_string());
+    labels.insert("unit".to_string(), metric.unit.to_string());
+    labels.insert("group".to_string(), metric.group.to_string());
+    labels
+}
+
+//写入一个指标的值，首次写入时创建文件
+pub fn add_metric_value<P: AsRef<Path>>(series_map: &mut HashMap<String, Box<TimeSeries+Send>>, sample_data_dir: P, metric: &MetricDef, time: i64, value: i64) -> io::Result<()> {
+    if !series_map.contains_key(metric.name) {
+        let path = sample_data_dir.as_ref().join(format!("{}{}", METRIC_FILE_PREFIX, metric.name));
+        let mut ts = TimeSeriesFileWriter::new_with_kind(ValueType::INT64, metric.kind, METRIC_UNIT_TIME, time, &path)?;
+        ts.set_labels(get_metric_ts_labels(metric))?;
+        series_map.insert(metric.name.to_string(), Box::new(ts));
+    }
+    let ts = series_map.get_mut(metric.name).unwrap();
+    ts.add_value(time, TSValue::int64(value))?;
+    Ok(())
+}
+
+//加载取样目录中的指标文件，跳过聚合层级文件(metric_xxx.10000ms.fts)
+pub fn load_metric_series<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<HashMap<String, Box<TimeSeries+Send>>> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    let mut series_map: HashMap<String, Box<TimeSeries+Send>> = HashMap::new();
+    for entry in std::fs::read_dir(sample_data_dir)? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        if !file_name.starts_with(METRIC_FILE_PREFIX) || !file_name.ends_with(".fts") {
+            continue;
+        }
+        let stem = &file_name[..file_name.len() - 4];
+        if stem.contains('.') {
+            continue;
+        }
+        let path = sample_data_dir.join(stem);
+        match TimeSeriesFileReader::new(&path) {
+            Ok(ts) => {
+                series_map.insert(stem[METRIC_FILE_PREFIX.len()..].to_string(), Box::new(ts));
+            }
+            Err(e) => println!("load metric series failed: {}, err: {}", path.display(), e)
+        }
+    }
+    Ok(series_map)
+}
+
+//返回一个指标在时间范围内的(时间, 值)，跳过没有数据的时间点
+//  包含 end_time 所在的时间点(时间序列按整个时间单位读取，不包含结束时间所在的单位)
+pub fn get_metric_points(series_map: &HashMap<String, Box<TimeSeries+Send>>, name: &str, start_time: i64, end_time: i64) -> Vec<(i64, i64)> {
+    let ts = match series_map.get(name) {
+        Some(ts) => ts,
+        None => return vec![]
+    };
+    let unit_time = ts.get_header_info().unit_time;
+    let result = ts.get_range_value(start_time, end_time.saturating_add(unit_time as i64), unit_time);
+    result.data.as_opt_int64().unwrap_or_default().iter().enumerate()
+        .filter_map(|(i, x)| x.map(|v| (result.begin_time + i as i64 * result.unit_time as i64, v)))
+        .filter(|(time, _)| *time <= end_time)
+        .collect()
+}
+
+//按分组返回时间范围内的指标值，GAUGE类型合并多个取样时取平均值
+//  transform: COUNTER类型(累计值)转换为每个时间单位的增量或每秒的速率，其它类型忽略
+pub fn get_metric_values(series_map: &HashMap<String, Box<TimeSeries+Send>>, group: &str, start_time: i64, end_time: i64, unit_time_ms: i64, transform: TSTransform) -> serde_json::Value {
+    let mut names: Vec<&String> = series_map.keys().collect();
+    names.sort();
+    let mut metrics = serde_json::Map::new();
+    for name in names {
+        let ts = &series_map[name];
+        let info = ts.get_header_info();
+        if group != "" && info.labels.get("group").map(|x| x.as_str()) != Some(group) {
+            continue;
+        }
+        let start_time = if start_time > 0 { start_time } else { info.begin_time };
+        let end_time = if end_time > 0 { end_time } else { info.end_time + info.unit_time as i64 };
+        let unit_time = std::cmp::max(unit_time_ms, info.unit_time as i64) as i32;
+        let transform = if info.metric_kind == MetricKind::COUNTER { transform } else { TSTransform::NONE };
+        let result = match ts.get_transformed_range_value(start_time, end_time, unit_time, transform) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("read metric series failed: {}, err: {}", name, e);
+                continue;
+            }
+        };
+        let data = match &result.data {
+            TSRangeValue::vec_opt_f64(x) => json!(x),
+            data => json!(data.as_opt_int64().unwrap_or_default()),
+        };
+        metrics.insert(name.clone(), json!({
+            "unit": info.labels.get("unit").cloned().unwrap_or_default(),
+            "metric_kind": format!("{:?}", info.metric_kind),
+            "transform": format!("{:?}", transform).to_lowercase(),
+            "begin_time": result.begin_time,
+            "end_time": result.end_time,
+            "unit_time": result.unit_time,
+            "data": data,
+        }));
+    }
+    serde_json::Value::Object(metrics)
+}
@@ -0,0 +1,198 @@
+
+//导出时间序列指标及热点方法表到CSV或Parquet文件，便于用 pandas/Spark 做自定义分析
+//  cpu_time.<ext>: time, thread_id, thread_name, cpu_time_us  (没有记录数据的点为空值)
+//  hot_methods.<ext>: method_id, method, samples, self_samples, cpu_time_ms, self_cpu_time_ms, duration_ms, self_duration_ms
+
+use ::sample::*;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::Write;
+use flare_utils::parquet_writer::*;
+use utils::*;
+use warmup::get_steady_start_time;
+
+pub const EXPORT_TABLES: &[&str] = &["cpu_time", "hot_methods"];
+
+#[derive(Clone, Debug)]
+pub struct MetricsExportOptions {
+    //csv, parquet
+    pub format: String,
+    pub tables: Vec<String>,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub unit_time_ms: i64,
+    //热点方法表排除JIT预热阶段
+    pub exclude_warmup: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ExportedTable {
+    pub name: String,
+    pub path: String,
+    pub rows: usize,
+}
+
+pub fn export_metrics(collector: &mut SampleCollector, export_dir: &str, options: &MetricsExportOptions) -> io::Result<Vec<ExportedTable>> {
+    let ext = match options.format.as_str() {
+        "csv" => "csv",
+        "parquet" => "parquet",
+        _ => return Err(new_invalid_input_error(&format!("invalid export format: {}, expect csv or parquet", options.format)))
+    };
+    if let Some(table) = options.tables.iter().find(|x| !EXPORT_TABLES.contains(&x.as_str())) {
+        return Err(new_invalid_input_error(&format!("invalid export table: {}, expect one of {:?}", table, EXPORT_TABLES)));
+    }
+    let sample_info = collector.get_sample_info();
+    let start_time = if options.start_time < 0 { sample_info.record_start_time } else { options.start_time };
+    //时间序列的查询范围不包含结束时间，默认包含最后一次取样
+    let end_time = if options.end_time < 0 { sample_info.last_record_time + sample_info.sample_interval } else { options.end_time };
+    std::fs::create_dir_all(export_dir)?;
+
+    let mut tables = vec![];
+    for name in &options.tables {
+        let columns = match name.as_str() {
+            "cpu_time" => get_cpu_time_table(collector, start_time, end_time, options.unit_time_ms.max(sample_info.sample_interval))?,
+            _ => {
+                let start_time = if options.exclude_warmup { get_steady_start_time(collector, start_time, end_time)? } else { start_time };
+                get_hot_methods_table(collector, start_time, end_time)?
+            }
+        };
+        let path = format!("{}/{}.{}", export_dir, name, ext);
+        match ext {
+            "csv" => write_csv_file(&path, &columns)?,
+            _ => write_parquet_file(&path, &columns)?,
+        }
+        tables.push(ExportedTable {
+            name: name.clone(),
+            path,
+            rows: columns.first().map(|x| x.data.len()).unwrap_or(0),
+        });
+    }
+    Ok(tables)
+}
+
+fn get_cpu_time_table(collector: &mut SampleCollector, start_time: i64, end_time: i64, unit_time_ms: i64) -> io::Result<Vec<ParquetColumn>> {
+    let mut threads = collector.get_threads()?;
+    threads.sort_by_key(|x| x.id);
+    let (mut times, mut thread_ids, mut thread_names, mut cpu_times) = (vec![], vec![], vec![], vec![]);
+    for thread in &threads {
+        if let Some(ts_result) = collector.get_thread_cpu_time(&thread.id, start_time, end_time, unit_time_ms) {
+            let data = ts_result.data.as_opt_int64().unwrap_or_default();
+            for (i, value) in data.iter().enumerate() {
+                times.push(Some(ts_result.begin_time + i as i64 * ts_result.unit_time as i64));
+                thread_ids.push(Some(thread.id));
+                thread_names.push(Some(thread.name.clone()));
+                cpu_times.push(*value);
+            }
+        }
+    }
+    Ok(vec![
+        ParquetColumn { name: "time".to_string(), data: ParquetColumnData::Int64(times) },
+        ParquetColumn { name: "thread_id".to_string(), data: ParquetColumnData::Int64(thread_ids) },
+        ParquetColumn { name: "thread_name".to_string(), data: ParquetColumnData::Utf8(thread_names) },
+        ParquetColumn { name: "cpu_time_us".to_string(), data: ParquetColumnData::Int64(cpu_times) },
+    ])
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct MethodStats {
+    pub samples: i64,
+    pub self_samples: i64,
+    //ns
+    pub cpu_time: i64,
+    pub self_cpu_time: i64,
+    //ms
+    pub duration: i64,
+    pub self_duration: i64,
+}
+
+//所有线程的方法统计，按取样数从大到小排列
+pub fn get_method_stats(collector: &mut SampleCollector, start_time: i64, end_time: i64) -> io::Result<Vec<(i64, MethodStats)>> {
+    let mut threads = collector.get_threads()?;
+    threads.sort_by_key(|x| x.id);
+    let mut stats_map: HashMap<i64, MethodStats> = HashMap::new();
+    for thread in &threads {
+        let thread_data_vec = match collector.load_thread_samples(thread.id, start_time, end_time) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("load thread samples failed, thread: {}, error: {}", thread.id, e);
+                continue;
+            }
+        };
+        for thread_data in &thread_data_vec {
+            //栈顶在前
+            if let Some(method) = thread_data.stacktrace.first() {
+                let stats = stats_map.entry(*method).or_default();
+                stats.self_samples += 1;
+                stats.self_cpu_time += thread_data.cpu_time_delta;
+                stats.self_duration += thread_data.self_duration;
+            }
+            let mut visited = HashSet::new();
+            for method in &thread_data.stacktrace {
+                if visited.insert(*method) {
+                    let stats = stats_map.entry(*method).or_default();
+                    stats.samples += 1;
+                    stats.cpu_time += thread_data.cpu_time_delta;
+                    stats.duration += thread_data.self_duration;
+                }
+            }
+        }
+    }
+    let mut methods: Vec<(i64, MethodStats)> = stats_map.into_iter().collect();
+    methods.sort_by(|a, b| b.1.samples.cmp(&a.1.samples).then(a.0.cmp(&b.0)));
+    Ok(methods)
+}
+
+fn get_hot_methods_table(collector: &mut SampleCollector, start_time: i64, end_time: i64) -> io::Result<Vec<ParquetColumn>> {
+    let methods = get_method_stats(collector, start_time, end_time)?;
+    let mut names = Vec::with_capacity(methods.len());
+    for (method, _) in &methods {
+        names.push(Some(collector.get_method_name(*method)));
+    }
+    let int_column = |name: &str, f: &Fn(&MethodStats) -> i64| ParquetColumn {
+        name: name.to_string(),
+        data: ParquetColumnData::Int64(methods.iter().map(|x| Some(f(&x.1))).collect())
+    };
+    Ok(vec![
+        ParquetColumn { name: "method_id".to_string(), data: ParquetColumnData::Int64(methods.iter().map(|x| Some(x.0)).collect()) },
+        ParquetColumn { name: "method".to_string(), data: ParquetColumnData::Utf8(names) },
+        int_column("samples", &|x| x.samples),
+        int_column("self_samples", &|x| x.self_samples),
+        //cpu time: ns -> ms
+        int_column("cpu_time_ms", &|x| x.cpu_time / 1_000_000),
+        int_column("self_cpu_time_ms", &|x| x.self_cpu_time / 1_000_000),
+        int_column("duration_ms", &|x| x.duration),
+        int_column("self_duration_ms", &|x| x.self_duration),
+    ])
+}
+
+fn write_csv_file(path: &str, columns: &[ParquetColumn]) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_csv(&mut writer, columns)?;
+    writer.flush()
+}
+
+//RFC 4180, 空值输出为空字段
+pub fn write_csv<W: Write>(writer: &mut W, columns: &[ParquetColumn]) -> io::Result<()> {
+    let header: Vec<String> = columns.iter().map(|x| escape_csv(&x.name)).collect();
+    writer.write_all(header.join(",").as_bytes())?;
+    writer.write_all(b"\r\n")?;
+    let rows = columns.first().map(|x| x.data.len()).unwrap_or(0);
+    for i in 0..rows {
+        let fields: Vec<String> = columns.iter().map(|column| match &column.data {
+            ParquetColumnData::Int64(x) => x.get(i).and_then(|v| *v).map(|v| v.to_string()).unwrap_or_default(),
+            ParquetColumnData::Double(x) => x.get(i).and_then(|v| *v).map(|v| v.to_string()).unwrap_or_default(),
+            ParquetColumnData::Utf8(x) => x.get(i).and_then(|v| v.as_ref()).map(|v| escape_csv(v)).unwrap_or_default(),
+        }).collect();
+        writer.write_all(fields.join(",").as_bytes())?;
+        writer.write_all(b"\r\n")?;
+    }
+    Ok(())
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\r' || c == '\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
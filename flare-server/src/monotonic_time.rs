@@ -0,0 +1,44 @@
+
+//写入时的时间规范化：agent主机的墙上时间跳变(如NTP step)会让取样时间倒退，时间范围查询及时序文件都假设时间递增
+//  新版本agent按单调时钟计算事件时间，旧版本agent或者其它数据源可能倒退，按线程取样的时间检测，
+//  倒退超过阈值时之后的事件时间整体向后平移，保持递增，并记录会话事件 clock_jump
+//  时钟同步(clock_sync)改变校正偏差时是预期的平移，调用 shift 同步调整，不作为跳变
+
+//倒退的阈值(ms)，小的倒退(如乱序到达)不处理
+pub const CLOCK_JUMP_THRESHOLD_MS: i64 = 1000;
+
+pub const KIND_CLOCK_JUMP: &str = "clock_jump";
+
+#[derive(Clone, Debug, Default)]
+pub struct TimeNormalizer {
+    //规范化之后的最大时间
+    last_time: i64,
+    //加到事件时间上的平移
+    adjust: i64,
+}
+
+impl TimeNormalizer {
+    pub fn get_adjust(&self) -> i64 {
+        self.adjust
+    }
+
+    //time 为规范化之前的时间，检测到倒退时返回倒退的时长
+    pub fn check(&mut self, time: i64, sample_interval: i64) -> Option<i64> {
+        let mut jump = None;
+        let adjusted = time + self.adjust;
+        if self.last_time > 0 && adjusted < self.last_time - CLOCK_JUMP_THRESHOLD_MS {
+            let backwards = self.last_time - adjusted;
+            self.adjust += backwards + sample_interval.max(1);
+            jump = Some(backwards);
+        }
+        self.last_time = self.last_time.max(time + self.adjust);
+        jump
+    }
+
+    //之后的事件时间预期平移 delta
+    pub fn shift(&mut self, delta: i64) {
+        if self.last_time > 0 {
+            self.last_time += delta;
+        }
+    }
+}
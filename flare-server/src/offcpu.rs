@@ -0,0 +1,175 @@
+
+//off-CPU取样(Linux eBPF)：调用bcc的offcputime统计目标进程阻塞(IO、锁、sleep等)的调用栈，
+//结果保存到会话目录，与on-CPU取样一起做wall-clock分析
+//  offcputime -f -d -p <pid> <duration_secs>
+//输出折叠格式，栈底在前，'-'之后为内核栈:
+//  <comm>;<user frames ...>;-;<kernel frames ...> <off_cpu_us>
+//comm是截断到15字节的线程名称，保存时按名称及线程存活时间匹配取样的线程句柄，唯一匹配时才关联
+//关联到线程的调用栈可以合并到调用树及火焰图(offcpu选项)，栈底为[off-cpu]节点，阻塞时间计入duration
+
+use std::io;
+use std::path::Path;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::process::Command;
+use chrono::Local;
+use ::sample::SampleCollector;
+use utils::*;
+
+pub const OFFCPU_FILE: &str = "offcpu_stacks.json";
+//off-CPU调用栈的栈底标记及内核栈的后缀
+pub const OFFCPU_ROOT_FRAME: &str = "[off-cpu]";
+pub const KERNEL_FRAME_SUFFIX: &str = "_[k]";
+//Linux线程名称(comm)的最大长度
+pub const COMM_MAX_LEN: usize = 15;
+//单次取样的最长时间，超过时按最长时间取样
+pub const MAX_OFFCPU_DURATION_SECS: i64 = 300;
+//合并到调用树的off-CPU帧使用的合成方法ID，从这个值开始递减
+pub const OFFCPU_FRAME_ID_START: i64 = -1_000_000;
+
+//一次off-CPU取样的结果
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OffCpuProfile {
+    pub pid: i64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub stacks: Vec<OffCpuStack>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct OffCpuStack {
+    pub thread_name: String,
+    //栈顶在前，内核栈带有_[k]后缀
+    pub frames: Vec<String>,
+    pub off_cpu_us: i64,
+    //匹配的线程句柄，没有匹配或者匹配多个线程时为None
+    #[serde(default)]
+    pub thread_handle: Option<i64>,
+}
+
+//线程名称是否与off-CPU取样的comm一致
+pub fn match_comm(comm: &str, thread_name: &str) -> bool {
+    if thread_name.len() <= COMM_MAX_LEN {
+        return comm == thread_name;
+    }
+    //按字节截断，可能截断在多字节字符中间
+    comm.len() <= COMM_MAX_LEN && thread_name.as_bytes().starts_with(comm.as_bytes()) && comm.len() >= COMM_MAX_LEN - 3
+}
+
+pub fn load_offcpu_profiles<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<OffCpuProfile>> {
+    let path = sample_data_dir.as_ref().join(OFFCPU_FILE);
+    if std::fs::metadata(&path).is_err() {
+        return Ok(vec![]);
+    }
+    let json = std::fs::read_to_string(path)?;
+    let profiles = serde_json::from_str::<Vec<OffCpuProfile>>(&json)?;
+    Ok(profiles)
+}
+
+pub fn save_offcpu_profiles<P: AsRef<Path>>(sample_data_dir: P, profiles: &[OffCpuProfile]) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(OFFCPU_FILE);
+    let json = serde_json::to_string_pretty(profiles)?;
+    std::fs::write(path, json.as_bytes())
+}
+
+//解析offcputime -f的折叠输出，忽略无法解析的行
+pub fn parse_offcpu_folded(output: &str) -> Vec<OffCpuStack> {
+    let mut stacks = vec![];
+    for line in output.lines() {
+        let line = line.trim();
+        let pos = match line.rfind(' ') {
+            Some(pos) => pos,
+            None => continue
+        };
+        let off_cpu_us = match line[pos + 1..].parse::<i64>() {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        let mut parts = line[..pos].split(';');
+        let thread_name = parts.next().unwrap_or("").to_string();
+        //栈底为off-cpu标记
+        let mut frames = vec![OFFCPU_ROOT_FRAME.to_string()];
+        let mut kernel = false;
+        for frame in parts {
+            if frame == "-" || frame == "--" {
+                kernel = true;
+                continue;
+            }
+            if frame.is_empty() {
+                continue;
+            }
+            if kernel && !frame.ends_with(KERNEL_FRAME_SUFFIX) {
+                frames.push(format!("{}{}", frame, KERNEL_FRAME_SUFFIX));
+            } else {
+                frames.push(frame.to_string());
+            }
+        }
+        frames.reverse();
+        stacks.push(OffCpuStack { thread_name, frames, off_cpu_us, thread_handle: None });
+    }
+    stacks
+}
+
+//合并时间范围内的off-CPU调用栈，按阻塞时间倒序
+//thread_handle: 只合并关联到该线程句柄的调用栈，小于0表示不限制
+pub fn merge_offcpu_stacks(profiles: &[OffCpuProfile], start_time: i64, end_time: i64, thread_name: &str, thread_handle: i64) -> Vec<OffCpuStack> {
+    let mut merged: Vec<OffCpuStack> = vec![];
+    for profile in profiles {
+        if (start_time > 0 && profile.end_time < start_time) || (end_time > 0 && profile.start_time > end_time) {
+            continue;
+        }
+        for stack in &profile.stacks {
+            if thread_name != "" && stack.thread_name != thread_name {
+                continue;
+            }
+            if thread_handle >= 0 && stack.thread_handle != Some(thread_handle) {
+                continue;
+            }
+            match merged.iter_mut().find(|x| x.thread_name == stack.thread_name && x.thread_handle == stack.thread_handle && x.frames == stack.frames) {
+                Some(x) => x.off_cpu_us += stack.off_cpu_us,
+                None => merged.push(stack.clone())
+            }
+        }
+    }
+    merged.sort_by(|a, b| b.off_cpu_us.cmp(&a.off_cpu_us));
+    merged
+}
+
+//在后台线程执行off-CPU取样，完成后保存到会话，返回实际的取样时间(秒)
+pub fn start_offcpu_sampling(tool: &str, pid: i64, duration_secs: i64, collector: Arc<Mutex<SampleCollector>>) -> io::Result<i64> {
+    if !cfg!(target_os = "linux") {
+        return Err(new_error(ErrorKind::Other, "off-cpu sampling requires linux eBPF"));
+    }
+    if tool == "" {
+        return Err(new_error(ErrorKind::Other, "off-cpu sampling is disabled, set 'offcpu_tool' in config file"));
+    }
+    if pid <= 0 || duration_secs <= 0 {
+        return Err(new_invalid_input_error("pid and duration must be greater than 0"));
+    }
+    let duration_secs = duration_secs.min(MAX_OFFCPU_DURATION_SECS);
+    let tool = tool.to_string();
+    std::thread::spawn(move || {
+        let start_time = Local::now().timestamp_millis();
+        println!("start off-cpu sampling: {} -p {} {}", tool, pid, duration_secs);
+        let output = Command::new(&tool)
+            .args(&["-f", "-d", "-p", &pid.to_string(), &duration_secs.to_string()])
+            .output();
+        let end_time = Local::now().timestamp_millis();
+        match output {
+            Ok(output) => {
+                if !output.status.success() {
+                    println!("off-cpu sampling failed: {}, stderr: {}", output.status, String::from_utf8_lossy(&output.stderr));
+                    return;
+                }
+                let stacks = parse_offcpu_folded(&String::from_utf8_lossy(&output.stdout));
+                println!("off-cpu sampling is done, pid: {}, stacks: {}", pid, stacks.len());
+                let profile = OffCpuProfile { pid, start_time, end_time, stacks };
+                if let Err(e) = collector.lock().unwrap().add_offcpu_profile(profile) {
+                    println!("save off-cpu stacks failed: {}", e);
+                }
+            }
+            Err(e) => println!("run off-cpu sampling tool failed: {}, err: {}", tool, e)
+        }
+    });
+    Ok(duration_secs)
+}
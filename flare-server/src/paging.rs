@@ -0,0 +1,106 @@
+
+//列表查询的分页及排序约定，所有返回大列表的命令使用相同的参数:
+//  page: 从0开始;  page_size: 每页数量，<=0 返回全部;  sort_by: 排序字段;  order: asc|desc，默认按字段的默认顺序
+//响应中增加 total(过滤后的总数), page, page_size, sort_by, order
+//排序字段为列表元素序列化后的字段，数值按大小、字符串按字典序、数组按长度比较，缺少字段的排在最后
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::io;
+use utils::{get_option_as_int, get_option_as_str, new_invalid_input_error};
+
+pub const ORDER_ASC: &str = "asc";
+pub const ORDER_DESC: &str = "desc";
+
+//(排序字段, 默认是否降序)，第一个为默认排序字段
+pub type SortField = (&'static str, bool);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageQuery {
+    pub page: usize,
+    pub page_size: usize,
+    pub sort_by: String,
+    pub desc: bool,
+}
+
+impl PageQuery {
+    pub fn from_options(options: &Map<String, Value>, sort_fields: &[SortField], default_page_size: i64) -> io::Result<PageQuery> {
+        let sort_by = get_option_as_str(options, "sort_by", sort_fields[0].0);
+        let default_desc = match sort_fields.iter().find(|x| x.0 == sort_by) {
+            Some(x) => x.1,
+            None => {
+                let names: Vec<&str> = sort_fields.iter().map(|x| x.0).collect();
+                return Err(new_invalid_input_error(&format!("invalid sort_by: {}, expect one of {:?}", sort_by, names)));
+            }
+        };
+        let desc = match get_option_as_str(options, "order", "") {
+            "" => default_desc,
+            ORDER_ASC => false,
+            ORDER_DESC => true,
+            order => return Err(new_invalid_input_error(&format!("invalid order: {}, expect asc or desc", order)))
+        };
+        Ok(PageQuery {
+            page: get_option_as_int(options, "page", 0).max(0) as usize,
+            page_size: get_option_as_int(options, "page_size", default_page_size).max(0) as usize,
+            sort_by: sort_by.to_string(),
+            desc,
+        })
+    }
+
+    pub fn get_order(&self) -> &'static str {
+        if self.desc { ORDER_DESC } else { ORDER_ASC }
+    }
+
+    //当前页第一个元素的位置，页码过大时不溢出(返回空页)
+    pub fn get_offset(&self) -> usize {
+        self.page.saturating_mul(self.page_size)
+    }
+
+    //已排序的列表取当前页
+    pub fn get_page<T>(&self, items: Vec<T>) -> Vec<T> {
+        if self.page_size == 0 {
+            return items;
+        }
+        items.into_iter().skip(self.get_offset()).take(self.page_size).collect()
+    }
+
+    //在响应中增加分页信息
+    pub fn add_page_info(&self, result: &mut Value, total: usize) {
+        result["total"] = json!(total);
+        result["page"] = json!(self.page);
+        result["page_size"] = json!(self.page_size);
+        result["sort_by"] = json!(self.sort_by);
+        result["order"] = json!(self.get_order());
+    }
+}
+
+fn compare_field(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a, b) {
+        (Some(Value::Number(x)), Some(Value::Number(y))) => x.as_f64().unwrap_or(0.0).partial_cmp(&y.as_f64().unwrap_or(0.0)).unwrap_or(Ordering::Equal),
+        (Some(Value::String(x)), Some(Value::String(y))) => x.cmp(y),
+        (Some(Value::Bool(x)), Some(Value::Bool(y))) => x.cmp(y),
+        (Some(Value::Array(x)), Some(Value::Array(y))) => x.len().cmp(&y.len()),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        _ => Ordering::Equal
+    }
+}
+
+//按 sort_by 字段排序(稳定排序)并分页，返回总数及当前页
+pub fn sort_and_page<T: Serialize>(items: &[T], query: &PageQuery) -> io::Result<(usize, Vec<Value>)> {
+    let mut values = vec![];
+    for item in items {
+        values.push(serde_json::to_value(item)?);
+    }
+    values.sort_by(|a, b| {
+        let (x, y) = (a.get(&query.sort_by), b.get(&query.sort_by));
+        //缺少字段的总是排在最后
+        match (x.is_some(), y.is_some(), query.desc) {
+            (true, true, true) => compare_field(y, x),
+            _ => compare_field(x, y)
+        }
+    });
+    let total = values.len();
+    Ok((total, query.get_page(values)))
+}
@@ -0,0 +1,234 @@
+
+//导入 Linux `perf script` 的输出，将原生调用栈转换为取样目录，可以用 open_sample 打开
+//  perf record -F 99 -g -p <pid> && perf script > out.perf
+//每个事件以空行分隔:
+//  <comm> <pid/tid> [cpu] <timestamp>: [period] <event>:
+//  \t<addr> <symbol>+<offset> (<dso>)
+//  ...
+//调用栈栈顶在前，与取样数据的保存顺序相同
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader};
+use chrono::Local;
+use flare_proto::names::escape_name;
+use ::sample::ThreadData;
+use sample_writer::SampleWriter;
+use utils::*;
+
+pub const DEFAULT_PERF_SAMPLE_INTERVAL: i64 = 10;
+
+#[derive(Clone, Debug)]
+pub struct PerfImportOptions {
+    //取样间隔(ms)，与 perf record -F 的频率对应，99Hz约为10ms
+    pub sample_interval: i64,
+    //第一个事件对应的时间(ms)，perf的时间戳是开机以来的秒数；<=0 时按文件修改时间倒推
+    pub start_time: i64,
+}
+
+impl Default for PerfImportOptions {
+    fn default() -> Self {
+        PerfImportOptions {
+            sample_interval: DEFAULT_PERF_SAMPLE_INTERVAL,
+            start_time: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct PerfImportStats {
+    pub sample_data_dir: String,
+    pub threads: usize,
+    pub samples: usize,
+    pub methods: usize,
+    //同一个线程在一个取样间隔内的多余事件
+    pub dropped_samples: usize,
+    //无法解析的事件头
+    pub invalid_events: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerfEvent {
+    pub comm: String,
+    pub pid: i64,
+    pub tid: i64,
+    //秒
+    pub timestamp: f64,
+    //栈顶在前
+    pub frames: Vec<String>,
+}
+
+struct ImportThread {
+    cpu_time: i64,
+    last_step: i64,
+}
+
+pub fn import_perf_script(perf_file: &str, sample_data_dir: &str, options: &PerfImportOptions) -> io::Result<PerfImportStats> {
+    let (events, invalid_events) = read_perf_script(perf_file)?;
+    if events.is_empty() {
+        return Err(new_invalid_input_error(&format!("no stack samples found in perf script output: {}", perf_file)));
+    }
+
+    let sample_interval = options.sample_interval.max(1);
+    let first_timestamp = events.iter().map(|x| x.timestamp).fold(std::f64::MAX, f64::min);
+    let last_timestamp = events.iter().map(|x| x.timestamp).fold(0.0, f64::max);
+    let start_time = if options.start_time > 0 {
+        options.start_time
+    } else {
+        let end_time = std::fs::metadata(perf_file)?.modified().ok()
+            .and_then(|x| x.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|x| x.as_millis() as i64)
+            .unwrap_or(Local::now().timestamp_millis());
+        end_time - ((last_timestamp - first_timestamp) * 1000.0) as i64
+    };
+    //对齐到取样间隔
+    let start_time = start_time - start_time % sample_interval;
+
+    let mut writer = SampleWriter::new(sample_data_dir, sample_interval, &format!("perf:{}", perf_file))?;
+    let mut threads: HashMap<i64, ImportThread> = HashMap::new();
+    let mut stats = PerfImportStats {
+        invalid_events,
+        ..Default::default()
+    };
+    let cpu_time_delta = sample_interval * 1_000_000;
+    for event in &events {
+        //按微秒取整，避免浮点误差
+        let offset_micros = ((event.timestamp - first_timestamp) * 1_000_000.0).round() as i64;
+        let step = offset_micros / 1000 / sample_interval;
+        let thread = threads.entry(event.tid).or_insert(ImportThread { cpu_time: 0, last_step: -1 });
+        if step <= thread.last_step {
+            stats.dropped_samples += 1;
+            continue;
+        }
+        thread.last_step = step;
+        thread.cpu_time += cpu_time_delta;
+
+        let mut stacktrace = Vec::with_capacity(event.frames.len());
+        for frame in &event.frames {
+            stacktrace.push(writer.get_or_add_method(&escape_name(frame))?);
+        }
+        let thread_data = ThreadData {
+            id: event.tid,
+            name: event.comm.clone(),
+            priority: 0,
+            daemon: false,
+            state: "RUNNABLE".to_string(),
+            cpu_time: thread.cpu_time,
+            cpu_time_delta,
+            sample_time: start_time + step * sample_interval,
+            sample_count: 1,
+            stacktrace,
+            duration: 0,
+            self_duration: 0,
+            self_cpu_time: 0,
+        };
+        writer.add_thread_sample(&thread_data)?;
+        stats.samples += 1;
+    }
+    stats.threads = threads.len();
+    stats.methods = writer.get_method_count();
+    stats.sample_data_dir = writer.finish()?;
+    Ok(stats)
+}
+
+//返回按时间排序的事件及无法解析的事件数量
+pub fn read_perf_script(perf_file: &str) -> io::Result<(Vec<PerfEvent>, usize)> {
+    let reader = BufReader::new(std::fs::File::open(perf_file)?);
+    let mut events = vec![];
+    let mut invalid_events = 0;
+    let mut current: Option<PerfEvent> = None;
+    let mut in_event = false;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            if let Some(event) = current.take() {
+                if !event.frames.is_empty() {
+                    events.push(event);
+                }
+            }
+            in_event = false;
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(|c: char| c.is_whitespace()) {
+            if let Some(event) = current.as_mut() {
+                if let Some(frame) = parse_frame(&line) {
+                    event.frames.push(frame);
+                }
+            }
+        } else if !in_event {
+            in_event = true;
+            current = parse_header(&line);
+            if current.is_none() {
+                invalid_events += 1;
+            }
+        }
+    }
+    if let Some(event) = current.take() {
+        if !event.frames.is_empty() {
+            events.push(event);
+        }
+    }
+    events.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap_or(std::cmp::Ordering::Equal));
+    Ok((events, invalid_events))
+}
+
+//<comm> <pid/tid>|<tid> [cpu] <timestamp>: ...，comm可能包含空格
+pub fn parse_header(line: &str) -> Option<PerfEvent> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    //时间戳是第一个以':'结尾的数字
+    let ts_pos = tokens.iter().position(|x| x.ends_with(':') && x[..x.len() - 1].parse::<f64>().is_ok())?;
+    let timestamp = tokens[ts_pos][..tokens[ts_pos].len() - 1].parse::<f64>().ok()?;
+    let mut pos = ts_pos;
+    if pos > 0 && tokens[pos - 1].starts_with('[') {
+        pos -= 1;
+    }
+    if pos < 2 {
+        return None;
+    }
+    let pid_tid = tokens[pos - 1];
+    let (pid, tid) = match pid_tid.find('/') {
+        Some(p) => (pid_tid[..p].parse::<i64>().ok()?, pid_tid[p + 1..].parse::<i64>().ok()?),
+        None => {
+            let tid = pid_tid.parse::<i64>().ok()?;
+            (tid, tid)
+        }
+    };
+    Some(PerfEvent {
+        comm: tokens[..pos - 1].join(" "),
+        pid,
+        tid,
+        timestamp,
+        frames: vec![],
+    })
+}
+
+//<addr> <symbol>+<offset> (<dso>) => "<symbol> (<dso文件名>)"，同一个模块的未知符号合并为一个方法
+pub fn parse_frame(line: &str) -> Option<String> {
+    let line = line.trim();
+    let rest = match line.find(char::is_whitespace) {
+        Some(p) => line[p..].trim(),
+        None => ""
+    };
+    let (symbol, dso) = match rest.rfind(" (") {
+        Some(p) if rest.ends_with(')') => (rest[..p].trim(), &rest[p + 2..rest.len() - 1]),
+        _ => match rest.starts_with('(') && rest.ends_with(')') {
+            true => ("", &rest[1..rest.len() - 1]),
+            false => (rest, "")
+        }
+    };
+    //去掉 +0x偏移
+    let symbol = match symbol.rfind("+0x") {
+        Some(p) => &symbol[..p],
+        None => symbol
+    };
+    let symbol = if symbol.is_empty() { "[unknown]" } else { symbol };
+    let dso = dso.rsplit('/').next().unwrap_or(dso);
+    if dso.is_empty() || dso == "[unknown]" {
+        Some(symbol.to_string())
+    } else {
+        Some(format!("{} ({})", symbol, dso))
+    }
+}
@@ -0,0 +1,1483 @@
+//插件脚本: 内置的沙箱脚本语言(语法接近Rhai)，插件用来对查询结果做后处理及生成自定义报告
+//脚本只能访问传入的变量及白名单中的内置函数，没有文件、网络、进程及时间等接口
+//执行时限制操作数及累计分配的内存，解析时限制语法嵌套深度，超出限制时中止执行并返回错误
+//数据类型与JSON相同: null, 布尔, 整数, 浮点数, 字符串, 数组 [1, 2], 对象 #{ name: "x", value: 1 }
+//语句: let x = 表达式;  x = 表达式;  x += 表达式;  if 条件 {} else {}  for x in 数组 {}  while 条件 {}  break;  continue;  return 表达式;
+//  访问: row.name, rows[0], rows[-1](从末尾开始)，对象不存在的字段为 null
+//  方法调用 x.f(a) 等同于 f(x, a)，rows.push(x) 在原数组上添加元素
+//  字符串拼接时使用 s += "..."，s = s + "..." 每次都复制整个字符串，计入内存限制
+//示例:
+//  let rows = [];
+//  for row in result.rows {
+//      if row.ratio >= 0.01 { rows.push(#{ name: row.name, percent: round(row.ratio * 100.0, 1) }); }
+//  }
+//  result.rows = sort_by(rows, "percent", true);
+
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::io;
+use serde_json::{Map, Number, Value};
+use utils::*;
+
+pub const DEFAULT_MAX_OPERATIONS: u64 = 1_000_000;
+pub const DEFAULT_MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+//语句块及表达式的嵌套深度上限，避免解析及执行时栈溢出
+pub const MAX_SYNTAX_DEPTH: usize = 128;
+//放入数组或对象的值的嵌套深度上限
+pub const MAX_VALUE_DEPTH: usize = 32;
+
+//估算内存时每个值的大小
+const VALUE_SIZE: usize = std::mem::size_of::<Value>();
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScriptLimits {
+    //最多执行的操作数: 语句、表达式求值及循环迭代各计1次，内置函数及复制值按处理的元素数计
+    #[serde(default = "default_max_operations")]
+    pub max_operations: u64,
+    //累计分配内存的上限(字节): 按创建及复制的字符串、数组和对象估算，释放的内存不扣除
+    #[serde(default = "default_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+fn default_max_operations() -> u64 {
+    DEFAULT_MAX_OPERATIONS
+}
+
+fn default_max_memory_bytes() -> usize {
+    DEFAULT_MAX_MEMORY_BYTES
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        ScriptLimits {
+            max_operations: default_max_operations(),
+            max_memory_bytes: default_max_memory_bytes(),
+        }
+    }
+}
+
+impl ScriptLimits {
+    pub fn validate(&self) -> io::Result<()> {
+        if self.max_operations == 0 || self.max_memory_bytes == 0 {
+            return Err(new_invalid_input_error("plugin script limits must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+//编译后的脚本，可以多次执行
+#[derive(Debug, Clone)]
+pub struct Script {
+    statements: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptOutput {
+    //return 语句返回的值，没有执行 return 时为 None
+    pub value: Option<Value>,
+    //执行结束时的顶层变量(包括传入的变量)
+    pub variables: HashMap<String, Value>,
+    pub operations: u64,
+    pub memory_bytes: usize,
+}
+
+//解析脚本，语法错误包含行号
+pub fn compile_script(source: &str) -> io::Result<Script> {
+    let tokens = tokenize(source).map_err(|e| e.into_io_error())?;
+    let mut parser = Parser { tokens, pos: 0, depth: 0, loops: 0 };
+    let mut statements = vec![];
+    while parser.peek() != &Token::End {
+        statements.push(parser.parse_statement().map_err(|e| e.into_io_error())?);
+    }
+    Ok(Script { statements })
+}
+
+impl Script {
+    //执行脚本，variables 为脚本可以访问及修改的顶层变量
+    pub fn run(&self, variables: HashMap<String, Value>, limits: &ScriptLimits) -> io::Result<ScriptOutput> {
+        let mut engine = Engine {
+            budget: Budget { limits, operations: 0, memory_bytes: 0 },
+            scopes: vec![variables],
+        };
+        let value = match engine.exec_statements(&self.statements).map_err(|e| e.into_io_error())? {
+            Flow::Return(value) => Some(value),
+            _ => None
+        };
+        Ok(ScriptOutput {
+            value,
+            variables: engine.scopes.swap_remove(0),
+            operations: engine.budget.operations,
+            memory_bytes: engine.budget.memory_bytes,
+        })
+    }
+}
+
+struct ScriptError {
+    message: String,
+    //0表示未知
+    line: usize,
+}
+
+type ScriptResult<T> = Result<T, ScriptError>;
+
+impl ScriptError {
+    fn into_io_error(self) -> io::Error {
+        if self.line > 0 {
+            new_invalid_input_error(&format!("script error at line {}: {}", self.line, self.message))
+        } else {
+            new_invalid_input_error(&format!("script error: {}", self.message))
+        }
+    }
+}
+
+fn script_error<T, S: Into<String>>(message: S) -> ScriptResult<T> {
+    Err(ScriptError { message: message.into(), line: 0 })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Sym(&'static str),
+    End,
+}
+
+//两个字符的符号在前，优先匹配
+const SYMBOLS: &[&str] = &["#{", "+=", "-=", "==", "!=", "<=", ">=", "&&", "||",
+    "{", "}", "(", ")", "[", "]", ",", ";", ":", ".", "=", "<", ">", "+", "-", "*", "/", "%", "!"];
+
+const KEYWORDS: &[&str] = &["let", "if", "else", "for", "in", "while", "return", "break", "continue", "true", "false", "null"];
+
+fn tokenize(source: &str) -> ScriptResult<Vec<(Token, usize)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    let mut line = 1;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c == '\n' {
+            line += 1;
+            pos += 1;
+        } else if c.is_whitespace() {
+            pos += 1;
+        } else if c == '/' && chars.get(pos + 1) == Some(&'/') {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+            }
+        } else if c.is_ascii_digit() {
+            let start = pos;
+            while pos < chars.len() && chars[pos].is_ascii_digit() {
+                pos += 1;
+            }
+            //数字后的 . 不是小数点时为方法调用
+            let is_float = pos + 1 < chars.len() && chars[pos] == '.' && chars[pos + 1].is_ascii_digit();
+            if is_float {
+                pos += 1;
+                while pos < chars.len() && chars[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+            }
+            let text: String = chars[start..pos].iter().collect();
+            let token = if is_float {
+                text.parse::<f64>().ok().filter(|x| x.is_finite()).map(Token::Float)
+            } else {
+                text.parse::<i64>().ok().map(Token::Int)
+            };
+            match token {
+                Some(token) => tokens.push((token, line)),
+                None => return Err(ScriptError { message: format!("invalid number: {}", text), line })
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = pos;
+            while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+            tokens.push((Token::Ident(chars[start..pos].iter().collect()), line));
+        } else if c == '"' {
+            let start_line = line;
+            let mut text = String::new();
+            pos += 1;
+            loop {
+                match chars.get(pos) {
+                    None => return Err(ScriptError { message: "unclosed string".to_string(), line: start_line }),
+                    Some('"') => {
+                        pos += 1;
+                        break;
+                    }
+                    Some('\\') => {
+                        match chars.get(pos + 1) {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some('"') => text.push('"'),
+                            Some('\\') => text.push('\\'),
+                            _ => return Err(ScriptError { message: "invalid escape in string".to_string(), line })
+                        }
+                        pos += 2;
+                    }
+                    Some(x) => {
+                        if *x == '\n' {
+                            line += 1;
+                        }
+                        text.push(*x);
+                        pos += 1;
+                    }
+                }
+            }
+            tokens.push((Token::Str(text), start_line));
+        } else {
+            match SYMBOLS.iter().find(|sym| sym.chars().enumerate().all(|(i, x)| chars.get(pos + i) == Some(&x))) {
+                Some(sym) => {
+                    tokens.push((Token::Sym(sym), line));
+                    pos += sym.len();
+                }
+                None => return Err(ScriptError { message: format!("unexpected character: {}", c), line })
+            }
+        }
+    }
+    tokens.push((Token::End, line));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Var(String),
+    Array(Vec<Expr>),
+    Map(Vec<(String, Expr)>),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Method(Box<Expr>, String, Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum StmtKind {
+    Let(String, Expr),
+    //赋值目标, "=" / "+=" / "-=", 值
+    Assign(Expr, &'static str, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    For(String, Expr, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Stmt {
+    kind: StmtKind,
+    line: usize,
+}
+
+//变量或者变量的字段/元素，可以赋值及按引用访问
+fn is_place(expr: &Expr) -> bool {
+    match expr {
+        Expr::Var(_) => true,
+        Expr::Field(base, _) | Expr::Index(base, _) => is_place(base),
+        _ => false
+    }
+}
+
+//二元运算符，优先级从低到高
+const BINARY_LEVELS: &[&[&str]] = &[&["||"], &["&&"], &["==", "!="], &["<", "<=", ">", ">="], &["+", "-"], &["*", "/", "%"]];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+    //所在的循环层数，循环外不能使用 break/continue
+    loops: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: &str) -> ScriptResult<T> {
+        let found = match self.peek() {
+            Token::Int(x) => x.to_string(),
+            Token::Float(x) => x.to_string(),
+            Token::Str(x) => format!("\"{}\"", x),
+            Token::Ident(x) => x.clone(),
+            Token::Sym(x) => x.to_string(),
+            Token::End => "end of script".to_string(),
+        };
+        Err(ScriptError { message: format!("{}, found {}", message, found), line: self.line() })
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        match self.peek() {
+            Token::Sym(x) => *x == sym,
+            _ => false
+        }
+    }
+
+    fn eat_sym(&mut self, sym: &str) -> bool {
+        let found = self.is_sym(sym);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect_sym(&mut self, sym: &str) -> ScriptResult<()> {
+        if self.eat_sym(sym) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", sym))
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Token::Ident(x) => x == keyword,
+            _ => false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect_ident(&mut self) -> ScriptResult<String> {
+        match self.peek().clone() {
+            Token::Ident(ref x) if !KEYWORDS.contains(&x.as_str()) => {
+                self.advance();
+                Ok(x.clone())
+            }
+            _ => self.error("expected identifier")
+        }
+    }
+
+    fn enter(&mut self) -> ScriptResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_SYNTAX_DEPTH {
+            return self.error(&format!("script is nested too deeply (max {})", MAX_SYNTAX_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn parse_block(&mut self) -> ScriptResult<Vec<Stmt>> {
+        self.expect_sym("{")?;
+        self.enter()?;
+        let mut statements = vec![];
+        while !self.eat_sym("}") {
+            if self.peek() == &Token::End {
+                return self.error("expected '}'");
+            }
+            statements.push(self.parse_statement()?);
+        }
+        self.depth -= 1;
+        Ok(statements)
+    }
+
+    fn parse_loop_body(&mut self) -> ScriptResult<Vec<Stmt>> {
+        self.loops += 1;
+        let body = self.parse_block()?;
+        self.loops -= 1;
+        Ok(body)
+    }
+
+    fn parse_statement(&mut self) -> ScriptResult<Stmt> {
+        let line = self.line();
+        let kind = if self.eat_keyword("let") {
+            let name = self.expect_ident()?;
+            self.expect_sym("=")?;
+            let value = self.parse_expr()?;
+            self.expect_sym(";")?;
+            StmtKind::Let(name, value)
+        } else if self.eat_keyword("if") {
+            self.parse_if()?
+        } else if self.eat_keyword("for") {
+            let name = self.expect_ident()?;
+            if !self.eat_keyword("in") {
+                return self.error("expected 'in'");
+            }
+            let iterable = self.parse_expr()?;
+            StmtKind::For(name, iterable, self.parse_loop_body()?)
+        } else if self.eat_keyword("while") {
+            let condition = self.parse_expr()?;
+            StmtKind::While(condition, self.parse_loop_body()?)
+        } else if self.eat_keyword("return") {
+            let value = if self.is_sym(";") { None } else { Some(self.parse_expr()?) };
+            self.expect_sym(";")?;
+            StmtKind::Return(value)
+        } else if self.is_keyword("break") || self.is_keyword("continue") {
+            if self.loops == 0 {
+                return self.error("break/continue outside of loop");
+            }
+            let kind = if self.eat_keyword("break") { StmtKind::Break } else { self.advance(); StmtKind::Continue };
+            self.expect_sym(";")?;
+            kind
+        } else {
+            let expr = self.parse_expr()?;
+            match ["=", "+=", "-="].iter().find(|x| self.is_sym(x)) {
+                Some(op) => {
+                    if !is_place(&expr) {
+                        return self.error("invalid assignment target");
+                    }
+                    self.advance();
+                    let value = self.parse_expr()?;
+                    self.expect_sym(";")?;
+                    StmtKind::Assign(expr, op, value)
+                }
+                None => {
+                    self.expect_sym(";")?;
+                    StmtKind::Expr(expr)
+                }
+            }
+        };
+        Ok(Stmt { kind, line })
+    }
+
+    fn parse_if(&mut self) -> ScriptResult<StmtKind> {
+        self.enter()?;
+        let condition = self.parse_expr()?;
+        let then_block = self.parse_block()?;
+        let else_block = if self.eat_keyword("else") {
+            if self.is_keyword("if") {
+                let line = self.line();
+                self.advance();
+                vec![Stmt { kind: self.parse_if()?, line }]
+            } else {
+                self.parse_block()?
+            }
+        } else {
+            vec![]
+        };
+        self.depth -= 1;
+        Ok(StmtKind::If(condition, then_block, else_block))
+    }
+
+    fn parse_expr(&mut self) -> ScriptResult<Expr> {
+        self.enter()?;
+        let expr = self.parse_binary(0)?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    //左结合的运算符链每一层都计入嵌套深度
+    fn parse_binary(&mut self, level: usize) -> ScriptResult<Expr> {
+        if level == BINARY_LEVELS.len() {
+            return self.parse_unary();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        let mut chain = 0;
+        while let Some(op) = BINARY_LEVELS[level].iter().find(|x| self.is_sym(x)) {
+            self.advance();
+            self.enter()?;
+            chain += 1;
+            let right = self.parse_binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        self.depth -= chain;
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> ScriptResult<Expr> {
+        if let Some(op) = ["-", "!"].iter().find(|x| self.is_sym(x)) {
+            self.advance();
+            self.enter()?;
+            let operand = self.parse_unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Unary(op, Box::new(operand)));
+        }
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> ScriptResult<Expr> {
+        let mut expr = self.parse_primary()?;
+        let mut chain = 0;
+        loop {
+            if self.eat_sym(".") {
+                let name = self.expect_ident()?;
+                expr = if self.is_sym("(") {
+                    if name != "push" {
+                        self.check_function(&name)?;
+                    }
+                    let args = self.parse_args()?;
+                    Expr::Method(Box::new(expr), name, args)
+                } else {
+                    Expr::Field(Box::new(expr), name)
+                };
+            } else if self.eat_sym("[") {
+                let index = self.parse_expr()?;
+                self.expect_sym("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                break;
+            }
+            self.enter()?;
+            chain += 1;
+        }
+        self.depth -= chain;
+        Ok(expr)
+    }
+
+    fn check_function(&self, name: &str) -> ScriptResult<()> {
+        if find_builtin(name).is_none() {
+            return Err(ScriptError { message: format!("unknown function: {}", name), line: self.line() });
+        }
+        Ok(())
+    }
+
+    fn parse_args(&mut self) -> ScriptResult<Vec<Expr>> {
+        self.expect_sym("(")?;
+        let mut args = vec![];
+        while !self.eat_sym(")") {
+            args.push(self.parse_expr()?);
+            if !self.is_sym(")") {
+                self.expect_sym(",")?;
+            }
+        }
+        Ok(args)
+    }
+
+    fn parse_primary(&mut self) -> ScriptResult<Expr> {
+        match self.peek().clone() {
+            Token::Int(x) => {
+                self.advance();
+                Ok(Expr::Literal(Value::from(x)))
+            }
+            Token::Float(x) => {
+                self.advance();
+                Ok(Expr::Literal(Value::from(x)))
+            }
+            Token::Str(x) => {
+                self.advance();
+                Ok(Expr::Literal(Value::String(x)))
+            }
+            Token::Ident(ref x) if x == "true" || x == "false" || x == "null" => {
+                self.advance();
+                Ok(Expr::Literal(match x.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => Value::Null
+                }))
+            }
+            Token::Ident(_) => {
+                let name = self.expect_ident()?;
+                if self.is_sym("(") {
+                    self.check_function(&name)?;
+                    let args = self.parse_args()?;
+                    Ok(Expr::Call(name, args))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+            Token::Sym("[") => {
+                self.advance();
+                let mut items = vec![];
+                while !self.eat_sym("]") {
+                    items.push(self.parse_expr()?);
+                    if !self.is_sym("]") {
+                        self.expect_sym(",")?;
+                    }
+                }
+                Ok(Expr::Array(items))
+            }
+            Token::Sym("#{") => {
+                self.advance();
+                let mut entries = vec![];
+                while !self.eat_sym("}") {
+                    let key = match self.peek().clone() {
+                        Token::Ident(x) | Token::Str(x) => x,
+                        _ => return self.error("expected map key")
+                    };
+                    self.advance();
+                    self.expect_sym(":")?;
+                    entries.push((key, self.parse_expr()?));
+                    if !self.is_sym("}") {
+                        self.expect_sym(",")?;
+                    }
+                }
+                Ok(Expr::Map(entries))
+            }
+            Token::Sym("(") => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect_sym(")")?;
+                Ok(expr)
+            }
+            _ => self.error("expected expression")
+        }
+    }
+}
+
+//执行计数: 超出操作数或者内存限制时返回错误
+struct Budget<'a> {
+    limits: &'a ScriptLimits,
+    operations: u64,
+    memory_bytes: usize,
+}
+
+impl<'a> Budget<'a> {
+    fn count(&mut self, operations: u64) -> ScriptResult<()> {
+        self.operations = self.operations.saturating_add(operations);
+        if self.operations > self.limits.max_operations {
+            return script_error(format!("exceeded max operations: {}", self.limits.max_operations));
+        }
+        Ok(())
+    }
+
+    fn alloc(&mut self, bytes: usize) -> ScriptResult<()> {
+        self.reserve(bytes)?;
+        self.memory_bytes += bytes;
+        Ok(())
+    }
+
+    //检查可以再分配 bytes 字节，不计入已分配的内存，用于结果可能远大于参数的内置函数
+    fn reserve(&self, bytes: usize) -> ScriptResult<()> {
+        if self.memory_bytes.saturating_add(bytes) > self.limits.max_memory_bytes {
+            return script_error(format!("exceeded max memory: {} bytes", self.limits.max_memory_bytes));
+        }
+        Ok(())
+    }
+
+    //复制或者创建的值
+    fn alloc_value(&mut self, value: &Value) -> ScriptResult<()> {
+        let (bytes, nodes, _) = measure(value);
+        self.count(nodes)?;
+        self.alloc(bytes)
+    }
+
+    //放入数组或对象的值，限制嵌套深度
+    fn check_element(&mut self, value: &Value) -> ScriptResult<()> {
+        let (_, nodes, depth) = measure(value);
+        self.count(nodes)?;
+        if depth >= MAX_VALUE_DEPTH {
+            return script_error(format!("value is nested too deeply (max {})", MAX_VALUE_DEPTH));
+        }
+        Ok(())
+    }
+}
+
+//估算值占用的内存(字节)、节点数及嵌套深度
+fn measure(value: &Value) -> (usize, u64, usize) {
+    match value {
+        Value::String(x) => (VALUE_SIZE + x.len(), 1, 0),
+        Value::Array(array) => array.iter().fold((VALUE_SIZE, 1, 1), |(bytes, nodes, depth), x| {
+            let (x_bytes, x_nodes, x_depth) = measure(x);
+            (bytes + x_bytes, nodes + x_nodes, depth.max(x_depth + 1))
+        }),
+        Value::Object(map) => map.iter().fold((VALUE_SIZE, 1, 1), |(bytes, nodes, depth), (key, x)| {
+            let (x_bytes, x_nodes, x_depth) = measure(x);
+            (bytes + key.len() + x_bytes, nodes + x_nodes, depth.max(x_depth + 1))
+        }),
+        _ => (VALUE_SIZE, 1, 0)
+    }
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+enum PathKey {
+    Field(String),
+    Index(Value),
+}
+
+static NULL: Value = Value::Null;
+
+struct Engine<'a> {
+    budget: Budget<'a>,
+    //变量作用域，第一个为顶层变量
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+fn lookup<'v>(scopes: &'v [HashMap<String, Value>], name: &str) -> ScriptResult<&'v Value> {
+    match scopes.iter().rev().find_map(|x| x.get(name)) {
+        Some(value) => Ok(value),
+        None => script_error(format!("undefined variable: {}", name))
+    }
+}
+
+fn lookup_mut<'v>(scopes: &'v mut [HashMap<String, Value>], name: &str) -> ScriptResult<&'v mut Value> {
+    match scopes.iter_mut().rev().find_map(|x| x.get_mut(name)) {
+        Some(value) => Ok(value),
+        None => script_error(format!("undefined variable: {}", name))
+    }
+}
+
+//负数下标从末尾开始
+fn array_index(len: usize, index: &Value) -> ScriptResult<usize> {
+    let index = match index.as_i64() {
+        Some(x) => x,
+        None => return script_error(format!("array index must be an integer, found {}", type_name(index)))
+    };
+    let actual = if index < 0 { (len as i64).checked_add(index) } else { Some(index) };
+    match actual {
+        Some(x) if x >= 0 && (x as usize) < len => Ok(x as usize),
+        _ => script_error(format!("array index out of bounds: {}, length: {}", index, len))
+    }
+}
+
+fn resolve<'v>(mut value: &'v Value, keys: &[PathKey]) -> ScriptResult<&'v Value> {
+    for key in keys {
+        value = match (value, key) {
+            (Value::Object(map), PathKey::Field(name)) | (Value::Object(map), PathKey::Index(Value::String(name))) => map.get(name).unwrap_or(&NULL),
+            (Value::Array(array), PathKey::Index(index)) => &array[array_index(array.len(), index)?],
+            (value, key) => return access_error(value, key)
+        };
+    }
+    Ok(value)
+}
+
+//对象中不存在的字段赋值时添加
+fn resolve_mut<'v>(mut value: &'v mut Value, keys: &[PathKey]) -> ScriptResult<&'v mut Value> {
+    for key in keys {
+        value = match (value, key) {
+            (Value::Object(map), PathKey::Field(name)) | (Value::Object(map), PathKey::Index(Value::String(name))) => map.entry(name.clone()).or_insert(Value::Null),
+            (Value::Array(array), PathKey::Index(index)) => {
+                let i = array_index(array.len(), index)?;
+                &mut array[i]
+            }
+            (value, key) => return access_error(value, key)
+        };
+    }
+    Ok(value)
+}
+
+fn access_error<T>(value: &Value, key: &PathKey) -> ScriptResult<T> {
+    match key {
+        PathKey::Field(name) => script_error(format!("can not access field {} of {}", name, type_name(value))),
+        PathKey::Index(index) => script_error(format!("can not index {} with {}", type_name(value), type_name(index)))
+    }
+}
+
+impl<'a> Engine<'a> {
+    fn exec_statements(&mut self, statements: &[Stmt]) -> ScriptResult<Flow> {
+        for statement in statements {
+            match self.exec(statement)? {
+                Flow::Normal => {}
+                flow => return Ok(flow)
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_block(&mut self, statements: &[Stmt], variable: Option<(&str, Value)>) -> ScriptResult<Flow> {
+        let mut scope = HashMap::new();
+        if let Some((name, value)) = variable {
+            scope.insert(name.to_string(), value);
+        }
+        self.scopes.push(scope);
+        let result = self.exec_statements(statements);
+        self.scopes.pop();
+        result
+    }
+
+    fn exec(&mut self, statement: &Stmt) -> ScriptResult<Flow> {
+        self.exec_kind(&statement.kind).map_err(|mut e| {
+            if e.line == 0 {
+                e.line = statement.line;
+            }
+            e
+        })
+    }
+
+    fn exec_kind(&mut self, kind: &StmtKind) -> ScriptResult<Flow> {
+        self.budget.count(1)?;
+        match kind {
+            StmtKind::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), value);
+            }
+            StmtKind::Assign(target, op, expr) => {
+                let value = self.eval(expr)?;
+                let mut keys = vec![];
+                let name = self.eval_place(target, &mut keys)?;
+                if !keys.is_empty() {
+                    self.budget.check_element(&value)?;
+                    self.budget.alloc(VALUE_SIZE)?;
+                }
+                let slot = resolve_mut(lookup_mut(&mut self.scopes, &name)?, &keys)?;
+                if *op == "=" {
+                    *slot = value;
+                } else {
+                    //在原值上运算，字符串拼接不复制原字符串
+                    let current = std::mem::replace(slot, Value::Null);
+                    *slot = binary_op(&mut self.budget, &op[..1], current, value)?;
+                }
+            }
+            StmtKind::If(condition, then_block, else_block) => {
+                if self.eval_condition(condition)? {
+                    return self.exec_block(then_block, None);
+                } else if !else_block.is_empty() {
+                    return self.exec_block(else_block, None);
+                }
+            }
+            StmtKind::For(name, iterable, body) => {
+                let items = match self.eval(iterable)? {
+                    Value::Array(array) => array,
+                    Value::Object(map) => {
+                        self.budget.alloc(map.len() * VALUE_SIZE)?;
+                        map.into_iter().map(|(key, _)| Value::String(key)).collect()
+                    }
+                    Value::String(text) => {
+                        self.budget.alloc(text.len() * VALUE_SIZE)?;
+                        text.chars().map(|x| Value::String(x.to_string())).collect()
+                    }
+                    other => return script_error(format!("can not iterate {}", type_name(&other)))
+                };
+                for item in items {
+                    self.budget.count(1)?;
+                    match self.exec_block(body, Some((name.as_str(), item)))? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        _ => {}
+                    }
+                }
+            }
+            StmtKind::While(condition, body) => {
+                while self.eval_condition(condition)? {
+                    match self.exec_block(body, None)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        _ => {}
+                    }
+                }
+            }
+            StmtKind::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.eval(expr)?,
+                    None => Value::Null
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+            StmtKind::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn eval_condition(&mut self, expr: &Expr) -> ScriptResult<bool> {
+        match self.eval(expr)? {
+            Value::Bool(x) => Ok(x),
+            other => script_error(format!("condition must be a bool, found {}", type_name(&other)))
+        }
+    }
+
+    //计算变量路径中的下标，返回变量名称
+    fn eval_place(&mut self, expr: &Expr, keys: &mut Vec<PathKey>) -> ScriptResult<String> {
+        match expr {
+            Expr::Var(name) => Ok(name.clone()),
+            Expr::Field(base, field) => {
+                let name = self.eval_place(base, keys)?;
+                keys.push(PathKey::Field(field.clone()));
+                Ok(name)
+            }
+            Expr::Index(base, index) => {
+                let name = self.eval_place(base, keys)?;
+                let index = self.eval(index)?;
+                keys.push(PathKey::Index(index));
+                Ok(name)
+            }
+            _ => script_error("expected variable")
+        }
+    }
+
+    //变量路径只复制最终的值
+    fn eval_place_value(&mut self, expr: &Expr) -> ScriptResult<Value> {
+        let mut keys = vec![];
+        let name = self.eval_place(expr, &mut keys)?;
+        let value = resolve(lookup(&self.scopes, &name)?, &keys)?.clone();
+        self.budget.alloc_value(&value)?;
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> ScriptResult<Value> {
+        self.budget.count(1)?;
+        match expr {
+            Expr::Literal(value) => {
+                if let Value::String(x) = value {
+                    self.budget.alloc(x.len())?;
+                }
+                Ok(value.clone())
+            }
+            Expr::Var(_) => self.eval_place_value(expr),
+            Expr::Field(..) | Expr::Index(..) if is_place(expr) => self.eval_place_value(expr),
+            Expr::Field(base, field) => {
+                let base = self.eval(base)?;
+                Ok(resolve(&base, &[PathKey::Field(field.clone())])?.clone())
+            }
+            Expr::Index(base, index) => {
+                let base = self.eval(base)?;
+                let index = self.eval(index)?;
+                Ok(resolve(&base, &[PathKey::Index(index)])?.clone())
+            }
+            Expr::Array(items) => {
+                let mut array = Vec::with_capacity(items.len());
+                for item in items {
+                    let value = self.eval(item)?;
+                    self.budget.check_element(&value)?;
+                    array.push(value);
+                }
+                self.budget.alloc(VALUE_SIZE * (array.len() + 1))?;
+                Ok(Value::Array(array))
+            }
+            Expr::Map(entries) => {
+                let mut map = Map::new();
+                for (key, item) in entries {
+                    let value = self.eval(item)?;
+                    self.budget.check_element(&value)?;
+                    self.budget.alloc(VALUE_SIZE + key.len())?;
+                    map.insert(key.clone(), value);
+                }
+                self.budget.alloc(VALUE_SIZE)?;
+                Ok(Value::Object(map))
+            }
+            Expr::Call(name, args) => self.call(name, None, args),
+            Expr::Method(target, name, args) => {
+                if name == "push" {
+                    self.push(target, args)
+                } else {
+                    self.call(name, Some(target), args)
+                }
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                unary_op(op, value)
+            }
+            Expr::Binary(op, left, right) if *op == "&&" || *op == "||" => {
+                let left = self.eval_condition(left)?;
+                //短路求值
+                if (*op == "&&") != left {
+                    return Ok(Value::Bool(left));
+                }
+                Ok(Value::Bool(self.eval_condition(right)?))
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary_op(&mut self.budget, op, left, right)
+            }
+        }
+    }
+
+    //变量参数按引用传给内置函数，避免复制大的查询结果
+    fn call(&mut self, name: &str, target: Option<&Expr>, args: &[Expr]) -> ScriptResult<Value> {
+        let &(_, min_args, max_args, function) = match find_builtin(name) {
+            Some(x) => x,
+            None => return script_error(format!("unknown function: {}", name))
+        };
+        let exprs: Vec<&Expr> = target.into_iter().chain(args.iter()).collect();
+        if exprs.len() < min_args || exprs.len() > max_args {
+            return script_error(format!("function {} expects {}-{} arguments, found {}", name, min_args, max_args, exprs.len()));
+        }
+        let mut places = Vec::with_capacity(exprs.len());
+        let mut values = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            if is_place(expr) {
+                let mut keys = vec![];
+                let name = self.eval_place(expr, &mut keys)?;
+                places.push(Some((name, keys)));
+                values.push(Value::Null);
+            } else {
+                places.push(None);
+                values.push(self.eval(expr)?);
+            }
+        }
+        let mut refs = Vec::with_capacity(values.len());
+        for (place, value) in places.iter().zip(values.iter()) {
+            refs.push(match place {
+                Some((name, keys)) => resolve(lookup(&self.scopes, name)?, keys)?,
+                None => value
+            });
+        }
+        let size: usize = refs.iter().map(|x| match x {
+            Value::String(x) => x.len(),
+            Value::Array(x) => x.len(),
+            Value::Object(x) => x.len(),
+            _ => 0
+        }).sum();
+        self.budget.count(1 + size as u64)?;
+        let result = function(&mut self.budget, &refs).map_err(|e| ScriptError { message: format!("{}: {}", name, e.message), line: e.line })?;
+        self.budget.alloc_value(&result)?;
+        Ok(result)
+    }
+
+    fn push(&mut self, target: &Expr, args: &[Expr]) -> ScriptResult<Value> {
+        if args.len() != 1 {
+            return script_error(format!("function push expects 1 argument, found {}", args.len()));
+        }
+        if !is_place(target) {
+            return script_error("push target must be a variable");
+        }
+        let value = self.eval(&args[0])?;
+        self.budget.check_element(&value)?;
+        self.budget.alloc(VALUE_SIZE)?;
+        let mut keys = vec![];
+        let name = self.eval_place(target, &mut keys)?;
+        match resolve_mut(lookup_mut(&mut self.scopes, &name)?, &keys)? {
+            Value::Array(array) => array.push(value),
+            other => return script_error(format!("push requires an array, found {}", type_name(other)))
+        }
+        Ok(Value::Null)
+    }
+}
+
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(x) if x.is_f64() => "float",
+        Value::Number(_) => "int",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "map",
+    }
+}
+
+//拼接字符串时的文本，字符串不加引号
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(x) => x.clone(),
+        other => other.to_string()
+    }
+}
+
+fn float_value(x: f64) -> ScriptResult<Value> {
+    match Number::from_f64(x) {
+        Some(x) => Ok(Value::Number(x)),
+        None => script_error(format!("invalid float result: {}", x))
+    }
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => match (l.as_i64(), r.as_i64()) {
+            (Some(l), Some(r)) => l == r,
+            _ => l.as_f64() == r.as_f64()
+        },
+        _ => left == right
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> ScriptResult<Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => match (l.as_i64(), r.as_i64()) {
+            (Some(l), Some(r)) => Ok(l.cmp(&r)),
+            _ => l.as_f64().unwrap_or(0.0).partial_cmp(&r.as_f64().unwrap_or(0.0))
+                .map_or_else(|| script_error("can not compare NaN"), Ok)
+        },
+        (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+        _ => script_error(format!("can not compare {} with {}", type_name(left), type_name(right)))
+    }
+}
+
+fn unary_op(op: &str, value: Value) -> ScriptResult<Value> {
+    match (op, &value) {
+        ("!", Value::Bool(x)) => Ok(Value::Bool(!x)),
+        ("-", Value::Number(x)) => match x.as_i64() {
+            Some(x) => x.checked_neg().map(Value::from).map_or_else(|| script_error("integer overflow"), Ok),
+            None => float_value(-x.as_f64().unwrap_or(0.0))
+        },
+        _ => script_error(format!("can not apply '{}' to {}", op, type_name(&value)))
+    }
+}
+
+fn binary_op(budget: &mut Budget, op: &str, left: Value, right: Value) -> ScriptResult<Value> {
+    match (op, left, right) {
+        ("+", Value::String(mut left), right) => {
+            let text = to_text(&right);
+            budget.alloc(text.len())?;
+            left.push_str(&text);
+            Ok(Value::String(left))
+        }
+        ("+", left, Value::String(right)) => {
+            let mut text = to_text(&left);
+            budget.alloc(text.len() + right.len())?;
+            text.push_str(&right);
+            Ok(Value::String(text))
+        }
+        ("+", Value::Array(mut left), Value::Array(right)) => {
+            budget.alloc(right.len() * VALUE_SIZE)?;
+            left.extend(right);
+            Ok(Value::Array(left))
+        }
+        ("==", left, right) => Ok(Value::Bool(values_equal(&left, &right))),
+        ("!=", left, right) => Ok(Value::Bool(!values_equal(&left, &right))),
+        ("<", left, right) => Ok(Value::Bool(compare(&left, &right)? == Ordering::Less)),
+        ("<=", left, right) => Ok(Value::Bool(compare(&left, &right)? != Ordering::Greater)),
+        (">", left, right) => Ok(Value::Bool(compare(&left, &right)? == Ordering::Greater)),
+        (">=", left, right) => Ok(Value::Bool(compare(&left, &right)? != Ordering::Less)),
+        (op, Value::Number(left), Value::Number(right)) => arithmetic(op, &left, &right),
+        (op, left, right) => script_error(format!("can not apply '{}' to {} and {}", op, type_name(&left), type_name(&right)))
+    }
+}
+
+//两个整数时按整数运算(除法取整)，溢出时返回错误，否则按浮点数运算
+fn arithmetic(op: &str, left: &Number, right: &Number) -> ScriptResult<Value> {
+    if let (Some(l), Some(r)) = (left.as_i64(), right.as_i64()) {
+        if (op == "/" || op == "%") && r == 0 {
+            return script_error("division by zero");
+        }
+        let result = match op {
+            "+" => l.checked_add(r),
+            "-" => l.checked_sub(r),
+            "*" => l.checked_mul(r),
+            "/" => l.checked_div(r),
+            "%" => l.checked_rem(r),
+            _ => return script_error(format!("unknown operator: {}", op))
+        };
+        return result.map(Value::from).map_or_else(|| script_error(format!("integer overflow: {} {} {}", l, op, r)), Ok);
+    }
+    let (l, r) = (left.as_f64().unwrap_or(0.0), right.as_f64().unwrap_or(0.0));
+    match op {
+        "+" => float_value(l + r),
+        "-" => float_value(l - r),
+        "*" => float_value(l * r),
+        "/" => float_value(l / r),
+        "%" => float_value(l % r),
+        _ => script_error(format!("unknown operator: {}", op))
+    }
+}
+
+type Builtin = fn(&mut Budget, &[&Value]) -> ScriptResult<Value>;
+
+//白名单内置函数: (名称, 最少参数数, 最多参数数, 函数)
+const BUILTINS: &[(&str, usize, usize, Builtin)] = &[
+    ("len", 1, 1, builtin_len),
+    ("type_of", 1, 1, builtin_type_of),
+    ("keys", 1, 1, builtin_keys),
+    ("values", 1, 1, builtin_values),
+    ("contains", 2, 2, builtin_contains),
+    ("starts_with", 2, 2, builtin_starts_with),
+    ("ends_with", 2, 2, builtin_ends_with),
+    ("to_string", 1, 1, builtin_to_string),
+    ("to_int", 1, 1, builtin_to_int),
+    ("to_float", 1, 1, builtin_to_float),
+    ("to_fixed", 2, 2, builtin_to_fixed),
+    ("abs", 1, 1, builtin_abs),
+    ("round", 1, 2, builtin_round),
+    ("min", 2, 2, builtin_min),
+    ("max", 2, 2, builtin_max),
+    ("sum", 1, 2, builtin_sum),
+    ("sort_by", 2, 3, builtin_sort_by),
+    ("reverse", 1, 1, builtin_reverse),
+    ("slice", 2, 3, builtin_slice),
+    ("range", 2, 2, builtin_range),
+    ("join", 2, 2, builtin_join),
+    ("split", 2, 2, builtin_split),
+    ("replace", 3, 3, builtin_replace),
+    ("upper", 1, 1, builtin_upper),
+    ("lower", 1, 1, builtin_lower),
+    ("trim", 1, 1, builtin_trim),
+];
+
+fn find_builtin(name: &str) -> Option<&'static (&'static str, usize, usize, Builtin)> {
+    BUILTINS.iter().find(|x| x.0 == name)
+}
+
+fn arg_error<T>(expected: &str, value: &Value) -> ScriptResult<T> {
+    script_error(format!("expected {}, found {}", expected, type_name(value)))
+}
+
+fn as_str(value: &Value) -> ScriptResult<&str> {
+    match value {
+        Value::String(x) => Ok(x),
+        other => arg_error("string", other)
+    }
+}
+
+fn as_array(value: &Value) -> ScriptResult<&Vec<Value>> {
+    match value {
+        Value::Array(x) => Ok(x),
+        other => arg_error("array", other)
+    }
+}
+
+fn as_map(value: &Value) -> ScriptResult<&Map<String, Value>> {
+    match value {
+        Value::Object(x) => Ok(x),
+        other => arg_error("map", other)
+    }
+}
+
+fn as_int(value: &Value) -> ScriptResult<i64> {
+    match value.as_i64() {
+        Some(x) => Ok(x),
+        None => arg_error("int", value)
+    }
+}
+
+fn as_number(value: &Value) -> ScriptResult<f64> {
+    match value {
+        Value::Number(x) => Ok(x.as_f64().unwrap_or(0.0)),
+        other => arg_error("number", other)
+    }
+}
+
+fn builtin_len(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    match args[0] {
+        Value::String(x) => Ok(Value::from(x.chars().count())),
+        Value::Array(x) => Ok(Value::from(x.len())),
+        Value::Object(x) => Ok(Value::from(x.len())),
+        other => arg_error("string, array or map", other)
+    }
+}
+
+fn builtin_type_of(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::from(type_name(args[0])))
+}
+
+fn builtin_keys(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::Array(as_map(args[0])?.keys().map(|x| Value::from(x.as_str())).collect()))
+}
+
+fn builtin_values(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::Array(as_map(args[0])?.values().cloned().collect()))
+}
+
+fn builtin_contains(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let found = match args[0] {
+        Value::String(x) => x.contains(as_str(args[1])?),
+        Value::Array(x) => x.iter().any(|item| values_equal(item, args[1])),
+        Value::Object(x) => x.contains_key(as_str(args[1])?),
+        other => return arg_error("string, array or map", other)
+    };
+    Ok(Value::Bool(found))
+}
+
+fn builtin_starts_with(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::Bool(as_str(args[0])?.starts_with(as_str(args[1])?)))
+}
+
+fn builtin_ends_with(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::Bool(as_str(args[0])?.ends_with(as_str(args[1])?)))
+}
+
+fn builtin_to_string(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::String(to_text(args[0])))
+}
+
+fn builtin_to_int(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let result = match args[0] {
+        Value::Number(x) => match x.as_i64() {
+            Some(x) => Some(x),
+            None => x.as_f64().filter(|x| x.is_finite() && x.abs() < i64::MAX as f64).map(|x| x as i64)
+        },
+        Value::String(x) => x.trim().parse::<i64>().ok(),
+        Value::Bool(x) => Some(*x as i64),
+        _ => None
+    };
+    match result {
+        Some(x) => Ok(Value::from(x)),
+        None => script_error(format!("can not convert to int: {}", args[0]))
+    }
+}
+
+fn builtin_to_float(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    match args[0] {
+        Value::Number(x) => float_value(x.as_f64().unwrap_or(0.0)),
+        Value::String(x) => match x.trim().parse::<f64>() {
+            Ok(x) => float_value(x),
+            Err(_) => script_error(format!("can not convert to float: {}", x))
+        },
+        other => arg_error("number or string", other)
+    }
+}
+
+fn decimal_digits(value: &Value) -> ScriptResult<usize> {
+    match as_int(value)? {
+        x @ 0..=15 => Ok(x as usize),
+        x => script_error(format!("decimal digits must be in 0-15, found {}", x))
+    }
+}
+
+//保留指定位数小数的文本，如 to_fixed(12.345, 1) 为 "12.3"
+fn builtin_to_fixed(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let digits = decimal_digits(args[1])?;
+    Ok(Value::from(format!("{:.*}", digits, as_number(args[0])?)))
+}
+
+fn builtin_abs(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    match args[0].as_i64() {
+        Some(x) => x.checked_abs().map(Value::from).map_or_else(|| script_error("integer overflow"), Ok),
+        None => float_value(as_number(args[0])?.abs())
+    }
+}
+
+fn builtin_round(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    if let Some(x) = args[0].as_i64() {
+        return Ok(Value::from(x));
+    }
+    let digits = match args.get(1) {
+        Some(x) => decimal_digits(x)?,
+        None => 0
+    };
+    let scale = 10f64.powi(digits as i32);
+    float_value((as_number(args[0])? * scale).round() / scale)
+}
+
+fn builtin_min(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    as_number(args[0])?;
+    as_number(args[1])?;
+    Ok(if compare(args[1], args[0])? == Ordering::Less { args[1].clone() } else { args[0].clone() })
+}
+
+fn builtin_max(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    as_number(args[0])?;
+    as_number(args[1])?;
+    Ok(if compare(args[1], args[0])? == Ordering::Greater { args[1].clone() } else { args[0].clone() })
+}
+
+//数组元素(或者元素的字段)之和，忽略 null，都是整数时结果为整数
+fn builtin_sum(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let field = match args.get(1) {
+        Some(x) => Some(as_str(x)?),
+        None => None
+    };
+    let mut int_sum: Option<i64> = Some(0);
+    let mut float_sum = 0.0;
+    for item in as_array(args[0])? {
+        let value = match field {
+            Some(field) => item.get(field).unwrap_or(&NULL),
+            None => item
+        };
+        match value {
+            Value::Null => continue,
+            Value::Number(x) => {
+                int_sum = match (int_sum, x.as_i64()) {
+                    (Some(sum), Some(x)) => Some(sum.checked_add(x).map_or_else(|| script_error("integer overflow"), Ok)?),
+                    _ => None
+                };
+                float_sum += x.as_f64().unwrap_or(0.0);
+            }
+            other => return arg_error("number", other)
+        }
+    }
+    match int_sum {
+        Some(x) => Ok(Value::from(x)),
+        None => float_value(float_sum)
+    }
+}
+
+//排序时 null 在前，不同类型的值视为相等
+fn sort_key_cmp(left: &Value, right: &Value) -> Ordering {
+    match (left, right) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        _ => compare(left, right).unwrap_or(Ordering::Equal)
+    }
+}
+
+//按字段排序(稳定排序)，字段为空字符串时按元素本身排序，第3个参数为 true 时降序
+fn builtin_sort_by(budget: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let array = as_array(args[0])?;
+    let field = as_str(args[1])?;
+    let desc = match args.get(2) {
+        Some(Value::Bool(x)) => *x,
+        Some(other) => return arg_error("bool", other),
+        None => false
+    };
+    let n = array.len() as u64;
+    budget.count(n * (64 - n.leading_zeros() as u64))?;
+    let mut result = array.clone();
+    let key = |x: &Value| -> Value {
+        if field.is_empty() { x.clone() } else { x.get(field).cloned().unwrap_or(Value::Null) }
+    };
+    result.sort_by(|a, b| {
+        let ordering = sort_key_cmp(&key(a), &key(b));
+        if desc { ordering.reverse() } else { ordering }
+    });
+    Ok(Value::Array(result))
+}
+
+fn builtin_reverse(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::Array(as_array(args[0])?.iter().rev().cloned().collect()))
+}
+
+//数组的 [start, end) 部分，负数从末尾开始，超出范围时截断
+fn builtin_slice(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let array = as_array(args[0])?;
+    let len = array.len() as i64;
+    let normalize = |x: i64| if x < 0 { (len + x).max(0) } else { x.min(len) };
+    let start = normalize(as_int(args[1])?);
+    let end = match args.get(2) {
+        Some(x) => normalize(as_int(x)?),
+        None => len
+    };
+    if start >= end {
+        return Ok(Value::Array(vec![]));
+    }
+    Ok(Value::Array(array[start as usize..end as usize].to_vec()))
+}
+
+//[start, end) 的整数数组
+fn builtin_range(budget: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let (start, end) = (as_int(args[0])?, as_int(args[1])?);
+    if start >= end {
+        return Ok(Value::Array(vec![]));
+    }
+    let len = (end as i128 - start as i128) as u128;
+    let len = if len > usize::MAX as u128 / VALUE_SIZE as u128 { usize::MAX } else { len as usize * VALUE_SIZE };
+    budget.reserve(len)?;
+    Ok(Value::Array((start..end).map(Value::from).collect()))
+}
+
+fn builtin_join(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let separator = as_str(args[1])?;
+    let texts: Vec<String> = as_array(args[0])?.iter().map(to_text).collect();
+    Ok(Value::from(texts.join(separator)))
+}
+
+fn builtin_split(budget: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let (text, separator) = (as_str(args[0])?, as_str(args[1])?);
+    if separator.is_empty() {
+        return script_error("separator can not be empty");
+    }
+    budget.reserve((text.matches(separator).count() + 1) * VALUE_SIZE + text.len())?;
+    Ok(Value::Array(text.split(separator).map(Value::from).collect()))
+}
+
+fn builtin_replace(budget: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    let (text, from, to) = (as_str(args[0])?, as_str(args[1])?, as_str(args[2])?);
+    if from.is_empty() {
+        return script_error("pattern can not be empty");
+    }
+    budget.reserve(text.len().saturating_add(text.matches(from).count().saturating_mul(to.len())))?;
+    Ok(Value::from(text.replace(from, to)))
+}
+
+fn builtin_upper(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::from(as_str(args[0])?.to_uppercase()))
+}
+
+fn builtin_lower(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::from(as_str(args[0])?.to_lowercase()))
+}
+
+fn builtin_trim(_: &mut Budget, args: &[&Value]) -> ScriptResult<Value> {
+    Ok(Value::from(as_str(args[0])?.trim()))
+}
@@ -0,0 +1,324 @@
+
+//分析插件: 启动时从插件目录加载 *.toml，注册自定义命令、报告及调用栈分类规则，不需要修改代码即可扩展分析功能
+//插件只能执行只读的查询语言(query_dsl)、模式匹配及沙箱脚本(plugin_script)，不能访问文件和网络
+//命令的 script 对查询结果做后处理: 变量 result 为查询结果，params 为命令参数，
+//  脚本执行 return 时以返回值作为结果，否则使用脚本修改后的 result
+//报告执行 queries 中的所有查询，script 使用变量 results(查询名称 -> 查询结果)及 params 生成报告内容(字符串，如Markdown)
+//脚本的操作数及内存限制由服务配置 plugin_script_limits 指定，加载插件时检查脚本语法
+//插件示例(plugins/spring.toml):
+//  name = "spring"
+//  description = "Spring MVC analysis"
+//  [[commands]]
+//  name = "top_controllers"
+//  query = "top {limit} methods where method like '%Controller.%' order by {order}"
+//  script = """
+//  let rows = [];
+//  for row in result.rows { if row.ratio >= 0.01 { rows.push(row); } }
+//  result.rows = rows;
+//  """
+//  [[reports]]
+//  name = "summary"
+//  queries = { controllers = "top 10 methods where method like '%Controller.%' order by cpu" }
+//  script = """
+//  let text = "# Controllers\n";
+//  for row in results.controllers.rows { text += "- " + row.name + ": " + to_fixed(row.ratio * 100.0, 1) + "%\n"; }
+//  return text;
+//  """
+//  [[classifiers]]
+//  category = "jdbc"
+//  patterns = ["java.sql.%", "com.mysql.%"]
+
+use ::sample::*;
+use query_dsl::*;
+use plugin_script::*;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use serde_json::{json, Value};
+use utils::*;
+use sample_path::path_to_string;
+
+pub const DEFAULT_PLUGINS_DIR: &str = "plugins";
+//未匹配任何分类规则的调用栈
+pub const OTHER_CATEGORY: &str = "other";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PluginCommand {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    //查询语言模板，{param} 使用命令参数替换
+    pub query: String,
+    //参数默认值
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+    //查询结果的后处理脚本，为空时直接返回查询结果
+    #[serde(default)]
+    pub script: String,
+}
+
+//自定义报告: 执行多个查询，由脚本生成报告内容
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PluginReport {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    //查询名称 -> 查询语言模板
+    #[serde(default)]
+    pub queries: BTreeMap<String, String>,
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+    //返回报告内容(字符串)的脚本
+    pub script: String,
+}
+
+//调用栈分类规则，按栈顶开始第一个匹配(LIKE语法)的方法确定分类
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FrameClassifier {
+    pub category: String,
+    pub patterns: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Plugin {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub commands: Vec<PluginCommand>,
+    #[serde(default)]
+    pub reports: Vec<PluginReport>,
+    #[serde(default)]
+    pub classifiers: Vec<FrameClassifier>,
+    #[serde(skip_deserializing)]
+    pub path: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CategoryStats {
+    pub category: String,
+    pub samples: i64,
+    pub cpu_time: i64,
+    pub ratio: f64,
+}
+
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Plugin>,
+    script_limits: ScriptLimits,
+}
+
+impl PluginRegistry {
+    //加载目录下的所有插件，单个插件加载失败不影响其它插件
+    pub fn load_dir(plugins_dir: &str, script_limits: &ScriptLimits) -> PluginRegistry {
+        let mut registry = PluginRegistry { plugins: vec![], script_limits: script_limits.clone() };
+        let mut paths = match std::fs::read_dir(plugins_dir) {
+            Ok(dir) => dir.filter_map(|x| x.ok()).map(|x| x.path())
+                .filter(|x| x.extension().map(|ext| ext == "toml").unwrap_or(false))
+                .collect::<Vec<_>>(),
+            Err(_) => return registry
+        };
+        paths.sort();
+        for path in paths {
+            match load_plugin(&path).and_then(|plugin| registry.register(plugin)) {
+                Ok(_) => println!("load plugin: {:?}", path),
+                Err(e) => println!("load plugin failed: {:?}, error: {}", path, e),
+            }
+        }
+        registry
+    }
+
+    pub fn register(&mut self, plugin: Plugin) -> io::Result<()> {
+        if self.plugins.iter().any(|x| x.name == plugin.name) {
+            return Err(new_invalid_input_error(&format!("duplicated plugin name: {}", plugin.name)));
+        }
+        //参数都有默认值时提前检查查询语句，总是检查脚本语法
+        for command in &plugin.commands {
+            check_query_template(&command.query, &command.defaults)
+                .map_err(|e| new_invalid_input_error(&format!("invalid query of command {}.{}: {}", plugin.name, command.name, e)))?;
+            if !command.script.is_empty() {
+                compile_script(&command.script)
+                    .map_err(|e| new_invalid_input_error(&format!("invalid script of command {}.{}: {}", plugin.name, command.name, e)))?;
+            }
+        }
+        for report in &plugin.reports {
+            for (name, query) in &report.queries {
+                check_query_template(query, &report.defaults)
+                    .map_err(|e| new_invalid_input_error(&format!("invalid query {} of report {}.{}: {}", name, plugin.name, report.name, e)))?;
+            }
+            compile_script(&report.script)
+                .map_err(|e| new_invalid_input_error(&format!("invalid script of report {}.{}: {}", plugin.name, report.name, e)))?;
+        }
+        self.plugins.push(plugin);
+        Ok(())
+    }
+
+    pub fn get_plugins(&self) -> &[Plugin] {
+        &self.plugins
+    }
+
+    pub fn get_plugin(&self, name: &str) -> io::Result<&Plugin> {
+        self.plugins.iter().find(|x| x.name == name)
+            .ok_or_else(|| new_error(io::ErrorKind::NotFound, &format!("plugin not found: {}", name)))
+    }
+
+    pub fn get_command(&self, plugin_name: &str, command_name: &str) -> io::Result<&PluginCommand> {
+        self.get_plugin(plugin_name)?.commands.iter().find(|x| x.name == command_name)
+            .ok_or_else(|| new_error(io::ErrorKind::NotFound, &format!("plugin command not found: {}.{}", plugin_name, command_name)))
+    }
+
+    pub fn get_report(&self, plugin_name: &str, report_name: &str) -> io::Result<&PluginReport> {
+        self.get_plugin(plugin_name)?.reports.iter().find(|x| x.name == report_name)
+            .ok_or_else(|| new_error(io::ErrorKind::NotFound, &format!("plugin report not found: {}.{}", plugin_name, report_name)))
+    }
+
+    pub fn get_script_limits(&self) -> &ScriptLimits {
+        &self.script_limits
+    }
+}
+
+fn check_query_template(template: &str, defaults: &HashMap<String, String>) -> io::Result<()> {
+    if let Ok(query_str) = render_query(template, defaults, &serde_json::Map::new()) {
+        parse_query(&query_str)?;
+    }
+    Ok(())
+}
+
+pub fn load_plugin(path: &Path) -> io::Result<Plugin> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut plugin = toml::from_str::<Plugin>(&contents).map_err(|e| new_error(io::ErrorKind::InvalidData, &e.to_string()))?;
+    plugin.path = path_to_string(path);
+    Ok(plugin)
+}
+
+//替换模板中的 {param}，参数值不能包含引号，避免改变查询语句的结构
+pub fn render_query(template: &str, defaults: &HashMap<String, String>, params: &serde_json::Map<String, Value>) -> io::Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').map(|x| start + x)
+            .ok_or_else(|| new_invalid_input_error(&format!("unclosed param of query template: {}", template)))?;
+        let name = &rest[start + 1..end];
+        let value = match params.get(name) {
+            Some(Value::String(x)) => x.clone(),
+            Some(Value::Number(x)) => x.to_string(),
+            Some(_) => return Err(new_invalid_input_error(&format!("invalid param value: {}", name))),
+            None => defaults.get(name).cloned()
+                .ok_or_else(|| new_invalid_input_error(&format!("missing param: {}", name)))?
+        };
+        if value.contains('\'') || value.contains('"') {
+            return Err(new_invalid_input_error(&format!("param value can not contain quotes: {}", name)));
+        }
+        result.push_str(&rest[..start]);
+        result.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+//执行插件命令，方法类的结果按分类规则标注分类，有脚本时由脚本处理查询结果
+pub fn run_plugin_command(collector: &mut SampleCollector, plugin: &Plugin, command: &PluginCommand, params: &serde_json::Map<String, Value>, limits: &ScriptLimits) -> io::Result<Value> {
+    let query_str = render_query(&command.query, &command.defaults, params)?;
+    let mut value = run_plugin_query(collector, plugin, &query_str)?;
+    if !command.script.is_empty() {
+        let mut variables = HashMap::new();
+        variables.insert("result".to_string(), value);
+        variables.insert("params".to_string(), script_params(&command.defaults, params));
+        let mut output = compile_script(&command.script)?.run(variables, limits)
+            .map_err(|e| new_invalid_input_error(&format!("run script of command {}.{} failed: {}", plugin.name, command.name, e)))?;
+        value = match output.value {
+            Some(x) => x,
+            None => output.variables.remove("result").unwrap_or(Value::Null)
+        };
+    }
+    Ok(json!({"plugin": plugin.name, "command": command.name, "query": query_str, "result": value}))
+}
+
+//执行报告的所有查询，由脚本生成报告内容
+pub fn run_plugin_report(collector: &mut SampleCollector, plugin: &Plugin, report: &PluginReport, params: &serde_json::Map<String, Value>, limits: &ScriptLimits) -> io::Result<Value> {
+    let mut queries = serde_json::Map::new();
+    let mut results = serde_json::Map::new();
+    for (name, template) in &report.queries {
+        let query_str = render_query(template, &report.defaults, params)?;
+        results.insert(name.clone(), run_plugin_query(collector, plugin, &query_str)?);
+        queries.insert(name.clone(), Value::from(query_str));
+    }
+    let mut variables = HashMap::new();
+    variables.insert("results".to_string(), Value::Object(results));
+    variables.insert("params".to_string(), script_params(&report.defaults, params));
+    let output = compile_script(&report.script)?.run(variables, limits)
+        .map_err(|e| new_invalid_input_error(&format!("run script of report {}.{} failed: {}", plugin.name, report.name, e)))?;
+    let content = match output.value {
+        Some(Value::String(x)) => x,
+        _ => return Err(new_invalid_input_error(&format!("script of report {}.{} must return a string", plugin.name, report.name)))
+    };
+    Ok(json!({"plugin": plugin.name, "report": report.name, "queries": queries, "content": content}))
+}
+
+fn run_plugin_query(collector: &mut SampleCollector, plugin: &Plugin, query_str: &str) -> io::Result<Value> {
+    let query = parse_query(query_str)?;
+    let result = execute_query(collector, &query)?;
+    let mut value = serde_json::to_value(&result)?;
+    if query.target == QueryTarget::Methods || query.target == QueryTarget::SelfMethods {
+        if let Some(rows) = value["rows"].as_array_mut() {
+            for (row, query_row) in rows.iter_mut().zip(result.rows.iter()) {
+                let category = classify_method(&plugin.classifiers, &query_row.name).unwrap_or(OTHER_CATEGORY);
+                row["category"] = Value::from(category);
+            }
+        }
+    }
+    Ok(value)
+}
+
+//脚本的 params 变量: 参数默认值及请求中的参数
+fn script_params(defaults: &HashMap<String, String>, params: &serde_json::Map<String, Value>) -> Value {
+    let mut result: serde_json::Map<String, Value> = defaults.iter().map(|(k, v)| (k.clone(), Value::from(v.as_str()))).collect();
+    for (k, v) in params {
+        result.insert(k.clone(), v.clone());
+    }
+    Value::Object(result)
+}
+
+pub fn classify_method<'a>(classifiers: &'a [FrameClassifier], method_name: &str) -> Option<&'a str> {
+    classifiers.iter()
+        .find(|x| x.patterns.iter().any(|pattern| like_match(method_name, pattern)))
+        .map(|x| x.category.as_str())
+}
+
+//按分类规则统计所有线程的取样
+pub fn classify_samples(collector: &mut SampleCollector, classifiers: &[FrameClassifier], start_time: i64, end_time: i64) -> io::Result<Vec<CategoryStats>> {
+    let start_time = if start_time < 0 { collector.get_sample_info().record_start_time } else { start_time };
+    let threads = collector.get_threads()?;
+    let mut method_categories: HashMap<i64, Option<String>> = HashMap::new();
+    let mut stats_map: HashMap<String, (i64, i64)> = HashMap::new();
+    let mut total_samples = 0;
+    for thread in &threads {
+        for thread_data in collector.load_thread_samples(thread.id, start_time, end_time)? {
+            let mut category = None;
+            //栈顶在前
+            for method in &thread_data.stacktrace {
+                if !method_categories.contains_key(method) {
+                    let method_name = collector.get_method_name(*method);
+                    method_categories.insert(*method, classify_method(classifiers, &method_name).map(|x| x.to_string()));
+                }
+                if let Some(Some(x)) = method_categories.get(method) {
+                    category = Some(x.clone());
+                    break;
+                }
+            }
+            let stats = stats_map.entry(category.unwrap_or_else(|| OTHER_CATEGORY.to_string())).or_insert((0, 0));
+            stats.0 += 1;
+            stats.1 += thread_data.cpu_time_delta;
+            total_samples += 1;
+        }
+    }
+    let mut result: Vec<CategoryStats> = stats_map.into_iter().map(|(category, (samples, cpu_time))| CategoryStats {
+        category,
+        samples,
+        cpu_time,
+        ratio: if total_samples > 0 { samples as f64 / total_samples as f64 } else { 0.0 },
+    }).collect();
+    result.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.category.cmp(&b.category)));
+    Ok(result)
+}
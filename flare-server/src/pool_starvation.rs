@@ -0,0 +1,234 @@
+
+//线程池饥饿检测：同一个线程池(按名称分组)的所有线程同时阻塞/等待在同一个锁或外部调用上，
+//并且持续一段时间，此时线程池无法处理新任务，是生产环境中常见的问题根源
+//  阻塞: BLOCKED/WAITING/TIMED_WAITING 状态，或者栈顶为socket等IO读取
+//  等待新任务的空闲线程(ThreadPoolExecutor.getTask等)不算阻塞
+//  阻塞点: 栈顶方法及第一个非JDK方法
+
+use ::sample::*;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use utils::*;
+
+//空闲线程池线程等待任务的方法
+pub const POOL_IDLE_FRAMES: &[&str] = &[
+    "java.util.concurrent.ThreadPoolExecutor.getTask",
+    "java.util.concurrent.ForkJoinPool.awaitWork",
+    "java.util.concurrent.ForkJoinPool.runWorker",
+    "java.util.concurrent.ScheduledThreadPoolExecutor$DelayedWorkQueue.take",
+    "org.apache.tomcat.util.threads.TaskQueue.take",
+    "org.apache.tomcat.util.threads.TaskQueue.poll",
+    "io.netty.channel.nio.NioEventLoop.select",
+    "org.eclipse.jetty.util.thread.QueuedThreadPool.idleJobPoll",
+];
+
+//RUNNABLE状态下阻塞在外部调用的方法
+pub const EXTERNAL_CALL_FRAMES: &[&str] = &[
+    "java.net.SocketInputStream.socketRead0",
+    "java.net.SocketInputStream.socketRead",
+    "sun.nio.ch.SocketDispatcher.read0",
+    "sun.nio.ch.FileDispatcherImpl.read0",
+    "sun.nio.ch.Net.poll",
+    "java.net.PlainSocketImpl.socketConnect",
+    "sun.nio.ch.Net.connect0",
+];
+
+const JDK_PACKAGES: &[&str] = &["java.", "javax.", "jdk.", "sun.", "com.sun."];
+
+#[derive(Clone, Debug)]
+pub struct StarvationOptions {
+    //线程池名称的通配符模式(如 http-nio-*-exec-*)，为空时去掉线程名称末尾的编号作为线程池名称
+    pub pool_patterns: Vec<String>,
+    pub min_threads: usize,
+    pub min_duration_ms: i64,
+}
+
+impl Default for StarvationOptions {
+    fn default() -> Self {
+        StarvationOptions {
+            pool_patterns: vec![],
+            min_threads: 2,
+            min_duration_ms: 1000,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StarvationEpisode {
+    pub pool: String,
+    pub threads: Vec<String>,
+    pub state: String,
+    //阻塞点
+    pub top_frame: String,
+    pub blocked_at: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub duration_ms: i64,
+    pub samples: usize,
+    //代表性的调用栈，栈顶在前
+    pub stacktrace: Vec<String>,
+}
+
+//通配符匹配，'*'匹配任意字符
+pub fn match_wildcard(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let mut rest = text;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            if !rest.starts_with(part) {
+                return false;
+            }
+            rest = &rest[part.len()..];
+        } else if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        } else {
+            match rest.find(part) {
+                Some(pos) => rest = &rest[pos + part.len()..],
+                None => return false
+            }
+        }
+    }
+    true
+}
+
+//线程池名称: 匹配的模式，或者去掉末尾编号的线程名称(pool-1-thread-3 => pool-1-thread-)
+pub fn get_pool_name(thread_name: &str, pool_patterns: &[String]) -> Option<String> {
+    if !pool_patterns.is_empty() {
+        return pool_patterns.iter().find(|x| match_wildcard(x, thread_name)).cloned();
+    }
+    let prefix = thread_name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if prefix.len() == thread_name.len() || prefix.is_empty() {
+        return None;
+    }
+    Some(prefix.to_string())
+}
+
+struct BlockedSample {
+    thread_id: i64,
+    state: String,
+    //(栈顶方法, 第一个非JDK方法)
+    key: (i64, i64),
+    stacktrace: Vec<i64>,
+}
+
+fn is_external_call(method_name: &str) -> bool {
+    EXTERNAL_CALL_FRAMES.iter().any(|x| method_name.starts_with(x))
+}
+
+fn is_pool_idle(method_names: &[String]) -> bool {
+    method_names.iter().any(|name| POOL_IDLE_FRAMES.iter().any(|x| name.starts_with(x)))
+}
+
+fn is_jdk_method(method_name: &str) -> bool {
+    JDK_PACKAGES.iter().any(|x| method_name.starts_with(x))
+}
+
+pub fn detect_pool_starvation(collector: &mut SampleCollector, start_time: i64, end_time: i64, options: &StarvationOptions) -> io::Result<Vec<StarvationEpisode>> {
+    let min_threads = options.min_threads.max(1);
+    let mut pools: BTreeMap<String, Vec<ThreadData>> = BTreeMap::new();
+    for thread in collector.get_threads()? {
+        if let Some(pool) = get_pool_name(&thread.name, &options.pool_patterns) {
+            pools.entry(pool).or_insert_with(Vec::new).push(thread);
+        }
+    }
+    let sample_interval = collector.get_sample_info().sample_interval.max(1);
+    let mut method_names: HashMap<i64, String> = HashMap::new();
+    let mut episodes = vec![];
+    for (pool, threads) in &pools {
+        if threads.len() < min_threads {
+            continue;
+        }
+        //取样时间 -> 线程池各线程的阻塞状态, None表示没有阻塞
+        let mut timeline: BTreeMap<i64, Vec<Option<BlockedSample>>> = BTreeMap::new();
+        for thread in threads {
+            let samples = match collector.load_thread_samples(thread.id, start_time, end_time) {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("load thread samples failed, thread: {}, error: {}", thread.id, e);
+                    continue;
+                }
+            };
+            for sample in samples {
+                let names: Vec<String> = sample.stacktrace.iter().map(|x| {
+                    method_names.entry(*x).or_insert_with(|| collector.get_method_name(*x)).clone()
+                }).collect();
+                let blocked = match names.first() {
+                    Some(top) => sample.state != "RUNNABLE" || is_external_call(top),
+                    None => false
+                };
+                let time = sample.sample_time - sample.sample_time % sample_interval;
+                let entry = if blocked && !is_pool_idle(&names) {
+                    let app_frame = names.iter().position(|x| !is_jdk_method(x)).map(|i| sample.stacktrace[i]).unwrap_or(-1);
+                    Some(BlockedSample { thread_id: thread.id, state: sample.state, key: (sample.stacktrace[0], app_frame), stacktrace: sample.stacktrace })
+                } else {
+                    None
+                };
+                timeline.entry(time).or_insert_with(Vec::new).push(entry);
+            }
+        }
+
+        //连续的饥饿取样合并为一次
+        let mut current: Option<(i64, i64, (i64, i64), usize, Vec<i64>, &BlockedSample)> = None;
+        let mut finished = vec![];
+        for (time, entries) in &timeline {
+            let starved_key = if entries.len() >= min_threads && entries.iter().all(|x| x.is_some()) {
+                let key = entries[0].as_ref().unwrap().key;
+                if entries.iter().all(|x| x.as_ref().unwrap().key == key) { Some(key) } else { None }
+            } else {
+                None
+            };
+            let continued = match (&current, starved_key) {
+                (Some(c), Some(key)) => c.2 == key && *time - c.1 <= sample_interval,
+                _ => false
+            };
+            if continued {
+                let c = current.as_mut().unwrap();
+                c.1 = *time;
+                c.3 += 1;
+                for x in entries {
+                    let tid = x.as_ref().unwrap().thread_id;
+                    if !c.4.contains(&tid) {
+                        c.4.push(tid);
+                    }
+                }
+                continue;
+            }
+            if let Some(c) = current.take() {
+                finished.push(c);
+            }
+            if let Some(key) = starved_key {
+                let tids = entries.iter().map(|x| x.as_ref().unwrap().thread_id).collect();
+                current = Some((*time, *time, key, 1, tids, entries[0].as_ref().unwrap()));
+            }
+        }
+        if let Some(c) = current.take() {
+            finished.push(c);
+        }
+
+        for (start, end, key, samples, tids, sample) in finished {
+            let duration_ms = end - start + sample_interval;
+            if duration_ms < options.min_duration_ms {
+                continue;
+            }
+            let mut thread_names: Vec<String> = threads.iter().filter(|x| tids.contains(&x.id)).map(|x| x.name.clone()).collect();
+            thread_names.sort();
+            episodes.push(StarvationEpisode {
+                pool: pool.clone(),
+                threads: thread_names,
+                state: sample.state.clone(),
+                top_frame: method_names.get(&key.0).cloned().unwrap_or_default(),
+                blocked_at: method_names.get(&key.1).cloned().unwrap_or_default(),
+                start_time: start,
+                end_time: end + sample_interval,
+                duration_ms,
+                samples,
+                stacktrace: sample.stacktrace.iter().map(|x| method_names.get(x).cloned().unwrap_or_default()).collect(),
+            });
+        }
+    }
+    episodes.sort_by(|a, b| b.duration_ms.cmp(&a.duration_ms).then(a.start_time.cmp(&b.start_time)));
+    Ok(episodes)
+}
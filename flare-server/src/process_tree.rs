@@ -0,0 +1,138 @@
+
+//目标进程的子进程跟踪(Linux /proc)：构建工具、测试框架等会启动子JVM，
+//发现带有flare-agent的子JVM后可以连接其agent，子会话在 list_sessions 中归属到父会话
+//  /proc/<pid>/stat: pid (comm) state ppid ...
+//  /proc/<pid>/cmdline: 以'\0'分隔的启动参数
+//  /proc/<pid>/environ: JAVA_TOOL_OPTIONS 中也可能包含 -agentpath
+
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+use utils::*;
+
+//flare-agent的库名称: libflareagent.so / flareagent.dll
+pub const AGENT_LIB_NAME: &str = "flareagent";
+pub const DEFAULT_AGENT_PORT: u16 = 3333;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: i64,
+    pub ppid: i64,
+    pub name: String,
+    pub cmdline: String,
+    pub is_jvm: bool,
+    //加载了flare-agent时的agent地址
+    pub agent_addr: Option<String>,
+}
+
+pub fn read_process_info(proc_root: &str, pid: i64) -> io::Result<ProcessInfo> {
+    let stat = std::fs::read_to_string(format!("{}/{}/stat", proc_root, pid))?;
+    //comm可能包含空格及括号，以最后一个')'为准
+    let (name, ppid) = match (stat.find('('), stat.rfind(')')) {
+        (Some(start), Some(end)) if start < end => {
+            let ppid = stat[end + 1..].split_whitespace().nth(1).and_then(|x| x.parse::<i64>().ok());
+            (stat[start + 1..end].to_string(), ppid)
+        }
+        _ => (String::new(), None)
+    };
+    let ppid = match ppid {
+        Some(x) => x,
+        None => return Err(new_error(ErrorKind::InvalidData, &format!("invalid stat of process: {}", pid)))
+    };
+
+    let args = read_nul_separated(&format!("{}/{}/cmdline", proc_root, pid));
+    let mut agent_addr = parse_agent_addr(&args);
+    if agent_addr.is_none() {
+        let environ = read_nul_separated(&format!("{}/{}/environ", proc_root, pid));
+        if let Some(options) = environ.iter().find(|x| x.starts_with("JAVA_TOOL_OPTIONS=")) {
+            let options: Vec<String> = options["JAVA_TOOL_OPTIONS=".len()..].split_whitespace().map(|x| x.to_string()).collect();
+            agent_addr = parse_agent_addr(&options);
+        }
+    }
+    let argv0 = args.get(0).map(|x| x.as_str()).unwrap_or("");
+    let is_jvm = name == "java" || argv0.ends_with("/java") || argv0 == "java" || argv0.ends_with("java.exe") || agent_addr.is_some();
+    Ok(ProcessInfo {
+        pid,
+        ppid,
+        name,
+        cmdline: args.join(" "),
+        is_jvm,
+        agent_addr,
+    })
+}
+
+fn read_nul_separated(path: &str) -> Vec<String> {
+    match std::fs::read(path) {
+        Ok(data) => data.split(|x| *x == 0).filter(|x| !x.is_empty()).map(|x| String::from_utf8_lossy(x).to_string()).collect(),
+        Err(_) => vec![]
+    }
+}
+
+//从启动参数中查找flare-agent的监听地址
+//  -agentpath:/path/libflareagent.so=address=host:port,interval=10
+pub fn parse_agent_addr(args: &[String]) -> Option<String> {
+    let arg = args.iter().find(|x| x.starts_with("-agentpath:") && x.contains(AGENT_LIB_NAME))?;
+    let mut host = "127.0.0.1".to_string();
+    let mut port = DEFAULT_AGENT_PORT;
+    if let Some(pos) = arg.find('=') {
+        for option in arg[pos + 1..].split(',') {
+            if !option.starts_with("address=") {
+                continue;
+            }
+            let value = &option["address=".len()..];
+            let port_str = match value.rfind(':') {
+                Some(p) => {
+                    //agent监听所有地址时从本机连接
+                    if &value[..p] != "0.0.0.0" && &value[..p] != "" {
+                        host = value[..p].to_string();
+                    }
+                    &value[p + 1..]
+                }
+                None => value
+            };
+            port = port_str.parse::<u16>().ok()?;
+        }
+    }
+    Some(format!("{}:{}", host, port))
+}
+
+//列出进程的所有子孙进程，按pid排序
+pub fn list_descendants(proc_root: &str, pid: i64) -> io::Result<Vec<ProcessInfo>> {
+    let mut processes: HashMap<i64, ProcessInfo> = HashMap::new();
+    for entry in std::fs::read_dir(proc_root)? {
+        let entry = match entry {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        let child_pid = match entry.file_name().to_string_lossy().parse::<i64>() {
+            Ok(x) => x,
+            Err(_) => continue
+        };
+        //进程可能已经退出
+        if let Ok(info) = read_process_info(proc_root, child_pid) {
+            processes.insert(child_pid, info);
+        }
+    }
+
+    let mut result = vec![];
+    let mut parents = vec![pid];
+    while let Some(parent) = parents.pop() {
+        for info in processes.values() {
+            if info.ppid == parent && info.pid != pid && !result.iter().any(|x: &ProcessInfo| x.pid == info.pid) {
+                parents.push(info.pid);
+                result.push(info.clone());
+            }
+        }
+    }
+    result.sort_by_key(|x| x.pid);
+    Ok(result)
+}
+
+//列出子孙进程中的JVM
+pub fn list_child_jvms(pid: i64) -> io::Result<Vec<ProcessInfo>> {
+    if !cfg!(target_os = "linux") {
+        return Err(new_error(ErrorKind::Other, "child process tracking requires linux /proc"));
+    }
+    let processes = list_descendants(::cgroup_metrics::PROC_ROOT, pid)?;
+    Ok(processes.into_iter().filter(|x| x.is_jvm).collect())
+}
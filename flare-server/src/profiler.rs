@@ -1,811 +1,3906 @@
-
-use super::sample::*;
-use std::{io, thread};
-use std::sync::{Arc, Mutex};
-use websocket::sync::Server;
-use websocket::OwnedMessage;
-use websocket::sync::sender::Sender;
-use websocket::sender::Writer;
-use websocket::server::upgrade::WsUpgrade;
-use websocket::server::upgrade::sync::Buffer;
-use serde::Serialize;
-use utils::*;
-use std::collections::HashMap;
-use std::io::ErrorKind;
-use serde_json::{json, Value};
-use std::cmp::{min, max};
-use chrono::Local;
-use flare_utils::stopwatch::Stopwatch;
-use tree::TreeNode;
-use inferno::flamegraph::*;
-use inferno::flamegraph;
-use std::str::FromStr;
-use inferno::flamegraph::color::BackgroundColor;
-use ::{tree, utils};
-use inferno::flamegraph::merge::{TimedFrame, Frame};
-use super::http_server::*;
-use method_analysis::*;
-
-type JsonValue = serde_json::Value;
-
-pub const FLARE_SAMPLES_DIR : &str = "flare-samples";
-
-#[derive(Clone, Serialize)]
-pub struct FlareResponse<T: ?Sized> {
-    pub result: String,
-    pub cmd: String,
-    pub data: Box<T>
-}
-
-pub struct Profiler {
-    self_ref: Option<Arc<Mutex<Profiler>>>,
-    bind_addr: String,
-    running: bool,
-    sample_session_map: HashMap<String, Arc<Mutex<SampleCollector>>>
-}
-
-impl Profiler {
-    pub fn new() -> Arc<Mutex<Profiler>> {
-        let mut inst = Arc::new(Mutex::new(Profiler {
-            self_ref: None,
-            bind_addr: "0.0.0.0:3891".to_string(),
-            running: true,
-            sample_session_map: HashMap::new(),
-        }));
-        inst.lock().unwrap().self_ref = Some(inst.clone());
-        inst.lock().unwrap().init();
-        inst
-    }
-
-    pub fn init(&mut self) {
-        match std::fs::read_dir(FLARE_SAMPLES_DIR) {
-            Err(e) => {
-                match std::fs::create_dir(FLARE_SAMPLES_DIR) {
-                    Err(e) => {
-                        println!("create dir failed: {}, error: {:?}", FLARE_SAMPLES_DIR, e);
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-
-    pub fn connect_agent(&mut self, agent_addr: &str) -> io::Result<String> {
-        println!("connecting to agent: {}", agent_addr);
-        let instance_id = agent_addr.to_string();
-        let value = self.sample_session_map.get(&instance_id);
-        if value.is_some() {
-            println!("already connected to agent: {}", agent_addr);
-            return Ok(instance_id);
-        }
-
-        let mut collector = SampleCollector::new(agent_addr)?;
-        collector.lock().unwrap().subscribe_events()?;
-        println!("connect agent: {} successful", agent_addr);
-        self.sample_session_map.insert(instance_id.clone(), collector);
-        Ok(instance_id)
-    }
-
-    pub fn open_sample(&mut self, sample_data_dir: &str) -> io::Result<String> {
-        println!("open sample {} ..", sample_data_dir);
-        let instance_id = sample_data_dir.to_string();
-        if let Ok(value) = self.get_sample_collector(&instance_id) {
-            return Ok(instance_id);
-        }
-
-        let mut collector = SampleCollector::open(sample_data_dir)?;
-        self.sample_session_map.insert(instance_id.clone(), collector);
-        Ok(instance_id)
-    }
-
-    pub fn close_session(&mut self, session_id: &str) -> io::Result<()> {
-        if let Some(collector) = self.sample_session_map.remove(session_id) {
-            println!("close session: {}", session_id);
-            collector.lock().unwrap().close();
-        }
-
-        Ok(())
-    }
-
-    pub fn close_all_session(&mut self) -> io::Result<()> {
-        let session_ids = self.sample_session_map.keys().map(|x|{ x.to_string() }).collect::<Vec<String>>();
-        for session_id in &session_ids {
-            self.close_session(session_id);
-        }
-        Ok(())
-    }
-
-    fn get_sample_collector(&mut self, session_id: &str) -> io::Result<Arc<Mutex<SampleCollector>>> {
-        let collector = if let Some(_collector) = self.sample_session_map.get(session_id) {
-            Some(_collector.clone())
-        }else {
-            None
-        };
-
-        if let Some(_collector) = collector {
-            if _collector.lock().unwrap().is_disconnected() {
-                println!("sample session is disconnected: {}, removing it", session_id);
-                self.sample_session_map.remove(session_id);
-                Err(io::Error::new(ErrorKind::NotFound, "sample session is disconnected"))
-            }else {
-                Ok(_collector)
-            }
-        } else {
-            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
-        }
-    }
-
-    pub fn get_dashboard(&mut self, session_id: &str) -> io::Result<DashboardInfo> {
-        let collector = self.get_sample_collector(session_id)?;
-        let data = collector.lock().unwrap().get_dashboard();
-        Ok(data)
-    }
-
-    pub fn get_all_thread_ids(&mut self, session_id: &str) -> io::Result<Vec<i64>> {
-        let collector = self.get_sample_collector(session_id)?;
-        let dashboard = collector.lock().unwrap().get_dashboard();
-        let mut thread_ids = vec![];
-        for thread in &dashboard.threads {
-            thread_ids.push(thread.id);
-        }
-        Ok(thread_ids)
-    }
-
-    pub fn get_thread_cpu_times(&mut self, session_id: &str, thread_ids: &[i64], mut start_time: i64, mut end_time: i64, mut unit_time_ms: i64, graph_width: i64) -> io::Result<Vec<Value>> {
-        if let Some(collector) = self.sample_session_map.get(session_id) {
-            let sample_info = collector.lock().unwrap().get_sample_info();
-            //限制时间范围
-            if start_time < 0 {
-                start_time = sample_info.record_start_time;
-            } else {
-                start_time = max(start_time, sample_info.record_start_time);
-            }
-
-            if end_time < 0 {
-                end_time = sample_info.last_record_time;
-            } else {
-                end_time = min(end_time, sample_info.last_record_time);
-            }
-
-            if unit_time_ms < 10 {
-                //计算聚合单位时间
-                let dt = end_time - start_time;
-                if dt <= 0 {
-                    return Err(new_invalid_input_error("time period error, end_time must be greater than start_time"))
-                }
-                let mut ratio = dt / graph_width / sample_info.sample_interval;
-                //超过十倍 按照十倍缩放
-                if ratio > 10 {
-                    ratio = ratio / 10 * 10;
-                }
-                unit_time_ms = ratio * sample_info.sample_interval;
-            }
-
-
-            let mut thread_cpu_times = vec![];
-            for thread_id in thread_ids {
-                let ts_result = collector.lock().unwrap().get_thread_cpu_time(thread_id, start_time, end_time, unit_time_ms);
-                if let Some(ts_result) = ts_result {
-                    let ts_data = ts_result.data.as_int64();
-                    thread_cpu_times.push(json!({
-                        "id":  thread_id,
-                        "start_time": ts_result.begin_time,
-                        "end_time": ts_result.end_time,
-                        "unit_time_ms": ts_result.unit_time,
-                        "total_cpu_time": ts_result.total_cpu_time,
-                        "steps": ts_result.steps,
-                        "ts_data": ts_data
-                    }));
-                }
-            }
-            Ok(thread_cpu_times)
-        }else {
-            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
-        }
-    }
-
-    pub fn get_call_tree(&mut self, session_id: &str, thread_ids: &[i64], start_time: i64, end_time: i64) -> io::Result<TreeNode> {
-        //xxx
-        let collector = self.get_sample_collector(session_id)?;
-        let call_tree = collector.lock().unwrap().get_call_tree(thread_ids, start_time, end_time)?;
-
-        //convert to json
-        Ok(call_tree.to_tree())
-    }
-
-    pub fn create_flame_graph_svg(&mut self, session_id: &str, thread_id: i64, start_time: &mut i64, end_time: &mut i64, stats_type_str: &str, image_width: usize) -> io::Result<String> {
-        let mut stats_type = StatsType::DURATION;
-        if let Ok(x) = StatsType::from_str(stats_type_str) {
-            stats_type = x;
-        }else {
-            return Err(new_invalid_input_error(&format!("invalid stats_type: {}", stats_type_str)));
-        }
-        let count_name = match stats_type {
-            StatsType::DURATION => "ms",
-            StatsType::CPU_TIME => "micros",
-            StatsType::SAMPLES => "samples",
-        };
-        let collector = self.get_sample_collector(session_id)?;
-        //create frame graph
-        let mut options = flamegraph::Options {
-            //colors: Palette::from_str("java").unwrap(),
-            //bgcolors: Some(BackgroundColor::from_str("blue").unwrap()),
-            //hash: true,
-            //top-down flame graph
-            direction: Direction::Inverted,
-            no_sort: false,
-            image_width: Some(image_width),
-            count_name: count_name.to_string(),
-            ..Default::default()
-        };
-        let mut writer = vec![];
-
-//        let call_stacks = collector.lock().unwrap().get_collapsed_call_stacks(thread_id, start_time, end_time, stats_type)?;
-//        let input = call_stacks.join("\n");
-//        if let Err(e) = flamegraph::from_lines(&mut options, input.lines(), &mut writer) {
-//            return Err(new_error(ErrorKind::Other, &format!("create flame graph failed: {}", e)));
-//        }
-
-        let stack_tree = collector.lock().unwrap().get_sequenced_call_tree(thread_id, start_time, end_time, true)?;
-        let mut frames = vec![];
-        let mut time = stack_tree.duration as usize;
-        let mut delta_max = 0;
-        self.prepare_flame_graph_frames(&stack_tree, &mut frames, &mut delta_max);
-
-        if let Err(e) = flamegraph::from_frames(&mut options, &mut writer, &mut frames, time, delta_max) {
-            return Err(new_error(ErrorKind::Other, &format!("create flame graph failed: {}", e)));
-        }
-
-        match std::str::from_utf8(&writer) {
-            Ok(svg) => Ok(svg.to_string()),
-            Err(e) => Err(new_error(ErrorKind::Other, &format!("flame graph to string failed: {}", e)))
-        }
-    }
-
-    fn prepare_flame_graph_frames<'a>(&self, node: &'a Box<TreeNode>, frames: &mut Vec<TimedFrame<'a>>, delta_max: &mut usize) {
-        let frame = TimedFrame::new(
-            &node.label,
-            node.depth as usize,
-            node.start_time as usize,
-            (node.start_time + node.duration) as usize,
-            None
-        );
-        match frame.end_time.checked_sub(frame.start_time) {
-            Some(x) => {},
-            None => {
-                println!("overflow: {}, time: {} - {}", node.label, frame.end_time, frame.start_time)
-            }
-        }
-        frames.push(frame);
-//        if node.children.is_empty() {
-//            *time += node.duration as usize;
-//        }
-        for child in &node.children {
-            self.prepare_flame_graph_frames(child, frames,delta_max);
-        }
-    }
-
-    pub fn get_sequenced_call_tree(&mut self, session_id: &str, thread_id: i64, start_time: &mut i64, end_time: &mut i64, stats_type_str: &str) -> io::Result<Box<tree::TreeNode>> {
-        let collector = self.get_sample_collector(session_id)?;
-        let result = collector.lock().unwrap().get_sequenced_call_tree(thread_id, start_time, end_time, true);
-        result
-    }
-
-    pub fn get_sample_info(&mut self, session_id: &str) -> io::Result<SampleInfo> {
-        if let Some(collector) = self.sample_session_map.get(session_id) {
-            Ok(collector.lock().unwrap().get_sample_info())
-        }else {
-            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
-        }
-    }
-
-    fn list_methods_by_filter(&self, session_id: &str, method_name_filter: &str) -> io::Result<Vec<MethodInfo>> {
-        if let Some(collector) = self.sample_session_map.get(session_id) {
-            Ok(collector.lock().unwrap().list_methods_by_filter(method_name_filter)?)
-        }else {
-            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
-        }
-    }
-
-    fn search_slow_method_calls(&self, session_id: &str, thread_id: i64, method_ids: &[i64], min_duration: i64, max_duration: i64) -> io::Result<Vec<Box<MethodCall>>> {
-        if let Some(collector) = self.sample_session_map.get(session_id) {
-            Ok(collector.lock().unwrap().search_slow_method_calls(thread_id, method_ids, min_duration, max_duration)?)
-        }else {
-            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
-        }
-    }
-
-    fn start_http_server(&mut self) {
-        thread::spawn(|| {
-            SimpleHttpServer::start_server();
-        });
-    }
-
-    fn start_ws_server(&mut self) {
-        let self_ref = self.self_ref.as_ref().unwrap().clone();
-        let bind_addr = self.bind_addr.clone();
-        thread::spawn(move || {
-            match Server::bind(bind_addr.clone()) {
-                Ok(server) => {
-                    println!("Flare profiler started on port: {}", bind_addr);
-                    for request in server.filter_map(Result::ok) {
-                        if !self_ref.lock().unwrap().is_running() {
-                            println!("Shutting down flare analysis server ...");
-                            return;
-                        }
-                        Profiler::handle_connection(self_ref.clone(), request);
-                    }
-                }
-                Err(e) => {
-                    println!("Start flare analysis server failed, bind addr: {}, error: {}", bind_addr, e);
-                    self_ref.lock().unwrap().shutdown();
-                }
-            }
-        });
-    }
-
-    fn handle_connection(self_ref: Arc<Mutex<Profiler>>, request: WsUpgrade<std::net::TcpStream, Option<Buffer>>) {
-        // Spawn a new thread for each connection.
-        thread::spawn(move || {
-            let ws_protocol = "flare-profiler";
-            if !request.protocols().contains(&ws_protocol.to_string()) {
-                request.reject().unwrap();
-                return;
-            }
-            let mut client = request.use_protocol(ws_protocol).accept().unwrap();
-//            let mut client = request.accept().unwrap();
-
-            let ip = client.peer_addr().unwrap();
-            println!("Connection from {}", ip);
-
-            //send first message
-//            let sample_info = self_ref.lock().unwrap().get_sample_info()?;
-//            client.send_message(&wrap_response( "sample_info", &sample_info));
-
-            //recv first message
-//            client.recv_message();
-
-            //recv and dispatch message
-            let (mut receiver, mut sender) = client.split().unwrap();
-            for message in receiver.incoming_messages() {
-                let message = message.unwrap();
-                match message {
-                    OwnedMessage::Close(_) => {
-                        let message = OwnedMessage::Close(None);
-                        sender.send_message(&message).unwrap();
-                        println!("Client {} disconnected", ip);
-                        return;
-                    }
-                    OwnedMessage::Ping(ping) => {
-                        let message = OwnedMessage::Pong(ping);
-                        sender.send_message(&message).unwrap();
-                    }
-                    OwnedMessage::Text(json) => {
-                        let mut cmd = String::new();
-                        if let Err(e) = self_ref.lock().unwrap().handle_request(&mut sender,json.clone(), &mut cmd) {
-                            let err = e.to_string();
-                            println!("handle request failed: {}, cmd: {}, json: {}", err, cmd, json);
-                            //send error
-                            sender.send_message(&wrap_error_response(&cmd, &err));
-                        }
-                    }
-                    _ => {
-                        sender.send_message(&message).unwrap()
-                    },
-                }
-            }
-        });
-    }
-
-    fn handle_request(&mut self, sender: &mut Writer<std::net::TcpStream>, json_str: String, _out_cmd: &mut String) -> io::Result<()> {
-        println!("recv: {}", json_str);
-        //TODO parse request to json
-        let request: JsonValue = serde_json::from_str(&json_str)?;
-        let temp;
-        let mut options = request["options"].as_object();
-        if options.is_none() {
-            temp = serde_json::Map::new();
-            options = Some(&temp);
-        }
-        let options = options.unwrap();
-
-        //cmd
-        let cmd= request["cmd"].as_str().unwrap_or("");
-        if cmd == "" {
-            return Err(new_invalid_input_error("missing attribute 'cmd'"));
-        }
-        _out_cmd.push_str(cmd);
-
-        match cmd {
-            "list_sessions" => {
-                self.handle_list_sessions(sender, cmd, options)?;
-            }
-            "history_samples" => {
-                self.handle_history_samples(sender, cmd, options)?;
-            }
-            "open_sample" => {
-                self.handle_open_sample(sender, cmd, options)?;
-            }
-            "attach_jvm" => {
-                self.handle_attach_jvm(sender, cmd, options)?;
-            }
-            "connect_agent" => {
-                self.handle_connect_agent(sender, cmd, options)?;
-            }
-            "close_session" => {
-                self.handle_close_session_request(sender, cmd, options)?;
-            }
-            "close_all_session" => {
-                self.handle_close_all_session_request(sender, cmd, options)?;
-            }
-            "dashboard" => {
-                self.handle_dashboard_request(sender, cmd, options)?;
-            }
-            "cpu_time" => {
-                self.handle_cpu_time_request(sender, cmd, options)?;
-            }
-            "call_tree" => {
-                self.handle_call_tree_request(sender, cmd, options)?;
-            }
-            "sequenced_call_tree" => {
-                self.handle_sequenced_call_tree_request(sender, cmd, options)?;
-            }
-            "flame_graph" => {
-                self.handle_flame_graph_request(sender, cmd, options)?;
-            }
-            "list_methods_by_filter" => {
-                self.handle_list_methods_by_filter_request(sender, cmd, options)?;
-            }
-            "search_slow_method_calls" => {
-                self.handle_search_slow_method_calls_request(sender, cmd, options)?;
-            }
-            _ => {
-                println!("unknown cmd: {}, request: {}", cmd, json_str);
-            }
-        }
-        Ok(())
-    }
-
-    //list open sessions
-    fn handle_list_sessions(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let mut sample_sessions = vec![];
-        for (instance_id, collector) in self.sample_session_map.iter() {
-            let sample_type = collector.lock().unwrap().get_sample_type();
-            sample_sessions.push(json!({"session_id": instance_id, "type": sample_type.to_string()}))
-        }
-        let data = json!({"sample_sessions": sample_sessions});
-        sender.send_message(&wrap_response(cmd, &data));
-        Ok(())
-    }
-
-    //list history samples
-    fn handle_history_samples(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let mut samples = vec![];
-        let paths = std::fs::read_dir(FLARE_SAMPLES_DIR)?;
-        for dir in paths {
-            let path_buf = dir.unwrap().path();
-            if !std::fs::metadata(&path_buf).unwrap().is_dir() {
-                continue;
-            }
-            samples.push(json!({"path": path_buf.to_str(), "type": "file"}));
-        }
-        let data = json!({"history_samples": samples});
-        sender.send_message(&wrap_response(cmd, &data));
-        Ok(())
-    }
-
-    fn handle_open_sample(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let sample_data_dir = options["sample_data_dir"].as_str().unwrap_or("");
-        if sample_data_dir == "" {
-            return Err(new_invalid_input_error("missing option 'sample_data_dir'"));
-        }
-        let instance_id = self.open_sample(sample_data_dir)?;
-        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "type": "file" })));
-        Ok(())
-    }
-
-    fn handle_attach_jvm(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let target_pid = options["target_pid"].as_u64();
-        if target_pid.is_none() {
-            return Err(new_invalid_input_error("missing option 'target_pid'"));
-        }
-
-        let sample_interval_ms = options["sample_interval_ms"].as_u64().unwrap_or(20);
-        let sample_duration_sec = options["sample_duration_sec"].as_u64().unwrap_or(0);
-
-        //attach
-        Ok(())
-    }
-
-    fn handle_connect_agent(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let agent_addr = options.get("agent_addr").map_or(None, |x| x.as_str());
-        if agent_addr.is_none() {
-            return Err(new_invalid_input_error("missing option 'agent_addr'"));
-        }
-        let instance_id = self.connect_agent(agent_addr.unwrap())?;
-        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "type": "attach" })));
-
-        Ok(())
-    }
-
-    fn handle_close_session_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        self.close_session(session_id)?;
-        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": session_id})));
-        Ok(())
-    }
-
-    fn handle_close_all_session_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        self.close_all_session()?;
-        sender.send_message(&wrap_response(&cmd, &json!({})));
-        Ok(())
-    }
-
-    fn handle_dashboard_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let dashboard_info = self.get_dashboard(session_id)?;
-        sender.send_message(&wrap_response(&cmd, &dashboard_info));
-        Ok(())
-    }
-
-    fn handle_cpu_time_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let mut thread_ids = get_option_as_int_array(options, "thread_ids")?;
-        let start_time = get_option_as_int(options, "start_time", -1);
-        let end_time = get_option_as_int(options, "end_time", -1);
-        let graph_width = get_option_as_int(options, "graph_width", 900);
-        let unit_time_ms = get_option_as_int(options, "unit_time_ms", -1);
-
-        if thread_ids.is_empty() {
-            thread_ids = self.get_all_thread_ids(session_id)?;
-        }
-        //TODO fetch only top n threads data
-        //fetch and send in batches, avoid long waiting
-        let t0 = Local::now().timestamp_millis();
-        println!("[{}] handle_cpu_time_request, fetching thread count: {}", utils::nowTime(), thread_ids.len());
-        let mut start = 0;
-        while start < thread_ids.len() {
-            let t1 = Local::now().timestamp_millis();
-            let end = min(start+50, thread_ids.len());
-            let thread_cpu_times = self.get_thread_cpu_times(session_id, &thread_ids[start..end], start_time, end_time, unit_time_ms, graph_width)?;;
-            let result = json!({
-                "session_id": session_id,
-                "thread_cpu_times": thread_cpu_times
-            });
-            let t2 = Local::now().timestamp_millis();
-            sender.send_message(&wrap_response(&cmd, &result));
-            println!("[{}] fetch thread cpu time data cost: {}ms, threads: {}-{}", utils::nowTime(), t2-t1, start, end);
-            start = end;
-        }
-        let t100 = Local::now().timestamp_millis();
-        println!("[{}] handle_cpu_time_request total cost: {}ms, thread count: {}", utils::nowTime(), t100-t0, thread_ids.len());
-
-        Ok(())
-    }
-
-    fn handle_call_tree_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let thread_ids = get_option_as_int_array(options, "thread_ids")?;
-        let start_time = get_option_as_int(options, "start_time", -1);
-        let end_time = get_option_as_int(options, "end_time", -1);
-        let mut sw = Stopwatch::start_new();
-
-        let call_tree = self.get_call_tree(session_id, thread_ids.as_slice(), start_time, end_time)?;
-        println!("build call tree data cost: {}ms, threads: {:?}", sw.lap(), &thread_ids);
-
-        let result = json!({
-                "session_id": session_id,
-                "call_tree_data": [call_tree]
-            });
-        let message = wrap_response(&cmd, &result);
-        println!("wrap message cost: {}ms", sw.lap());
-
-        sender.send_message(&message);
-        println!("handle_call_tree_request total cost: {}ms", sw.elapsed_ms());
-        Ok(())
-    }
-
-    fn handle_flame_graph_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let thread_id = get_option_as_int(options, "thread_id", -1);
-        let start_time = get_option_as_int(options, "start_time", -1);
-        let end_time = get_option_as_int(options, "end_time", -1);
-        let mut image_width = get_option_as_int(options, "image_width", 900);
-        if image_width <= 0 {
-            image_width = 900;
-        }
-        let stats_type = get_option_as_str(options, "stats_type", "duration");
-        let mut sw = Stopwatch::start_new();
-
-        if thread_id <= 0 {
-            return Err(new_invalid_input_error("missing or invalid option 'thread_id'"));
-        }
-        let mut new_start_time = start_time;
-        let mut new_end_time = end_time;
-        let svg = self.create_flame_graph_svg(session_id, thread_id, &mut new_start_time, &mut new_end_time, stats_type, image_width as usize)?;
-        let result = json!({
-                "session_id": session_id,
-                "thread_id": thread_id,
-                "start_time": new_start_time,
-                "end_time": new_end_time,
-                "stats_type": stats_type,
-                "image_width": image_width,
-                "flame_graph_data": svg
-            });
-        let message = wrap_response(&cmd, &result);
-        sender.send_message(&message);
-        println!("handle_flame_graph_request total cost: {}ms", sw.elapsed_ms());
-
-        Ok(())
-    }
-
-    fn handle_sequenced_call_tree_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let thread_id = get_option_as_int(options, "thread_id", -1);
-        let start_time = get_option_as_int(options, "start_time", -1);
-        let end_time = get_option_as_int(options, "end_time", -1);
-        let stats_type = get_option_as_str(options, "stats_type", "duration");
-        let mut sw = Stopwatch::start_new();
-
-        if thread_id <= 0 {
-            return Err(new_invalid_input_error("missing or invalid option 'thread_id'"));
-        }
-        let mut new_start_time = start_time;
-        let mut new_end_time = end_time;
-        let stacks = self.get_sequenced_call_tree(session_id, thread_id, &mut new_start_time, &mut new_end_time, stats_type)?;
-        let result = json!({
-                "session_id": session_id,
-                "thread_id": thread_id,
-                "start_time": new_start_time,
-                "end_time": new_end_time,
-                "stats_type": stats_type,
-                "sequenced_call_tree_data": stacks
-            });
-        let message = wrap_response(&cmd, &result);
-        sender.send_message(&message);
-        println!("handle_sequenced_call_tree_request total cost: {}ms", sw.elapsed_ms());
-
-        Ok(())
-    }
-
-    fn handle_list_methods_by_filter_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let mut sw = Stopwatch::start_new();
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let method_name_filter = get_option_as_str(options, "method_name_filter", "");
-
-        let method_info_vec = self.list_methods_by_filter(session_id, method_name_filter)?;
-        let filter_method_size = method_info_vec.len();
-        println!("filter method size: {}", filter_method_size);
-        let mut method_infos: &[MethodInfo] = &method_info_vec;
-//        if method_infos.len() > 30 {
-//            method_infos = &method_info_vec[0..30];
-//        }
-        let result = json!({
-                "session_id": session_id,
-                "method_name_filter": method_name_filter,
-                "total_method_size": filter_method_size,
-                "method_infos": method_infos
-            });
-        let message = wrap_response(&cmd, &result);
-        sender.send_message(&message);
-        println!("handle_list_methods_by_filter_request total cost: {}ms", sw.elapsed_ms());
-        Ok(())
-    }
-
-    fn handle_search_slow_method_calls_request(&mut self, sender: &mut Writer<std::net::TcpStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
-        let mut sw = Stopwatch::start_new();
-        let session_id = get_option_as_str_required(options, "session_id")?;
-        let mut method_ids = get_option_as_int_array(options, "method_ids")?;
-        let min_duration = get_option_as_int(options, "min_duration", 100);
-        let max_duration = get_option_as_int(options, "max_duration", -1);
-        let max_size = get_option_as_int(options, "max_size", 2000) as usize;
-        let thread_name_filter = get_option_as_str(options, "thread_name_filter", "");
-
-        //sort asc for binarysearch
-        method_ids.sort();
-
-        //let method_calls : Vec<String> = vec![];
-        let mut search_error = false;
-        let mut search_error_msg = "".to_string();
-        if let Some(collector) = self.sample_session_map.get(session_id) {
-            let mut total = 0;
-            let threads = collector.lock().unwrap().get_threads()?;
-            let thread_size = threads.len();
-            let mut search_progress = 0;
-            let mut method_analysis = MethodAnalysis::new();
-            //search every thread
-            for (i,thread) in threads.iter().enumerate() {
-                sw.lap();
-
-                //filter thread by name
-                if !thread_name_filter.is_empty() && !thread.name.contains(thread_name_filter) {
-                    continue;
-                }
-
-                match collector.lock().unwrap().search_slow_method_calls(thread.id, &method_ids, min_duration, max_duration) {
-                    Ok(method_calls ) => {
-                        let search_cost = sw.lap();
-                        //send progress
-                        let new_search_progress = 100*i/thread_size;
-                        if new_search_progress - search_progress > 5 {
-                            search_progress = new_search_progress;
-                            let result = json!({
-                                "session_id": session_id,
-                                //"method_ids": method_ids,
-                                "search_progress": search_progress,
-                                "search_finished": false,
-                                "search_message": format!("searching {}", thread.name)
-                            });
-                            sender.send_message(&wrap_response(&cmd, &result));
-                            println!("search progress: {}%", search_progress);
-                        }
-
-                        if method_calls.is_empty() {
-                            //println!("slow method calls not found, thread: {}, search cost: {}ms", thread.id, search_cost);
-                            continue;
-                        }
-                        total += method_calls.len();
-                        println!("found slow method calls: {}, thread: {}, search cost: {}ms, send cost: {}ms", method_calls.len(), thread.id,  search_cost, sw.lap());
-
-                        for method_call in method_calls {
-                            method_analysis.add_method_call(method_call);
-                        }
-                    }
-                    Err(e) => {
-                        println!("found slow method calls failed, thread: {}, error: {}", thread.id, e);
-                    }
-                }
-
-                if total >= max_size {
-                    search_error = true;
-                    search_error_msg = format!("too many slow method calls: {}, search aborted!", total);
-                    println!("too many slow method calls: {}, search progress: {}/{} ({}%), abort searching!", total, i, thread_size, search_progress);
-                    break;
-                }
-            }
-
-            //fill call method name
-            for method_group in &mut method_analysis.call_groups {
-                for call in &mut method_group.call_stack {
-                    match collector.lock().unwrap().get_method_info(call.method_id) {
-                        Some(val) => {
-                            call.full_name = val.full_name.clone();
-                        },
-                        None => {
-                            call.full_name = call.method_id.to_string();
-                        }
-                    }
-                }
-            }
-
-            let result = json!({
-                "session_id": session_id,
-                "method_ids": method_ids,
-                "method_call_groups": method_analysis.get_method_groups(),
-                "search_progress": search_progress,
-                "search_finished": !search_error,
-                "search_error": search_error,
-                "search_message": search_error_msg
-            });
-            sender.send_message(&wrap_response(&cmd, &result));
-            println!("handle_search_slow_method_calls_request total cost: {}ms", sw.elapsed_ms());
-        }else {
-            return Err(io::Error::new(ErrorKind::NotFound, "sample session not found"));
-        }
-        Ok(())
-    }
-
-    pub fn startup(&mut self) {
-        self.start_ws_server();
-        self.start_http_server();
-    }
-
-    pub fn shutdown(&mut self) {
-        self.running = false;
-    }
-
-    pub fn is_running(&self) -> bool {
-        self.running
-    }
-}
\ No newline at end of file
+
+use super::sample::*;
+use std::{io, thread};
+use std::sync::{Arc, Mutex};
+use websocket::sync::Server;
+use websocket::OwnedMessage;
+use websocket::sync::sender::Sender;
+use websocket::sender::Writer;
+use websocket::server::upgrade::WsUpgrade;
+use websocket::server::upgrade::sync::Buffer;
+use serde::Serialize;
+use utils::*;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use serde_json::{json, Value};
+use std::cmp::{min, max};
+use chrono::Local;
+use flare_utils::stopwatch::Stopwatch;
+use flare_utils::timeseries::{align_unit_time_to_tier, TSTransform};
+use tree::{TreeNode, PruneOptions, prune_tree};
+use inferno::flamegraph::*;
+use inferno::flamegraph;
+use std::str::FromStr;
+use inferno::flamegraph::color::BackgroundColor;
+use ::{tree, utils};
+use inferno::flamegraph::merge::{TimedFrame, Frame};
+use super::http_server::*;
+use method_analysis::*;
+use idle_frames::*;
+use sample_writer::SampleWriter;
+use sample_split;
+use sample_export::*;
+use agg_index::build_agg_index;
+use task_pool::{TaskPool, TaskPriority};
+use config::{ServerConfig, DEFAULT_CONFIG_FILE, diff_config};
+use daemon::DaemonConfig;
+use samples_watcher::*;
+use sample_path::*;
+use protocol;
+use schema;
+use access::*;
+use audit::{AuditQuery, PendingAudit, new_audit_entry, append_audit_entry, load_audit_entries};
+use session_events::{SessionEventQuery, check_level, summarize_session_events};
+use disk_guard::*;
+use storage_usage::*;
+use self_profile::*;
+use rate_limit::{ConnectionLimiter, set_heavy_query_counter, wrap_throttled_response};
+use paging::{PageQuery, SortField, sort_and_page, ORDER_ASC, ORDER_DESC};
+use command_recorder;
+use query_dsl;
+use metrics_export::*;
+use webhook::*;
+use plugins::*;
+use symbol_cache::set_symbol_cache_config;
+use runtime_adapter::*;
+use offcpu::*;
+use trigger::*;
+use deadlock::*;
+use heap_histogram::*;
+use allocation::*;
+use deopt::get_deopt_stats;
+use classloader::*;
+use ingest_filter::IngestFilter;
+use gc::{get_gc_pauses_in_range, summarize_gc_pauses};
+use marker::{Marker, Interval};
+use saved_view::SavedView;
+use report::*;
+use i18n::*;
+use format_hints::{FormatOptions, set_response_format};
+use baseline::*;
+use metric_series::METRIC_UNIT_TIME;
+use cgroup_metrics::find_agent_pid;
+use process_tree::*;
+use agent_attach::attach_agent;
+use agent_mux::MuxSessionAdapter;
+use record_group::*;
+use shared_stream::SharedStream;
+use warmup::*;
+use pool_starvation::*;
+use spin_loop::*;
+use insights::generate_insights;
+use std::collections::HashSet;
+
+type JsonValue = serde_json::Value;
+
+pub const FLARE_SAMPLES_DIR : &str = "flare-samples";
+//分段返回的调用树最多缓存数量
+const MAX_CACHED_TREES: usize = 16;
+//分析线程数量，每个会话同时执行的最大任务数量
+const ANALYSIS_WORKERS: usize = 4;
+const MAX_TASKS_PER_SESSION: usize = 2;
+//attach_jvm 加载agent后连接agent的重试次数，间隔500ms
+const ATTACH_CONNECT_RETRIES: i32 = 10;
+//等待agent推送结果的最长时间(ms)，请求的 timeout_ms 超出时截断
+const MAX_AGENT_RESULT_TIMEOUT_MS: i64 = 120_000;
+
+pub use flare_proto::ws::FlareResponse;
+
+//正在后台加载的取样
+#[derive(Clone, Serialize)]
+pub struct LoadingState {
+    pub phase: String,
+    pub percent: i64,
+}
+
+//订阅事件的客户端及订阅时的身份，受限的令牌只推送允许访问的会话的事件
+struct EventSubscriber {
+    writer: Writer<SharedStream>,
+    identity: ClientIdentity,
+}
+
+pub struct Profiler {
+    self_ref: Option<Arc<Mutex<Profiler>>>,
+    bind_addr: String,
+    running: bool,
+    sample_session_map: HashMap<String, Arc<Mutex<SampleCollector>>>,
+    //tree handle -> (session_id, call tree)
+    tree_cache: Vec<(String, String, Box<TreeNode>)>,
+    next_tree_id: i64,
+    config: ServerConfig,
+    //取样目录列表缓存，由目录监视线程更新
+    history_samples: Option<Vec<SampleDirEntry>>,
+    //分析任务线程池
+    task_pool: TaskPool,
+    //session_id -> origin (sample data dir or agent address)
+    session_origins: HashMap<String, String>,
+    next_session_seq: u64,
+    //session_id -> open count
+    session_refcounts: HashMap<String, usize>,
+    //session_id -> last query time
+    session_access_times: HashMap<String, i64>,
+    //session_id -> loading state
+    loading_sessions: HashMap<String, LoadingState>,
+    //接收后台事件通知的客户端
+    event_subscribers: Vec<EventSubscriber>,
+    notifier: WebhookNotifier,
+    //已发送断开通知的agent会话
+    lost_agent_sessions: HashSet<String>,
+    //session_id -> 触发器上一次检查的状态
+    trigger_states: HashMap<String, TriggerState>,
+    disk_low: bool,
+    plugins: PluginRegistry,
+    //子JVM会话 -> 父会话
+    session_parents: HashMap<String, String>,
+    //已经发现的子JVM进程，每个会话只通知一次
+    known_child_pids: HashMap<String, HashSet<i64>>,
+    //正在录制的分组
+    record_groups: HashMap<String, RecordGroup>,
+    //session_id -> 已推送的会话事件数量
+    pushed_session_events: HashMap<String, usize>,
+    //session_id -> 上一次查询磁盘占用的(时间, 字节数)，用于估算增长速度
+    storage_measurements: HashMap<String, (i64, u64)>,
+    //启动参数 --read-only，重新加载配置时保持只读
+    read_only_arg: bool,
+    //当前请求在任务池中执行，任务完成时记录审计日志
+    audit_deferred: bool,
+}
+
+impl Profiler {
+    pub fn new() -> Arc<Mutex<Profiler>> {
+        let mut inst = Arc::new(Mutex::new(Profiler {
+            self_ref: None,
+            bind_addr: "0.0.0.0:3891".to_string(),
+            running: true,
+            sample_session_map: HashMap::new(),
+            tree_cache: vec![],
+            next_tree_id: 1,
+            config: ServerConfig::default(),
+            history_samples: None,
+            task_pool: TaskPool::new(ANALYSIS_WORKERS, MAX_TASKS_PER_SESSION),
+            session_origins: HashMap::new(),
+            next_session_seq: 0,
+            session_refcounts: HashMap::new(),
+            session_access_times: HashMap::new(),
+            loading_sessions: HashMap::new(),
+            event_subscribers: vec![],
+            notifier: WebhookNotifier::new(vec![]),
+            lost_agent_sessions: HashSet::new(),
+            trigger_states: HashMap::new(),
+            disk_low: false,
+            plugins: PluginRegistry::default(),
+            session_parents: HashMap::new(),
+            known_child_pids: HashMap::new(),
+            record_groups: HashMap::new(),
+            pushed_session_events: HashMap::new(),
+            storage_measurements: HashMap::new(),
+            read_only_arg: false,
+            audit_deferred: false,
+        }));
+        inst.lock().unwrap().self_ref = Some(inst.clone());
+        inst.lock().unwrap().init();
+        inst
+    }
+
+    pub fn init(&mut self) {
+        self.config = ServerConfig::read_config();
+        match validate_webhooks(&self.config.webhooks) {
+            Ok(_) => self.notifier = WebhookNotifier::new(self.config.webhooks.clone()),
+            Err(e) => println!("invalid webhooks config, notifications are disabled: {}", e)
+        }
+        self.plugins = PluginRegistry::load_dir(&self.config.plugins_dir, &self.config.plugin_script_limits);
+        set_adapter_file_roots(&self.config.samples_roots);
+        set_symbol_cache_config(&self.config.symbol_cache, self.config.get_primary_samples_root());
+        if let Err(e) = check_disk_guard_action(&self.config.disk_guard.action) {
+            println!("invalid disk guard config, only notify: {}", e);
+            self.config.disk_guard.action = DISK_GUARD_NOTIFY.to_string();
+        }
+        for samples_root in &self.config.samples_roots {
+            match std::fs::read_dir(samples_root) {
+                Err(e) => {
+                    match std::fs::create_dir_all(samples_root) {
+                        Err(e) => {
+                            println!("create dir failed: {}, error: {:?}", samples_root, e);
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(record_file) = &self.config.record_commands_file {
+            if let Err(e) = command_recorder::start_recording(record_file) {
+                println!("start recording commands failed: {}, err: {}", record_file, e);
+            }
+        }
+        if self.config.read_only {
+            println!("server is read-only");
+        }
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.config.read_only = read_only;
+        self.read_only_arg = read_only;
+        println!("set server read-only: {}", read_only);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.config.read_only
+    }
+
+    pub fn get_grafana_cors_origins(&self) -> Vec<String> {
+        self.config.grafana_cors_origins.clone()
+    }
+
+    pub fn get_daemon_config(&self) -> DaemonConfig {
+        self.config.daemon.clone()
+    }
+
+    //重新加载配置文件，返回改变的配置项；配置文件无效时返回错误，当前配置不变
+    //打开的会话及录制不受影响，clock_sync、flush_policy 对之后的会话生效，rate_limit 对之后的连接生效
+    pub fn reload_config(&mut self) -> io::Result<Vec<String>> {
+        let mut config = ServerConfig::read_for_reload()?;
+        if self.read_only_arg {
+            config.read_only = true;
+        }
+        for samples_root in &config.samples_roots {
+            std::fs::create_dir_all(samples_root)
+                .map_err(|e| new_error(e.kind(), &format!("create samples root failed: {}, err: {}", samples_root, e)))?;
+        }
+        let changed = diff_config(&self.config, &config);
+        let old_config = std::mem::replace(&mut self.config, config);
+        if changed.iter().any(|x| x == "webhooks") {
+            self.notifier = WebhookNotifier::new(self.config.webhooks.clone());
+        }
+        if changed.iter().any(|x| x == "samples_roots") {
+            self.history_samples = None;
+            set_adapter_file_roots(&self.config.samples_roots);
+        }
+        if changed.iter().any(|x| x == "samples_roots" || x == "symbol_cache") {
+            set_symbol_cache_config(&self.config.symbol_cache, self.config.get_primary_samples_root());
+        }
+        if old_config.record_commands_file != self.config.record_commands_file {
+            command_recorder::stop_recording();
+            if let Some(record_file) = &self.config.record_commands_file {
+                if let Err(e) = command_recorder::start_recording(record_file) {
+                    println!("start recording commands failed: {}, err: {}", record_file, e);
+                }
+            }
+        }
+        //插件文件可能改变，总是重新加载
+        self.plugins = PluginRegistry::load_dir(&self.config.plugins_dir, &self.config.plugin_script_limits);
+        println!("config reloaded, changed: {:?}", changed);
+        self.broadcast_event("config_reloaded", &json!({
+            "changed": changed
+        }));
+        Ok(changed)
+    }
+
+    //添加取样根目录，保存到配置文件中
+    pub fn add_samples_root(&mut self, samples_root: &str) -> io::Result<Vec<String>> {
+        let samples_root = samples_root.trim_end_matches(|c| c == '/' || c == '\\');
+        if samples_root.is_empty() {
+            return Err(new_invalid_input_error("invalid samples root"));
+        }
+        if !self.config.samples_roots.iter().any(|x| x == samples_root) {
+            std::fs::create_dir_all(samples_root)?;
+            self.config.samples_roots.push(samples_root.to_string());
+            self.config.save_to_file(DEFAULT_CONFIG_FILE)?;
+            set_adapter_file_roots(&self.config.samples_roots);
+        }
+        Ok(self.config.samples_roots.clone())
+    }
+
+    pub fn connect_agent(&mut self, agent_addr: &str) -> io::Result<String> {
+        let samples_root = self.config.get_primary_samples_root().to_string();
+        self.connect_agent_to_root(agent_addr, &samples_root, false, 0)
+    }
+
+    //与已连接的会话共享agent连接，按不同的取样间隔(ms，0为agent的间隔)录制新的会话
+    pub fn connect_agent_multiplexed(&mut self, agent_addr: &str, interval: i64) -> io::Result<String> {
+        let samples_root = self.config.get_primary_samples_root().to_string();
+        self.connect_agent_to_root(agent_addr, &samples_root, true, interval)
+    }
+
+    //取样数据保存到指定的目录下，录制分组使用分组目录
+    //  同一个agent的会话共享连接(见 agent_mux)，multiplex为false时返回已经连接的会话
+    fn connect_agent_to_root(&mut self, agent_addr: &str, samples_root: &str, multiplex: bool, interval: i64) -> io::Result<String> {
+        println!("connecting to agent: {}", agent_addr);
+        if !multiplex {
+            if let Some(instance_id) = self.find_session_by_origin(agent_addr) {
+                if self.get_sample_collector(&instance_id).is_ok() {
+                    println!("already connected to agent: {}", agent_addr);
+                    return Ok(instance_id);
+                }
+            }
+        }
+
+        let mut collector = SampleCollector::new(agent_addr, samples_root)?;
+        collector.lock().unwrap().set_clock_sync_config(self.config.clock_sync.clone());
+        collector.lock().unwrap().set_flush_policy(self.config.flush_policy.clone())?;
+        collector.lock().unwrap().start_adapter(Box::new(MuxSessionAdapter::new(agent_addr, interval)))?;
+        collector.lock().unwrap().set_record_host_metrics(self.config.record_host_metrics);
+        if let Some(pid) = find_agent_pid(agent_addr) {
+            println!("found target process of agent: {}, pid: {}", agent_addr, pid);
+            collector.lock().unwrap().set_target_pid(pid);
+        }
+        println!("connect agent: {} successful", agent_addr);
+        let instance_id = self.new_session_id(agent_addr);
+        self.sample_session_map.insert(instance_id.clone(), collector);
+        self.notifier.notify(NotifyEvent::RecordingStarted, &format!("recording started, agent: {}", agent_addr),
+                             json!({"session_id": instance_id, "agent_addr": agent_addr}));
+        Ok(instance_id)
+    }
+
+    //通过运行时适配器录制非JVM的取样数据，jvm运行时等同于connect_agent
+    pub fn connect_runtime(&mut self, runtime: &str, target: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<String> {
+        if runtime == "jvm" {
+            return self.connect_agent(target);
+        }
+        let origin = format!("{}:{}", runtime, target);
+        println!("connecting to runtime: {}", origin);
+        if let Some(instance_id) = self.find_session_by_origin(&origin) {
+            if self.get_sample_collector(&instance_id).is_ok() {
+                println!("already connected to runtime: {}", origin);
+                return Ok(instance_id);
+            }
+        }
+
+        let adapter = create_adapter(runtime, target, options)?;
+        let mut collector = SampleCollector::new(&origin, self.config.get_primary_samples_root())?;
+        collector.lock().unwrap().set_clock_sync_config(self.config.clock_sync.clone());
+        collector.lock().unwrap().set_flush_policy(self.config.flush_policy.clone())?;
+        collector.lock().unwrap().start_adapter(adapter)?;
+        collector.lock().unwrap().set_record_host_metrics(self.config.record_host_metrics);
+        println!("connect runtime: {} successful", origin);
+        let instance_id = self.new_session_id(&origin);
+        self.sample_session_map.insert(instance_id.clone(), collector);
+        self.notifier.notify(NotifyEvent::RecordingStarted, &format!("recording started, runtime: {}", origin),
+                             json!({"session_id": instance_id, "runtime": runtime, "target": target}));
+        Ok(instance_id)
+    }
+
+    //连接子JVM的agent，子会话归属到父会话
+    pub fn attach_child(&mut self, parent_session_id: &str, child: &ProcessInfo) -> io::Result<String> {
+        let agent_addr = match &child.agent_addr {
+            Some(x) => x.clone(),
+            None => return Err(new_invalid_input_error(&format!("flare agent is not loaded in child process: {}", child.pid)))
+        };
+        let instance_id = self.connect_agent(&agent_addr)?;
+        self.get_sample_collector(&instance_id)?.lock().unwrap().set_target_pid(child.pid);
+        self.session_parents.insert(instance_id.clone(), parent_session_id.to_string());
+        self.known_child_pids.entry(parent_session_id.to_string()).or_insert_with(HashSet::new).insert(child.pid);
+        println!("attach child jvm: {}, pid: {}, parent session: {}", agent_addr, child.pid, parent_session_id);
+        Ok(instance_id)
+    }
+
+    //同时连接一组agent，任何一个连接失败时全部停止
+    pub fn start_record_group(&mut self, name: &str, targets: &[String], settings: GroupSettings) -> io::Result<RecordGroup> {
+        if targets.is_empty() {
+            return Err(new_invalid_input_error("record group targets is empty"));
+        }
+        let mut members = vec![];
+        for target in targets {
+            let member = resolve_group_target(target)?;
+            if let Some(session_id) = self.find_session_by_origin(&member.agent_addr) {
+                if self.sample_session_map.contains_key(&session_id) {
+                    return Err(new_invalid_input_error(&format!("agent is already recording: {}, session: {}", member.agent_addr, session_id)));
+                }
+            }
+            if members.iter().any(|x: &GroupMember| x.agent_addr == member.agent_addr) {
+                return Err(new_invalid_input_error(&format!("duplicated record target: {}", member.agent_addr)));
+            }
+            members.push(member);
+        }
+
+        let samples_root = self.config.get_primary_samples_root().to_string();
+        let group_id = format!("{:08x}", fnv1a_hash_update(fnv1a_hash(name.as_bytes()), &Local::now().timestamp_nanos().to_le_bytes()) as u32);
+        let mut group = RecordGroup::new(&group_id, name, &samples_root, settings)?;
+        for i in 0..members.len() {
+            let agent_addr = members[i].agent_addr.clone();
+            match self.connect_agent_to_root(&agent_addr, &group.group_dir, false, 0) {
+                Ok(session_id) => {
+                    //录制可能已经结束，不能使用get_sample_collector
+                    let collector = self.sample_session_map[&session_id].clone();
+                    let mut collector = collector.lock().unwrap();
+                    collector.set_record_host_metrics(group.settings.record_host_metrics);
+                    if members[i].pid > 0 {
+                        collector.set_target_pid(members[i].pid);
+                    } else {
+                        members[i].pid = collector.get_target_pid();
+                    }
+                    members[i].session_id = session_id;
+                }
+                Err(e) => {
+                    println!("start record group failed: {}, agent: {}, err: {}", name, agent_addr, e);
+                    for member in &members[..i] {
+                        if let Err(e) = self.close_session(&member.session_id) {
+                            println!("close record group member failed: {}, session: {}, err: {}", name, member.session_id, e);
+                        }
+                    }
+                    let _ = std::fs::remove_dir_all(&group.group_dir);
+                    return Err(new_error(e.kind(), &format!("connect agent failed: {}, err: {}", agent_addr, e)));
+                }
+            }
+        }
+        group.members = members;
+        group.save()?;
+        println!("start record group: {}, members: {}, dir: {}", name, group.members.len(), group.group_dir);
+        self.record_groups.insert(group_id, group.clone());
+        Ok(group)
+    }
+
+    //停止分组的所有成员，保存各成员的取样目录
+    pub fn stop_record_group(&mut self, group_id: &str) -> io::Result<RecordGroup> {
+        let mut group = match self.record_groups.remove(group_id) {
+            Some(x) => x,
+            None => return Err(new_invalid_input_error(&format!("record group not found: {}", group_id)))
+        };
+        //某个成员关闭失败时继续关闭其它成员并保存分组，最后返回错误
+        let mut close_error = None;
+        for member in &group.members {
+            if self.sample_session_map.contains_key(&member.session_id) {
+                if let Err(e) = self.close_session(&member.session_id) {
+                    println!("close record group member failed: {}, session: {}, err: {}", group.name, member.session_id, e);
+                    close_error = Some(e);
+                }
+            }
+        }
+        group.end_time = Local::now().timestamp_millis();
+        group.update_member_dirs();
+        group.save()?;
+        println!("stop record group: {}, dir: {}", group.name, group.group_dir);
+        if let Some(e) = close_error {
+            return Err(e);
+        }
+        Ok(group)
+    }
+
+    fn get_session_group_id(&self, session_id: &str) -> Option<&str> {
+        self.record_groups.values().find(|x| x.members.iter().any(|m| m.session_id == session_id)).map(|x| x.group_id.as_str())
+    }
+
+    fn get_session_target_pid(&mut self, session_id: &str) -> io::Result<i64> {
+        let pid = self.get_sample_collector(session_id)?.lock().unwrap().get_target_pid();
+        if pid <= 0 {
+            return Err(new_invalid_input_error(&format!("target process of session is unknown: {}", session_id)));
+        }
+        Ok(pid)
+    }
+
+    pub fn open_sample(&mut self, sample_data_dir: &str) -> io::Result<String> {
+        println!("open sample {} ..", sample_data_dir);
+        //同一个目录的不同写法使用同一个会话
+        let origin = canonicalize_sample_dir(sample_data_dir);
+        if let Some(instance_id) = self.find_session_by_origin(&origin) {
+            if self.loading_sessions.contains_key(&instance_id) {
+                return Err(new_error(ErrorKind::Other, &format!("sample session is loading: {}", instance_id)));
+            }
+            if self.get_sample_collector(&instance_id).is_ok() {
+                self.retain_session(&instance_id);
+                return Ok(instance_id);
+            }
+        }
+
+        let mut collector = SampleCollector::open_with_options(string_to_path(&origin), self.config.read_only, &mut |_, _| {})?;
+        let instance_id = self.new_session_id(&origin);
+        self.sample_session_map.insert(instance_id.clone(), collector);
+        self.retain_session(&instance_id);
+        Ok(instance_id)
+    }
+
+    //在后台线程加载取样数据，立即返回session_id和加载状态，加载进度通过open_sample_progress事件推送
+    pub fn open_sample_async(&mut self, sample_data_dir: &str, max_resident_bytes: usize) -> io::Result<(String, String)> {
+        let origin = canonicalize_sample_dir(sample_data_dir);
+        if let Some(instance_id) = self.find_session_by_origin(&origin) {
+            if self.sample_session_map.contains_key(&instance_id) {
+                self.retain_session(&instance_id);
+                return Ok((instance_id, "ready".to_string()));
+            }
+            if self.loading_sessions.contains_key(&instance_id) {
+                self.retain_session(&instance_id);
+                return Ok((instance_id, "loading".to_string()));
+            }
+        }
+        let instance_id = self.new_session_id(&origin);
+        self.retain_session(&instance_id);
+        println!("open sample async {} ..", sample_data_dir);
+        self.loading_sessions.insert(instance_id.clone(), LoadingState { phase: "pending".to_string(), percent: 0 });
+
+        let self_ref = self.self_ref.as_ref().unwrap().clone();
+        let session_id = instance_id.clone();
+        let read_only = self.config.read_only;
+        thread::spawn(move || {
+            let mut last_percent = -1;
+            let result = SampleCollector::open_with_options(string_to_path(&origin), read_only, &mut |phase, percent| {
+                if percent != last_percent {
+                    last_percent = percent;
+                    self_ref.lock().unwrap().on_open_sample_progress(&session_id, phase, percent);
+                }
+            });
+            if let Ok(collector) = &result {
+                collector.lock().unwrap().set_max_resident_bytes(max_resident_bytes);
+            }
+            self_ref.lock().unwrap().on_open_sample_finished(&session_id, result);
+        });
+        Ok((instance_id, "loading".to_string()))
+    }
+
+    //生成与路径、地址无关的会话id，会话来源保存在session_origins中
+    fn new_session_id(&mut self, origin: &str) -> String {
+        loop {
+            self.next_session_seq += 1;
+            let mut hash = fnv1a_hash(origin.as_bytes());
+            hash = fnv1a_hash_update(hash, &Local::now().timestamp_nanos().to_le_bytes());
+            hash = fnv1a_hash_update(hash, &self.next_session_seq.to_le_bytes());
+            let session_id = format!("{:08x}", hash as u32);
+            if !self.session_origins.contains_key(&session_id) {
+                self.session_origins.insert(session_id.clone(), origin.to_string());
+                return session_id;
+            }
+        }
+    }
+
+    fn find_session_by_origin(&self, origin: &str) -> Option<String> {
+        self.session_origins.iter().find(|(_, x)| x.as_str() == origin).map(|(id, _)| id.clone())
+    }
+
+    //已打开的会话，供HTTP数据源等在不持有Profiler锁的情况下查询
+    pub fn get_sample_sessions(&self) -> Vec<(String, Arc<Mutex<SampleCollector>>)> {
+        self.sample_session_map.iter().map(|(session_id, collector)| (session_id.clone(), collector.clone())).collect()
+    }
+
+    pub fn get_session_origin(&self, session_id: &str) -> Option<&str> {
+        self.session_origins.get(session_id).map(|x| x.as_str())
+    }
+
+    fn on_open_sample_progress(&mut self, session_id: &str, phase: &str, percent: i64) {
+        if let Some(state) = self.loading_sessions.get_mut(session_id) {
+            state.phase = phase.to_string();
+            state.percent = percent;
+        }
+        self.broadcast_session_event(session_id, "open_sample_progress", &json!({
+            "session_id": session_id,
+            "state": "loading",
+            "phase": phase,
+            "percent": percent
+        }));
+    }
+
+    fn on_open_sample_finished(&mut self, session_id: &str, result: io::Result<Arc<Mutex<SampleCollector>>>) {
+        self.loading_sessions.remove(session_id);
+        //失败时移除来源之前取得会话的名称
+        let scope_names = self.get_session_scope_names(session_id);
+        if result.is_err() {
+            self.session_refcounts.remove(session_id);
+            self.session_origins.remove(session_id);
+        }
+        match result {
+            Ok(collector) => {
+                self.sample_session_map.insert(session_id.to_string(), collector);
+                self.broadcast_session_event(session_id, "open_sample_progress", &json!({
+                    "session_id": session_id,
+                    "state": "ready",
+                    "phase": "ready",
+                    "percent": 100
+                }));
+            }
+            Err(e) => {
+                println!("open sample failed: {}, err: {}", session_id, e);
+                self.broadcast_scoped_event(&scope_names, "open_sample_progress", &json!({
+                    "session_id": session_id,
+                    "state": "failed",
+                    "error": e.to_string()
+                }));
+            }
+        }
+    }
+
+    //已订阅的连接更新身份(hello之后再次订阅)
+    fn add_event_subscriber(&mut self, sender: &Writer<SharedStream>) -> io::Result<()> {
+        let peer_addr = sender.stream.peer_addr()?;
+        let identity = get_client_identity();
+        if let Some(subscriber) = self.event_subscribers.iter_mut().find(|x| x.writer.stream.peer_addr().ok() == Some(peer_addr)) {
+            subscriber.identity = identity;
+            return Ok(());
+        }
+        self.event_subscribers.push(EventSubscriber { writer: clone_writer(sender)?, identity });
+        Ok(())
+    }
+
+    //推送不属于某个会话的事件到所有订阅的客户端
+    fn broadcast_event<T: Serialize>(&mut self, cmd: &str, value: &T) {
+        let value = json!(value);
+        self.broadcast_event_with(cmd, |_| Some(value.clone()));
+    }
+
+    //会话的事件只推送给可以访问该会话的客户端
+    fn broadcast_session_event<T: Serialize>(&mut self, session_id: &str, cmd: &str, value: &T) {
+        let scope_names = self.get_session_scope_names(session_id);
+        self.broadcast_scoped_event(&scope_names, cmd, value);
+    }
+
+    //scope_names: 会话已经关闭时，关闭之前取得的会话名称
+    fn broadcast_scoped_event<T: Serialize>(&mut self, scope_names: &[String], cmd: &str, value: &T) {
+        let value = json!(value);
+        self.broadcast_event_with(cmd, |identity| if identity.can_access(scope_names) { Some(value.clone()) } else { None });
+    }
+
+    //按订阅者的身份生成推送的内容，返回None时不推送；移除已断开的连接
+    //订阅者的身份按当前配置重新验证，令牌已经删除时不推送
+    fn broadcast_event_with<F: Fn(&ClientIdentity) -> Option<Value>>(&mut self, cmd: &str, make_value: F) {
+        let access_tokens = &self.config.access_tokens;
+        let mut subscribers = vec![];
+        for mut subscriber in self.event_subscribers.drain(..) {
+            let identity = subscriber.identity.refresh(access_tokens);
+            if !identity.is_allowed(access_tokens) {
+                subscribers.push(subscriber);
+                continue;
+            }
+            let sent = match make_value(&identity) {
+                Some(value) => subscriber.writer.send_message(&wrap_response(cmd, &value)).is_ok(),
+                None => true
+            };
+            if sent {
+                subscribers.push(subscriber);
+            }
+        }
+        self.event_subscribers = subscribers;
+    }
+
+    fn retain_session(&mut self, session_id: &str) {
+        *self.session_refcounts.entry(session_id.to_string()).or_insert(0) += 1;
+    }
+
+    //释放一次打开的会话，最后一次释放时才关闭，返回剩余的引用数
+    pub fn release_session(&mut self, session_id: &str) -> io::Result<usize> {
+        let refcount = match self.session_refcounts.get_mut(session_id) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                *count
+            }
+            _ => 0
+        };
+        if refcount == 0 {
+            self.close_session(session_id)?;
+        }
+        Ok(refcount)
+    }
+
+    pub fn close_session(&mut self, session_id: &str) -> io::Result<()> {
+        self.tree_cache.retain(|(_, x, _)| x != session_id);
+        self.session_access_times.remove(session_id);
+        self.session_refcounts.remove(session_id);
+        self.session_origins.remove(session_id);
+        if let Some(collector) = self.sample_session_map.remove(session_id) {
+            println!("close session: {}", session_id);
+            let mut collector = collector.lock().unwrap();
+            if collector.get_sample_type() == "attach" && !collector.is_disconnected() {
+                let sample_info = collector.get_sample_info();
+                self.notifier.notify(NotifyEvent::RecordingFinished, &format!("recording finished, agent: {}, sample dir: {}", sample_info.agent_addr, sample_info.sample_data_dir),
+                                     json!({"session_id": session_id, "agent_addr": sample_info.agent_addr, "sample_data_dir": sample_info.sample_data_dir}));
+            }
+            collector.close();
+        }
+        self.lost_agent_sessions.remove(session_id);
+        self.trigger_states.remove(session_id);
+        self.known_child_pids.remove(session_id);
+        self.pushed_session_events.remove(session_id);
+        self.storage_measurements.remove(session_id);
+        self.session_parents.remove(session_id);
+        self.session_parents.retain(|_, parent| parent != session_id);
+
+        Ok(())
+    }
+
+    pub fn close_all_session(&mut self) -> io::Result<()> {
+        let session_ids = self.sample_session_map.keys().map(|x|{ x.to_string() }).collect::<Vec<String>>();
+        let mut close_error = None;
+        for session_id in &session_ids {
+            if let Err(e) = self.close_session(session_id) {
+                println!("close session failed: {}, err: {}", session_id, e);
+                close_error = Some(e);
+            }
+        }
+        match close_error {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    fn get_sample_collector(&mut self, session_id: &str) -> io::Result<Arc<Mutex<SampleCollector>>> {
+        if let Some(state) = self.loading_sessions.get(session_id) {
+            return Err(new_error(ErrorKind::Other, &format!("sample session is loading: {}, {}%", session_id, state.percent)));
+        }
+        let collector = if let Some(_collector) = self.sample_session_map.get(session_id) {
+            Some(_collector.clone())
+        }else {
+            None
+        };
+
+        if let Some(_collector) = collector {
+            self.session_access_times.insert(session_id.to_string(), Local::now().timestamp_millis());
+            if _collector.lock().unwrap().is_disconnected() {
+                println!("sample session is disconnected: {}, removing it", session_id);
+                self.sample_session_map.remove(session_id);
+                self.session_origins.remove(session_id);
+                Err(io::Error::new(ErrorKind::NotFound, "sample session is disconnected"))
+            }else {
+                Ok(_collector)
+            }
+        } else {
+            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
+        }
+    }
+
+    pub fn get_dashboard(&mut self, session_id: &str) -> io::Result<DashboardInfo> {
+        let collector = self.get_sample_collector(session_id)?;
+        let data = collector.lock().unwrap().get_dashboard();
+        Ok(data)
+    }
+
+    pub fn list_threads(&mut self, session_id: &str, query: &ThreadQuery) -> io::Result<(usize, Vec<ThreadData>)> {
+        let collector = self.get_sample_collector(session_id)?;
+        let result = collector.lock().unwrap().list_threads(query);
+        Ok(result)
+    }
+
+    pub fn get_all_thread_ids(&mut self, session_id: &str) -> io::Result<Vec<i64>> {
+        let collector = self.get_sample_collector(session_id)?;
+        let dashboard = collector.lock().unwrap().get_dashboard();
+        let mut thread_ids = vec![];
+        for thread in &dashboard.threads {
+            thread_ids.push(thread.id);
+        }
+        Ok(thread_ids)
+    }
+
+    pub fn get_thread_cpu_times(&mut self, session_id: &str, thread_ids: &[i64], mut start_time: i64, mut end_time: i64, mut unit_time_ms: i64, graph_width: i64) -> io::Result<Vec<Value>> {
+        if let Some(collector) = self.sample_session_map.get(session_id) {
+            let sample_info = collector.lock().unwrap().get_sample_info();
+            //限制时间范围
+            if start_time < 0 {
+                start_time = sample_info.record_start_time;
+            } else {
+                start_time = max(start_time, sample_info.record_start_time);
+            }
+
+            if end_time < 0 {
+                end_time = sample_info.last_record_time;
+            } else {
+                end_time = min(end_time, sample_info.last_record_time);
+            }
+
+            if unit_time_ms < 10 {
+                //计算聚合单位时间
+                let dt = end_time - start_time;
+                if dt <= 0 {
+                    return Err(new_invalid_input_error("time period error, end_time must be greater than start_time"))
+                }
+                let mut ratio = dt / graph_width / sample_info.sample_interval;
+                //超过十倍 按照十倍缩放
+                if ratio > 10 {
+                    ratio = ratio / 10 * 10;
+                }
+                unit_time_ms = align_unit_time_to_tier(ratio * sample_info.sample_interval);
+            }
+
+
+            let mut thread_cpu_times = vec![];
+            for thread_id in thread_ids {
+                let ts_result = collector.lock().unwrap().get_thread_cpu_time(thread_id, start_time, end_time, unit_time_ms);
+                if let Some(ts_result) = ts_result {
+                    //没有记录数据的点返回null
+                    let ts_data = ts_result.data.as_opt_int64();
+                    let gaps: Vec<Value> = ts_result.gaps.iter().map(|x| json!({"start_time": x.begin_time, "end_time": x.end_time})).collect();
+                    thread_cpu_times.push(json!({
+                        "id":  thread_id,
+                        "start_time": ts_result.begin_time,
+                        "end_time": ts_result.end_time,
+                        "unit_time_ms": ts_result.unit_time,
+                        "total_cpu_time": ts_result.total_cpu_time,
+                        "steps": ts_result.steps,
+                        "ts_data": ts_data,
+                        "gaps": gaps
+                    }));
+                }
+            }
+            Ok(thread_cpu_times)
+        }else {
+            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
+        }
+    }
+
+    //将多个会话的CPU/线程数指标对齐到同一个时间轴上，time_axis: absolute 按绝对时间对齐，relative 按各自的开始录制时间对齐
+    pub fn get_overlay_dashboard(&mut self, session_ids: &[String], time_axis: &str, mut unit_time_ms: i64, graph_width: i64) -> io::Result<Value> {
+        let relative = match time_axis {
+            "relative" => true,
+            "absolute" => false,
+            _ => return Err(new_invalid_input_error(&format!("invalid time_axis: {}", time_axis)))
+        };
+        if session_ids.is_empty() {
+            return Err(new_invalid_input_error("option 'session_ids' is empty"));
+        }
+
+        let mut collectors = vec![];
+        for session_id in session_ids {
+            let collector = self.get_sample_collector(session_id)?;
+            let sample_info = collector.lock().unwrap().get_sample_info();
+            collectors.push((session_id, collector, sample_info));
+        }
+
+        //计算公共时间轴
+        let axis_start_time = collectors.iter().map(|(_, _, info)| info.record_start_time).min().unwrap_or(0);
+        let mut span = 0;
+        let mut sample_interval = 1;
+        for (_, _, info) in &collectors {
+            let session_span = if relative {
+                info.last_record_time - info.record_start_time
+            } else {
+                info.last_record_time - axis_start_time
+            };
+            span = max(span, session_span);
+            sample_interval = max(sample_interval, info.sample_interval);
+        }
+        if span <= 0 {
+            return Err(new_invalid_input_error("time period error, sessions have no recorded data"));
+        }
+        if unit_time_ms < 10 {
+            let mut ratio = span / max(graph_width, 1) / sample_interval;
+            //超过十倍 按照十倍缩放
+            if ratio > 10 {
+                ratio = ratio / 10 * 10;
+            }
+            unit_time_ms = align_unit_time_to_tier(max(ratio, 1) * sample_interval);
+        }
+        let steps = (span / unit_time_ms + 1) as usize;
+
+        let mut sessions = vec![];
+        for (session_id, collector, info) in &collectors {
+            let session_start_time = if relative { info.record_start_time } else { axis_start_time };
+            let mut cpu_time = vec![0i64; steps];
+            let mut thread_count = vec![0i64; steps];
+            let mut collector = collector.lock().unwrap();
+            let dashboard = collector.get_dashboard();
+            for thread in &dashboard.threads {
+                let ts_result = collector.get_thread_cpu_time(&thread.id, info.record_start_time, info.last_record_time, unit_time_ms);
+                if let Some(ts_result) = ts_result {
+                    let mut last_idx = steps;
+                    for (i, val) in ts_result.data.as_int64().unwrap_or(vec![]).iter().enumerate() {
+                        let time = ts_result.begin_time + i as i64 * ts_result.unit_time as i64;
+                        let idx = ((time - session_start_time) / unit_time_ms) as usize;
+                        if time < session_start_time || idx >= steps {
+                            continue;
+                        }
+                        cpu_time[idx] += *val;
+                        //同一个线程在一个时间单位内只计数一次
+                        if *val > 0 && idx != last_idx {
+                            thread_count[idx] += 1;
+                            last_idx = idx;
+                        }
+                    }
+                }
+            }
+            sessions.push(json!({
+                "session_id": session_id,
+                "record_start_time": info.record_start_time,
+                "last_record_time": info.last_record_time,
+                "sample_interval": info.sample_interval,
+                "offset": session_start_time - axis_start_time,
+                "cpu_time": cpu_time,
+                "thread_count": thread_count
+            }));
+        }
+
+        Ok(json!({
+            "time_axis": time_axis,
+            "start_time": if relative { 0 } else { axis_start_time },
+            "end_time": if relative { span } else { axis_start_time + span },
+            "unit_time_ms": unit_time_ms,
+            "steps": steps,
+            "sessions": sessions
+        }))
+    }
+
+    //同一时间范围的CPU序列、GC暂停、标记及CPU最高的线程，用于在一个视图中关联分析
+    pub fn get_combined_view(&mut self, session_id: &str, mut start_time: i64, mut end_time: i64, mut unit_time_ms: i64, graph_width: i64, top_threads: usize) -> io::Result<Value> {
+        let collector = self.get_sample_collector(session_id)?;
+        let mut collector = collector.lock().unwrap();
+        let sample_info = collector.get_sample_info();
+        //限制时间范围
+        if start_time < 0 {
+            start_time = sample_info.record_start_time;
+        } else {
+            start_time = max(start_time, sample_info.record_start_time);
+        }
+        if end_time < 0 {
+            end_time = sample_info.last_record_time;
+        } else {
+            end_time = min(end_time, sample_info.last_record_time);
+        }
+        if end_time <= start_time {
+            return Err(new_invalid_input_error("time period error, end_time must be greater than start_time"));
+        }
+        if unit_time_ms < 10 {
+            let mut ratio = (end_time - start_time) / max(graph_width, 1) / max(sample_info.sample_interval, 1);
+            //超过十倍 按照十倍缩放
+            if ratio > 10 {
+                ratio = ratio / 10 * 10;
+            }
+            unit_time_ms = align_unit_time_to_tier(max(ratio, 1) * sample_info.sample_interval);
+        }
+        let steps = ((end_time - start_time) / unit_time_ms + 1) as usize;
+
+        //所有线程的CPU时间及活跃线程数
+        let mut cpu_time = vec![0i64; steps];
+        let mut thread_count = vec![0i64; steps];
+        let mut thread_totals = vec![];
+        for thread in collector.get_threads()? {
+            let ts_result = match collector.get_thread_cpu_time(&thread.id, start_time, end_time, unit_time_ms) {
+                Some(x) => x,
+                None => continue
+            };
+            let mut series = vec![0i64; steps];
+            let mut total = 0;
+            for (i, val) in ts_result.data.as_int64().unwrap_or(vec![]).iter().enumerate() {
+                let time = ts_result.begin_time + i as i64 * ts_result.unit_time as i64;
+                if time < start_time || ((time - start_time) / unit_time_ms) as usize >= steps {
+                    continue;
+                }
+                let idx = ((time - start_time) / unit_time_ms) as usize;
+                series[idx] += *val;
+                total += *val;
+            }
+            for (idx, val) in series.iter().enumerate() {
+                cpu_time[idx] += *val;
+                if *val > 0 {
+                    thread_count[idx] += 1;
+                }
+            }
+            if total > 0 {
+                thread_totals.push((thread.id, thread.name, total, series));
+            }
+        }
+        thread_totals.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        thread_totals.truncate(top_threads);
+        let threads: Vec<Value> = thread_totals.into_iter().map(|(id, name, total, series)| json!({
+            "id": id,
+            "name": name,
+            "total_cpu_time": total,
+            "cpu_time": series
+        })).collect();
+
+        let gc_pauses = get_gc_pauses_in_range(collector.get_gc_pauses(), start_time, end_time);
+        let gc_summary = summarize_gc_pauses(&gc_pauses, start_time, end_time);
+        let markers: Vec<Marker> = collector.get_markers().into_iter().filter(|x| x.time >= start_time && x.time <= end_time).collect();
+        //与时间范围重叠的阶段
+        let intervals: Vec<Interval> = collector.get_intervals().into_iter()
+            .filter(|x| x.start_time <= end_time && (x.end_time < 0 || x.end_time >= start_time)).collect();
+
+        Ok(json!({
+            "session_id": session_id,
+            "start_time": start_time,
+            "end_time": end_time,
+            "unit_time_ms": unit_time_ms,
+            "steps": steps,
+            "cpu_time": cpu_time,
+            "thread_count": thread_count,
+            "gc_pauses": gc_pauses,
+            "gc_summary": gc_summary,
+            "markers": markers,
+            "intervals": intervals,
+            "top_threads": threads
+        }))
+    }
+
+    //合并多个会话的取样数据到新的取样目录，每个来源的调用栈增加一个 [来源] 根节点，线程id重新分配
+    pub fn merge_sessions(&mut self, session_ids: &[String], start_time: i64, end_time: i64) -> io::Result<String> {
+        if session_ids.len() < 2 {
+            return Err(new_invalid_input_error("option 'session_ids' requires at least two sessions"));
+        }
+        let mut collectors = vec![];
+        let mut sample_interval = 0;
+        for session_id in session_ids {
+            let collector = self.get_sample_collector(session_id)?;
+            let interval = collector.lock().unwrap().get_sample_info().sample_interval;
+            if sample_interval == 0 || interval < sample_interval {
+                sample_interval = interval;
+            }
+            collectors.push((session_id, collector));
+        }
+
+        let now_time = Local::now().format("%Y%m%dT%H%M%S").to_string();
+        //同一秒内多次合并时目录名称相同，加上序号
+        let sample_data_dir = path_to_string(&create_unique_dir(std::path::Path::new(self.config.get_primary_samples_root()), &format!("merged-{}", now_time))?);
+        let mut writer = SampleWriter::new(&sample_data_dir, sample_interval, "merged")?;
+        let mut next_thread_id = 1;
+        for (session_id, collector) in &collectors {
+            let origin = self.get_session_origin(session_id).unwrap_or(session_id).to_string();
+            let source_name = file_name_string(&string_to_path(&origin)).unwrap_or_else(|| origin.clone());
+            let root_method = writer.get_or_add_method(&format!("[{}]", source_name))?;
+            //source method id -> merged method id
+            let mut method_map: HashMap<i64, i64> = HashMap::new();
+            let mut collector = collector.lock().unwrap();
+            let mut threads = collector.get_threads()?;
+            threads.sort_by(|a, b| a.id.cmp(&b.id));
+            for thread in &threads {
+                let thread_data_vec = collector.load_thread_samples(thread.id, start_time, end_time)?;
+                if thread_data_vec.is_empty() {
+                    continue;
+                }
+                for mut thread_data in thread_data_vec {
+                    let mut stacktrace = Vec::with_capacity(thread_data.stacktrace.len() + 1);
+                    for method in &thread_data.stacktrace {
+                        let new_method = match method_map.get(method) {
+                            Some(x) => *x,
+                            None => {
+                                let x = writer.get_or_add_method(&collector.get_method_name(*method))?;
+                                method_map.insert(*method, x);
+                                x
+                            }
+                        };
+                        stacktrace.push(new_method);
+                    }
+                    //stacktrace[0] is top frame, root frame is the last one
+                    stacktrace.push(root_method);
+                    thread_data.stacktrace = stacktrace;
+                    thread_data.id = next_thread_id;
+                    writer.add_thread_sample(&thread_data)?;
+                }
+                next_thread_id += 1;
+            }
+            println!("merge session: {}, threads: {}", session_id, threads.len());
+        }
+        let sample_data_dir = writer.finish()?;
+        self.open_sample(&sample_data_dir)
+    }
+
+    //指定interval选项时使用阶段的时间范围，否则使用start_time/end_time选项
+    fn get_option_time_range(&mut self, session_id: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<(i64, i64)> {
+        let interval = get_option_as_str(options, "interval", "");
+        if interval != "" {
+            let seq = get_option_as_int(options, "interval_seq", 0);
+            let collector = self.get_sample_collector(session_id)?;
+            let range = collector.lock().unwrap().get_interval_time_range(interval, max(seq, 0) as usize);
+            return range;
+        }
+        let start_time = get_option_as_int(options, "start_time", -1);
+        let end_time = get_option_as_int(options, "end_time", -1);
+        Ok((start_time, end_time))
+    }
+
+    //缓存完整的调用树，返回前levels层数据，更深的节点通过 expand_node 按需获取
+    fn cache_tree(&mut self, session_id: &str, tree: Box<TreeNode>, levels: i32) -> (String, Value) {
+        let tree_id = format!("t{}", self.next_tree_id);
+        self.next_tree_id += 1;
+        let data = tree.to_json_limited(&tree_id, levels);
+        self.tree_cache.push((tree_id.clone(), session_id.to_string(), tree));
+        if self.tree_cache.len() > MAX_CACHED_TREES {
+            self.tree_cache.remove(0);
+        }
+        (tree_id, data)
+    }
+
+    pub fn expand_node(&mut self, handle: &str, levels: i32) -> io::Result<Value> {
+        let mut parts = handle.split('/');
+        let tree_id = parts.next().unwrap_or("");
+        let mut path = vec![];
+        for part in parts {
+            match part.parse::<usize>() {
+                Ok(x) => path.push(x),
+                Err(_) => return Err(new_invalid_input_error(&format!("invalid node handle: {}", handle)))
+            }
+        }
+        match self.tree_cache.iter().find(|(id, _, _)| id == tree_id) {
+            Some((_, _, tree)) => match tree.find_node(&path) {
+                Some(node) => Ok(node.to_json_limited(handle, levels)),
+                None => Err(new_error(ErrorKind::NotFound, &format!("tree node not found: {}", handle)))
+            },
+            None => Err(new_error(ErrorKind::NotFound, "call tree is expired, please query again"))
+        }
+    }
+
+    //include_offcpu: 合并关联到线程的off-CPU调用栈
+    pub fn get_call_tree(&mut self, session_id: &str, thread_ids: &[i64], start_time: i64, end_time: i64, include_offcpu: bool) -> io::Result<TreeNode> {
+        let collector = self.get_sample_collector(session_id)?;
+        let mut collector = collector.lock().unwrap();
+        let mut call_tree = collector.get_call_tree(thread_ids, start_time, end_time)?;
+        if include_offcpu {
+            collector.add_offcpu_call_stacks(&mut call_tree, thread_ids, start_time, end_time);
+        }
+
+        //convert to json
+        Ok(call_tree.to_tree())
+    }
+
+    pub fn create_flame_graph_svg(collector: &Arc<Mutex<SampleCollector>>, thread_id: i64, start_time: &mut i64, end_time: &mut i64, stats_type_str: &str, image_width: usize, idle_mode: IdleMode, idle_stats: &mut IdleStats, prune_options: &PruneOptions) -> io::Result<String> {
+        let mut stats_type = StatsType::DURATION;
+        if let Ok(x) = StatsType::from_str(stats_type_str) {
+            stats_type = x;
+        }else {
+            return Err(new_invalid_input_error(&format!("invalid stats_type: {}", stats_type_str)));
+        }
+        let count_name = match stats_type {
+            StatsType::DURATION => "ms",
+            StatsType::CPU_TIME => "micros",
+            StatsType::SAMPLES => "samples",
+        };
+        //create frame graph
+        let mut options = flamegraph::Options {
+            //colors: Palette::from_str("java").unwrap(),
+            //bgcolors: Some(BackgroundColor::from_str("blue").unwrap()),
+            //hash: true,
+            //top-down flame graph
+            direction: Direction::Inverted,
+            no_sort: false,
+            image_width: Some(image_width),
+            count_name: count_name.to_string(),
+            ..Default::default()
+        };
+        let mut writer = vec![];
+
+//        let call_stacks = collector.lock().unwrap().get_collapsed_call_stacks(thread_id, start_time, end_time, stats_type)?;
+//        let input = call_stacks.join("\n");
+//        if let Err(e) = flamegraph::from_lines(&mut options, input.lines(), &mut writer) {
+//            return Err(new_error(ErrorKind::Other, &format!("create flame graph failed: {}", e)));
+//        }
+
+        let mut stack_tree = collector.lock().unwrap().get_sequenced_call_tree(thread_id, start_time, end_time, true, idle_mode, idle_stats)?;
+        prune_tree(&mut stack_tree, prune_options);
+        let mut frames = vec![];
+        let mut time = stack_tree.duration as usize;
+        let mut delta_max = 0;
+        Profiler::prepare_flame_graph_frames(&stack_tree, &mut frames, &mut delta_max);
+
+        if let Err(e) = flamegraph::from_frames(&mut options, &mut writer, &mut frames, time, delta_max) {
+            return Err(new_error(ErrorKind::Other, &format!("create flame graph failed: {}", e)));
+        }
+
+        match std::str::from_utf8(&writer) {
+            Ok(svg) => Ok(svg.to_string()),
+            Err(e) => Err(new_error(ErrorKind::Other, &format!("flame graph to string failed: {}", e)))
+        }
+    }
+
+    fn prepare_flame_graph_frames<'a>(node: &'a Box<TreeNode>, frames: &mut Vec<TimedFrame<'a>>, delta_max: &mut usize) {
+        let frame = TimedFrame::new(
+            &node.label,
+            node.depth as usize,
+            node.start_time as usize,
+            (node.start_time + node.duration) as usize,
+            None
+        );
+        match frame.end_time.checked_sub(frame.start_time) {
+            Some(x) => {},
+            None => {
+                println!("overflow: {}, time: {} - {}", node.label, frame.end_time, frame.start_time)
+            }
+        }
+        frames.push(frame);
+//        if node.children.is_empty() {
+//            *time += node.duration as usize;
+//        }
+        for child in &node.children {
+            Profiler::prepare_flame_graph_frames(child, frames,delta_max);
+        }
+    }
+
+    //按调用树合并的火焰图，录制时生成的聚合索引可以避免读取全部原始取样数据
+    pub fn create_merged_flame_graph_svg(collector: &Arc<Mutex<SampleCollector>>, thread_id: i64, start_time: i64, end_time: i64, stats_type_str: &str, image_width: usize, prune_options: &PruneOptions, include_offcpu: bool) -> io::Result<String> {
+        Profiler::create_threads_flame_graph_svg(collector, &[thread_id], start_time, end_time, stats_type_str, image_width, prune_options, include_offcpu)
+    }
+
+    //多个线程合并的调用树生成火焰图
+    pub fn create_threads_flame_graph_svg(collector: &Arc<Mutex<SampleCollector>>, thread_ids: &[i64], start_time: i64, end_time: i64, stats_type_str: &str, image_width: usize, prune_options: &PruneOptions, include_offcpu: bool) -> io::Result<String> {
+        let stats_type = match StatsType::from_str(stats_type_str) {
+            Ok(x) => x,
+            Err(_) => return Err(new_invalid_input_error(&format!("invalid stats_type: {}", stats_type_str)))
+        };
+        let count_name = match stats_type {
+            StatsType::DURATION => "ms",
+            StatsType::CPU_TIME => "micros",
+            StatsType::SAMPLES => "samples",
+        };
+        let mut options = flamegraph::Options {
+            direction: Direction::Inverted,
+            image_width: Some(image_width),
+            count_name: count_name.to_string(),
+            ..Default::default()
+        };
+
+        let mut call_tree = {
+            let mut collector = collector.lock().unwrap();
+            let mut call_tree = collector.get_call_tree(thread_ids, start_time, end_time)?;
+            if include_offcpu {
+                collector.add_offcpu_call_stacks(&mut call_tree, thread_ids, start_time, end_time);
+            }
+            call_tree.to_tree()
+        };
+        prune_tree(&mut call_tree, prune_options);
+        let mut lines = vec![];
+        for child in &call_tree.children {
+            Profiler::prepare_collapsed_stacks(child, "", stats_type, &mut lines);
+        }
+
+        let mut writer = vec![];
+        if let Err(e) = flamegraph::from_lines(&mut options, lines.iter().map(|x| x.as_str()), &mut writer) {
+            return Err(new_error(ErrorKind::Other, &format!("create flame graph failed: {}", e)));
+        }
+        match std::str::from_utf8(&writer) {
+            Ok(svg) => Ok(svg.to_string()),
+            Err(e) => Err(new_error(ErrorKind::Other, &format!("flame graph to string failed: {}", e)))
+        }
+    }
+
+    fn prepare_collapsed_stacks(node: &Box<TreeNode>, parent_stack: &str, stats_type: StatsType, lines: &mut Vec<String>) {
+        let stack = if parent_stack.is_empty() { node.label.clone() } else { format!("{};{}", parent_stack, node.label) };
+        let get_value = |x: &TreeNode| match stats_type {
+            StatsType::DURATION => x.duration,
+            StatsType::CPU_TIME => x.cpu,
+            StatsType::SAMPLES => x.calls,
+        };
+        //self value
+        let children_value: i64 = node.children.iter().map(|x| get_value(x)).sum();
+        let self_value = get_value(node) - children_value;
+        if self_value > 0 {
+            lines.push(format!("{} {}", stack, self_value));
+        }
+        for child in &node.children {
+            Profiler::prepare_collapsed_stacks(child, &stack, stats_type, lines);
+        }
+    }
+
+    pub fn get_sequenced_call_tree(&mut self, session_id: &str, thread_id: i64, start_time: &mut i64, end_time: &mut i64, stats_type_str: &str, idle_mode: IdleMode, idle_stats: &mut IdleStats, prune_options: &PruneOptions) -> io::Result<Box<tree::TreeNode>> {
+        let collector = self.get_sample_collector(session_id)?;
+        let mut result = collector.lock().unwrap().get_sequenced_call_tree(thread_id, start_time, end_time, true, idle_mode, idle_stats)?;
+        prune_tree(&mut result, prune_options);
+        Ok(result)
+    }
+
+    pub fn get_sample_info(&mut self, session_id: &str) -> io::Result<SampleInfo> {
+        if let Some(collector) = self.sample_session_map.get(session_id) {
+            Ok(collector.lock().unwrap().get_sample_info())
+        }else {
+            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
+        }
+    }
+
+    fn list_methods_by_filter(&self, session_id: &str, method_name_filter: &str) -> io::Result<Vec<MethodInfo>> {
+        if let Some(collector) = self.sample_session_map.get(session_id) {
+            Ok(collector.lock().unwrap().list_methods_by_filter(method_name_filter)?)
+        }else {
+            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
+        }
+    }
+
+    fn search_slow_method_calls(&self, session_id: &str, thread_id: i64, method_ids: &[i64], min_duration: i64, max_duration: i64) -> io::Result<Vec<Box<MethodCall>>> {
+        if let Some(collector) = self.sample_session_map.get(session_id) {
+            Ok(collector.lock().unwrap().search_slow_method_calls(thread_id, method_ids, min_duration, max_duration)?)
+        }else {
+            Err(io::Error::new(ErrorKind::NotFound, "sample session not found"))
+        }
+    }
+
+    fn start_http_server(&mut self) {
+        let self_ref = self.self_ref.as_ref().unwrap().clone();
+        thread::spawn(move || {
+            SimpleHttpServer::start_server(self_ref);
+        });
+    }
+
+    fn start_ws_server(&mut self) {
+        let self_ref = self.self_ref.as_ref().unwrap().clone();
+        let bind_addr = self.bind_addr.clone();
+        thread::spawn(move || {
+            match Server::bind(bind_addr.clone()) {
+                Ok(server) => {
+                    println!("Flare profiler started on port: {}", bind_addr);
+                    for request in server.filter_map(Result::ok) {
+                        if !self_ref.lock().unwrap().is_running() {
+                            println!("Shutting down flare analysis server ...");
+                            return;
+                        }
+                        Profiler::handle_connection(self_ref.clone(), request);
+                    }
+                }
+                Err(e) => {
+                    println!("Start flare analysis server failed, bind addr: {}, error: {}", bind_addr, e);
+                    self_ref.lock().unwrap().shutdown();
+                }
+            }
+        });
+    }
+
+    fn handle_connection(self_ref: Arc<Mutex<Profiler>>, request: WsUpgrade<std::net::TcpStream, Option<Buffer>>) {
+        // Spawn a new thread for each connection.
+        thread::spawn(move || {
+            let ws_protocol = "flare-profiler";
+            if !request.protocols().contains(&ws_protocol.to_string()) {
+                request.reject().unwrap();
+                return;
+            }
+            let mut client = request.use_protocol(ws_protocol).accept().unwrap();
+//            let mut client = request.accept().unwrap();
+
+            let ip = client.peer_addr().unwrap();
+            println!("Connection from {}", ip);
+            set_client_identity(Some(ClientIdentity::anonymous(&ip.to_string())));
+
+            //send first message: protocol version and capabilities
+            if let Err(e) = client.send_message(&wrap_response("hello", &protocol::get_capabilities())) {
+                println!("send hello message failed: {}, err: {}", ip, e);
+                return;
+            }
+
+            //recv first message
+//            client.recv_message();
+
+            //recv and dispatch message
+            let (mut receiver, sender) = client.split().unwrap();
+            //任务及事件推送复制的Writer共享写锁
+            let mut sender = Writer { stream: SharedStream::new(sender.stream), sender: sender.sender };
+            let rate_limit = self_ref.lock().unwrap().config.rate_limit.clone();
+            let mut limiter = ConnectionLimiter::new(&rate_limit, Local::now().timestamp_millis());
+            for message in receiver.incoming_messages() {
+                let message = message.unwrap();
+                match message {
+                    OwnedMessage::Close(_) => {
+                        let message = OwnedMessage::Close(None);
+                        sender.send_message(&message).unwrap();
+                        println!("Client {} disconnected", ip);
+                        return;
+                    }
+                    OwnedMessage::Ping(ping) => {
+                        let message = OwnedMessage::Pong(ping);
+                        sender.send_message(&message).unwrap();
+                    }
+                    OwnedMessage::Text(json) => {
+                        command_recorder::record_request(&ip.to_string(), &json);
+                        let request_cmd = serde_json::from_str::<JsonValue>(&json).ok()
+                            .and_then(|x| x["cmd"].as_str().map(|x| x.to_string())).unwrap_or_default();
+                        if let Some(throttled) = limiter.check(&request_cmd, Local::now().timestamp_millis()) {
+                            println!("request throttled: {}, cmd: {}, throttled count: {}", ip, request_cmd, limiter.get_throttled_count());
+                            sender.send_message(&wrap_throttled_response(&request_cmd, throttled));
+                            continue;
+                        }
+                        let mut cmd = String::new();
+                        set_heavy_query_counter(Some(limiter.get_heavy_query_counter()));
+                        let result = self_ref.lock().unwrap().handle_request(&mut sender,json.clone(), &mut cmd);
+                        set_heavy_query_counter(None);
+                        if let Err(e) = result {
+                            let err = e.to_string();
+                            println!("handle request failed: {}, cmd: {}, json: {}", err, cmd, json);
+                            //send error
+                            sender.send_message(&wrap_error_response(&cmd, &err));
+                        }
+                    }
+                    _ => {
+                        sender.send_message(&message).unwrap()
+                    },
+                }
+            }
+        });
+    }
+
+    pub fn handle_request(&mut self, sender: &mut Writer<SharedStream>, json_str: String, _out_cmd: &mut String) -> io::Result<()> {
+        println!("recv: {}", json_str);
+        //TODO parse request to json
+        let request: JsonValue = serde_json::from_str(&json_str)?;
+        let temp;
+        let mut options = request["options"].as_object();
+        if options.is_none() {
+            temp = serde_json::Map::new();
+            options = Some(&temp);
+        }
+        let options = options.unwrap();
+
+        //cmd
+        let cmd= request["cmd"].as_str().unwrap_or("");
+        if cmd == "" {
+            return Err(new_invalid_input_error("missing attribute 'cmd'"));
+        }
+        _out_cmd.push_str(cmd);
+
+        //被拒绝的控制类命令也记录审计日志
+        //按当前配置重新验证令牌，处理请求期间使用更新后的身份(允许的会话等)
+        let identity = get_client_identity().refresh(&self.config.access_tokens);
+        if identity != get_client_identity() {
+            set_client_identity(Some(identity.clone()));
+        }
+        self.audit_deferred = false;
+        let result = match self.check_access(&identity, cmd, options) {
+            Ok(_) => {
+                set_response_format(FormatOptions::from_request(options)?);
+                let _span = enter_span(cmd);
+                let result = self.dispatch_request(sender, cmd, options, &json_str);
+                set_response_format(None);
+                result
+            }
+            Err(e) => Err(e)
+        };
+        if is_control_command(cmd) && (result.is_err() || !self.audit_deferred) {
+            self.write_audit_log(&identity, cmd, options, &result);
+        }
+        //错误信息按请求的语言返回
+        if let Err(e) = result {
+            let lang = self.get_request_lang(options);
+            return Err(new_error(e.kind(), &localize_error(&lang, &e.to_string())));
+        }
+        Ok(())
+    }
+
+    fn check_access(&self, identity: &ClientIdentity, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        if cmd != "hello" && !identity.is_allowed(&self.config.access_tokens) {
+            return Err(new_error(ErrorKind::PermissionDenied, "authentication required, send token in hello request"));
+        }
+        if self.config.read_only && is_write_command(cmd) {
+            return Err(new_error(ErrorKind::PermissionDenied, &format!("server is read-only, cmd is rejected: {}", cmd)));
+        }
+        if identity.is_scoped() {
+            self.check_session_scope(identity, cmd, options)?;
+        }
+        Ok(())
+    }
+
+    //受限的令牌只能访问允许的会话及取样目录
+    fn check_session_scope(&self, identity: &ClientIdentity, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut targets = vec![];
+        if let Some(session_id) = options.get("session_id").and_then(|x| x.as_str()) {
+            targets.push(self.get_session_scope_names(session_id));
+        }
+        if let Some(session_ids) = options.get("session_ids").and_then(|x| x.as_array()) {
+            for session_id in session_ids.iter().filter_map(|x| x.as_str()) {
+                targets.push(self.get_session_scope_names(session_id));
+            }
+        }
+        if let Some(sample_data_dir) = options.get("sample_data_dir").and_then(|x| x.as_str()) {
+            targets.push(get_sample_dir_scope_names(&canonicalize_sample_dir(sample_data_dir)));
+        }
+        //调用树节点属于创建它的会话
+        if let Some(handle) = options.get("handle").and_then(|x| x.as_str()) {
+            let tree_id = handle.split('/').next().unwrap_or("");
+            if let Some((_, session_id, _)) = self.tree_cache.iter().find(|(id, _, _)| id == tree_id) {
+                targets.push(self.get_session_scope_names(session_id));
+            }
+        }
+        if targets.is_empty() {
+            if SCOPED_GLOBAL_COMMANDS.contains(&cmd) {
+                return Ok(());
+            }
+            return Err(new_error(ErrorKind::PermissionDenied, &format!("access denied, token '{}' is restricted to sessions: {}", identity.name, cmd)));
+        }
+        for names in &targets {
+            if !identity.can_access(names) {
+                return Err(new_error(ErrorKind::PermissionDenied, &format!("access denied, token '{}' can not access session: {}", identity.name, names[0])));
+            }
+        }
+        Ok(())
+    }
+
+    //会话ID、agent地址及取样目录名称
+    fn get_session_scope_names(&self, session_id: &str) -> Vec<String> {
+        let mut names = vec![session_id.to_string()];
+        if let Some(origin) = self.get_session_origin(session_id) {
+            //来源是agent地址时按地址匹配，是取样目录时只匹配目录名称
+            names.extend(get_sample_dir_scope_names(origin));
+        }
+        if let Some(collector) = self.sample_session_map.get(session_id) {
+            let sample_info = collector.lock().unwrap().get_sample_info();
+            names.push(sample_info.agent_addr.clone());
+            names.extend(get_sample_dir_scope_names(&sample_info.sample_data_dir));
+        }
+        names
+    }
+
+    pub fn is_session_visible(&self, session_id: &str) -> bool {
+        get_client_identity().can_access(&self.get_session_scope_names(session_id))
+    }
+
+    //HTTP接口(如Grafana数据源)没有hello请求，按请求中的令牌确定身份；没有配置令牌时不检查
+    pub fn authenticate_token(&self, addr: &str, token: &str) -> io::Result<ClientIdentity> {
+        if self.config.access_tokens.is_empty() {
+            return Ok(ClientIdentity::anonymous(addr));
+        }
+        match find_access_token(&self.config.access_tokens, token) {
+            Some(access_token) => Ok(ClientIdentity::from_token(addr, access_token)),
+            None if token.is_empty() => Err(new_error(ErrorKind::PermissionDenied, "authentication required, send access token")),
+            None => Err(new_error(ErrorKind::PermissionDenied, "invalid access token"))
+        }
+    }
+
+    //身份可以访问的已打开会话
+    pub fn get_visible_sample_sessions(&self, identity: &ClientIdentity) -> Vec<(String, Arc<Mutex<SampleCollector>>)> {
+        self.get_sample_sessions().into_iter()
+            .filter(|(session_id, _)| identity.can_access(&self.get_session_scope_names(session_id)))
+            .collect()
+    }
+
+    pub fn set_access_tokens(&mut self, access_tokens: Vec<AccessToken>) {
+        self.config.access_tokens = access_tokens;
+    }
+
+    fn write_audit_log(&self, identity: &ClientIdentity, cmd: &str, options: &serde_json::Map<String, serde_json::Value>, result: &io::Result<()>) {
+        let entry = new_audit_entry(Local::now().timestamp_millis(), identity, cmd, options, result);
+        let path = self.config.get_audit_log_file();
+        if let Err(e) = append_audit_entry(&path, &entry) {
+            println!("write audit log failed: {}, err: {}", path, e);
+        }
+    }
+
+    //控制类命令提交到任务池时调用，提交成功后由任务记录审计日志
+    fn defer_audit_log(&mut self, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> Option<PendingAudit> {
+        if !is_control_command(cmd) {
+            return None;
+        }
+        self.audit_deferred = true;
+        Some(PendingAudit::new(&self.config.get_audit_log_file(), get_client_identity(), cmd, options))
+    }
+
+    fn get_request_lang(&self, options: &serde_json::Map<String, serde_json::Value>) -> String {
+        normalize_lang(get_option_as_str(options, "lang", &self.config.language)).to_string()
+    }
+
+    fn dispatch_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>, json_str: &str) -> io::Result<()> {
+        match cmd {
+            "hello" => {
+                self.handle_hello_request(sender, cmd, options)?;
+            }
+            "list_sessions" => {
+                self.handle_list_sessions(sender, cmd, options)?;
+            }
+            "history_samples" => {
+                self.handle_history_samples(sender, cmd, options)?;
+            }
+            "open_sample" => {
+                self.handle_open_sample(sender, cmd, options)?;
+            }
+            "attach_jvm" => {
+                self.handle_attach_jvm(sender, cmd, options)?;
+            }
+            "connect_agent" => {
+                self.handle_connect_agent(sender, cmd, options)?;
+            }
+            "connect_runtime" => {
+                self.handle_connect_runtime(sender, cmd, options)?;
+            }
+            "list_runtimes" => {
+                sender.send_message(&wrap_response(&cmd, &json!({ "runtimes": list_adapters() })));
+            }
+            "close_session" => {
+                self.handle_close_session_request(sender, cmd, options)?;
+            }
+            "close_all_session" => {
+                self.handle_close_all_session_request(sender, cmd, options)?;
+            }
+            "dashboard" => {
+                self.handle_dashboard_request(sender, cmd, options)?;
+            }
+            "overlay_dashboard" => {
+                self.handle_overlay_dashboard_request(sender, cmd, options)?;
+            }
+            "merge_sessions" => {
+                self.handle_merge_sessions_request(sender, cmd, options)?;
+            }
+            "split_sample" => {
+                self.handle_split_sample_request(sender, cmd, options)?;
+            }
+            "add_marker" => {
+                self.handle_add_marker_request(sender, cmd, options)?;
+            }
+            "save_view" => {
+                self.handle_save_view_request(sender, cmd, options)?;
+            }
+            "list_views" => {
+                self.handle_list_views_request(sender, cmd, options)?;
+            }
+            "list_markers" => {
+                self.handle_list_markers_request(sender, cmd, options)?;
+            }
+            "list_intervals" => {
+                self.handle_list_intervals_request(sender, cmd, options)?;
+            }
+            "list_series" => {
+                self.handle_list_series_request(sender, cmd, options)?;
+            }
+            "export_sample" => {
+                self.handle_export_sample_request(sender, cmd, options)?;
+            }
+            "generate_report" => {
+                self.handle_generate_report_request(sender, cmd, options)?;
+            }
+            "export_metrics" => {
+                self.handle_export_metrics_request(sender, cmd, options)?;
+            }
+            "load_mapping" => {
+                self.handle_load_mapping_request(sender, cmd, options)?;
+            }
+            "list_threads" => {
+                self.handle_list_threads_request(sender, cmd, options)?;
+            }
+            "expand_node" => {
+                self.handle_expand_node_request(sender, cmd, options)?;
+            }
+            "add_samples_root" => {
+                self.handle_add_samples_root_request(sender, cmd, options)?;
+            }
+            "build_index" => {
+                self.handle_build_index_request(sender, cmd, options)?;
+            }
+            "cpu_time" => {
+                self.handle_cpu_time_request(sender, cmd, options)?;
+            }
+            "call_tree" => {
+                self.handle_call_tree_request(sender, cmd, options)?;
+            }
+            "sequenced_call_tree" => {
+                self.handle_sequenced_call_tree_request(sender, cmd, options)?;
+            }
+            "flame_graph" => {
+                self.handle_flame_graph_request(sender, cmd, options)?;
+            }
+            "list_methods_by_filter" => {
+                self.handle_list_methods_by_filter_request(sender, cmd, options)?;
+            }
+            "search_slow_method_calls" => {
+                self.handle_search_slow_method_calls_request(sender, cmd, options)?;
+            }
+            "database_time" => {
+                self.handle_database_time_request(sender, cmd, options)?;
+            }
+            "query" => {
+                self.handle_query_request(sender, cmd, options)?;
+            }
+            "list_plugins" => {
+                self.handle_list_plugins_request(sender, cmd, options)?;
+            }
+            "plugin_command" => {
+                self.handle_plugin_command_request(sender, cmd, options)?;
+            }
+            "plugin_report" => {
+                self.handle_plugin_report_request(sender, cmd, options)?;
+            }
+            "classify_samples" => {
+                self.handle_classify_samples_request(sender, cmd, options)?;
+            }
+            "start_offcpu_sampling" => {
+                self.handle_start_offcpu_sampling_request(sender, cmd, options)?;
+            }
+            "offcpu_stacks" => {
+                self.handle_offcpu_stacks_request(sender, cmd, options)?;
+            }
+            "cgroup_metrics" => {
+                self.handle_metric_values_request(sender, cmd, options, "cgroup")?;
+            }
+            "host_metrics" => {
+                self.handle_metric_values_request(sender, cmd, options, "host")?;
+            }
+            "list_child_processes" => {
+                self.handle_list_child_processes_request(sender, cmd, options)?;
+            }
+            "attach_child" => {
+                self.handle_attach_child_request(sender, cmd, options)?;
+            }
+            "warmup_phase" => {
+                self.handle_warmup_phase_request(sender, cmd, options)?;
+            }
+            "pool_starvation" => {
+                self.handle_pool_starvation_request(sender, cmd, options)?;
+            }
+            "spin_loops" => {
+                self.handle_spin_loops_request(sender, cmd, options)?;
+            }
+            "insights" => {
+                self.handle_insights_request(sender, cmd, options)?;
+            }
+            "record_group" => {
+                self.handle_record_group_request(sender, cmd, options)?;
+            }
+            "stop_record_group" => {
+                self.handle_stop_record_group_request(sender, cmd, options)?;
+            }
+            "list_record_groups" => {
+                self.handle_list_record_groups_request(sender, cmd)?;
+            }
+            "detect_deadlocks" => {
+                self.handle_detect_deadlocks_request(sender, cmd, options)?;
+            }
+            "list_deadlocks" => {
+                self.handle_list_deadlocks_request(sender, cmd, options)?;
+            }
+            "thread_dump" => {
+                self.handle_thread_dump_request(sender, cmd, options)?;
+            }
+            "list_thread_dumps" => {
+                self.handle_list_thread_dumps_request(sender, cmd, options)?;
+            }
+            "get_thread_dump" => {
+                self.handle_get_thread_dump_request(sender, cmd, options)?;
+            }
+            "heap_histogram" => {
+                self.handle_heap_histogram_request(sender, cmd, options)?;
+            }
+            "list_heap_histograms" => {
+                self.handle_list_heap_histograms_request(sender, cmd, options)?;
+            }
+            "diff_heap_histograms" => {
+                self.handle_diff_heap_histograms_request(sender, cmd, options)?;
+            }
+            "allocation_rate" => {
+                self.handle_allocation_rate_request(sender, cmd, options)?;
+            }
+            "deopt_stats" => {
+                self.handle_deopt_stats_request(sender, cmd, options)?;
+            }
+            "class_loader_leaks" => {
+                self.handle_class_loader_leaks_request(sender, cmd, options)?;
+            }
+            "ingest_filter" => {
+                self.handle_ingest_filter_request(sender, cmd, options)?;
+            }
+            "flush_policy" => {
+                self.handle_flush_policy_request(sender, cmd, options)?;
+            }
+            "storage_usage" => {
+                self.handle_storage_usage_request(sender, cmd, options)?;
+            }
+            "thread_handles" => {
+                self.handle_thread_handles_request(sender, cmd, options)?;
+            }
+            "self_profile" => {
+                self.handle_self_profile_request(sender, cmd, options)?;
+            }
+            "combined_view" => {
+                self.handle_combined_view_request(sender, cmd, options)?;
+            }
+            "set_baseline" => {
+                self.handle_set_baseline_request(sender, cmd, options)?;
+            }
+            "list_baselines" => {
+                self.handle_list_baselines_request(sender, cmd, options)?;
+            }
+            "compare_to_baseline" => {
+                self.handle_compare_to_baseline_request(sender, cmd, options)?;
+            }
+            "schema" => {
+                self.handle_schema_request(sender, cmd, options)?;
+            }
+            "reload_config" => {
+                self.handle_reload_config_request(sender, cmd, options)?;
+            }
+            "audit_log" => {
+                self.handle_audit_log_request(sender, cmd, options)?;
+            }
+            "session_events" => {
+                self.handle_session_events_request(sender, cmd, options)?;
+            }
+            _ => {
+                println!("unknown cmd: {}, request: {}", cmd, json_str);
+                return Err(new_invalid_input_error(&format!("unsupported cmd: {}, protocol version: {}", cmd, protocol::PROTOCOL_VERSION)));
+            }
+        }
+        Ok(())
+    }
+
+    //前端发送自己的协议版本和需要的功能，返回服务端能力及协商的功能
+    fn handle_hello_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let client_version = get_option_as_int(options, "protocol_version", protocol::MIN_PROTOCOL_VERSION as i64) as i32;
+        if client_version < protocol::MIN_PROTOCOL_VERSION {
+            return Err(new_invalid_input_error(&format!("client protocol version {} is too old, server requires at least {}, please upgrade the frontend",
+                                                        client_version, protocol::MIN_PROTOCOL_VERSION)));
+        }
+        //配置了访问令牌时验证令牌，之后的请求使用令牌的身份
+        let token = get_option_as_str(options, "token", "");
+        let mut identity = get_client_identity();
+        if let Some(access_token) = find_access_token(&self.config.access_tokens, token) {
+            identity = ClientIdentity::from_token(&identity.addr, access_token);
+            set_client_identity(Some(identity.clone()));
+        } else if !self.config.access_tokens.is_empty() {
+            return Err(new_error(ErrorKind::PermissionDenied, "invalid access token"));
+        }
+        let requested_features: Vec<&str> = match options.get("features").and_then(|x| x.as_array()) {
+            Some(features) => features.iter().filter_map(|x| x.as_str()).collect(),
+            None => vec![]
+        };
+        let mut capabilities = protocol::get_capabilities();
+        capabilities["client_protocol_version"] = json!(client_version);
+        capabilities["negotiated_protocol_version"] = json!(min(client_version, protocol::PROTOCOL_VERSION));
+        capabilities["negotiated_features"] = json!(protocol::negotiate_features(&requested_features));
+        capabilities["identity"] = json!(identity.name);
+        capabilities["read_only"] = json!(self.config.read_only);
+        sender.send_message(&wrap_response(&cmd, &capabilities));
+        Ok(())
+    }
+
+    //list open sessions
+    fn handle_list_sessions(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut sample_sessions = vec![];
+        for (instance_id, collector) in self.sample_session_map.iter() {
+            if !self.is_session_visible(instance_id) {
+                continue;
+            }
+            let collector = collector.lock().unwrap();
+            let sample_type = collector.get_sample_type();
+            sample_sessions.push(json!({"session_id": instance_id, "origin": self.get_session_origin(instance_id), "type": sample_type.to_string(), "state": "ready", "resident_bytes": collector.get_resident_bytes(),
+                "refcount": self.session_refcounts.get(instance_id).cloned().unwrap_or(1), "parent_session_id": self.session_parents.get(instance_id), "group_id": self.get_session_group_id(instance_id)}))
+        }
+        for (instance_id, state) in self.loading_sessions.iter() {
+            if !self.is_session_visible(instance_id) {
+                continue;
+            }
+            sample_sessions.push(json!({"session_id": instance_id, "origin": self.get_session_origin(instance_id), "type": "file", "state": "loading", "phase": state.phase, "percent": state.percent}))
+        }
+        let queued_tasks = self.task_pool.get_queued_tasks();
+        let data = json!({
+            "sample_sessions": sample_sessions,
+            "analysis_pool": {"workers": self.task_pool.get_workers(), "queued_interactive": queued_tasks[0], "queued_background": queued_tasks[1]}
+        });
+        sender.send_message(&wrap_response(cmd, &data));
+        Ok(())
+    }
+
+    //list history samples
+    //subscribe 为true时订阅取样目录变化(samples_changed)等事件推送
+    fn handle_history_samples(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        if get_option_as_bool(options, "subscribe", false) {
+            self.add_event_subscriber(sender)?;
+        }
+        if self.history_samples.is_none() {
+            self.history_samples = Some(scan_samples_roots(&self.config.samples_roots));
+        }
+        let query = PageQuery::from_options(options, SAMPLE_DIR_SORT_FIELDS, 0)?;
+        let identity = get_client_identity();
+        let samples: Vec<&SampleDirEntry> = self.history_samples.as_ref().unwrap().iter()
+            .filter(|x| identity.can_access(&get_sample_dir_scope_names(&x.path)))
+            .collect();
+        let (total, samples) = sort_and_page(&samples, &query)?;
+        let mut data = json!({"history_samples": samples, "samples_roots": self.config.samples_roots});
+        query.add_page_info(&mut data, total);
+        sender.send_message(&wrap_response(cmd, &data));
+        Ok(())
+    }
+
+    fn handle_open_sample(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let sample_data_dir = options["sample_data_dir"].as_str().unwrap_or("");
+        if sample_data_dir == "" {
+            return Err(new_invalid_input_error("missing option 'sample_data_dir'"));
+        }
+        //调用栈索引常驻内存上限
+        let max_resident_mb = get_option_as_int(options, "max_resident_mb", (DEFAULT_MAX_RESIDENT_BYTES / 1024 / 1024) as i64);
+        let max_resident_bytes = (min(max(max_resident_mb, 1), MAX_RESIDENT_MB_LIMIT) as usize).saturating_mul(1024 * 1024);
+        //默认加载完成后返回，async为true时在后台加载，立即返回加载状态，通过open_sample_progress事件推送进度
+        if !get_option_as_bool(options, "async", false) {
+            let instance_id = self.open_sample(sample_data_dir)?;
+            self.get_sample_collector(&instance_id)?.lock().unwrap().set_max_resident_bytes(max_resident_bytes);
+            sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "origin": self.get_session_origin(&instance_id), "type": "file", "state": "ready" })));
+            return Ok(());
+        }
+        self.add_event_subscriber(sender)?;
+        let (instance_id, state) = self.open_sample_async(sample_data_dir, max_resident_bytes)?;
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "origin": self.get_session_origin(&instance_id), "type": "file", "state": state })));
+        Ok(())
+    }
+
+    //加载agent及等待agent监听较慢，在任务池中执行，不持有Profiler锁，完成后回复
+    fn handle_attach_jvm(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let target_pid = options.get("target_pid").and_then(|x| x.as_u64());
+        if target_pid.is_none() {
+            return Err(new_invalid_input_error("missing option 'target_pid'"));
+        }
+
+        let target_pid = target_pid.unwrap() as i64;
+        let sample_interval_ms = options.get("sample_interval_ms").and_then(|x| x.as_u64()).unwrap_or(20);
+        let agent_port = get_option_as_int(options, "agent_port", DEFAULT_AGENT_PORT as i64);
+        if agent_port <= 0 || agent_port > u16::max_value() as i64 {
+            return Err(new_invalid_input_error(&format!("invalid option 'agent_port': {}", agent_port)));
+        }
+        let agent_port = agent_port as u16;
+
+        let self_ref = self.self_ref.as_ref().unwrap().clone();
+        let mut writer = clone_writer(sender)?;
+        let audit = self.defer_audit_log(cmd, options);
+        let cmd = cmd.to_string();
+        self.task_pool.submit(&format!("attach_jvm:{}", target_pid), TaskPriority::INTERACTIVE, move || {
+            let result = attach_jvm_and_connect(&self_ref, target_pid, agent_port, sample_interval_ms as i64);
+            send_audited_task_result(&mut writer, &cmd, result, audit);
+        });
+        Ok(())
+    }
+
+    fn handle_connect_agent(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let agent_addr = options.get("agent_addr").map_or(None, |x| x.as_str());
+        if agent_addr.is_none() {
+            return Err(new_invalid_input_error("missing option 'agent_addr'"));
+        }
+        //multiplex: 与已连接的会话共享agent连接，使用不同的取样间隔及写入过滤录制新的会话
+        let instance_id = if get_option_as_bool(options, "multiplex", false) {
+            self.connect_agent_multiplexed(agent_addr.unwrap(), get_option_as_int(options, "interval", 0))?
+        } else {
+            self.connect_agent(agent_addr.unwrap())?
+        };
+        //远程agent需要指定目标进程才能采集cgroup指标
+        let pid = get_option_as_int(options, "pid", -1);
+        if pid > 0 {
+            self.get_sample_collector(&instance_id)?.lock().unwrap().set_target_pid(pid);
+        }
+        if let Some(stack_retention) = options.get("stack_retention").and_then(|x| x.as_str()) {
+            self.get_sample_collector(&instance_id)?.lock().unwrap().set_stack_retention(stack_retention)?;
+        }
+        if let Some(filter) = options.get("ingest_filter").and_then(|x| x.as_object()) {
+            let filter = IngestFilter::from_options(filter)?;
+            self.get_sample_collector(&instance_id)?.lock().unwrap().set_ingest_filter(filter)?;
+        }
+        if let Some(policy) = options.get("flush_policy").and_then(|x| x.as_object()) {
+            let policy = self.config.flush_policy.merge_options(policy)?;
+            self.get_sample_collector(&instance_id)?.lock().unwrap().set_flush_policy(policy)?;
+        }
+        let agent_compat = self.get_sample_collector(&instance_id)?.lock().unwrap().get_agent_compat().cloned();
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "origin": self.get_session_origin(&instance_id), "type": "attach", "agent": agent_compat })));
+
+        Ok(())
+    }
+
+    fn handle_connect_runtime(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let runtime = get_option_as_str_required(options, "runtime")?;
+        let target = get_option_as_str_required(options, "target")?;
+        let instance_id = self.connect_runtime(runtime, target, options)?;
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "origin": self.get_session_origin(&instance_id), "type": "attach", "runtime": runtime })));
+        Ok(())
+    }
+
+    fn handle_close_session_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let refcount = self.release_session(session_id)?;
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": session_id, "refcount": refcount, "closed": refcount == 0 })));
+        Ok(())
+    }
+
+    fn handle_close_all_session_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        self.close_all_session()?;
+        sender.send_message(&wrap_response(&cmd, &json!({})));
+        Ok(())
+    }
+
+    fn handle_dashboard_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let mut dashboard_info = self.get_dashboard(session_id)?;
+        //大量线程时可以分页返回，线程总数使用 list_threads 查询
+        if options.contains_key("page_size") {
+            let query = parse_thread_query(options, 100)?;
+            let (_, threads) = self.list_threads(session_id, &query)?;
+            dashboard_info.threads = threads;
+        }
+        sender.send_message(&wrap_response(&cmd, &dashboard_info));
+        Ok(())
+    }
+
+    fn handle_overlay_dashboard_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_ids = get_option_as_str_array(options, "session_ids")?;
+        let time_axis = get_option_as_str(options, "time_axis", "relative");
+        let unit_time_ms = get_option_as_int(options, "unit_time_ms", -1);
+        let graph_width = get_option_as_int(options, "graph_width", 900);
+        let mut sw = Stopwatch::start_new();
+
+        let result = self.get_overlay_dashboard(&session_ids, time_axis, unit_time_ms, graph_width)?;
+        sender.send_message(&wrap_response(&cmd, &result));
+        println!("overlay_dashboard total cost: {}ms, sessions: {}", sw.elapsed_ms(), session_ids.len());
+        Ok(())
+    }
+
+    fn handle_merge_sessions_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_ids = get_option_as_str_array(options, "session_ids")?;
+        let start_time = get_option_as_int(options, "start_time", -1);
+        let end_time = get_option_as_int(options, "end_time", -1);
+        let mut sw = Stopwatch::start_new();
+
+        let session_id = self.merge_sessions(&session_ids, start_time, end_time)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "type": "file",
+            "sources": session_ids
+        })));
+        println!("merge_sessions total cost: {}ms, sessions: {}", sw.elapsed_ms(), session_ids.len());
+        Ok(())
+    }
+
+    fn handle_split_sample_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let split_by = get_option_as_str(options, "split_by", "interval");
+        let interval_minutes = get_option_as_int(options, "interval_minutes", 60);
+        let mut split_times = get_option_as_int_array_or_empty(options, "split_times")?;
+        let mut sw = Stopwatch::start_new();
+
+        let collector = self.get_sample_collector(session_id)?;
+        let by_markers = split_by == "markers";
+        let mut writer = clone_writer(sender)?;
+        let audit = self.defer_audit_log(cmd, options);
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::BACKGROUND, move || {
+            let mut collector = collector.lock().unwrap();
+            if by_markers {
+                split_times = collector.get_markers().iter().map(|x| x.time).collect();
+            } else if split_times.is_empty() {
+                let sample_info = collector.get_sample_info();
+                split_times = sample_split::get_interval_split_times(sample_info.record_start_time, sample_info.last_record_time, interval_minutes * 60_000);
+            }
+            let result = sample_split::split_sample(&mut collector, &split_times).map(|sample_dirs| {
+                println!("split_sample total cost: {}ms, parts: {}", sw.elapsed_ms(), sample_dirs.len());
+                json!({
+                    "session_id": session_id,
+                    "sample_dirs": sample_dirs
+                })
+            });
+            send_audited_task_result(&mut writer, &cmd, result, audit);
+        });
+        Ok(())
+    }
+
+    fn handle_add_marker_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let label = get_option_as_str_required(options, "label")?;
+        let color = get_option_as_str(options, "color", "#ff0000");
+        let time = get_option_as_int(options, "time", Local::now().timestamp_millis());
+
+        let collector = self.get_sample_collector(session_id)?;
+        let marker = collector.lock().unwrap().add_marker(time, label, color, "user")?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "marker": marker
+        })));
+        Ok(())
+    }
+
+    fn handle_list_markers_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let markers = collector.lock().unwrap().get_markers();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "markers": markers
+        })));
+        Ok(())
+    }
+
+    //保存命名的查询配置，同名的视图被覆盖
+    fn handle_save_view_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let name = get_option_as_str_required(options, "name")?;
+        let filters = match options.get("filters") {
+            Some(x) => match x.as_object() {
+                Some(x) => x.clone(),
+                None => return Err(new_invalid_input_error("option 'filters' is not object"))
+            },
+            None => serde_json::Map::new()
+        };
+        let now = Local::now().timestamp_millis();
+        let view = SavedView {
+            name: name.to_string(),
+            description: get_option_as_str(options, "description", "").to_string(),
+            created_time: now,
+            updated_time: now,
+            start_time: get_option_as_int(options, "start_time", -1),
+            end_time: get_option_as_int(options, "end_time", -1),
+            thread_ids: get_option_as_int_array_or_empty(options, "thread_ids")?,
+            filters,
+        };
+
+        let collector = self.get_sample_collector(session_id)?;
+        let view = collector.lock().unwrap().save_view(view)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "view": view
+        })));
+        Ok(())
+    }
+
+    fn handle_list_views_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let views = collector.lock().unwrap().get_views();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "views": views
+        })));
+        Ok(())
+    }
+
+    fn handle_list_intervals_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let intervals = collector.lock().unwrap().get_intervals();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "intervals": intervals
+        })));
+        Ok(())
+    }
+
+    fn handle_list_series_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let series = collector.lock().unwrap().list_series();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "series": series
+        })));
+        Ok(())
+    }
+
+    fn handle_export_sample_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let anonymize = get_option_as_str(options, "anonymize", "none");
+        //映射文件写入主取样根目录下
+        let mapping_file = match get_option_as_str(options, "mapping_file", "") {
+            "" => String::new(),
+            x => join_relative_path(self.config.get_primary_samples_root(), x)?
+        };
+        let keep_packages = match options.get("keep_packages") {
+            Some(_) => get_option_as_str_array(options, "keep_packages")?,
+            None => DEFAULT_KEEP_PACKAGES.iter().map(|x| x.to_string()).collect()
+        };
+        let mut sw = Stopwatch::start_new();
+
+        let now_time = Local::now().format("%Y%m%dT%H%M%S").to_string();
+        //导出目录是主取样根目录下的相对路径
+        let default_dir = format!("export-{}", now_time);
+        let export_dir = join_relative_path(self.config.get_primary_samples_root(), get_option_as_str(options, "export_dir", &default_dir))?;
+        let export_options = ExportOptions {
+            anonymize: anonymize.to_string(),
+            keep_packages,
+            mapping_file: mapping_file.clone(),
+        };
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let audit = self.defer_audit_log(cmd, options);
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        let anonymize = anonymize.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::BACKGROUND, move || {
+            let result = export_sample(&mut collector.lock().unwrap(), &export_dir, &export_options).map(|dir| {
+                println!("export_sample total cost: {}ms, dir: {}", sw.elapsed_ms(), dir);
+                json!({
+                    "session_id": session_id,
+                    "sample_data_dir": dir,
+                    "anonymize": anonymize,
+                    "mapping_file": mapping_file
+                })
+            });
+            send_audited_task_result(&mut writer, &cmd, result, audit);
+        });
+        Ok(())
+    }
+
+    fn handle_export_metrics_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let tables = match options.get("tables") {
+            Some(_) => get_option_as_str_array(options, "tables")?,
+            None => EXPORT_TABLES.iter().map(|x| x.to_string()).collect()
+        };
+        let export_options = MetricsExportOptions {
+            format: get_option_as_str(options, "format", "csv").to_string(),
+            tables,
+            start_time,
+            end_time,
+            unit_time_ms: get_option_as_int(options, "unit_time_ms", 1000),
+            exclude_warmup: get_option_as_bool(options, "exclude_warmup", false),
+        };
+        let mut sw = Stopwatch::start_new();
+
+        let now_time = Local::now().format("%Y%m%dT%H%M%S").to_string();
+        //导出目录是主取样根目录下的相对路径
+        let default_dir = format!("metrics-{}", now_time);
+        let export_dir = join_relative_path(self.config.get_primary_samples_root(), get_option_as_str(options, "export_dir", &default_dir))?;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::BACKGROUND, move || {
+            let result = export_metrics(&mut collector.lock().unwrap(), &export_dir, &export_options).map(|tables| {
+                println!("export_metrics total cost: {}ms, dir: {}", sw.elapsed_ms(), export_dir);
+                json!({
+                    "session_id": session_id,
+                    "export_dir": export_dir,
+                    "format": export_options.format,
+                    "tables": tables
+                })
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    //生成报告(html/markdown/pdf)到会话目录，在后台任务中执行
+    fn handle_generate_report_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let defaults = ReportOptions::default();
+        let report_options = ReportOptions {
+            format: get_option_as_str(options, "format", &defaults.format).to_string(),
+            start_time,
+            end_time,
+            top_methods: get_option_as_int(options, "top_methods", defaults.top_methods as i64).max(1) as usize,
+            flame_graph_width: get_option_as_int(options, "flame_graph_width", defaults.flame_graph_width as i64).max(100) as usize,
+            pdf_tool: self.config.pdf_tool.clone(),
+            lang: self.get_request_lang(options),
+        };
+        let mut sw = Stopwatch::start_new();
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::BACKGROUND, move || {
+            let result = generate_report(&collector, &report_options).map(|report| {
+                println!("generate_report total cost: {}ms, path: {}", sw.elapsed_ms(), report.path);
+                json!({
+                    "session_id": session_id,
+                    "format": report_options.format,
+                    "path": report.path,
+                    "size": report.size
+                })
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    fn handle_load_mapping_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let mapping_file = get_option_as_str_required(options, "mapping_file")?;
+        //只能读取取样根目录下的映射文件
+        let mapping_path = resolve_path_under_roots(mapping_file, &self.config.samples_roots)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let (class_count, method_count) = collector.lock().unwrap().load_mapping(&path_to_string(&mapping_path))?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "mapping_file": mapping_file,
+            "classes": class_count,
+            "methods": method_count
+        })));
+        Ok(())
+    }
+
+    fn handle_list_threads_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let query = parse_thread_query(options, 100)?;
+        let (total, threads) = self.list_threads(session_id, &query)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "total": total,
+            "page": query.page,
+            "page_size": query.page_size,
+            "sort_by": get_option_as_str(options, "sort_by", THREAD_SORT_FIELDS[0].0),
+            "order": if query.desc { ORDER_DESC } else { ORDER_ASC },
+            "threads": threads
+        })));
+        Ok(())
+    }
+
+    fn handle_expand_node_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let handle = get_option_as_str_required(options, "handle")?;
+        let levels = get_option_as_int(options, "levels", 3) as i32;
+        let node = self.expand_node(handle, max(levels, 1))?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "handle": handle,
+            "node": node
+        })));
+        Ok(())
+    }
+
+    fn handle_add_samples_root_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let samples_root = get_option_as_str_required(options, "samples_root")?;
+        let samples_roots = self.add_samples_root(samples_root)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "samples_roots": samples_roots
+        })));
+        Ok(())
+    }
+
+    //为旧的取样数据重新生成聚合索引，处理过程中推送进度通知
+    fn handle_build_index_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let sample_data_dir = get_option_as_str_required(options, "sample_data_dir")?;
+        for collector in self.sample_session_map.values() {
+            let collector = collector.lock().unwrap();
+            if collector.get_sample_type() != "file" && collector.get_sample_info().sample_data_dir == sample_data_dir {
+                return Err(new_invalid_input_error(&format!("sample data dir is recording: {}", sample_data_dir)));
+            }
+        }
+        let mut sw = Stopwatch::start_new();
+        let self_ref = self.self_ref.as_ref().unwrap().clone();
+        let mut writer = clone_writer(sender)?;
+        let audit = self.defer_audit_log(cmd, options);
+        let cmd = cmd.to_string();
+        let sample_data_dir = sample_data_dir.to_string();
+
+        self.task_pool.submit(&sample_data_dir.clone(), TaskPriority::BACKGROUND, move || {
+            let mut last_percent = -1;
+            let result = build_agg_index(&sample_data_dir, &mut |done, total| {
+                let percent = if total > 0 { (done * 100 / total) as i64 } else { 100 };
+                if percent != last_percent {
+                    last_percent = percent;
+                    writer.send_message(&wrap_response("build_index_progress", &json!({
+                        "sample_data_dir": sample_data_dir,
+                        "done": done,
+                        "total": total,
+                        "percent": percent
+                    })));
+                }
+            });
+            //reload index of opened session
+            if result.is_ok() {
+                let profiler = self_ref.lock().unwrap();
+                let collector = profiler.find_session_by_origin(&canonicalize_sample_dir(&sample_data_dir))
+                    .and_then(|session_id| profiler.sample_session_map.get(&session_id).cloned());
+                drop(profiler);
+                if let Some(collector) = collector {
+                    collector.lock().unwrap().reload_agg_index();
+                }
+            }
+            let result = result.map(|samples| json!({
+                "sample_data_dir": sample_data_dir,
+                "samples": samples,
+                "cost": sw.elapsed_ms()
+            }));
+            send_audited_task_result(&mut writer, &cmd, result, audit);
+            println!("handle_build_index_request total cost: {}ms", sw.elapsed_ms());
+        });
+        Ok(())
+    }
+
+    fn handle_cpu_time_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let mut thread_ids = get_option_as_int_array(options, "thread_ids")?;
+        let start_time = get_option_as_int(options, "start_time", -1);
+        let end_time = get_option_as_int(options, "end_time", -1);
+        let graph_width = get_option_as_int(options, "graph_width", 900);
+        let unit_time_ms = get_option_as_int(options, "unit_time_ms", -1);
+
+        if thread_ids.is_empty() {
+            if options.contains_key("page_size") || options.contains_key("name_filter") || options.contains_key("sort_by") {
+                let query = parse_thread_query(options, 0)?;
+                let (_, threads) = self.list_threads(session_id, &query)?;
+                thread_ids = threads.iter().map(|x| x.id).collect();
+            } else {
+                thread_ids = self.get_all_thread_ids(session_id)?;
+            }
+        }
+        //TODO fetch only top n threads data
+        //fetch and send in batches, avoid long waiting
+        let t0 = Local::now().timestamp_millis();
+        println!("[{}] handle_cpu_time_request, fetching thread count: {}", utils::nowTime(), thread_ids.len());
+        let mut start = 0;
+        while start < thread_ids.len() {
+            let t1 = Local::now().timestamp_millis();
+            let end = min(start+50, thread_ids.len());
+            let thread_cpu_times = self.get_thread_cpu_times(session_id, &thread_ids[start..end], start_time, end_time, unit_time_ms, graph_width)?;;
+            let result = json!({
+                "session_id": session_id,
+                "thread_cpu_times": thread_cpu_times
+            });
+            let t2 = Local::now().timestamp_millis();
+            sender.send_message(&wrap_response(&cmd, &result));
+            println!("[{}] fetch thread cpu time data cost: {}ms, threads: {}-{}", utils::nowTime(), t2-t1, start, end);
+            start = end;
+        }
+        let t100 = Local::now().timestamp_millis();
+        println!("[{}] handle_cpu_time_request total cost: {}ms, thread count: {}", utils::nowTime(), t100-t0, thread_ids.len());
+
+        Ok(())
+    }
+
+    fn handle_call_tree_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let thread_ids = get_option_as_int_array(options, "thread_ids")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let mut sw = Stopwatch::start_new();
+
+        let levels = get_option_as_int(options, "levels", 0) as i32;
+        let include_offcpu = get_option_as_bool(options, "offcpu", false);
+        let mut call_tree = self.get_call_tree(session_id, thread_ids.as_slice(), start_time, end_time, include_offcpu)?;
+        prune_tree(&mut call_tree, &parse_prune_options(options, false));
+        println!("build call tree data cost: {}ms, threads: {:?}", sw.lap(), &thread_ids);
+
+        let result = if levels > 0 {
+            let (tree_handle, data) = self.cache_tree(session_id, Box::new(call_tree), levels);
+            json!({
+                "session_id": session_id,
+                "tree_handle": tree_handle,
+                "call_tree_data": [data]
+            })
+        } else {
+            json!({
+                "session_id": session_id,
+                "call_tree_data": [call_tree]
+            })
+        };
+        let message = wrap_response(&cmd, &result);
+        println!("wrap message cost: {}ms", sw.lap());
+
+        sender.send_message(&message);
+        println!("handle_call_tree_request total cost: {}ms", sw.elapsed_ms());
+        Ok(())
+    }
+
+    fn handle_flame_graph_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let thread_id = get_option_as_int(options, "thread_id", -1);
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let mut image_width = get_option_as_int(options, "image_width", 900);
+        if image_width <= 0 {
+            image_width = 900;
+        }
+        let stats_type = get_option_as_str(options, "stats_type", "duration");
+        let idle_mode_str = get_option_as_str(options, "idle_mode", "keep");
+        let idle_mode = parse_idle_mode(idle_mode_str)?;
+        let mut sw = Stopwatch::start_new();
+
+        if thread_id <= 0 {
+            return Err(new_invalid_input_error("missing or invalid option 'thread_id'"));
+        }
+        //graph_mode: sequenced 按时间顺序, merged 按调用树合并
+        let graph_mode = get_option_as_str(options, "graph_mode", "sequenced").to_string();
+        if graph_mode != "sequenced" && graph_mode != "merged" {
+            return Err(new_invalid_input_error(&format!("invalid graph_mode: {}", graph_mode)));
+        }
+        //off-CPU调用栈没有时间顺序，只能合并到merged火焰图
+        let include_offcpu = get_option_as_bool(options, "offcpu", false);
+        if include_offcpu && graph_mode != "merged" {
+            return Err(new_invalid_input_error("option 'offcpu' requires graph_mode 'merged'"));
+        }
+        let prune_options = parse_prune_options(options, graph_mode == "sequenced");
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        let stats_type = stats_type.to_string();
+        let idle_mode_str = idle_mode_str.to_string();
+
+        //在分析线程池中执行，结果通过客户端连接的副本发送
+        self.task_pool.submit(&session_id.clone(), TaskPriority::INTERACTIVE, move || {
+            let mut new_start_time = start_time;
+            let mut new_end_time = end_time;
+            let mut idle_stats = IdleStats::default();
+            let svg = if graph_mode == "sequenced" {
+                Profiler::create_flame_graph_svg(&collector, thread_id, &mut new_start_time, &mut new_end_time, &stats_type, image_width as usize, idle_mode, &mut idle_stats, &prune_options)
+            } else {
+                Profiler::create_merged_flame_graph_svg(&collector, thread_id, start_time, end_time, &stats_type, image_width as usize, &prune_options, include_offcpu)
+            };
+            let result = svg.map(|svg| json!({
+                "session_id": session_id,
+                "thread_id": thread_id,
+                "start_time": new_start_time,
+                "end_time": new_end_time,
+                "stats_type": stats_type,
+                "image_width": image_width,
+                "graph_mode": graph_mode,
+                "idle_mode": idle_mode_str,
+                "idle_stats": idle_stats,
+                "flame_graph_data": svg
+            }));
+            send_task_result(&mut writer, &cmd, result);
+            println!("handle_flame_graph_request total cost: {}ms", sw.elapsed_ms());
+        });
+        Ok(())
+    }
+
+    fn handle_sequenced_call_tree_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let thread_id = get_option_as_int(options, "thread_id", -1);
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let stats_type = get_option_as_str(options, "stats_type", "duration");
+        let idle_mode_str = get_option_as_str(options, "idle_mode", "keep");
+        let idle_mode = parse_idle_mode(idle_mode_str)?;
+        let mut sw = Stopwatch::start_new();
+
+        if thread_id <= 0 {
+            return Err(new_invalid_input_error("missing or invalid option 'thread_id'"));
+        }
+        let mut new_start_time = start_time;
+        let mut new_end_time = end_time;
+        let mut idle_stats = IdleStats::default();
+        let levels = get_option_as_int(options, "levels", 0) as i32;
+        let prune_options = parse_prune_options(options, true);
+        let stacks = self.get_sequenced_call_tree(session_id, thread_id, &mut new_start_time, &mut new_end_time, stats_type, idle_mode, &mut idle_stats, &prune_options)?;
+        let (tree_handle, stacks) = if levels > 0 {
+            let (tree_handle, data) = self.cache_tree(session_id, stacks, levels);
+            (Some(tree_handle), data)
+        } else {
+            (None, serde_json::to_value(&stacks)?)
+        };
+        let result = json!({
+                "session_id": session_id,
+                "thread_id": thread_id,
+                "start_time": new_start_time,
+                "end_time": new_end_time,
+                "stats_type": stats_type,
+                "idle_mode": idle_mode_str,
+                "idle_stats": idle_stats,
+                "tree_handle": tree_handle,
+                "sequenced_call_tree_data": stacks
+            });
+        let message = wrap_response(&cmd, &result);
+        sender.send_message(&message);
+        println!("handle_sequenced_call_tree_request total cost: {}ms", sw.elapsed_ms());
+
+        Ok(())
+    }
+
+    fn handle_list_methods_by_filter_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut sw = Stopwatch::start_new();
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let method_name_filter = get_option_as_str(options, "method_name_filter", "");
+        let query = PageQuery::from_options(options, METHOD_SORT_FIELDS, 0)?;
+
+        let method_info_vec = self.list_methods_by_filter(session_id, method_name_filter)?;
+        let filter_method_size = method_info_vec.len();
+        println!("filter method size: {}", filter_method_size);
+        let (_, method_infos) = sort_and_page(&method_info_vec, &query)?;
+        let mut result = json!({
+                "session_id": session_id,
+                "method_name_filter": method_name_filter,
+                "total_method_size": filter_method_size,
+                "method_infos": method_infos
+            });
+        query.add_page_info(&mut result, filter_method_size);
+        let message = wrap_response(&cmd, &result);
+        sender.send_message(&message);
+        println!("handle_list_methods_by_filter_request total cost: {}ms", sw.elapsed_ms());
+        Ok(())
+    }
+
+    fn handle_search_slow_method_calls_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut sw = Stopwatch::start_new();
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let mut method_ids = get_option_as_int_array(options, "method_ids")?;
+        let min_duration = get_option_as_int(options, "min_duration", 100);
+        let max_duration = get_option_as_int(options, "max_duration", -1);
+        let max_size = get_option_as_int(options, "max_size", 2000) as usize;
+        let thread_name_filter = get_option_as_str(options, "thread_name_filter", "");
+        let query = PageQuery::from_options(options, METHOD_CALL_GROUP_SORT_FIELDS, 0)?;
+
+        //sort asc for binarysearch
+        method_ids.sort();
+
+        //let method_calls : Vec<String> = vec![];
+        let mut search_error = false;
+        let mut search_error_msg = "".to_string();
+        if let Some(collector) = self.sample_session_map.get(session_id) {
+            let mut total = 0;
+            let threads = collector.lock().unwrap().get_threads()?;
+            let thread_size = threads.len();
+            let mut search_progress = 0;
+            let mut method_analysis = MethodAnalysis::new();
+            //search every thread
+            for (i,thread) in threads.iter().enumerate() {
+                sw.lap();
+
+                //filter thread by name
+                if !thread_name_filter.is_empty() && !thread.name.contains(thread_name_filter) {
+                    continue;
+                }
+
+                match collector.lock().unwrap().search_slow_method_calls(thread.id, &method_ids, min_duration, max_duration) {
+                    Ok(method_calls ) => {
+                        let search_cost = sw.lap();
+                        //send progress
+                        let new_search_progress = 100*i/thread_size;
+                        if new_search_progress - search_progress > 5 {
+                            search_progress = new_search_progress;
+                            let result = json!({
+                                "session_id": session_id,
+                                //"method_ids": method_ids,
+                                "search_progress": search_progress,
+                                "search_finished": false,
+                                "search_message": format!("searching {}", thread.name)
+                            });
+                            sender.send_message(&wrap_response(&cmd, &result));
+                            println!("search progress: {}%", search_progress);
+                        }
+
+                        if method_calls.is_empty() {
+                            //println!("slow method calls not found, thread: {}, search cost: {}ms", thread.id, search_cost);
+                            continue;
+                        }
+                        total += method_calls.len();
+                        println!("found slow method calls: {}, thread: {}, search cost: {}ms, send cost: {}ms", method_calls.len(), thread.id,  search_cost, sw.lap());
+
+                        for method_call in method_calls {
+                            method_analysis.add_method_call(method_call);
+                        }
+                    }
+                    Err(e) => {
+                        println!("found slow method calls failed, thread: {}, error: {}", thread.id, e);
+                    }
+                }
+
+                if total >= max_size {
+                    search_error = true;
+                    search_error_msg = format!("too many slow method calls: {}, search aborted!", total);
+                    println!("too many slow method calls: {}, search progress: {}/{} ({}%), abort searching!", total, i, thread_size, search_progress);
+                    break;
+                }
+            }
+
+            //fill call method name
+            for method_group in &mut method_analysis.call_groups {
+                for call in &mut method_group.call_stack {
+                    match collector.lock().unwrap().get_method_info(call.method_id) {
+                        Some(val) => {
+                            call.full_name = val.full_name.clone();
+                        },
+                        None => {
+                            call.full_name = call.method_id.to_string();
+                        }
+                    }
+                }
+            }
+
+            let (total_groups, method_call_groups) = sort_and_page(method_analysis.get_method_groups(), &query)?;
+            let mut result = json!({
+                "session_id": session_id,
+                "method_ids": method_ids,
+                "method_call_groups": method_call_groups,
+                "search_progress": search_progress,
+                "search_finished": !search_error,
+                "search_error": search_error,
+                "search_message": search_error_msg
+            });
+            query.add_page_info(&mut result, total_groups);
+            sender.send_message(&wrap_response(&cmd, &result));
+            println!("handle_search_slow_method_calls_request total cost: {}ms", sw.elapsed_ms());
+        }else {
+            return Err(io::Error::new(ErrorKind::NotFound, "sample session not found"));
+        }
+        Ok(())
+    }
+
+    fn handle_database_time_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut sw = Stopwatch::start_new();
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let mut thread_ids = get_option_as_int_array_or_empty(options, "thread_ids")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+
+        if thread_ids.is_empty() {
+            thread_ids = self.get_all_thread_ids(session_id)?;
+        }
+        let collector = self.get_sample_collector(session_id)?;
+        let database_time = collector.lock().unwrap().get_database_time(&thread_ids, start_time, end_time)?;
+        let result = json!({
+                "session_id": session_id,
+                "start_time": start_time,
+                "end_time": end_time,
+                "database_time": database_time
+            });
+        sender.send_message(&wrap_response(&cmd, &result));
+        println!("handle_database_time_request total cost: {}ms, threads: {}", sw.elapsed_ms(), thread_ids.len());
+        Ok(())
+    }
+
+    fn handle_query_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut sw = Stopwatch::start_new();
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let query_str = get_option_as_str_required(options, "query")?;
+        let query = query_dsl::parse_query(query_str)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let query_result = query_dsl::execute_query(&mut collector.lock().unwrap(), &query)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "query": query_str,
+            "result": query_result
+        })));
+        println!("handle_query_request total cost: {}ms, query: {}", sw.elapsed_ms(), query_str);
+        Ok(())
+    }
+
+    //对目标进程执行off-CPU取样，结果保存到会话
+    fn handle_start_offcpu_sampling_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        //只能取样会话的目标进程，pid可以省略
+        let target_pid = self.get_session_target_pid(session_id)?;
+        let pid = get_option_as_int(options, "pid", target_pid);
+        if pid != target_pid {
+            return Err(new_invalid_input_error(&format!("pid {} is not the target process of session: {}", pid, target_pid)));
+        }
+        let duration_secs = get_option_as_int(options, "duration_secs", 10);
+        let collector = self.get_sample_collector(session_id)?;
+        let duration_secs = start_offcpu_sampling(&self.config.offcpu_tool, pid, duration_secs, collector)?;
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": session_id, "pid": pid, "duration_secs": duration_secs })));
+        Ok(())
+    }
+
+    //合并时间范围内的off-CPU调用栈
+    fn handle_offcpu_stacks_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let thread_name = get_option_as_str(options, "thread_name", "");
+        //thread_id: 线程句柄，只返回关联到该线程的调用栈
+        let thread_id = get_option_as_int(options, "thread_id", -1);
+        let limit = get_option_as_int(options, "limit", 100).max(1) as usize;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut stacks = merge_offcpu_stacks(collector.lock().unwrap().get_offcpu_profiles(), start_time, end_time, thread_name, thread_id);
+        let total_off_cpu_us: i64 = stacks.iter().map(|x| x.off_cpu_us).sum();
+        stacks.truncate(limit);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "total_off_cpu_us": total_off_cpu_us,
+            "stacks": stacks
+        })));
+        Ok(())
+    }
+
+    //资源指标的时间序列，group: cgroup, host
+    //transform: counter类型的指标默认返回每秒的速率(rate)，delta为每个时间单位的增量，none为累计值
+    fn handle_metric_values_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>, group: &str) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let unit_time_ms = get_option_as_int(options, "unit_time_ms", METRIC_UNIT_TIME as i64);
+        let transform = get_option_as_str(options, "transform", "rate");
+        let transform = TSTransform::parse(transform)
+            .ok_or_else(|| new_invalid_input_error(&format!("invalid transform: {}, expected: none, delta, rate", transform)))?;
+        let collector = self.get_sample_collector(session_id)?;
+        let collector = collector.lock().unwrap();
+        let metrics = collector.get_metric_values(group, start_time, end_time, unit_time_ms, transform);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "pid": collector.get_target_pid(),
+            "metrics": metrics
+        })));
+        Ok(())
+    }
+
+    //请求agent检测死锁，检测结果异步推送并保存到会话，通过list_deadlocks查询
+    //interval_ms: 定期检测的间隔，0 关闭定期检测
+    fn handle_detect_deadlocks_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let interval_ms = get_option_as_int(options, "interval_ms", -1);
+        let collector = self.get_sample_collector(session_id)?;
+        let collector = collector.lock().unwrap();
+        if collector.get_sample_type() != "attach" {
+            return Err(new_invalid_input_error("deadlock detection requires an attach session"));
+        }
+        collector.request_deadlock_detection(interval_ms)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "interval_ms": interval_ms,
+            "deadlocks": collector.get_deadlocks().len()
+        })));
+        Ok(())
+    }
+
+    fn handle_list_deadlocks_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let deadlocks = get_deadlocks(&mut collector.lock().unwrap(), start_time, end_time);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "deadlocks": deadlocks
+        })));
+        Ok(())
+    }
+
+    //请求agent获取完整线程dump，等待agent推送并保存后返回dump内容
+    fn handle_thread_dump_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let timeout_ms = get_option_as_int(options, "timeout_ms", 10_000);
+        let collector = self.get_sample_collector(session_id)?;
+        let dump_count = {
+            let collector = collector.lock().unwrap();
+            if collector.get_sample_type() != "attach" {
+                return Err(new_invalid_input_error("thread dump requires an attach session"));
+            }
+            collector.request_thread_dump()?;
+            collector.get_thread_dumps().len()
+        };
+        let audit = self.defer_audit_log(cmd, options);
+        self.wait_agent_result(sender, cmd, session_id, collector, timeout_ms, audit, move |collector| {
+            let info = collector.get_thread_dumps().get(dump_count)?.clone();
+            Some(collector.load_thread_dump(info.time).map(|content| json!({ "time": info.time, "threads": info.threads, "content": content })))
+        })
+    }
+
+    //等待agent异步推送的结果，会话收到结果后调用check，返回Some时发送结果，等待期间不占用任务线程
+    //  超时在取样目录监视线程中检查(见 expire_agent_results)，实际超时最多晚 WATCH_INTERVAL_MS
+    //  timeout_ms 限制在 [1, MAX_AGENT_RESULT_TIMEOUT_MS]，避免等待者长时间留在会话中
+    fn wait_agent_result<F>(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, session_id: &str, collector: Arc<Mutex<SampleCollector>>, timeout_ms: i64, audit: Option<PendingAudit>, check: F) -> io::Result<()>
+        where F: Fn(&SampleCollector) -> Option<io::Result<serde_json::Value>> + Send + 'static {
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        let mut audit = audit;
+        let deadline = Local::now().timestamp_millis() + timeout_ms.max(1).min(MAX_AGENT_RESULT_TIMEOUT_MS);
+        collector.lock().unwrap().add_agent_result_waiter(deadline, Box::new(move |collector, error| {
+            let result = match error {
+                Some(e) => Err(new_error(e.kind(), &format!("{}: {}", e, cmd))),
+                None => match check(collector) {
+                    Some(result) => result.map(|mut data| {
+                        data["session_id"] = json!(session_id);
+                        data
+                    }),
+                    None => return false
+                }
+            };
+            send_audited_task_result(&mut writer, &cmd, result, audit.take());
+            true
+        }));
+        Ok(())
+    }
+
+    //请求agent统计堆直方图，等待agent推送并保存后返回
+    //force_gc: 统计前强制GC，limit: 返回的类数量
+    fn handle_heap_histogram_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let force_gc = get_option_as_bool(options, "force_gc", false);
+        let limit = get_option_as_int(options, "limit", 0).max(0);
+        let timeout_ms = get_option_as_int(options, "timeout_ms", 30_000);
+        let collector = self.get_sample_collector(session_id)?;
+        let histogram_count = {
+            let collector = collector.lock().unwrap();
+            if collector.get_sample_type() != "attach" {
+                return Err(new_invalid_input_error("heap histogram requires an attach session"));
+            }
+            collector.request_heap_histogram(force_gc, limit)?;
+            collector.get_heap_histograms().len()
+        };
+        let audit = self.defer_audit_log(cmd, options);
+        self.wait_agent_result(sender, cmd, session_id, collector, timeout_ms, audit, move |collector| {
+            let info = collector.get_heap_histograms().get(histogram_count)?.clone();
+            Some(collector.load_heap_histogram(info.time).map(|histogram| json!({ "histogram": histogram })))
+        })
+    }
+
+    fn handle_list_heap_histograms_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let histograms = collector.lock().unwrap().get_heap_histograms().to_vec();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "histograms": histograms
+        })));
+        Ok(())
+    }
+
+    //比较两次堆直方图，before/after 为直方图的时间
+    fn handle_diff_heap_histograms_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let before_time = get_option_as_int(options, "before", -1);
+        let after_time = get_option_as_int(options, "after", -1);
+        let limit = get_option_as_int(options, "limit", 100).max(1) as usize;
+        let collector = self.get_sample_collector(session_id)?;
+        let (before, after) = {
+            let collector = collector.lock().unwrap();
+            (collector.load_heap_histogram(before_time)?, collector.load_heap_histogram(after_time)?)
+        };
+        let mut diffs = diff_heap_histograms(&before, &after);
+        diffs.truncate(limit);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "before": before.get_info(),
+            "after": after.get_info(),
+            "classes": diffs
+        })));
+        Ok(())
+    }
+
+    //时间范围内各线程的分配速率序列及分配最多的方法，需要agent参数 alloc_interval 开启分配统计
+    //unit_time_ms: 序列的单位时间，limit: 返回的线程/方法数量
+    fn handle_allocation_rate_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let unit_time_ms = get_option_as_int(options, "unit_time_ms", METRIC_UNIT_TIME as i64).max(1);
+        let limit = get_option_as_int(options, "limit", 20).max(1) as usize;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut collector = collector.lock().unwrap();
+        let sample_info = collector.get_sample_info();
+        let start_time = if start_time < 0 { sample_info.record_start_time } else { start_time };
+        let end_time = if end_time < 0 { sample_info.last_record_time } else { end_time };
+        let report = get_allocation_rate(&mut collector, start_time, end_time, unit_time_ms, limit);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "allocation": report
+        })));
+        Ok(())
+    }
+
+    //按方法汇总的反优化次数，需要agent参数 deopt_interval 开启编译事件
+    fn handle_deopt_stats_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let limit = get_option_as_int(options, "limit", 50).max(1) as usize;
+        let collector = self.get_sample_collector(session_id)?;
+        let stats = get_deopt_stats(&mut collector.lock().unwrap(), start_time, end_time, limit);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "total": stats.iter().map(|x| x.count).sum::<i64>(),
+            "methods": stats
+        })));
+        Ok(())
+    }
+
+    //最新的类加载器及可能泄漏的类加载器，需要agent参数 classloader_interval
+    fn handle_class_loader_leaks_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let mut leak_options = ClassLoaderLeakOptions::default();
+        leak_options.min_growth = get_option_as_int(options, "min_growth", leak_options.min_growth);
+        leak_options.min_snapshots = get_option_as_int(options, "min_snapshots", leak_options.min_snapshots as i64).max(2) as usize;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut collector = collector.lock().unwrap();
+        let loaders = get_latest_class_loaders(&collector, start_time, end_time);
+        let leaks = detect_class_loader_leaks(&mut collector, start_time, end_time, &leak_options);
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "loaders": loaders,
+            "leaks": leaks
+        })));
+        Ok(())
+    }
+
+    //设置(指定 filter 时)及查询会话的写入过滤
+    fn handle_ingest_filter_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut collector = collector.lock().unwrap();
+        if let Some(filter) = options.get("filter") {
+            let filter = match filter.as_object() {
+                Some(x) => IngestFilter::from_options(x)?,
+                None => return Err(new_invalid_input_error("option 'filter' is not an object"))
+            };
+            collector.set_ingest_filter(filter)?;
+        }
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "filter": collector.get_ingest_filter(),
+            "stats": collector.get_ingest_stats()
+        })));
+        Ok(())
+    }
+
+    //设置(指定任一策略选项时)及查询录制会话的刷新策略，返回还没有写入文件的数据量
+    fn handle_flush_policy_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut collector = collector.lock().unwrap();
+        if ["interval_ms", "max_buffer_bytes", "fsync"].iter().any(|x| options.contains_key(*x)) {
+            let policy = collector.get_flush_status().policy.merge_options(options)?;
+            collector.set_flush_policy(policy)?;
+        }
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "flush": collector.get_flush_status()
+        })));
+        Ok(())
+    }
+
+    //线程id与线程句柄的映射，按线程查询时使用句柄；指定 thread_id 时只返回该线程id的句柄
+    fn handle_thread_handles_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let thread_id = get_option_as_int(options, "thread_id", -1);
+        let collector = self.get_sample_collector(session_id)?;
+        let collector = collector.lock().unwrap();
+        let all_handles = collector.get_thread_handles();
+        let handles: Vec<Value> = all_handles.iter()
+            .filter(|x| thread_id < 0 || x.thread_id == thread_id)
+            .map(|x| json!({
+                "handle": x.handle,
+                "thread_id": x.thread_id,
+                "name": x.name,
+                "start_time": x.start_time,
+                "end_time": x.end_time,
+                "reused": all_handles.iter().any(|y| y.thread_id == x.thread_id && y.handle != x.handle)
+            }))
+            .collect();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "handles": handles
+        })));
+        Ok(())
+    }
+
+    //会话取样目录按数据类型的磁盘占用，录制中的会话返回增长速度的估算；没有指定 session_id 时返回所有会话
+    fn handle_storage_usage_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let mut session_ids: Vec<String> = match options.get("session_id").and_then(|x| x.as_str()) {
+            Some(session_id) => vec![session_id.to_string()],
+            None => self.sample_session_map.keys().filter(|x| self.is_session_visible(x)).cloned().collect()
+        };
+        session_ids.sort();
+        let now = Local::now().timestamp_millis();
+        let mut sessions = vec![];
+        let mut total_bytes = 0;
+        for session_id in &session_ids {
+            let (recording, sample_info) = {
+                let collector = self.get_sample_collector(session_id)?;
+                let collector = collector.lock().unwrap();
+                (collector.get_sample_type() == "attach" && !collector.is_disconnected(), collector.get_sample_info())
+            };
+            let usage = if sample_info.sample_data_dir == "" {
+                StorageUsage::default()
+            } else {
+                measure_storage_usage(&sample_info.sample_data_dir)?
+            };
+            let growth = if recording {
+                let last = self.storage_measurements.get(session_id).cloned();
+                if last.map_or(true, |(time, _)| now - time >= MIN_GROWTH_INTERVAL_MS) {
+                    self.storage_measurements.insert(session_id.clone(), (now, usage.total_bytes));
+                }
+                let record_duration_ms = sample_info.last_record_time - sample_info.record_start_time;
+                Some(estimate_growth(last, now, usage.total_bytes, record_duration_ms, get_free_disk_space(&sample_info.sample_data_dir)))
+            } else {
+                None
+            };
+            total_bytes += usage.total_bytes;
+            sessions.push(json!({
+                "session_id": session_id,
+                "sample_data_dir": sample_info.sample_data_dir,
+                "recording": recording,
+                "usage": usage,
+                "growth": growth
+            }));
+        }
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "sessions": sessions,
+            "total_bytes": total_bytes
+        })));
+        Ok(())
+    }
+
+    //开始、停止及查询服务端的区段计时(见 self_profile)，停止后返回的取样目录可以用 open_sample 打开
+    fn handle_self_profile_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let status = match get_option_as_str(options, "action", "status") {
+            "start" => {
+                let interval_ms = get_option_as_int(options, "interval_ms", DEFAULT_SELF_PROFILE_INTERVAL_MS);
+                let duration_secs = get_option_as_int(options, "duration_secs", 0);
+                Some(self.start_self_profiling(interval_ms, duration_secs)?)
+            }
+            "stop" => Some(stop_self_profiling()?),
+            "status" => get_self_profiling_status(),
+            action => return Err(new_invalid_input_error(&format!("unknown span timing action: {}", action)))
+        };
+        sender.send_message(&wrap_response(&cmd, &json!({ "self_profile": status })));
+        Ok(())
+    }
+
+    //区段计时保存在录制输出目录下，duration_secs 为0时直到停止
+    pub fn start_self_profiling(&mut self, interval_ms: i64, duration_secs: i64) -> io::Result<SelfProfileStatus> {
+        let now_time = Local::now().format("%Y%m%dT%H%M%S").to_string();
+        let sample_data_dir = format!("{}/{}-self-{}", self.config.get_primary_samples_root(), SELF_PROFILE_AGENT_ADDR, now_time);
+        start_self_profiling(&sample_data_dir, interval_ms, duration_secs)
+    }
+
+    //把会话的取样登记为应用标签的基线，同一个标签的旧基线被替换
+    fn handle_set_baseline_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let app_tag = get_option_as_str_required(options, "app_tag")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let sample_data_dir = collector.lock().unwrap().get_sample_info().sample_data_dir;
+        if sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let baseline = set_baseline(self.config.get_primary_samples_root(), Baseline {
+            app_tag: app_tag.to_string(),
+            sample_data_dir: canonicalize_sample_dir(&sample_data_dir),
+            start_time,
+            end_time,
+            description: get_option_as_str(options, "description", "").to_string(),
+            marked_time: Local::now().timestamp_millis(),
+        })?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "baseline": baseline
+        })));
+        Ok(())
+    }
+
+    fn handle_list_baselines_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, _options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let baselines = load_baselines(self.config.get_primary_samples_root())?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "baselines": baselines
+        })));
+        Ok(())
+    }
+
+    //重新加载配置文件，配置了访问令牌时只有管理员可以执行
+    fn handle_reload_config_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, _options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        if !get_client_identity().is_admin(&self.config.access_tokens) {
+            return Err(new_error(ErrorKind::PermissionDenied, "reload config requires an admin token"));
+        }
+        let changed = self.reload_config()?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "changed": changed
+        })));
+        Ok(())
+    }
+
+    //查询审计日志，配置了访问令牌时只有管理员可以查询
+    fn handle_audit_log_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        if !get_client_identity().is_admin(&self.config.access_tokens) {
+            return Err(new_error(ErrorKind::PermissionDenied, "audit log requires an admin token"));
+        }
+        let query = AuditQuery {
+            start_time: get_option_as_int(options, "start_time", -1),
+            end_time: get_option_as_int(options, "end_time", -1),
+            identity: get_option_as_str(options, "identity", "").to_string(),
+            action: get_option_as_str(options, "action", "").to_string(),
+        };
+        let page_query = PageQuery::from_options(options, AUDIT_SORT_FIELDS, 100)?;
+        let entries = load_audit_entries(&self.config.get_audit_log_file(), &query)?;
+        let (total, entries) = sort_and_page(&entries, &page_query)?;
+        let mut result = json!({ "entries": entries });
+        page_query.add_page_info(&mut result, total);
+        sender.send_message(&wrap_response(&cmd, &result));
+        Ok(())
+    }
+
+    //agent推送的诊断信息，按级别、类型、时间过滤，summary 为过滤后按类型的汇总
+    fn handle_session_events_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let query = SessionEventQuery {
+            start_time: get_option_as_int(options, "start_time", -1),
+            end_time: get_option_as_int(options, "end_time", -1),
+            level: get_option_as_str(options, "level", "").to_string(),
+            kind: get_option_as_str(options, "kind", "").to_string(),
+        };
+        check_level(&query.level)?;
+        let page_query = PageQuery::from_options(options, SESSION_EVENT_SORT_FIELDS, 100)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let events: Vec<_> = collector.lock().unwrap().get_session_events().iter().filter(|x| query.matches(x)).cloned().collect();
+        let summary = summarize_session_events(&events);
+        let (total, events) = sort_and_page(&events, &page_query)?;
+        let mut result = json!({
+            "session_id": session_id,
+            "events": events,
+            "summary": summary
+        });
+        page_query.add_page_info(&mut result, total);
+        sender.send_message(&wrap_response(&cmd, &result));
+        Ok(())
+    }
+
+    //协议的JSON Schema，指定 command 时只返回该命令的schema
+    fn handle_schema_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let command = get_option_as_str(options, "command", "");
+        let result = if command.is_empty() {
+            schema::get_protocol_schema()
+        } else {
+            schema::get_command_schema(command)?
+        };
+        sender.send_message(&wrap_response(&cmd, &result));
+        Ok(())
+    }
+
+    //与应用标签的基线比较，列出栈顶占比增加超过阈值的方法
+    fn handle_compare_to_baseline_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let app_tag = get_option_as_str_required(options, "app_tag")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let defaults = CompareOptions::default();
+        let threshold_percent = get_option_as_f64(options, "threshold", defaults.threshold_percent);
+        //threshold: 方法占比增加的百分点，cpu_threshold: 总CPU增加的百分比，默认与 threshold 相同
+        let compare_options = CompareOptions {
+            threshold_percent,
+            cpu_threshold_percent: get_option_as_f64(options, "cpu_threshold", threshold_percent),
+            min_samples: get_option_as_int(options, "min_samples", defaults.min_samples),
+            limit: get_option_as_int(options, "limit", defaults.limit as i64).max(1) as usize,
+        };
+        let baseline = get_baseline(self.config.get_primary_samples_root(), app_tag)?;
+        let candidate = self.get_sample_collector(session_id)?;
+        let baseline_session_id = self.open_sample(&baseline.sample_data_dir)?;
+        let baseline_collector = self.get_sample_collector(&baseline_session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::BACKGROUND, move || {
+            //依次加锁，基线与候选是同一个会话时不会死锁
+            let baseline_profile = get_method_profile(&mut baseline_collector.lock().unwrap(), baseline.start_time, baseline.end_time);
+            let result = baseline_profile.and_then(|baseline_profile| {
+                let candidate_profile = get_method_profile(&mut candidate.lock().unwrap(), start_time, end_time)?;
+                let comparison = compare_profiles(&baseline_profile, &candidate_profile, &compare_options);
+                Ok(json!({
+                    "session_id": session_id,
+                    "baseline": baseline,
+                    "baseline_session_id": baseline_session_id,
+                    "regressed": comparison.is_regressed(),
+                    "comparison": comparison
+                }))
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    //一次返回时间范围内的CPU序列、GC暂停、标记和CPU最高的线程
+    fn handle_combined_view_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let graph_width = get_option_as_int(options, "graph_width", 900);
+        let unit_time_ms = get_option_as_int(options, "unit_time_ms", -1);
+        let top_threads = get_option_as_int(options, "top_threads", 10).max(0) as usize;
+        let result = self.get_combined_view(session_id, start_time, end_time, unit_time_ms, graph_width, top_threads)?;
+        sender.send_message(&wrap_response(&cmd, &result));
+        Ok(())
+    }
+
+    fn handle_list_thread_dumps_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let collector = self.get_sample_collector(session_id)?;
+        let thread_dumps = collector.lock().unwrap().get_thread_dumps().to_vec();
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "thread_dumps": thread_dumps
+        })));
+        Ok(())
+    }
+
+    fn handle_get_thread_dump_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let time = get_option_as_int(options, "time", -1);
+        //thread_id: 线程句柄，只返回该线程的部分
+        let thread_id = get_option_as_int(options, "thread_id", -1);
+        let collector = self.get_sample_collector(session_id)?;
+        let content = if thread_id >= 0 {
+            collector.lock().unwrap().load_thread_dump_of_thread(time, thread_id)?
+        } else {
+            collector.lock().unwrap().load_thread_dump(time)?
+        };
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "time": time,
+            "thread_id": thread_id,
+            "content": content
+        })));
+        Ok(())
+    }
+
+    fn handle_list_child_processes_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let pid = self.get_session_target_pid(session_id)?;
+        let children = list_child_jvms(pid)?;
+        let child_sessions: Vec<&String> = self.session_parents.iter().filter(|(_, parent)| parent.as_str() == session_id).map(|x| x.0).collect();
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": session_id, "pid": pid, "children": children, "child_sessions": child_sessions })));
+        Ok(())
+    }
+
+    //连接子JVM: 指定agent_addr，或者指定pid从启动参数中查找agent地址
+    fn handle_attach_child_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let child_pid = get_option_as_int(options, "pid", -1);
+        let agent_addr = get_option_as_str(options, "agent_addr", "");
+        let parent_pid = self.get_session_target_pid(session_id)?;
+        //只能连接进程树中目标进程的子JVM，agent_addr 必须是子进程监听的本机地址
+        let child_pid = if child_pid > 0 {
+            child_pid
+        } else if agent_addr != "" {
+            match find_agent_pid(agent_addr) {
+                Some(pid) => pid,
+                None => return Err(new_error(ErrorKind::PermissionDenied, &format!("agent is not listening in a local child process: {}", agent_addr)))
+            }
+        } else {
+            return Err(new_invalid_input_error("missing option 'pid' or 'agent_addr'"));
+        };
+        let mut child = match list_child_jvms(parent_pid)?.into_iter().find(|x| x.pid == child_pid) {
+            Some(x) => x,
+            None => return Err(new_invalid_input_error(&format!("child jvm not found: {}, parent pid: {}", child_pid, parent_pid)))
+        };
+        if agent_addr != "" {
+            if find_agent_pid(agent_addr) != Some(child.pid) {
+                return Err(new_error(ErrorKind::PermissionDenied, &format!("agent {} does not belong to child jvm: {}", agent_addr, child.pid)));
+            }
+            child.agent_addr = Some(agent_addr.to_string());
+        }
+        let instance_id = self.attach_child(session_id, &child)?;
+        sender.send_message(&wrap_response(&cmd, &json!({ "session_id": instance_id, "origin": self.get_session_origin(&instance_id), "type": "attach", "parent_session_id": session_id, "pid": child.pid })));
+        Ok(())
+    }
+
+    fn handle_warmup_phase_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let default_options = WarmupOptions::default();
+        let warmup_options = WarmupOptions {
+            window_ms: get_option_as_int(options, "window_ms", default_options.window_ms),
+            top_methods: get_option_as_int(options, "top_methods", default_options.top_methods as i64).max(1) as usize,
+            min_similarity: get_option_as_f64(options, "min_similarity", default_options.min_similarity),
+            max_compile_ratio: get_option_as_f64(options, "max_compile_ratio", default_options.max_compile_ratio),
+            stable_windows: get_option_as_int(options, "stable_windows", default_options.stable_windows as i64).max(1) as usize,
+        };
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::INTERACTIVE, move || {
+            let result = detect_warmup(&mut collector.lock().unwrap(), start_time, end_time, &warmup_options).map(|analysis| {
+                json!({ "session_id": session_id, "warmup": analysis })
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    fn handle_pool_starvation_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let default_options = StarvationOptions::default();
+        let starvation_options = StarvationOptions {
+            pool_patterns: match options.get("pool_patterns") {
+                Some(_) => get_option_as_str_array(options, "pool_patterns")?,
+                None => vec![]
+            },
+            min_threads: get_option_as_int(options, "min_threads", default_options.min_threads as i64).max(1) as usize,
+            min_duration_ms: get_option_as_int(options, "min_duration_ms", default_options.min_duration_ms),
+        };
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::INTERACTIVE, move || {
+            let result = detect_pool_starvation(&mut collector.lock().unwrap(), start_time, end_time, &starvation_options).map(|episodes| {
+                json!({ "session_id": session_id, "episodes": episodes })
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    fn handle_spin_loops_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let default_options = SpinLoopOptions::default();
+        let spin_options = SpinLoopOptions {
+            min_cpu_ratio: get_option_as_f64(options, "min_cpu_ratio", default_options.min_cpu_ratio),
+            min_samples: get_option_as_int(options, "min_samples", default_options.min_samples as i64).max(1) as usize,
+            top_depth: get_option_as_int(options, "top_depth", default_options.top_depth as i64).max(1) as usize,
+            max_distinct_frames: get_option_as_int(options, "max_distinct_frames", default_options.max_distinct_frames as i64).max(1) as usize,
+            min_coverage: get_option_as_f64(options, "min_coverage", default_options.min_coverage),
+        };
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        self.task_pool.submit(&session_id.clone(), TaskPriority::INTERACTIVE, move || {
+            let result = detect_spin_loops(&mut collector.lock().unwrap(), start_time, end_time, &spin_options).map(|threads| {
+                json!({ "session_id": session_id, "threads": threads })
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    fn handle_insights_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let mut writer = clone_writer(sender)?;
+        let cmd = cmd.to_string();
+        let session_id = session_id.to_string();
+        let lang = self.get_request_lang(options);
+        self.task_pool.submit(&session_id.clone(), TaskPriority::BACKGROUND, move || {
+            let result = generate_insights(&mut collector.lock().unwrap(), start_time, end_time, &lang).map(|insights| {
+                json!({ "session_id": session_id, "insights": insights })
+            });
+            send_task_result(&mut writer, &cmd, result);
+        });
+        Ok(())
+    }
+
+    //targets: agent地址或者本机进程pid的数组
+    fn handle_record_group_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let name = get_option_as_str(options, "name", "group");
+        let targets: Vec<String> = match options.get("targets").and_then(|x| x.as_array()) {
+            Some(values) => values.iter().map(|x| match x.as_i64() {
+                Some(pid) => pid.to_string(),
+                None => x.as_str().unwrap_or("").to_string()
+            }).collect(),
+            None => return Err(new_invalid_input_error("missing option 'targets'"))
+        };
+        let settings = GroupSettings {
+            record_host_metrics: get_option_as_bool(options, "record_host_metrics", self.config.record_host_metrics),
+            duration_secs: get_option_as_int(options, "duration_secs", 0),
+        };
+        let group = self.start_record_group(name, &targets, settings)?;
+        sender.send_message(&wrap_response(&cmd, &json!(group)));
+        Ok(())
+    }
+
+    fn handle_stop_record_group_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let group_id = get_option_as_str_required(options, "group_id")?;
+        let group = self.stop_record_group(group_id)?;
+        sender.send_message(&wrap_response(&cmd, &json!(group)));
+        Ok(())
+    }
+
+    fn handle_list_record_groups_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str) -> io::Result<()> {
+        let groups: Vec<&RecordGroup> = self.record_groups.values().collect();
+        sender.send_message(&wrap_response(&cmd, &json!({ "record_groups": groups })));
+        Ok(())
+    }
+
+    //指定plugin时只返回该插件
+    fn handle_list_plugins_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let plugins = match options.get("plugin").and_then(|x| x.as_str()) {
+            Some(plugin_name) => vec![self.plugins.get_plugin(plugin_name)?],
+            None => self.plugins.get_plugins().iter().collect()
+        };
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "plugins_dir": self.config.plugins_dir,
+            "plugins": plugins
+        })));
+        Ok(())
+    }
+
+    //执行插件注册的命令，params为查询模板的参数
+    fn handle_plugin_command_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let plugin_name = get_option_as_str_required(options, "plugin")?;
+        let command_name = get_option_as_str_required(options, "command")?;
+        let empty_params = serde_json::Map::new();
+        let params = options.get("params").and_then(|x| x.as_object()).unwrap_or(&empty_params);
+        let collector = self.get_sample_collector(session_id)?;
+        let plugin = self.plugins.get_plugin(plugin_name)?;
+        let command = self.plugins.get_command(plugin_name, command_name)?;
+        let mut result = run_plugin_command(&mut collector.lock().unwrap(), plugin, command, params, self.plugins.get_script_limits())?;
+        result["session_id"] = json!(session_id);
+        sender.send_message(&wrap_response(&cmd, &result));
+        Ok(())
+    }
+
+    //生成插件定义的报告，params为查询模板及脚本的参数
+    fn handle_plugin_report_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let plugin_name = get_option_as_str_required(options, "plugin")?;
+        let report_name = get_option_as_str_required(options, "report")?;
+        let empty_params = serde_json::Map::new();
+        let params = options.get("params").and_then(|x| x.as_object()).unwrap_or(&empty_params);
+        let collector = self.get_sample_collector(session_id)?;
+        let plugin = self.plugins.get_plugin(plugin_name)?;
+        let report = self.plugins.get_report(plugin_name, report_name)?;
+        let mut result = run_plugin_report(&mut collector.lock().unwrap(), plugin, report, params, self.plugins.get_script_limits())?;
+        result["session_id"] = json!(session_id);
+        sender.send_message(&wrap_response(&cmd, &result));
+        Ok(())
+    }
+
+    //按插件的分类规则统计取样
+    fn handle_classify_samples_request(&mut self, sender: &mut Writer<SharedStream>, cmd: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<()> {
+        let session_id = get_option_as_str_required(options, "session_id")?;
+        let plugin_name = get_option_as_str_required(options, "plugin")?;
+        let (start_time, end_time) = self.get_option_time_range(session_id, options)?;
+        let collector = self.get_sample_collector(session_id)?;
+        let plugin = self.plugins.get_plugin(plugin_name)?;
+        let categories = classify_samples(&mut collector.lock().unwrap(), &plugin.classifiers, start_time, end_time)?;
+        sender.send_message(&wrap_response(&cmd, &json!({
+            "session_id": session_id,
+            "plugin": plugin_name,
+            "categories": categories
+        })));
+        Ok(())
+    }
+
+    pub fn startup(&mut self) {
+        self.start_ws_server();
+        self.start_http_server();
+        self.start_samples_watcher();
+    }
+
+    //定期扫描取样根目录(轮询，见 samples_watcher)，其它工具复制或者删除的取样目录会通知到订阅的客户端
+    //同时执行关闭空闲的会话等周期性检查
+    fn start_samples_watcher(&mut self) {
+        let self_ref = self.self_ref.as_ref().unwrap().clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(std::time::Duration::from_millis(WATCH_INTERVAL_MS));
+                let samples_roots = {
+                    let profiler = self_ref.lock().unwrap();
+                    if !profiler.is_running() {
+                        return;
+                    }
+                    profiler.config.samples_roots.clone()
+                };
+                let samples = scan_samples_roots(&samples_roots);
+                let mut profiler = self_ref.lock().unwrap();
+                profiler.on_samples_scanned(samples);
+                profiler.close_idle_sessions();
+                profiler.check_agent_sessions();
+                profiler.check_child_processes();
+                profiler.check_record_groups();
+                profiler.check_disk_space();
+                profiler.check_session_events();
+                profiler.check_triggers();
+                profiler.expire_agent_results();
+            }
+        });
+    }
+
+    //关闭长时间没有查询的取样文件会话，不会关闭连接agent的会话
+    fn close_idle_sessions(&mut self) {
+        let idle_timeout_ms = self.config.session_idle_timeout_secs * 1000;
+        if idle_timeout_ms <= 0 {
+            return;
+        }
+        let now = Local::now().timestamp_millis();
+        let mut idle_sessions = vec![];
+        for (session_id, collector) in self.sample_session_map.iter() {
+            let last_access_time = *self.session_access_times.entry(session_id.to_string()).or_insert(now);
+            if now - last_access_time < idle_timeout_ms {
+                continue;
+            }
+            //多个客户端打开的会话由各自的 close_session 释放
+            if self.session_refcounts.get(session_id).map_or(false, |x| *x > 1) {
+                continue;
+            }
+            //正在执行任务的会话不是空闲的
+            if let Ok(collector) = collector.try_lock() {
+                if collector.get_sample_type() == "file" {
+                    idle_sessions.push(session_id.to_string());
+                }
+            }
+        }
+        for session_id in &idle_sessions {
+            println!("close idle session: {}", session_id);
+            let scope_names = self.get_session_scope_names(session_id);
+            if let Err(e) = self.close_session(session_id) {
+                println!("close idle session failed: {}, err: {}", session_id, e);
+                continue;
+            }
+            self.broadcast_scoped_event(&scope_names, "session_closed", &json!({
+                "session_id": session_id,
+                "reason": "idle"
+            }));
+        }
+    }
+
+    //agent断开后录制停止，只通知一次
+    fn check_agent_sessions(&mut self) {
+        let mut lost_sessions = vec![];
+        for (session_id, collector) in self.sample_session_map.iter() {
+            if self.lost_agent_sessions.contains(session_id) {
+                continue;
+            }
+            if let Ok(collector) = collector.try_lock() {
+                if collector.get_sample_type() == "attach" && collector.is_disconnected() {
+                    lost_sessions.push((session_id.clone(), collector.get_sample_info()));
+                }
+            }
+        }
+        for (session_id, sample_info) in lost_sessions {
+            println!("agent is lost: {}, session: {}", sample_info.agent_addr, session_id);
+            self.notifier.notify(NotifyEvent::AgentLost, &format!("agent is lost: {}, sample dir: {}", sample_info.agent_addr, sample_info.sample_data_dir),
+                                 json!({"session_id": session_id, "agent_addr": sample_info.agent_addr, "sample_data_dir": sample_info.sample_data_dir}));
+            self.lost_agent_sessions.insert(session_id);
+        }
+    }
+
+    //发现录制中的目标进程启动的子JVM，通知前端，配置了auto_attach_children时自动连接其agent
+    fn check_child_processes(&mut self) {
+        if !cfg!(target_os = "linux") {
+            return;
+        }
+        let mut parents = vec![];
+        for (session_id, collector) in self.sample_session_map.iter() {
+            if let Ok(collector) = collector.try_lock() {
+                if collector.get_sample_type() == "attach" && !collector.is_disconnected() && collector.get_target_pid() > 0 {
+                    parents.push((session_id.clone(), collector.get_target_pid()));
+                }
+            }
+        }
+        for (session_id, pid) in parents {
+            let children = match list_child_jvms(pid) {
+                Ok(x) => x,
+                Err(_) => continue
+            };
+            let known_pids = self.known_child_pids.entry(session_id.clone()).or_insert_with(HashSet::new);
+            let new_children: Vec<ProcessInfo> = children.into_iter().filter(|x| known_pids.insert(x.pid)).collect();
+            for child in new_children {
+                println!("found child jvm: {}, agent: {:?}, parent session: {}", child.pid, child.agent_addr, session_id);
+                self.broadcast_session_event(&session_id, "child_jvm_detected", &json!({
+                    "session_id": session_id,
+                    "child": child
+                }));
+                if self.config.auto_attach_children && child.agent_addr.is_some() {
+                    match self.attach_child(&session_id, &child) {
+                        Ok(child_session_id) => self.broadcast_session_event(&child_session_id, "child_session_attached", &json!({
+                            "session_id": child_session_id,
+                            "parent_session_id": session_id,
+                            "pid": child.pid
+                        })),
+                        Err(e) => println!("attach child jvm failed: {}, err: {}", child.pid, e)
+                    }
+                }
+            }
+        }
+    }
+
+    //录制分组到期后一起停止
+    fn check_record_groups(&mut self) {
+        let now = Local::now().timestamp_millis();
+        let expired_groups: Vec<String> = self.record_groups.values()
+            .filter(|x| x.settings.duration_secs > 0 && now - x.start_time >= x.settings.duration_secs * 1000)
+            .map(|x| x.group_id.clone()).collect();
+        for group_id in expired_groups {
+            match self.stop_record_group(&group_id) {
+                Ok(group) => self.broadcast_scoped_event(&get_record_group_scope_names(&group), "record_group_stopped", &json!(group)),
+                Err(e) => println!("stop record group failed: {}, err: {}", group_id, e)
+            }
+        }
+    }
+
+    //可用空间低于阈值时通知一次，恢复到 resume_free_mb 后重新检查
+    fn check_disk_space(&mut self) {
+        if self.config.disk_free_threshold_mb <= 0 {
+            return;
+        }
+        let samples_root = self.config.get_primary_samples_root().to_string();
+        if let Some(free_bytes) = get_free_disk_space(&samples_root) {
+            let free_mb = (free_bytes / 1024 / 1024) as i64;
+            let disk_low = self.config.disk_guard.is_disk_low(free_mb, self.config.disk_free_threshold_mb, self.disk_low);
+            if disk_low && !self.disk_low {
+                println!("disk free space is low: {}MB, samples root: {}", free_mb, samples_root);
+                self.notifier.notify(NotifyEvent::DiskThresholdExceeded, &format!("disk free space is low: {}MB, threshold: {}MB, samples root: {}", free_mb, self.config.disk_free_threshold_mb, samples_root),
+                                     json!({"samples_root": samples_root, "free_mb": free_mb, "threshold_mb": self.config.disk_free_threshold_mb}));
+            }
+            self.disk_low = disk_low;
+            self.guard_recording_sessions(free_mb, disk_low);
+        }
+    }
+
+    //空间不足期间新开始的录制同样处理，降采样的会话在空间恢复后恢复正常取样
+    fn guard_recording_sessions(&mut self, free_mb: i64, disk_low: bool) {
+        let action = self.config.disk_guard.action.clone();
+        if action == DISK_GUARD_NOTIFY {
+            return;
+        }
+        let mut guarded_sessions = vec![];
+        for (session_id, collector) in self.sample_session_map.iter() {
+            if let Ok(mut collector) = collector.try_lock() {
+                if collector.get_sample_type() != "attach" || collector.is_disconnected() {
+                    continue;
+                }
+                let result = if action == DISK_GUARD_STOP && disk_low {
+                    collector.stop_recording_on_disk_low(free_mb).map(|_| "recording_stopped")
+                } else if action == DISK_GUARD_DOWNSAMPLE && disk_low && !collector.get_disk_guard_state().is_downsampling() {
+                    collector.set_disk_low_downsampling(free_mb, self.config.disk_guard.downsample_rate).map(|_| "recording_downsampled")
+                } else if action == DISK_GUARD_DOWNSAMPLE && !disk_low && collector.get_disk_guard_state().is_downsampling() {
+                    collector.set_disk_low_downsampling(free_mb, 0).map(|_| "recording_resumed")
+                } else {
+                    continue;
+                };
+                match result {
+                    Ok(event) => guarded_sessions.push((session_id.clone(), event, collector.get_disk_guard_state().clone())),
+                    Err(e) => println!("guard recording session failed: {}, err: {}", session_id, e)
+                }
+            }
+        }
+        for (session_id, event, state) in guarded_sessions {
+            if event == "recording_stopped" {
+                //不是agent断开，不发送 agent_lost 通知
+                self.lost_agent_sessions.insert(session_id.clone());
+            }
+            self.broadcast_session_event(&session_id, event, &json!({
+                "session_id": session_id,
+                "reason": KIND_DISK_LOW,
+                "free_mb": free_mb,
+                "disk_guard": state
+            }));
+        }
+    }
+
+    //等待agent结果超时的请求返回错误，会话正在使用时下次再检查
+    fn expire_agent_results(&mut self) {
+        let now = Local::now().timestamp_millis();
+        for collector in self.sample_session_map.values() {
+            if let Ok(mut collector) = collector.try_lock() {
+                collector.expire_agent_result_waiters(now);
+            }
+        }
+    }
+
+    //录制中的会话新增的诊断信息推送给可以访问该会话的订阅客户端
+    fn check_session_events(&mut self) {
+        let mut new_events = vec![];
+        for (session_id, collector) in self.sample_session_map.iter() {
+            if let Ok(collector) = collector.try_lock() {
+                if collector.get_sample_type() != "attach" {
+                    continue;
+                }
+                let events = collector.get_session_events();
+                let pushed = self.pushed_session_events.entry(session_id.clone()).or_insert(0);
+                if events.len() > *pushed {
+                    new_events.push((session_id.clone(), events[*pushed..].to_vec()));
+                    *pushed = events.len();
+                }
+            }
+        }
+        for (session_id, events) in new_events {
+            self.broadcast_session_event(&session_id, "session_event", &json!({
+                "session_id": session_id,
+                "events": events
+            }));
+        }
+    }
+
+    //按配置的触发器检查录制中的会话
+    pub fn check_triggers(&mut self) {
+        if self.config.triggers.is_empty() {
+            return;
+        }
+        let mut fired = vec![];
+        for (session_id, collector) in self.sample_session_map.iter() {
+            if let Ok(collector) = collector.try_lock() {
+                if collector.get_sample_type() != "attach" || collector.is_disconnected() {
+                    continue;
+                }
+                let input = TriggerInput {
+                    cpu_usage: collector.get_cgroup_metrics().map(|x| (x.time, x.cpu_usage_us)),
+                    deadlocks: collector.get_deadlocks().len(),
+                    error_events: collector.get_session_events().iter().filter(|x| x.level == ::session_events::LEVEL_ERROR).count(),
+                };
+                let state = self.trigger_states.entry(session_id.clone()).or_insert_with(TriggerState::default);
+                for (trigger, message) in evaluate_triggers(&self.config.triggers, state, &input) {
+                    fired.push((session_id.clone(), trigger, message));
+                }
+            }
+        }
+        for (session_id, trigger, message) in fired {
+            println!("trigger fired: {}, session: {}, {}", trigger, session_id, message);
+            self.notify_trigger_fired(&session_id, &trigger, &message);
+        }
+    }
+
+    //触发器触发时发送通知
+    pub fn notify_trigger_fired(&self, session_id: &str, trigger: &str, message: &str) {
+        self.notifier.notify(NotifyEvent::TriggerFired, message, json!({"session_id": session_id, "trigger": trigger}));
+    }
+
+    fn on_samples_scanned(&mut self, samples: Vec<SampleDirEntry>) {
+        if let Some(old_samples) = &self.history_samples {
+            let (added, removed) = diff_samples(old_samples, &samples);
+            if !added.is_empty() || !removed.is_empty() {
+                println!("samples changed, added: {}, removed: {}", added.len(), removed.len());
+                //受限的令牌只推送允许访问的取样目录
+                self.broadcast_event_with("samples_changed", |identity| {
+                    let visible = |x: &&SampleDirEntry| identity.can_access(&get_sample_dir_scope_names(&x.path));
+                    let added: Vec<&SampleDirEntry> = added.iter().filter(visible).collect();
+                    let removed: Vec<&SampleDirEntry> = removed.iter().filter(visible).collect();
+                    if added.is_empty() && removed.is_empty() {
+                        return None;
+                    }
+                    Some(json!({
+                        "added": added,
+                        "removed": removed
+                    }))
+                });
+            }
+        }
+        self.history_samples = Some(samples);
+    }
+
+    pub fn shutdown(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+}
+
+//取样目录的名称，不匹配上级路径，避免 *order* 之类的模式匹配到所在目录下的所有取样
+fn get_sample_dir_scope_names(sample_data_dir: &str) -> Vec<String> {
+    file_name_string(&string_to_path(sample_data_dir)).into_iter().collect()
+}
+
+//录制分组的目录名称及成员的会话、agent地址和取样目录名称，可以访问任意一个成员即可
+fn get_record_group_scope_names(group: &RecordGroup) -> Vec<String> {
+    let mut names = get_sample_dir_scope_names(&group.group_dir);
+    for member in &group.members {
+        names.push(member.session_id.clone());
+        names.push(member.agent_addr.clone());
+        for sample_data_dir in &member.sample_data_dirs {
+            names.extend(get_sample_dir_scope_names(sample_data_dir));
+        }
+    }
+    names
+}
+
+//线程列表的排序字段，cpu、samples 默认降序
+const THREAD_SORT_FIELDS: &[SortField] = &[("id", false), ("cpu", true), ("samples", true), ("name", false)];
+const SAMPLE_DIR_SORT_FIELDS: &[SortField] = &[("path", false), ("type", false), ("root", false)];
+const METHOD_SORT_FIELDS: &[SortField] = &[("full_name", false), ("method_id", false)];
+//默认最新的在前
+const AUDIT_SORT_FIELDS: &[SortField] = &[("time", true), ("identity", false), ("action", false)];
+const SESSION_EVENT_SORT_FIELDS: &[SortField] = &[("time", false), ("kind", false), ("count", true)];
+//method_calls 按调用次数比较
+const METHOD_CALL_GROUP_SORT_FIELDS: &[SortField] = &[("group_id", false), ("method_calls", true)];
+
+//线程列表的过滤/排序/分页选项
+fn parse_thread_query(options: &serde_json::Map<String, serde_json::Value>, default_page_size: i64) -> io::Result<ThreadQuery> {
+    let page = PageQuery::from_options(options, THREAD_SORT_FIELDS, default_page_size)?;
+    let sort_by = match ThreadSortBy::from_str(&page.sort_by) {
+        Ok(x) => x,
+        Err(_) => return Err(new_invalid_input_error(&format!("invalid sort_by: {}", page.sort_by)))
+    };
+    Ok(ThreadQuery {
+        name_filter: get_option_as_str(options, "name_filter", "").to_string(),
+        state: get_option_as_str(options, "state", "").to_string(),
+        sort_by,
+        desc: page.desc,
+        page: page.page,
+        page_size: page.page_size,
+    })
+}
+
+fn parse_prune_options(options: &serde_json::Map<String, serde_json::Value>, sequenced: bool) -> PruneOptions {
+    PruneOptions {
+        min_samples: get_option_as_int(options, "min_samples", 0),
+        min_percent: get_option_as_f64(options, "min_percent", 0.0),
+        max_depth: get_option_as_int(options, "max_depth", 0) as i32,
+        sequenced,
+    }
+}
+
+//复制客户端连接的发送端，用于后台任务发送结果
+fn clone_writer(sender: &Writer<SharedStream>) -> io::Result<Writer<SharedStream>> {
+    Ok(Writer {
+        stream: sender.stream.try_clone()?,
+        sender: Sender::new(false),
+    })
+}
+
+fn send_task_result(writer: &mut Writer<SharedStream>, cmd: &str, result: io::Result<Value>) {
+    let message = match result {
+        Ok(data) => wrap_response(cmd, &data),
+        Err(e) => {
+            println!("handle request failed: {}, cmd: {}", e, cmd);
+            wrap_error_response(cmd, &e.to_string())
+        }
+    };
+    writer.send_message(&message);
+}
+
+//加载agent后连接，每次重试只短暂持有Profiler锁
+fn attach_jvm_and_connect(profiler: &Arc<Mutex<Profiler>>, target_pid: i64, agent_port: u16, sample_interval_ms: i64) -> io::Result<Value> {
+    let agent_addr = attach_agent(target_pid, agent_port, sample_interval_ms)?;
+    //agent加载后才开始监听，等待连接成功
+    let mut retry = 0;
+    loop {
+        let result = profiler.lock().unwrap().connect_agent(&agent_addr);
+        match result {
+            Ok(instance_id) => {
+                let mut profiler = profiler.lock().unwrap();
+                let collector = profiler.get_sample_collector(&instance_id)?;
+                let mut collector = collector.lock().unwrap();
+                collector.set_target_pid(target_pid);
+                return Ok(json!({ "session_id": instance_id, "origin": profiler.get_session_origin(&instance_id), "type": "attach", "agent_addr": agent_addr, "agent": collector.get_agent_compat() }));
+            }
+            Err(e) => {
+                retry += 1;
+                //agent版本不兼容时不再重试
+                if retry >= ATTACH_CONNECT_RETRIES || e.kind() == ErrorKind::InvalidData {
+                    return Err(new_error(e.kind(), &format!("connect to attached agent failed: {}, err: {}", agent_addr, e)));
+                }
+                thread::sleep(std::time::Duration::from_millis(500));
+            }
+        }
+    }
+}
+
+//任务完成时记录提交时推迟的审计日志
+fn send_audited_task_result(writer: &mut Writer<SharedStream>, cmd: &str, result: io::Result<Value>, audit: Option<PendingAudit>) {
+    if let Some(audit) = audit {
+        audit.finish(Local::now().timestamp_millis(), &result);
+    }
+    send_task_result(writer, cmd, result);
+}
+
+fn canonicalize_sample_dir(sample_data_dir: &str) -> String {
+    canonicalize_path(sample_data_dir)
+}
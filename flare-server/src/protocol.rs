@@ -0,0 +1,122 @@
+
+use serde_json::{json, Value};
+use flare_proto::ws::Capabilities;
+//消息类型及协议版本定义在 flare-proto
+pub use flare_proto::ws::{PROTOCOL_VERSION, MIN_PROTOCOL_VERSION};
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+//服务端支持的命令
+pub const SUPPORTED_COMMANDS: &[&str] = &[
+    "hello",
+    "list_sessions",
+    "history_samples",
+    "open_sample",
+    "attach_jvm",
+    "connect_agent",
+    "connect_runtime",
+    "list_runtimes",
+    "close_session",
+    "close_all_session",
+    "dashboard",
+    "overlay_dashboard",
+    "merge_sessions",
+    "split_sample",
+    "add_marker",
+    "list_markers",
+    "list_intervals",
+    "list_series",
+    "export_sample",
+    "export_metrics",
+    "load_mapping",
+    "list_threads",
+    "expand_node",
+    "add_samples_root",
+    "build_index",
+    "cpu_time",
+    "call_tree",
+    "sequenced_call_tree",
+    "flame_graph",
+    "list_methods_by_filter",
+    "search_slow_method_calls",
+    "database_time",
+    "query",
+    "list_plugins",
+    "plugin_command",
+    "plugin_report",
+    "classify_samples",
+    "start_offcpu_sampling",
+    "offcpu_stacks",
+    "cgroup_metrics",
+    "host_metrics",
+    "list_child_processes",
+    "attach_child",
+    "warmup_phase",
+    "pool_starvation",
+    "spin_loops",
+    "insights",
+    "record_group",
+    "stop_record_group",
+    "list_record_groups",
+    "detect_deadlocks",
+    "list_deadlocks",
+    "thread_dump",
+    "list_thread_dumps",
+    "get_thread_dump",
+    "heap_histogram",
+    "list_heap_histograms",
+    "diff_heap_histograms",
+    "allocation_rate",
+    "deopt_stats",
+    "class_loader_leaks",
+    "ingest_filter",
+    "combined_view",
+    "save_view",
+    "list_views",
+    "generate_report",
+    "set_baseline",
+    "list_baselines",
+    "compare_to_baseline",
+    "schema",
+    "audit_log",
+    "session_events",
+    "flush_policy",
+    "storage_usage",
+    "thread_handles",
+    "self_profile",
+    "reload_config",
+];
+
+//可选功能: (名称, 是否支持)
+pub const FEATURES: &[(&str, bool)] = &[
+    ("compression", false),
+    ("binary", false),
+    //open_sample_progress, samples_changed 等服务端推送事件
+    ("push", true),
+];
+
+pub fn is_feature_supported(feature: &str) -> bool {
+    FEATURES.iter().any(|&(name, supported)| name == feature && supported)
+}
+
+//连接建立后服务端发送的第一个消息
+pub fn get_capabilities() -> Value {
+    let mut features = serde_json::Map::new();
+    for &(name, supported) in FEATURES {
+        features.insert(name.to_string(), json!(supported));
+    }
+    json!(Capabilities {
+        protocol_version: PROTOCOL_VERSION,
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        server_version: SERVER_VERSION.to_string(),
+        commands: SUPPORTED_COMMANDS.iter().map(|x| x.to_string()).collect(),
+        features,
+    })
+}
+
+//根据前端的协议版本和请求的功能协商，返回双方都支持的功能
+pub fn negotiate_features(requested_features: &[&str]) -> Vec<String> {
+    requested_features.iter()
+        .filter(|x| is_feature_supported(x))
+        .map(|x| x.to_string())
+        .collect()
+}
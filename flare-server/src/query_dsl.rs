@@ -0,0 +1,578 @@
+
+//简单的查询语言，用于界面上没有提供的临时分析，例如:
+//  top 10 methods where thread like 'worker%' between 1570000000000 and 1570000060000
+//  top 5 self_methods where method like 'com.example.%' and state = 'RUNNABLE' order by cpu
+//  top 20 threads during 'warmup'
+//  top 5 metrics where metric like 'cgroup_%' between +0s and +60s order by max
+//
+//语法(关键字不区分大小写):
+//  top <n> <methods|self_methods|threads|states|metrics>
+//      [where <condition> [and <condition>]...]
+//      [between <time> and <time> | during '<interval>']
+//      [order by <samples|cpu|duration|avg|min|max|last>]
+//  condition: thread like '<pattern>' | thread = '<name>' | thread_id = <id> | state = '<state>' | method like '<pattern>'
+//      | metric like '<pattern>' | metric = '<name>'
+//  time: 绝对时间(ms)，或者相对于开始录制时间的偏移: +30s, +5m, +500ms，超出范围、开始时间大于结束时间
+//      或者与录制时间范围没有重叠时返回错误
+//  pattern: SQL LIKE, % 匹配任意字符串，_ 匹配单个字符
+//methods 统计包含该方法的取样(调用栈中重复的方法只计一次)，self_methods 只统计栈顶方法
+//method like 过滤包含匹配方法的取样，对于 methods/self_methods 同时只返回匹配的方法
+//metrics 统计时间范围内的指标(cgroup/主机/JVM等)，samples 为数据点个数，默认按平均值排序
+//  只能使用 metric 条件及 samples/avg/min/max/last 排序，其它目标不能使用这些条件及排序
+
+use ::sample::*;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use utils::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueryTarget {
+    Methods,
+    SelfMethods,
+    Threads,
+    States,
+    Metrics,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueryMetric {
+    Samples,
+    Cpu,
+    Duration,
+    Avg,
+    Min,
+    Max,
+    Last,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum QueryCondition {
+    ThreadLike(String),
+    ThreadName(String),
+    ThreadId(i64),
+    State(String),
+    MethodLike(String),
+    MetricLike(String),
+    MetricName(String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum QueryTimeRange {
+    All,
+    //(start, end) 偏移量相对于开始录制时间
+    Between(QueryTime, QueryTime),
+    During(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueryTime {
+    Absolute(i64),
+    Relative(i64),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Query {
+    pub limit: usize,
+    pub target: QueryTarget,
+    pub conditions: Vec<QueryCondition>,
+    pub time_range: QueryTimeRange,
+    pub order_by: QueryMetric,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QueryRow {
+    pub name: String,
+    pub samples: i64,
+    //ms
+    pub cpu_time: i64,
+    //ms
+    pub duration: i64,
+    //占所有匹配取样的比例
+    pub ratio: f64,
+    //metrics 查询的指标值
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metric: Option<MetricStats>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct MetricStats {
+    pub avg: f64,
+    pub min: i64,
+    pub max: i64,
+    pub last: i64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QueryResult {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub total_samples: i64,
+    pub rows: Vec<QueryRow>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Word(String),
+    Number(i64),
+    Str(String),
+    Symbol(char),
+}
+
+fn tokenize(query: &str) -> io::Result<Vec<Token>> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    //两个引号表示引号本身
+                    Some(x) if *x == c && chars.get(i + 1) == Some(&c) => { value.push(c); i += 2; }
+                    Some(x) if *x == c => { i += 1; break; }
+                    Some(x) => { value.push(*x); i += 1; }
+                    None => return Err(new_invalid_input_error(&format!("unterminated string: {}", value))),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let value: String = chars[start..i].iter().collect();
+            let number = value.parse::<i64>().map_err(|_| new_invalid_input_error(&format!("invalid number: {}", value)))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else if c == '=' || c == '+' {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(new_invalid_input_error(&format!("unexpected character '{}' at {}", c, i)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_word(&self) -> Option<&str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(x)) => Some(x.as_str()),
+            _ => None
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect_word(&mut self, word: &str) -> io::Result<()> {
+        match self.next() {
+            Some(Token::Word(ref x)) if x == word => Ok(()),
+            token => Err(new_invalid_input_error(&format!("expect '{}' but found {}", word, format_token(&token))))
+        }
+    }
+
+    fn next_word(&mut self) -> io::Result<String> {
+        match self.next() {
+            Some(Token::Word(x)) => Ok(x),
+            token => Err(new_invalid_input_error(&format!("expect keyword but found {}", format_token(&token))))
+        }
+    }
+
+    fn next_number(&mut self) -> io::Result<i64> {
+        match self.next() {
+            Some(Token::Number(x)) => Ok(x),
+            token => Err(new_invalid_input_error(&format!("expect number but found {}", format_token(&token))))
+        }
+    }
+
+    fn next_str(&mut self) -> io::Result<String> {
+        match self.next() {
+            Some(Token::Str(x)) => Ok(x),
+            token => Err(new_invalid_input_error(&format!("expect quoted string but found {}", format_token(&token))))
+        }
+    }
+
+    fn next_op(&mut self) -> io::Result<String> {
+        match self.next() {
+            Some(Token::Symbol('=')) => Ok("=".to_string()),
+            Some(Token::Word(ref x)) if x == "like" => Ok("like".to_string()),
+            token => Err(new_invalid_input_error(&format!("expect '=' or 'like' but found {}", format_token(&token))))
+        }
+    }
+
+    fn parse_condition(&mut self) -> io::Result<QueryCondition> {
+        let field = self.next_word()?;
+        let op = self.next_op()?;
+        match (field.as_str(), op.as_str()) {
+            ("thread", "like") => Ok(QueryCondition::ThreadLike(self.next_str()?)),
+            ("thread", "=") => Ok(QueryCondition::ThreadName(self.next_str()?)),
+            ("thread_id", "=") => Ok(QueryCondition::ThreadId(self.next_number()?)),
+            ("state", "=") => Ok(QueryCondition::State(self.next_str()?.to_uppercase())),
+            ("method", "like") => Ok(QueryCondition::MethodLike(self.next_str()?)),
+            ("metric", "like") => Ok(QueryCondition::MetricLike(self.next_str()?)),
+            ("metric", "=") => Ok(QueryCondition::MetricName(self.next_str()?)),
+            _ => Err(new_invalid_input_error(&format!("unsupported condition: {} {}", field, op)))
+        }
+    }
+
+    fn parse_time(&mut self) -> io::Result<QueryTime> {
+        match self.next() {
+            Some(Token::Number(x)) => Ok(QueryTime::Absolute(x)),
+            Some(Token::Symbol('+')) => {
+                let value = self.next_number()?;
+                let unit = self.next_word()?;
+                let scale = match unit.as_str() {
+                    "ms" => 1,
+                    "s" => 1000,
+                    "m" => 60_000,
+                    "h" => 3_600_000,
+                    _ => return Err(new_invalid_input_error(&format!("unsupported time unit: {}", unit)))
+                };
+                value.checked_mul(scale).map(QueryTime::Relative)
+                    .ok_or_else(|| new_invalid_input_error(&format!("time is out of range: +{}{}", value, unit)))
+            }
+            token => Err(new_invalid_input_error(&format!("expect time but found {}", format_token(&token))))
+        }
+    }
+}
+
+fn format_token(token: &Option<Token>) -> String {
+    match token {
+        Some(Token::Word(x)) => format!("'{}'", x),
+        Some(Token::Number(x)) => format!("{}", x),
+        Some(Token::Str(x)) => format!("'{}'", x),
+        Some(Token::Symbol(x)) => format!("'{}'", x),
+        None => "end of query".to_string(),
+    }
+}
+
+pub fn parse_query(query: &str) -> io::Result<Query> {
+    let mut parser = Parser { tokens: tokenize(query)?, pos: 0 };
+    parser.expect_word("top")?;
+    let limit = parser.next_number()? as usize;
+    let target = match parser.next_word()?.as_str() {
+        "methods" => QueryTarget::Methods,
+        "self_methods" => QueryTarget::SelfMethods,
+        "threads" => QueryTarget::Threads,
+        "states" => QueryTarget::States,
+        "metrics" => QueryTarget::Metrics,
+        x => return Err(new_invalid_input_error(&format!("unsupported query target: {}, expect methods, self_methods, threads, states or metrics", x)))
+    };
+    let mut query = Query {
+        limit,
+        target,
+        conditions: vec![],
+        time_range: QueryTimeRange::All,
+        order_by: if target == QueryTarget::Metrics { QueryMetric::Avg } else { QueryMetric::Samples },
+    };
+
+    while let Some(word) = parser.peek_word().map(|x| x.to_string()) {
+        parser.pos += 1;
+        match word.as_str() {
+            "where" => {
+                query.conditions.push(parser.parse_condition()?);
+                while parser.peek_word() == Some("and") {
+                    parser.pos += 1;
+                    query.conditions.push(parser.parse_condition()?);
+                }
+            }
+            "between" => {
+                let start = parser.parse_time()?;
+                parser.expect_word("and")?;
+                let end = parser.parse_time()?;
+                query.time_range = QueryTimeRange::Between(start, end);
+            }
+            "during" => {
+                query.time_range = QueryTimeRange::During(parser.next_str()?);
+            }
+            "order" => {
+                parser.expect_word("by")?;
+                query.order_by = match parser.next_word()?.as_str() {
+                    "samples" => QueryMetric::Samples,
+                    "cpu" => QueryMetric::Cpu,
+                    "duration" => QueryMetric::Duration,
+                    "avg" => QueryMetric::Avg,
+                    "min" => QueryMetric::Min,
+                    "max" => QueryMetric::Max,
+                    "last" => QueryMetric::Last,
+                    x => return Err(new_invalid_input_error(&format!("unsupported order by: {}", x)))
+                };
+            }
+            _ => return Err(new_invalid_input_error(&format!("unexpected keyword: '{}'", word)))
+        }
+    }
+    if parser.pos < parser.tokens.len() {
+        return Err(new_invalid_input_error(&format!("unexpected {}", format_token(&parser.tokens.get(parser.pos).cloned()))));
+    }
+    check_query(&query)?;
+    Ok(query)
+}
+
+//指标与取样的条件及排序不能混用
+fn check_query(query: &Query) -> io::Result<()> {
+    let is_metrics = query.target == QueryTarget::Metrics;
+    for cond in &query.conditions {
+        let is_metric_cond = match cond {
+            QueryCondition::MetricLike(_) | QueryCondition::MetricName(_) => true,
+            _ => false
+        };
+        if is_metric_cond != is_metrics {
+            return Err(new_invalid_input_error(&format!("condition {:?} is not supported by target {:?}", cond, query.target)));
+        }
+    }
+    let is_metric_order = match query.order_by {
+        QueryMetric::Samples => is_metrics,
+        QueryMetric::Cpu | QueryMetric::Duration => false,
+        QueryMetric::Avg | QueryMetric::Min | QueryMetric::Max | QueryMetric::Last => true,
+    };
+    if is_metric_order != is_metrics {
+        return Err(new_invalid_input_error(&format!("order by {:?} is not supported by target {:?}", query.order_by, query.target)));
+    }
+    Ok(())
+}
+
+//SQL LIKE
+pub fn like_match(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    //动态规划: matched[j] 表示 value[..i] 与 pattern[..j] 是否匹配
+    let mut matched = vec![false; pattern.len() + 1];
+    matched[0] = true;
+    for j in 0..pattern.len() {
+        matched[j + 1] = matched[j] && pattern[j] == '%';
+    }
+    for c in &value {
+        let mut next = vec![false; pattern.len() + 1];
+        for j in 0..pattern.len() {
+            next[j + 1] = match pattern[j] {
+                '%' => next[j] || matched[j + 1],
+                '_' => matched[j],
+                p => matched[j] && p == *c,
+            };
+        }
+        matched = next;
+    }
+    matched[pattern.len()]
+}
+
+#[derive(Default)]
+struct Stats {
+    samples: i64,
+    cpu_time: i64,
+    duration: i64,
+}
+
+impl Stats {
+    fn add(&mut self, thread_data: &ThreadData) {
+        self.samples += 1;
+        self.cpu_time += thread_data.cpu_time_delta;
+        self.duration += thread_data.self_duration;
+    }
+}
+
+fn resolve_time(sample_info: &SampleInfo, time: &QueryTime) -> io::Result<i64> {
+    match time {
+        QueryTime::Absolute(x) => Ok(*x),
+        QueryTime::Relative(x) => sample_info.record_start_time.checked_add(*x)
+            .ok_or_else(|| new_invalid_input_error(&format!("time out of range: +{}ms", x))),
+    }
+}
+
+//开始时间不能大于结束时间，并且必须与录制时间范围有重叠
+fn resolve_time_range(sample_info: &SampleInfo, start: &QueryTime, end: &QueryTime) -> io::Result<(i64, i64)> {
+    let start_time = resolve_time(sample_info, start)?;
+    let end_time = resolve_time(sample_info, end)?;
+    if start_time > end_time {
+        return Err(new_invalid_input_error(&format!("start time {} is after end time {}", start_time, end_time)));
+    }
+    if end_time < sample_info.record_start_time || start_time > sample_info.last_record_time {
+        return Err(new_invalid_input_error(&format!("time range [{}, {}] is outside the recording [{}, {}]",
+            start_time, end_time, sample_info.record_start_time, sample_info.last_record_time)));
+    }
+    Ok((start_time, end_time))
+}
+
+pub fn execute_query(collector: &mut SampleCollector, query: &Query) -> io::Result<QueryResult> {
+    let sample_info = collector.get_sample_info();
+    let (start_time, end_time) = match &query.time_range {
+        QueryTimeRange::All => (sample_info.record_start_time, sample_info.last_record_time),
+        QueryTimeRange::Between(start, end) => resolve_time_range(&sample_info, start, end)?,
+        QueryTimeRange::During(name) => collector.get_interval_time_range(name, 0)?,
+    };
+    if query.target == QueryTarget::Metrics {
+        return execute_metric_query(collector, query, start_time, end_time);
+    }
+
+    let mut threads = collector.get_threads()?;
+    threads.retain(|thread| query.conditions.iter().all(|cond| match cond {
+        QueryCondition::ThreadLike(pattern) => like_match(&thread.name, pattern),
+        QueryCondition::ThreadName(name) => &thread.name == name,
+        QueryCondition::ThreadId(id) => thread.id == *id,
+        _ => true
+    }));
+    threads.sort_by_key(|x| x.id);
+    let states: Vec<&String> = query.conditions.iter().filter_map(|x| match x { QueryCondition::State(x) => Some(x), _ => None }).collect();
+    let method_patterns: Vec<&String> = query.conditions.iter().filter_map(|x| match x { QueryCondition::MethodLike(x) => Some(x), _ => None }).collect();
+
+    //方法名称是否匹配，缓存结果
+    let mut method_matches: HashMap<i64, bool> = HashMap::new();
+    let mut is_method_match = |collector: &mut SampleCollector, method: i64| -> bool {
+        if method_patterns.is_empty() {
+            return true;
+        }
+        if let Some(matched) = method_matches.get(&method) {
+            return *matched;
+        }
+        let name = collector.get_method_name(method);
+        let matched = method_patterns.iter().all(|pattern| like_match(&name, pattern));
+        method_matches.insert(method, matched);
+        matched
+    };
+
+    let mut total_samples = 0;
+    let mut stats_map: HashMap<String, Stats> = HashMap::new();
+    let mut method_stats: HashMap<i64, Stats> = HashMap::new();
+    for thread in &threads {
+        let thread_data_vec = match collector.load_thread_samples(thread.id, start_time, end_time) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("load thread samples failed, thread: {}, error: {}", thread.id, e);
+                continue;
+            }
+        };
+        for thread_data in &thread_data_vec {
+            if thread_data.sample_time < start_time || thread_data.sample_time > end_time {
+                continue;
+            }
+            if !states.is_empty() && !states.iter().any(|x| **x == thread_data.state) {
+                continue;
+            }
+            if !method_patterns.is_empty() && !thread_data.stacktrace.iter().any(|x| is_method_match(collector, *x)) {
+                continue;
+            }
+            total_samples += 1;
+            match query.target {
+                QueryTarget::Threads => stats_map.entry(format!("{} [{}]", thread.name, thread.id)).or_default().add(thread_data),
+                QueryTarget::States => stats_map.entry(thread_data.state.clone()).or_default().add(thread_data),
+                QueryTarget::SelfMethods => {
+                    if let Some(method) = thread_data.stacktrace.first() {
+                        if is_method_match(collector, *method) {
+                            method_stats.entry(*method).or_default().add(thread_data);
+                        }
+                    }
+                }
+                QueryTarget::Methods => {
+                    let mut visited = HashSet::new();
+                    for method in &thread_data.stacktrace {
+                        if visited.insert(*method) && is_method_match(collector, *method) {
+                            method_stats.entry(*method).or_default().add(thread_data);
+                        }
+                    }
+                }
+                //已经由 execute_metric_query 处理
+                QueryTarget::Metrics => {}
+            }
+        }
+    }
+    for (method, stats) in method_stats {
+        stats_map.insert(collector.get_method_name(method), stats);
+    }
+
+    let mut rows: Vec<QueryRow> = stats_map.into_iter().map(|(name, stats)| QueryRow {
+        name,
+        samples: stats.samples,
+        cpu_time: stats.cpu_time / 1_000_000,
+        duration: stats.duration,
+        ratio: if total_samples > 0 { stats.samples as f64 / total_samples as f64 } else { 0.0 },
+        metric: None,
+    }).collect();
+    sort_rows(&mut rows, query);
+    Ok(QueryResult {
+        start_time,
+        end_time,
+        total_samples,
+        rows,
+    })
+}
+
+//指标查询: 每个匹配的指标一行，total_samples 为所有匹配指标的数据点个数
+fn execute_metric_query(collector: &mut SampleCollector, query: &Query, start_time: i64, end_time: i64) -> io::Result<QueryResult> {
+    let mut total_samples = 0;
+    let mut rows = vec![];
+    for name in collector.get_metric_names() {
+        let matched = query.conditions.iter().all(|cond| match cond {
+            QueryCondition::MetricLike(pattern) => like_match(&name, pattern),
+            QueryCondition::MetricName(x) => &name == x,
+            _ => true
+        });
+        if !matched {
+            continue;
+        }
+        let values: Vec<i64> = collector.get_metric_points(&name, start_time, end_time).iter()
+            .filter(|(time, _)| *time >= start_time).map(|(_, value)| *value).collect();
+        if values.is_empty() {
+            continue;
+        }
+        total_samples += values.len() as i64;
+        let sum: i128 = values.iter().map(|x| *x as i128).sum();
+        rows.push(QueryRow {
+            name,
+            samples: values.len() as i64,
+            cpu_time: 0,
+            duration: 0,
+            ratio: 0.0,
+            metric: Some(MetricStats {
+                avg: sum as f64 / values.len() as f64,
+                min: *values.iter().min().unwrap(),
+                max: *values.iter().max().unwrap(),
+                last: *values.last().unwrap(),
+            }),
+        });
+    }
+    for row in &mut rows {
+        row.ratio = row.samples as f64 / total_samples as f64;
+    }
+    sort_rows(&mut rows, query);
+    Ok(QueryResult {
+        start_time,
+        end_time,
+        total_samples,
+        rows,
+    })
+}
+
+fn sort_rows(rows: &mut Vec<QueryRow>, query: &Query) {
+    let order_by = query.order_by;
+    let key = |x: &QueryRow| -> f64 {
+        match (order_by, &x.metric) {
+            (QueryMetric::Samples, _) => x.samples as f64,
+            (QueryMetric::Cpu, _) => x.cpu_time as f64,
+            (QueryMetric::Duration, _) => x.duration as f64,
+            (QueryMetric::Avg, Some(m)) => m.avg,
+            (QueryMetric::Min, Some(m)) => m.min as f64,
+            (QueryMetric::Max, Some(m)) => m.max as f64,
+            (QueryMetric::Last, Some(m)) => m.last as f64,
+            (_, None) => 0.0,
+        }
+    };
+    rows.sort_by(|a, b| key(b).partial_cmp(&key(a)).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.name.cmp(&b.name)));
+    rows.truncate(query.limit);
+}
@@ -0,0 +1,152 @@
+
+//每个websocket连接的命令限流，避免失控的前端拖垮共享的分析服务
+//  commands_per_sec/burst: 令牌桶，每秒补充 commands_per_sec 个令牌，最多积累 burst 个，每个命令消耗一个
+//  max_heavy_queries: 每个连接同时在分析线程池中执行的重查询(火焰图、导出、报告等)数量
+//超过限制的命令不执行，返回失败响应，data 为 {"message": "...", "throttled": true, "retry_after_ms": n}
+//值为0表示不限制，默认不限制
+
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use websocket::OwnedMessage;
+use flare_proto::ws::{FlareResponse, RESULT_FAILURE};
+use command_recorder;
+
+//在分析线程池中执行的命令
+pub const HEAVY_COMMANDS: &[&str] = &[
+    "split_sample",
+    "export_sample",
+    "generate_report",
+    "export_metrics",
+    "build_index",
+    "flame_graph",
+    "warmup_phase",
+    "pool_starvation",
+    "spin_loops",
+    "insights",
+    "thread_dump",
+    "heap_histogram",
+    "compare_to_baseline",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub commands_per_sec: f64,
+    //0表示与 commands_per_sec 相同
+    #[serde(default)]
+    pub burst: u32,
+    #[serde(default)]
+    pub max_heavy_queries: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ThrottledData {
+    pub message: String,
+    pub throttled: bool,
+    pub retry_after_ms: i64,
+}
+
+pub struct ConnectionLimiter {
+    config: RateLimitConfig,
+    tokens: f64,
+    last_refill_time: i64,
+    //本连接提交到分析线程池、尚未完成的任务数
+    running_heavy_queries: Arc<AtomicUsize>,
+    throttled_count: u64,
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: &RateLimitConfig, now: i64) -> ConnectionLimiter {
+        let mut limiter = ConnectionLimiter {
+            config: config.clone(),
+            tokens: 0.0,
+            last_refill_time: now,
+            running_heavy_queries: Arc::new(AtomicUsize::new(0)),
+            throttled_count: 0,
+        };
+        limiter.tokens = limiter.get_burst();
+        limiter
+    }
+
+    fn get_burst(&self) -> f64 {
+        if self.config.burst > 0 { self.config.burst as f64 } else { self.config.commands_per_sec.max(1.0) }
+    }
+
+    fn refill(&mut self, now: i64) {
+        let elapsed = (now - self.last_refill_time).max(0) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * self.config.commands_per_sec).min(self.get_burst());
+        self.last_refill_time = now;
+    }
+
+    //允许执行时返回None，否则返回限流信息
+    pub fn check(&mut self, cmd: &str, now: i64) -> Option<ThrottledData> {
+        let result = self.check_quota(cmd, now);
+        if result.is_some() {
+            self.throttled_count += 1;
+        }
+        result
+    }
+
+    fn check_quota(&mut self, cmd: &str, now: i64) -> Option<ThrottledData> {
+        if self.config.max_heavy_queries > 0 && HEAVY_COMMANDS.contains(&cmd) {
+            let running = self.get_running_heavy_queries();
+            if running >= self.config.max_heavy_queries {
+                return Some(ThrottledData {
+                    message: format!("too many concurrent heavy queries: {}, limit: {}", running, self.config.max_heavy_queries),
+                    throttled: true,
+                    retry_after_ms: 1000,
+                });
+            }
+        }
+        if self.config.commands_per_sec > 0.0 {
+            self.refill(now);
+            if self.tokens < 1.0 {
+                let retry_after_ms = ((1.0 - self.tokens) * 1000.0 / self.config.commands_per_sec).ceil() as i64;
+                return Some(ThrottledData {
+                    message: format!("rate limit exceeded: {} commands/sec", self.config.commands_per_sec),
+                    throttled: true,
+                    retry_after_ms: retry_after_ms.max(1),
+                });
+            }
+            self.tokens -= 1.0;
+        }
+        None
+    }
+
+    pub fn get_running_heavy_queries(&self) -> usize {
+        self.running_heavy_queries.load(Ordering::SeqCst)
+    }
+
+    pub fn get_throttled_count(&self) -> u64 {
+        self.throttled_count
+    }
+
+    pub fn get_heavy_query_counter(&self) -> Arc<AtomicUsize> {
+        self.running_heavy_queries.clone()
+    }
+}
+
+//当前线程正在处理的请求所属连接的重查询计数，提交到分析线程池的任务按此计数
+thread_local! {
+    static HEAVY_QUERY_COUNTER: RefCell<Option<Arc<AtomicUsize>>> = RefCell::new(None);
+}
+
+pub fn set_heavy_query_counter(counter: Option<Arc<AtomicUsize>>) {
+    HEAVY_QUERY_COUNTER.with(|x| *x.borrow_mut() = counter);
+}
+
+pub fn get_heavy_query_counter() -> Option<Arc<AtomicUsize>> {
+    HEAVY_QUERY_COUNTER.with(|x| x.borrow().clone())
+}
+
+pub fn wrap_throttled_response(cmd: &str, data: ThrottledData) -> OwnedMessage {
+    let response = FlareResponse {
+        result: RESULT_FAILURE.to_string(),
+        cmd: cmd.to_string(),
+        data: Box::new(data),
+    };
+    let text = serde_json::to_string(&response).unwrap();
+    command_recorder::record_response(&text);
+    OwnedMessage::Text(text)
+}
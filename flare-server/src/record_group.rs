@@ -0,0 +1,132 @@
+
+//录制分组：同时录制多个进程(如一组微服务)，使用相同的设置一起开始、一起停止，
+//所有成员的取样目录保存在同一个分组目录下，分组信息保存在 group.json
+//  <samples_root>/group-<name>-<time>/group.json
+//  <samples_root>/group-<name>-<time>/<agent_addr>-<time>/...
+
+use std::io;
+use chrono::Local;
+use process_tree::read_process_info;
+use cgroup_metrics::PROC_ROOT;
+use utils::*;
+use sample_path::{path_to_string, file_name_string};
+use std::path::{Path, PathBuf};
+
+pub const GROUP_FILE: &str = "group.json";
+pub const GROUP_DIR_PREFIX: &str = "group-";
+
+//所有成员共用的录制设置
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct GroupSettings {
+    #[serde(default)]
+    pub record_host_metrics: bool,
+    //录制时长，到期后一起停止，0表示手动停止
+    #[serde(default)]
+    pub duration_secs: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct GroupMember {
+    pub agent_addr: String,
+    //-1表示未知
+    pub pid: i64,
+    #[serde(default)]
+    pub session_id: String,
+    //停止录制时更新，录制超过滚动周期时有多个目录
+    #[serde(default)]
+    pub sample_data_dirs: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RecordGroup {
+    pub group_id: String,
+    pub name: String,
+    pub group_dir: String,
+    pub settings: GroupSettings,
+    pub start_time: i64,
+    //0表示正在录制
+    pub end_time: i64,
+    pub members: Vec<GroupMember>,
+}
+
+impl RecordGroup {
+    pub fn new(group_id: &str, name: &str, samples_root: &str, settings: GroupSettings) -> io::Result<RecordGroup> {
+        let now = Local::now();
+        let group_dir = format!("{}/{}{}-{}", samples_root, GROUP_DIR_PREFIX, sanitize_file_name(name), now.format("%Y%m%dT%H%M%S"));
+        std::fs::create_dir_all(&group_dir)?;
+        Ok(RecordGroup {
+            group_id: group_id.to_string(),
+            name: name.to_string(),
+            group_dir,
+            settings,
+            start_time: now.timestamp_millis(),
+            end_time: 0,
+            members: vec![],
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.end_time == 0
+    }
+
+    //按agent地址查找成员的取样目录
+    pub fn update_member_dirs(&mut self) {
+        let mut dirs: Vec<String> = list_member_dirs(&self.group_dir).iter().map(|x| path_to_string(x)).collect();
+        dirs.sort();
+        for member in &mut self.members {
+            let prefix = format!("{}/{}-", self.group_dir, sanitize_file_name(&member.agent_addr));
+            member.sample_data_dirs = dirs.iter().filter(|x| x.starts_with(&prefix)).cloned().collect();
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(format!("{}/{}", self.group_dir, GROUP_FILE), json.as_bytes())
+    }
+
+    pub fn load(group_dir: &str) -> io::Result<RecordGroup> {
+        let json = std::fs::read_to_string(format!("{}/{}", group_dir, GROUP_FILE))?;
+        let mut group = serde_json::from_str::<RecordGroup>(&json)?;
+        //分组目录可能被移动过
+        group.group_dir = group_dir.to_string();
+        Ok(group)
+    }
+}
+
+pub fn is_group_dir<P: AsRef<Path>>(dir: P) -> bool {
+    std::fs::metadata(dir.as_ref().join(GROUP_FILE)).map(|x| x.is_file()).unwrap_or(false)
+}
+
+//分组目录下的成员取样目录
+pub fn list_member_dirs<P: AsRef<Path>>(group_dir: P) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Ok(paths) = std::fs::read_dir(group_dir) {
+        for entry in paths.filter_map(Result::ok) {
+            let path = entry.path();
+            if !path.is_dir() || file_name_string(&path).map(|x| x.starts_with(".")).unwrap_or(true) {
+                continue;
+            }
+            dirs.push(path);
+        }
+    }
+    dirs
+}
+
+//录制目标：agent地址(host:port)，或者加载了flare-agent的本机进程pid
+pub fn resolve_group_target(target: &str) -> io::Result<GroupMember> {
+    match target.parse::<i64>() {
+        Ok(pid) => {
+            let info = read_process_info(PROC_ROOT, pid)?;
+            match info.agent_addr {
+                Some(agent_addr) => Ok(GroupMember { agent_addr, pid, session_id: String::new(), sample_data_dirs: vec![] }),
+                None => Err(new_invalid_input_error(&format!("flare agent is not loaded in process: {}", pid)))
+            }
+        }
+        Err(_) => {
+            if target.is_empty() || !target.contains(':') {
+                return Err(new_invalid_input_error(&format!("invalid record target: {}", target)));
+            }
+            Ok(GroupMember { agent_addr: target.to_string(), pid: -1, session_id: String::new(), sample_data_dirs: vec![] })
+        }
+    }
+}
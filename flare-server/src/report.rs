@@ -0,0 +1,397 @@
+
+//报告：把一个时间范围的概要、热点方法、火焰图、GC暂停及问题洞察生成为独立的HTML文件(不依赖外部资源)，
+//保存在会话目录下，用于附加到工单或者分享给没有安装flare的人
+//  reports/report-<start_time>-<end_time>.html
+//  reports/report-<start_time>-<end_time>.md  Markdown格式，火焰图保存为同目录下的 -flamegraph.svg，用于粘贴到wiki
+//  reports/report-<start_time>-<end_time>.pdf 由配置的HTML转PDF工具从HTML报告生成
+
+use ::sample::*;
+use ::profiler::Profiler;
+use std::io;
+use std::sync::{Arc, Mutex};
+use chrono::{Local, TimeZone};
+use gc::*;
+use insights::{generate_insights, Insight};
+use metrics_export::{get_method_stats, MethodStats};
+use tree::PruneOptions;
+use std::io::ErrorKind;
+use std::process::Command;
+use utils::{new_error, new_invalid_input_error};
+use i18n::*;
+
+pub const REPORT_DIR: &str = "reports";
+const GC_CHART_WIDTH: i64 = 900;
+const GC_CHART_HEIGHT: i64 = 120;
+
+pub const REPORT_FORMATS: &[&str] = &["html", "markdown", "pdf"];
+
+#[derive(Clone, Debug)]
+pub struct ReportOptions {
+    //html, markdown, pdf
+    pub format: String,
+    //-1表示不限制
+    pub start_time: i64,
+    pub end_time: i64,
+    pub top_methods: usize,
+    pub flame_graph_width: usize,
+    //HTML转PDF的工具(如 wkhtmltopdf)，以 <tool> <input.html> <output.pdf> 方式调用，为空时不支持PDF
+    pub pdf_tool: String,
+    //报告及问题洞察的语言: en, zh
+    pub lang: String,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            format: "html".to_string(),
+            start_time: -1,
+            end_time: -1,
+            top_methods: 20,
+            flame_graph_width: 1200,
+            pdf_tool: String::new(),
+            lang: LANG_EN.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ReportMethod {
+    pub method_name: String,
+    pub self_samples: i64,
+    pub samples: i64,
+    //ns
+    pub self_cpu_time: i64,
+}
+
+//报告中的数据，各种格式的报告使用同一份数据渲染
+#[derive(Serialize, Clone, Debug)]
+pub struct ReportData {
+    pub lang: String,
+    pub agent_addr: String,
+    pub sample_data_dir: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub sample_interval: i64,
+    pub threads: usize,
+    pub samples: i64,
+    pub cpu_time: i64,
+    pub top_methods: Vec<ReportMethod>,
+    pub gc_pauses: Vec<GcPause>,
+    pub gc_summary: GcSummary,
+    pub insights: Vec<Insight>,
+    //没有取样数据时为空
+    pub flame_graph_svg: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GeneratedReport {
+    pub path: String,
+    pub size: u64,
+}
+
+pub fn collect_report_data(collector: &Arc<Mutex<SampleCollector>>, options: &ReportOptions) -> io::Result<ReportData> {
+    let sample_info = collector.lock().unwrap().get_sample_info();
+    let start_time = if options.start_time < 0 { sample_info.record_start_time } else { options.start_time };
+    //默认包含最后一次取样
+    let end_time = if options.end_time < 0 { sample_info.last_record_time + sample_info.sample_interval } else { options.end_time };
+    if end_time <= start_time {
+        return Err(new_invalid_input_error("time period error, end_time must be greater than start_time"));
+    }
+
+    let (threads, methods, gc_pauses, insights) = {
+        let mut collector = collector.lock().unwrap();
+        let threads = collector.get_threads()?;
+        let methods = get_method_stats(&mut collector, start_time, end_time)?;
+        let gc_pauses = get_gc_pauses_in_range(collector.get_gc_pauses(), start_time, end_time);
+        let insights = generate_insights(&mut collector, start_time, end_time, &options.lang)?;
+        (threads, methods, gc_pauses, insights)
+    };
+    //每个取样只有一个栈顶方法
+    let samples = methods.iter().map(|x| x.1.self_samples).sum();
+    let cpu_time = methods.iter().map(|x| x.1.self_cpu_time).sum();
+    let mut top_methods: Vec<(i64, MethodStats)> = methods.into_iter().filter(|x| x.1.self_samples > 0).collect();
+    top_methods.sort_by(|a, b| b.1.self_samples.cmp(&a.1.self_samples).then(a.0.cmp(&b.0)));
+    top_methods.truncate(options.top_methods);
+    let top_methods = {
+        let mut collector = collector.lock().unwrap();
+        top_methods.into_iter().map(|(method_id, stats)| ReportMethod {
+            method_name: collector.get_method_name(method_id),
+            self_samples: stats.self_samples,
+            samples: stats.samples,
+            self_cpu_time: stats.self_cpu_time,
+        }).collect()
+    };
+
+    let flame_graph_svg = if samples > 0 {
+        //所有线程合并，裁剪占比很小的节点控制文件大小
+        let thread_ids: Vec<i64> = threads.iter().map(|x| x.id).collect();
+        let prune_options = PruneOptions { min_samples: 0, min_percent: 0.1, max_depth: 0, sequenced: false };
+        Profiler::create_threads_flame_graph_svg(collector, &thread_ids, start_time, end_time, "samples", options.flame_graph_width, &prune_options, false)?
+    } else {
+        String::new()
+    };
+
+    Ok(ReportData {
+        lang: normalize_lang(&options.lang).to_string(),
+        agent_addr: sample_info.agent_addr,
+        sample_data_dir: sample_info.sample_data_dir,
+        start_time,
+        end_time,
+        sample_interval: sample_info.sample_interval,
+        threads: threads.len(),
+        samples,
+        cpu_time,
+        top_methods,
+        gc_summary: summarize_gc_pauses(&gc_pauses, start_time, end_time),
+        gc_pauses,
+        insights,
+        flame_graph_svg,
+    })
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c)
+        }
+    }
+    result
+}
+
+pub fn format_report_time(time: i64) -> String {
+    Local.timestamp_millis(time).format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+//GC暂停柱状图，横轴为时间，柱高为暂停时间
+fn render_gc_chart(data: &ReportData) -> String {
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" class=\"gc-chart\">", GC_CHART_WIDTH, GC_CHART_HEIGHT + 20);
+    svg.push_str(&format!("<line x1=\"0\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999\"/>", GC_CHART_HEIGHT, GC_CHART_WIDTH, GC_CHART_HEIGHT));
+    let span = (data.end_time - data.start_time).max(1);
+    let max_duration = data.gc_summary.max_duration.max(1);
+    for pause in &data.gc_pauses {
+        let x = (pause.time - data.start_time) * GC_CHART_WIDTH / span;
+        let height = (pause.duration * GC_CHART_HEIGHT / max_duration).max(1);
+        svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"3\" height=\"{}\" fill=\"#e4572e\"><title>{} {:.3}ms</title></rect>",
+                              x, GC_CHART_HEIGHT - height, height, format_report_time(pause.time), pause.duration as f64 / 1000.0));
+    }
+    svg.push_str(&format!("<text x=\"0\" y=\"{}\" font-size=\"11\">{}</text>", GC_CHART_HEIGHT + 15, escape_html(&format_report_time(data.start_time))));
+    svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"11\" text-anchor=\"end\">{}</text>", GC_CHART_WIDTH, GC_CHART_HEIGHT + 15, escape_html(&format_report_time(data.end_time))));
+    svg.push_str("</svg>");
+    svg
+}
+
+//概要中的(名称, 值)
+fn get_summary_rows(data: &ReportData) -> Vec<(String, String)> {
+    let lang = &data.lang;
+    let duration = data.end_time - data.start_time;
+    vec![
+        (tr(lang, "report.agent"), data.agent_addr.clone()),
+        (tr(lang, "report.sample_dir"), data.sample_data_dir.clone()),
+        (tr(lang, "report.time_range"), format!("{} ~ {}", format_report_time(data.start_time), format_report_time(data.end_time))),
+        (tr(lang, "report.duration"), format!("{:.3}s", duration as f64 / 1000.0)),
+        (tr(lang, "report.sample_interval"), format!("{}ms", data.sample_interval)),
+        (tr(lang, "report.threads"), data.threads.to_string()),
+        (tr(lang, "report.samples"), data.samples.to_string()),
+        (tr(lang, "report.cpu_time"), format!("{}ms", data.cpu_time / 1_000_000)),
+        (tr(lang, "report.gc_pauses"), tr_args(lang, "report.gc_pause_summary", &[("count", data.gc_summary.count.to_string()),
+            ("total", format!("{:.3}", data.gc_summary.total_duration as f64 / 1000.0)), ("max", format!("{:.3}", data.gc_summary.max_duration as f64 / 1000.0)),
+            ("ratio", format!("{:.2}", data.gc_summary.pause_ratio * 100.0))])),
+        (tr(lang, "report.generated_at"), Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+    ]
+}
+
+pub fn render_html_report(data: &ReportData) -> String {
+    let lang = &data.lang;
+    let mut html = String::new();
+    html.push_str(&format!("<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n", lang));
+    html.push_str(&format!("<title>{} - {}</title>\n", tr(lang, "report.title"), escape_html(&data.agent_addr)));
+    html.push_str("<style>\nbody{font-family:sans-serif;margin:20px;color:#333}\ntable{border-collapse:collapse;margin-bottom:20px}\n\
+th,td{border:1px solid #ddd;padding:4px 8px;text-align:left;font-size:13px}\nth{background:#f5f5f5}\ntd.num{text-align:right}\n\
+.critical{color:#c0392b}\n.warning{color:#d68910}\n.info{color:#2471a3}\n</style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>{}</h1>\n", tr(lang, "report.title")));
+
+    //概要
+    html.push_str(&format!("<h2>{}</h2>\n<table>\n", tr(lang, "report.summary")));
+    let rows = get_summary_rows(data);
+    for (name, value) in rows {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, escape_html(&value)));
+    }
+    html.push_str("</table>\n");
+
+    //问题洞察
+    html.push_str(&format!("<h2>{}</h2>\n", tr(lang, "report.insights")));
+    if data.insights.is_empty() {
+        html.push_str(&format!("<p>{}</p>\n", tr(lang, "report.no_issues")));
+    } else {
+        html.push_str(&format!("<table>\n<tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>\n",
+                               tr(lang, "report.severity"), tr(lang, "report.kind"), tr(lang, "report.insight_title"), tr(lang, "report.time_range")));
+        for insight in &data.insights {
+            html.push_str(&format!("<tr><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{} ~ {}</td></tr>\n",
+                                   escape_html(&insight.severity), escape_html(&tr(lang, &format!("severity.{}", insight.severity))), escape_html(&insight.kind), escape_html(&insight.title),
+                                   format_report_time(insight.start_time), format_report_time(insight.end_time)));
+        }
+        html.push_str("</table>\n");
+    }
+
+    //热点方法
+    html.push_str(&format!("<h2>{}</h2>\n", tr(lang, "report.top_methods")));
+    if data.top_methods.is_empty() {
+        html.push_str(&format!("<p>{}</p>\n", tr(lang, "report.no_samples")));
+    } else {
+        html.push_str(&format!("<table>\n<tr><th>#</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>\n", tr(lang, "report.method"),
+                               tr(lang, "report.self_samples"), tr(lang, "report.self_percent"), tr(lang, "report.total_samples"), tr(lang, "report.self_cpu_time")));
+        for (i, method) in data.top_methods.iter().enumerate() {
+            html.push_str(&format!("<tr><td class=\"num\">{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.2}%</td><td class=\"num\">{}</td><td class=\"num\">{}ms</td></tr>\n",
+                                   i + 1, escape_html(&method.method_name), method.self_samples, method.self_samples as f64 * 100.0 / data.samples.max(1) as f64,
+                                   method.samples, method.self_cpu_time / 1_000_000));
+        }
+        html.push_str("</table>\n");
+    }
+
+    //火焰图，去掉xml声明后内嵌
+    html.push_str(&format!("<h2>{}</h2>\n", tr(lang, "report.flame_graph")));
+    match data.flame_graph_svg.find("<svg") {
+        Some(pos) => {
+            html.push_str("<div class=\"flame-graph\">\n");
+            html.push_str(&data.flame_graph_svg[pos..]);
+            html.push_str("\n</div>\n");
+        }
+        None => html.push_str(&format!("<p>{}</p>\n", tr(lang, "report.no_samples")))
+    }
+
+    html.push_str(&format!("<h2>{}</h2>\n", tr(lang, "report.gc_pauses")));
+    if data.gc_pauses.is_empty() {
+        html.push_str(&format!("<p>{}</p>\n", tr(lang, "report.no_gc_pauses")));
+    } else {
+        html.push_str(&render_gc_chart(data));
+        html.push_str("\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+//Markdown中的表格单元格，方法名等用代码格式避免被解释为HTML标签
+fn md_code(text: &str) -> String {
+    format!("`{}`", text.replace('|', "\\|"))
+}
+
+fn md_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+//flame_graph_file 为同目录下的火焰图SVG文件名，为空时不引用
+pub fn render_markdown_report(data: &ReportData, flame_graph_file: &str) -> String {
+    let lang = &data.lang;
+    let mut md = String::new();
+    md.push_str(&format!("# {}\n\n", tr(lang, "report.title")));
+    md.push_str(&format!("## {}\n\n| | |\n|---|---|\n", tr(lang, "report.summary")));
+    for (name, value) in get_summary_rows(data) {
+        md.push_str(&format!("| {} | {} |\n", name, md_cell(&value)));
+    }
+
+    md.push_str(&format!("\n## {}\n\n", tr(lang, "report.insights")));
+    if data.insights.is_empty() {
+        md.push_str(&format!("{}\n", tr(lang, "report.no_issues")));
+    } else {
+        md.push_str(&format!("| {} | {} | {} | {} |\n|---|---|---|---|\n", tr(lang, "report.severity"), tr(lang, "report.kind"),
+                             tr(lang, "report.insight_title"), tr(lang, "report.time_range")));
+        for insight in &data.insights {
+            md.push_str(&format!("| {} | {} | {} | {} ~ {} |\n", md_cell(&tr(lang, &format!("severity.{}", insight.severity))), md_cell(&insight.kind), md_cell(&insight.title),
+                                 format_report_time(insight.start_time), format_report_time(insight.end_time)));
+        }
+    }
+
+    md.push_str(&format!("\n## {}\n\n", tr(lang, "report.top_methods")));
+    if data.top_methods.is_empty() {
+        md.push_str(&format!("{}\n", tr(lang, "report.no_samples")));
+    } else {
+        md.push_str(&format!("| # | {} | {} | {} | {} | {} |\n|---:|---|---:|---:|---:|---:|\n", tr(lang, "report.method"), tr(lang, "report.self_samples"),
+                             tr(lang, "report.self_percent"), tr(lang, "report.total_samples"), tr(lang, "report.self_cpu_time")));
+        for (i, method) in data.top_methods.iter().enumerate() {
+            md.push_str(&format!("| {} | {} | {} | {:.2}% | {} | {}ms |\n", i + 1, md_code(&method.method_name), method.self_samples,
+                                 method.self_samples as f64 * 100.0 / data.samples.max(1) as f64, method.samples, method.self_cpu_time / 1_000_000));
+        }
+    }
+
+    md.push_str(&format!("\n## {}\n\n", tr(lang, "report.flame_graph")));
+    if flame_graph_file != "" {
+        md.push_str(&format!("![{}]({})\n", tr(lang, "report.flame_graph"), flame_graph_file));
+    } else {
+        md.push_str(&format!("{}\n", tr(lang, "report.no_samples")));
+    }
+
+    //Markdown中没有图表，列出最长的暂停
+    md.push_str(&format!("\n## {}\n\n", tr(lang, "report.gc_pauses")));
+    if data.gc_pauses.is_empty() {
+        md.push_str(&format!("{}\n", tr(lang, "report.no_gc_pauses")));
+    } else {
+        let mut pauses = data.gc_pauses.clone();
+        pauses.sort_by(|a, b| b.duration.cmp(&a.duration).then(a.time.cmp(&b.time)));
+        md.push_str(&format!("| {} | {} |\n|---|---:|\n", tr(lang, "report.time"), tr(lang, "report.pause")));
+        for pause in pauses.iter().take(10) {
+            md.push_str(&format!("| {} | {:.3}ms |\n", format_report_time(pause.time), pause.duration as f64 / 1000.0));
+        }
+    }
+    md
+}
+
+pub fn get_report_path(sample_data_dir: &str, start_time: i64, end_time: i64, ext: &str) -> String {
+    format!("{}/{}/report-{}-{}.{}", sample_data_dir, REPORT_DIR, start_time, end_time, ext)
+}
+
+//生成报告到会话目录，返回报告文件
+pub fn generate_report(collector: &Arc<Mutex<SampleCollector>>, options: &ReportOptions) -> io::Result<GeneratedReport> {
+    if !REPORT_FORMATS.contains(&options.format.as_str()) {
+        return Err(new_invalid_input_error(&format!("invalid report format: {}, expect one of {:?}", options.format, REPORT_FORMATS)));
+    }
+    if options.format == "pdf" && options.pdf_tool == "" {
+        return Err(new_error(ErrorKind::Other, "pdf report is disabled, set 'pdf_tool' in config file"));
+    }
+    let data = collect_report_data(collector, options)?;
+    if data.sample_data_dir == "" {
+        return Err(new_invalid_input_error("sample data dir is not created"));
+    }
+    std::fs::create_dir_all(format!("{}/{}", data.sample_data_dir, REPORT_DIR))?;
+    let html_path = get_report_path(&data.sample_data_dir, data.start_time, data.end_time, "html");
+    let path = match options.format.as_str() {
+        "markdown" => {
+            //火焰图保存为同目录下的SVG文件
+            let mut flame_graph_file = String::new();
+            if let Some(pos) = data.flame_graph_svg.find("<svg") {
+                flame_graph_file = format!("report-{}-{}-flamegraph.svg", data.start_time, data.end_time);
+                std::fs::write(format!("{}/{}/{}", data.sample_data_dir, REPORT_DIR, flame_graph_file), data.flame_graph_svg[pos..].as_bytes())?;
+            }
+            let path = get_report_path(&data.sample_data_dir, data.start_time, data.end_time, "md");
+            std::fs::write(&path, render_markdown_report(&data, &flame_graph_file).as_bytes())?;
+            path
+        }
+        "pdf" => {
+            std::fs::write(&html_path, render_html_report(&data).as_bytes())?;
+            let path = get_report_path(&data.sample_data_dir, data.start_time, data.end_time, "pdf");
+            let output = Command::new(&options.pdf_tool).args(&[&html_path, &path]).output();
+            match output {
+                Ok(output) => {
+                    if !output.status.success() {
+                        return Err(new_error(ErrorKind::Other, &format!("convert report to pdf failed: {}, stderr: {}", output.status, String::from_utf8_lossy(&output.stderr))));
+                    }
+                }
+                Err(e) => return Err(new_error(ErrorKind::Other, &format!("run pdf tool failed: {}, err: {}", options.pdf_tool, e)))
+            }
+            path
+        }
+        _ => {
+            std::fs::write(&html_path, render_html_report(&data).as_bytes())?;
+            html_path
+        }
+    };
+    Ok(GeneratedReport {
+        size: std::fs::metadata(&path)?.len(),
+        path,
+    })
+}
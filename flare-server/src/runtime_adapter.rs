@@ -0,0 +1,375 @@
+
+//运行时适配器: 从不同运行时(JVM agent、Python/Node.js/.NET 等外部采样工具)读取取样事件，
+//转换为 flare-proto 的 AgentEvent 后进入相同的存储及分析流程
+//  jvm:       连接flare agent，RESP编码的事件流 (target: agent地址 host:port)
+//  collapsed: 读取折叠调用栈快照文件 (target: 文件路径)，可由 py-spy dump 等工具定期输出转换得到
+//新的适配器通过 register_adapter 注册
+//读取文件的适配器只能打开取样根目录下的文件，根目录由分析服务按配置设置(set_adapter_file_roots)
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Mutex;
+use chrono::Local;
+use flare_proto::agent::*;
+use flare_proto::handshake::{AgentCompat, negotiate, new_subscribe_request};
+use flare_proto::names::escape_name;
+use resp::{Decoder, Value};
+use protocol::SERVER_VERSION;
+use sample_path::{path_to_string, resolve_path_under_roots};
+use utils::*;
+
+//连接后等待agent第一个事件(hello或者旧版本的sample_info)的时间
+const HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+//agent版本不一致、部分功能不可用的会话事件
+pub const KIND_AGENT_VERSION: &str = "agent_version";
+
+pub trait RuntimeAdapter: Send {
+    //运行时名称
+    fn runtime(&self) -> &str;
+
+    //数据源描述，用于会话来源及录制目录名称
+    fn target(&self) -> &str;
+
+    fn connect(&mut self) -> io::Result<()>;
+
+    //阻塞读取下一个事件，Ok(None)表示数据源已结束
+    fn next_event(&mut self) -> io::Result<Option<AgentEvent>>;
+
+    //连接后返回停止读取的回调，在其它线程调用以中断阻塞的next_event
+    fn shutdown_hook(&self) -> Option<Box<Fn() + Send>>;
+
+    //连接后返回向数据源发送控制请求的回调(如死锁检测)，不支持时返回None
+    fn request_hook(&self) -> Option<Box<Fn(&Value) -> io::Result<()> + Send>>;
+
+    //连接时协商的agent版本及能力，没有版本协商的数据源返回None
+    fn agent_compat(&self) -> Option<AgentCompat> {
+        None
+    }
+
+    //重放之前录制的事件时返回false，事件时间与接收时间无关
+    fn is_realtime(&self) -> bool {
+        true
+    }
+}
+
+pub type AdapterFactory = fn(target: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<Box<RuntimeAdapter>>;
+
+lazy_static! {
+    static ref ADAPTER_FACTORIES: Mutex<Vec<(String, AdapterFactory)>> = Mutex::new(vec![
+        ("jvm".to_string(), JvmAgentAdapter::create as AdapterFactory),
+        ("collapsed".to_string(), CollapsedStackAdapter::create as AdapterFactory),
+    ]);
+    static ref ADAPTER_FILE_ROOTS: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+//配置的取样根目录，重新加载配置时更新
+pub fn set_adapter_file_roots(roots: &[String]) {
+    *ADAPTER_FILE_ROOTS.lock().unwrap() = roots.to_vec();
+}
+
+pub fn register_adapter(runtime: &str, factory: AdapterFactory) -> io::Result<()> {
+    let mut factories = ADAPTER_FACTORIES.lock().unwrap();
+    if factories.iter().any(|x| x.0 == runtime) {
+        return Err(new_invalid_input_error(&format!("runtime adapter is already registered: {}", runtime)));
+    }
+    factories.push((runtime.to_string(), factory));
+    Ok(())
+}
+
+pub fn list_adapters() -> Vec<String> {
+    ADAPTER_FACTORIES.lock().unwrap().iter().map(|x| x.0.clone()).collect()
+}
+
+pub fn create_adapter(runtime: &str, target: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<Box<RuntimeAdapter>> {
+    let factory = ADAPTER_FACTORIES.lock().unwrap().iter().find(|x| x.0 == runtime).map(|x| x.1);
+    match factory {
+        Some(factory) => factory(target, options),
+        None => Err(new_invalid_input_error(&format!("unknown runtime: {}, expect one of {:?}", runtime, list_adapters())))
+    }
+}
+
+//读取agent事件流的第一个事件检查版本，source为agent地址或者录制文件
+//  旧版本agent没有hello，返回读取的第一个事件
+pub fn read_handshake<R: Read>(decoder: &mut Decoder<R>, source: &str) -> io::Result<(AgentCompat, Option<AgentEvent>)> {
+    let first_event = loop {
+        let value = decoder.decode().map_err(|e| new_error(e.kind(), &format!("read handshake from {} failed: {}, make sure it is a flare agent and its version matches flare-server {}",
+                                                                              source, e, SERVER_VERSION)))?;
+        let event = AgentEvent::from_resp(&value).map_err(|e| new_error(e.kind(), &format!("decode handshake from {} failed: {}, upgrade flare-agent to {}",
+                                                                                             source, e, SERVER_VERSION)))?;
+        if let Some(event) = event {
+            break event;
+        }
+    };
+    let (compat, pending) = match first_event {
+        AgentEvent::Hello(hello) => (negotiate(Some(&hello), SERVER_VERSION)?, None),
+        event => (negotiate(None, SERVER_VERSION)?, Some(event))
+    };
+    println!("flare agent: {}, version: {}, agent protocol: {}", source, compat.agent_version, compat.proto_version);
+    if let Some(warning) = &compat.warning {
+        println!("{}", warning);
+    }
+    Ok((compat, pending))
+}
+
+//flare agent (JVMTI)
+pub struct JvmAgentAdapter {
+    agent_addr: String,
+    decoder: Option<Decoder<TcpStream>>,
+    stream: Option<TcpStream>,
+    compat: Option<AgentCompat>,
+    //握手时读取的旧版本agent的第一个事件
+    pending: Option<AgentEvent>,
+}
+
+impl JvmAgentAdapter {
+    pub fn new(agent_addr: &str) -> JvmAgentAdapter {
+        JvmAgentAdapter {
+            agent_addr: agent_addr.to_string(),
+            decoder: None,
+            stream: None,
+            compat: None,
+            pending: None,
+        }
+    }
+
+    //读取第一个事件检查agent版本，不兼容时返回带升级提示的错误
+    fn handshake(&mut self) -> io::Result<()> {
+        let decoder = self.decoder.as_mut().ok_or_else(|| new_error(io::ErrorKind::NotConnected, "agent is not connected"))?;
+        let (compat, pending) = read_handshake(decoder, &self.agent_addr)?;
+        self.pending = pending;
+        self.compat = Some(compat);
+        Ok(())
+    }
+
+    fn create(target: &str, _options: &serde_json::Map<String, serde_json::Value>) -> io::Result<Box<RuntimeAdapter>> {
+        Ok(Box::new(JvmAgentAdapter::new(target)))
+    }
+
+    //读取下一个事件及控制请求响应中的会话标签
+    pub fn next_tagged_event(&mut self) -> io::Result<Option<(AgentEvent, Option<String>)>> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some((event, None)));
+        }
+        let decoder = self.decoder.as_mut().ok_or_else(|| new_error(io::ErrorKind::NotConnected, "agent is not connected"))?;
+        loop {
+            let value = decoder.decode()?;
+            //跳过不认识的事件
+            if let Some(event) = AgentEvent::from_resp(&value)? {
+                return Ok(Some((event, get_event_tag(&value))));
+            }
+        }
+    }
+}
+
+impl RuntimeAdapter for JvmAgentAdapter {
+    fn runtime(&self) -> &str {
+        "jvm"
+    }
+
+    fn target(&self) -> &str {
+        &self.agent_addr
+    }
+
+    fn connect(&mut self) -> io::Result<()> {
+        let mut stream = match TcpStream::connect(&self.agent_addr) {
+            Ok(stream) => {
+                println!("Successfully connected to flare agent at: {:?}", self.agent_addr);
+                stream
+            }
+            Err(e) => {
+                println!("Failed to connect to flare agent: {:?}, error: {:?}", self.agent_addr, e);
+                return Err(e);
+            }
+        };
+        let cmd_value = new_subscribe_request(SERVER_VERSION);
+        stream.write_all(cmd_value.encode().as_slice())?;
+        println!("start subscribe events, awaiting reply: {}", cmd_value.to_encoded_string()?);
+        stream.set_read_timeout(Some(std::time::Duration::from_millis(HANDSHAKE_TIMEOUT_MS)))?;
+        self.stream = Some(stream.try_clone()?);
+        self.decoder = Some(Decoder::with_buf_bulk(BufReader::new(stream.try_clone()?)));
+        if let Err(e) = self.handshake() {
+            stream.shutdown(Shutdown::Both);
+            self.stream = None;
+            self.decoder = None;
+            return Err(e);
+        }
+        stream.set_read_timeout(None)?;
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<AgentEvent>> {
+        Ok(self.next_tagged_event()?.map(|x| x.0))
+    }
+
+    fn shutdown_hook(&self) -> Option<Box<Fn() + Send>> {
+        let stream = self.stream.as_ref()?.try_clone().ok()?;
+        Some(Box::new(move || {
+            let peer_addr = stream.peer_addr().map(|x| x.to_string()).unwrap_or("??".to_string());
+            println!("closing agent connection: {} ..", peer_addr);
+            stream.shutdown(Shutdown::Both);
+        }))
+    }
+
+    fn request_hook(&self) -> Option<Box<Fn(&Value) -> io::Result<()> + Send>> {
+        let stream = self.stream.as_ref()?.try_clone().ok()?;
+        Some(Box::new(move |request| {
+            (&stream).write_all(request.encode().as_slice())
+        }))
+    }
+
+    fn agent_compat(&self) -> Option<AgentCompat> {
+        self.compat.clone()
+    }
+}
+
+//折叠调用栈快照，空行分隔每次取样，每行一个线程:
+//  <thread_name>;<root frame>;...;<leaf frame> [count]
+//外部采样工具通常没有线程CPU时间，每次取样按运行中的线程计算一个取样间隔的CPU时间
+pub struct CollapsedStackAdapter {
+    path: String,
+    sample_interval: i64,
+    reader: Option<Box<BufRead + Send>>,
+    start_time: i64,
+    sample_index: i64,
+    threads: HashMap<String, i64>,
+    methods: HashMap<String, i64>,
+    //已解析待发送的事件
+    pending: Vec<AgentEvent>,
+    finished: bool,
+}
+
+impl CollapsedStackAdapter {
+    pub fn new(path: &str, sample_interval: i64) -> CollapsedStackAdapter {
+        CollapsedStackAdapter {
+            path: path.to_string(),
+            sample_interval: sample_interval.max(1),
+            reader: None,
+            start_time: 0,
+            sample_index: 0,
+            threads: HashMap::new(),
+            methods: HashMap::new(),
+            pending: vec![],
+            finished: false,
+        }
+    }
+
+    fn create(target: &str, options: &serde_json::Map<String, serde_json::Value>) -> io::Result<Box<RuntimeAdapter>> {
+        let sample_interval = get_option_as_int(options, "sample_interval", 20);
+        Ok(Box::new(CollapsedStackAdapter::new(target, sample_interval)))
+    }
+
+    //读取一次取样的所有线程，事件按发送顺序倒序放入pending
+    fn read_snapshot(&mut self) -> io::Result<()> {
+        let reader = self.reader.as_mut().ok_or_else(|| new_error(io::ErrorKind::NotConnected, "collapsed stack file is not opened"))?;
+        let mut lines = vec![];
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                self.finished = true;
+                break;
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                if lines.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            lines.push(line.to_string());
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let time = self.start_time + self.sample_index * self.sample_interval;
+        let cpu_time_delta = self.sample_interval * 1_000_000;
+        self.sample_index += 1;
+        let mut events = vec![];
+        let mut method_events = vec![];
+        for line in &lines {
+            //去掉末尾的计数
+            let stack = match line.rfind(' ') {
+                Some(pos) if line[pos + 1..].parse::<u64>().is_ok() => &line[..pos],
+                _ => line.as_str()
+            };
+            let mut frames = stack.split(';');
+            let thread_name = frames.next().unwrap_or("").trim().to_string();
+            let next_thread_id = self.threads.len() as i64 + 1;
+            let thread_id = *self.threads.entry(thread_name.clone()).or_insert(next_thread_id);
+            let mut stacktrace = vec![];
+            for frame in frames.filter(|x| !x.is_empty()) {
+                let method_id = match self.methods.get(frame) {
+                    Some(id) => *id,
+                    None => {
+                        let id = self.methods.len() as i64 + 1;
+                        self.methods.insert(frame.to_string(), id);
+                        method_events.push(AgentEvent::Method(MethodEvent { id, name: escape_name(frame).into_owned() }));
+                        id
+                    }
+                };
+                stacktrace.push(method_id);
+            }
+            //栈顶在前
+            stacktrace.reverse();
+            let cpu_time = cpu_time_delta * self.sample_index;
+            events.push(AgentEvent::Thread(ThreadEvent {
+                time,
+                id: thread_id,
+                name: thread_name,
+                cpu_time,
+                cpu_time_delta,
+                state: "RUNNABLE".to_string(),
+                stacktrace,
+                //按线程名称分配的id不会重用
+                start_time: 0,
+            }));
+        }
+        //第一个线程事件创建保存目录后才能保存方法，新方法放在本次取样的线程事件之后
+        events.append(&mut method_events);
+        events.reverse();
+        self.pending = events;
+        Ok(())
+    }
+}
+
+impl RuntimeAdapter for CollapsedStackAdapter {
+    fn runtime(&self) -> &str {
+        "collapsed"
+    }
+
+    fn target(&self) -> &str {
+        &self.path
+    }
+
+    fn connect(&mut self) -> io::Result<()> {
+        let path = resolve_path_under_roots(&self.path, &ADAPTER_FILE_ROOTS.lock().unwrap())?;
+        println!("open collapsed stack file: {}", path_to_string(&path));
+        let file = std::fs::File::open(&path)?;
+        self.reader = Some(Box::new(BufReader::new(file)));
+        self.start_time = Local::now().timestamp_millis();
+        self.pending = vec![AgentEvent::SampleInfo(SampleInfoEvent {
+            start_time: self.start_time,
+            sample_interval: self.sample_interval,
+            last_sample_time: self.start_time,
+        })];
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<AgentEvent>> {
+        while self.pending.is_empty() && !self.finished {
+            self.read_snapshot()?;
+        }
+        Ok(self.pending.pop())
+    }
+
+    //读到文件末尾自动结束
+    fn shutdown_hook(&self) -> Option<Box<Fn() + Send>> {
+        None
+    }
+
+    fn request_hook(&self) -> Option<Box<Fn(&Value) -> io::Result<()> + Send>> {
+        None
+    }
+}
@@ -14,26 +14,75 @@ use flare_utils::tuple_indexed::*;
 use flare_utils::tuple_indexed::{TupleIndexedFile, TupleValue};
 use flare_utils::timeseries::{TimeSeries, TSValue, TimeSeriesFileWriter, TimeSeriesFileReader};
 use flare_utils::{ValueType, file_utils};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use utils::*;
 use std::hash::Hash;
 use std::sync::{Mutex, Arc};
 use std::cmp::min;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use flare_utils::file_utils::open_file;
+use flare_utils::file_utils::{open_file, path_with_suffix};
 use call_tree::*;
 use std::ops::{Index, Deref, DerefMut};
 use flare_utils::stopwatch::*;
 use std::str::FromStr;
 use tree;
+use database_analysis::*;
+use idle_frames::*;
+use marker::*;
+use saved_view::*;
+use deobfuscate::ProguardMapping;
+use symbol_cache::*;
+use agg_index::*;
+use flare_proto::agent::*;
+use flare_proto::handshake::{AgentCompat, request_capability};
+use runtime_adapter::*;
+use offcpu::*;
+use deadlock::*;
+use thread_dump::*;
+use heap_histogram::*;
+use allocation::*;
+use finalizer::FINALIZER_PENDING_METRIC;
+use deopt::*;
+use classloader::*;
+use ingest_filter::*;
+use gc::*;
+use session_events::*;
+use thread_handles::*;
+use sample_path::{path_to_string, create_unique_dir};
+use agent_recording::{import_agent_recording, get_imported_sample_dir};
+use flare_proto::recording::is_recording_dir;
+use data_quality::*;
+use clock_sync::*;
+use monotonic_time::*;
+use flush_policy::*;
+use disk_guard::*;
+use self_profile::enter_span;
+use stack_record::*;
+use metric_series::*;
+use cgroup_metrics::*;
+use host_metrics::*;
 
 
 type JavaLong = i64;
 type JavaMethod = i64;
 
+//等待agent异步推送的结果(线程dump、堆直方图)，error为None时检查结果，返回true表示已完成；
+//  超时或者会话关闭时传入错误，回调发送错误后完成
+pub type AgentResultWaiter = Box<FnMut(&SampleCollector, Option<io::Error>) -> bool + Send>;
+
 pub const FLARE_SAMPLES_DIR : &str = "flare-samples";
 
+//合成的调用栈节点(非JVM方法)使用负数id
+//打开取样时线程调用栈索引常驻内存的默认上限
+pub const DEFAULT_MAX_RESIDENT_BYTES: usize = 512 * 1024 * 1024;
+//open_sample 的 max_resident_mb 参数上限(1TB)
+pub const MAX_RESIDENT_MB_LIMIT: i64 = 1024 * 1024;
+const MAX_CPU_TS_CACHE_ENTRIES: usize = 256;
+
+pub const IDLE_FRAME_ID: JavaMethod = -1;
+pub const OTHER_FRAME_ID: JavaMethod = -2;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ThreadData {
     pub id: JavaLong,
@@ -91,7 +140,16 @@ pub struct MethodCall {
 #[derive(Serialize, Deserialize)]
 pub struct DashboardInfo {
     pub sample_info: SampleInfo,
-    pub threads: Vec<ThreadData>
+    pub threads: Vec<ThreadData>,
+    //目标进程所在cgroup的最新资源指标，没有目标进程或者不是Linux时为空
+    pub cgroup: Option<CgroupMetrics>,
+    //最新的主机资源指标，未开启记录时为空
+    pub host: Option<HostMetrics>,
+    //最近一分钟分配最多的线程及方法，agent未开启分配统计时为空
+    #[serde(default)]
+    pub top_allocating_threads: Vec<ThreadAllocationRate>,
+    #[serde(default)]
+    pub top_allocating_methods: Vec<MethodAllocation>,
     //jvm_info: JvmInfo,
 }
 
@@ -103,16 +161,86 @@ pub struct SampleInfo {
     pub last_record_time: i64,
     pub agent_addr: String,
     pub sample_data_dir: String,
+    //raw: 保存原始调用栈及聚合索引，aggregated: 只保存聚合索引
+    #[serde(default = "default_stack_retention")]
+    pub stack_retention: String,
+    #[serde(default)]
+    pub data_quality: DataQuality,
+    //磁盘空间不足时停止录制或者降采样的记录
+    #[serde(default, skip_serializing_if = "DiskGuardState::is_empty")]
+    pub disk_guard: DiskGuardState,
+}
+
+//只保存聚合索引时，按时间顺序的调用树、线程取样等需要原始调用栈的查询返回空的结果，
+//调用树按分钟精度统计，录制中最近不满一分钟的数据还没有写入
+pub const STACK_RETENTION_RAW: &str = "raw";
+pub const STACK_RETENTION_AGGREGATED: &str = "aggregated";
+
+fn default_stack_retention() -> String {
+    STACK_RETENTION_RAW.to_string()
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SummaryInfo {
-    sample_info: SampleInfo,
-    threads: Vec<ThreadData>
+    pub sample_info: SampleInfo,
+    pub threads: Vec<ThreadData>,
+    //录制时使用的写入过滤
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingest_filter: Option<IngestFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingest_stats: Option<IngestStats>,
+}
+
+//时序文件的描述信息，标签保存在文件头中
+#[derive(Serialize, Clone, Debug)]
+pub struct SeriesInfo {
+    pub name: String,
+    pub value_type: String,
+    pub metric_kind: String,
+    pub unit_time: i32,
+    pub begin_time: i64,
+    pub end_time: i64,
+    pub labels: BTreeMap<String, String>,
+}
+
+//线程CPU时间序列的标签，数值单位为微秒
+pub fn get_cpu_ts_labels(thread_id: JavaLong, thread_name: &str) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::new();
+    labels.insert("metric".to_string(), "cpu_time".to_string());
+    labels.insert("unit".to_string(), "us".to_string());
+    labels.insert("thread_id".to_string(), thread_id.to_string());
+    labels.insert("thread_name".to_string(), thread_name.to_string());
+    labels
+}
+
+// 线程列表排序方式
+#[derive(Eq, PartialEq, Debug, Clone, Copy, EnumString)]
+pub enum ThreadSortBy {
+    #[strum(serialize="id")]
+    ID,
+
+    #[strum(serialize="cpu")]
+    CPU,
+
+    #[strum(serialize="samples")]
+    SAMPLES,
+
+    #[strum(serialize="name")]
+    NAME,
+}
+
+pub struct ThreadQuery {
+    pub name_filter: String,
+    pub state: String,
+    pub sort_by: ThreadSortBy,
+    pub desc: bool,
+    //page从0开始，page_size<=0时返回全部
+    pub page: usize,
+    pub page_size: usize,
 }
 
 // 统计方式
-#[derive(Eq, PartialEq, Debug, EnumString)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy, EnumString)]
 pub enum StatsType {
     #[strum(serialize="duration")]
     DURATION,
@@ -130,8 +258,17 @@ pub struct SampleCollector {
     connected: bool,
     disconnected: bool,
     agent_addr: String,
-    agent_stream: Option<TcpStream>,
+    //停止读取运行时适配器的事件
+    adapter_shutdown_hook: Option<Box<Fn() + Send>>,
+    //向运行时适配器的数据源发送控制请求
+    adapter_request_hook: Option<Box<Fn(&Value) -> io::Result<()> + Send>>,
+    //连接时协商的agent版本及能力
+    agent_compat: Option<AgentCompat>,
+    //版本提示只记录一次会话事件
+    agent_warning_recorded: bool,
     readonly: bool,
+    //只读模式打开，不写入取样目录及符号缓存
+    write_protected: bool,
     running: bool,
 
     //sample option
@@ -141,38 +278,126 @@ pub struct SampleCollector {
 
     //collector
     record_start_time: i64,
+    //每15分钟更换保存目录，导入时保存到一个目录
+    roll_data_dir: bool,
     last_record_time: i64,
     //last save summary time
     last_save_time: i64,
 
     //sample data processor
     threads : HashMap<JavaLong, ThreadData>,
+    //转义后的取样目录，用于显示及发送给客户端
     sample_data_dir: String,
+    //读写文件使用的取样目录，可以不是有效的UTF-8
+    sample_data_path: PathBuf,
+    //录制输出的根目录
+    samples_root: PathBuf,
     sample_cpu_ts_map: HashMap<JavaLong, Option<Box<TimeSeries+Send>>>,
     sample_cpu_ts_cache: HashMap<String, Option<Arc<TSResult>>>,
     sample_stacktrace_map: HashMap<JavaLong, Option<TupleIndexedFile>>,
+    //录制中每个线程还没有写入调用栈文件的数据块
+    stack_chunks: HashMap<JavaLong, StackChunkBuilder>,
+    //按需加载的调用栈索引，最近使用的在后面
+    stacktrace_file_lru: Vec<JavaLong>,
+    stacktrace_file_bytes: HashMap<JavaLong, usize>,
+    resident_bytes: usize,
+    max_resident_bytes: usize,
     sample_method_idx_file: Option<TupleIndexedFile>,
     method_cache: HashMap<JavaMethod, Option<MethodInfo>>,
     method_entries: Vec<MethodInfo>,
     method_entry_cache_time: i64,
     method_info_update_time: i64,
     call_tree_cahce: HashMap<JavaLong, Box<tree::TreeNode>>,
+    synthetic_frames: HashMap<JavaMethod, String>,
+    markers: Vec<Marker>,
+    views: Vec<SavedView>,
+    intervals: Vec<Interval>,
+    offcpu_profiles: Vec<OffCpuProfile>,
+    //off-CPU帧名称 => 合成的方法ID
+    offcpu_frame_ids: HashMap<String, JavaMethod>,
+    deadlocks: Vec<DeadlockCycle>,
+    thread_dumps: Vec<ThreadDumpInfo>,
+    //(超时时间, 回调)
+    agent_result_waiters: Vec<(i64, AgentResultWaiter)>,
+    heap_histograms: Vec<HeapHistogramInfo>,
+    allocation_samples: AllocationStore,
+    deopt_records: Vec<DeoptRecord>,
+    class_loader_samples: Vec<ClassLoaderSample>,
+    gc_pauses: Vec<GcPause>,
+    session_events: Vec<SessionEvent>,
+    //线程id -> 线程句柄，threads等按线程保存的数据都使用句柄
+    thread_handles: ThreadHandleTable,
+    data_quality: DataQuality,
+    disk_guard: DiskGuardState,
+    clock_sync_config: ClockSyncConfig,
+    clock_estimator: ClockEstimator,
+    last_clock_sync_time: i64,
+    //加到事件时间上的偏差，第一次同步之后整个会话使用固定的偏差
+    clock_offset: i64,
+    clock_offset_fixed: bool,
+    //第一次同步的请求时间，收到响应之前缓冲事件
+    clock_sync_wait_start: i64,
+    pending_clock_events: Vec<AgentEvent>,
+    time_normalizer: TimeNormalizer,
+    flush_policy: FlushPolicy,
+    last_flush_time: i64,
+    ingest_filter: Option<IngestFilter>,
+    ingest_stats: IngestStats,
+    stack_retention: String,
+    //agent所在的目标进程，用于采集cgroup资源指标
+    target_pid: i64,
+    cgroup_metrics: Option<CgroupMetrics>,
+    record_host_metrics: bool,
+    host_metrics: Option<HostMetrics>,
+    last_metrics_time: i64,
+    metric_ts_map: HashMap<String, Box<TimeSeries+Send>>,
+    mapping: Option<ProguardMapping>,
+    symbol_cache_key: String,
+    agg_index_builder: Option<AggIndexBuilder>,
+    agg_index: Option<AggIndex>,
 //    tree_arena: TreeArena
 }
 
 impl SampleCollector {
 
 
-    pub fn new(addr: &str) -> io::Result<Arc<Mutex<SampleCollector>>> {
+    pub fn new<P: AsRef<Path>>(addr: &str, samples_root: P) -> io::Result<Arc<Mutex<SampleCollector>>> {
         let mut collector = SampleCollector::new_instance();
         collector.lock().unwrap().agent_addr = addr.to_string();
+        collector.lock().unwrap().samples_root = samples_root.as_ref().to_path_buf();
         Ok(collector)
     }
 
-    pub fn open(sample_dir: &str) -> io::Result<Arc<Mutex<SampleCollector>>> {
-        println!("load sample data from dir: {}", sample_dir);
+    pub fn open<P: AsRef<Path>>(sample_dir: P) -> io::Result<Arc<Mutex<SampleCollector>>> {
+        SampleCollector::open_with_progress(sample_dir, &mut |_, _| {})
+    }
+
+    //加载取样数据，progress(phase, percent)
+    pub fn open_with_progress<P: AsRef<Path>>(sample_dir: P, progress: &mut FnMut(&str, i64)) -> io::Result<Arc<Mutex<SampleCollector>>> {
+        SampleCollector::open_with_options(sample_dir, false, progress)
+    }
+
+    //read_only: 不写入取样目录，不转换agent录制
+    pub fn open_with_options<P: AsRef<Path>>(sample_dir: P, read_only: bool, progress: &mut FnMut(&str, i64)) -> io::Result<Arc<Mutex<SampleCollector>>> {
+        //agent独立录制的目录先转换为取样目录
+        let imported_dir;
+        let sample_dir = if is_recording_dir(sample_dir.as_ref()) {
+            progress("import", 0);
+            let recording_dir = sample_dir.as_ref();
+            imported_dir = if read_only {
+                get_imported_sample_dir(recording_dir).ok_or_else(|| new_error(ErrorKind::PermissionDenied,
+                    &format!("agent recording is not imported, can not import in read-only mode: {}", path_to_string(recording_dir))))?
+            } else {
+                import_agent_recording(recording_dir)?
+            };
+            imported_dir.as_path()
+        } else {
+            sample_dir.as_ref()
+        };
+        println!("load sample data from dir: {}", path_to_string(sample_dir));
         let mut collector = SampleCollector::new_instance();
-        match collector.lock().unwrap().load_sample(sample_dir) {
+        collector.lock().unwrap().write_protected = read_only;
+        match collector.lock().unwrap().load_sample(sample_dir, progress) {
             Ok(_) => {},
             Err(e) => {
                 println!("load sample failed: {:?}", e);
@@ -187,32 +412,87 @@ impl SampleCollector {
         let mut collector = Arc::new(Mutex::new(SampleCollector {
             this_ref: None,
             readonly: false,
+            write_protected: false,
             running: true,
             sample_type: "".to_string(),
             sample_interval: 20,
             sample_start_time: 0,
             record_start_time: 0,
+            roll_data_dir: true,
             last_record_time: 0,
             last_save_time: 0,
             threads: HashMap::new(),
             sample_data_dir: "".to_string(),
+            sample_data_path: PathBuf::new(),
+            samples_root: PathBuf::from(FLARE_SAMPLES_DIR),
             sample_cpu_ts_map: HashMap::new(),
             sample_cpu_ts_cache: Default::default(),
             sample_stacktrace_map: HashMap::new(),
+            stack_chunks: HashMap::new(),
+            stacktrace_file_lru: vec![],
+            stacktrace_file_bytes: HashMap::new(),
+            resident_bytes: 0,
+            max_resident_bytes: DEFAULT_MAX_RESIDENT_BYTES,
             sample_method_idx_file: None,
             connected: false,
             disconnected: false,
             agent_addr: "".to_string(),
-            agent_stream: None,
+            adapter_shutdown_hook: None,
+            adapter_request_hook: None,
+            agent_compat: None,
+            agent_warning_recorded: false,
             method_cache: HashMap::new(),
 //            tree_arena: TreeArena::new()
             method_entries: vec![],
             method_entry_cache_time: 0,
             method_info_update_time: 0,
-            call_tree_cahce: Default::default()
+            call_tree_cahce: Default::default(),
+            synthetic_frames: Default::default(),
+            markers: vec![],
+            views: vec![],
+            offcpu_profiles: vec![],
+            offcpu_frame_ids: HashMap::new(),
+            deadlocks: vec![],
+            thread_dumps: vec![],
+            agent_result_waiters: vec![],
+            heap_histograms: vec![],
+            allocation_samples: AllocationStore::new(MAX_ALLOCATION_SAMPLES),
+            deopt_records: vec![],
+            class_loader_samples: vec![],
+            gc_pauses: vec![],
+            session_events: vec![],
+            thread_handles: ThreadHandleTable::new(),
+            data_quality: DataQuality::default(),
+            disk_guard: DiskGuardState::default(),
+            clock_sync_config: ClockSyncConfig::default(),
+            clock_estimator: ClockEstimator::default(),
+            last_clock_sync_time: 0,
+            clock_offset: 0,
+            clock_offset_fixed: false,
+            clock_sync_wait_start: 0,
+            pending_clock_events: vec![],
+            time_normalizer: TimeNormalizer::default(),
+            flush_policy: FlushPolicy::default(),
+            last_flush_time: 0,
+            ingest_filter: None,
+            ingest_stats: IngestStats::default(),
+            stack_retention: default_stack_retention(),
+            target_pid: -1,
+            cgroup_metrics: None,
+            record_host_metrics: false,
+            host_metrics: None,
+            last_metrics_time: 0,
+            metric_ts_map: HashMap::new(),
+            intervals: vec![],
+            mapping: None,
+            symbol_cache_key: "".to_string(),
+            agg_index_builder: None,
+            agg_index: None,
         }));
         //self ref for threads
         collector.lock().unwrap().this_ref = Some(collector.clone());
+        collector.lock().unwrap().add_synthetic_frame(IDLE_FRAME_ID, "<idle>");
+        collector.lock().unwrap().add_synthetic_frame(OTHER_FRAME_ID, "<other>");
         collector
     }
 
@@ -223,15 +503,11 @@ impl SampleCollector {
         //release self ref 必须释放自引用，否则不会释放此对象，打开的文件句柄也不会自动关闭
         self.this_ref = None;
         //close agent connection
-        if let Some(stream) = &self.agent_stream {
-            let mut peer_addr = "??".to_string();
-            if let Ok(addr) = stream.peer_addr() {
-                peer_addr = addr.to_string();
-            }
-            println!("closing agent connection: {} ..", peer_addr);
-            stream.shutdown(Shutdown::Both);
+        if let Some(shutdown_hook) = self.adapter_shutdown_hook.take() {
+            shutdown_hook();
         }
-        self.agent_stream = None;
+        self.adapter_request_hook = None;
+        self.finish_agent_result_waiters(|_| true, ErrorKind::NotConnected, "sample session is closed");
     }
 
     pub fn is_disconnected(&self) -> bool {
@@ -239,11 +515,12 @@ impl SampleCollector {
     }
 
     //加载取样数据
-    fn load_sample(&mut self, sample_data_dir: &str) -> io::Result<()> {
+    fn load_sample(&mut self, sample_data_path: &Path, progress: &mut FnMut(&str, i64)) -> io::Result<()> {
         self.readonly = true;
         self.sample_type = "file".to_string();
-        self.sample_data_dir = sample_data_dir.to_string();
-        let dir_meta = std::fs::metadata(sample_data_dir);
+        self.set_sample_data_path(sample_data_path.to_path_buf());
+        let sample_data_dir = &path_to_string(sample_data_path);
+        let dir_meta = std::fs::metadata(sample_data_path);
         if dir_meta.is_err() {
             return Err(new_error(ErrorKind::NotFound, "sample data dir not found"));
         }
@@ -251,10 +528,9 @@ impl SampleCollector {
             return Err(new_error(ErrorKind::NotFound, "sample data dir is not a directory"));
         }
 
-        self.sample_data_dir = sample_data_dir.to_string();
-
         //summary info
-        let path = format!("{}/summary_info.json", sample_data_dir);
+        progress("summary", 0);
+        let path = sample_data_path.join("summary_info.json");
         let json = std::fs::read_to_string(path)?;
 
         let summary = serde_json::from_str::<SummaryInfo>(&json);
@@ -270,40 +546,126 @@ impl SampleCollector {
         self.agent_addr = sample_info.agent_addr.clone();
         self.record_start_time = sample_info.record_start_time;
         self.last_record_time = sample_info.last_record_time;
+        self.ingest_filter = summary.ingest_filter.clone();
+        self.stack_retention = sample_info.stack_retention.clone();
+        self.data_quality = sample_info.data_quality.clone();
+        self.disk_guard = sample_info.disk_guard.clone();
+        self.ingest_stats = summary.ingest_stats.clone().unwrap_or_default();
 
         //threads
-        for thread in &summary.threads {
+        let thread_count = summary.threads.len();
+        for (i, thread) in summary.threads.iter().enumerate() {
+            progress("threads", 5 + (i * 80 / thread_count) as i64);
             self.threads.insert(thread.id, thread.clone());
 
             //load cpu time ts
-            let thread_cpu_ts_file = format!("{}/thread_{}_cpu_time", sample_data_dir, thread.id);
+            let thread_cpu_ts_file = sample_data_path.join(format!("thread_{}_cpu_time", thread.id));
             match TimeSeriesFileReader::new(&thread_cpu_ts_file) {
                 Ok(ts) => {
                     self.sample_cpu_ts_map.insert(thread.id, Some(Box::new(ts)));
                 },
                 Err(e) => {
-                    println!("load thread cpu time file failed: {}, err: {}", thread_cpu_ts_file, e);
+                    println!("load thread cpu time file failed: {}, err: {}", path_to_string(&thread_cpu_ts_file), e);
                 }
             }
 
-            //load thread stacktrace
-            let thread_stack_file = format!("{}/thread_{}_stack", sample_data_dir, thread.id);
-            match TupleIndexedFile::new_reader(&thread_stack_file) {
-                Ok(file) => {
-                    self.sample_stacktrace_map.insert(thread.id, Some(file));
-                },
-                Err(e) => {
-                    println!("load thread stacktrace file failed: {}, err: {}", thread_stack_file, e);
-                }
-            }
+            //thread stacktrace 在首次查询时加载
         }
 
         //method info idx file
-        let method_idx_path = format!("{}/method_info", sample_data_dir);
-        let mut method_idx_file = TupleIndexedFile::new_writer(&method_idx_path, ValueType::INT64)?;
+        progress("methods", 85);
+        let method_idx_path = sample_data_path.join("method_info");
+        let mut method_idx_file = if self.write_protected {
+            TupleIndexedFile::new_reader(&method_idx_path)?
+        } else {
+            TupleIndexedFile::new_writer(&method_idx_path, ValueType::INT64)?
+        };
         self.sample_method_idx_file = Some(method_idx_file);
         let now = Local::now().timestamp_millis();
         self.method_info_update_time = now;
+
+        //symbol cache
+        match get_symbol_cache_key(sample_data_path, !self.write_protected) {
+            Ok(key) => {
+                if let Some(methods) = load_symbol_cache(&key) {
+                    println!("load symbol cache: {}, methods: {}", key, methods.len());
+                    for method_info in &methods {
+                        self.method_cache.insert(method_info.method_id, Some(method_info.clone()));
+                    }
+                    self.method_entries = methods;
+                    self.method_entry_cache_time = now;
+                }
+                self.symbol_cache_key = key;
+            }
+            Err(e) => println!("get symbol cache key failed: {}, err: {}", sample_data_dir, e)
+        }
+
+        //aggregation index
+        progress("index", 95);
+        self.reload_agg_index();
+
+        //markers
+        match load_markers(sample_data_path) {
+            Ok(markers) => self.markers = markers,
+            Err(e) => println!("load markers failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_views(sample_data_path) {
+            Ok(views) => self.views = views,
+            Err(e) => println!("load views failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_intervals(sample_data_path) {
+            Ok(intervals) => self.intervals = intervals,
+            Err(e) => println!("load intervals failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_metric_series(sample_data_path) {
+            Ok(series_map) => self.metric_ts_map = series_map,
+            Err(e) => println!("load metric series failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_offcpu_profiles(sample_data_path) {
+            Ok(profiles) => self.offcpu_profiles = profiles,
+            Err(e) => println!("load off-cpu stacks failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_deadlocks(sample_data_path) {
+            Ok(cycles) => self.deadlocks = cycles,
+            Err(e) => println!("load deadlocks failed: {}, err: {}", sample_data_dir, e)
+        }
+        match list_thread_dumps(sample_data_path) {
+            Ok(dumps) => self.thread_dumps = dumps,
+            Err(e) => println!("load thread dumps failed: {}, err: {}", sample_data_dir, e)
+        }
+        match list_heap_histograms(sample_data_path) {
+            Ok(histograms) => self.heap_histograms = histograms,
+            Err(e) => println!("load heap histograms failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_allocation_samples(sample_data_path) {
+            Ok(samples) => {
+                self.allocation_samples = AllocationStore::new(MAX_ALLOCATION_SAMPLES);
+                for sample in samples {
+                    self.allocation_samples.push(sample);
+                }
+            },
+            Err(e) => println!("load allocation samples failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_deopt_records(sample_data_path) {
+            Ok(records) => self.deopt_records = records,
+            Err(e) => println!("load deoptimizations failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_class_loader_samples(sample_data_path) {
+            Ok(samples) => self.class_loader_samples = samples,
+            Err(e) => println!("load class loaders failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_gc_pauses(sample_data_path) {
+            Ok(pauses) => self.gc_pauses = pauses,
+            Err(e) => println!("load gc pauses failed: {}, err: {}", sample_data_dir, e)
+        }
+        match load_session_events(sample_data_path) {
+            Ok(events) => self.session_events = events,
+            Err(e) => println!("load session events failed: {}, err: {}", sample_data_dir, e)
+        }
+        match ThreadHandleTable::load(sample_data_path) {
+            Ok(table) => self.thread_handles = table,
+            Err(e) => println!("load thread handles failed: {}, err: {}", sample_data_dir, e)
+        }
         //load threads
 //        let paths = std::fs::read_dir("sample_data_dir")?;
 //        for path in paths {
@@ -317,38 +679,103 @@ impl SampleCollector {
         Ok(())
     }
 
+    //读写文件使用 sample_data_path，sample_data_dir 是转义后的名称
+    fn set_sample_data_path(&mut self, path: PathBuf) {
+        self.sample_data_dir = path_to_string(&path);
+        self.sample_data_path = path;
+    }
+
+    pub fn set_roll_data_dir(&mut self, roll_data_dir: bool) {
+        self.roll_data_dir = roll_data_dir;
+    }
+
+    pub fn set_max_resident_bytes(&mut self, max_resident_bytes: usize) {
+        self.max_resident_bytes = max_resident_bytes;
+    }
+
+    pub fn get_resident_bytes(&self) -> usize {
+        self.resident_bytes
+    }
+
+    //打开的取样按需加载线程调用栈索引，超过常驻内存上限时释放最久未使用的索引
+    fn get_stacktrace_file(&mut self, thread_id: JavaLong) -> Option<&mut TupleIndexedFile> {
+        if self.readonly {
+            if !self.sample_stacktrace_map.contains_key(&thread_id) {
+                self.load_stacktrace_file(thread_id);
+            }
+            if let Some(pos) = self.stacktrace_file_lru.iter().position(|x| *x == thread_id) {
+                self.stacktrace_file_lru.remove(pos);
+                self.stacktrace_file_lru.push(thread_id);
+            }
+        }
+        self.sample_stacktrace_map.get_mut(&thread_id).and_then(|x| x.as_mut())
+    }
+
+    fn load_stacktrace_file(&mut self, thread_id: JavaLong) {
+        let thread_stack_file = self.sample_data_path.join(format!("thread_{}_stack", thread_id));
+        match TupleIndexedFile::new_reader(&thread_stack_file) {
+            Ok(file) => {
+                //索引按块读取，常驻内存的只有每块的第一个索引值及索引块缓存
+                let estimated_bytes = file.get_resident_bytes();
+                while !self.stacktrace_file_lru.is_empty() && self.resident_bytes + estimated_bytes > self.max_resident_bytes {
+                    let evict_thread_id = self.stacktrace_file_lru.remove(0);
+                    self.sample_stacktrace_map.remove(&evict_thread_id);
+                    self.resident_bytes -= self.stacktrace_file_bytes.remove(&evict_thread_id).unwrap_or(0);
+                    println!("release thread stacktrace index: {}, resident bytes: {}", evict_thread_id, self.resident_bytes);
+                }
+                self.sample_stacktrace_map.insert(thread_id, Some(file));
+                self.stacktrace_file_lru.push(thread_id);
+                self.stacktrace_file_bytes.insert(thread_id, estimated_bytes);
+                self.resident_bytes += estimated_bytes;
+            },
+            Err(e) => {
+                println!("load thread stacktrace file failed: {}, err: {}", path_to_string(&thread_stack_file), e);
+                self.sample_stacktrace_map.insert(thread_id, None);
+            }
+        }
+    }
+
     //按周期滚动更换数据保存目录
     fn check_and_roll_data_dir(&mut self, sample_time: i64) -> io::Result<bool> {
         //采样文件最大时间周期
-        if self.record_start_time==0 || sample_time - self.record_start_time > 900_000 {
+        if self.record_start_time==0 || (self.roll_data_dir && sample_time - self.record_start_time > 900_000) {
             //create sample data dir
             let now = Local::now();
             let now_time = now.format("%Y%m%dT%H%M%S").to_string();
-            let sample_data_dir = format!("{}/{}-{}", FLARE_SAMPLES_DIR, self.agent_addr.replace(":","_"), now_time);
-            std::fs::create_dir_all(sample_data_dir.clone())?;
-            println!("save sample data to dir: {}", sample_data_dir);
+            //同一个agent的多个会话同时开始时目录名称相同，加上序号
+            let sample_data_dir = create_unique_dir(&self.samples_root, &format!("{}-{}", sanitize_file_name(&self.agent_addr), now_time))?;
+            println!("save sample data to dir: {}", path_to_string(&sample_data_dir));
 
             //method info idx file
-            let method_idx_path = format!("{}/method_info", sample_data_dir);
+            let method_idx_path = sample_data_dir.join("method_info");
             if self.sample_method_idx_file.is_some() {
                 //copy old method info file to new dir
-                let old_method_file = format!("{}/method_info.fidx", self.sample_data_dir);
-                let new_method_file = format!("{}/method_info.fidx", sample_data_dir);
+                let old_method_file = self.sample_data_path.join("method_info.fidx");
+                let new_method_file = sample_data_dir.join("method_info.fidx");
                 std::fs::copy(old_method_file, new_method_file)?;
 
-                let old_method_file = format!("{}/method_info.fdata", self.sample_data_dir);
-                let new_method_file = format!("{}/method_info.fdata", sample_data_dir);
+                let old_method_file = self.sample_data_path.join("method_info.fdata");
+                let new_method_file = sample_data_dir.join("method_info.fdata");
                 std::fs::copy(old_method_file, new_method_file)?;
             }
             let mut method_idx_file = TupleIndexedFile::new_writer(&method_idx_path, ValueType::INT64)?;
 
+            //aggregation index
+            self.finish_agg_index();
+            match AggIndexBuilder::new(&sample_data_dir) {
+                Ok(builder) => self.agg_index_builder = Some(builder),
+                Err(e) => println!("create aggregation index failed: {}, err: {}", path_to_string(&sample_data_dir), e)
+            }
+
             self.record_start_time = sample_time;
-            self.sample_data_dir = sample_data_dir;
+            self.set_sample_data_path(sample_data_dir);
             self.sample_method_idx_file = Some(method_idx_file);
             let now = Local::now().timestamp_millis();
             self.method_info_update_time = now;
             self.sample_cpu_ts_map.clear();
+            self.write_stack_chunks();
             self.sample_stacktrace_map.clear();
+            self.metric_ts_map.clear();
             self.sample_cpu_ts_cache.clear();
             //reset sample count
             for thread in self.threads.values_mut() {
@@ -361,7 +788,8 @@ impl SampleCollector {
     }
 
     fn save_summary_info(&mut self) -> io::Result<()> {
-        if self.readonly {
+        //还没有收到取样时没有保存目录
+        if self.readonly || self.sample_data_dir.is_empty() {
             return Ok(());
         }
 
@@ -373,19 +801,31 @@ impl SampleCollector {
 
         let mut info = SummaryInfo {
             sample_info: self.get_sample_info(),
-            threads: vec![]
+            threads: vec![],
+            ingest_filter: self.ingest_filter.clone(),
+            ingest_stats: self.ingest_filter.as_ref().map(|_| self.ingest_stats.clone()),
         };
         for thread in self.threads.values() {
             info.threads.push(thread.clone());
         }
 
-        let path = format!("{}/summary_info.json", self.sample_data_dir);
+        let path = self.sample_data_path.join("summary_info.json");
         match file_utils::open_file(&path, true) {
             Ok(mut file) => {
                 let json = serde_json::to_string_pretty(&info).unwrap();
                 file.write_all(json.as_bytes());
                 file.set_len(json.as_bytes().len() as u64);
                 self.last_save_time = Local::now().timestamp_millis();
+                if self.thread_handles.has_reused() {
+                    if let Err(e) = self.thread_handles.save(&self.sample_data_path) {
+                        println!("save thread handles failed: {}", e);
+                    }
+                }
+                if let Some(builder) = self.agg_index_builder.as_ref() {
+                    if let Err(e) = builder.save_info() {
+                        println!("save aggregation index failed: {}", e);
+                    }
+                }
                 Ok(())
             }
             Err(e) => {
@@ -395,110 +835,522 @@ impl SampleCollector {
         }
     }
 
-    pub fn connect_agent(&mut self) -> io::Result<TcpStream> {
-        self.sample_type = "attach".to_string();
-        match TcpStream::connect(&self.agent_addr) {
-            Ok(mut stream) => {
-                println!("Successfully connected to flare agent at: {:?}", self.agent_addr);
-                self.connected = true;
-                Ok(stream)
-            }
-            Err(e) => {
-                println!("Failed to connect to flare agent: {:?}, error: {:?}", self.agent_addr, e);
-                Err(e)
-            }
-        }
+    //连接flare agent (JVM)
+    pub fn subscribe_events(&mut self) -> Result<bool, Error> {
+        let adapter = Box::new(JvmAgentAdapter::new(&self.agent_addr));
+        self.start_adapter(adapter)?;
+        Ok(true)
     }
 
-    pub fn subscribe_events(&mut self) -> Result<bool, Error> {
-        let mut stream = self.connect_agent()?;
-        let cmdValue = resp::Value::Array(vec![Value::String("subscribe-events".to_string())]);
-        let cmd = cmdValue.encode();
-        let size = stream.write(cmd.as_slice()).unwrap();
-        println!("start subscribe events, awaiting reply: {}", cmdValue.to_encoded_string()?);
+    //连接运行时适配器，在后台线程读取取样事件直到数据源结束或者关闭会话
+    pub fn start_adapter(&mut self, mut adapter: Box<RuntimeAdapter>) -> io::Result<()> {
+        //导入录制的事件流时不是实时取样，不检查接收延迟
+        self.sample_type = if adapter.is_realtime() { "attach" } else { "import" }.to_string();
+        adapter.connect()?;
+        self.connected = true;
+        self.adapter_shutdown_hook = adapter.shutdown_hook();
+        self.adapter_request_hook = adapter.request_hook();
+        self.agent_compat = adapter.agent_compat();
+        self.check_clock_sync(Local::now().timestamp_millis());
 
         if let Some(this_ref) = &self.this_ref {
-            self.agent_stream = Some(stream.try_clone()?);
             let this = this_ref.clone();
             std::thread::spawn(move ||{
-                let mut decoder = resp::Decoder::with_buf_bulk(BufReader::new(stream));
-                while match decoder.decode() {
-                    Ok(data) => {
-                        this.lock().unwrap().on_sample_data(data)
+                while match adapter.next_event() {
+                    Ok(Some(event)) => {
+                        this.lock().unwrap().on_sample_data(event)
                     },
+                    Ok(None) => false,
                     Err(e) => {
                         println!("Failed to receive data: {}", e);
                         false
                     }
                 }{}
-                println!("subscribe events is stopped.");
+                println!("subscribe events is stopped: {} {}", adapter.runtime(), adapter.target());
                 this.lock().unwrap().on_disconnected();
             });
         }
-        Ok(true)
+        Ok(())
     }
 
     fn on_disconnected(&mut self) {
+        //没有收到同步响应的事件不校正时间
+        if !self.pending_clock_events.is_empty() {
+            self.fix_clock_offset(0);
+        }
         self.running = false;
         self.disconnected = true;
+        //数据源已经结束，释放连接
+        if let Some(shutdown_hook) = self.adapter_shutdown_hook.take() {
+            shutdown_hook();
+        }
+        self.adapter_request_hook = None;
+        //有限的数据源(如快照文件)可能在1秒内读取完，强制保存最后的汇总信息
+        self.last_save_time = 0;
+        self.save_summary_info();
+        self.flush_stacktrace_files();
+        self.flush_series_and_methods();
+        self.finish_agg_index();
+    }
+
+    //写入剩余的聚合数据
+    fn finish_agg_index(&mut self) {
+        if let Some(mut builder) = self.agg_index_builder.take() {
+            if let Err(e) = builder.finish() {
+                println!("finish aggregation index failed: {}, err: {}", self.sample_data_dir, e);
+            }
+        }
     }
 
-    fn on_sample_data(&mut self, sample_data: resp::Value) -> bool {
+    fn on_sample_data(&mut self, event: AgentEvent) -> bool {
         if !self.running {
             return false;
         }
         //println!("events: \n{}", sample_data.to_string_pretty());
-        if let resp::Value::Array(data_vec) = sample_data {
-            if let Value::String(cmd) = &data_vec[0] {
-                if cmd == "method" {
-                    self.on_method_data(&data_vec);
-                } else if cmd == "thread" {
-                    self.on_thread_data(&data_vec);
-                } else if cmd == "sample_info" {
-                    self.on_sample_info_data(&data_vec);
-                }
+        let local_time = Local::now().timestamp_millis();
+        self.check_clock_sync(local_time);
+        //等待第一次时钟同步的响应，确定偏差之后再处理缓冲的事件
+        if self.is_waiting_clock_sync(&event) {
+            self.pending_clock_events.push(event);
+            if local_time - self.clock_sync_wait_start > CLOCK_SYNC_WAIT_MS || self.pending_clock_events.len() > MAX_PENDING_CLOCK_EVENTS {
+                println!("clock sync response timeout: {}, events are not corrected", self.agent_addr);
+                self.fix_clock_offset(0);
             }
+            return true;
+        }
+        let mut event = event;
+        if let AgentEvent::Thread(x) = &event {
+            if self.sample_type == "attach" {
+                self.data_quality.on_receive(x.time, local_time);
+            }
+            if let Some(backwards) = self.time_normalizer.check(x.time + self.clock_offset, self.sample_interval) {
+                self.on_clock_jump(x.time, backwards);
+            }
+        }
+        correct_event_time(&mut event, self.clock_offset + self.time_normalizer.get_adjust());
+        match event {
+            AgentEvent::Method(event) => self.on_method_data(&event),
+            AgentEvent::Thread(mut event) => {
+                self.data_quality.on_sample_time(event.time, self.sample_interval);
+                let stack_len = event.stacktrace.len();
+                if let Some(filter) = self.ingest_filter.as_ref() {
+                    if !filter.apply(&mut event, &mut self.ingest_stats) {
+                        return true;
+                    }
+                }
+                if !self.disk_guard.keep_sample(event.time, self.sample_interval) {
+                    return true;
+                }
+                self.data_quality.on_stack(stack_len, event.stacktrace.len());
+                if let Err(e) = self.on_thread_data(&event) {
+                    println!("save thread data failed: thread_id: {}, err: {}", event.id, e);
+                }
+            },
+            AgentEvent::SampleInfo(event) => self.on_sample_info_data(&event),
+            AgentEvent::Marker(event) => self.on_marker_data(&event),
+            AgentEvent::IntervalBegin(event) => self.on_interval_begin_data(&event),
+            AgentEvent::IntervalEnd(event) => self.on_interval_end_data(&event),
+            AgentEvent::DeadlockThread(event) => {
+                if let Err(e) = self.on_deadlock_thread_data(&event) {
+                    println!("save deadlock failed: thread_id: {}, err: {}", event.id, e);
+                }
+            },
+            AgentEvent::ThreadDump(event) => {
+                if let Err(e) = self.on_thread_dump_data(&event) {
+                    println!("save thread dump failed: time: {}, err: {}", event.time, e);
+                }
+                self.notify_agent_result_waiters();
+            },
+            AgentEvent::HeapHistogram(event) => {
+                if let Err(e) = self.on_heap_histogram_data(&event) {
+                    println!("save heap histogram failed: time: {}, err: {}", event.time, e);
+                }
+                self.notify_agent_result_waiters();
+            },
+            AgentEvent::Allocation(event) => {
+                if let Err(e) = self.on_allocation_data(&event) {
+                    println!("save allocation failed: thread_id: {}, err: {}", event.id, e);
+                }
+            },
+            AgentEvent::Finalizer(event) => {
+                if let Err(e) = self.on_finalizer_data(&event) {
+                    println!("save finalizer metric failed: time: {}, err: {}", event.time, e);
+                }
+            },
+            AgentEvent::Deoptimization(event) => {
+                if let Err(e) = self.on_deoptimization_data(&event) {
+                    println!("save deoptimization failed: method: {}, err: {}", event.method, e);
+                }
+            },
+            AgentEvent::ClassLoader(event) => {
+                if let Err(e) = self.on_class_loader_data(&event) {
+                    println!("save class loader failed: loader: {}, err: {}", event.name, e);
+                }
+            },
+            AgentEvent::Gc(event) => {
+                if let Err(e) = self.on_gc_data(&event) {
+                    println!("save gc pause failed: time: {}, err: {}", event.time, e);
+                }
+            },
+            AgentEvent::Diagnostic(event) => {
+                println!("agent {}: [{}] {} (x{})", event.level, event.kind, event.message, event.count);
+                if let Err(e) = self.on_diagnostic_data(&event) {
+                    println!("save session event failed: time: {}, err: {}", event.time, e);
+                }
+            },
+            AgentEvent::ClockSync(event) => self.on_clock_sync_data(&event, local_time),
+            //握手时已经处理
+            AgentEvent::Hello(_) => {}
         }
 
         self.save_summary_info();
+        self.check_flush(local_time);
+        self.collect_resource_metrics();
         true
     }
 
-    fn on_sample_info_data(&mut self, data_vec: &Vec<Value>) {
-        let start_time= get_resp_property_as_int(data_vec, "start_time", 1, 0);
-        let sample_interval= get_resp_property_as_int(data_vec, "sample_interval", 1, 0);
-        let last_sample_time= get_resp_property_as_int(data_vec, "last_sample_time", 1, 0);
+    //只有部分线程有新的取样时，其它线程缓冲的调用栈也按时间写入
+    fn check_flush(&mut self, now: i64) {
+        if now - self.last_flush_time >= self.flush_policy.interval_ms {
+            self.flush_stacktrace_files();
+        }
+    }
+
+    fn flush_stacktrace_files(&mut self) {
+        self.write_stack_chunks();
+        for (thread_id, idx_file) in self.sample_stacktrace_map.iter_mut() {
+            if let Some(idx_file) = idx_file {
+                if idx_file.get_buffered_bytes() > 0 {
+                    if let Err(e) = idx_file.flush() {
+                        println!("flush thread stack file failed: thread_id: {}, err: {}", thread_id, e);
+                    }
+                }
+            }
+        }
+        self.last_flush_time = Local::now().timestamp_millis();
+    }
+
+    //时序数据及方法名称默认在文件关闭时写入，连接断开时立即写入，关闭后可以马上打开取样目录
+    fn flush_series_and_methods(&mut self) {
+        let series = self.sample_cpu_ts_map.values_mut().filter_map(|x| x.as_mut()).chain(self.metric_ts_map.values_mut());
+        for ts in series {
+            if let Err(e) = ts.flush() {
+                println!("flush time series failed: {}, err: {:?}", ts.get_header_info().path.display(), e);
+            }
+        }
+        if let Some(method_idx_file) = self.sample_method_idx_file.as_mut() {
+            if let Err(e) = method_idx_file.flush() {
+                println!("flush method info file failed: {}, err: {}", self.sample_data_dir, e);
+            }
+        }
+    }
+
+    //未满的数据块也写入调用栈文件
+    fn write_stack_chunks(&mut self) {
+        for (thread_id, chunk) in self.stack_chunks.iter_mut() {
+            if chunk.is_empty() {
+                continue;
+            }
+            if let Some(Some(idx_file)) = self.sample_stacktrace_map.get_mut(thread_id) {
+                let (step, data) = chunk.take();
+                if let Err(e) = idx_file.add_value(TupleValue::uint32(step), &data) {
+                    println!("write thread stack chunk failed: thread_id: {}, err: {}", thread_id, e);
+                }
+            }
+        }
+    }
+
+    //只影响录制中的会话
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) -> io::Result<()> {
+        if self.readonly {
+            return Err(new_invalid_input_error("can not set flush policy of a saved sample"));
+        }
+        for idx_file in self.sample_stacktrace_map.values_mut() {
+            if let Some(idx_file) = idx_file {
+                idx_file.set_flush_policy(policy.interval_ms, policy.max_buffer_bytes as usize, policy.fsync);
+            }
+        }
+        self.flush_policy = policy;
+        Ok(())
+    }
+
+    pub fn get_flush_status(&self) -> FlushStatus {
+        let buffered_bytes = self.sample_stacktrace_map.values()
+            .map(|x| x.as_ref().map_or(0, |x| x.get_buffered_bytes()))
+            .sum::<usize>() + self.stack_chunks.values().map(|x| if x.is_empty() { 0 } else { x.get_encoded_len() }).sum::<usize>();
+        FlushStatus {
+            policy: self.flush_policy.clone(),
+            buffered_bytes,
+            last_flush_time: self.last_flush_time,
+        }
+    }
+
+    //按固定间隔采集目标进程的资源指标
+    fn collect_resource_metrics(&mut self) {
+        let now = Local::now().timestamp_millis();
+        if self.sample_data_dir == "" || now - self.last_metrics_time < METRIC_UNIT_TIME as i64 {
+            return;
+        }
+        self.last_metrics_time = now;
+        if self.target_pid > 0 && cfg!(target_os = "linux") {
+            match read_cgroup_metrics(self.target_pid, now) {
+                Ok(metrics) => {
+                    for metric in CGROUP_METRICS {
+                        let value = metrics.get_metric_value(metric.name);
+                        if value < 0 {
+                            continue;
+                        }
+                        if let Err(e) = add_metric_value(&mut self.metric_ts_map, &self.sample_data_path, metric, now, value) {
+                            println!("save metric failed: {}, err: {}", metric.name, e);
+                        }
+                    }
+                    self.cgroup_metrics = Some(metrics);
+                }
+                Err(e) => {
+                    println!("read cgroup metrics failed: pid: {}, err: {}, stop collecting", self.target_pid, e);
+                    self.target_pid = -1;
+                }
+            }
+        }
+        if self.record_host_metrics && cfg!(target_os = "linux") {
+            match read_host_metrics(now) {
+                Ok(metrics) => {
+                    for metric in HOST_METRICS {
+                        let value = metrics.get_metric_value(metric.name);
+                        if value < 0 {
+                            continue;
+                        }
+                        if let Err(e) = add_metric_value(&mut self.metric_ts_map, &self.sample_data_path, metric, now, value) {
+                            println!("save metric failed: {}, err: {}", metric.name, e);
+                        }
+                    }
+                    self.host_metrics = Some(metrics);
+                }
+                Err(e) => {
+                    println!("read host metrics failed: {}, stop collecting", e);
+                    self.record_host_metrics = false;
+                }
+            }
+        }
+    }
+
+    pub fn set_record_host_metrics(&mut self, record_host_metrics: bool) {
+        self.record_host_metrics = record_host_metrics;
+    }
+
+    pub fn set_clock_sync_config(&mut self, config: ClockSyncConfig) {
+        self.clock_sync_config = config;
+    }
+
+    //连接后立即同步一次，之后按配置的间隔同步
+    fn check_clock_sync(&mut self, now: i64) {
+        if self.adapter_request_hook.is_none() || !self.supports_agent_request(CLOCK_SYNC_REQUEST) {
+            return;
+        }
+        let interval = self.clock_sync_config.interval_secs * 1000;
+        if self.last_clock_sync_time > 0 && (interval <= 0 || now - self.last_clock_sync_time < interval) {
+            return;
+        }
+        self.last_clock_sync_time = now;
+        match self.send_agent_request(CLOCK_SYNC_REQUEST, new_clock_sync_args(now)) {
+            Ok(_) => {
+                if self.clock_sync_wait_start == 0 {
+                    self.clock_sync_wait_start = now;
+                }
+            }
+            Err(e) => println!("send clock sync request failed: {}, err: {}", self.agent_addr, e)
+        }
+    }
+
+    //已经发送第一次同步请求、还没有确定偏差时缓冲事件，同步响应及握手不缓冲
+    fn is_waiting_clock_sync(&self, event: &AgentEvent) -> bool {
+        if !self.clock_sync_config.correction || self.clock_offset_fixed || self.clock_sync_wait_start == 0 {
+            return false;
+        }
+        match event {
+            AgentEvent::ClockSync(_) | AgentEvent::Hello(_) => false,
+            _ => true
+        }
+    }
+
+    //确定会话的偏差，之后的同步只记录偏差的变化，不再改变事件时间，避免时间范围内的取样不连续
+    fn fix_clock_offset(&mut self, offset: i64) {
+        self.clock_offset_fixed = true;
+        self.time_normalizer.shift(offset - self.clock_offset);
+        self.clock_offset = offset;
+        let pending_events = std::mem::replace(&mut self.pending_clock_events, vec![]);
+        for event in pending_events {
+            self.on_sample_data(event);
+        }
+    }
+
+    fn on_clock_sync_data(&mut self, event: &ClockSyncEvent, local_time: i64) {
+        let sample = compute_clock_sample(event, local_time);
+        self.clock_estimator.add_sample(sample);
+        if let Some(best) = self.clock_estimator.get_best_sample() {
+            if self.clock_sync_config.correction && !self.clock_offset_fixed {
+                self.fix_clock_offset(get_clock_correction(&best));
+            }
+            self.data_quality.on_clock_sync(best.offset, self.clock_offset);
+            println!("clock sync: agent: {}, offset: {}ms, delay: {}ms, best offset: {}ms", self.agent_addr, sample.offset, sample.delay, best.offset);
+        }
+    }
+
+    //只影响之后收到的取样
+    pub fn set_ingest_filter(&mut self, filter: IngestFilter) -> io::Result<()> {
+        if self.readonly {
+            return Err(new_invalid_input_error("can not set ingest filter of a saved sample"));
+        }
+        self.ingest_filter = if filter.is_empty() { None } else { Some(filter) };
+        Ok(())
+    }
+
+    pub fn get_ingest_filter(&self) -> Option<&IngestFilter> {
+        self.ingest_filter.as_ref()
+    }
+
+    pub fn get_ingest_stats(&self) -> &IngestStats {
+        &self.ingest_stats
+    }
+
+    pub fn set_target_pid(&mut self, pid: i64) {
+        self.target_pid = pid;
+    }
+
+    pub fn get_target_pid(&self) -> i64 {
+        self.target_pid
+    }
+
+    pub fn get_metric_values(&self, group: &str, start_time: i64, end_time: i64, unit_time_ms: i64, transform: TSTransform) -> serde_json::Value {
+        get_metric_values(&self.metric_ts_map, group, start_time, end_time, unit_time_ms, transform)
+    }
+
+    pub fn get_metric_points(&self, name: &str, start_time: i64, end_time: i64) -> Vec<(i64, i64)> {
+        get_metric_points(&self.metric_ts_map, name, start_time, end_time)
+    }
+
+    //指标名称，按名称排序
+    pub fn get_metric_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.metric_ts_map.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn on_sample_info_data(&mut self, event: &SampleInfoEvent) {
+        let start_time = event.start_time;
+        let sample_interval = event.sample_interval;
+        let last_sample_time = event.last_sample_time;
         self.sample_start_time = start_time;
         self.sample_interval = sample_interval;
         println!("on sample info: start_time:{}, sample_interval:{}", start_time, sample_interval);
 
         self.check_and_roll_data_dir(last_sample_time);
+        self.record_agent_warning(start_time);
     }
 
-    fn on_method_data(&mut self, data_vec: &Vec<Value>) {
-        if let Some(Value::Integer(method_id)) = get_resp_property(data_vec, "id", 1) {
-            if let Some(Value::String(method_name)) = get_resp_property(data_vec, "name", 1) {
-                self.save_method_info(*method_id, method_name);
-            }else {
-                println!("parse method name failed")
+    //agent版本不一致时在会话事件中提示升级
+    fn record_agent_warning(&mut self, time: i64) {
+        if self.agent_warning_recorded || self.sample_data_dir == "" {
+            return;
+        }
+        let message = match self.agent_compat.as_ref().and_then(|x| x.warning.clone()) {
+            Some(message) => message,
+            None => return
+        };
+        self.agent_warning_recorded = true;
+        let session_event = SessionEvent {
+            time,
+            level: LEVEL_WARN.to_string(),
+            kind: KIND_AGENT_VERSION.to_string(),
+            message,
+            count: 1,
+        };
+        if let Err(e) = self.add_session_event(session_event) {
+            println!("save session event failed: {}", e);
+        }
+    }
+
+    pub fn get_agent_compat(&self) -> Option<&AgentCompat> {
+        self.agent_compat.as_ref()
+    }
+
+    //agent是否支持控制请求，旧版本agent及其它运行时不检查
+    pub fn supports_agent_request(&self, cmd: &str) -> bool {
+        match &self.agent_compat {
+            Some(compat) => compat.supports(request_capability(cmd)),
+            None => true
+        }
+    }
+
+    fn on_marker_data(&mut self, event: &MarkerEvent) {
+        if let Err(e) = self.add_marker(event.time, &event.label, &event.color, "agent") {
+            println!("save agent marker failed: {}", e);
+        }
+    }
+
+    fn on_interval_begin_data(&mut self, event: &IntervalBeginEvent) {
+        let thread_id = if event.thread_id > 0 { self.thread_handles.get_handle(event.thread_id) } else { 0 };
+        self.intervals.push(Interval {
+            name: event.name.clone(),
+            start_time: event.time,
+            end_time: -1,
+            thread_id,
+        });
+        self.save_intervals();
+    }
+
+    fn on_interval_end_data(&mut self, event: &IntervalEndEvent) {
+        let time = event.time;
+        //结束同一个线程最近一个未结束的阶段，支持嵌套；旧版本agent没有线程id，结束最近一个未结束的阶段
+        let thread_id = event.thread_id;
+        if let Some(interval) = self.intervals.iter_mut().rev()
+            .find(|x| x.end_time < 0 && (thread_id == 0 || x.thread_id % THREAD_HANDLE_GENERATION == thread_id)) {
+            interval.end_time = time;
+        }
+        self.save_intervals();
+    }
+
+    fn save_intervals(&mut self) {
+        if self.sample_data_dir == "" {
+            return;
+        }
+        let record_start_time = self.record_start_time;
+        let intervals: Vec<Interval> = self.intervals.iter()
+            .filter(|x| x.end_time < 0 || x.end_time >= record_start_time).cloned().collect();
+        if let Err(e) = save_intervals(&self.sample_data_path, &intervals) {
+            println!("save intervals failed: {}", e);
+        }
+    }
+
+    pub fn get_intervals(&self) -> Vec<Interval> {
+        self.intervals.clone()
+    }
+
+    //按名称查找阶段的时间范围，同名阶段按出现顺序用seq区分
+    pub fn get_interval_time_range(&self, name: &str, seq: usize) -> io::Result<(i64, i64)> {
+        match self.intervals.iter().filter(|x| x.name == name).nth(seq) {
+            Some(interval) => {
+                let end_time = if interval.end_time < 0 { self.last_record_time } else { interval.end_time };
+                Ok((interval.start_time, end_time))
             }
-        }else {
-            println!("parse method id failed")
+            None => Err(new_error(ErrorKind::NotFound, &format!("interval not found: {}", name)))
         }
     }
 
-    fn on_thread_data(&mut self, data_vec: &Vec<Value>) -> io::Result<()> {
-        //let map = parse_resp_properties(data_vec, 1);
-        let sample_time= get_resp_property_as_int(data_vec, "time", 1, 0);
-        let thread_id= get_resp_property_as_int(data_vec, "id", 1, 0);
-        let cpu_time= get_resp_property_as_int(data_vec, "cpu_time", 1, 0);
-        let cpu_time_delta= get_resp_property_as_int(data_vec, "cpu_time_delta", 1, 0);
-        let name= get_resp_property_as_str(data_vec, "name", 1, "");
-        let state= get_resp_property_as_str(data_vec, "state", 1, "");
-        let mut stacktrace = &vec![];
-        if let Some(Value::Array(x)) = get_resp_property(data_vec, "stacktrace", 1) {
-            stacktrace = x;
+    fn on_method_data(&mut self, event: &MethodEvent) {
+        self.save_method_info(event.id, &event.name);
+    }
+
+    fn on_thread_data(&mut self, event: &ThreadEvent) -> io::Result<()> {
+        let sample_time = event.time;
+        let (thread_id, reused) = self.thread_handles.resolve(event.id, event.start_time, &event.name, event.cpu_time, sample_time);
+        if reused {
+            self.on_thread_id_reused(event, thread_id);
         }
+        let cpu_time = event.cpu_time;
+        let cpu_time_delta = event.cpu_time_delta;
+        let name = event.name.as_str();
+        let state = event.state.as_str();
 
         //create thread cpu ts
         let mut is_new = false;
@@ -527,14 +1379,7 @@ impl SampleCollector {
         thread_data.state = state.to_string();
         thread_data.name = name.to_string();
 
-        //stacktrace
-        let mut stack_frames = Vec::with_capacity(stacktrace.len());
-        for frame in stacktrace {
-            if let Value::Integer(method_id) = frame {
-                stack_frames.push(*method_id);
-            }
-        }
-        thread_data.stacktrace = stack_frames;
+        thread_data.stacktrace = event.stacktrace.clone();
         //clone: break mut ref of self
         let thread_data = thread_data.clone();
 
@@ -547,11 +1392,16 @@ impl SampleCollector {
 
         //save thread cpu time
         let sample_interval = self.sample_interval as i32;
-        let sample_data_dir = &self.sample_data_dir;
+        let sample_data_path = &self.sample_data_path;
         let cpu_ts = self.sample_cpu_ts_map.entry(thread_id).or_insert_with(||{
-            let path = format!("{}/thread_{}_cpu_time", sample_data_dir, thread_id);
+            let path = sample_data_path.join(format!("thread_{}_cpu_time", thread_id));
             match TimeSeriesFileWriter::new(ValueType::INT32, sample_interval , sample_time, &path) {
-                Ok(ts) => Some(Box::new(ts)),
+                Ok(mut ts) => {
+                    if let Err(e) = ts.set_labels(get_cpu_ts_labels(thread_id, &thread_data.name)) {
+                        println!("set thread cpu ts labels failed: thread_id: {}, err: {}", thread_id, e);
+                    }
+                    Some(Box::new(ts))
+                },
                 Err(e) => {
                     println!("create thread cpu ts file failed: thread_id: {}, err: {}", thread_id, e);
                     None
@@ -566,10 +1416,18 @@ impl SampleCollector {
         }
 
         //save thread stack data
+        let has_raw_stacks = self.has_raw_stacks();
+        let flush_policy = &self.flush_policy;
         let thread_stack_idx = self.sample_stacktrace_map.entry(thread_id).or_insert_with(||{
-            let path = format!("{}/thread_{}_stack", sample_data_dir, thread_id);
+            if !has_raw_stacks {
+                return None;
+            }
+            let path = sample_data_path.join(format!("thread_{}_stack", thread_id));
             match TupleIndexedFile::new_writer(&path, ValueType::UINT32) {
-                Ok(idx_file) => Some(idx_file),
+                Ok(mut idx_file) => {
+                    idx_file.set_flush_policy(flush_policy.interval_ms, flush_policy.max_buffer_bytes as usize, flush_policy.fsync);
+                    Some(idx_file)
+                },
                 Err(e) => {
                     println!("create thread cpu ts file failed: thread_id: {}, err: {}", thread_id, e);
                     None
@@ -581,8 +1439,21 @@ impl SampleCollector {
 //            let stack_data = Value::Array(stacktrace.clone());
 //            idx_file.add_value(TupleValue::uint32(ts_steps), stack_data.encode().as_slice());
 
-            let data = serde_json::to_vec(&thread_data)?;
-            idx_file.add_value(TupleValue::uint32(ts_steps), &data);
+            //按列缓冲到数据块中，数据块满了或者刷新缓冲数据时写入
+            let chunk = self.stack_chunks.entry(thread_id).or_insert_with(StackChunkBuilder::new);
+            chunk.add(ts_steps, &thread_data);
+            if chunk.is_full() || chunk.get_encoded_len() as i64 >= flush_policy.max_buffer_bytes {
+                let (step, data) = chunk.take();
+                if let Err(e) = idx_file.add_value(TupleValue::uint32(step), &data) {
+                    println!("write thread stack chunk failed: thread_id: {}, err: {}", thread_id, e);
+                }
+            }
+        }
+
+        if let Some(builder) = self.agg_index_builder.as_mut() {
+            if let Err(e) = builder.add_sample(&thread_data) {
+                println!("update aggregation index failed: thread_id: {}, err: {}", thread_id, e);
+            }
         }
 
         Ok(())
@@ -600,8 +1471,17 @@ impl SampleCollector {
 
         let mut info = DashboardInfo {
             sample_info: self.get_sample_info(),
-            threads: vec![]
+            threads: vec![],
+            cgroup: self.cgroup_metrics.clone(),
+            host: self.host_metrics.clone(),
+            top_allocating_threads: vec![],
+            top_allocating_methods: vec![],
         };
+        if let Some(last_time) = self.allocation_samples.last_time() {
+            let report = get_allocation_rate(self, last_time - DASHBOARD_ALLOCATION_WINDOW, last_time, METRIC_UNIT_TIME as i64, DASHBOARD_TOP_ALLOCATORS);
+            info.top_allocating_threads = report.threads;
+            info.top_allocating_methods = report.methods;
+        }
 
         //println!("{:8} {:48} {:8} {:8} {:8} {:8} {:8} {:8}", "ID", "NAME", "GROUP", "PRIORITY", "STATE", "%CPU", "TIME", "DAEMON");
         for thread in self.threads.values_mut() {
@@ -619,6 +1499,36 @@ impl SampleCollector {
         info
     }
 
+    //过滤、排序并分页返回线程列表，同时返回过滤后的线程总数
+    pub fn list_threads(&self, query: &ThreadQuery) -> (usize, Vec<ThreadData>) {
+        let name_filter = query.name_filter.to_lowercase();
+        let mut threads: Vec<&ThreadData> = self.threads.values().filter(|thread| {
+            thread.sample_count > 0
+                && (name_filter == "" || thread.name.to_lowercase().contains(&name_filter))
+                && (query.state == "" || thread.state == query.state)
+        }).collect();
+        match query.sort_by {
+            ThreadSortBy::ID => threads.sort_by(|a, b| a.id.cmp(&b.id)),
+            ThreadSortBy::CPU => threads.sort_by(|a, b| a.cpu_time.cmp(&b.cpu_time)),
+            ThreadSortBy::SAMPLES => threads.sort_by(|a, b| a.sample_count.cmp(&b.sample_count)),
+            ThreadSortBy::NAME => threads.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+        if query.desc {
+            threads.reverse();
+        }
+        let total = threads.len();
+        let result = if query.page_size > 0 {
+            threads.into_iter().skip(query.page.saturating_mul(query.page_size)).take(query.page_size).cloned().collect()
+        } else {
+            threads.into_iter().cloned().collect()
+        };
+        (total, result)
+    }
+
+    pub fn get_sample_data_path(&self) -> &Path {
+        &self.sample_data_path
+    }
+
     pub fn get_sample_info(&self) -> SampleInfo {
         SampleInfo {
             sample_start_time: self.sample_start_time,
@@ -626,15 +1536,467 @@ impl SampleCollector {
             last_record_time: self.last_record_time,
             sample_interval: self.sample_interval,
             agent_addr: self.agent_addr.clone(),
-            sample_data_dir: self.sample_data_dir.clone()
+            sample_data_dir: self.sample_data_dir.clone(),
+            stack_retention: self.stack_retention.clone(),
+            data_quality: self.data_quality.clone(),
+            disk_guard: self.disk_guard.clone(),
         }
     }
 
+    pub fn set_stack_retention(&mut self, stack_retention: &str) -> io::Result<()> {
+        if self.readonly {
+            return Err(new_invalid_input_error("can not change stack retention of a saved sample"));
+        }
+        if stack_retention != STACK_RETENTION_RAW && stack_retention != STACK_RETENTION_AGGREGATED {
+            return Err(new_invalid_input_error(&format!("unknown stack retention: {}", stack_retention)));
+        }
+        self.stack_retention = stack_retention.to_string();
+        Ok(())
+    }
+
+    pub fn has_raw_stacks(&self) -> bool {
+        self.stack_retention != STACK_RETENTION_AGGREGATED
+    }
+
+    pub fn add_marker(&mut self, time: i64, label: &str, color: &str, source: &str) -> io::Result<Marker> {
+        let marker = Marker {
+            time,
+            label: label.to_string(),
+            color: color.to_string(),
+            source: source.to_string(),
+        };
+        self.markers.push(marker.clone());
+        self.markers.sort_by(|a, b| a.time.cmp(&b.time));
+        //录制中的会话数据目录会滚动，只保存属于当前目录时间范围的标记
+        let record_start_time = self.record_start_time;
+        let markers: Vec<Marker> = self.markers.iter().filter(|x| x.time >= record_start_time).cloned().collect();
+        if self.sample_data_dir != "" {
+            save_markers(&self.sample_data_path, &markers)?;
+        }
+        Ok(marker)
+    }
+
+    pub fn get_markers(&self) -> Vec<Marker> {
+        self.markers.clone()
+    }
+
+    //同名的视图被覆盖，保留原来的创建时间
+    pub fn save_view(&mut self, mut view: SavedView) -> io::Result<SavedView> {
+        check_view_name(&view.name)?;
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        match self.views.iter_mut().find(|x| x.name == view.name) {
+            Some(old) => {
+                view.created_time = old.created_time;
+                *old = view.clone();
+            }
+            None => self.views.push(view.clone())
+        }
+        save_views(&self.sample_data_path, &self.views)?;
+        Ok(view)
+    }
+
+    pub fn get_views(&self) -> Vec<SavedView> {
+        self.views.clone()
+    }
+
+    pub fn add_offcpu_profile(&mut self, mut profile: OffCpuProfile) -> io::Result<()> {
+        for stack in profile.stacks.iter_mut() {
+            stack.thread_handle = self.find_offcpu_thread_handle(&stack.thread_name, profile.start_time, profile.end_time);
+        }
+        self.offcpu_profiles.push(profile);
+        if self.sample_data_dir != "" {
+            save_offcpu_profiles(&self.sample_data_path, &self.offcpu_profiles)?;
+        }
+        Ok(())
+    }
+
+    //comm匹配且在取样期间存活的线程句柄，只有一个线程匹配时才关联
+    fn find_offcpu_thread_handle(&self, comm: &str, start_time: i64, end_time: i64) -> Option<i64> {
+        let mut handles = self.threads.values()
+            .filter(|x| match_comm(comm, &x.name))
+            .filter(|x| match self.thread_handles.get_thread_handle(x.id) {
+                Some(handle) => handle.end_time >= start_time && handle.start_time <= end_time,
+                //没有句柄记录(旧的取样目录)时不检查存活时间
+                None => true
+            })
+            .map(|x| x.id);
+        match (handles.next(), handles.next()) {
+            (Some(handle), None) => Some(handle),
+            _ => None
+        }
+    }
+
+    pub fn get_offcpu_profiles(&self) -> &[OffCpuProfile] {
+        &self.offcpu_profiles
+    }
+
+    fn on_deadlock_thread_data(&mut self, event: &DeadlockThreadEvent) -> io::Result<()> {
+        let mut thread = new_deadlock_thread(event);
+        thread.thread_id = self.thread_handles.get_handle(thread.thread_id);
+        thread.owner_id = self.thread_handles.get_handle(thread.owner_id);
+        let accepted = match self.deadlocks.last_mut() {
+            Some(cycle) if cycle.accept(event) => {
+                cycle.threads.push(thread.clone());
+                true
+            }
+            _ => false
+        };
+        if !accepted {
+            println!("deadlock detected: time: {}, cycle: {}, thread: {}, lock: {}", event.time, event.cycle, event.name, event.lock);
+            self.deadlocks.push(DeadlockCycle { time: event.time, cycle: event.cycle, threads: vec![thread] });
+        }
+        if self.sample_data_dir != "" {
+            save_deadlocks(&self.sample_data_path, &self.deadlocks)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_deadlocks(&self) -> &[DeadlockCycle] {
+        &self.deadlocks
+    }
+
+    //请求agent立即检测死锁，interval_ms > 0 时同时开启定期检测，0 关闭定期检测，< 0 保持不变
+    pub fn request_deadlock_detection(&self, interval_ms: i64) -> io::Result<()> {
+        let mut args = vec![];
+        if interval_ms >= 0 {
+            args.push(Value::String("interval".to_string()));
+            args.push(Value::Integer(interval_ms));
+        }
+        self.send_agent_request("detect-deadlocks", args)
+    }
+
+    fn on_thread_dump_data(&mut self, event: &ThreadDumpEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let info = save_thread_dump(&self.sample_data_path, event)?;
+        println!("thread dump saved: time: {}, threads: {}, size: {}", info.time, info.threads, info.size);
+        self.thread_dumps.push(info);
+        Ok(())
+    }
+
+    pub fn get_thread_dumps(&self) -> &[ThreadDumpInfo] {
+        &self.thread_dumps
+    }
+
+    pub fn load_thread_dump(&self, time: i64) -> io::Result<String> {
+        if !self.thread_dumps.iter().any(|x| x.time == time) {
+            return Err(new_invalid_input_error(&format!("thread dump not found: {}", time)));
+        }
+        load_thread_dump(&self.sample_data_path, time)
+    }
+
+    //dump中某个线程句柄的部分，dump时该线程id属于其它句柄(重用的线程id)时返回NotFound
+    pub fn load_thread_dump_of_thread(&self, time: i64, thread_handle: i64) -> io::Result<String> {
+        let content = self.load_thread_dump(time)?;
+        let thread_id = thread_handle % THREAD_HANDLE_GENERATION;
+        if self.thread_handles.find_handle_at(thread_id, time) != thread_handle {
+            return Err(new_error(ErrorKind::NotFound, &format!("thread {} is not alive at thread dump: {}", thread_handle, time)));
+        }
+        extract_thread(&content, thread_id)
+            .ok_or_else(|| new_error(ErrorKind::NotFound, &format!("thread {} not found in thread dump: {}", thread_handle, time)))
+    }
+
+    //请求agent获取完整线程dump，结果异步推送
+    pub fn request_thread_dump(&self) -> io::Result<()> {
+        self.send_agent_request("thread-dump", vec![])
+    }
+
+    //先检查一次，请求发出后结果可能已经收到
+    pub fn add_agent_result_waiter(&mut self, deadline: i64, mut waiter: AgentResultWaiter) {
+        if !waiter(self, None) {
+            self.agent_result_waiters.push((deadline, waiter));
+        }
+    }
+
+    fn notify_agent_result_waiters(&mut self) {
+        let waiters = std::mem::replace(&mut self.agent_result_waiters, vec![]);
+        for (deadline, mut waiter) in waiters {
+            if !waiter(self, None) {
+                self.agent_result_waiters.push((deadline, waiter));
+            }
+        }
+    }
+
+    //超时的请求发送错误
+    pub fn expire_agent_result_waiters(&mut self, now: i64) {
+        self.finish_agent_result_waiters(|deadline| deadline <= now, ErrorKind::TimedOut, "wait for agent result timeout");
+    }
+
+    fn finish_agent_result_waiters<F: Fn(i64) -> bool>(&mut self, expired: F, kind: ErrorKind, message: &str) {
+        let waiters = std::mem::replace(&mut self.agent_result_waiters, vec![]);
+        for (deadline, mut waiter) in waiters {
+            if expired(deadline) {
+                waiter(self, Some(new_error(kind, message)));
+            } else {
+                self.agent_result_waiters.push((deadline, waiter));
+            }
+        }
+    }
+
+    fn on_heap_histogram_data(&mut self, event: &HeapHistogramEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let histogram = HeapHistogram::from_event(event);
+        save_heap_histogram(&self.sample_data_path, &histogram)?;
+        let info = histogram.get_info();
+        println!("heap histogram saved: time: {}, classes: {}, total_bytes: {}", info.time, info.classes, info.total_bytes);
+        self.heap_histograms.push(info);
+        Ok(())
+    }
+
+    pub fn get_heap_histograms(&self) -> &[HeapHistogramInfo] {
+        &self.heap_histograms
+    }
+
+    pub fn load_heap_histogram(&self, time: i64) -> io::Result<HeapHistogram> {
+        if !self.heap_histograms.iter().any(|x| x.time == time) {
+            return Err(new_invalid_input_error(&format!("heap histogram not found: {}", time)));
+        }
+        load_heap_histogram(&self.sample_data_path, time)
+    }
+
+    fn on_allocation_data(&mut self, event: &AllocationEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let mut sample = new_allocation_sample(event);
+        sample.thread_id = self.thread_handles.get_handle(sample.thread_id);
+        append_allocation_sample(&self.sample_data_path, &sample)?;
+        self.allocation_samples.push(sample);
+        Ok(())
+    }
+
+    pub fn get_allocation_samples(&self) -> &AllocationStore {
+        &self.allocation_samples
+    }
+
+    fn on_class_loader_data(&mut self, event: &ClassLoaderEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let sample = new_class_loader_sample(event);
+        append_class_loader_sample(&self.sample_data_path, &sample)?;
+        self.class_loader_samples.push(sample);
+        Ok(())
+    }
+
+    pub fn get_class_loader_samples(&self) -> &[ClassLoaderSample] {
+        &self.class_loader_samples
+    }
+
+    fn on_gc_data(&mut self, event: &GcEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let pause = new_gc_pause(event);
+        append_gc_pause(&self.sample_data_path, &pause)?;
+        self.gc_pauses.push(pause);
+        Ok(())
+    }
+
+    pub fn get_gc_pauses(&self) -> &[GcPause] {
+        &self.gc_pauses
+    }
+
+    fn on_diagnostic_data(&mut self, event: &DiagnosticEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let session_event = new_session_event(event);
+        if session_event.kind == KIND_DROPPED_EVENTS {
+            self.data_quality.on_dropped(session_event.count);
+        }
+        self.add_session_event(session_event)
+    }
+
+    fn add_session_event(&mut self, session_event: SessionEvent) -> io::Result<()> {
+        append_session_event(&self.sample_data_path, &session_event)?;
+        self.session_events.push(session_event);
+        Ok(())
+    }
+
+    //线程id被新的线程重用，之后的取样保存到新的句柄
+    fn on_thread_id_reused(&mut self, event: &ThreadEvent, handle: JavaLong) {
+        let message = format!("thread id {} is reused by thread '{}', new samples are saved to thread handle {}", event.id, event.name, handle);
+        println!("{}: agent: {}", message, self.agent_addr);
+        if self.sample_data_dir == "" {
+            return;
+        }
+        let session_event = SessionEvent {
+            time: event.time,
+            level: LEVEL_INFO.to_string(),
+            kind: KIND_THREAD_ID_REUSED.to_string(),
+            message,
+            count: 1,
+        };
+        if let Err(e) = self.add_session_event(session_event) {
+            println!("save session event failed: {}", e);
+        }
+    }
+
+    pub fn get_thread_handles(&self) -> &[ThreadHandle] {
+        self.thread_handles.get_handles()
+    }
+
+    //取样时间倒退，之后的事件时间已经平移
+    fn on_clock_jump(&mut self, agent_time: i64, backwards: i64) {
+        let message = format!("sample time went backwards by {}ms, later events are shifted by {}ms", backwards, self.time_normalizer.get_adjust());
+        println!("{}: agent: {}", message, self.agent_addr);
+        self.data_quality.clock_jumps += 1;
+        if self.sample_data_dir == "" {
+            return;
+        }
+        let session_event = SessionEvent {
+            time: agent_time + self.clock_offset + self.time_normalizer.get_adjust(),
+            level: LEVEL_WARN.to_string(),
+            kind: KIND_CLOCK_JUMP.to_string(),
+            message,
+            count: 1,
+        };
+        if let Err(e) = self.add_session_event(session_event) {
+            println!("save session event failed: {}", e);
+        }
+    }
+
+    //磁盘空间不足时停止录制，保存已写入的数据后断开agent
+    pub fn stop_recording_on_disk_low(&mut self, free_mb: i64) -> io::Result<()> {
+        if self.readonly || self.disconnected {
+            return Err(new_invalid_input_error("sample session is not recording"));
+        }
+        let now = Local::now().timestamp_millis();
+        self.disk_guard.on_stop(now, free_mb);
+        self.add_disk_low_event(now, format!("disk free space is low: {}MB, recording is stopped", free_mb));
+        self.running = false;
+        self.last_save_time = 0;
+        self.save_summary_info();
+        self.flush_stacktrace_files();
+        if let Some(shutdown_hook) = self.adapter_shutdown_hook.take() {
+            shutdown_hook();
+        }
+        Ok(())
+    }
+
+    //磁盘空间不足时降采样，rate 为0或者1时恢复正常取样
+    pub fn set_disk_low_downsampling(&mut self, free_mb: i64, rate: i64) -> io::Result<()> {
+        if self.readonly || self.disconnected {
+            return Err(new_invalid_input_error("sample session is not recording"));
+        }
+        let now = Local::now().timestamp_millis();
+        if rate > 1 {
+            self.disk_guard.on_downsample(now, free_mb, rate);
+            self.add_disk_low_event(now, format!("disk free space is low: {}MB, keep one of every {} samples", free_mb, rate));
+        } else if self.disk_guard.is_downsampling() {
+            self.disk_guard.on_resume(now);
+            println!("disk free space is recovered: {}MB, resume sampling: {}", free_mb, self.sample_data_dir);
+        }
+        if self.sample_data_dir != "" {
+            self.last_save_time = 0;
+            self.save_summary_info();
+        }
+        Ok(())
+    }
+
+    pub fn get_disk_guard_state(&self) -> &DiskGuardState {
+        &self.disk_guard
+    }
+
+    fn add_disk_low_event(&mut self, time: i64, message: String) {
+        println!("{}: {}", message, self.sample_data_dir);
+        if self.sample_data_dir == "" {
+            return;
+        }
+        let session_event = SessionEvent {
+            time,
+            level: LEVEL_WARN.to_string(),
+            kind: KIND_DISK_LOW.to_string(),
+            message,
+            count: 1,
+        };
+        if let Err(e) = self.add_session_event(session_event) {
+            println!("save session event failed: {}", e);
+        }
+    }
+
+    pub fn get_cgroup_metrics(&self) -> Option<&CgroupMetrics> {
+        self.cgroup_metrics.as_ref()
+    }
+
+    pub fn get_session_events(&self) -> &[SessionEvent] {
+        &self.session_events
+    }
+
+    fn on_deoptimization_data(&mut self, event: &DeoptimizationEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        let record = new_deopt_record(event);
+        append_deopt_record(&self.sample_data_path, &record)?;
+        self.deopt_records.push(record);
+        Ok(())
+    }
+
+    pub fn get_deopt_records(&self) -> &[DeoptRecord] {
+        &self.deopt_records
+    }
+
+    fn on_finalizer_data(&mut self, event: &FinalizerEvent) -> io::Result<()> {
+        if self.sample_data_dir == "" {
+            return Err(new_invalid_input_error("sample data dir is not created"));
+        }
+        add_metric_value(&mut self.metric_ts_map, &self.sample_data_path, FINALIZER_PENDING_METRIC, event.time, event.pending)
+    }
+
+    //请求agent统计堆直方图，limit为0时返回全部的类
+    pub fn request_heap_histogram(&self, force_gc: bool, limit: i64) -> io::Result<()> {
+        self.send_agent_request("heap-histogram", vec![
+            Value::String("force_gc".to_string()),
+            Value::Integer(force_gc as i64),
+            Value::String("limit".to_string()),
+            Value::Integer(limit),
+        ])
+    }
+
+    //通过运行时适配器发送控制请求: [cmd, key1, value1, ...]
+    fn send_agent_request(&self, cmd: &str, args: Vec<Value>) -> io::Result<()> {
+        if let Some(compat) = &self.agent_compat {
+            compat.check_request(cmd)?;
+        }
+        let hook = self.adapter_request_hook.as_ref()
+            .ok_or_else(|| new_invalid_input_error(&format!("session does not support agent request: {}", cmd)))?;
+        let mut request = vec![Value::String(cmd.to_string())];
+        request.extend(args);
+        hook(&Value::Array(request))
+    }
+
+    pub fn list_series(&self) -> Vec<SeriesInfo> {
+        let metric_series = self.metric_ts_map.values();
+        let mut series: Vec<SeriesInfo> = self.sample_cpu_ts_map.values().filter_map(|x| x.as_ref()).chain(metric_series).map(|ts| {
+            let info = ts.get_header_info();
+            let name = std::path::Path::new(&info.path).file_stem().and_then(|x| x.to_str()).unwrap_or("").to_string();
+            SeriesInfo {
+                name,
+                value_type: format!("{:?}", info.value_type),
+                metric_kind: format!("{:?}", info.metric_kind),
+                unit_time: info.unit_time,
+                begin_time: info.begin_time,
+                end_time: info.end_time,
+                labels: info.labels.clone(),
+            }
+        }).collect();
+        series.sort_by(|a, b| a.name.cmp(&b.name));
+        series
+    }
+
     pub fn get_sample_type(&self) -> String {
         self.sample_type.clone()
     }
 
     pub fn get_thread_cpu_time(&mut self, thread_id: &i64, start_time: i64, end_time: i64, unit_time_ms: i64) -> Option<Arc<TSResult>> {
+        let _span = enter_span("SampleCollector.get_thread_cpu_time");
 //        match self.sample_cpu_ts_map.get(thread_id) {
 //            Some(ts) => {
 //                match ts {
@@ -654,6 +2016,9 @@ impl SampleCollector {
         //只有打开取样文件才缓存CPU统计数据
         if self.sample_type == "file" {
             let cache_key = format!("thread_cpu_ts_{}_{}_{}_{}", thread_id, unit_time_ms, start_time, end_time);
+            if self.sample_cpu_ts_cache.len() >= MAX_CPU_TS_CACHE_ENTRIES && !self.sample_cpu_ts_cache.contains_key(&cache_key) {
+                self.sample_cpu_ts_cache.clear();
+            }
             self.sample_cpu_ts_cache.entry(cache_key).or_insert_with(||{
                 if let Some(tsf) = ts {
                     Some(Arc::new(tsf.get_range_value(start_time, end_time, unit_time_ms as i32)))
@@ -671,6 +2036,7 @@ impl SampleCollector {
     }
 
     pub fn get_collapsed_call_stacks(&mut self, thread_id: i64, start_time: i64, end_time: i64, stats_type: StatsType) -> io::Result<Vec<String>> {
+        let _span = enter_span("SampleCollector.get_collapsed_call_stacks");
         let mut start_step = 0;
         let mut end_step = 0;
         let mut sw = Stopwatch::start_new();
@@ -686,18 +2052,17 @@ impl SampleCollector {
         //TODO 可能单次读取的数据比较多，导致内存消耗太大
         let mut thread_data_vec = vec![];
         let mut last_sample_time = 0;
-        self.sample_stacktrace_map.get_mut(&thread_id).unwrap_or(&mut None).as_mut().map(|idx_file| {
-            idx_file.get_range_value(&TupleValue::uint32(start_step), &TupleValue::uint32(end_step), |bytes|{
+        if let Ok(Some(range)) = self.map_stack_range(thread_id, start_step, end_step) {
+            for record in parse_stack_records(range.iter(), start_step, end_step) {
                 //parse stack data
-                if let Ok(mut thread_data) = serde_json::from_slice::<ThreadData>(bytes.as_slice()) {
-                    if last_sample_time != 0 {
-                        thread_data.self_duration = thread_data.sample_time - last_sample_time;
-                    }
-                    last_sample_time = thread_data.sample_time;
-                    thread_data_vec.push(thread_data);
+                let mut thread_data = record.to_thread_data();
+                if last_sample_time != 0 {
+                    thread_data.self_duration = thread_data.sample_time - last_sample_time;
                 }
-            });
-        });
+                last_sample_time = thread_data.sample_time;
+                thread_data_vec.push(thread_data);
+            }
+        }
         println!("thread: {}, load stacktrace cost:{}, count:{}", thread_id, sw.lap(), thread_data_vec.len());
 
         let mut collapsed_stacks = vec![];
@@ -729,8 +2094,40 @@ impl SampleCollector {
         Ok(collapsed_stacks)
     }
 
+    //读取线程指定时间范围的取样数据，并计算每次取样的self_duration
+    pub fn load_thread_samples(&mut self, thread_id: i64, start_time: i64, end_time: i64) -> io::Result<Vec<ThreadData>> {
+        let _span = enter_span("SampleCollector.load_thread_samples");
+        let end_time = if end_time < 0 { self.last_record_time } else { end_time };
+        let start_step;
+        let end_step;
+        if let Some(ts_file) = self.sample_cpu_ts_map.get(&thread_id).unwrap_or(&None) {
+            start_step = ts_file.time_to_step(start_time);
+            end_step = ts_file.time_to_step(end_time);
+        } else {
+            return Err(new_error(ErrorKind::NotFound, "thread cpu time file not found"));
+        }
+
+        let mut thread_data_vec: Vec<ThreadData> = vec![];
+        if let Some(range) = self.map_stack_range(thread_id, start_step, end_step)? {
+            for record in parse_stack_records(range.iter(), start_step, end_step) {
+                let thread_data = record.to_thread_data();
+                if let Some(last) = thread_data_vec.last_mut() {
+                    last.self_duration = thread_data.sample_time - last.sample_time;
+                }
+                thread_data_vec.push(thread_data);
+            }
+        }
+        //last sample duration: use sample interval
+        let sample_interval = self.sample_interval;
+        if let Some(last) = thread_data_vec.last_mut() {
+            last.self_duration = sample_interval;
+        }
+        Ok(thread_data_vec)
+    }
+
     //获取顺序排列（时间顺序）的方法调用树
-    pub fn get_sequenced_call_tree(&mut self, thread_id: i64, start_time: &mut i64, end_time: &mut i64, fill_method_name: bool) -> io::Result<Box<tree::TreeNode>> {
+    pub fn get_sequenced_call_tree(&mut self, thread_id: i64, start_time: &mut i64, end_time: &mut i64, fill_method_name: bool, idle_mode: IdleMode, idle_stats: &mut IdleStats) -> io::Result<Box<tree::TreeNode>> {
+        let _span = enter_span("SampleCollector.get_sequenced_call_tree");
         let mut start_step = 0;
         let mut end_step = 0;
         let mut sw = Stopwatch::start_new();
@@ -750,19 +2147,18 @@ impl SampleCollector {
         //TODO fix range
         let mut thread_data_vec = vec![];
         let mut last_thread_data: Option<ThreadData> = None;
-        self.sample_stacktrace_map.get_mut(&thread_id).unwrap_or(&mut None).as_mut().map(|idx_file| {
-            idx_file.get_range_value(&TupleValue::uint32(start_step), &TupleValue::uint32(end_step), |bytes| {
+        if let Ok(Some(range)) = self.map_stack_range(thread_id, start_step, end_step) {
+            for record in parse_stack_records(range.iter(), start_step, end_step) {
                 //parse stack data
-                if let Ok(mut thread_data) = serde_json::from_slice::<ThreadData>(bytes.as_slice()) {
-                    if last_thread_data.is_some() {
-                        let mut last_call = last_thread_data.take().unwrap();
-                        last_call.self_duration = thread_data.sample_time - last_call.sample_time;
-                        thread_data_vec.push(last_call);
-                    }
-                    last_thread_data = Some(thread_data);
+                let thread_data = record.to_thread_data();
+                if last_thread_data.is_some() {
+                    let mut last_call = last_thread_data.take().unwrap();
+                    last_call.self_duration = thread_data.sample_time - last_call.sample_time;
+                    thread_data_vec.push(last_call);
                 }
-            });
-        });
+                last_thread_data = Some(thread_data);
+            }
+        }
         //last method call
         if let Some(mut last_call) = last_thread_data {
             // how long of last method call duration?
@@ -774,6 +2170,7 @@ impl SampleCollector {
             }
         }
         println!("thread: {}, load stacktrace cost:{}, count:{}", thread_id, sw.lap(), thread_data_vec.len());
+        let mut thread_data_vec = self.apply_idle_mode(thread_data_vec, idle_mode, idle_stats);
 
         thread_data_vec.first_mut().map(|thread_data|{
             *start_time = thread_data.sample_time;
@@ -788,6 +2185,39 @@ impl SampleCollector {
         result
     }
 
+    //识别栈顶为空闲方法的取样，按idle_mode保留、合并或者丢弃，同时统计空闲时间占比
+    pub fn apply_idle_mode(&mut self, thread_data_vec: Vec<ThreadData>, idle_mode: IdleMode, idle_stats: &mut IdleStats) -> Vec<ThreadData> {
+        let mut result = Vec::with_capacity(thread_data_vec.len());
+        for mut thread_data in thread_data_vec {
+            //stacktrace[0] is top frame
+            let mut idle_frames = 0;
+            for method in &thread_data.stacktrace {
+                if !is_idle_frame(&self.get_method_name(*method)) {
+                    break;
+                }
+                idle_frames += 1;
+            }
+            let idle = idle_frames > 0;
+            idle_stats.add_sample(thread_data.self_duration, idle);
+            if !idle {
+                result.push(thread_data);
+                continue;
+            }
+            match idle_mode {
+                IdleMode::KEEP => {
+                    result.push(thread_data);
+                }
+                IdleMode::COLLAPSE => {
+                    thread_data.stacktrace.drain(0..idle_frames);
+                    thread_data.stacktrace.insert(0, IDLE_FRAME_ID);
+                    result.push(thread_data);
+                }
+                IdleMode::TRIM => {}
+            }
+        }
+        result
+    }
+
     //火焰图的顺序树
     //每一层与最后一个节点相同时进行合并，不同时append新节点
     //处理前后半个采样间隔的问题
@@ -841,129 +2271,219 @@ impl SampleCollector {
     }
 
     pub fn get_call_tree(&mut self, thread_ids: &[i64], start_time: i64, end_time: i64) -> io::Result<CallStackTree> {
+        let _span = enter_span("SampleCollector.get_call_tree");
         //TODO
         let mut stack_tree = CallStackTree::new(0, "CallStack");
         let mut sw = Stopwatch::start_new();
+        if !self.has_raw_stacks() && !self.readonly {
+            //录制中的会话重新读取已经写入的分钟汇总
+            self.reload_agg_index();
+        }
 
         for thread_id in thread_ids {
-            let mut start_step = 0;
-            let mut end_step = 0;
             sw.start();
-            if let Some(ts_file) = self.sample_cpu_ts_map.get(thread_id).unwrap_or(&None) {
-                start_step = ts_file.time_to_step(start_time);
-                end_step = ts_file.time_to_step(end_time);
-            }else {
-                continue;
-            }
-            println!("thread: {}, convert time to step cost:{}, steps:{}", thread_id, sw.lap(), end_step-start_step);
-
-            //TODO 可能单次读取的数据比较多，导致内存消耗太大
-            let mut thread_data_vec = vec![];
-            let mut last_sample_time = 0;
-            self.sample_stacktrace_map.get_mut(thread_id).unwrap_or(&mut None).as_mut().map(|idx_file| {
-                idx_file.get_range_value(&TupleValue::uint32(start_step), &TupleValue::uint32(end_step), |bytes|{
-                    //parse stack data
-                    if let Ok(mut thread_data) = serde_json::from_slice::<ThreadData>(bytes.as_slice()) {
-                        if last_sample_time != 0 {
-                            thread_data.duration = thread_data.sample_time - last_sample_time;
-                        }
-                        last_sample_time = thread_data.sample_time;
-                        thread_data_vec.push(thread_data);
+            //优先使用聚合索引中完整的分钟汇总，其余的时间范围读取原始取样数据
+            //  (prime_start, range_start, range_end): prime_start 到 range_start 之间的取样只用于计算第一次取样的增量时间
+            let mut raw_ranges = vec![(start_time, start_time, end_time)];
+            let mut stack_summaries = vec![];
+            if !self.has_raw_stacks() {
+                //没有原始调用栈，使用与时间范围相交的分钟汇总
+                raw_ranges = vec![];
+                if let Some(agg_index) = self.agg_index.as_mut() {
+                    for minute in agg_index.get_overlapping_minutes(*thread_id, start_time, end_time)? {
+                        stack_summaries.extend(minute.stacks.iter().cloned());
                     }
-                });
-            });
-            println!("thread: {}, load stacktrace cost:{}, count:{}", thread_id, sw.lap(), thread_data_vec.len());
-
-            //thread cpu_time 延时更新，暂时将增量时间平均分配到两次更新CPU时间中的方法调用上
-            let mut last_divide_cpu_time = 0;
-            let mut start = 0;
-            let mut end = 0;
-            let mut pending_thread_data_vec: Vec<&mut ThreadData> = vec![];
-            for (i,thread_data) in thread_data_vec.iter_mut().enumerate() {
-                if last_divide_cpu_time == 0 {
-                    last_divide_cpu_time = thread_data.cpu_time;
                 }
-                if thread_data.cpu_time != last_divide_cpu_time {
-                    end = i;
-                    let curr_cpu_time = thread_data.cpu_time;
-                    self.divide_cpu_time(pending_thread_data_vec, last_divide_cpu_time, curr_cpu_time);
-                    pending_thread_data_vec = vec![];
-                    last_divide_cpu_time = curr_cpu_time;
-                    start = end;
+            } else if let Some(agg_index) = self.agg_index.as_mut() {
+                let minutes = agg_index.get_minutes(*thread_id, start_time, end_time)?;
+                if let (Some(first), Some(last)) = (minutes.first(), minutes.last()) {
+                    //分钟汇总之后的取样从最后一个汇总分钟的取样开始计算增量，与只读取原始数据的结果一致
+                    raw_ranges = vec![(start_time, start_time, first.minute_time - 1),
+                                      (last.minute_time, last.minute_time + AGG_MINUTE_MS, end_time)];
+                    for minute in &minutes {
+                        stack_summaries.extend(minute.stacks.iter().cloned());
+                    }
+                    println!("thread: {}, load aggregation index cost:{}, minutes:{}", thread_id, sw.lap(), minutes.len());
                 }
-                pending_thread_data_vec.push(thread_data);
             }
-
-            for thread_data in &thread_data_vec {
-                self.add_stack_trace(&mut stack_tree, thread_data);
+            for stack in &stack_summaries {
+                self.add_stack_summary(&mut stack_tree, stack);
+            }
+            for (prime_start, range_start, range_end) in raw_ranges {
+                //每个线程及每段原始数据分别计算增量时间，不使用其它线程或者其它时间段的最后一次取样
+                stack_tree.reset_call_stack_time();
+                if range_start <= range_end {
+                    self.add_thread_call_stacks(&mut stack_tree, *thread_id, prime_start, range_start, range_end);
+                }
             }
             println!("thread: {}, build tree cost:{}", thread_id, sw.lap());
+        }
+        println!("total threads: {}, total cost:{}, tree nodes: {}, tree memory: {} bytes", thread_ids.len(), sw.elapsed_ms(),
+                 stack_tree.node_count(), stack_tree.memory_size());
 
-//            if let Some(method_idx) = self.sample_method_idx_file.as_mut() {
-//                for method in stack_frames {
-//                    let bytes = method_idx.get_value(&TupleValue::int64(method))?;
-//                    let method_name = std::str::from_utf8(bytes.as_slice())?;
-//                }
-//            }
+        Ok(stack_tree)
+    }
 
+    //prime_start 到 start_time 之间的取样不加入调用树，只用于计算 start_time 之后第一次取样的增量时间
+    fn add_thread_call_stacks(&mut self, stack_tree: &mut CallStackTree, thread_id: i64, prime_start: i64, start_time: i64, end_time: i64) {
+        let start_step;
+        let end_step;
+        if let Some(ts_file) = self.sample_cpu_ts_map.get(&thread_id).unwrap_or(&None) {
+            start_step = ts_file.time_to_step(prime_start);
+            end_step = ts_file.time_to_step(end_time);
+        }else {
+            return;
         }
-        println!("total threads: {}, total cost:{}", thread_ids.len(), sw.elapsed_ms());
 
-        Ok(stack_tree)
+        //调用栈直接引用映射的文件数据，不为每次取样分配调用栈数组
+        let range = match self.map_stack_range(thread_id, start_step, end_step) {
+            Ok(Some(range)) => range,
+            _ => return
+        };
+        let mut records = parse_stack_records(range.iter(), start_step, end_step);
+
+        //thread cpu_time 延时更新，暂时将增量时间平均分配到两次更新CPU时间中的方法调用上
+        let mut last_divide_cpu_time = 0;
+        let mut pending_start = 0;
+        for i in 0..records.len() {
+            if last_divide_cpu_time == 0 {
+                last_divide_cpu_time = records[i].cpu_time;
+            }
+            if records[i].cpu_time != last_divide_cpu_time {
+                let curr_cpu_time = records[i].cpu_time;
+                Self::divide_cpu_time(&mut records[pending_start..i], last_divide_cpu_time, curr_cpu_time);
+                pending_start = i;
+                last_divide_cpu_time = curr_cpu_time;
+            }
+        }
+
+        for record in &records {
+            if record.sample_time < start_time {
+                stack_tree.start_call_stack(record.sample_time, record.cpu_time);
+                continue;
+            }
+            self.add_stack_trace(stack_tree, record.sample_time, record.cpu_time, record.frames().rev());
+        }
     }
 
-    fn divide_cpu_time(&mut self, mut thread_data_batch: Vec<&mut ThreadData>, last_cpu_time: i64, curr_cpu_time: i64){
-        let cpu_time_per_trace = (curr_cpu_time - last_cpu_time) / thread_data_batch.len() as i64;
+    fn divide_cpu_time(records: &mut [StackRecord], last_cpu_time: i64, curr_cpu_time: i64) {
+        let cpu_time_per_trace = (curr_cpu_time - last_cpu_time) / records.len() as i64;
         let mut thread_cpu_time = last_cpu_time;
-        let mut i=0;
-        let len = thread_data_batch.len();
-        for thread_data in thread_data_batch.as_mut_slice() {
-            if i < len {
-                thread_cpu_time += cpu_time_per_trace;
-            }else {
-                thread_cpu_time = curr_cpu_time;
-            }
-            i += 1;
-            (*thread_data).cpu_time = thread_cpu_time;
-            //thread_data.cpu_time_delta =
+        for record in records.iter_mut() {
+            thread_cpu_time += cpu_time_per_trace;
+            record.cpu_time = thread_cpu_time;
         }
     }
 
-    fn add_stack_trace(&mut self, call_tree: &mut CallStackTree, thread_data: &ThreadData) {
+    //映射线程调用栈文件中的取样记录，没有调用栈文件时返回None
+    fn map_stack_range(&mut self, thread_id: JavaLong, start_step: u32, end_step: u32) -> io::Result<Option<MappedRange>> {
+        match self.get_stacktrace_file(thread_id) {
+            Some(idx_file) => idx_file.map_range(&TupleValue::uint32(start_step), &TupleValue::uint32(end_step)).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    //frames 从栈底开始
+    fn add_stack_trace<I: Iterator<Item=JavaMethod>>(&mut self, call_tree: &mut CallStackTree, sample_time: i64, cpu_time: i64, frames: I) {
 
         call_tree.reset_top_call_stack_node();
-        let (delta_duration, delta_cpu_time) = call_tree.start_call_stack(thread_data.sample_time, thread_data.cpu_time);
+        let (delta_duration, delta_cpu_time) = call_tree.start_call_stack(sample_time, cpu_time);
 
-        //save nodes in temp vec, process it after build call tree, avoid second borrow muttable *self
-        let mut naming_nodes: Vec<(NodeId, JavaMethod)> = vec![];
+        //save methods in temp vec, process it after build call tree, avoid second borrow muttable *self
+        let mut naming_methods: Vec<JavaMethod> = vec![];
 
         //reverse call
-        for method_id in thread_data.stacktrace.iter().rev() {
-            if !call_tree.begin_call(method_id, delta_duration, delta_cpu_time) {
-                naming_nodes.push((call_tree.get_top_node().data.node_id, method_id.clone()));
+        for method_id in frames {
+            if !call_tree.begin_call(&method_id, delta_duration, delta_cpu_time) {
+                naming_methods.push(method_id);
             }
         }
 
         //call_tree.end_last_call(cpu_time);
         //println!("add call stack: {} cpu_time:{}", thread_info.name, cpu_time);
 
-        //get method call_name of node
-        for (node_id, method_id) in naming_nodes {
+        self.set_method_names(call_tree, naming_methods);
+    }
+
+    //新增节点的方法名称，每个方法只查询一次
+    fn set_method_names(&mut self, call_tree: &mut CallStackTree, methods: Vec<JavaMethod>) {
+        for method_id in methods {
+            if call_tree.has_method_name(method_id) {
+                continue;
+            }
             if let Some(method_info) = self.get_method_info(method_id) {
-                call_tree.get_mut_node(&node_id).data.name = method_info.full_name.clone();
+                call_tree.set_method_name(method_id, &method_info.full_name);
+            }
+        }
+    }
+
+    //把时间范围内关联到线程的off-CPU调用栈合并到调用树，阻塞时间四舍五入到ms计入duration，没有取样次数及CPU时间
+    pub fn add_offcpu_call_stacks(&mut self, call_tree: &mut CallStackTree, thread_ids: &[i64], start_time: i64, end_time: i64) {
+        let mut stacks = vec![];
+        for thread_id in thread_ids {
+            stacks.extend(merge_offcpu_stacks(&self.offcpu_profiles, start_time, end_time, "", *thread_id));
+        }
+        for stack in &stacks {
+            call_tree.reset_top_call_stack_node();
+            let duration = (stack.off_cpu_us + 500) / 1000;
+            let mut naming_methods: Vec<JavaMethod> = vec![];
+            for frame in stack.frames.iter().rev() {
+                let method_id = self.get_offcpu_frame_id(frame);
+                if !call_tree.begin_calls(&method_id, 0, duration, 0) {
+                    naming_methods.push(method_id);
+                }
+            }
+            self.set_method_names(call_tree, naming_methods);
+        }
+    }
 
+    fn get_offcpu_frame_id(&mut self, frame: &str) -> JavaMethod {
+        if let Some(method_id) = self.offcpu_frame_ids.get(frame) {
+            return *method_id;
+        }
+        let method_id = OFFCPU_FRAME_ID_START - self.offcpu_frame_ids.len() as i64;
+        self.offcpu_frame_ids.insert(frame.to_string(), method_id);
+        self.add_synthetic_frame(method_id, frame);
+        method_id
+    }
+
+    //合并聚合索引中的调用栈汇总
+    fn add_stack_summary(&mut self, call_tree: &mut CallStackTree, stack: &StackSummary) {
+        call_tree.reset_top_call_stack_node();
+        let mut naming_methods: Vec<JavaMethod> = vec![];
+        for method_id in stack.frames.iter().rev() {
+            if !call_tree.begin_calls(method_id, stack.samples, stack.duration, stack.cpu_time) {
+                naming_methods.push(*method_id);
             }
         }
+        self.set_method_names(call_tree, naming_methods);
+    }
+
+    //注册合成的调用栈节点名称，如 <idle>
+    pub fn add_synthetic_frame(&mut self, method: JavaMethod, name: &str) {
+        self.synthetic_frames.insert(method, name.to_string());
+        self.method_cache.remove(&method);
     }
 
     pub fn get_method_info(&mut self, method: JavaMethod) -> &Option<MethodInfo> {
         let method_idx_file = self.sample_method_idx_file.as_mut();
+        let synthetic_frames = &self.synthetic_frames;
+        let mapping = &self.mapping;
         self.method_cache.entry(method).or_insert_with(|| {
+            if let Some(name) = synthetic_frames.get(&method) {
+                return Some(MethodInfo {
+                    method_id: method,
+                    full_name: name.clone(),
+                    hits_count: 0
+                });
+            }
             if let Some(method_idx) = method_idx_file {
                 if let Ok(bytes) = method_idx.get_value(&TupleValue::int64(method)){
                     let mut method_name = std::str::from_utf8(bytes.as_slice()).unwrap_or("").to_string();
                     if method_name == "" {
                         method_name = method.to_string();
+                    } else if let Some(name) = mapping.as_ref().and_then(|x| x.translate(&method_name)) {
+                        method_name = name;
                     }
                     return Some(MethodInfo {
                         method_id: method,
@@ -976,7 +2496,55 @@ impl SampleCollector {
         })
     }
 
+    //统计JDBC驱动方法的耗时，按调用的业务方法聚合
+    pub fn get_database_time(&mut self, thread_ids: &[i64], start_time: i64, end_time: i64) -> io::Result<DatabaseTimeResult> {
+        let _span = enter_span("SampleCollector.get_database_time");
+        let mut analysis = DatabaseAnalysis::new();
+        for thread_id in thread_ids {
+            match self.load_thread_samples(*thread_id, start_time, end_time) {
+                Ok(thread_data_vec) => {
+                    analysis.add_samples(self, &thread_data_vec);
+                }
+                Err(e) => {
+                    println!("load thread samples failed, thread: {}, error: {}", thread_id, e);
+                }
+            }
+        }
+        Ok(analysis.get_result())
+    }
+
+    pub fn get_method_name(&mut self, method: JavaMethod) -> String {
+        match self.get_method_info(method) {
+            Some(method_info) => method_info.full_name.clone(),
+            None => method.to_string()
+        }
+    }
+
+    //加载混淆映射文件，之后查询结果中的方法名都会还原为原始名称
+    //重新加载聚合索引，如build_index之后
+    pub fn reload_agg_index(&mut self) {
+        self.agg_index = None;
+        if has_agg_index(&self.sample_data_path) {
+            match AggIndex::open(&self.sample_data_path) {
+                Ok(agg_index) => self.agg_index = Some(agg_index),
+                Err(e) => println!("load aggregation index failed: {}, err: {}", self.sample_data_dir, e)
+            }
+        }
+    }
+
+    pub fn load_mapping(&mut self, mapping_file: &str) -> io::Result<(usize, usize)> {
+        let mapping = ProguardMapping::load(mapping_file)?;
+        let counts = (mapping.get_class_count(), mapping.get_method_count());
+        self.mapping = Some(mapping);
+        //清除使用旧名称的缓存
+        self.method_cache.clear();
+        self.method_entries.clear();
+        self.call_tree_cahce.clear();
+        Ok(counts)
+    }
+
     pub fn list_methods_by_filter(&mut self, method_name_filter: &str) -> io::Result<Vec<MethodInfo>> {
+        let _span = enter_span("SampleCollector.list_methods_by_filter");
         let mut method_infos = vec![];
         if let Some(method_idx_file) = &mut self.sample_method_idx_file {
 
@@ -985,18 +2553,29 @@ impl SampleCollector {
             if self.method_info_update_time > self.method_entry_cache_time || self.method_entries.is_empty() {
                 println!("get all method entries ...");
                 let entries = method_idx_file.get_all_entries()?;
+                self.method_entries.clear();
                 for (method,bytes) in &entries {
                     let method_name;
                     unsafe {
                         method_name = std::str::from_utf8_unchecked(&bytes);
                     }
+                    let full_name = match &self.mapping {
+                        Some(mapping) => mapping.translate(method_name).unwrap_or(method_name.to_string()),
+                        None => method_name.to_string()
+                    };
                     self.method_entries.push(MethodInfo {
                         method_id: *method,
-                        full_name: method_name.to_string(),
+                        full_name,
                         hits_count: 0
                     });
                 }
                 self.method_entry_cache_time = now;
+                //只缓存未经过映射转换的只读取样
+                if self.readonly && !self.write_protected && self.mapping.is_none() && self.symbol_cache_key != "" {
+                    if let Err(e) = save_symbol_cache(&self.symbol_cache_key, &self.method_entries) {
+                        println!("save symbol cache failed: {}", e);
+                    }
+                }
             }
             for method_info in &self.method_entries {
                 let method_name = &method_info.full_name;
@@ -1012,10 +2591,11 @@ impl SampleCollector {
 
     //search slow method calls
     pub  fn search_slow_method_calls(&mut self, thread_id: i64, method_ids: &[i64], min_duration: i64, max_duration: i64) -> io::Result<Vec<Box<MethodCall>>> {
+        let _span = enter_span("SampleCollector.search_slow_method_calls");
         let mut method_calls = vec![];
 
-        let mut start_time = self.record_start_time;
-        let mut end_time = self.last_record_time;
+        let start_time = self.record_start_time;
+        let end_time = self.last_record_time;
         let mut thread_name = "".to_owned();
         if let Some(thread) = self.threads.get(&thread_id) {
             thread_name = thread.name.clone();
@@ -1030,12 +2610,26 @@ impl SampleCollector {
 //        }
 //        self.search_call_tree(&mut method_calls,  call_tree.unwrap(), thread_id, &thread_name, method_ids, min_duration, max_duration);
 
-        let mut call_tree = self.get_sequenced_call_tree(thread_id, &mut start_time, &mut end_time, false)?;
-        self.search_call_tree(&mut method_calls,  &call_tree, thread_id, &thread_name, method_ids, min_duration, max_duration);
+        //只读取可能包含这些方法的时间范围
+        let ranges = self.get_method_search_ranges(thread_id, method_ids, start_time, end_time)?;
+        for (mut range_start, mut range_end) in ranges {
+            let mut idle_stats = IdleStats::default();
+            let call_tree = self.get_sequenced_call_tree(thread_id, &mut range_start, &mut range_end, false, IdleMode::KEEP, &mut idle_stats)?;
+            self.search_call_tree(&mut method_calls,  &call_tree, thread_id, &thread_name, method_ids, min_duration, max_duration);
+        }
 
         Ok(method_calls)
     }
 
+    //使用聚合索引的分钟方法集合排除不包含方法的时间，没有聚合索引时返回整个时间范围
+    //按方法搜索调用栈的只有 search_slow_method_calls，其它查询按时间范围读取，不使用方法索引
+    fn get_method_search_ranges(&mut self, thread_id: i64, method_ids: &[i64], start_time: i64, end_time: i64) -> io::Result<Vec<(i64, i64)>> {
+        if let Some(agg_index) = self.agg_index.as_mut() {
+            return agg_index.get_method_ranges(thread_id, method_ids, start_time, end_time);
+        }
+        Ok(vec![(start_time, end_time)])
+    }
+
     fn search_call_tree(&self, result: &mut Vec<Box<MethodCall>>, node: &Box<tree::TreeNode>, thread_id: i64, thread_name: &str, method_ids: &[i64], min_duration: i64, max_duration: i64) {
         //TODO search
         if node.duration >= min_duration {
@@ -1144,6 +2738,7 @@ impl Drop for SampleCollector {
     fn drop(&mut self) {
         println!("dropping sample collector: {} ..", self.sample_data_dir);
         self.save_summary_info();
+        self.finish_agg_index();
         self.close();
     }
 }
\ No newline at end of file
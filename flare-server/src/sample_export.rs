@@ -0,0 +1,123 @@
+
+use ::sample::*;
+use sample_writer::SampleWriter;
+use std::collections::HashMap;
+use std::io;
+use serde_json::json;
+use utils::*;
+
+//默认不做混淆的类名前缀(JDK类)
+pub const DEFAULT_KEEP_PACKAGES: &[&str] = &["java.", "javax.", "jdk.", "sun.", "com.sun."];
+
+pub struct ExportOptions {
+    //none: 保持原始名称, hash: 按名称哈希生成固定的标识, map: 按出现顺序编号
+    pub anonymize: String,
+    pub keep_packages: Vec<String>,
+    //原始名称映射文件(类名/方法名及线程名)，不要与导出的目录一起分享
+    pub mapping_file: String,
+}
+
+struct NameAnonymizer {
+    mode: String,
+    keep_packages: Vec<String>,
+    //original -> opaque
+    classes: HashMap<String, String>,
+    methods: HashMap<String, String>,
+    threads: HashMap<String, String>,
+    //opaque full name -> original full name
+    mapping: HashMap<String, String>,
+    //opaque thread name -> original thread name
+    thread_mapping: HashMap<String, String>,
+}
+
+impl NameAnonymizer {
+
+    fn new(mode: &str, keep_packages: &[String]) -> NameAnonymizer {
+        NameAnonymizer {
+            mode: mode.to_string(),
+            keep_packages: keep_packages.to_vec(),
+            classes: HashMap::new(),
+            methods: HashMap::new(),
+            threads: HashMap::new(),
+            mapping: HashMap::new(),
+            thread_mapping: HashMap::new(),
+        }
+    }
+
+    //full name: com.foo.Bar.method()
+    fn anonymize(&mut self, full_name: &str) -> String {
+        if self.mode == "none" || self.keep_packages.iter().any(|x| full_name.starts_with(x.as_str())) {
+            return full_name.to_string();
+        }
+        let name = full_name.trim_end_matches("()");
+        let (class_name, method_name) = match name.rfind('.') {
+            Some(pos) => (&name[0..pos], &name[pos+1..]),
+            None => ("", name)
+        };
+        let class_id = NameAnonymizer::get_opaque_id(&self.mode, &mut self.classes, "c", class_name);
+        let method_id = NameAnonymizer::get_opaque_id(&self.mode, &mut self.methods, "m", method_name);
+        let new_name = format!("{}.{}()", class_id, method_id);
+        self.mapping.insert(new_name.clone(), full_name.to_string());
+        new_name
+    }
+
+    //线程名称通常包含服务或者业务名称，混淆时全部替换，不保留JDK线程的名称
+    fn anonymize_thread(&mut self, thread_name: &str) -> String {
+        if self.mode == "none" {
+            return thread_name.to_string();
+        }
+        let new_name = NameAnonymizer::get_opaque_id(&self.mode, &mut self.threads, "t", thread_name);
+        self.thread_mapping.insert(new_name.clone(), thread_name.to_string());
+        new_name
+    }
+
+    fn get_opaque_id(mode: &str, cache: &mut HashMap<String, String>, prefix: &str, name: &str) -> String {
+        let next_id = cache.len() + 1;
+        cache.entry(name.to_string()).or_insert_with(|| {
+            if mode == "hash" {
+                format!("{}{:016x}", prefix, fnv1a_hash(name.as_bytes()))
+            } else {
+                format!("{}{}", prefix, next_id)
+            }
+        }).clone()
+    }
+}
+
+//导出取样数据到新的目录，可选对类名/方法名进行混淆
+pub fn export_sample(collector: &mut SampleCollector, export_dir: &str, options: &ExportOptions) -> io::Result<String> {
+    match options.anonymize.as_str() {
+        "none" | "hash" | "map" => {},
+        _ => return Err(new_invalid_input_error(&format!("invalid anonymize mode: {}", options.anonymize)))
+    }
+    let sample_info = collector.get_sample_info();
+    let mut anonymizer = NameAnonymizer::new(&options.anonymize, &options.keep_packages);
+    let agent_addr = if options.anonymize == "none" { sample_info.agent_addr.clone() } else { "".to_string() };
+    let mut writer = SampleWriter::new(export_dir, sample_info.sample_interval, &agent_addr)?;
+    for method in collector.list_methods_by_filter("")? {
+        let name = anonymizer.anonymize(&method.full_name);
+        writer.add_method(method.method_id, &name)?;
+    }
+
+    let mut threads = collector.get_threads()?;
+    threads.sort_by(|a, b| a.id.cmp(&b.id));
+    for thread in &threads {
+        let thread_data_vec = collector.load_thread_samples(thread.id, -1, -1)?;
+        for thread_data in thread_data_vec {
+            let mut thread_data = thread_data;
+            thread_data.name = anonymizer.anonymize_thread(&thread_data.name);
+            writer.add_thread_sample(&thread_data)?;
+        }
+    }
+    let dir = writer.finish()?;
+
+    if options.mapping_file != "" && (!anonymizer.mapping.is_empty() || !anonymizer.thread_mapping.is_empty()) {
+        let json = serde_json::to_string_pretty(&json!({
+            "sample_data_dir": dir,
+            "mode": options.anonymize,
+            "methods": anonymizer.mapping,
+            "threads": anonymizer.thread_mapping
+        }))?;
+        std::fs::write(&options.mapping_file, json.as_bytes())?;
+    }
+    Ok(dir)
+}
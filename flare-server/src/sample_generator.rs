@@ -0,0 +1,252 @@
+
+//生成模拟的取样数据，用于存储及查询的性能测试
+
+use std::io;
+use ::sample::ThreadData;
+use sample_writer::SampleWriter;
+use utils::*;
+
+type JavaMethod = i64;
+
+//方法1为Thread.run(栈底)，方法2为Object.wait(空闲)，其余为普通方法，至少需要一个
+const MIN_METHODS: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct GeneratorOptions {
+    pub threads: usize,
+    pub duration_ms: i64,
+    pub sample_interval: i64,
+    pub start_time: i64,
+    //方法池大小，小于 MIN_METHODS 时使用 MIN_METHODS
+    pub methods: usize,
+    //每个线程的调用栈种类，少数调用栈占多数取样
+    pub stacks_per_thread: usize,
+    //调用栈深度在 [min_depth, max_depth] 之间，集中在 mean_depth 附近
+    pub min_depth: usize,
+    pub max_depth: usize,
+    pub mean_depth: usize,
+    //空闲(等待)取样占比
+    pub idle_ratio: f64,
+    pub seed: u64,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            threads: 16,
+            duration_ms: 60_000,
+            sample_interval: 20,
+            start_time: 1_570_000_000_000,
+            methods: 2000,
+            stacks_per_thread: 50,
+            min_depth: 5,
+            max_depth: 120,
+            mean_depth: 30,
+            idle_ratio: 0.3,
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct GeneratorStats {
+    pub sample_data_dir: String,
+    pub threads: usize,
+    pub samples: usize,
+    pub methods: usize,
+    pub frames: usize,
+}
+
+//xorshift64*，固定种子时生成的数据可重复
+pub struct SimpleRandom {
+    state: u64,
+}
+
+impl SimpleRandom {
+    pub fn new(seed: u64) -> SimpleRandom {
+        SimpleRandom { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    //[0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    //[0, bound)
+    pub fn next_usize(&mut self, bound: usize) -> usize {
+        if bound == 0 {
+            return 0;
+        }
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+struct GeneratedThread {
+    name: String,
+    //栈顶在前
+    stacks: Vec<Vec<JavaMethod>>,
+    //累计权重，按类似Zipf分布选择调用栈
+    weights: Vec<f64>,
+    idle_method: JavaMethod,
+    cpu_time: i64,
+}
+
+fn get_method_count(methods: usize) -> usize {
+    std::cmp::max(methods, MIN_METHODS)
+}
+
+//生成方法名称，方法id从1开始，与调用栈使用的方法id范围一致
+pub fn generate_method_names(methods: usize) -> Vec<String> {
+    let methods = get_method_count(methods);
+    let mut names = Vec::with_capacity(methods);
+    names.push("java.lang.Thread.run()V".to_string());
+    names.push("java.lang.Object.wait(J)V".to_string());
+    for i in names.len()..methods {
+        names.push(format!("com.example.service{}.Component{}.method{}()V", i % 37, i % 101, i));
+    }
+    names
+}
+
+//三角分布的调用栈深度
+fn next_depth(random: &mut SimpleRandom, options: &GeneratorOptions) -> usize {
+    let min = options.min_depth.max(1) as f64;
+    let max = options.max_depth.max(options.min_depth.max(1)) as f64;
+    let mode = (options.mean_depth as f64).max(min).min(max);
+    if max <= min {
+        return min as usize;
+    }
+    let u = random.next_f64();
+    let c = (mode - min) / (max - min);
+    let depth = if u < c {
+        min + (u * (max - min) * (mode - min)).sqrt()
+    } else {
+        max - ((1.0 - u) * (max - min) * (max - mode)).sqrt()
+    };
+    depth.round() as usize
+}
+
+//普通方法的id范围: [MIN_METHODS, methods]
+fn next_method(random: &mut SimpleRandom, methods: usize) -> JavaMethod {
+    (MIN_METHODS + random.next_usize(methods - MIN_METHODS + 1)) as JavaMethod
+}
+
+fn generate_threads(random: &mut SimpleRandom, options: &GeneratorOptions) -> Vec<GeneratedThread> {
+    let methods = get_method_count(options.methods);
+    let mut threads = Vec::with_capacity(options.threads);
+    for t in 0..options.threads {
+        let stack_count = std::cmp::max(options.stacks_per_thread, 1);
+        let mut stacks = Vec::with_capacity(stack_count);
+        //每个线程的调用栈共享相同的调用链前缀
+        let base_depth = next_depth(random, options) / 2;
+        let base: Vec<JavaMethod> = (0..base_depth).map(|_| next_method(random, methods)).collect();
+        for _ in 0..stack_count {
+            let depth = std::cmp::max(next_depth(random, options), base.len() + 1);
+            //栈底是 Thread.run
+            let mut frames = Vec::with_capacity(depth);
+            frames.push(1);
+            frames.extend_from_slice(&base[..std::cmp::min(base.len(), depth - 1)]);
+            while frames.len() < depth {
+                frames.push(next_method(random, methods));
+            }
+            frames.reverse();
+            stacks.push(frames);
+        }
+        let mut weights = Vec::with_capacity(stack_count);
+        let mut total = 0.0;
+        for i in 0..stack_count {
+            total += 1.0 / (i + 1) as f64;
+            weights.push(total);
+        }
+        threads.push(GeneratedThread {
+            name: format!("synthetic-worker-{}", t),
+            stacks,
+            weights,
+            idle_method: 2,
+            cpu_time: 0,
+        });
+    }
+    threads
+}
+
+fn pick_stack<'a>(random: &mut SimpleRandom, thread: &'a GeneratedThread) -> &'a Vec<JavaMethod> {
+    let total = *thread.weights.last().unwrap();
+    let value = random.next_f64() * total;
+    let pos = thread.weights.iter().position(|x| *x >= value).unwrap_or(thread.weights.len() - 1);
+    &thread.stacks[pos]
+}
+
+//按时间顺序生成所有线程的取样，callback返回Err时停止
+pub fn generate_thread_samples<F>(options: &GeneratorOptions, mut callback: F) -> io::Result<usize>
+    where F: FnMut(&ThreadData) -> io::Result<()> {
+    let mut random = SimpleRandom::new(options.seed);
+    let mut threads = generate_threads(&mut random, options);
+    let sample_interval = std::cmp::max(options.sample_interval, 1);
+    let steps = options.duration_ms / sample_interval;
+    let mut samples = 0;
+    for step in 0..steps {
+        let sample_time = options.start_time + step * sample_interval;
+        for (i, thread) in threads.iter_mut().enumerate() {
+            let idle = random.next_f64() < options.idle_ratio;
+            let (state, cpu_time_delta, stacktrace) = if idle {
+                ("WAITING", 0, vec![thread.idle_method, 1])
+            } else {
+                //cpu占用在取样间隔的50%~100%之间, nanos
+                let cpu = (sample_interval as f64 * (0.5 + random.next_f64() * 0.5) * 1_000_000.0) as i64;
+                ("RUNNABLE", cpu, pick_stack(&mut random, thread).clone())
+            };
+            thread.cpu_time += cpu_time_delta;
+            let thread_data = ThreadData {
+                id: 1000 + i as i64,
+                name: thread.name.clone(),
+                priority: 5,
+                daemon: false,
+                state: state.to_string(),
+                cpu_time: thread.cpu_time,
+                cpu_time_delta,
+                sample_time,
+                sample_count: 1,
+                stacktrace,
+                duration: 0,
+                self_duration: 0,
+                self_cpu_time: 0,
+            };
+            callback(&thread_data)?;
+            samples += 1;
+        }
+    }
+    Ok(samples)
+}
+
+//生成取样目录，可以用 open_sample 打开
+pub fn generate_sample(sample_data_dir: &str, options: &GeneratorOptions) -> io::Result<GeneratorStats> {
+    if options.threads == 0 || options.duration_ms <= 0 {
+        return Err(new_invalid_input_error("threads and duration must be greater than 0"));
+    }
+    let mut writer = SampleWriter::new(sample_data_dir, options.sample_interval, "synthetic")?;
+    let method_names = generate_method_names(options.methods);
+    for (i, name) in method_names.iter().enumerate() {
+        writer.add_method(i as i64 + 1, name)?;
+    }
+    let mut frames = 0;
+    let samples = generate_thread_samples(options, |thread_data| {
+        frames += thread_data.stacktrace.len();
+        writer.add_thread_sample(thread_data)
+    })?;
+    let sample_data_dir = writer.finish()?;
+    Ok(GeneratorStats {
+        sample_data_dir,
+        threads: options.threads,
+        samples,
+        methods: method_names.len(),
+        frames,
+    })
+}
@@ -0,0 +1,103 @@
+
+//升级旧版本格式的取样数据，升级前备份整个取样目录
+
+use std::io;
+use std::path::Path;
+use chrono::Local;
+use flare_utils::timeseries::*;
+use flare_utils::tuple_indexed::*;
+use utils::*;
+
+#[derive(Serialize, Debug, Default)]
+pub struct MigrateStats {
+    pub sample_data_dir: String,
+    pub backup_dir: Option<String>,
+    pub ts_files: usize,
+    pub tuple_files: usize,
+}
+
+//取样目录中需要升级的文件(不含扩展名)
+struct MigrateFiles {
+    ts_files: Vec<String>,
+    tuple_files: Vec<String>,
+}
+
+pub fn migrate_sample(sample_data_dir: &str) -> io::Result<MigrateStats> {
+    if !Path::new(sample_data_dir).is_dir() {
+        return Err(new_invalid_input_error(&format!("sample data dir not found: {}", sample_data_dir)));
+    }
+    let mut files = MigrateFiles { ts_files: vec![], tuple_files: vec![] };
+    find_migrate_files(Path::new(sample_data_dir), &mut files)?;
+
+    let mut stats = MigrateStats {
+        sample_data_dir: sample_data_dir.to_string(),
+        ..Default::default()
+    };
+    if files.ts_files.is_empty() && files.tuple_files.is_empty() {
+        return Ok(stats);
+    }
+
+    let backup_dir = format!("{}.bak-{}", sample_data_dir.trim_end_matches(|c| c == '/' || c == '\\'), Local::now().format("%Y%m%dT%H%M%S"));
+    copy_dir(Path::new(sample_data_dir), Path::new(&backup_dir))?;
+    println!("backup sample data to: {}", backup_dir);
+    stats.backup_dir = Some(backup_dir);
+
+    for path in &files.ts_files {
+        if migrate_ts_file(path)? {
+            stats.ts_files += 1;
+        }
+    }
+    for path in &files.tuple_files {
+        if migrate_tuple_file(path)? {
+            stats.tuple_files += 1;
+        }
+    }
+    Ok(stats)
+}
+
+fn find_migrate_files(dir: &Path, files: &mut MigrateFiles) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_migrate_files(&path, files)?;
+            continue;
+        }
+        let (ext, base_path) = match (path.extension().and_then(|x| x.to_str()), path.with_extension("").to_str()) {
+            (Some(ext), Some(base_path)) => (ext.to_string(), base_path.to_string()),
+            _ => continue
+        };
+        match ext.as_str() {
+            "fts" => {
+                let reader = TimeSeriesFileReader::new(&base_path)
+                    .map_err(|e| new_invalid_input_error(&format!("open ts file failed: {}, err: {}", path.display(), e)))?;
+                if reader.get_header_info().version != TS_FORMAT_VERSION {
+                    files.ts_files.push(base_path);
+                }
+            }
+            "fidx" => {
+                let reader = TupleIndexedFile::new_reader(&base_path)
+                    .map_err(|e| new_invalid_input_error(&format!("open indexed file failed: {}, err: {}", path.display(), e)))?;
+                if reader.get_format_version() != TUPLE_FORMAT_VERSION_V2 {
+                    files.tuple_files.push(base_path);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        let dest_path = dest.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &dest_path)?;
+        } else {
+            std::fs::copy(&path, &dest_path)?;
+        }
+    }
+    Ok(())
+}
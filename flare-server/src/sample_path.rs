@@ -0,0 +1,150 @@
+//取样目录路径与协议/配置中字符串的转换
+//  有效Unicode的路径原样转换(Windows上的路径总是如此)，中文等非ASCII路径不会被丢弃或者替换
+//  去掉 canonicalize 在Windows上返回的 \\?\ 前缀，同一目录在会话查找、权限范围中的名称保持一致
+//  Unix上不是有效UTF-8的路径转义为 UTF-8 字符串: 无效的字节为 \xNN，反斜杠为 \\，可以无损还原
+//转义的字符串只用于协议、配置及显示，打开取样时还原为 PathBuf，取样文件按原始路径读写
+
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+
+//在上级目录下创建新的目录，名称已经存在时加上序号: name-2, name-3 ..
+//  create_dir 检查并创建，多个线程同时创建时不会使用同一个目录
+pub fn create_unique_dir(parent: &Path, name: &str) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(parent)?;
+    let mut dir = parent.join(name);
+    let mut seq = 1;
+    loop {
+        match std::fs::create_dir(&dir) {
+            Ok(_) => return Ok(dir),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                seq += 1;
+                dir = parent.join(format!("{}-{}", name, seq));
+            }
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+pub fn path_to_string(path: &Path) -> String {
+    match path.to_str() {
+        Some(s) => strip_verbatim_prefix(s),
+        None => escape_path(path)
+    }
+}
+
+//文件名，用于显示及比较
+pub fn file_name_string(path: &Path) -> Option<String> {
+    path.file_name().map(|x| path_to_string(Path::new(x)))
+}
+
+//字符串对应的路径，存在的原样路径优先，其次是转义还原的路径
+pub fn string_to_path(s: &str) -> PathBuf {
+    let path = PathBuf::from(s);
+    if !s.contains('\\') || path.exists() {
+        return path;
+    }
+    unescape_path(s).unwrap_or(path)
+}
+
+pub fn canonicalize_path(s: &str) -> String {
+    match std::fs::canonicalize(string_to_path(s)) {
+        Ok(path) => path_to_string(&path),
+        Err(_) => s.to_string()
+    }
+}
+
+//客户端指定的文件必须在取样根目录下，按规范化的路径比较，符号链接及 .. 不能跳出根目录
+//返回规范化的路径，文件不存在或者不在根目录下时返回错误
+pub fn resolve_path_under_roots(s: &str, roots: &[String]) -> io::Result<PathBuf> {
+    let path = std::fs::canonicalize(string_to_path(s))?;
+    for root in roots {
+        if let Ok(root) = std::fs::canonicalize(string_to_path(root)) {
+            if path.starts_with(&root) {
+                return Ok(path);
+            }
+        }
+    }
+    Err(io::Error::new(ErrorKind::PermissionDenied, format!("path is not under samples roots: {}", s)))
+}
+
+//客户端指定的输出目录(如导出目录)是根目录下的相对路径，拒绝绝对路径及 ..
+pub fn join_relative_path(root: &str, s: &str) -> io::Result<String> {
+    let path = Path::new(s);
+    if s.is_empty() || !path.components().all(|x| match x { Component::Normal(_) => true, _ => false }) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, format!("path must be relative to samples root, without '..': {}", s)));
+    }
+    Ok(path_to_string(&Path::new(root).join(path)))
+}
+
+fn strip_verbatim_prefix(s: &str) -> String {
+    //\\?\UNC\server\share -> \\server\share
+    if s.starts_with(VERBATIM_UNC_PREFIX) {
+        return format!(r"\\{}", &s[VERBATIM_UNC_PREFIX.len()..]);
+    }
+    //\\?\C:\dir -> C:\dir
+    if s.starts_with(VERBATIM_PREFIX) && s[VERBATIM_PREFIX.len()..].chars().nth(1) == Some(':') {
+        return s[VERBATIM_PREFIX.len()..].to_string();
+    }
+    s.to_string()
+}
+
+#[cfg(unix)]
+fn escape_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = path.as_os_str().as_bytes();
+    let mut result = String::with_capacity(bytes.len() + 8);
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let (valid, invalid) = match std::str::from_utf8(&bytes[pos..]) {
+            Ok(s) => (s, None),
+            Err(e) => (std::str::from_utf8(&bytes[pos..pos + e.valid_up_to()]).unwrap_or(""), Some(bytes[pos + e.valid_up_to()]))
+        };
+        result.push_str(&valid.replace('\\', "\\\\"));
+        pos += valid.len();
+        if let Some(byte) = invalid {
+            result.push_str(&format!("\\x{:02X}", byte));
+            pos += 1;
+        }
+    }
+    result
+}
+
+#[cfg(not(unix))]
+fn escape_path(path: &Path) -> String {
+    //Windows上只有不成对的代理项不能转换，实际的路径中不会出现
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(unix)]
+fn unescape_path(s: &str) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] != b'\\' {
+            result.push(bytes[pos]);
+            pos += 1;
+        } else if bytes.get(pos + 1) == Some(&b'\\') {
+            result.push(b'\\');
+            pos += 2;
+        } else if bytes.get(pos + 1) == Some(&b'x') && pos + 4 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[pos + 2..pos + 4]).ok()?;
+            result.push(u8::from_str_radix(hex, 16).ok()?);
+            pos += 4;
+        } else {
+            return None;
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(result)))
+}
+
+#[cfg(not(unix))]
+fn unescape_path(_s: &str) -> Option<PathBuf> {
+    None
+}
@@ -0,0 +1,82 @@
+
+use ::sample::*;
+use sample_writer::SampleWriter;
+use std::io;
+use chrono::{Local, TimeZone};
+use utils::*;
+use marker::*;
+
+//按固定时间间隔计算切分点，对齐到整点(如每小时)
+pub fn get_interval_split_times(start_time: i64, end_time: i64, interval_ms: i64) -> Vec<i64> {
+    let mut split_times = vec![];
+    if interval_ms <= 0 {
+        return split_times;
+    }
+    let mut time = (start_time / interval_ms + 1) * interval_ms;
+    while time < end_time {
+        split_times.push(time);
+        time += interval_ms;
+    }
+    split_times
+}
+
+//按切分时间点将取样数据拆分为多个子取样目录，返回新建的目录(没有数据的时间段不生成目录)
+pub fn split_sample(collector: &mut SampleCollector, split_times: &[i64]) -> io::Result<Vec<String>> {
+    let sample_info = collector.get_sample_info();
+    let mut split_times: Vec<i64> = split_times.iter()
+        .filter(|x| **x > sample_info.record_start_time && **x <= sample_info.last_record_time)
+        .cloned().collect();
+    split_times.sort();
+    split_times.dedup();
+    if split_times.is_empty() {
+        return Err(new_invalid_input_error("no split time in the range of sample"));
+    }
+
+    //time range: [start, end)
+    let mut ranges = vec![];
+    let mut start = sample_info.record_start_time;
+    for time in &split_times {
+        ranges.push((start, *time));
+        start = *time;
+    }
+    ranges.push((start, sample_info.last_record_time + 1));
+
+    let methods = collector.list_methods_by_filter("")?;
+    let mut threads = collector.get_threads()?;
+    threads.sort_by(|a, b| a.id.cmp(&b.id));
+    let base_dir = sample_info.sample_data_dir.trim_end_matches(|c| c == '/' || c == '\\').to_string();
+
+    let mut result = vec![];
+    for (start_time, end_time) in ranges {
+        let mut writer: Option<SampleWriter> = None;
+        for thread in &threads {
+            let thread_data_vec = collector.load_thread_samples(thread.id, start_time, end_time)?;
+            for thread_data in &thread_data_vec {
+                if thread_data.sample_time < start_time || thread_data.sample_time >= end_time {
+                    continue;
+                }
+                if writer.is_none() {
+                    let time_str = Local.timestamp_millis(start_time).format("%Y%m%dT%H%M%S").to_string();
+                    let dir = format!("{}-part-{}", base_dir, time_str);
+                    let mut new_writer = SampleWriter::new(&dir, sample_info.sample_interval, &sample_info.agent_addr)?;
+                    for method in &methods {
+                        new_writer.add_method(method.method_id, &method.full_name)?;
+                    }
+                    writer = Some(new_writer);
+                }
+                writer.as_mut().unwrap().add_thread_sample(thread_data)?;
+            }
+        }
+        if let Some(writer) = writer {
+            let dir = writer.finish()?;
+            let markers: Vec<Marker> = collector.get_markers().into_iter()
+                .filter(|x| x.time >= start_time && x.time < end_time).collect();
+            if !markers.is_empty() {
+                save_markers(&dir, &markers)?;
+            }
+            println!("split sample: {}", dir);
+            result.push(dir);
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,176 @@
+
+use ::sample::*;
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+use flare_utils::ValueType;
+use flare_utils::timeseries::{TimeSeries, TSValue, TimeSeriesFileWriter};
+use flare_utils::tuple_indexed::{TupleIndexedFile, TupleValue};
+use utils::*;
+use agg_index::AggIndexBuilder;
+use stack_record::*;
+
+type JavaLong = i64;
+type JavaMethod = i64;
+
+//将取样数据写入新的取样目录，目录格式与录制时保存的一致，可以用 open_sample 打开
+pub struct SampleWriter {
+    sample_data_dir: String,
+    sample_interval: i64,
+    sample_start_time: i64,
+    record_start_time: i64,
+    last_record_time: i64,
+    agent_addr: String,
+    threads: HashMap<JavaLong, ThreadData>,
+    cpu_ts_map: HashMap<JavaLong, TimeSeriesFileWriter>,
+    stacktrace_map: HashMap<JavaLong, TupleIndexedFile>,
+    stack_chunks: HashMap<JavaLong, StackChunkBuilder>,
+    method_idx_file: TupleIndexedFile,
+    //method name -> method id
+    method_ids: HashMap<String, JavaMethod>,
+    next_method_id: JavaMethod,
+    agg_index_builder: AggIndexBuilder,
+}
+
+impl SampleWriter {
+
+    pub fn new(sample_data_dir: &str, sample_interval: i64, agent_addr: &str) -> io::Result<SampleWriter> {
+        if std::fs::metadata(format!("{}/summary_info.json", sample_data_dir)).is_ok() {
+            return Err(new_error(ErrorKind::AlreadyExists, &format!("sample data dir already exists: {}", sample_data_dir)));
+        }
+        std::fs::create_dir_all(sample_data_dir)?;
+        let method_idx_path = format!("{}/method_info", sample_data_dir);
+        let method_idx_file = TupleIndexedFile::new_writer(&method_idx_path, ValueType::INT64)?;
+        let agg_index_builder = AggIndexBuilder::new(sample_data_dir)?;
+        Ok(SampleWriter {
+            sample_data_dir: sample_data_dir.to_string(),
+            sample_interval,
+            sample_start_time: 0,
+            record_start_time: 0,
+            last_record_time: 0,
+            agent_addr: agent_addr.to_string(),
+            threads: HashMap::new(),
+            cpu_ts_map: HashMap::new(),
+            stacktrace_map: HashMap::new(),
+            stack_chunks: HashMap::new(),
+            method_idx_file,
+            method_ids: HashMap::new(),
+            next_method_id: 1,
+            agg_index_builder,
+        })
+    }
+
+    pub fn get_sample_data_dir(&self) -> &str {
+        &self.sample_data_dir
+    }
+
+    //按方法名分配新的方法id，相同名称的方法共用一个id
+    pub fn get_or_add_method(&mut self, method_name: &str) -> io::Result<JavaMethod> {
+        if let Some(method_id) = self.method_ids.get(method_name) {
+            return Ok(*method_id);
+        }
+        let method_id = self.next_method_id;
+        self.next_method_id += 1;
+        self.method_idx_file.add_value(TupleValue::int64(method_id), method_name.as_bytes())?;
+        self.method_ids.insert(method_name.to_string(), method_id);
+        Ok(method_id)
+    }
+
+    pub fn get_method_count(&self) -> usize {
+        self.method_ids.len()
+    }
+
+    //保留原始的方法id
+    pub fn add_method(&mut self, method_id: JavaMethod, method_name: &str) -> io::Result<()> {
+        self.method_idx_file.add_value(TupleValue::int64(method_id), method_name.as_bytes())?;
+        self.method_ids.insert(method_name.to_string(), method_id);
+        if method_id >= self.next_method_id {
+            self.next_method_id = method_id + 1;
+        }
+        Ok(())
+    }
+
+    //同一个线程的取样必须按时间顺序写入
+    pub fn add_thread_sample(&mut self, thread_data: &ThreadData) -> io::Result<()> {
+        let thread_id = thread_data.id;
+        let sample_time = thread_data.sample_time;
+        if self.record_start_time == 0 || sample_time < self.record_start_time {
+            self.record_start_time = sample_time;
+            self.sample_start_time = sample_time;
+        }
+        if sample_time > self.last_record_time {
+            self.last_record_time = sample_time;
+        }
+
+        let thread = self.threads.entry(thread_id).or_insert_with(|| {
+            let mut thread = thread_data.clone();
+            thread.sample_count = 0;
+            thread.stacktrace = vec![];
+            thread
+        });
+        thread.sample_count += 1;
+        thread.sample_time = sample_time;
+        thread.cpu_time = thread_data.cpu_time;
+        thread.state = thread_data.state.clone();
+
+        //save thread cpu time
+        if !self.cpu_ts_map.contains_key(&thread_id) {
+            let path = format!("{}/thread_{}_cpu_time", self.sample_data_dir, thread_id);
+            let mut ts = TimeSeriesFileWriter::new(ValueType::INT32, self.sample_interval as i32, sample_time, &path)?;
+            ts.set_labels(get_cpu_ts_labels(thread_id, &thread_data.name))?;
+            self.cpu_ts_map.insert(thread_id, ts);
+        }
+        let ts = self.cpu_ts_map.get_mut(&thread_id).unwrap();
+        let ts_steps = ts.add_value(sample_time, TSValue::int32((thread_data.cpu_time_delta / 1000) as i32))?;
+
+        //save thread stack data
+        if !self.stacktrace_map.contains_key(&thread_id) {
+            let path = format!("{}/thread_{}_stack", self.sample_data_dir, thread_id);
+            let idx_file = TupleIndexedFile::new_writer(&path, ValueType::UINT32)?;
+            self.stacktrace_map.insert(thread_id, idx_file);
+        }
+        let chunk = self.stack_chunks.entry(thread_id).or_insert_with(StackChunkBuilder::new);
+        chunk.add(ts_steps, thread_data);
+        if chunk.is_full() {
+            let (step, data) = chunk.take();
+            self.stacktrace_map.get_mut(&thread_id).unwrap().add_value(TupleValue::uint32(step), &data)?;
+        }
+        self.agg_index_builder.add_sample(thread_data)
+    }
+
+    //关闭数据文件并保存summary info，返回取样目录
+    pub fn finish(mut self) -> io::Result<String> {
+        for (thread_id, chunk) in self.stack_chunks.iter_mut() {
+            if !chunk.is_empty() {
+                let (step, data) = chunk.take();
+                self.stacktrace_map.get_mut(thread_id).unwrap().add_value(TupleValue::uint32(step), &data)?;
+            }
+        }
+        self.cpu_ts_map.clear();
+        self.stacktrace_map.clear();
+        self.agg_index_builder.finish()?;
+
+        let mut threads: Vec<ThreadData> = self.threads.values().cloned().collect();
+        threads.sort_by(|a, b| a.id.cmp(&b.id));
+        let info = SummaryInfo {
+            sample_info: SampleInfo {
+                sample_interval: self.sample_interval,
+                sample_start_time: self.sample_start_time,
+                record_start_time: self.record_start_time,
+                last_record_time: self.last_record_time,
+                agent_addr: self.agent_addr.clone(),
+                sample_data_dir: self.sample_data_dir.clone(),
+                stack_retention: STACK_RETENTION_RAW.to_string(),
+                data_quality: Default::default(),
+                disk_guard: Default::default(),
+            },
+            threads,
+            ingest_filter: None,
+            ingest_stats: None,
+        };
+        let path = format!("{}/summary_info.json", self.sample_data_dir);
+        let json = serde_json::to_string_pretty(&info)?;
+        std::fs::write(path, json.as_bytes())?;
+        Ok(self.sample_data_dir.clone())
+    }
+}
@@ -0,0 +1,76 @@
+
+use std::collections::HashSet;
+use agg_index::has_agg_index;
+use record_group::{is_group_dir, list_member_dirs};
+use sample_path::{path_to_string, string_to_path, file_name_string};
+
+//取样根目录变化检查: 每 WATCH_INTERVAL_MS 重新扫描一次根目录并与上次的结果比较，不使用文件系统通知
+//  取样根目录可能在网络文件系统上，文件系统通知不可靠；只读取根目录及取样目录的元数据，扫描开销很小
+//  其它工具复制或删除的取样目录最多延迟一个周期推送 samples_changed
+pub const WATCH_INTERVAL_MS: u64 = 2000;
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct SampleDirEntry {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub sample_type: String,
+    pub indexed: bool,
+    pub root: String,
+    //所属的录制分组目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+//扫描所有取样根目录下的取样目录，读取目录使用原始路径，返回的路径是转义后的字符串
+pub fn scan_samples_roots(samples_roots: &[String]) -> Vec<SampleDirEntry> {
+    let mut samples = vec![];
+    for samples_root in samples_roots {
+        let paths = match std::fs::read_dir(string_to_path(samples_root)) {
+            Ok(paths) => paths,
+            Err(e) => {
+                println!("read samples root failed: {}, err: {}", samples_root, e);
+                continue;
+            }
+        };
+        for dir in paths.filter_map(Result::ok) {
+            let path_buf = dir.path();
+            if !std::fs::metadata(&path_buf).map(|x| x.is_dir()).unwrap_or(false) {
+                continue;
+            }
+            //skip hidden dir, e.g. .symbols
+            if file_name_string(&path_buf).map(|x| x.starts_with(".")).unwrap_or(true) {
+                continue;
+            }
+            //录制分组目录下是各成员的取样目录
+            if is_group_dir(&path_buf) {
+                for member_dir in list_member_dirs(&path_buf) {
+                    samples.push(SampleDirEntry {
+                        path: path_to_string(&member_dir),
+                        sample_type: "file".to_string(),
+                        indexed: has_agg_index(&member_dir),
+                        root: samples_root.clone(),
+                        group: Some(path_to_string(&path_buf)),
+                    });
+                }
+                continue;
+            }
+            samples.push(SampleDirEntry {
+                path: path_to_string(&path_buf),
+                sample_type: "file".to_string(),
+                indexed: has_agg_index(&path_buf),
+                root: samples_root.clone(),
+                group: None,
+            });
+        }
+    }
+    samples
+}
+
+//比较两次扫描的结果，返回 (新增的目录, 删除的目录)
+pub fn diff_samples(old_samples: &[SampleDirEntry], new_samples: &[SampleDirEntry]) -> (Vec<SampleDirEntry>, Vec<SampleDirEntry>) {
+    let old_paths: HashSet<&str> = old_samples.iter().map(|x| x.path.as_str()).collect();
+    let new_paths: HashSet<&str> = new_samples.iter().map(|x| x.path.as_str()).collect();
+    let added = new_samples.iter().filter(|x| !old_paths.contains(x.path.as_str())).cloned().collect();
+    let removed = old_samples.iter().filter(|x| !new_paths.contains(x.path.as_str())).cloned().collect();
+    (added, removed)
+}
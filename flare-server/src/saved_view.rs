@@ -0,0 +1,56 @@
+
+//保存的视图：命名的查询配置(时间范围、过滤条件、选中的线程)，保存在会话目录下，
+//打开同一个取样目录时可以恢复到相同的视图，也可以随取样目录一起交给其他人
+//  views.json
+
+use std::io;
+use std::path::Path;
+use serde_json;
+use serde_json::{Map, Value};
+use utils::new_invalid_input_error;
+
+pub const VIEWS_FILE: &str = "views.json";
+const MAX_VIEW_NAME_LEN: usize = 128;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SavedView {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub created_time: i64,
+    pub updated_time: i64,
+    //-1表示不限制
+    pub start_time: i64,
+    pub end_time: i64,
+    #[serde(default)]
+    pub thread_ids: Vec<i64>,
+    //其它查询选项，如 name_filter、sort_by、query 等，由前端解释
+    #[serde(default)]
+    pub filters: Map<String, Value>,
+}
+
+pub fn check_view_name(name: &str) -> io::Result<()> {
+    if name.trim().is_empty() {
+        return Err(new_invalid_input_error("view name is empty"));
+    }
+    if name.len() > MAX_VIEW_NAME_LEN {
+        return Err(new_invalid_input_error(&format!("view name is too long, max length: {}", MAX_VIEW_NAME_LEN)));
+    }
+    Ok(())
+}
+
+pub fn load_views<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<SavedView>> {
+    let path = sample_data_dir.as_ref().join(VIEWS_FILE);
+    if std::fs::metadata(&path).is_err() {
+        return Ok(vec![]);
+    }
+    let json = std::fs::read_to_string(path)?;
+    let views = serde_json::from_str::<Vec<SavedView>>(&json)?;
+    Ok(views)
+}
+
+pub fn save_views<P: AsRef<Path>>(sample_data_dir: P, views: &[SavedView]) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(VIEWS_FILE);
+    let json = serde_json::to_string_pretty(views)?;
+    std::fs::write(path, json.as_bytes())
+}
@@ -0,0 +1,338 @@
+
+//websocket协议的JSON Schema(draft-07)，前端可以据此生成代码或者做契约测试，通过 schema 命令获取
+//  请求/响应/错误消息及 hello 的能力数据由 flare-proto 中的类型推导
+//  命令参数定义在 COMMAND_OPTIONS，响应数据(data)的字段定义在 COMMAND_RESULTS，新增命令、参数或响应字段时需要同步修改
+//  examples/test_schema.rs 执行命令并按 COMMAND_RESULTS 检查实际的响应，字段缺失或类型不一致时测试失败
+//类型: string, integer, number, boolean, object, any, <type>[] 为数组，<type>? 可以为 null
+
+use serde_json::{json, Map, Value};
+use flare_proto::ws::{FlareRequest, FlareResponse, RESULT_SUCCESS, RESULT_FAILURE};
+use protocol::{self, SUPPORTED_COMMANDS};
+use std::io;
+use utils::new_invalid_input_error;
+
+pub const JSON_SCHEMA_DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+//(名称, 类型, 是否必须)
+type OptionDef = (&'static str, &'static str, bool);
+
+//所有命令都支持的参数
+const COMMON_OPTIONS: &[OptionDef] = &[("lang", "string", false), ("format_hints", "any", false)];
+//指定 interval 时使用阶段的时间范围
+const TIME_RANGE_OPTIONS: &[OptionDef] = &[("start_time", "integer", false), ("end_time", "integer", false), ("interval", "string", false), ("interval_seq", "integer", false)];
+//列表的分页及排序(paging.rs)
+const PAGE_OPTIONS: &[OptionDef] = &[("page", "integer", false), ("page_size", "integer", false), ("sort_by", "string", false), ("order", "string", false)];
+//线程过滤
+const THREAD_QUERY_OPTIONS: &[OptionDef] = &[("name_filter", "string", false), ("state", "string", false)];
+//调用树剪枝
+const PRUNE_OPTIONS: &[OptionDef] = &[("min_samples", "integer", false), ("min_percent", "number", false), ("max_depth", "integer", false)];
+
+//(命令, 参数, 参数组)
+const COMMAND_OPTIONS: &[(&str, &[OptionDef], &[&[OptionDef]])] = &[
+    ("hello", &[("protocol_version", "integer", false), ("features", "string[]", false), ("token", "string", false)], &[]),
+    ("list_sessions", &[], &[]),
+    ("history_samples", &[("subscribe", "boolean", false)], &[PAGE_OPTIONS]),
+    ("open_sample", &[("max_resident_mb", "integer", false), ("async", "boolean", false), ("sample_data_dir", "string", true)], &[]),
+    ("attach_jvm", &[("target_pid", "integer", true), ("sample_interval_ms", "integer", false), ("sample_duration_sec", "integer", false), ("agent_port", "integer", false)], &[]),
+    ("connect_agent", &[("pid", "integer", false), ("agent_addr", "string", false), ("stack_retention", "string", false), ("ingest_filter", "object", false), ("flush_policy", "object", false), ("multiplex", "boolean", false), ("interval", "integer", false)], &[]),
+    ("connect_runtime", &[("runtime", "string", true), ("target", "string", true)], &[]),
+    ("list_runtimes", &[], &[]),
+    ("close_session", &[("session_id", "string", true)], &[]),
+    ("close_all_session", &[], &[]),
+    ("dashboard", &[("session_id", "string", true)], &[THREAD_QUERY_OPTIONS, PAGE_OPTIONS]),
+    ("overlay_dashboard", &[("session_ids", "string[]", true), ("time_axis", "string", false), ("unit_time_ms", "integer", false), ("graph_width", "integer", false)], &[]),
+    ("merge_sessions", &[("session_ids", "string[]", true), ("start_time", "integer", false), ("end_time", "integer", false)], &[]),
+    ("split_sample", &[("session_id", "string", true), ("split_by", "string", false), ("interval_minutes", "integer", false), ("split_times", "integer[]", false)], &[]),
+    ("add_marker", &[("session_id", "string", true), ("label", "string", true), ("color", "string", false), ("time", "integer", false)], &[]),
+    ("save_view", &[("session_id", "string", true), ("name", "string", true), ("description", "string", false), ("start_time", "integer", false), ("end_time", "integer", false), ("thread_ids", "integer[]", false), ("filters", "object", false)], &[]),
+    ("list_views", &[("session_id", "string", true)], &[]),
+    ("list_markers", &[("session_id", "string", true)], &[]),
+    ("list_intervals", &[("session_id", "string", true)], &[]),
+    ("list_series", &[("session_id", "string", true)], &[]),
+    ("export_sample", &[("session_id", "string", true), ("anonymize", "string", false), ("mapping_file", "string", false), ("keep_packages", "string[]", false), ("export_dir", "string", false)], &[]),
+    ("generate_report", &[("session_id", "string", true), ("format", "string", false), ("top_methods", "integer", false), ("flame_graph_width", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("export_metrics", &[("session_id", "string", true), ("tables", "string[]", false), ("format", "string", false), ("unit_time_ms", "integer", false), ("exclude_warmup", "boolean", false), ("export_dir", "string", false)], &[TIME_RANGE_OPTIONS]),
+    ("load_mapping", &[("session_id", "string", true), ("mapping_file", "string", true)], &[]),
+    ("list_threads", &[("session_id", "string", true)], &[THREAD_QUERY_OPTIONS, PAGE_OPTIONS]),
+    ("expand_node", &[("handle", "string", true), ("levels", "integer", false)], &[]),
+    ("add_samples_root", &[("samples_root", "string", true)], &[]),
+    ("build_index", &[("sample_data_dir", "string", true)], &[]),
+    ("cpu_time", &[("session_id", "string", true), ("thread_ids", "integer[]", true), ("start_time", "integer", false), ("end_time", "integer", false), ("graph_width", "integer", false), ("unit_time_ms", "integer", false)], &[THREAD_QUERY_OPTIONS, PAGE_OPTIONS]),
+    ("call_tree", &[("session_id", "string", true), ("thread_ids", "integer[]", true), ("levels", "integer", false), ("offcpu", "boolean", false)], &[TIME_RANGE_OPTIONS, PRUNE_OPTIONS]),
+    ("sequenced_call_tree", &[("session_id", "string", true), ("thread_id", "integer", false), ("stats_type", "string", false), ("idle_mode", "string", false), ("levels", "integer", false)], &[TIME_RANGE_OPTIONS, PRUNE_OPTIONS]),
+    ("flame_graph", &[("session_id", "string", true), ("thread_id", "integer", false), ("image_width", "integer", false), ("stats_type", "string", false), ("idle_mode", "string", false), ("graph_mode", "string", false), ("offcpu", "boolean", false)], &[TIME_RANGE_OPTIONS, PRUNE_OPTIONS]),
+    ("list_methods_by_filter", &[("session_id", "string", true), ("method_name_filter", "string", false)], &[PAGE_OPTIONS]),
+    ("search_slow_method_calls", &[("session_id", "string", true), ("method_ids", "integer[]", true), ("min_duration", "integer", false), ("max_duration", "integer", false), ("max_size", "integer", false), ("thread_name_filter", "string", false)], &[PAGE_OPTIONS]),
+    ("database_time", &[("session_id", "string", true), ("thread_ids", "integer[]", false)], &[TIME_RANGE_OPTIONS]),
+    ("query", &[("session_id", "string", true), ("query", "string", true)], &[]),
+    ("list_plugins", &[("plugin", "string", false)], &[]),
+    ("plugin_command", &[("session_id", "string", true), ("plugin", "string", true), ("command", "string", true), ("params", "object", false)], &[]),
+    ("plugin_report", &[("session_id", "string", true), ("plugin", "string", true), ("report", "string", true), ("params", "object", false)], &[]),
+    ("classify_samples", &[("session_id", "string", true), ("plugin", "string", true)], &[TIME_RANGE_OPTIONS]),
+    ("start_offcpu_sampling", &[("session_id", "string", true), ("pid", "integer", false), ("duration_secs", "integer", false)], &[]),
+    ("offcpu_stacks", &[("session_id", "string", true), ("thread_name", "string", false), ("thread_id", "integer", false), ("limit", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("cgroup_metrics", &[("session_id", "string", true), ("unit_time_ms", "integer", false), ("transform", "string", false)], &[TIME_RANGE_OPTIONS]),
+    ("host_metrics", &[("session_id", "string", true), ("unit_time_ms", "integer", false), ("transform", "string", false)], &[TIME_RANGE_OPTIONS]),
+    ("list_child_processes", &[("session_id", "string", true)], &[]),
+    ("attach_child", &[("session_id", "string", true), ("pid", "integer", false), ("agent_addr", "string", false)], &[]),
+    ("warmup_phase", &[("session_id", "string", true), ("window_ms", "integer", false), ("top_methods", "integer", false), ("min_similarity", "number", false), ("max_compile_ratio", "number", false), ("stable_windows", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("pool_starvation", &[("session_id", "string", true), ("pool_patterns", "string[]", true), ("min_threads", "integer", false), ("min_duration_ms", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("spin_loops", &[("session_id", "string", true), ("min_cpu_ratio", "number", false), ("min_samples", "integer", false), ("top_depth", "integer", false), ("max_distinct_frames", "integer", false), ("min_coverage", "number", false)], &[TIME_RANGE_OPTIONS]),
+    ("insights", &[("session_id", "string", true)], &[TIME_RANGE_OPTIONS]),
+    ("record_group", &[("name", "string", false), ("record_host_metrics", "boolean", false), ("duration_secs", "integer", false), ("targets", "string[]", false)], &[]),
+    ("stop_record_group", &[("group_id", "string", true)], &[]),
+    ("list_record_groups", &[], &[]),
+    ("detect_deadlocks", &[("session_id", "string", true), ("interval_ms", "integer", false)], &[]),
+    ("list_deadlocks", &[("session_id", "string", true)], &[TIME_RANGE_OPTIONS]),
+    ("thread_dump", &[("session_id", "string", true), ("timeout_ms", "integer", false)], &[]),
+    ("list_thread_dumps", &[("session_id", "string", true)], &[]),
+    ("get_thread_dump", &[("session_id", "string", true), ("time", "integer", false), ("thread_id", "integer", false)], &[]),
+    ("heap_histogram", &[("session_id", "string", true), ("force_gc", "boolean", false), ("limit", "integer", false), ("timeout_ms", "integer", false)], &[]),
+    ("list_heap_histograms", &[("session_id", "string", true)], &[]),
+    ("diff_heap_histograms", &[("session_id", "string", true), ("before", "integer", false), ("after", "integer", false), ("limit", "integer", false)], &[]),
+    ("allocation_rate", &[("session_id", "string", true), ("unit_time_ms", "integer", false), ("limit", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("deopt_stats", &[("session_id", "string", true), ("limit", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("class_loader_leaks", &[("session_id", "string", true), ("min_growth", "integer", false), ("min_snapshots", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("ingest_filter", &[("session_id", "string", true), ("filter", "object", false)], &[]),
+    ("combined_view", &[("session_id", "string", true), ("graph_width", "integer", false), ("unit_time_ms", "integer", false), ("top_threads", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("set_baseline", &[("session_id", "string", true), ("app_tag", "string", true), ("description", "string", false)], &[TIME_RANGE_OPTIONS]),
+    ("list_baselines", &[], &[]),
+    ("compare_to_baseline", &[("session_id", "string", true), ("app_tag", "string", true), ("threshold", "number", false), ("cpu_threshold", "number", false), ("min_samples", "integer", false), ("limit", "integer", false)], &[TIME_RANGE_OPTIONS]),
+    ("schema", &[("command", "string", false)], &[]),
+    ("audit_log", &[("start_time", "integer", false), ("end_time", "integer", false), ("identity", "string", false), ("action", "string", false)], &[PAGE_OPTIONS]),
+    ("session_events", &[("session_id", "string", true), ("start_time", "integer", false), ("end_time", "integer", false), ("level", "string", false), ("kind", "string", false)], &[PAGE_OPTIONS]),
+    ("storage_usage", &[("session_id", "string", false)], &[]),
+    ("thread_handles", &[("session_id", "string", true), ("thread_id", "integer", false)], &[]),
+    ("self_profile", &[("action", "string", false), ("interval_ms", "integer", false), ("duration_secs", "integer", false)], &[]),
+    ("flush_policy", &[("session_id", "string", true), ("interval_ms", "integer", false), ("max_buffer_bytes", "integer", false), ("fsync", "boolean", false)], &[]),
+    ("reload_config", &[], &[]),
+];
+
+//分页列表的响应字段(paging.rs)
+const PAGE_RESULTS: &[OptionDef] = &[("total", "integer", true), ("page", "integer", true), ("page_size", "integer", true), ("sort_by", "string", true), ("order", "string", true)];
+//打开或连接会话的响应字段
+const SESSION_RESULTS: &[OptionDef] = &[("session_id", "string", true), ("origin", "string", true), ("type", "string", true)];
+//录制组(record_group.rs RecordGroup)
+const RECORD_GROUP_RESULTS: &[OptionDef] = &[("group_id", "string", true), ("name", "string", true), ("group_dir", "string", true), ("settings", "object", true), ("start_time", "integer", true), ("end_time", "integer", true), ("members", "object[]", true)];
+
+//(命令, 响应数据的字段, 字段组)，hello 的响应数据为 capabilities
+const COMMAND_RESULTS: &[(&str, &[OptionDef], &[&[OptionDef]])] = &[
+    ("hello", &[], &[]),
+    ("list_sessions", &[("sample_sessions", "object[]", true), ("analysis_pool", "object", true)], &[]),
+    ("history_samples", &[("history_samples", "object[]", true), ("samples_roots", "string[]", true)], &[PAGE_RESULTS]),
+    ("open_sample", &[("state", "string", true)], &[SESSION_RESULTS]),
+    ("attach_jvm", &[("agent_addr", "string", true), ("agent", "object?", true)], &[SESSION_RESULTS]),
+    ("connect_agent", &[("agent", "object?", true)], &[SESSION_RESULTS]),
+    ("connect_runtime", &[("runtime", "string", true)], &[SESSION_RESULTS]),
+    ("list_runtimes", &[("runtimes", "string[]", true)], &[]),
+    ("close_session", &[("session_id", "string", true), ("refcount", "integer", true), ("closed", "boolean", true)], &[]),
+    ("close_all_session", &[], &[]),
+    ("dashboard", &[("sample_info", "object", true), ("threads", "object[]", true), ("cgroup", "object?", true), ("host", "object?", true), ("top_allocating_threads", "object[]", true), ("top_allocating_methods", "object[]", true)], &[]),
+    ("overlay_dashboard", &[("time_axis", "string", true), ("start_time", "integer", true), ("end_time", "integer", true), ("unit_time_ms", "integer", true), ("steps", "integer", true), ("sessions", "object[]", true)], &[]),
+    ("merge_sessions", &[("session_id", "string", true), ("type", "string", true), ("sources", "string[]", true)], &[]),
+    ("split_sample", &[("session_id", "string", true), ("sample_dirs", "string[]", true)], &[]),
+    ("add_marker", &[("session_id", "string", true), ("marker", "object", true)], &[]),
+    ("save_view", &[("session_id", "string", true), ("view", "object", true)], &[]),
+    ("list_views", &[("session_id", "string", true), ("views", "object[]", true)], &[]),
+    ("list_markers", &[("session_id", "string", true), ("markers", "object[]", true)], &[]),
+    ("list_intervals", &[("session_id", "string", true), ("intervals", "object[]", true)], &[]),
+    ("list_series", &[("session_id", "string", true), ("series", "object[]", true)], &[]),
+    ("export_sample", &[("session_id", "string", true), ("sample_data_dir", "string", true), ("anonymize", "string", true), ("mapping_file", "string", true)], &[]),
+    ("generate_report", &[("session_id", "string", true), ("format", "string", true), ("path", "string", true), ("size", "integer", true)], &[]),
+    ("export_metrics", &[("session_id", "string", true), ("export_dir", "string", true), ("format", "string", true), ("tables", "string[]", true)], &[]),
+    ("load_mapping", &[("session_id", "string", true), ("mapping_file", "string", true), ("classes", "integer", true), ("methods", "integer", true)], &[]),
+    ("list_threads", &[("session_id", "string", true), ("threads", "object[]", true)], &[PAGE_RESULTS]),
+    ("expand_node", &[("handle", "string", true), ("node", "object", true)], &[]),
+    ("add_samples_root", &[("samples_roots", "string[]", true)], &[]),
+    ("build_index", &[("sample_data_dir", "string", true), ("samples", "integer", true), ("cost", "integer", true)], &[]),
+    ("cpu_time", &[("session_id", "string", true), ("thread_cpu_times", "object[]", true)], &[]),
+    //levels 大于0时返回 tree_handle，通过 expand_node 展开
+    ("call_tree", &[("session_id", "string", true), ("call_tree_data", "object[]", true), ("tree_handle", "string", false)], &[]),
+    ("sequenced_call_tree", &[("session_id", "string", true), ("thread_id", "integer", true), ("start_time", "integer", true), ("end_time", "integer", true), ("stats_type", "string", true), ("idle_mode", "string", true), ("idle_stats", "object", true), ("tree_handle", "string?", true), ("sequenced_call_tree_data", "object", true)], &[]),
+    ("flame_graph", &[("session_id", "string", true), ("thread_id", "integer", true), ("start_time", "integer", true), ("end_time", "integer", true), ("stats_type", "string", true), ("image_width", "integer", true), ("graph_mode", "string", true), ("idle_mode", "string", true), ("idle_stats", "object", true), ("flame_graph_data", "string", true)], &[]),
+    ("list_methods_by_filter", &[("session_id", "string", true), ("method_name_filter", "string", true), ("total_method_size", "integer", true), ("method_infos", "object[]", true)], &[PAGE_RESULTS]),
+    //搜索过程中推送进度，search_finished 或 search_error 为 true 的最后一个响应才有结果及分页字段
+    ("search_slow_method_calls", &[("session_id", "string", true), ("search_progress", "integer", true), ("search_finished", "boolean", true), ("search_message", "string", true),
+        ("search_error", "boolean", false), ("method_ids", "integer[]", false), ("method_call_groups", "object[]", false),
+        ("total", "integer", false), ("page", "integer", false), ("page_size", "integer", false), ("sort_by", "string", false), ("order", "string", false)], &[]),
+    ("database_time", &[("session_id", "string", true), ("start_time", "integer", true), ("end_time", "integer", true), ("database_time", "object", true)], &[]),
+    ("query", &[("session_id", "string", true), ("query", "string", true), ("result", "object", true)], &[]),
+    ("list_plugins", &[("plugins_dir", "string", true), ("plugins", "object[]", true)], &[]),
+    //其它字段由插件命令决定
+    ("plugin_command", &[("session_id", "string", true)], &[]),
+    ("plugin_report", &[("session_id", "string", true), ("plugin", "string", true), ("report", "string", true), ("queries", "object", true), ("content", "string", true)], &[]),
+    ("classify_samples", &[("session_id", "string", true), ("plugin", "string", true), ("categories", "object[]", true)], &[]),
+    ("start_offcpu_sampling", &[("session_id", "string", true), ("pid", "integer", true), ("duration_secs", "integer", true)], &[]),
+    ("offcpu_stacks", &[("session_id", "string", true), ("total_off_cpu_us", "integer", true), ("stacks", "object[]", true)], &[]),
+    ("cgroup_metrics", &[("session_id", "string", true), ("pid", "integer", true), ("metrics", "object", true)], &[]),
+    ("host_metrics", &[("session_id", "string", true), ("pid", "integer", true), ("metrics", "object", true)], &[]),
+    ("list_child_processes", &[("session_id", "string", true), ("pid", "integer", true), ("children", "object[]", true), ("child_sessions", "string[]", true)], &[]),
+    ("attach_child", &[("parent_session_id", "string", true), ("pid", "integer", true)], &[SESSION_RESULTS]),
+    ("warmup_phase", &[("session_id", "string", true), ("warmup", "object", true)], &[]),
+    ("pool_starvation", &[("session_id", "string", true), ("episodes", "object[]", true)], &[]),
+    ("spin_loops", &[("session_id", "string", true), ("threads", "object[]", true)], &[]),
+    ("insights", &[("session_id", "string", true), ("insights", "object[]", true)], &[]),
+    ("record_group", &[], &[RECORD_GROUP_RESULTS]),
+    ("stop_record_group", &[], &[RECORD_GROUP_RESULTS]),
+    ("list_record_groups", &[("record_groups", "object[]", true)], &[]),
+    //deadlocks: 已检测到的死锁数量，检测结果通过 list_deadlocks 查询
+    ("detect_deadlocks", &[("session_id", "string", true), ("interval_ms", "integer", true), ("deadlocks", "integer", true)], &[]),
+    ("list_deadlocks", &[("session_id", "string", true), ("deadlocks", "object[]", true)], &[]),
+    ("thread_dump", &[("session_id", "string", true), ("time", "integer", true), ("threads", "integer", true), ("content", "string", true)], &[]),
+    ("list_thread_dumps", &[("session_id", "string", true), ("thread_dumps", "object[]", true)], &[]),
+    ("get_thread_dump", &[("session_id", "string", true), ("time", "integer", true), ("thread_id", "integer", true), ("content", "string", true)], &[]),
+    ("heap_histogram", &[("session_id", "string", true), ("histogram", "object", true)], &[]),
+    ("list_heap_histograms", &[("session_id", "string", true), ("histograms", "object[]", true)], &[]),
+    ("diff_heap_histograms", &[("session_id", "string", true), ("before", "object", true), ("after", "object", true), ("classes", "object[]", true)], &[]),
+    ("allocation_rate", &[("session_id", "string", true), ("allocation", "object", true)], &[]),
+    ("deopt_stats", &[("session_id", "string", true), ("total", "integer", true), ("methods", "object[]", true)], &[]),
+    ("class_loader_leaks", &[("session_id", "string", true), ("loaders", "object[]", true), ("leaks", "object[]", true)], &[]),
+    ("ingest_filter", &[("session_id", "string", true), ("filter", "object?", true), ("stats", "object", true)], &[]),
+    ("combined_view", &[("session_id", "string", true), ("start_time", "integer", true), ("end_time", "integer", true), ("unit_time_ms", "integer", true), ("steps", "integer", true), ("cpu_time", "integer[]", true), ("thread_count", "integer[]", true),
+        ("gc_pauses", "object[]", true), ("gc_summary", "object", true), ("markers", "object[]", true), ("intervals", "object[]", true), ("top_threads", "object[]", true)], &[]),
+    ("set_baseline", &[("session_id", "string", true), ("baseline", "object", true)], &[]),
+    ("list_baselines", &[("baselines", "object[]", true)], &[]),
+    ("compare_to_baseline", &[("session_id", "string", true), ("baseline", "object", true), ("baseline_session_id", "string", true), ("regressed", "boolean", true), ("comparison", "object", true)], &[]),
+    //指定 command 时为该命令的schema，否则为整个协议的schema
+    ("schema", &[], &[]),
+    ("audit_log", &[("entries", "object[]", true)], &[PAGE_RESULTS]),
+    ("session_events", &[("session_id", "string", true), ("events", "object[]", true), ("summary", "object[]", true)], &[PAGE_RESULTS]),
+    ("storage_usage", &[("sessions", "object[]", true), ("total_bytes", "integer", true)], &[]),
+    ("thread_handles", &[("session_id", "string", true), ("handles", "object[]", true)], &[]),
+    //没有进行中或已完成的区段计时时为 null
+    ("self_profile", &[("self_profile", "object?", true)], &[]),
+    ("flush_policy", &[("session_id", "string", true), ("flush", "object", true)], &[]),
+    //changed: 变化的配置项名称
+    ("reload_config", &[("changed", "string[]", true)], &[]),
+];
+
+fn get_type_schema(kind: &str) -> Value {
+    if kind.ends_with('?') {
+        let mut schema = get_type_schema(&kind[..kind.len() - 1]);
+        if let Some(x) = schema.get("type").cloned() {
+            schema["type"] = json!([x, "null"]);
+        }
+        return schema;
+    }
+    if kind.ends_with("[]") {
+        return json!({"type": "array", "items": get_type_schema(&kind[..kind.len() - 2])});
+    }
+    match kind {
+        "any" => json!({}),
+        _ => json!({"type": kind})
+    }
+}
+
+fn get_options_schema(groups: &[&[OptionDef]]) -> Value {
+    let mut properties = Map::new();
+    let mut required = vec![];
+    for options in groups {
+        for &(name, kind, is_required) in options.iter() {
+            properties.insert(name.to_string(), get_type_schema(kind));
+            if is_required {
+                required.push(name);
+            }
+        }
+    }
+    json!({"type": "object", "properties": properties, "required": required})
+}
+
+//由类型的示例值推导schema，对象的全部属性都是必须的，空数组不描述元素类型
+pub fn infer_schema(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({}),
+        Value::Bool(_) => json!({"type": "boolean"}),
+        Value::Number(x) => if x.is_f64() { json!({"type": "number"}) } else { json!({"type": "integer"}) },
+        Value::String(_) => json!({"type": "string"}),
+        Value::Array(items) => match items.first() {
+            Some(item) => json!({"type": "array", "items": infer_schema(item)}),
+            None => json!({"type": "array"})
+        },
+        Value::Object(object) => {
+            let mut properties = Map::new();
+            for (key, value) in object {
+                properties.insert(key.clone(), infer_schema(value));
+            }
+            let required: Vec<&String> = object.keys().collect();
+            json!({"type": "object", "properties": properties, "required": required})
+        }
+    }
+}
+
+fn get_definitions() -> Value {
+    let mut request = infer_schema(&json!(FlareRequest { cmd: String::new(), options: Map::new() }));
+    //options 可以省略
+    request["required"] = json!(["cmd"]);
+    let mut response = infer_schema(&json!(FlareResponse::success("", Value::Null)));
+    response["properties"]["result"] = json!({"type": "string", "enum": [RESULT_SUCCESS, RESULT_FAILURE]});
+    let mut error_response = infer_schema(&json!(FlareResponse::failure("", "")));
+    error_response["properties"]["result"] = json!({"type": "string", "enum": [RESULT_FAILURE]});
+    json!({
+        "request": request,
+        "response": response,
+        "error_response": error_response,
+        "capabilities": infer_schema(&protocol::get_capabilities()),
+    })
+}
+
+//命令响应数据(data)的schema
+pub fn get_result_schema(cmd: &str) -> io::Result<Value> {
+    if cmd == "hello" {
+        return Ok(json!({"$ref": "#/definitions/capabilities"}));
+    }
+    match COMMAND_RESULTS.iter().find(|x| x.0 == cmd) {
+        Some(&(_, fields, groups)) => {
+            let mut all_fields = vec![fields];
+            all_fields.extend_from_slice(groups);
+            Ok(get_options_schema(&all_fields))
+        }
+        None => Err(new_invalid_input_error(&format!("unsupported cmd: {}, protocol version: {}", cmd, protocol::PROTOCOL_VERSION)))
+    }
+}
+
+pub fn get_command_schema(cmd: &str) -> io::Result<Value> {
+    let &(_, options, groups) = match COMMAND_OPTIONS.iter().find(|x| x.0 == cmd) {
+        Some(x) => x,
+        None => return Err(new_invalid_input_error(&format!("unsupported cmd: {}, protocol version: {}", cmd, protocol::PROTOCOL_VERSION)))
+    };
+    let mut all_options = vec![options];
+    all_options.extend_from_slice(groups);
+    all_options.push(COMMON_OPTIONS);
+    let response = json!({"allOf": [{"$ref": "#/definitions/response"}, {"properties": {"data": get_result_schema(cmd)?}}]});
+    Ok(json!({
+        "request": {
+            "type": "object",
+            "properties": {
+                "cmd": {"type": "string", "const": cmd},
+                "options": get_options_schema(&all_options),
+            },
+            "required": ["cmd"],
+        },
+        "response": response,
+        "error": {"$ref": "#/definitions/error_response"},
+    }))
+}
+
+//没有参数或响应数据定义的命令
+pub fn get_undocumented_commands() -> Vec<&'static str> {
+    SUPPORTED_COMMANDS.iter()
+        .filter(|x| !COMMAND_OPTIONS.iter().any(|c| c.0 == **x) || (**x != "hello" && !COMMAND_RESULTS.iter().any(|c| c.0 == **x)))
+        .cloned().collect()
+}
+
+pub fn get_protocol_schema() -> Value {
+    let mut commands = Map::new();
+    for cmd in SUPPORTED_COMMANDS {
+        if let Ok(schema) = get_command_schema(cmd) {
+            commands.insert(cmd.to_string(), schema);
+        }
+    }
+    json!({
+        "$schema": JSON_SCHEMA_DRAFT,
+        "title": "flare profiler websocket protocol",
+        "protocol_version": protocol::PROTOCOL_VERSION,
+        "server_version": protocol::SERVER_VERSION,
+        "definitions": get_definitions(),
+        "commands": commands,
+    })
+}
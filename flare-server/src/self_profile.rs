@@ -0,0 +1,262 @@
+
+//服务端的区段计时(span timing)：诊断某个分析查询为什么慢
+//  不是对服务端线程调用栈的取样: 只能看到代码中手动标记的区段，区段内部的函数调用不可见
+//  请求处理、分析任务及取样会话的耗时操作进入命名的区段(span)，每个线程维护当前的区段栈，
+//  开启后后台线程按间隔记录各线程的区段栈，写入一个普通的取样目录(SampleWriter)，
+//  可以用 open_sample 打开，按火焰图等方式查看查询时间花在哪些区段
+//  区段名作为方法名，栈底为请求的命令，没有进入区段的空闲线程不记录
+//  记录的时间是墙上时间(每次记录累加一个间隔)，不是线程的CPU时间，等待锁或者IO的时间同样计入
+//命令及启动参数沿用 self_profile / --self-profile
+//没有开启时进入区段只检查一个标志，不记录
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use chrono::Local;
+use sample::ThreadData;
+use sample_writer::SampleWriter;
+use utils::*;
+
+pub const SELF_PROFILE_AGENT_ADDR: &str = "flare-server";
+pub const DEFAULT_SELF_PROFILE_INTERVAL_MS: i64 = 10;
+
+struct ThreadSpans {
+    id: i64,
+    name: String,
+    spans: Vec<String>,
+}
+
+struct SelfProfileSession {
+    seq: usize,
+    writer: SampleWriter,
+    interval_ms: i64,
+    start_time: i64,
+    duration_secs: i64,
+    samples: i64,
+    //thread id -> 累计的CPU时间(ns)
+    cpu_times: HashMap<i64, i64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SelfProfileStatus {
+    pub running: bool,
+    pub sample_data_dir: String,
+    pub interval_ms: i64,
+    pub start_time: i64,
+    //记录的线程区段栈数量
+    pub samples: i64,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(1);
+static NEXT_SESSION_SEQ: AtomicUsize = AtomicUsize::new(1);
+
+lazy_static! {
+    static ref PROFILED_THREADS: Mutex<Vec<Arc<Mutex<ThreadSpans>>>> = Mutex::new(vec![]);
+    static ref SESSION: Mutex<Option<SelfProfileSession>> = Mutex::new(None);
+    //最近一次结束的区段计时
+    static ref LAST_STATUS: Mutex<Option<SelfProfileStatus>> = Mutex::new(None);
+}
+
+thread_local! {
+    static CURRENT_THREAD: RefCell<Option<Arc<Mutex<ThreadSpans>>>> = RefCell::new(None);
+}
+
+//离开作用域时退出进入的区段
+pub struct SpanGuard {
+    depth: usize,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        if self.depth == 0 {
+            return;
+        }
+        with_thread_spans(|spans| {
+            let len = spans.spans.len().saturating_sub(self.depth);
+            spans.spans.truncate(len);
+        });
+    }
+}
+
+fn with_thread_spans<F: FnOnce(&mut ThreadSpans)>(f: F) {
+    CURRENT_THREAD.with(|current| {
+        let mut current = current.borrow_mut();
+        if current.is_none() {
+            let current_thread = thread::current();
+            let id = NEXT_THREAD_ID.fetch_add(1, Ordering::SeqCst) as i64;
+            let name = current_thread.name().map_or_else(|| format!("thread-{}", id), |x| x.to_string());
+            let spans = Arc::new(Mutex::new(ThreadSpans { id, name, spans: vec![] }));
+            PROFILED_THREADS.lock().unwrap().push(spans.clone());
+            *current = Some(spans);
+        }
+        f(&mut current.as_ref().unwrap().lock().unwrap());
+    });
+}
+
+pub fn is_self_profiling() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn enter_span(name: &str) -> SpanGuard {
+    if !is_self_profiling() {
+        return SpanGuard { depth: 0 };
+    }
+    with_thread_spans(|spans| spans.spans.push(name.to_string()));
+    SpanGuard { depth: 1 }
+}
+
+//分析任务在线程池中执行，继续提交任务时的区段栈
+pub fn enter_spans(names: &[String]) -> SpanGuard {
+    if !is_self_profiling() || names.is_empty() {
+        return SpanGuard { depth: 0 };
+    }
+    with_thread_spans(|spans| spans.spans.extend_from_slice(names));
+    SpanGuard { depth: names.len() }
+}
+
+pub fn get_span_stack() -> Vec<String> {
+    if !is_self_profiling() {
+        return vec![];
+    }
+    let mut result = vec![];
+    with_thread_spans(|spans| result = spans.spans.clone());
+    result
+}
+
+pub fn start_self_profiling(sample_data_dir: &str, interval_ms: i64, duration_secs: i64) -> io::Result<SelfProfileStatus> {
+    if interval_ms <= 0 || duration_secs < 0 {
+        return Err(new_invalid_input_error("interval_ms must be positive and duration_secs must not be negative"));
+    }
+    let mut session = SESSION.lock().unwrap();
+    if session.is_some() {
+        return Err(new_error(ErrorKind::AlreadyExists, "span timing is already running"));
+    }
+    let writer = SampleWriter::new(sample_data_dir, interval_ms, SELF_PROFILE_AGENT_ADDR)?;
+    let seq = NEXT_SESSION_SEQ.fetch_add(1, Ordering::SeqCst);
+    *session = Some(SelfProfileSession {
+        seq,
+        writer,
+        interval_ms,
+        start_time: Local::now().timestamp_millis(),
+        duration_secs,
+        samples: 0,
+        cpu_times: HashMap::new(),
+    });
+    ENABLED.store(true, Ordering::SeqCst);
+    thread::Builder::new()
+        .name("flare-self-profile".to_string())
+        .spawn(move || sample_loop(seq, interval_ms))?;
+    println!("span timing is started, interval: {}ms, sample dir: {}", interval_ms, sample_data_dir);
+    Ok(get_status(session.as_ref().unwrap()))
+}
+
+pub fn stop_self_profiling() -> io::Result<SelfProfileStatus> {
+    match SESSION.lock().unwrap().take() {
+        Some(session) => finish_session(session),
+        None => Err(new_invalid_input_error("span timing is not running"))
+    }
+}
+
+//正在运行或者最近一次结束的区段计时
+pub fn get_self_profiling_status() -> Option<SelfProfileStatus> {
+    if let Some(session) = SESSION.lock().unwrap().as_ref() {
+        return Some(get_status(session));
+    }
+    LAST_STATUS.lock().unwrap().clone()
+}
+
+fn get_status(session: &SelfProfileSession) -> SelfProfileStatus {
+    SelfProfileStatus {
+        running: true,
+        sample_data_dir: session.writer.get_sample_data_dir().to_string(),
+        interval_ms: session.interval_ms,
+        start_time: session.start_time,
+        samples: session.samples,
+    }
+}
+
+fn finish_session(session: SelfProfileSession) -> io::Result<SelfProfileStatus> {
+    ENABLED.store(false, Ordering::SeqCst);
+    let mut status = get_status(&session);
+    status.running = false;
+    *LAST_STATUS.lock().unwrap() = Some(status.clone());
+    //没有取样时也保存汇总信息，目录可以打开
+    session.writer.finish()?;
+    println!("span timing is stopped, samples: {}, sample dir: {}", status.samples, status.sample_data_dir);
+    Ok(status)
+}
+
+fn sample_loop(seq: usize, interval_ms: i64) {
+    loop {
+        thread::sleep(Duration::from_millis(interval_ms as u64));
+        let mut session_ref = SESSION.lock().unwrap();
+        match session_ref.as_ref() {
+            Some(session) if session.seq == seq => {}
+            _ => return
+        }
+        let now = Local::now().timestamp_millis();
+        let expired = {
+            let session = session_ref.as_mut().unwrap();
+            if let Err(e) = sample_threads(session, now) {
+                println!("span timing record failed: {}", e);
+            }
+            session.duration_secs > 0 && now - session.start_time >= session.duration_secs * 1000
+        };
+        if expired {
+            if let Err(e) = finish_session(session_ref.take().unwrap()) {
+                println!("finish span timing failed: {}", e);
+            }
+            return;
+        }
+    }
+}
+
+fn sample_threads(session: &mut SelfProfileSession, now: i64) -> io::Result<()> {
+    let mut stacks = vec![];
+    {
+        let mut threads = PROFILED_THREADS.lock().unwrap();
+        //已经结束的线程只剩下这里的引用
+        threads.retain(|x| Arc::strong_count(x) > 1);
+        for thread_spans in threads.iter() {
+            let thread_spans = thread_spans.lock().unwrap();
+            if !thread_spans.spans.is_empty() {
+                stacks.push((thread_spans.id, thread_spans.name.clone(), thread_spans.spans.clone()));
+            }
+        }
+    }
+    //墙上时间，见文件开头的说明
+    let cpu_time_delta = session.interval_ms * 1_000_000;
+    for (thread_id, name, spans) in stacks {
+        //栈顶在前
+        let mut stacktrace = vec![];
+        for span in spans.iter().rev() {
+            stacktrace.push(session.writer.get_or_add_method(span)?);
+        }
+        let cpu_time = session.cpu_times.entry(thread_id).or_insert(0);
+        *cpu_time += cpu_time_delta;
+        let thread_data = ThreadData {
+            id: thread_id,
+            name,
+            priority: 0,
+            daemon: false,
+            state: "RUNNABLE".to_string(),
+            cpu_time: *cpu_time,
+            cpu_time_delta,
+            sample_time: now,
+            sample_count: 1,
+            stacktrace,
+            duration: 0,
+            self_duration: 0,
+            self_cpu_time: 0,
+        };
+        session.writer.add_thread_sample(&thread_data)?;
+        session.samples += 1;
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+
+//会话事件：agent推送的诊断信息(取样超时、JVMTI调用失败、发送队列丢弃数据等)，每条追加一行到会话目录下的文件
+//  session_events.json
+//查询使用 session_events 命令，录制中新增的事件推送给订阅的客户端(session_event)，数据质量下降时前端可以提示
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use flare_proto::agent::DiagnosticEvent;
+use utils::new_invalid_input_error;
+
+pub const SESSION_EVENTS_FILE: &str = "session_events.json";
+
+pub const LEVEL_INFO: &str = "info";
+pub const LEVEL_WARN: &str = "warn";
+pub const LEVEL_ERROR: &str = "error";
+pub const LEVELS: &[&str] = &[LEVEL_INFO, LEVEL_WARN, LEVEL_ERROR];
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SessionEvent {
+    pub time: i64,
+    pub level: String,
+    pub kind: String,
+    pub message: String,
+    //agent在一个上报周期内合并的次数
+    pub count: i64,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct SessionEventSummary {
+    pub kind: String,
+    pub level: String,
+    pub events: usize,
+    pub count: i64,
+    pub last_time: i64,
+    pub last_message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SessionEventQuery {
+    //-1表示不限制
+    pub start_time: i64,
+    pub end_time: i64,
+    //最低级别，空表示全部
+    pub level: String,
+    pub kind: String,
+}
+
+impl SessionEventQuery {
+    pub fn matches(&self, event: &SessionEvent) -> bool {
+        (self.start_time < 0 || event.time >= self.start_time)
+            && (self.end_time < 0 || event.time <= self.end_time)
+            && (self.level.is_empty() || get_level_rank(&event.level) >= get_level_rank(&self.level))
+            && (self.kind.is_empty() || event.kind == self.kind)
+    }
+}
+
+//未知的级别按info处理
+pub fn get_level_rank(level: &str) -> usize {
+    LEVELS.iter().position(|x| *x == level).unwrap_or(0)
+}
+
+pub fn check_level(level: &str) -> io::Result<()> {
+    if !level.is_empty() && !LEVELS.contains(&level) {
+        return Err(new_invalid_input_error(&format!("invalid level: {}, expect one of {}", level, LEVELS.join(", "))));
+    }
+    Ok(())
+}
+
+pub fn new_session_event(event: &DiagnosticEvent) -> SessionEvent {
+    let level = if LEVELS.contains(&event.level.as_str()) { event.level.clone() } else { LEVEL_INFO.to_string() };
+    SessionEvent {
+        time: event.time,
+        level,
+        kind: event.kind.clone(),
+        message: event.message.clone(),
+        count: event.count.max(1),
+    }
+}
+
+pub fn append_session_event<P: AsRef<Path>>(sample_data_dir: P, event: &SessionEvent) -> io::Result<()> {
+    let path = sample_data_dir.as_ref().join(SESSION_EVENTS_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    //one event per line
+    let mut data = serde_json::to_vec(event)?;
+    data.push(b'\n');
+    file.write_all(&data)
+}
+
+pub fn load_session_events<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<SessionEvent>> {
+    let path = sample_data_dir.as_ref().join(SESSION_EVENTS_FILE);
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut events = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        events.push(serde_json::from_str::<SessionEvent>(&line)?);
+    }
+    Ok(events)
+}
+
+//按类型汇总，级别高的在前
+pub fn summarize_session_events(events: &[SessionEvent]) -> Vec<SessionEventSummary> {
+    let mut summary_map: BTreeMap<(String, String), SessionEventSummary> = BTreeMap::new();
+    for event in events {
+        let summary = summary_map.entry((event.kind.clone(), event.level.clone())).or_insert_with(|| SessionEventSummary {
+            kind: event.kind.clone(),
+            level: event.level.clone(),
+            events: 0,
+            count: 0,
+            last_time: 0,
+            last_message: String::new(),
+        });
+        summary.events += 1;
+        summary.count += event.count;
+        if event.time >= summary.last_time {
+            summary.last_time = event.time;
+            summary.last_message = event.message.clone();
+        }
+    }
+    let mut summaries: Vec<SessionEventSummary> = summary_map.into_iter().map(|x| x.1).collect();
+    summaries.sort_by(|a, b| get_level_rank(&b.level).cmp(&get_level_rank(&a.level)).then(a.kind.cmp(&b.kind)));
+    summaries
+}
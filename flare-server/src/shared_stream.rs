@@ -0,0 +1,51 @@
+//websocket连接的输出流，连接线程、任务池线程及事件推送使用复制的Writer发送消息
+//  复制的流共享写锁，websocket的每个帧用一次 write_all 写入，加锁后不同线程发送的帧不会交错
+
+use std::io;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+
+pub struct SharedStream {
+    stream: TcpStream,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl SharedStream {
+    pub fn new(stream: TcpStream) -> SharedStream {
+        SharedStream {
+            stream,
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    //复制的流与原来的流使用同一个写锁
+    pub fn try_clone(&self) -> io::Result<SharedStream> {
+        Ok(SharedStream {
+            stream: self.stream.try_clone()?,
+            write_lock: self.write_lock.clone(),
+        })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+}
+
+impl Write for SharedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.stream.write(buf)
+    }
+
+    //整个帧写完之前不释放写锁
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.stream.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.stream.flush()
+    }
+}
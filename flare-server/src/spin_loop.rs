@@ -0,0 +1,105 @@
+
+//忙等待/自旋检测：线程持续RUNNABLE并且CPU占用高，但调用栈一直停留在很少的几个方法上(没有进展)，
+//常见于自旋锁、轮询标志位、空的while循环等
+//  栈顶方法集合: 每个取样栈顶的 top_depth 个方法，绝大多数取样落在少数几个集合中时认为在自旋
+
+use ::sample::*;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Clone, Debug)]
+pub struct SpinLoopOptions {
+    //线程CPU占用率(CPU时间/取样时间)的下限
+    pub min_cpu_ratio: f64,
+    pub min_samples: usize,
+    //比较的栈顶方法数量
+    pub top_depth: usize,
+    //最多的栈顶方法集合数量
+    pub max_distinct_frames: usize,
+    //前 max_distinct_frames 个集合覆盖的取样占比
+    pub min_coverage: f64,
+}
+
+impl Default for SpinLoopOptions {
+    fn default() -> Self {
+        SpinLoopOptions {
+            min_cpu_ratio: 0.8,
+            min_samples: 50,
+            top_depth: 3,
+            max_distinct_frames: 3,
+            min_coverage: 0.9,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpinLoopThread {
+    pub thread_id: i64,
+    pub thread_name: String,
+    pub samples: usize,
+    pub cpu_ratio: f64,
+    //前几个栈顶方法集合覆盖的取样占比
+    pub coverage: f64,
+    pub distinct_frames: usize,
+    //自旋所在的方法，栈顶在前
+    pub frames: Vec<String>,
+    //代表性的调用栈
+    pub stacktrace: Vec<String>,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+pub fn detect_spin_loops(collector: &mut SampleCollector, start_time: i64, end_time: i64, options: &SpinLoopOptions) -> io::Result<Vec<SpinLoopThread>> {
+    let sample_interval = collector.get_sample_info().sample_interval.max(1);
+    let top_depth = options.top_depth.max(1);
+    let mut result = vec![];
+    for thread in collector.get_threads()? {
+        let samples = match collector.load_thread_samples(thread.id, start_time, end_time) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("load thread samples failed, thread: {}, error: {}", thread.id, e);
+                continue;
+            }
+        };
+        let runnable: Vec<&ThreadData> = samples.iter().filter(|x| x.state == "RUNNABLE" && !x.stacktrace.is_empty()).collect();
+        //自旋期间不会进入其他状态
+        if runnable.len() < options.min_samples || runnable.len() * 10 < samples.len() * 9 {
+            continue;
+        }
+        let cpu_time: i64 = runnable.iter().map(|x| x.cpu_time_delta).sum();
+        let cpu_ratio = cpu_time as f64 / (runnable.len() as i64 * sample_interval * 1_000_000) as f64;
+        if cpu_ratio < options.min_cpu_ratio {
+            continue;
+        }
+
+        let mut frame_counts: HashMap<&[i64], (usize, &ThreadData)> = HashMap::new();
+        for sample in &runnable {
+            let depth = top_depth.min(sample.stacktrace.len());
+            let entry = frame_counts.entry(&sample.stacktrace[..depth]).or_insert((0, sample));
+            entry.0 += 1;
+        }
+        let mut counts: Vec<(&[i64], (usize, &ThreadData))> = frame_counts.into_iter().collect();
+        counts.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then(a.0.cmp(b.0)));
+        let covered: usize = counts.iter().take(options.max_distinct_frames.max(1)).map(|x| (x.1).0).sum();
+        let coverage = covered as f64 / runnable.len() as f64;
+        if coverage < options.min_coverage {
+            continue;
+        }
+
+        let (frames, (_, sample)) = &counts[0];
+        result.push(SpinLoopThread {
+            thread_id: thread.id,
+            thread_name: thread.name.clone(),
+            samples: runnable.len(),
+            cpu_ratio: (cpu_ratio * 1000.0).round() / 1000.0,
+            coverage: (coverage * 1000.0).round() / 1000.0,
+            distinct_frames: counts.len(),
+            frames: frames.iter().map(|x| collector.get_method_name(*x)).collect(),
+            stacktrace: sample.stacktrace.iter().map(|x| collector.get_method_name(*x)).collect(),
+            start_time: runnable[0].sample_time,
+            end_time: runnable[runnable.len() - 1].sample_time + sample_interval,
+        });
+    }
+    result.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.thread_id.cmp(&b.thread_id)));
+    Ok(result)
+}
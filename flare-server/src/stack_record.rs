@@ -0,0 +1,479 @@
+
+//线程调用栈记录的存储格式，保存在 thread_<id>_stack.fdata 中，每次取样一条
+//  旧版本为 ThreadData 的JSON，读取时需要为每次取样分配字符串及调用栈数组，折叠大范围的取样时分配频繁
+//  二进制格式按大端紧凑存储，读取时不复制，StackRecord 直接引用映射的文件数据(见 TupleIndexedFile::map_range)：
+//    magic(1) version(1) flags(1) state_len(1) name_len(2) priority(4)
+//    id(8) cpu_time(8) cpu_time_delta(8) sample_time(8) sample_count(8) frame_count(4)
+//    state(state_len) name(name_len) frames(8 * frame_count, 栈顶在前)
+//  方法id是jmethodID，保持64位；按大端存储不能直接转换为本机的数组，按需解码
+//JSON记录以'{'开头，与magic不同，两种格式可以在同一个文件中，旧的取样目录不需要升级
+//
+//新录制的调用栈按列存储为数据块(StackChunk)，每个数据块包含一个线程连续的多次取样：
+//    magic(1) version(1) string_count(2) row_count(4) frame_count(4) strings_len(4)
+//    steps(4 * n) sample_time(8 * n) id(8 * n) cpu_time(8 * n) cpu_time_delta(8 * n) sample_count(8 * n)
+//    priority(4 * n) flags(n) name(2 * n) state(2 * n) frame_offsets(4 * (n + 1))
+//    string_offsets(4 * (string_count + 1)) strings(strings_len) frames(8 * frame_count)
+//  按时间过滤时只读取 steps 列，命中的行才引用调用栈数据；同一列的值相邻存放，线程名称及状态在数据块内去重，压缩率更高
+//  数据块的索引是最后一行的步数，map_range 返回与查询范围相交的数据块，再按 steps 列过滤
+//  数据块与单条记录可以在同一个文件中
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+use sample::ThreadData;
+use utils::*;
+
+pub const STACK_RECORD_MAGIC: u8 = 0xF5;
+pub const STACK_RECORD_VERSION: u8 = 1;
+const STACK_RECORD_HEADER_LEN: usize = 54;
+const FLAG_DAEMON: u8 = 1;
+
+pub const STACK_CHUNK_MAGIC: u8 = 0xF6;
+pub const STACK_CHUNK_VERSION: u8 = 1;
+//每个数据块的最大行数，刷新缓冲数据时未满的数据块也会写入
+pub const STACK_CHUNK_MAX_ROWS: usize = 256;
+const STACK_CHUNK_HEADER_LEN: usize = 16;
+//每行定长列的字节数，不含 frame_offsets
+const STACK_CHUNK_ROW_LEN: usize = 53;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackRecord<'a> {
+    pub id: i64,
+    pub name: Cow<'a, str>,
+    pub priority: u32,
+    pub daemon: bool,
+    pub state: Cow<'a, str>,
+    pub cpu_time: i64,
+    pub cpu_time_delta: i64,
+    pub sample_time: i64,
+    pub sample_count: i64,
+    //编码后的调用栈，每个方法8字节
+    frames: Cow<'a, [u8]>,
+}
+
+impl<'a> StackRecord<'a> {
+    //解析二进制记录或者旧版本的JSON记录
+    pub fn parse(data: &'a [u8]) -> io::Result<StackRecord<'a>> {
+        match data.first() {
+            Some(&STACK_RECORD_MAGIC) => StackRecord::parse_binary(data),
+            Some(b'{') => {
+                let thread_data = serde_json::from_slice::<ThreadData>(data)?;
+                Ok(StackRecord::from_thread_data(&thread_data))
+            }
+            _ => Err(new_error(ErrorKind::InvalidData, "invalid stack record"))
+        }
+    }
+
+    fn parse_binary(data: &'a [u8]) -> io::Result<StackRecord<'a>> {
+        if data.len() < STACK_RECORD_HEADER_LEN {
+            return Err(new_error(ErrorKind::UnexpectedEof, "stack record is truncated"));
+        }
+        if data[1] != STACK_RECORD_VERSION {
+            return Err(new_error(ErrorKind::InvalidData, &format!("unsupported stack record version: {}", data[1])));
+        }
+        let state_len = data[3] as usize;
+        let name_len = read_u16(data, 4) as usize;
+        let frame_count = read_u32(data, 50) as usize;
+        let state_end = STACK_RECORD_HEADER_LEN + state_len;
+        let name_end = state_end + name_len;
+        if data.len() != name_end + frame_count * 8 {
+            return Err(new_error(ErrorKind::InvalidData, "invalid stack record length"));
+        }
+        let to_str = |bytes: &'a [u8]| std::str::from_utf8(bytes).map_err(|_| new_error(ErrorKind::InvalidData, "stack record string is not utf8"));
+        Ok(StackRecord {
+            id: read_i64(data, 10),
+            name: Cow::Borrowed(to_str(&data[state_end..name_end])?),
+            priority: read_u32(data, 6),
+            daemon: data[2] & FLAG_DAEMON != 0,
+            state: Cow::Borrowed(to_str(&data[STACK_RECORD_HEADER_LEN..state_end])?),
+            cpu_time: read_i64(data, 18),
+            cpu_time_delta: read_i64(data, 26),
+            sample_time: read_i64(data, 34),
+            sample_count: read_i64(data, 42),
+            frames: Cow::Borrowed(&data[name_end..]),
+        })
+    }
+
+    pub fn from_thread_data(thread_data: &ThreadData) -> StackRecord<'static> {
+        let mut frames = Vec::with_capacity(thread_data.stacktrace.len() * 8);
+        for method in &thread_data.stacktrace {
+            frames.extend_from_slice(&method.to_be_bytes());
+        }
+        StackRecord {
+            id: thread_data.id,
+            name: Cow::Owned(thread_data.name.clone()),
+            priority: thread_data.priority,
+            daemon: thread_data.daemon,
+            state: Cow::Owned(thread_data.state.clone()),
+            cpu_time: thread_data.cpu_time,
+            cpu_time_delta: thread_data.cpu_time_delta,
+            sample_time: thread_data.sample_time,
+            sample_count: thread_data.sample_count,
+            frames: Cow::Owned(frames),
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len() / 8
+    }
+
+    //栈顶在前，.rev() 从栈底开始
+    pub fn frames(&self) -> impl DoubleEndedIterator<Item=i64> + '_ {
+        self.frames.chunks_exact(8).map(|x| read_i64(x, 0))
+    }
+
+    pub fn to_thread_data(&self) -> ThreadData {
+        ThreadData {
+            id: self.id,
+            name: self.name.to_string(),
+            priority: self.priority,
+            daemon: self.daemon,
+            state: self.state.to_string(),
+            cpu_time: self.cpu_time,
+            cpu_time_delta: self.cpu_time_delta,
+            sample_time: self.sample_time,
+            sample_count: self.sample_count,
+            stacktrace: self.frames().collect(),
+            duration: 0,
+            self_duration: 0,
+            self_cpu_time: 0,
+        }
+    }
+}
+
+//线程名称及状态超长时截断到字符边界
+pub fn encode_stack_record(thread_data: &ThreadData) -> Vec<u8> {
+    let state = truncate_str(&thread_data.state, u8::max_value() as usize);
+    let name = truncate_str(&thread_data.name, u16::max_value() as usize);
+    let mut data = Vec::with_capacity(STACK_RECORD_HEADER_LEN + state.len() + name.len() + thread_data.stacktrace.len() * 8);
+    data.push(STACK_RECORD_MAGIC);
+    data.push(STACK_RECORD_VERSION);
+    data.push(if thread_data.daemon { FLAG_DAEMON } else { 0 });
+    data.push(state.len() as u8);
+    data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    data.extend_from_slice(&thread_data.priority.to_be_bytes());
+    for x in &[thread_data.id, thread_data.cpu_time, thread_data.cpu_time_delta, thread_data.sample_time, thread_data.sample_count] {
+        data.extend_from_slice(&x.to_be_bytes());
+    }
+    data.extend_from_slice(&(thread_data.stacktrace.len() as u32).to_be_bytes());
+    data.extend_from_slice(state.as_bytes());
+    data.extend_from_slice(name.as_bytes());
+    for method in &thread_data.stacktrace {
+        data.extend_from_slice(&method.to_be_bytes());
+    }
+    data
+}
+
+pub fn decode_thread_data(data: &[u8]) -> io::Result<ThreadData> {
+    StackRecord::parse(data).map(|x| x.to_thread_data())
+}
+
+//解析映射范围内的调用栈，数据块只返回步数在[start_step, end_step]内的行，单条记录全部返回，忽略损坏的数据
+pub fn parse_stack_records<'a, I: Iterator<Item=&'a [u8]>>(values: I, start_step: u32, end_step: u32) -> Vec<StackRecord<'a>> {
+    let mut records = vec![];
+    for data in values {
+        if data.first() == Some(&STACK_CHUNK_MAGIC) {
+            if let Ok(chunk) = StackChunk::parse(data) {
+                for row in chunk.rows_in_steps(start_step, end_step) {
+                    if let Ok(record) = chunk.record(row) {
+                        records.push(record);
+                    }
+                }
+            }
+        } else if let Ok(record) = StackRecord::parse(data) {
+            records.push(record);
+        }
+    }
+    records
+}
+
+//录制时缓冲一个线程的取样，按列编码为一个数据块
+#[derive(Default)]
+pub struct StackChunkBuilder {
+    steps: Vec<u32>,
+    sample_times: Vec<i64>,
+    ids: Vec<i64>,
+    cpu_times: Vec<i64>,
+    cpu_time_deltas: Vec<i64>,
+    sample_counts: Vec<i64>,
+    priorities: Vec<u32>,
+    flags: Vec<u8>,
+    names: Vec<u16>,
+    states: Vec<u16>,
+    frame_ends: Vec<u32>,
+    frames: Vec<i64>,
+    strings: Vec<String>,
+    string_index: HashMap<String, u16>,
+}
+
+impl StackChunkBuilder {
+    pub fn new() -> StackChunkBuilder {
+        Default::default()
+    }
+
+    //同一个线程的取样按步数顺序添加
+    pub fn add(&mut self, step: u32, thread_data: &ThreadData) {
+        let name = self.add_string(&thread_data.name);
+        let state = self.add_string(&thread_data.state);
+        self.steps.push(step);
+        self.sample_times.push(thread_data.sample_time);
+        self.ids.push(thread_data.id);
+        self.cpu_times.push(thread_data.cpu_time);
+        self.cpu_time_deltas.push(thread_data.cpu_time_delta);
+        self.sample_counts.push(thread_data.sample_count);
+        self.priorities.push(thread_data.priority);
+        self.flags.push(if thread_data.daemon { FLAG_DAEMON } else { 0 });
+        self.names.push(name);
+        self.states.push(state);
+        self.frames.extend_from_slice(&thread_data.stacktrace);
+        self.frame_ends.push(self.frames.len() as u32);
+    }
+
+    fn add_string(&mut self, s: &str) -> u16 {
+        if let Some(index) = self.string_index.get(s) {
+            return *index;
+        }
+        let index = self.strings.len() as u16;
+        self.strings.push(s.to_string());
+        self.string_index.insert(s.to_string(), index);
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.steps.len() >= STACK_CHUNK_MAX_ROWS
+    }
+
+    //编码后的字节数
+    pub fn get_encoded_len(&self) -> usize {
+        let strings_len: usize = self.strings.iter().map(|x| x.len()).sum();
+        STACK_CHUNK_HEADER_LEN + self.steps.len() * STACK_CHUNK_ROW_LEN + (self.steps.len() + 1) * 4
+            + (self.strings.len() + 1) * 4 + strings_len + self.frames.len() * 8
+    }
+
+    //编码缓冲的取样并清空，返回(索引步数, 数据块)
+    pub fn take(&mut self) -> (u32, Vec<u8>) {
+        let step = self.steps.last().cloned().unwrap_or(0);
+        let data = self.encode();
+        *self = StackChunkBuilder::new();
+        (step, data)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let row_count = self.steps.len();
+        let strings_len: usize = self.strings.iter().map(|x| x.len()).sum();
+        let mut data = Vec::with_capacity(self.get_encoded_len());
+        data.push(STACK_CHUNK_MAGIC);
+        data.push(STACK_CHUNK_VERSION);
+        data.extend_from_slice(&(self.strings.len() as u16).to_be_bytes());
+        data.extend_from_slice(&(row_count as u32).to_be_bytes());
+        data.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+        data.extend_from_slice(&(strings_len as u32).to_be_bytes());
+        for x in &self.steps {
+            data.extend_from_slice(&x.to_be_bytes());
+        }
+        for column in &[&self.sample_times, &self.ids, &self.cpu_times, &self.cpu_time_deltas, &self.sample_counts] {
+            for x in column.iter() {
+                data.extend_from_slice(&x.to_be_bytes());
+            }
+        }
+        for x in &self.priorities {
+            data.extend_from_slice(&x.to_be_bytes());
+        }
+        data.extend_from_slice(&self.flags);
+        for column in &[&self.names, &self.states] {
+            for x in column.iter() {
+                data.extend_from_slice(&x.to_be_bytes());
+            }
+        }
+        data.extend_from_slice(&0u32.to_be_bytes());
+        for x in &self.frame_ends {
+            data.extend_from_slice(&x.to_be_bytes());
+        }
+        let mut string_end = 0u32;
+        data.extend_from_slice(&string_end.to_be_bytes());
+        for x in &self.strings {
+            string_end += x.len() as u32;
+            data.extend_from_slice(&string_end.to_be_bytes());
+        }
+        for x in &self.strings {
+            data.extend_from_slice(x.as_bytes());
+        }
+        for x in &self.frames {
+            data.extend_from_slice(&x.to_be_bytes());
+        }
+        data
+    }
+}
+
+//引用映射数据的列式数据块，按行读取时不复制调用栈
+pub struct StackChunk<'a> {
+    row_count: usize,
+    steps: &'a [u8],
+    sample_times: &'a [u8],
+    ids: &'a [u8],
+    cpu_times: &'a [u8],
+    cpu_time_deltas: &'a [u8],
+    sample_counts: &'a [u8],
+    priorities: &'a [u8],
+    flags: &'a [u8],
+    names: &'a [u8],
+    states: &'a [u8],
+    frame_offsets: &'a [u8],
+    string_offsets: &'a [u8],
+    strings: &'a [u8],
+    frames: &'a [u8],
+}
+
+impl<'a> StackChunk<'a> {
+    pub fn parse(data: &'a [u8]) -> io::Result<StackChunk<'a>> {
+        if data.len() < STACK_CHUNK_HEADER_LEN {
+            return Err(new_error(ErrorKind::UnexpectedEof, "stack chunk is truncated"));
+        }
+        if data[0] != STACK_CHUNK_MAGIC {
+            return Err(new_error(ErrorKind::InvalidData, "invalid stack chunk"));
+        }
+        if data[1] != STACK_CHUNK_VERSION {
+            return Err(new_error(ErrorKind::InvalidData, &format!("unsupported stack chunk version: {}", data[1])));
+        }
+        let string_count = read_u16(data, 2) as usize;
+        let row_count = read_u32(data, 4) as usize;
+        let frame_count = read_u32(data, 8) as usize;
+        let strings_len = read_u32(data, 12) as usize;
+        let expected_len = STACK_CHUNK_HEADER_LEN + row_count * STACK_CHUNK_ROW_LEN + (row_count + 1) * 4
+            + (string_count + 1) * 4 + strings_len + frame_count * 8;
+        if data.len() != expected_len {
+            return Err(new_error(ErrorKind::InvalidData, "invalid stack chunk length"));
+        }
+        let mut pos = STACK_CHUNK_HEADER_LEN;
+        let mut column = |len: usize| -> &'a [u8] {
+            let slice = &data[pos..pos + len];
+            pos += len;
+            slice
+        };
+        let chunk = StackChunk {
+            row_count,
+            steps: column(row_count * 4),
+            sample_times: column(row_count * 8),
+            ids: column(row_count * 8),
+            cpu_times: column(row_count * 8),
+            cpu_time_deltas: column(row_count * 8),
+            sample_counts: column(row_count * 8),
+            priorities: column(row_count * 4),
+            flags: column(row_count),
+            names: column(row_count * 2),
+            states: column(row_count * 2),
+            frame_offsets: column((row_count + 1) * 4),
+            string_offsets: column((string_count + 1) * 4),
+            strings: column(strings_len),
+            frames: column(frame_count * 8),
+        };
+        if !is_valid_offsets(chunk.frame_offsets, frame_count) || !is_valid_offsets(chunk.string_offsets, strings_len) {
+            return Err(new_error(ErrorKind::InvalidData, "invalid stack chunk offsets"));
+        }
+        Ok(chunk)
+    }
+
+    pub fn len(&self) -> usize {
+        self.row_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_count == 0
+    }
+
+    pub fn step(&self, row: usize) -> u32 {
+        read_u32(self.steps, row * 4)
+    }
+
+    pub fn sample_time(&self, row: usize) -> i64 {
+        read_i64(self.sample_times, row * 8)
+    }
+
+    pub fn thread_id(&self, row: usize) -> i64 {
+        read_i64(self.ids, row * 8)
+    }
+
+    //只读取 steps 列过滤行
+    pub fn rows_in_steps(&self, start_step: u32, end_step: u32) -> impl Iterator<Item=usize> + '_ {
+        (0..self.row_count).filter(move |row| {
+            let step = self.step(*row);
+            step >= start_step && step <= end_step
+        })
+    }
+
+    pub fn record(&self, row: usize) -> io::Result<StackRecord<'a>> {
+        let frame_start = read_u32(self.frame_offsets, row * 4) as usize;
+        let frame_end = read_u32(self.frame_offsets, row * 4 + 4) as usize;
+        Ok(StackRecord {
+            id: self.thread_id(row),
+            name: Cow::Borrowed(self.get_string(read_u16(self.names, row * 2))?),
+            priority: read_u32(self.priorities, row * 4),
+            daemon: self.flags[row] & FLAG_DAEMON != 0,
+            state: Cow::Borrowed(self.get_string(read_u16(self.states, row * 2))?),
+            cpu_time: read_i64(self.cpu_times, row * 8),
+            cpu_time_delta: read_i64(self.cpu_time_deltas, row * 8),
+            sample_time: self.sample_time(row),
+            sample_count: read_i64(self.sample_counts, row * 8),
+            frames: Cow::Borrowed(&self.frames[frame_start * 8..frame_end * 8]),
+        })
+    }
+
+    fn get_string(&self, index: u16) -> io::Result<&'a str> {
+        let index = index as usize;
+        if (index + 1) * 4 >= self.string_offsets.len() {
+            return Err(new_error(ErrorKind::InvalidData, "invalid stack chunk string index"));
+        }
+        let start = read_u32(self.string_offsets, index * 4) as usize;
+        let end = read_u32(self.string_offsets, index * 4 + 4) as usize;
+        std::str::from_utf8(&self.strings[start..end]).map_err(|_| new_error(ErrorKind::InvalidData, "stack chunk string is not utf8"))
+    }
+}
+
+//偏移量从0开始递增，最后一个等于数据长度
+fn is_valid_offsets(offsets: &[u8], len: usize) -> bool {
+    let mut last = 0;
+    for i in 0..offsets.len() / 4 {
+        let offset = read_u32(offsets, i * 4) as usize;
+        if (i == 0 && offset != 0) || offset < last {
+            return false;
+        }
+        last = offset;
+    }
+    last == len
+}
+
+fn truncate_str(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([data[pos], data[pos + 1]])
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&data[pos..pos + 4]);
+    u32::from_be_bytes(buf)
+}
+
+fn read_i64(data: &[u8], pos: usize) -> i64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[pos..pos + 8]);
+    i64::from_be_bytes(buf)
+}
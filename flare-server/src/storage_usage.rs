@@ -0,0 +1,120 @@
+
+//会话取样目录占用的磁盘空间，按数据类型统计，用于容量规划
+//  stacks:    线程调用栈 thread_<id>_stack.fidx/.fdata
+//  metrics:   时序文件 *.fts (线程CPU时间、资源指标及其聚合层级)
+//  indexes:   聚合索引目录 agg_index 及方法信息 method_info.fidx/.fdata
+//  snapshots: 线程转储及堆直方图快照目录
+//  other:     汇总信息、标记、会话事件等JSON文件及报告
+//录制中的会话按两次查询之间的增长估算增长速度，第一次查询时按录制时长的平均值估算；
+//录制按周期滚动取样目录，只统计当前的目录
+
+use std::io;
+use std::path::Path;
+use agg_index::AGG_INDEX_DIR;
+use heap_histogram::HEAP_HISTOGRAM_DIR;
+use thread_dump::THREAD_DUMP_DIR;
+
+pub const STORAGE_STACKS: &str = "stacks";
+pub const STORAGE_METRICS: &str = "metrics";
+pub const STORAGE_INDEXES: &str = "indexes";
+pub const STORAGE_SNAPSHOTS: &str = "snapshots";
+pub const STORAGE_OTHER: &str = "other";
+
+//两次测量的最小间隔(ms)，间隔太短时按录制时长的平均值估算
+pub const MIN_GROWTH_INTERVAL_MS: i64 = 1000;
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct StorageUsage {
+    pub total_bytes: u64,
+    pub stacks_bytes: u64,
+    pub metrics_bytes: u64,
+    pub indexes_bytes: u64,
+    pub snapshots_bytes: u64,
+    pub other_bytes: u64,
+    pub files: usize,
+}
+
+impl StorageUsage {
+    fn add(&mut self, category: &str, bytes: u64) {
+        match category {
+            STORAGE_STACKS => self.stacks_bytes += bytes,
+            STORAGE_METRICS => self.metrics_bytes += bytes,
+            STORAGE_INDEXES => self.indexes_bytes += bytes,
+            STORAGE_SNAPSHOTS => self.snapshots_bytes += bytes,
+            _ => self.other_bytes += bytes,
+        }
+        self.total_bytes += bytes;
+        self.files += 1;
+    }
+}
+
+//top_dir 为文件所在的取样目录下的第一级目录，文件直接在取样目录下时为空
+pub fn get_storage_category(top_dir: &str, file_name: &str) -> &'static str {
+    if top_dir == AGG_INDEX_DIR {
+        return STORAGE_INDEXES;
+    }
+    if top_dir == THREAD_DUMP_DIR || top_dir == HEAP_HISTOGRAM_DIR {
+        return STORAGE_SNAPSHOTS;
+    }
+    if !top_dir.is_empty() {
+        return STORAGE_OTHER;
+    }
+    if file_name.starts_with("thread_") && (file_name.ends_with("_stack.fidx") || file_name.ends_with("_stack.fdata")) {
+        STORAGE_STACKS
+    } else if file_name.ends_with(".fts") {
+        STORAGE_METRICS
+    } else if file_name.starts_with("method_info.") {
+        STORAGE_INDEXES
+    } else {
+        STORAGE_OTHER
+    }
+}
+
+pub fn measure_storage_usage(sample_data_dir: &str) -> io::Result<StorageUsage> {
+    let mut usage = StorageUsage::default();
+    measure_dir(Path::new(sample_data_dir), "", &mut usage)?;
+    Ok(usage)
+}
+
+fn measure_dir(dir: &Path, top_dir: &str, usage: &mut StorageUsage) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if metadata.is_dir() {
+            let top_dir = if top_dir.is_empty() { file_name.as_str() } else { top_dir };
+            measure_dir(&entry.path(), top_dir, usage)?;
+        } else {
+            usage.add(get_storage_category(top_dir, &file_name), metadata.len());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct StorageGrowth {
+    pub bytes_per_sec: f64,
+    pub bytes_per_hour: i64,
+    //按当前速度写满磁盘的剩余时间，不能估算时为null
+    pub secs_until_disk_full: Option<i64>,
+}
+
+//last 为上一次测量的(时间, 字节数)，record_duration_ms 为已录制的时长
+pub fn estimate_growth(last: Option<(i64, u64)>, now: i64, total_bytes: u64, record_duration_ms: i64, free_bytes: Option<u64>) -> StorageGrowth {
+    let bytes_per_sec = match last {
+        Some((time, bytes)) if now - time >= MIN_GROWTH_INTERVAL_MS => {
+            total_bytes.saturating_sub(bytes) as f64 * 1000.0 / (now - time) as f64
+        }
+        _ if record_duration_ms > 0 => total_bytes as f64 * 1000.0 / record_duration_ms as f64,
+        _ => 0.0
+    };
+    let bytes_per_sec = (bytes_per_sec * 100.0).round() / 100.0;
+    StorageGrowth {
+        bytes_per_sec,
+        bytes_per_hour: (bytes_per_sec * 3600.0) as i64,
+        secs_until_disk_full: match free_bytes {
+            Some(free) if bytes_per_sec > 0.0 => Some((free as f64 / bytes_per_sec) as i64),
+            _ => None
+        },
+    }
+}
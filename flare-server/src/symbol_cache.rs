@@ -0,0 +1,152 @@
+use ::sample::MethodInfo;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, BufReader};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use utils::*;
+
+pub const SYMBOL_CACHE_VERSION: u32 = 1;
+//取样目录下保存的key: "<method_info.fdata的大小> <哈希>"
+pub const SYMBOL_CACHE_KEY_FILE: &str = "method_info.key";
+const DEFAULT_MAX_ENTRIES: usize = 256;
+
+//方法id与名称的缓存，以取样的方法表(method_info.fdata，方法id及类名/方法签名)的哈希为key，
+//方法id只在同一个JVM进程内有效，内容相同的取样(同一个取样的多次打开、拆分/导出的子取样)共用一个缓存
+//  方法表只在第一次打开时计算哈希并保存到取样目录，之后打开只读取保存的key
+//  缓存文件数量超过上限时删除最久未使用的缓存
+#[derive(Serialize, Deserialize)]
+pub struct SymbolCache {
+    pub version: u32,
+    pub key: String,
+    pub methods: Vec<(i64, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SymbolCacheConfig {
+    //为空时使用主取样根目录下的 .symbols
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_max_entries() -> usize {
+    DEFAULT_MAX_ENTRIES
+}
+
+impl Default for SymbolCacheConfig {
+    fn default() -> Self {
+        SymbolCacheConfig {
+            dir: String::new(),
+            max_entries: default_max_entries(),
+        }
+    }
+}
+
+lazy_static! {
+    //(缓存目录, 最多缓存文件数)
+    static ref SYMBOL_CACHE_SETTINGS: Mutex<(String, usize)> = Mutex::new((format!("{}/.symbols", ::sample::FLARE_SAMPLES_DIR), DEFAULT_MAX_ENTRIES));
+}
+
+//启动及重新加载配置时更新
+pub fn set_symbol_cache_config(config: &SymbolCacheConfig, primary_samples_root: &str) {
+    let dir = if config.dir.is_empty() { format!("{}/.symbols", primary_samples_root) } else { config.dir.clone() };
+    *SYMBOL_CACHE_SETTINGS.lock().unwrap() = (dir, config.max_entries);
+}
+
+pub fn get_symbol_cache_dir() -> String {
+    SYMBOL_CACHE_SETTINGS.lock().unwrap().0.clone()
+}
+
+//优先读取保存的key，方法表大小改变或者没有保存时重新计算，save_key为false时不写入取样目录
+pub fn get_symbol_cache_key<P: AsRef<Path>>(sample_data_dir: P, save_key: bool) -> io::Result<String> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    let data_path = sample_data_dir.join("method_info.fdata");
+    let data_len = std::fs::metadata(&data_path)?.len();
+    let key_path = sample_data_dir.join(SYMBOL_CACHE_KEY_FILE);
+    if let Ok(content) = std::fs::read_to_string(&key_path) {
+        let mut parts = content.split_whitespace();
+        if let (Some(len), Some(key)) = (parts.next(), parts.next()) {
+            if len.parse::<u64>().ok() == Some(data_len) {
+                return Ok(key.to_string());
+            }
+        }
+    }
+
+    let file = File::open(&data_path)?;
+    let mut reader = BufReader::with_capacity(1024*100, file);
+    let mut buf = [0u8; 8192];
+    let mut hash = FNV1A_OFFSET_BASIS;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hash = fnv1a_hash_update(hash, &buf[0..n]);
+    }
+    let key = format!("{:016x}", hash);
+    if save_key {
+        if let Err(e) = std::fs::write(&key_path, format!("{} {}", data_len, key)) {
+            println!("save symbol cache key failed: {:?}, err: {}", key_path, e);
+        }
+    }
+    Ok(key)
+}
+
+pub fn load_symbol_cache(key: &str) -> Option<Vec<MethodInfo>> {
+    let path = format!("{}/{}.json", get_symbol_cache_dir(), key);
+    let json = std::fs::read_to_string(&path).ok()?;
+    let cache: SymbolCache = serde_json::from_str(&json).ok()?;
+    if cache.version != SYMBOL_CACHE_VERSION || cache.key != key {
+        return None;
+    }
+    //更新修改时间，淘汰时保留最近使用的缓存
+    if let Ok(file) = OpenOptions::new().append(true).open(&path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Some(cache.methods.into_iter().map(|(method_id, full_name)| MethodInfo {
+        method_id,
+        full_name,
+        hits_count: 0
+    }).collect())
+}
+
+pub fn save_symbol_cache(key: &str, methods: &[MethodInfo]) -> io::Result<()> {
+    let (cache_dir, max_entries) = SYMBOL_CACHE_SETTINGS.lock().unwrap().clone();
+    std::fs::create_dir_all(&cache_dir)?;
+    let cache = SymbolCache {
+        version: SYMBOL_CACHE_VERSION,
+        key: key.to_string(),
+        methods: methods.iter().map(|x| (x.method_id, x.full_name.clone())).collect(),
+    };
+    let path = format!("{}/{}.json", cache_dir, key);
+    let json = serde_json::to_string(&cache)?;
+    std::fs::write(path, json.as_bytes())?;
+    evict_symbol_caches(&cache_dir, max_entries)?;
+    Ok(())
+}
+
+//删除最久未使用的缓存文件，保留max_entries个，返回删除的数量
+pub fn evict_symbol_caches(cache_dir: &str, max_entries: usize) -> io::Result<usize> {
+    let mut entries = vec![];
+    for entry in std::fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if path.extension().map(|x| x == "json").unwrap_or(false) {
+            let modified = std::fs::metadata(&path).and_then(|x| x.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((modified, path));
+        }
+    }
+    if entries.len() <= max_entries {
+        return Ok(0);
+    }
+    entries.sort();
+    let count = entries.len() - max_entries;
+    for (_, path) in entries.iter().take(count) {
+        if let Err(e) = std::fs::remove_file(path) {
+            println!("remove symbol cache failed: {:?}, err: {}", path, e);
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,170 @@
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::panic::{self, AssertUnwindSafe};
+use format_hints::{get_response_format, set_response_format};
+use rate_limit::get_heavy_query_counter;
+use self_profile::{get_span_stack, enter_spans};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// 任务优先级，交互查询优先于导出等后台任务
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TaskPriority {
+    INTERACTIVE = 0,
+    BACKGROUND = 1,
+}
+
+const LANE_COUNT: usize = 2;
+
+struct PooledTask {
+    session_id: String,
+    priority: TaskPriority,
+    task: Box<FnOnce() + Send>,
+}
+
+struct PoolState {
+    lanes: Vec<VecDeque<PooledTask>>,
+    //session_id -> running tasks
+    running_sessions: HashMap<String, usize>,
+    running_background: usize,
+}
+
+//分析任务线程池，按优先级执行任务，并限制每个会话同时执行的任务数量
+pub struct TaskPool {
+    state: Arc<(Mutex<PoolState>, Condvar)>,
+    workers: usize,
+}
+
+impl TaskPool {
+
+    pub fn new(workers: usize, max_tasks_per_session: usize) -> TaskPool {
+        let workers = std::cmp::max(workers, 1);
+        let state = Arc::new((Mutex::new(PoolState {
+            lanes: (0..LANE_COUNT).map(|_| VecDeque::new()).collect(),
+            running_sessions: HashMap::new(),
+            running_background: 0,
+        }), Condvar::new()));
+        //保留一个线程给交互查询，避免后台任务占满线程池
+        let max_background = std::cmp::max(workers - 1, 1);
+        for i in 0..workers {
+            let state = state.clone();
+            thread::Builder::new()
+                .name(format!("flare-analysis-{}", i))
+                .spawn(move || worker_loop(state, max_tasks_per_session, max_background))
+                .unwrap();
+        }
+        TaskPool {
+            state,
+            workers,
+        }
+    }
+
+    pub fn submit<F>(&self, session_id: &str, priority: TaskPriority, task: F)
+        where F: FnOnce() + Send + 'static {
+        let &(ref lock, ref cvar) = &*self.state;
+        //后台任务发送的响应使用提交任务的请求的格式化选项
+        let format = get_response_format();
+        //计入提交任务的连接的重查询数量，任务结束后减少
+        let counter = get_heavy_query_counter();
+        //区段计时时任务继续提交任务的请求的区段
+        let spans = get_span_stack();
+        if let Some(counter) = &counter {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+        let mut state = lock.lock().unwrap();
+        state.lanes[priority as usize].push_back(PooledTask {
+            session_id: session_id.to_string(),
+            priority,
+            task: Box::new(move || {
+                set_response_format(format);
+                let _guard = CounterGuard(counter);
+                let _span = enter_spans(&spans);
+                task();
+                set_response_format(None);
+            }),
+        });
+        cvar.notify_all();
+    }
+
+    //返回每个优先级排队中的任务数量
+    pub fn get_queued_tasks(&self) -> Vec<usize> {
+        let &(ref lock, _) = &*self.state;
+        let state = lock.lock().unwrap();
+        state.lanes.iter().map(|x| x.len()).collect()
+    }
+
+    pub fn get_workers(&self) -> usize {
+        self.workers
+    }
+}
+
+//任务panic时也会减少计数
+struct CounterGuard(Option<Arc<AtomicUsize>>);
+
+impl Drop for CounterGuard {
+    fn drop(&mut self) {
+        if let Some(counter) = &self.0 {
+            counter.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+fn worker_loop(state: Arc<(Mutex<PoolState>, Condvar)>, max_tasks_per_session: usize, max_background: usize) {
+    let &(ref lock, ref cvar) = &*state;
+    loop {
+        let pooled_task = {
+            let mut state = lock.lock().unwrap();
+            loop {
+                if let Some(task) = take_next_task(&mut state, max_tasks_per_session, max_background) {
+                    break task;
+                }
+                state = cvar.wait(state).unwrap();
+            }
+        };
+
+        let session_id = pooled_task.session_id;
+        let priority = pooled_task.priority;
+        //任务panic时不影响工作线程
+        if let Err(_) = panic::catch_unwind(AssertUnwindSafe(pooled_task.task)) {
+            println!("analysis task panicked, session: {}", session_id);
+        }
+
+        let mut state = lock.lock().unwrap();
+        let remove = match state.running_sessions.get_mut(&session_id) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false
+        };
+        if remove {
+            state.running_sessions.remove(&session_id);
+        }
+        if priority == TaskPriority::BACKGROUND {
+            state.running_background -= 1;
+        }
+        cvar.notify_all();
+    }
+}
+
+fn take_next_task(state: &mut PoolState, max_tasks_per_session: usize, max_background: usize) -> Option<PooledTask> {
+    for lane in 0..LANE_COUNT {
+        if lane == TaskPriority::BACKGROUND as usize && state.running_background >= max_background {
+            continue;
+        }
+        let pos = {
+            let running_sessions = &state.running_sessions;
+            state.lanes[lane].iter().position(|x| *running_sessions.get(&x.session_id).unwrap_or(&0) < max_tasks_per_session)
+        };
+        if let Some(pos) = pos {
+            let task = state.lanes[lane].remove(pos).unwrap();
+            *state.running_sessions.entry(task.session_id.clone()).or_insert(0) += 1;
+            if task.priority == TaskPriority::BACKGROUND {
+                state.running_background += 1;
+            }
+            return Some(task);
+        }
+    }
+    None
+}
@@ -0,0 +1,423 @@
+
+//模拟agent：按脚本通过真实的resp协议发送取样数据，用于不依赖JVM的端到端测试
+
+use std::io;
+use std::io::{Write, BufReader, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use resp::{Value, Decoder};
+use sample::SampleCollector;
+use utils::*;
+use flare_proto::agent::*;
+use clock_sync::CLOCK_SYNC_REQUEST;
+use flare_proto::handshake::ALL_CAPABILITIES;
+use flare_proto::{AGENT_PROTO_VERSION, MIN_AGENT_PROTO_VERSION};
+use chrono::Local;
+
+type JavaLong = i64;
+type JavaMethod = i64;
+
+//脚本中的线程，每次取样轮流使用stacks中的调用栈
+#[derive(Clone)]
+pub struct ScriptedThread {
+    pub id: JavaLong,
+    pub name: String,
+    pub state: String,
+    //栈顶在前
+    pub stacks: Vec<Vec<JavaMethod>>,
+    //每次取样增加的cpu时间，nanos
+    pub cpu_time_delta: i64,
+    //线程存活的取样范围[first_sample, end_sample)，结束后线程id可以被新的线程重用
+    pub first_sample: usize,
+    pub end_sample: usize,
+}
+
+//脚本中的事件，在第sample_index次取样之前发送
+#[derive(Clone)]
+pub enum ScriptedEvent {
+    Marker { sample_index: usize, label: String, color: String },
+    IntervalBegin { sample_index: usize, name: String, thread_id: JavaLong },
+    IntervalEnd { sample_index: usize, thread_id: JavaLong },
+    //检测到的死锁环中的一个线程
+    DeadlockThread { sample_index: usize, cycle: i64, thread_id: JavaLong, name: String, lock: String, owner_id: JavaLong, stacktrace: Vec<JavaMethod> },
+    ThreadDump { sample_index: usize, content: String },
+    //(类名, 实例数量, 字节数)
+    //与agent相同按字节数排序，limit 大于0时截断，总数按截断前计算
+    HeapHistogram { sample_index: usize, classes: Vec<(String, i64, i64)>, limit: usize },
+    //线程距上次读取分配的字节数
+    Allocation { sample_index: usize, thread_id: JavaLong, name: String, bytes: i64, stacktrace: Vec<JavaMethod> },
+    //等待执行finalize()的对象数量
+    Finalizer { sample_index: usize, pending: i64 },
+    Deoptimization { sample_index: usize, method: JavaMethod, count: i64 },
+    ClassLoader { sample_index: usize, id: i64, name: String, classes: i64, stacktrace: Vec<JavaMethod> },
+    //GC暂停，duration 单位为微秒
+    Gc { sample_index: usize, duration: i64 },
+    //agent的诊断信息，如取样超时
+    Diagnostic { sample_index: usize, level: String, kind: String, message: String, count: i64 },
+    //clock-sync请求的响应，skew 为本地时间减去agent时间(ms)
+    ClockSync { sample_index: usize, skew: i64 },
+    //agent主机的墙上时间跳变，之后的事件时间加上delta(ms)，模拟不使用单调时钟的旧版本agent
+    ClockJump { sample_index: usize, delta: i64 },
+}
+
+#[derive(Clone)]
+pub struct AgentScript {
+    pub start_time: i64,
+    pub sample_interval: i64,
+    pub samples: usize,
+    pub methods: Vec<(JavaMethod, String)>,
+    pub threads: Vec<ScriptedThread>,
+    pub events: Vec<ScriptedEvent>,
+    //按取样间隔实时发送，否则尽快发送
+    pub realtime: bool,
+    //订阅后首先发送的版本信息，None模拟不发送hello的旧版本agent
+    pub hello: Option<HelloEvent>,
+}
+
+impl AgentScript {
+
+    pub fn new(start_time: i64, sample_interval: i64, samples: usize) -> AgentScript {
+        AgentScript {
+            start_time,
+            sample_interval,
+            samples,
+            methods: vec![],
+            threads: vec![],
+            events: vec![],
+            realtime: false,
+            hello: Some(new_agent_hello()),
+        }
+    }
+
+    pub fn add_method(&mut self, method_id: JavaMethod, name: &str) -> &mut AgentScript {
+        self.methods.push((method_id, name.to_string()));
+        self
+    }
+
+    pub fn add_thread(&mut self, thread_id: JavaLong, name: &str, stacks: Vec<Vec<JavaMethod>>, cpu_time_delta: i64) -> &mut AgentScript {
+        self.add_thread_range(thread_id, name, stacks, cpu_time_delta, 0, usize::max_value())
+    }
+
+    //只在第[first_sample, end_sample)次取样中出现的线程
+    pub fn add_thread_range(&mut self, thread_id: JavaLong, name: &str, stacks: Vec<Vec<JavaMethod>>, cpu_time_delta: i64, first_sample: usize, end_sample: usize) -> &mut AgentScript {
+        self.threads.push(ScriptedThread {
+            id: thread_id,
+            name: name.to_string(),
+            state: "RUNNABLE".to_string(),
+            stacks,
+            cpu_time_delta,
+            first_sample,
+            end_sample,
+        });
+        self
+    }
+
+    pub fn add_event(&mut self, event: ScriptedEvent) -> &mut AgentScript {
+        self.events.push(event);
+        self
+    }
+
+    pub fn get_end_time(&self) -> i64 {
+        self.start_time + self.sample_interval * std::cmp::max(self.samples as i64 - 1, 0)
+    }
+
+    //按发送顺序生成所有消息
+    pub fn encode_messages(&self) -> Vec<Value> {
+        let mut messages = vec![];
+        if let Some(hello) = &self.hello {
+            messages.push(AgentEvent::Hello(hello.clone()).to_resp());
+        }
+        messages.push(encode_sample_info(self.start_time, self.sample_interval, self.start_time));
+        for (method_id, name) in &self.methods {
+            messages.push(encode_method(*method_id, name));
+        }
+        let mut cpu_times = vec![0i64; self.threads.len()];
+        let mut clock_shift = 0;
+        for i in 0..self.samples {
+            for event in &self.events {
+                if let ScriptedEvent::ClockJump { sample_index, delta } = event {
+                    if *sample_index == i {
+                        clock_shift += *delta;
+                    }
+                }
+            }
+            let sample_time = self.start_time + self.sample_interval * i as i64 + clock_shift;
+            for event in &self.events {
+                if let Some(message) = encode_event(event, i, sample_time) {
+                    messages.push(message);
+                }
+            }
+            for (thread, cpu_time) in self.threads.iter().zip(cpu_times.iter_mut()) {
+                if thread.stacks.is_empty() || i < thread.first_sample || i >= thread.end_sample {
+                    continue;
+                }
+                *cpu_time += thread.cpu_time_delta;
+                let stack = &thread.stacks[i % thread.stacks.len()];
+                //agent第一次取样到线程的时间
+                let thread_start_time = self.start_time + self.sample_interval * thread.first_sample as i64;
+                messages.push(encode_thread(thread, thread_start_time, sample_time, *cpu_time, stack));
+            }
+        }
+        messages
+    }
+}
+
+//当前版本agent的hello
+pub fn new_agent_hello() -> HelloEvent {
+    HelloEvent {
+        proto_version: AGENT_PROTO_VERSION as i64,
+        min_proto_version: MIN_AGENT_PROTO_VERSION as i64,
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: ALL_CAPABILITIES,
+    }
+}
+
+fn encode_sample_info(start_time: i64, sample_interval: i64, last_sample_time: i64) -> Value {
+    AgentEvent::SampleInfo(SampleInfoEvent { start_time, sample_interval, last_sample_time }).to_resp()
+}
+
+//与agent相同，方法名按原始字节发送(bulk string)
+fn encode_method(method_id: JavaMethod, name: &str) -> Value {
+    encode_raw_method(method_id, name.as_bytes())
+}
+
+fn encode_thread(thread: &ScriptedThread, start_time: i64, sample_time: i64, cpu_time: i64, stack: &[JavaMethod]) -> Value {
+    AgentEvent::Thread(ThreadEvent {
+        time: sample_time,
+        id: thread.id,
+        name: thread.name.clone(),
+        cpu_time,
+        cpu_time_delta: thread.cpu_time_delta,
+        state: thread.state.clone(),
+        stacktrace: stack.to_vec(),
+        start_time,
+    }).to_resp()
+}
+
+fn encode_event(event: &ScriptedEvent, sample_index: usize, time: i64) -> Option<Value> {
+    match event {
+        ScriptedEvent::Marker { sample_index: index, label, color } if *index == sample_index => {
+            Some(AgentEvent::Marker(MarkerEvent { time, label: label.clone(), color: color.clone() }).to_resp())
+        }
+        ScriptedEvent::IntervalBegin { sample_index: index, name, thread_id } if *index == sample_index => {
+            Some(AgentEvent::IntervalBegin(IntervalBeginEvent { time, name: name.clone(), thread_id: *thread_id }).to_resp())
+        }
+        ScriptedEvent::IntervalEnd { sample_index: index, thread_id } if *index == sample_index => {
+            Some(AgentEvent::IntervalEnd(IntervalEndEvent { time, thread_id: *thread_id }).to_resp())
+        }
+        ScriptedEvent::DeadlockThread { sample_index: index, cycle, thread_id, name, lock, owner_id, stacktrace } if *index == sample_index => {
+            Some(AgentEvent::DeadlockThread(DeadlockThreadEvent {
+                time,
+                cycle: *cycle,
+                id: *thread_id,
+                name: name.clone(),
+                state: "BLOCKED".to_string(),
+                lock: lock.clone(),
+                owner_id: *owner_id,
+                stacktrace: stacktrace.clone(),
+            }).to_resp())
+        }
+        ScriptedEvent::ThreadDump { sample_index: index, content } if *index == sample_index => {
+            let threads = content.lines().filter(|x| x.starts_with('"')).count() as i64;
+            Some(AgentEvent::ThreadDump(ThreadDumpEvent { time, threads, content: content.clone() }).to_resp())
+        }
+        ScriptedEvent::HeapHistogram { sample_index: index, classes, limit } if *index == sample_index => {
+            let mut classes = classes.clone();
+            classes.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+            let total_classes = classes.len() as i64;
+            let total_count = classes.iter().map(|x| x.1).sum();
+            let total_bytes = classes.iter().map(|x| x.2).sum();
+            if *limit > 0 {
+                classes.truncate(*limit);
+            }
+            Some(AgentEvent::HeapHistogram(HeapHistogramEvent {
+                time,
+                force_gc: false,
+                classes: classes.iter().map(|x| x.0.clone()).collect(),
+                counts: classes.iter().map(|x| x.1).collect(),
+                bytes: classes.iter().map(|x| x.2).collect(),
+                total_classes,
+                total_count,
+                total_bytes,
+            }).to_resp())
+        }
+        ScriptedEvent::Allocation { sample_index: index, thread_id, name, bytes, stacktrace } if *index == sample_index => {
+            Some(AgentEvent::Allocation(AllocationEvent {
+                time,
+                id: *thread_id,
+                name: name.clone(),
+                bytes: *bytes,
+                stacktrace: stacktrace.clone(),
+            }).to_resp())
+        }
+        ScriptedEvent::Finalizer { sample_index: index, pending } if *index == sample_index => {
+            Some(AgentEvent::Finalizer(FinalizerEvent { time, pending: *pending }).to_resp())
+        }
+        ScriptedEvent::Deoptimization { sample_index: index, method, count } if *index == sample_index => {
+            Some(AgentEvent::Deoptimization(DeoptimizationEvent { time, method: *method, count: *count }).to_resp())
+        }
+        ScriptedEvent::ClassLoader { sample_index: index, id, name, classes, stacktrace } if *index == sample_index => {
+            Some(AgentEvent::ClassLoader(ClassLoaderEvent { time, id: *id, name: name.clone(), classes: *classes, stacktrace: stacktrace.clone() }).to_resp())
+        }
+        ScriptedEvent::Gc { sample_index: index, duration } if *index == sample_index => {
+            Some(AgentEvent::Gc(GcEvent { time, duration: *duration }).to_resp())
+        }
+        ScriptedEvent::ClockSync { sample_index: index, skew } if *index == sample_index => {
+            //模拟2ms前发出的请求，agent处理耗时1ms
+            let client_time = Local::now().timestamp_millis() - 2;
+            let receive_time = client_time + 1 - *skew;
+            Some(AgentEvent::ClockSync(ClockSyncEvent { client_time, receive_time, send_time: receive_time }).to_resp())
+        }
+        ScriptedEvent::Diagnostic { sample_index: index, level, kind, message, count } if *index == sample_index => {
+            Some(AgentEvent::Diagnostic(DiagnosticEvent { time, level: level.clone(), kind: kind.clone(), message: message.clone(), count: *count }).to_resp())
+        }
+        _ => None
+    }
+}
+
+//模拟agent服务，接受一个客户端的subscribe-events请求后按脚本发送数据，发送完毕后关闭连接
+pub struct FakeAgentServer {
+    addr: String,
+    handle: Option<JoinHandle<io::Result<usize>>>,
+}
+
+impl FakeAgentServer {
+
+    pub fn start(script: AgentScript) -> io::Result<FakeAgentServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let handle = thread::Builder::new()
+            .name("flare-fake-agent".to_string())
+            .spawn(move || {
+                let (stream, _) = listener.accept()?;
+                serve_client(stream, &script)
+            })?;
+        println!("fake agent is listening on: {}", addr);
+        Ok(FakeAgentServer {
+            addr,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn get_addr(&self) -> &str {
+        &self.addr
+    }
+
+    //等待发送完毕，返回发送的消息数量
+    pub fn wait(&mut self) -> io::Result<usize> {
+        match self.handle.take() {
+            Some(handle) => match handle.join() {
+                Ok(result) => result,
+                Err(_) => Err(new_error(ErrorKind::Other, "fake agent thread panicked"))
+            },
+            None => Err(new_error(ErrorKind::Other, "fake agent is already finished"))
+        }
+    }
+}
+
+fn serve_client(mut stream: TcpStream, script: &AgentScript) -> io::Result<usize> {
+    let mut decoder = Decoder::new(BufReader::new(stream.try_clone()?));
+    let request = decoder.decode()?;
+    let cmd = match &request {
+        Value::Array(vec) => match vec.get(0) {
+            Some(Value::String(cmd)) => cmd.clone(),
+            _ => String::new()
+        },
+        _ => String::new()
+    };
+    if cmd != "subscribe-events" {
+        return Err(new_invalid_input_error(&format!("unexpected agent request: {}", cmd)));
+    }
+    //与agent一样继续读取控制请求(如 clock-sync)，读取到连接关闭，避免关闭时有未读取的数据而重置连接
+    //  thread-dump 请求返回固定内容的线程dump，带回请求中的会话标签
+    //  脚本中没有 clock-sync 响应时与agent一样按本机时间响应，偏差在测量误差内，不校正事件时间
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let response_writer = writer.clone();
+    let response_time = script.start_time;
+    let answer_clock_sync = !script.events.iter().any(|x| match x {
+        ScriptedEvent::ClockSync { .. } => true,
+        _ => false
+    });
+    //读取线程结束时关闭通道
+    let (reader_done, reader_waiter) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        let _reader_done = reader_done;
+        while let Ok(request) = decoder.decode() {
+            if let Some(response) = encode_control_response(&request, response_time, answer_clock_sync) {
+                response_writer.lock().unwrap().write_all(response.encode().as_slice()).ok();
+            }
+        }
+    });
+
+    let mut sent = 0;
+    let mut last_time = script.start_time;
+    for message in script.encode_messages() {
+        if script.realtime {
+            if let Some(time) = get_message_time(&message) {
+                if time > last_time {
+                    thread::sleep(Duration::from_millis((time - last_time) as u64));
+                    last_time = time;
+                }
+            }
+        }
+        writer.lock().unwrap().write_all(message.encode().as_slice())?;
+        sent += 1;
+    }
+    stream.flush()?;
+    //只关闭写入端，等待对方读取完毕并关闭连接，同时关闭读取端时对方再发送的请求会重置连接，未读取的事件被丢弃
+    stream.shutdown(std::net::Shutdown::Write)?;
+    reader_waiter.recv_timeout(Duration::from_secs(5)).ok();
+    Ok(sent)
+}
+
+fn encode_control_response(request: &Value, time: i64, answer_clock_sync: bool) -> Option<Value> {
+    match request {
+        Value::Array(vec) if vec.get(0) == Some(&Value::String("thread-dump".to_string())) => {
+            let response = AgentEvent::ThreadDump(ThreadDumpEvent { time, threads: 0, content: "Full thread dump (fake agent):\n".to_string() }).to_resp();
+            Some(with_event_tag(response, &get_event_tag(request).unwrap_or_default()))
+        }
+        Value::Array(vec) if answer_clock_sync && vec.get(0) == Some(&Value::String(CLOCK_SYNC_REQUEST.to_string())) => {
+            let client_time = get_resp_property_as_int(vec, "client_time", 1, 0);
+            let agent_time = Local::now().timestamp_millis();
+            let response = AgentEvent::ClockSync(ClockSyncEvent { client_time, receive_time: agent_time, send_time: agent_time }).to_resp();
+            Some(with_event_tag(response, &get_event_tag(request).unwrap_or_default()))
+        }
+        _ => None
+    }
+}
+
+fn get_message_time(message: &Value) -> Option<i64> {
+    if let Value::Array(vec) = message {
+        if let Some(Value::Integer(time)) = get_resp_property(vec, "time", 1) {
+            return Some(*time);
+        }
+    }
+    None
+}
+
+//启动模拟agent并录制脚本的全部数据，连接关闭后返回取样会话
+pub fn record_script(script: AgentScript, samples_root: &str, timeout_ms: u64) -> io::Result<Arc<Mutex<SampleCollector>>> {
+    record_script_with(script, samples_root, timeout_ms, |_| Ok(()))
+}
+
+//开始接收取样之前先配置collector，比如写入过滤
+pub fn record_script_with<F>(script: AgentScript, samples_root: &str, timeout_ms: u64, setup: F) -> io::Result<Arc<Mutex<SampleCollector>>>
+    where F: FnOnce(&mut SampleCollector) -> io::Result<()> {
+    let mut agent = FakeAgentServer::start(script)?;
+    let collector = SampleCollector::new(agent.get_addr(), samples_root)?;
+    setup(&mut collector.lock().unwrap())?;
+    collector.lock().unwrap().subscribe_events()?;
+    agent.wait()?;
+
+    let start = Instant::now();
+    while !collector.lock().unwrap().is_disconnected() {
+        if start.elapsed() > Duration::from_millis(timeout_ms) {
+            return Err(new_error(ErrorKind::TimedOut, "wait for recording finished timeout"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(collector)
+}
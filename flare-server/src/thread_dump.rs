@@ -0,0 +1,90 @@
+
+//按需获取的完整线程dump(jstack格式)，每次保存为会话目录下的一个文本文件
+//  thread_dumps/<time>.txt
+
+use std::io;
+use std::path::{Path, PathBuf};
+use flare_proto::agent::ThreadDumpEvent;
+
+pub const THREAD_DUMP_DIR: &str = "thread_dumps";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ThreadDumpInfo {
+    pub time: i64,
+    pub threads: i64,
+    pub size: u64,
+}
+
+fn get_thread_dump_path(sample_data_dir: &Path, time: i64) -> PathBuf {
+    sample_data_dir.join(THREAD_DUMP_DIR).join(format!("{}.txt", time))
+}
+
+//线程数量: 以线程名称开头的行
+fn count_threads(content: &str) -> i64 {
+    content.lines().filter(|x| x.starts_with('"')).count() as i64
+}
+
+//线程标题行中的线程id: "name" #<tid> [daemon] prio=...
+fn parse_thread_id(line: &str) -> Option<i64> {
+    let name_end = line.rfind("\" #")?;
+    line[name_end + 3..].split(' ').next()?.parse().ok()
+}
+
+//dump中某个线程的部分(标题行到下一个线程之前)，没有该线程时返回None
+pub fn extract_thread(content: &str, thread_id: i64) -> Option<String> {
+    let mut result: Option<String> = None;
+    for line in content.lines() {
+        if line.starts_with('"') {
+            if result.is_some() {
+                break;
+            }
+            if parse_thread_id(line) == Some(thread_id) {
+                result = Some(String::new());
+            }
+        }
+        if let Some(section) = result.as_mut() {
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
+    result
+}
+
+pub fn save_thread_dump<P: AsRef<Path>>(sample_data_dir: P, event: &ThreadDumpEvent) -> io::Result<ThreadDumpInfo> {
+    let sample_data_dir = sample_data_dir.as_ref();
+    std::fs::create_dir_all(sample_data_dir.join(THREAD_DUMP_DIR))?;
+    std::fs::write(get_thread_dump_path(sample_data_dir, event.time), event.content.as_bytes())?;
+    Ok(ThreadDumpInfo {
+        time: event.time,
+        threads: event.threads,
+        size: event.content.len() as u64,
+    })
+}
+
+pub fn load_thread_dump<P: AsRef<Path>>(sample_data_dir: P, time: i64) -> io::Result<String> {
+    std::fs::read_to_string(get_thread_dump_path(sample_data_dir.as_ref(), time))
+}
+
+//按时间排序
+pub fn list_thread_dumps<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<Vec<ThreadDumpInfo>> {
+    let dir = sample_data_dir.as_ref().join(THREAD_DUMP_DIR);
+    if std::fs::metadata(&dir).is_err() {
+        return Ok(vec![]);
+    }
+    let mut dumps = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let time = match path.file_stem().and_then(|x| x.to_str()).and_then(|x| x.parse::<i64>().ok()) {
+            Some(time) => time,
+            None => continue
+        };
+        let content = std::fs::read_to_string(&path)?;
+        dumps.push(ThreadDumpInfo {
+            time,
+            threads: count_threads(&content),
+            size: content.len() as u64,
+        });
+    }
+    dumps.sort_by_key(|x| x.time);
+    Ok(dumps)
+}
@@ -0,0 +1,135 @@
+//线程句柄：JVM的线程id在线程结束后可能被新的线程重用，长时间的会话中按id汇总会把不同线程的数据混在一起
+//  写入时按(线程id, 开始时间)为每个线程分配稳定的句柄，会话中的线程数据、调用栈文件及所有按线程的查询都使用句柄
+//  第一次出现的线程句柄等于线程id，旧的取样目录和客户端不受影响；重用的线程id分配新的句柄 id + generation * THREAD_HANDLE_GENERATION
+//  agent报告线程的开始时间(agent第一次看到该线程的时间)，开始时间改变时是新的线程
+//  旧版本agent没有开始时间(0)，按启发式判断: 线程累计CPU时间倒退，或者长时间没有取样之后线程名称改变时，判断为新的线程
+//映射表保存在取样目录下的 thread_handles.json
+
+use std::collections::HashMap;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+
+type JavaLong = i64;
+
+pub const THREAD_HANDLES_FILE: &str = "thread_handles.json";
+pub const KIND_THREAD_ID_REUSED: &str = "thread_id_reused";
+//重用的线程id每一代句柄的增量，JVM线程id远小于此值
+pub const THREAD_HANDLE_GENERATION: i64 = 1 << 32;
+//没有取样超过此时间(ms)且名称改变时判断为新的线程
+pub const THREAD_REUSE_MIN_GAP_MS: i64 = 1000;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ThreadHandle {
+    pub handle: JavaLong,
+    pub thread_id: JavaLong,
+    pub name: String,
+    pub start_time: i64,
+    //最后一次取样的时间
+    pub end_time: i64,
+    //agent报告的线程开始时间，0表示未知
+    #[serde(default)]
+    pub agent_start_time: i64,
+    #[serde(skip)]
+    last_cpu_time: i64,
+}
+
+#[derive(Default)]
+pub struct ThreadHandleTable {
+    handles: Vec<ThreadHandle>,
+    //线程id -> 当前句柄在handles中的位置
+    current: HashMap<JavaLong, usize>,
+}
+
+impl ThreadHandleTable {
+    pub fn new() -> ThreadHandleTable {
+        Default::default()
+    }
+
+    //返回取样所属线程的句柄，以及是否分配了重用线程id的新句柄
+    //agent_start_time: agent报告的线程开始时间，0表示未知，使用启发式判断
+    pub fn resolve(&mut self, thread_id: JavaLong, agent_start_time: i64, name: &str, cpu_time: i64, sample_time: i64) -> (JavaLong, bool) {
+        if let Some(index) = self.current.get(&thread_id).cloned() {
+            let handle = &mut self.handles[index];
+            let same_thread = if agent_start_time > 0 && handle.agent_start_time > 0 {
+                agent_start_time == handle.agent_start_time
+            } else {
+                let cpu_time_reset = cpu_time < handle.last_cpu_time;
+                let renamed = handle.name != name && sample_time - handle.end_time > THREAD_REUSE_MIN_GAP_MS;
+                !cpu_time_reset && !renamed
+            };
+            if same_thread {
+                if handle.agent_start_time == 0 {
+                    handle.agent_start_time = agent_start_time;
+                }
+                handle.end_time = handle.end_time.max(sample_time);
+                handle.last_cpu_time = cpu_time;
+                return (handle.handle, false);
+            }
+        }
+        let generation = self.handles.iter().filter(|x| x.thread_id == thread_id).count() as i64;
+        let handle = thread_id + generation * THREAD_HANDLE_GENERATION;
+        self.current.insert(thread_id, self.handles.len());
+        self.handles.push(ThreadHandle {
+            handle,
+            thread_id,
+            name: name.to_string(),
+            start_time: sample_time,
+            end_time: sample_time,
+            agent_start_time,
+            last_cpu_time: cpu_time,
+        });
+        (handle, generation > 0)
+    }
+
+    //线程id当前的句柄，没有取样的线程返回线程id
+    pub fn get_handle(&self, thread_id: JavaLong) -> JavaLong {
+        self.current.get(&thread_id).map_or(thread_id, |x| self.handles[*x].handle)
+    }
+
+    //某个时间使用该线程id的线程句柄，用于线程转储等只有线程id的数据，没有取样的线程返回线程id
+    pub fn find_handle_at(&self, thread_id: JavaLong, time: i64) -> JavaLong {
+        //取开始时间不晚于time的最后一代，time早于所有取样时返回第一代
+        let mut result = None;
+        for handle in self.handles.iter().filter(|x| x.thread_id == thread_id) {
+            if result.is_none() || handle.start_time <= time {
+                result = Some(handle.handle);
+            }
+        }
+        result.unwrap_or(thread_id)
+    }
+
+    pub fn get_thread_handle(&self, handle: JavaLong) -> Option<&ThreadHandle> {
+        self.handles.iter().find(|x| x.handle == handle)
+    }
+
+    pub fn get_handles(&self) -> &[ThreadHandle] {
+        &self.handles
+    }
+
+    //是否有线程id被重用，没有重用时不需要保存映射表
+    pub fn has_reused(&self) -> bool {
+        self.handles.iter().any(|x| x.handle != x.thread_id)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, sample_data_dir: P) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.handles)?;
+        std::fs::write(sample_data_dir.as_ref().join(THREAD_HANDLES_FILE), json.as_bytes())
+    }
+
+    //旧的取样目录没有映射表，句柄就是线程id
+    pub fn load<P: AsRef<Path>>(sample_data_dir: P) -> io::Result<ThreadHandleTable> {
+        let json = match std::fs::read_to_string(sample_data_dir.as_ref().join(THREAD_HANDLES_FILE)) {
+            Ok(json) => json,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(ThreadHandleTable::new()),
+            Err(e) => return Err(e)
+        };
+        let handles: Vec<ThreadHandle> = serde_json::from_str(&json)?;
+        let mut table = ThreadHandleTable::new();
+        for handle in handles {
+            table.current.insert(handle.thread_id, table.handles.len());
+            table.handles.push(handle);
+        }
+        Ok(table)
+    }
+}
@@ -1,63 +1,163 @@
-
-use std::sync::{Mutex, Arc};
-use std::cmp::min;
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use sample::ThreadData;
-
-
-#[derive(Serialize, Deserialize)]
-pub struct TreeNode {
-    pub parent: Option<Box<TreeNode>>,
-    pub children: Vec<Box<TreeNode>>,
-    //method_id
-    pub id: i64,
-    //method full_name
-    pub label: String,
-    pub calls: i64,
-    pub cpu: i64,
-    pub duration: i64,
-    pub start_time: i64,
-    pub depth: i32
-}
-
-impl TreeNode {
-    pub fn new(id: i64, label: &str) -> TreeNode {
-        TreeNode {
-            parent: None,
-            children: vec![],
-            id,
-            label: label.to_string(),
-            calls: 0,
-            cpu: 0,
-            duration: 0,
-            start_time: 0,
-            depth: 0
-        }
-    }
-
-    pub fn append_child<'a>(&'a mut self, childNode: TreeNode) -> &'a mut Box<TreeNode> {
-//        self.cost += childNode.cost;
-        let node = Box::new(childNode);
-        self.children.push(node);
-        self.children.last_mut().unwrap()
-    }
-
-    pub fn last_child(&mut self) -> Option<&mut Box<TreeNode>> {
-        self.children.last_mut()
-    }
-
-    pub fn merge_last_child(&mut self, method_id: i64, self_duration: i64, self_cpu_time: i64, samples: i64) -> bool {
-        let mut last_node = self.last_child();
-        if last_node.is_some() {
-            let last_node = last_node.unwrap();
-            if last_node.id == method_id {
-                last_node.duration += self_duration;
-                last_node.cpu += self_cpu_time;
-                last_node.calls += samples;
-                return true;
-            }
-        }
-        return false;
-    }
-}
+
+use std::sync::{Mutex, Arc};
+use std::cmp::min;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sample::{ThreadData, OTHER_FRAME_ID};
+
+
+#[derive(Serialize, Deserialize)]
+pub struct TreeNode {
+    pub parent: Option<Box<TreeNode>>,
+    pub children: Vec<Box<TreeNode>>,
+    //method_id
+    pub id: i64,
+    //method full_name
+    pub label: String,
+    pub calls: i64,
+    pub cpu: i64,
+    pub duration: i64,
+    pub start_time: i64,
+    pub depth: i32
+}
+
+impl TreeNode {
+    pub fn new(id: i64, label: &str) -> TreeNode {
+        TreeNode {
+            parent: None,
+            children: vec![],
+            id,
+            label: label.to_string(),
+            calls: 0,
+            cpu: 0,
+            duration: 0,
+            start_time: 0,
+            depth: 0
+        }
+    }
+
+    pub fn append_child<'a>(&'a mut self, childNode: TreeNode) -> &'a mut Box<TreeNode> {
+//        self.cost += childNode.cost;
+        let node = Box::new(childNode);
+        self.children.push(node);
+        self.children.last_mut().unwrap()
+    }
+
+    pub fn last_child(&mut self) -> Option<&mut Box<TreeNode>> {
+        self.children.last_mut()
+    }
+
+    pub fn merge_last_child(&mut self, method_id: i64, self_duration: i64, self_cpu_time: i64, samples: i64) -> bool {
+        let mut last_node = self.last_child();
+        if last_node.is_some() {
+            let last_node = last_node.unwrap();
+            if last_node.id == method_id {
+                last_node.duration += self_duration;
+                last_node.cpu += self_cpu_time;
+                last_node.calls += samples;
+                return true;
+            }
+        }
+        return false;
+    }
+}
+
+impl TreeNode {
+
+    //只输出levels层子节点，被截断的节点附带handle，可以通过 expand_node 继续展开
+    pub fn to_json_limited(&self, handle: &str, levels: i32) -> serde_json::Value {
+        let mut children = vec![];
+        if levels > 0 {
+            for (i, child) in self.children.iter().enumerate() {
+                children.push(child.to_json_limited(&format!("{}/{}", handle, i), levels - 1));
+            }
+        }
+        json!({
+            "id": self.id,
+            "label": self.label,
+            "calls": self.calls,
+            "cpu": self.cpu,
+            "duration": self.duration,
+            "start_time": self.start_time,
+            "depth": self.depth,
+            "handle": handle,
+            "child_count": self.children.len(),
+            "has_more": levels <= 0 && !self.children.is_empty(),
+            "children": children
+        })
+    }
+
+    //path: 子节点序号，如 0/2/1
+    pub fn find_node(&self, path: &[usize]) -> Option<&TreeNode> {
+        let mut node = self;
+        for i in path {
+            node = node.children.get(*i)?;
+        }
+        Some(node)
+    }
+}
+
+//裁剪不重要的子树，被裁剪的权重合并到 <other> 节点
+pub struct PruneOptions {
+    pub min_samples: i64,
+    //相对根节点耗时的百分比
+    pub min_percent: f64,
+    //0表示不限制
+    pub max_depth: i32,
+    //顺序树(火焰图)中只合并相邻被裁剪的节点，保持时间位置
+    pub sequenced: bool,
+}
+
+impl PruneOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.min_samples > 0 || self.min_percent > 0.0 || self.max_depth > 0
+    }
+}
+
+pub fn prune_tree(root: &mut TreeNode, options: &PruneOptions) {
+    if !options.is_enabled() {
+        return;
+    }
+    let min_duration = (root.duration as f64 * options.min_percent / 100.0) as i64;
+    prune_children(root, options, min_duration, 1);
+}
+
+fn prune_children(node: &mut TreeNode, options: &PruneOptions, min_duration: i64, depth: i32) {
+    if options.max_depth > 0 && depth > options.max_depth {
+        node.children.clear();
+        return;
+    }
+    let children = std::mem::replace(&mut node.children, vec![]);
+    let mut other: Option<Box<TreeNode>> = None;
+    for mut child in children {
+        if child.calls < options.min_samples || child.duration < min_duration {
+            match other.as_mut() {
+                Some(other) => {
+                    other.calls += child.calls;
+                    other.cpu += child.cpu;
+                    other.duration += child.duration;
+                }
+                None => {
+                    let mut other_node = TreeNode::new(OTHER_FRAME_ID, "<other>");
+                    other_node.calls = child.calls;
+                    other_node.cpu = child.cpu;
+                    other_node.duration = child.duration;
+                    other_node.start_time = child.start_time;
+                    other_node.depth = child.depth;
+                    other = Some(Box::new(other_node));
+                }
+            }
+            continue;
+        }
+        if options.sequenced {
+            if let Some(other) = other.take() {
+                node.children.push(other);
+            }
+        }
+        prune_children(&mut child, options, min_duration, depth + 1);
+        node.children.push(child);
+    }
+    if let Some(other) = other {
+        node.children.push(other);
+    }
+}
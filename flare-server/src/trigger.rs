@@ -0,0 +1,104 @@
+//触发器：定期检查录制中的会话，条件满足时发送 trigger_fired 通知(webhook)
+//配置示例(flare-server.conf):
+//  [[triggers]]
+//  name = "high-cpu"
+//  kind = "cpu"            #cpu: 目标进程cgroup的CPU使用率(%，多核累计，如200表示两个核)
+//  threshold = 200.0
+//  [[triggers]]
+//  name = "deadlock"
+//  kind = "deadlock"       #agent检测到新的死锁
+//  [[triggers]]
+//  name = "agent-error"
+//  kind = "error_event"    #agent上报了新的error级别的会话事件
+//cpu触发器超过阈值时触发一次，降到阈值以下后才会再次触发；其它触发器每次检查有新增时触发
+
+use std::collections::HashSet;
+use std::io;
+use utils::*;
+
+pub const TRIGGER_CPU: &str = "cpu";
+pub const TRIGGER_DEADLOCK: &str = "deadlock";
+pub const TRIGGER_ERROR_EVENT: &str = "error_event";
+const TRIGGER_KINDS: &[&str] = &[TRIGGER_CPU, TRIGGER_DEADLOCK, TRIGGER_ERROR_EVENT];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TriggerConfig {
+    pub name: String,
+    pub kind: String,
+    #[serde(default)]
+    pub threshold: f64,
+}
+
+//检查名称、类型及阈值
+pub fn validate_triggers(triggers: &[TriggerConfig]) -> io::Result<()> {
+    let mut names = HashSet::new();
+    for trigger in triggers {
+        if trigger.name.is_empty() || !names.insert(trigger.name.as_str()) {
+            return Err(new_invalid_input_error(&format!("trigger name is empty or duplicated: '{}'", trigger.name)));
+        }
+        if !TRIGGER_KINDS.contains(&trigger.kind.as_str()) {
+            return Err(new_invalid_input_error(&format!("invalid trigger kind: {}, expect one of {:?}", trigger.kind, TRIGGER_KINDS)));
+        }
+        if trigger.kind == TRIGGER_CPU && trigger.threshold <= 0.0 {
+            return Err(new_invalid_input_error(&format!("cpu trigger threshold must be greater than 0: {}", trigger.name)));
+        }
+    }
+    Ok(())
+}
+
+//一次检查时会话的状态
+pub struct TriggerInput {
+    //最新的cgroup CPU累计使用时间 (time, cpu_usage_us)
+    pub cpu_usage: Option<(i64, i64)>,
+    pub deadlocks: usize,
+    pub error_events: usize,
+}
+
+//每个会话上一次检查的状态
+#[derive(Default)]
+pub struct TriggerState {
+    last_cpu_usage: Option<(i64, i64)>,
+    deadlocks: usize,
+    error_events: usize,
+    //已触发、条件还没有恢复的cpu触发器
+    fired: HashSet<String>,
+}
+
+//返回触发的 (触发器名称, 消息)
+pub fn evaluate_triggers(triggers: &[TriggerConfig], state: &mut TriggerState, input: &TriggerInput) -> Vec<(String, String)> {
+    let mut fired = vec![];
+    let cpu_percent = match (state.last_cpu_usage, input.cpu_usage) {
+        (Some((last_time, last_usage)), Some((time, usage))) if time > last_time && usage >= last_usage => {
+            Some((usage - last_usage) as f64 / ((time - last_time) * 1000) as f64 * 100.0)
+        }
+        _ => None
+    };
+    for trigger in triggers {
+        match trigger.kind.as_str() {
+            TRIGGER_CPU => {
+                let cpu_percent = match cpu_percent {
+                    Some(x) => x,
+                    None => continue
+                };
+                if cpu_percent < trigger.threshold {
+                    state.fired.remove(&trigger.name);
+                } else if state.fired.insert(trigger.name.clone()) {
+                    fired.push((trigger.name.clone(), format!("cpu usage {:.1}% exceeds threshold {:.1}%", cpu_percent, trigger.threshold)));
+                }
+            }
+            TRIGGER_DEADLOCK if input.deadlocks > state.deadlocks => {
+                fired.push((trigger.name.clone(), format!("{} new deadlock(s) detected", input.deadlocks - state.deadlocks)));
+            }
+            TRIGGER_ERROR_EVENT if input.error_events > state.error_events => {
+                fired.push((trigger.name.clone(), format!("{} new error event(s) reported by agent", input.error_events - state.error_events)));
+            }
+            _ => {}
+        }
+    }
+    if input.cpu_usage.is_some() {
+        state.last_cpu_usage = input.cpu_usage;
+    }
+    state.deadlocks = input.deadlocks;
+    state.error_events = input.error_events;
+    fired
+}
@@ -1,155 +1,225 @@
-use std::collections::HashMap;
-use websocket::OwnedMessage;
-use serde::Serialize;
-use FlareResponse;
-use serde_json::{Number,json};
-use std::io::ErrorKind;
-use std::io;
-use chrono::Local;
-
-pub fn nowTime() -> String {
-    let date = Local::now();
-    return date.format("%Y-%m-%d %H:%M:%S.%6f").to_string();
-    //println!("{:?} {}", date, date.format("[%Y-%m-%d %H:%M:%S.%3f]"));
-}
-
-pub fn get_resp_property<'a>(data_vec: &'a Vec<resp::Value>, key: &str, start: i32) -> Option<&'a resp::Value> {
-    for x in (start as usize..data_vec.len()).step_by(2) {
-        if let resp::Value::String(name) = &data_vec[x] {
-            if name == key {
-                return Some(&data_vec[x+1]);
-            }
-        }
-    }
-    None
-}
-
-pub fn get_resp_property_as_int(data_vec: &Vec<resp::Value>, key: &str, start: i32, default_value: i64) -> i64 {
-    for x in (start as usize..data_vec.len()).step_by(2) {
-        if let resp::Value::String(name) = &data_vec[x] {
-            if name == key {
-                if let resp::Value::Integer(x) = &data_vec[x+1] {
-                    return *x
-                }
-            }
-        }
-    }
-    default_value
-}
-
-pub fn get_resp_property_as_str<'a>(data_vec: &'a Vec<resp::Value>, key: &str, start: i32, default_value: &'a str) -> &'a str {
-    for x in (start as usize..data_vec.len()).step_by(2) {
-        if let resp::Value::String(name) = &data_vec[x] {
-            if name == key {
-                if let resp::Value::String(x) = &data_vec[x+1] {
-                    return x
-                }
-            }
-        }
-    }
-    default_value
-}
-
-pub fn parse_resp_properties<'a>(data_vec: &'a Vec<resp::Value>, start: i32) -> HashMap<&'a String, &'a resp::Value> {
-    let mut map = HashMap::new();
-    for x in (start as usize..data_vec.len()).step_by(2) {
-        if let resp::Value::String(name) = &data_vec[x] {
-            map.insert(name, &data_vec[x+1]);
-        }
-    }
-    map
-}
-
-
-pub fn wrap_response<T: ?Sized>(cmd: &str, value: &T) -> OwnedMessage
-    where
-        T: Serialize,
-{
-    let response = FlareResponse {
-        result: "success".to_string(),
-        cmd: cmd.to_string(),
-        data: Box::new(value)
-    };
-    OwnedMessage::Text(serde_json::to_string(&response).unwrap())
-}
-
-pub fn wrap_error_response(cmd: &str, message: &str) -> OwnedMessage {
-    let response = FlareResponse {
-        result: "failure".to_string(),
-        cmd: cmd.to_string(),
-        data: Box::new(json!({ "message": message }))
-    };
-    OwnedMessage::Text(serde_json::to_string(&response).unwrap())
-}
-
-pub fn get_option_as_str_required<'a>(options: &'a serde_json::Map<String, serde_json::Value>, key: &str) -> io::Result<&'a str> {
-    match options.get(key) {
-        Some(val) => {
-            match val.as_str() {
-                Some(s) => Ok(s),
-                None => {
-                    Err(io::Error::new(ErrorKind::InvalidInput, format!("option value is not a string '{}'", key)))
-                },
-            }
-        },
-        None => {
-            Err(io::Error::new(ErrorKind::InvalidInput, format!("missing option '{}'", key)))
-        }
-    }
-}
-
-pub fn get_option_as_str<'a>(options: &'a serde_json::Map<String, serde_json::Value>, key: &str, default_value: &'a str) -> &'a str {
-    match options.get(key) {
-        Some(val) => {
-            match val.as_str() {
-                Some(s) => s.trim(),
-                None => default_value
-            }
-        },
-        None => default_value
-    }
-}
-
-pub fn get_option_as_int(options: &serde_json::Map<String, serde_json::Value>, key: &str, default_value: i64) -> i64 {
-    match options.get(key) {
-        Some(val) => {
-            match val.as_i64() {
-                Some(s) => s,
-                None => default_value
-            }
-        },
-        None => default_value
-    }
-}
-
-pub fn get_option_as_int_array(options: &serde_json::Map<String, serde_json::Value>, key: &str) -> io::Result<Vec<i64>> {
-    let val = options.get(key);
-    if val.is_none() {
-        return Err(new_invalid_input_error(&format!("missing option: {}", key)));
-    }
-    let val = val.unwrap().as_array();
-    if val.is_none() {
-        return Err(new_invalid_input_error(&format!("option '{}' is not int array ", key)));
-    }
-    let vals = val.unwrap();
-    let mut data = vec![];
-    for v in vals {
-        match v.as_i64() {
-            Some(x) => {
-                data.push(x);
-            },
-            None => {
-                return Err(new_invalid_input_error(&format!("option '{}' contains none int value: {} ", key, v)));
-            },
-        }
-    }
-    Ok(data)
-}
-
-pub fn new_error(kind: ErrorKind, msg: &str) -> io::Error {
-    io::Error::new(kind, msg)
-}
-
-pub fn new_invalid_input_error(msg: &str) -> io::Error {
-    io::Error::new(ErrorKind::InvalidInput, msg)
-}
\ No newline at end of file
+use std::collections::HashMap;
+use websocket::OwnedMessage;
+use serde::Serialize;
+use FlareResponse;
+use serde_json::{Number,json};
+use std::io::ErrorKind;
+use std::io;
+use chrono::Local;
+use command_recorder;
+use format_hints;
+
+pub fn nowTime() -> String {
+    let date = Local::now();
+    return date.format("%Y-%m-%d %H:%M:%S.%6f").to_string();
+    //println!("{:?} {}", date, date.format("[%Y-%m-%d %H:%M:%S.%3f]"));
+}
+
+pub fn get_resp_property<'a>(data_vec: &'a Vec<resp::Value>, key: &str, start: i32) -> Option<&'a resp::Value> {
+    for x in (start as usize..data_vec.len()).step_by(2) {
+        if let resp::Value::String(name) = &data_vec[x] {
+            if name == key {
+                return Some(&data_vec[x+1]);
+            }
+        }
+    }
+    None
+}
+
+pub fn get_resp_property_as_int(data_vec: &Vec<resp::Value>, key: &str, start: i32, default_value: i64) -> i64 {
+    for x in (start as usize..data_vec.len()).step_by(2) {
+        if let resp::Value::String(name) = &data_vec[x] {
+            if name == key {
+                if let resp::Value::Integer(x) = &data_vec[x+1] {
+                    return *x
+                }
+            }
+        }
+    }
+    default_value
+}
+
+pub fn get_resp_property_as_str<'a>(data_vec: &'a Vec<resp::Value>, key: &str, start: i32, default_value: &'a str) -> &'a str {
+    for x in (start as usize..data_vec.len()).step_by(2) {
+        if let resp::Value::String(name) = &data_vec[x] {
+            if name == key {
+                if let resp::Value::String(x) = &data_vec[x+1] {
+                    return x
+                }
+            }
+        }
+    }
+    default_value
+}
+
+pub fn parse_resp_properties<'a>(data_vec: &'a Vec<resp::Value>, start: i32) -> HashMap<&'a String, &'a resp::Value> {
+    let mut map = HashMap::new();
+    for x in (start as usize..data_vec.len()).step_by(2) {
+        if let resp::Value::String(name) = &data_vec[x] {
+            map.insert(name, &data_vec[x+1]);
+        }
+    }
+    map
+}
+
+
+pub fn wrap_response<T: ?Sized>(cmd: &str, value: &T) -> OwnedMessage
+    where
+        T: Serialize,
+{
+    //请求开启了格式化提示时，在数值字段旁边增加格式化后的文本
+    let text = match format_hints::get_response_format() {
+        Some(format) => {
+            let mut data = serde_json::to_value(value).unwrap();
+            format_hints::add_format_hints(&mut data, &format);
+            serde_json::to_string(&FlareResponse::success(cmd, &data)).unwrap()
+        }
+        None => serde_json::to_string(&FlareResponse::success(cmd, value)).unwrap()
+    };
+    command_recorder::record_response(&text);
+    OwnedMessage::Text(text)
+}
+
+pub fn wrap_error_response(cmd: &str, message: &str) -> OwnedMessage {
+    let response = FlareResponse::failure(cmd, message);
+    let text = serde_json::to_string(&response).unwrap();
+    command_recorder::record_response(&text);
+    OwnedMessage::Text(text)
+}
+
+pub fn get_option_as_str_required<'a>(options: &'a serde_json::Map<String, serde_json::Value>, key: &str) -> io::Result<&'a str> {
+    match options.get(key) {
+        Some(val) => {
+            match val.as_str() {
+                Some(s) => Ok(s),
+                None => {
+                    Err(io::Error::new(ErrorKind::InvalidInput, format!("option value is not a string '{}'", key)))
+                },
+            }
+        },
+        None => {
+            Err(io::Error::new(ErrorKind::InvalidInput, format!("missing option '{}'", key)))
+        }
+    }
+}
+
+pub fn get_option_as_str<'a>(options: &'a serde_json::Map<String, serde_json::Value>, key: &str, default_value: &'a str) -> &'a str {
+    match options.get(key) {
+        Some(val) => {
+            match val.as_str() {
+                Some(s) => s.trim(),
+                None => default_value
+            }
+        },
+        None => default_value
+    }
+}
+
+pub fn get_option_as_int(options: &serde_json::Map<String, serde_json::Value>, key: &str, default_value: i64) -> i64 {
+    match options.get(key) {
+        Some(val) => {
+            match val.as_i64() {
+                Some(s) => s,
+                None => default_value
+            }
+        },
+        None => default_value
+    }
+}
+
+pub fn get_option_as_f64(options: &serde_json::Map<String, serde_json::Value>, key: &str, default_value: f64) -> f64 {
+    match options.get(key) {
+        Some(val) => val.as_f64().unwrap_or(default_value),
+        None => default_value
+    }
+}
+
+pub fn get_option_as_bool(options: &serde_json::Map<String, serde_json::Value>, key: &str, default_value: bool) -> bool {
+    match options.get(key) {
+        Some(val) => val.as_bool().unwrap_or(default_value),
+        None => default_value
+    }
+}
+
+pub fn get_option_as_int_array(options: &serde_json::Map<String, serde_json::Value>, key: &str) -> io::Result<Vec<i64>> {
+    let val = options.get(key);
+    if val.is_none() {
+        return Err(new_invalid_input_error(&format!("missing option: {}", key)));
+    }
+    let val = val.unwrap().as_array();
+    if val.is_none() {
+        return Err(new_invalid_input_error(&format!("option '{}' is not int array ", key)));
+    }
+    let vals = val.unwrap();
+    let mut data = vec![];
+    for v in vals {
+        match v.as_i64() {
+            Some(x) => {
+                data.push(x);
+            },
+            None => {
+                return Err(new_invalid_input_error(&format!("option '{}' contains none int value: {} ", key, v)));
+            },
+        }
+    }
+    Ok(data)
+}
+
+//选项不存在时返回空数组
+pub fn get_option_as_int_array_or_empty(options: &serde_json::Map<String, serde_json::Value>, key: &str) -> io::Result<Vec<i64>> {
+    if options.get(key).is_none() {
+        return Ok(vec![]);
+    }
+    get_option_as_int_array(options, key)
+}
+
+pub fn get_option_as_str_array(options: &serde_json::Map<String, serde_json::Value>, key: &str) -> io::Result<Vec<String>> {
+    let val = options.get(key);
+    if val.is_none() {
+        return Err(new_invalid_input_error(&format!("missing option: {}", key)));
+    }
+    let val = val.unwrap().as_array();
+    if val.is_none() {
+        return Err(new_invalid_input_error(&format!("option '{}' is not string array ", key)));
+    }
+    let vals = val.unwrap();
+    let mut data = vec![];
+    for v in vals {
+        match v.as_str() {
+            Some(x) => {
+                data.push(x.to_string());
+            },
+            None => {
+                return Err(new_invalid_input_error(&format!("option '{}' contains none string value: {} ", key, v)));
+            },
+        }
+    }
+    Ok(data)
+}
+
+pub const FNV1A_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+//FNV-1a 64, 不同版本之间结果保持稳定
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    fnv1a_hash_update(FNV1A_OFFSET_BASIS, bytes)
+}
+
+pub fn fnv1a_hash_update(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn new_error(kind: ErrorKind, msg: &str) -> io::Error {
+    io::Error::new(kind, msg)
+}
+
+pub fn new_invalid_input_error(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, msg)
+}
+//用作文件名，替换路径分隔符等特殊字符
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' }).collect()
+}
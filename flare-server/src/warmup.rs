@@ -0,0 +1,195 @@
+
+//基准测试的JIT预热检测：按时间窗口统计热点方法集合及JIT编译线程的CPU，
+//热点集合趋于稳定且编译活动减少后进入稳态，返回预热结束的时间点
+//  热点集合相似度: 相邻窗口 top N 自身方法的 Jaccard 系数
+//  编译活动: 名称包含 CompilerThread 的线程的CPU时间(JVM隐藏编译线程时没有此项)
+
+use ::sample::*;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use utils::*;
+
+//窗口数量上限，避免 window_ms 过小时分配过多内存
+pub const MAX_WARMUP_WINDOWS: usize = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct WarmupOptions {
+    pub window_ms: i64,
+    //每个窗口的热点方法数量
+    pub top_methods: usize,
+    //热点集合相似度不低于此值时认为稳定
+    pub min_similarity: f64,
+    //编译CPU不超过峰值的此比例时认为编译活动已减少
+    pub max_compile_ratio: f64,
+    //连续稳定的窗口数量
+    pub stable_windows: usize,
+}
+
+impl Default for WarmupOptions {
+    fn default() -> Self {
+        WarmupOptions {
+            window_ms: 5000,
+            top_methods: 20,
+            min_similarity: 0.7,
+            max_compile_ratio: 0.2,
+            stable_windows: 3,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WarmupWindow {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub samples: usize,
+    //应用线程的CPU时间(ms)
+    pub cpu_time_ms: i64,
+    pub compile_cpu_time_ms: i64,
+    //与上一个窗口的热点集合相似度，第一个窗口为0
+    pub hot_set_similarity: f64,
+    pub stable: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WarmupAnalysis {
+    pub start_time: i64,
+    pub end_time: i64,
+    //进入稳态的时间，没有检测到稳态时为-1
+    pub warmup_end_time: i64,
+    pub steady_state: bool,
+    //是否有编译线程的数据
+    pub has_compiler_threads: bool,
+    pub windows: Vec<WarmupWindow>,
+}
+
+pub fn is_compiler_thread(name: &str) -> bool {
+    name.contains("CompilerThread") || name.starts_with("JVMCI")
+}
+
+pub fn detect_warmup(collector: &mut SampleCollector, start_time: i64, end_time: i64, options: &WarmupOptions) -> io::Result<WarmupAnalysis> {
+    if options.window_ms <= 0 || options.stable_windows == 0 {
+        return Err(new_invalid_input_error("window_ms and stable_windows must be greater than 0"));
+    }
+    //时间范围限制在录制范围内
+    let sample_info = collector.get_sample_info();
+    let record_end_time = sample_info.last_record_time.saturating_add(sample_info.sample_interval);
+    let start_time = if start_time > 0 { start_time.max(sample_info.record_start_time) } else { sample_info.record_start_time };
+    let end_time = if end_time > 0 { end_time.min(record_end_time) } else { record_end_time };
+    let duration = end_time.checked_sub(start_time).unwrap_or(0).max(0);
+    let window_count = (duration / options.window_ms + if duration % options.window_ms > 0 { 1 } else { 0 }) as usize;
+    if window_count > MAX_WARMUP_WINDOWS {
+        let min_window_ms = duration / MAX_WARMUP_WINDOWS as i64 + 1;
+        return Err(new_invalid_input_error(&format!("too many windows: {}, window_ms must be at least {}", window_count, min_window_ms)));
+    }
+
+    //每个窗口: 自身方法取样数, 取样数, 应用CPU, 编译CPU
+    let mut method_counts: Vec<HashMap<i64, usize>> = vec![HashMap::new(); window_count];
+    let mut samples = vec![0usize; window_count];
+    let mut cpu_times = vec![0i64; window_count];
+    let mut compile_cpu_times = vec![0i64; window_count];
+    let mut has_compiler_threads = false;
+    for thread in collector.get_threads()? {
+        let compiler = is_compiler_thread(&thread.name);
+        has_compiler_threads |= compiler;
+        let thread_samples = match collector.load_thread_samples(thread.id, start_time, end_time) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("load thread samples failed, thread: {}, error: {}", thread.id, e);
+                continue;
+            }
+        };
+        for thread_data in &thread_samples {
+            let index = ((thread_data.sample_time - start_time) / options.window_ms) as usize;
+            if thread_data.sample_time < start_time || index >= window_count {
+                continue;
+            }
+            if compiler {
+                compile_cpu_times[index] += thread_data.cpu_time_delta;
+                continue;
+            }
+            cpu_times[index] += thread_data.cpu_time_delta;
+            if thread_data.state != "RUNNABLE" {
+                continue;
+            }
+            samples[index] += 1;
+            //栈顶在前
+            if let Some(method) = thread_data.stacktrace.first() {
+                *method_counts[index].entry(*method).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let max_compile_cpu = compile_cpu_times.iter().cloned().max().unwrap_or(0);
+    let mut windows: Vec<WarmupWindow> = Vec::with_capacity(window_count);
+    let mut last_hot_set: Option<HashSet<i64>> = None;
+    let mut compile_settled_flags = Vec::with_capacity(window_count);
+    for i in 0..window_count {
+        let hot_set = get_hot_set(&method_counts[i], options.top_methods);
+        let similarity = match &last_hot_set {
+            Some(last) => jaccard_similarity(last, &hot_set),
+            None => 0.0
+        };
+        let compile_settled = max_compile_cpu == 0 || compile_cpu_times[i] as f64 <= max_compile_cpu as f64 * options.max_compile_ratio;
+        compile_settled_flags.push(compile_settled);
+        let window_start = start_time + i as i64 * options.window_ms;
+        windows.push(WarmupWindow {
+            start_time: window_start,
+            end_time: window_start.saturating_add(options.window_ms).min(end_time),
+            samples: samples[i],
+            cpu_time_ms: cpu_times[i] / 1_000_000,
+            compile_cpu_time_ms: compile_cpu_times[i] / 1_000_000,
+            hot_set_similarity: similarity,
+            stable: samples[i] > 0 && similarity >= options.min_similarity && compile_settled,
+        });
+        last_hot_set = Some(hot_set);
+    }
+
+    //第一个连续稳定的窗口序列之前为预热阶段
+    let mut warmup_end_time = -1;
+    let mut run = 0;
+    for (i, window) in windows.iter().enumerate() {
+        run = if window.stable { run + 1 } else { 0 };
+        if run == options.stable_windows {
+            //第一个稳定窗口与前一个窗口的热点相同，前一个窗口没有编译活动时稳态从前一个窗口开始
+            let first = i + 1 - run;
+            warmup_end_time = if first > 0 && compile_settled_flags[first - 1] && windows[first - 1].samples > 0 {
+                windows[first - 1].start_time
+            } else {
+                windows[first].start_time
+            };
+            break;
+        }
+    }
+    Ok(WarmupAnalysis {
+        start_time,
+        end_time,
+        warmup_end_time,
+        steady_state: warmup_end_time > 0,
+        has_compiler_threads,
+        windows,
+    })
+}
+
+fn get_hot_set(method_counts: &HashMap<i64, usize>, top_methods: usize) -> HashSet<i64> {
+    let mut methods: Vec<(&i64, &usize)> = method_counts.iter().collect();
+    methods.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    methods.iter().take(top_methods).map(|x| *x.0).collect()
+}
+
+fn jaccard_similarity(a: &HashSet<i64>, b: &HashSet<i64>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+//排除预热阶段后的开始时间，没有检测到稳态时不变
+pub fn get_steady_start_time(collector: &mut SampleCollector, start_time: i64, end_time: i64) -> io::Result<i64> {
+    let analysis = detect_warmup(collector, start_time, end_time, &WarmupOptions::default())?;
+    if analysis.steady_state && analysis.warmup_end_time > start_time {
+        println!("exclude warm-up window: {} - {}", analysis.start_time, analysis.warmup_end_time);
+        return Ok(analysis.warmup_end_time);
+    }
+    Ok(start_time)
+}
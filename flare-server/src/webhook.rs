@@ -0,0 +1,218 @@
+
+//事件通知: 录制开始/结束、触发器触发、agent断开、磁盘空间不足时POST JSON到配置的webhook地址
+//在后台线程发送，不阻塞请求处理；支持http及https(使用系统的TLS库及根证书校验服务端证书)
+//配置示例(flare-server.conf):
+//  [[webhooks]]
+//  url = "http://127.0.0.1:8080/flare-events"
+//  events = ["recording_finished", "agent_lost"]   #为空表示所有事件
+//  format = "slack"                                #json(默认) 或 slack
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use native_tls::TlsConnector;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use chrono::Local;
+use serde_json::{json, Value};
+use utils::*;
+
+pub const NOTIFY_EVENTS: &[&str] = &["recording_started", "recording_finished", "trigger_fired", "agent_lost", "disk_threshold_exceeded"];
+const WEBHOOK_TIMEOUT_MS: u64 = 5000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NotifyEvent {
+    RecordingStarted,
+    RecordingFinished,
+    TriggerFired,
+    AgentLost,
+    DiskThresholdExceeded,
+}
+
+impl NotifyEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifyEvent::RecordingStarted => "recording_started",
+            NotifyEvent::RecordingFinished => "recording_finished",
+            NotifyEvent::TriggerFired => "trigger_fired",
+            NotifyEvent::AgentLost => "agent_lost",
+            NotifyEvent::DiskThresholdExceeded => "disk_threshold_exceeded",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    //json, slack
+    #[serde(default = "default_webhook_format")]
+    pub format: String,
+}
+
+fn default_webhook_format() -> String {
+    "json".to_string()
+}
+
+impl WebhookConfig {
+    pub fn accept(&self, event: NotifyEvent) -> bool {
+        self.events.is_empty() || self.events.iter().any(|x| x == event.as_str())
+    }
+}
+
+//检查配置的事件名称及地址
+pub fn validate_webhooks(webhooks: &[WebhookConfig]) -> io::Result<()> {
+    for webhook in webhooks {
+        parse_http_url(&webhook.url)?;
+        if webhook.format != "json" && webhook.format != "slack" {
+            return Err(new_invalid_input_error(&format!("invalid webhook format: {}, expect json or slack", webhook.format)));
+        }
+        if let Some(event) = webhook.events.iter().find(|x| !NOTIFY_EVENTS.contains(&x.as_str())) {
+            return Err(new_invalid_input_error(&format!("invalid webhook event: {}, expect one of {:?}", event, NOTIFY_EVENTS)));
+        }
+    }
+    Ok(())
+}
+
+pub struct WebhookNotifier {
+    webhooks: Vec<WebhookConfig>,
+    sender: Option<mpsc::Sender<(String, String)>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(webhooks: Vec<WebhookConfig>) -> WebhookNotifier {
+        let sender = if webhooks.is_empty() {
+            None
+        } else {
+            let (sender, receiver) = mpsc::channel::<(String, String)>();
+            thread::spawn(move || {
+                for (url, body) in receiver {
+                    if let Err(e) = post_json(&url, &body) {
+                        println!("send webhook failed: {}, error: {}", url, e);
+                    }
+                }
+            });
+            Some(sender)
+        };
+        WebhookNotifier { webhooks, sender }
+    }
+
+    //data为事件的附加信息(session_id等)，合并到JSON消息中
+    pub fn notify(&self, event: NotifyEvent, message: &str, data: Value) {
+        let sender = match &self.sender {
+            Some(x) => x,
+            None => return
+        };
+        for webhook in self.webhooks.iter().filter(|x| x.accept(event)) {
+            let body = build_payload(&webhook.format, event, message, &data);
+            if sender.send((webhook.url.clone(), body.to_string())).is_err() {
+                println!("webhook sender is closed, event: {}", event.as_str());
+            }
+        }
+    }
+}
+
+pub fn build_payload(format: &str, event: NotifyEvent, message: &str, data: &Value) -> Value {
+    if format == "slack" {
+        return json!({"text": format!("[flare-profiler] {}: {}", event.as_str(), message)});
+    }
+    let mut payload = json!({
+        "event": event.as_str(),
+        "time": Local::now().timestamp_millis(),
+        "message": message,
+    });
+    if let (Some(payload), Some(data)) = (payload.as_object_mut(), data.as_object()) {
+        for (key, value) in data {
+            payload.insert(key.clone(), value.clone());
+        }
+    }
+    payload
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HttpUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+//http[s]://host[:port][/path]
+pub fn parse_http_url(url: &str) -> io::Result<HttpUrl> {
+    let (tls, rest, default_port) = if url.starts_with("https://") {
+        (true, &url["https://".len()..], 443)
+    } else if url.starts_with("http://") {
+        (false, &url["http://".len()..], 80)
+    } else {
+        return Err(new_invalid_input_error(&format!("invalid webhook url: {}", url)));
+    };
+    let (host_port, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/")
+    };
+    let (host, port) = match host_port.rfind(':') {
+        Some(pos) => {
+            let port = host_port[pos + 1..].parse::<u16>().map_err(|_| new_invalid_input_error(&format!("invalid webhook port: {}", url)))?;
+            (&host_port[..pos], port)
+        }
+        None => (host_port, default_port)
+    };
+    if host.is_empty() {
+        return Err(new_invalid_input_error(&format!("invalid webhook host: {}", url)));
+    }
+    Ok(HttpUrl { tls, host: host.to_string(), port, path: path.to_string() })
+}
+
+pub fn post_json(url: &str, body: &str) -> io::Result<()> {
+    let url = parse_http_url(url)?;
+    let timeout = Duration::from_millis(WEBHOOK_TIMEOUT_MS);
+    let addr = (url.host.as_str(), url.port).to_socket_addrs()?.next()
+        .ok_or_else(|| new_error(io::ErrorKind::NotFound, &format!("resolve host failed: {}", url.host)))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    if url.tls {
+        let connector = TlsConnector::new().map_err(|e| new_error(io::ErrorKind::Other, &format!("create tls connector failed: {}", e)))?;
+        let mut stream = connector.connect(&url.host, stream)
+            .map_err(|e| new_error(io::ErrorKind::Other, &format!("tls handshake failed: {}, error: {}", url.host, e)))?;
+        send_request(&mut stream, &url, body)
+    } else {
+        let mut stream = stream;
+        send_request(&mut stream, &url, body)
+    }
+}
+
+fn send_request<S: Read + Write>(stream: &mut S, url: &HttpUrl, body: &str) -> io::Result<()> {
+    let request = format!("POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                          url.path, url.host, url.port, body.len(), body);
+    stream.write_all(request.as_bytes())?;
+
+    //只检查状态行
+    let mut buf = [0u8; 64];
+    let len = stream.read(&mut buf)?;
+    let status_line = String::from_utf8_lossy(&buf[..len]);
+    let status = status_line.split_whitespace().nth(1).and_then(|x| x.parse::<u16>().ok()).unwrap_or(0);
+    if status < 200 || status >= 300 {
+        return Err(new_error(io::ErrorKind::Other, &format!("unexpected response status: {}", status_line.lines().next().unwrap_or(""))));
+    }
+    Ok(())
+}
+
+//检查目录所在磁盘的可用空间(字节)
+#[cfg(unix)]
+pub fn get_free_disk_space(path: &str) -> Option<u64> {
+    use std::ffi::CString;
+    let c_path = CString::new(path).ok()?;
+    let mut stat: ::libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { ::libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn get_free_disk_space(_path: &str) -> Option<u64> {
+    None
+}
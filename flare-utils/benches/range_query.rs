@@ -0,0 +1,83 @@
+//时序文件范围查询(get_range_value)的基准测试，覆盖不同的文件大小、合并比例及数值类型
+//  cargo bench --bench range_query [-- <filter>]
+//  测试数据按固定规则生成在 target/bench-data，不同提交之间的结果可以直接比较:
+//    cargo bench --bench range_query -- --save-baseline <commit>
+//    cargo bench --bench range_query -- --baseline <commit>
+//  环境变量 FLARE_BENCH_NO_SIMD=1 时使用标量的聚合实现
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use flare_utils::ValueType;
+use flare_utils::simd::set_simd_enabled;
+use flare_utils::timeseries::*;
+use std::fs;
+
+const DATA_DIR: &str = "target/bench-data";
+const UNIT_TIME: i64 = 100;
+//固定的开始时间，保证生成的文件及查询范围每次相同
+const BEGIN_TIME: i64 = 1_500_000_000_000;
+const SIZES: [i64; 3] = [10_000, 100_000, 1_000_000];
+const RATIOS: [i32; 4] = [1, 8, 64, 512];
+
+fn bench_range_query(c: &mut Criterion) {
+    if std::env::var("FLARE_BENCH_NO_SIMD").map_or(false, |x| x == "1") {
+        set_simd_enabled(false);
+    }
+    fs::create_dir_all(DATA_DIR).unwrap();
+
+    let value_types = [("int32", ValueType::INT32), ("int64", ValueType::INT64), ("float64", ValueType::FLOAT64)];
+    for (type_name, value_type) in value_types.iter() {
+        let mut group = c.benchmark_group(format!("range_query/{}", type_name));
+        for size in SIZES.iter() {
+            let path = format!("{}/{}_{}", DATA_DIR, type_name, size);
+            prepare_file(&path, *value_type, *size);
+            let reader = TimeSeriesFileReader::new(&path).unwrap();
+
+            //查询中间一半的范围
+            let start_time = BEGIN_TIME + UNIT_TIME * (*size / 4);
+            let end_time = BEGIN_TIME + UNIT_TIME * (*size * 3 / 4);
+            for ratio in RATIOS.iter() {
+                let unit_time_ms = *ratio * UNIT_TIME as i32;
+                group.bench_with_input(BenchmarkId::new(size.to_string(), format!("x{}", ratio)), &unit_time_ms, |b, unit_time_ms| {
+                    b.iter(|| {
+                        let result = reader.get_range_value(start_time, end_time, *unit_time_ms);
+                        assert!(result.steps > 0);
+                        result
+                    })
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+//已生成的文件数量一致时直接使用
+fn prepare_file(path: &str, value_type: ValueType, size: i64) {
+    if let Ok(reader) = TimeSeriesFileReader::new(path) {
+        let info = reader.get_header_info();
+        if info.amount as i64 == size && info.value_type == value_type && info.version == TS_FORMAT_VERSION {
+            return;
+        }
+    }
+    let file_name = path.rsplit('/').next().unwrap().to_string();
+    for entry in fs::read_dir(DATA_DIR).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_name().to_string_lossy().starts_with(&format!("{}.", file_name)) {
+            fs::remove_file(entry.path()).unwrap();
+        }
+    }
+    println!("generating bench data: {}, values: {}", path, size);
+    let mut writer = TimeSeriesFileWriter::new(value_type, UNIT_TIME as i32, BEGIN_TIME, path).unwrap();
+    for i in 0..size {
+        //周期变化的数值，不同合并比例下的结果不同
+        let n = (i * 7919) % 1000;
+        let value = match value_type {
+            ValueType::INT32 => TSValue::int32(n as i32),
+            ValueType::INT64 => TSValue::int64(n * 1_000_000),
+            _ => TSValue::float64(n as f64 / 10.0),
+        };
+        writer.add_value(BEGIN_TIME + i * UNIT_TIME, value).unwrap();
+    }
+}
+
+criterion_group!(benches, bench_range_query);
+criterion_main!(benches);
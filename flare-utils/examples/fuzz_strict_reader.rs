@@ -0,0 +1,141 @@
+extern crate rand;
+
+use flare_utils::ValueType;
+use flare_utils::timeseries::*;
+use flare_utils::tuple_indexed::*;
+use rand::Rng;
+use std::{fs, io};
+
+//随机破坏文件内容，验证严格模式的读取不会panic
+//usage: fuzz_strict_reader [iterations]
+fn main() -> io::Result<()> {
+    let iterations = std::env::args().nth(1).and_then(|x| x.parse::<usize>().ok()).unwrap_or(1000);
+    let dir = "target/fuzz-strict-reader";
+    fs::create_dir_all(dir)?;
+
+    let ts_path = format!("{}/ts", dir);
+    let tuple_path = format!("{}/tuple", dir);
+    create_ts_file(&ts_path)?;
+    create_tuple_file(&tuple_path)?;
+    let ts_data = fs::read(format!("{}.fts", ts_path))?;
+    let fidx_data = fs::read(format!("{}.fidx", tuple_path))?;
+    let fdata_data = fs::read(format!("{}.fdata", tuple_path))?;
+
+    check_crafted_cases(dir, &ts_path, &ts_data)?;
+
+    let mut rng = rand::thread_rng();
+    let mut panics = 0;
+    let mut errors = 0;
+    for _ in 0..iterations {
+        let mutated_ts = mutate(&mut rng, &ts_data);
+        let mutated_fidx = mutate(&mut rng, &fidx_data);
+        let mutated_fdata = mutate(&mut rng, &fdata_data);
+        let case_ts = format!("{}/case_ts", dir);
+        let case_tuple = format!("{}/case_tuple", dir);
+        fs::write(format!("{}.fts", case_ts), &mutated_ts)?;
+        fs::write(format!("{}.fidx", case_tuple), &mutated_fidx)?;
+        fs::write(format!("{}.fdata", case_tuple), &mutated_fdata)?;
+
+        let result = std::panic::catch_unwind(|| {
+            let mut failed = 0;
+            match TimeSeriesFileReader::new_strict(&case_ts) {
+                Ok(reader) => {
+                    let info = reader.get_header_info();
+                    if reader.try_get_range_value(info.begin_time, info.end_time, info.unit_time.saturating_mul(4)).is_err() {
+                        failed += 1;
+                    }
+                }
+                Err(_) => failed += 1
+            }
+            match TupleIndexedFile::new_strict_reader(&case_tuple) {
+                Ok(mut reader) => {
+                    if reader.get_range_value(&TupleValue::uint32(0), &TupleValue::uint32(u32::max_value()), |_| {}).is_err() {
+                        failed += 1;
+                    }
+                    if reader.get_all_entries().is_err() {
+                        failed += 1;
+                    }
+                }
+                Err(_) => failed += 1
+            }
+            failed
+        });
+        match result {
+            Ok(failed) => errors += failed,
+            Err(_) => {
+                panics += 1;
+                let _ = fs::copy(format!("{}.fts", case_ts), format!("{}/panic_{}.fts", dir, panics));
+                let _ = fs::copy(format!("{}.fidx", case_tuple), format!("{}/panic_{}.fidx", dir, panics));
+                let _ = fs::copy(format!("{}.fdata", case_tuple), format!("{}/panic_{}.fdata", dir, panics));
+            }
+        }
+    }
+    println!("fuzz iterations: {}, errors: {}, panics: {}", iterations, errors, panics);
+    if panics > 0 {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("strict readers panicked {} times, cases are saved in {}", panics, dir)));
+    }
+    Ok(())
+}
+
+//随机破坏很难生成的文件头及查询范围: 时间差溢出时返回错误
+fn check_crafted_cases(dir: &str, ts_path: &str, ts_data: &[u8]) -> io::Result<()> {
+    //文件头: TSHS(4) header_len(2) value_type(1) unit_len(1) unit_time(4) begin_time(8) end_time(8) amount(4) ...
+    let mut data = ts_data.to_vec();
+    data[8..12].copy_from_slice(&1i32.to_be_bytes());
+    data[12..20].copy_from_slice(&(i64::min_value() + 1).to_be_bytes());
+    data[20..28].copy_from_slice(&i64::max_value().to_be_bytes());
+    let case_ts = format!("{}/crafted_ts", dir);
+    fs::write(format!("{}.fts", case_ts), &data)?;
+    let err = TimeSeriesFileReader::new_strict(&case_ts).err().expect("overflowing time range should be rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let reader = TimeSeriesFileReader::new_strict(ts_path)?;
+    assert!(reader.try_get_range_value(i64::min_value(), i64::max_value(), 20).is_err());
+    assert!(reader.try_get_transformed_range_value(i64::min_value(), 0, 20, TSTransform::DELTA).is_err());
+    assert!(reader.try_get_range_value(1_000_000, i64::max_value(), 20).is_ok());
+    Ok(())
+}
+
+fn mutate<R: Rng>(rng: &mut R, data: &[u8]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    match rng.gen_range(0, 3) {
+        //flip bytes
+        0 => {
+            for _ in 0..rng.gen_range(1, 8) {
+                let pos = rng.gen_range(0, data.len());
+                data[pos] = rng.gen();
+            }
+        }
+        //truncate
+        1 => {
+            let len = rng.gen_range(0, data.len());
+            data.truncate(len);
+        }
+        //append garbage
+        _ => {
+            for _ in 0..rng.gen_range(1, 64) {
+                data.push(rng.gen());
+            }
+        }
+    }
+    data
+}
+
+fn create_ts_file(path: &str) -> io::Result<()> {
+    let _ = fs::remove_file(format!("{}.fts", path));
+    let mut tsfile = TimeSeriesFileWriter::new(ValueType::INT32, 20, 1_000_000, path)?;
+    for i in 0..500 {
+        tsfile.add_value(1_000_000 + i * 20, TSValue::int32(i as i32))?;
+    }
+    Ok(())
+}
+
+fn create_tuple_file(path: &str) -> io::Result<()> {
+    let _ = fs::remove_file(format!("{}.fidx", path));
+    let _ = fs::remove_file(format!("{}.fdata", path));
+    let mut tuple_file = TupleIndexedFile::new_writer(path, ValueType::UINT32)?;
+    for i in 0..200u32 {
+        tuple_file.add_value(TupleValue::uint32(i), format!("value-{}", i).as_bytes())?;
+    }
+    tuple_file.flush()
+}
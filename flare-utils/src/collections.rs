@@ -1,18 +1,31 @@
-
-pub mod MapUtil {
-    use std::collections::HashMap;
-
-    pub fn get_as_i64(map: &mut HashMap<String, String>, key: &str) -> i64 {
-        map.get(key).unwrap().parse::<i64>().unwrap()
-    }
-
-    pub fn get_as_i32(map: &mut HashMap<String, String>, key: &str) -> i32 {
-        map.get(key).unwrap().parse::<i32>().unwrap()
-    }
-
-    pub fn get_as_i8(map: &mut HashMap<String, String>, key: &str) -> i8 {
-        map.get(key).unwrap().parse::<i8>().unwrap()
-    }
-}
-
-
+
+pub mod MapUtil {
+    use std::collections::HashMap;
+    use std::io;
+    use std::str::FromStr;
+
+    pub fn get_as_i64(map: &mut HashMap<String, String>, key: &str) -> i64 {
+        map.get(key).unwrap().parse::<i64>().unwrap()
+    }
+
+    pub fn get_as_i32(map: &mut HashMap<String, String>, key: &str) -> i32 {
+        map.get(key).unwrap().parse::<i32>().unwrap()
+    }
+
+    pub fn get_as_i8(map: &mut HashMap<String, String>, key: &str) -> i8 {
+        map.get(key).unwrap().parse::<i8>().unwrap()
+    }
+
+    //return error instead of panic if the key is missing or invalid
+    pub fn parse_value<T: FromStr>(map: &HashMap<String, String>, key: &str) -> io::Result<T> {
+        match map.get(key) {
+            Some(value) => match value.parse::<T>() {
+                Ok(x) => Ok(x),
+                Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid header value: {}={}", key, value)))
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("missing header: {}", key)))
+        }
+    }
+}
+
+
@@ -1,107 +1,223 @@
-
-use std::fs::{OpenOptions, File};
-use std::io;
-use std::collections::HashMap;
-//use eclectic::map::*;
-
-use super::{FileEndian,WriteBytesExt,ReadBytesExt};
-use std::io::{Write, Read, ErrorKind, BufReader, BufRead, Seek, SeekFrom};
-
-//open file with read and write permissions
-pub fn open_file(path: &str, rw: bool) -> Result<File, io::Error> {
-    OpenOptions::new()
-        .read(true)
-        .write(rw)
-        .create(rw)
-        .open(path.to_string())
-}
-
-//write common file header
-pub fn write_header_info(file: &mut File, header_map: &mut HashMap<&str, String>, header_segment_flag: &str, data_segment_flag: &str) -> Result<u64, io::Error> {
-    //file version
-    header_map.insert("ver", "0.1.0".to_string());
-
-    //encode header
-    let mut header_vec = vec![];
-    //property size (1 byte)
-    header_vec.write_i8(header_map.len() as i8);
-    for (name, value) in header_map.iter() {
-        header_vec.write_all(name.as_bytes());
-        header_vec.write_u8(0);
-        header_vec.write_all(value.as_bytes());
-        header_vec.write_u8(0);
-    }
-    let max_len = 192;
-    if header_vec.len() > max_len {
-        return Err(io::Error::new(ErrorKind::InvalidInput, "header len is too large!"));
-    }
-    //添加一个空闲空间，避免后面重写头部覆盖数据
-    let pad_len = max_len - header_vec.len();
-    let mut pad_data = Vec::new();
-    pad_data.resize(pad_len, 0);
-    header_vec.write_all(&pad_data);
-
-    //write file header
-    //TS file header segment: TSHS (4 bytes)
-    file.write_all(header_segment_flag.as_bytes())?;
-
-    //header len (2 bytes)
-    file.write_u16::<FileEndian>(header_vec.len() as u16)?;
-
-    //header data (n bytes)
-    file.write_all(header_vec.as_slice())?;
-
-    //data segment flag: TSDS
-    file.write_all(data_segment_flag.as_bytes())?;
-    file.flush();
-
-    Ok(file.seek(SeekFrom::Current(0)).unwrap())
-}
-
-pub fn read_header_info(file: &mut File, header_map: &mut HashMap<String, String>, header_segment_flag: &str, data_segment_flag: &str) -> Result<u64, io::Error> {
-    //read file header
-    let flag = read_file_flag(file);
-    if flag != header_segment_flag {
-        println!("Invalid file, header segment flag not match, expect '{}' but '{}'", header_segment_flag, flag);
-        return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid file, header segment not match"));
-    }
-
-    //header len (2 bytes)
-    let header_len = file.read_u16::<FileEndian>().unwrap() as u64;
-    let header_offset = 4 + 2;
-    let header_count = file.read_u8().unwrap();
-
-    let mut buf_reader = BufReader::new(file as &mut Read);
-    let reader = &mut buf_reader;
-    for i in 00..header_count {
-        let name = read_utf8(reader);
-        let value = read_utf8(reader);
-        header_map.insert(name.to_string(), value.to_string());
-    }
-
-    //verify data segment flag
-    file.seek(SeekFrom::Start(header_offset+header_len));
-    let flag = read_file_flag(file);
-    if flag != data_segment_flag {
-        println!("Invalid file, data segment flag not match, expect '{}' but '{}'", data_segment_flag, flag);
-        return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid file, data segment not match"));
-    }
-    Ok(file.seek(SeekFrom::Current(0)).unwrap())
-}
-
-fn read_utf8(buf_reader: &mut BufReader<&mut Read>) -> String {
-    let mut buf = vec![];
-    let num_bytes = buf_reader.read_until(b'\0', &mut buf)
-        .expect("expect delimiter '\0'");
-    let s = std::str::from_utf8(buf.as_slice()).unwrap();
-    s.trim_matches(|x|x=='\0' ).to_string()
-}
-
-fn read_file_flag(file: &mut Read) -> String {
-    let mut flag_buf = [0 as u8; 4];
-//TS file header segment: TSHS (4 bytes)
-    file.read_exact(&mut flag_buf[..]);
-    let flag = std::str::from_utf8(&flag_buf[..]).unwrap();
-    flag.to_string()
-}
-
+
+use std::fs::{OpenOptions, File};
+use std::io;
+use std::collections::HashMap;
+//use eclectic::map::*;
+
+use super::{FileEndian,WriteBytesExt,ReadBytesExt};
+use std::io::{Write, Read, ErrorKind, BufRead, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+//open file with read and write permissions
+pub fn open_file<P: AsRef<Path>>(path: P, rw: bool) -> Result<File, io::Error> {
+    OpenOptions::new()
+        .read(true)
+        .write(rw)
+        .create(rw)
+        .open(path)
+}
+
+//路径加上后缀(如 .fidx)，不要求路径是有效的UTF-8
+pub fn path_with_suffix<P: AsRef<Path>>(path: P, suffix: &str) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+//write common file header
+pub fn write_header_info(file: &mut File, header_map: &mut HashMap<&str, String>, header_segment_flag: &str, data_segment_flag: &str) -> Result<u64, io::Error> {
+    //file version
+    header_map.entry("ver").or_insert("0.1.0".to_string());
+    //byte order
+    header_map.insert("byte_order", super::FILE_BYTE_ORDER.to_string());
+
+    //encode header
+    let mut header_vec = vec![];
+    //property size (1 byte)
+    header_vec.write_i8(header_map.len() as i8);
+    for (name, value) in header_map.iter() {
+        header_vec.write_all(name.as_bytes());
+        header_vec.write_u8(0);
+        header_vec.write_all(value.as_bytes());
+        header_vec.write_u8(0);
+    }
+    let max_len = 192;
+    if header_vec.len() > max_len {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "header len is too large!"));
+    }
+    //添加一个空闲空间，避免后面重写头部覆盖数据
+    let pad_len = max_len - header_vec.len();
+    let mut pad_data = Vec::new();
+    pad_data.resize(pad_len, 0);
+    header_vec.write_all(&pad_data);
+
+    //write file header
+    //TS file header segment: TSHS (4 bytes)
+    file.write_all(header_segment_flag.as_bytes())?;
+
+    //header len (2 bytes)
+    file.write_u16::<FileEndian>(header_vec.len() as u16)?;
+
+    //header data (n bytes)
+    file.write_all(header_vec.as_slice())?;
+
+    //data segment flag: TSDS
+    file.write_all(data_segment_flag.as_bytes())?;
+    file.flush();
+
+    Ok(file.seek(SeekFrom::Current(0)).unwrap())
+}
+
+pub fn read_header_info<R: Read + Seek>(file: &mut R, header_map: &mut HashMap<String, String>, header_segment_flag: &str, data_segment_flag: &str) -> Result<u64, io::Error> {
+    //read file header
+    let flag = read_file_flag(file)?;
+    if flag != header_segment_flag {
+        println!("Invalid file, header segment flag not match, expect '{}' but '{}'", header_segment_flag, flag);
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid file, header segment not match"));
+    }
+
+    //header len (2 bytes)
+    let header_len = file.read_u16::<FileEndian>()? as u64;
+    let header_offset = 4 + 2;
+    if header_len == 0 {
+        return Err(io::Error::new(ErrorKind::InvalidData, "Invalid file, header len is 0"));
+    }
+
+    //header properties must be inside the header segment
+    let mut header_data = vec![0u8; header_len as usize];
+    file.read_exact(&mut header_data)?;
+    let header_count = header_data[0];
+    let mut reader = &header_data[1..];
+    for _ in 0..header_count {
+        let name = read_utf8(&mut reader)?;
+        let value = read_utf8(&mut reader)?;
+        header_map.insert(name, value);
+    }
+
+    //旧版本文件没有byte_order属性，同样是大端存储
+    if let Some(byte_order) = header_map.get("byte_order") {
+        if byte_order != super::FILE_BYTE_ORDER {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid file, unsupported byte order: {}", byte_order)));
+        }
+    }
+
+    //verify data segment flag
+    file.seek(SeekFrom::Start(header_offset+header_len))?;
+    let flag = read_file_flag(file)?;
+    if flag != data_segment_flag {
+        println!("Invalid file, data segment flag not match, expect '{}' but '{}'", data_segment_flag, flag);
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid file, data segment not match"));
+    }
+    file.seek(SeekFrom::Current(0))
+}
+
+fn read_utf8(reader: &mut &[u8]) -> io::Result<String> {
+    let mut buf = vec![];
+    reader.read_until(b'\0', &mut buf)?;
+    if buf.last() != Some(&0) {
+        return Err(io::Error::new(ErrorKind::InvalidData, "Invalid file header, expect delimiter '\\0'"));
+    }
+    match std::str::from_utf8(&buf[..buf.len()-1]) {
+        Ok(s) => Ok(s.to_string()),
+        Err(_) => Err(io::Error::new(ErrorKind::InvalidData, "Invalid file header, not utf8 string"))
+    }
+}
+
+fn read_file_flag(file: &mut Read) -> io::Result<String> {
+    let mut flag_buf = [0 as u8; 4];
+    //TS file header segment: TSHS (4 bytes)
+    file.read_exact(&mut flag_buf[..])?;
+    Ok(String::from_utf8_lossy(&flag_buf[..]).to_string())
+}
+
+//只读映射文件的一段数据，读取大范围的数据时不需要复制到内存，按需加载页面
+//  unix平台使用mmap，其它平台或者映射失败时读取到内存
+//  映射期间文件不能被截断(取样文件只追加写入)，否则访问映射的数据会出错
+pub struct MappedRegion {
+    data: MappedData,
+}
+
+enum MappedData {
+    #[cfg(unix)]
+    Mapped { ptr: *mut libc::c_void, map_len: usize, delta: usize, len: usize },
+    Buffer(Vec<u8>),
+}
+
+//映射的数据是只读的，可以在线程之间传递
+unsafe impl Send for MappedRegion {}
+unsafe impl Sync for MappedRegion {}
+
+impl MappedRegion {
+    //映射 [offset, offset+len)，超出文件长度时返回错误
+    pub fn map(file: &mut File, offset: u64, len: usize) -> io::Result<MappedRegion> {
+        let file_len = file.seek(SeekFrom::End(0))?;
+        if offset + len as u64 > file_len {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "mapped region is out of file"));
+        }
+        if len == 0 {
+            return Ok(MappedRegion { data: MappedData::Buffer(vec![]) });
+        }
+        #[cfg(unix)]
+        {
+            if let Some(region) = MappedRegion::mmap(file, offset, len) {
+                return Ok(region);
+            }
+        }
+        let mut buf = vec![0u8; len];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)?;
+        Ok(MappedRegion { data: MappedData::Buffer(buf) })
+    }
+
+    #[cfg(unix)]
+    fn mmap(file: &File, offset: u64, len: usize) -> Option<MappedRegion> {
+        use std::os::unix::io::AsRawFd;
+        //映射的起始位置必须按页对齐
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        if page_size <= 0 {
+            return None;
+        }
+        let page_size = page_size as u64;
+        let aligned_offset = offset / page_size * page_size;
+        let delta = (offset - aligned_offset) as usize;
+        let map_len = len + delta;
+        let ptr = unsafe {
+            libc::mmap(std::ptr::null_mut(), map_len, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), aligned_offset as libc::off_t)
+        };
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+        Some(MappedRegion { data: MappedData::Mapped { ptr, map_len, delta, len } })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.data {
+            #[cfg(unix)]
+            MappedData::Mapped { ptr, delta, len, .. } => unsafe { std::slice::from_raw_parts((*ptr as *const u8).add(*delta), *len) },
+            MappedData::Buffer(buf) => buf.as_slice(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        match &self.data {
+            #[cfg(unix)]
+            MappedData::Mapped { .. } => true,
+            _ => false
+        }
+    }
+}
+
+impl Drop for MappedRegion {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let MappedData::Mapped { ptr, map_len, .. } = &self.data {
+                unsafe { libc::munmap(*ptr, *map_len); }
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+
+//固定桶的直方图(类似HDR Histogram)，用于记录每个时间段内的耗时分布(如safepoint耗时)
+//每2的幂次区间平均分为4个子桶，相对误差不超过25%，可表示 [0, 2^33) 的值，超出部分计入最后一个桶
+//按大端紧凑存储，每个桶一个u32计数，所有桶都为u32::MAX表示空值
+
+use std::io;
+use std::io::{Read, Write};
+use byteorder::{WriteBytesExt, ReadBytesExt};
+use super::FileEndian;
+
+//每个2的幂次区间的子桶数量(2^SUB_BUCKET_BITS)
+const SUB_BUCKET_BITS: u32 = 2;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+pub const HISTOGRAM_BUCKETS: usize = 128;
+//encoded bytes
+pub const HISTOGRAM_LEN: usize = HISTOGRAM_BUCKETS * 4;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Histogram {
+    counts: Vec<u32>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram { counts: vec![0; HISTOGRAM_BUCKETS] }
+    }
+
+    //使用预先分好桶的计数创建，桶数必须为HISTOGRAM_BUCKETS
+    pub fn from_counts(counts: Vec<u32>) -> io::Result<Histogram> {
+        if counts.len() != HISTOGRAM_BUCKETS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid histogram buckets: {}, expect {}", counts.len(), HISTOGRAM_BUCKETS)));
+        }
+        Ok(Histogram { counts })
+    }
+
+    pub fn get_counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    pub fn record_n(&mut self, value: u64, count: u32) {
+        let index = get_bucket_index(value);
+        //u32::MAX 保留给空值
+        self.counts[index] = self.counts[index].saturating_add(count).min(u32::max_value() - 1);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (x, y) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *x = x.saturating_add(*y).min(u32::max_value() - 1);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().map(|x| *x as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|x| *x == 0)
+    }
+
+    //按桶的中间值估算
+    pub fn mean(&self) -> f64 {
+        let count = self.count();
+        if count == 0 {
+            return 0.0;
+        }
+        let sum: f64 = self.counts.iter().enumerate()
+            .map(|(i, x)| *x as f64 * (get_bucket_lower_bound(i) + get_bucket_upper_bound(i)) as f64 / 2.0)
+            .sum();
+        sum / count as f64
+    }
+
+    pub fn min(&self) -> u64 {
+        self.counts.iter().position(|x| *x > 0).map(get_bucket_lower_bound).unwrap_or(0)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.counts.iter().rposition(|x| *x > 0).map(get_bucket_upper_bound).unwrap_or(0)
+    }
+
+    //percentile: [0, 100]，返回所在桶的上界
+    pub fn value_at_percentile(&self, percentile: f64) -> u64 {
+        let count = self.count();
+        if count == 0 {
+            return 0;
+        }
+        let percentile = percentile.max(0.0).min(100.0);
+        let target = ((percentile / 100.0 * count as f64).ceil() as u64).max(1);
+        let mut total = 0;
+        for (i, x) in self.counts.iter().enumerate() {
+            total += *x as u64;
+            if total >= target {
+                return get_bucket_upper_bound(i);
+            }
+        }
+        self.max()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for x in &self.counts {
+            writer.write_u32::<FileEndian>(*x)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_null<W: Write>(writer: &mut W) -> io::Result<()> {
+        for _ in 0..HISTOGRAM_BUCKETS {
+            writer.write_u32::<FileEndian>(u32::max_value())?;
+        }
+        Ok(())
+    }
+
+    //返回None表示空值
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Histogram>> {
+        let mut counts = vec![0; HISTOGRAM_BUCKETS];
+        reader.read_u32_into::<FileEndian>(&mut counts)?;
+        if counts.iter().all(|x| *x == u32::max_value()) {
+            return Ok(None);
+        }
+        Ok(Some(Histogram { counts }))
+    }
+}
+
+pub fn get_bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let msb = 63 - value.leading_zeros();
+    let sub = ((value >> (msb - SUB_BUCKET_BITS)) as usize) & (SUB_BUCKETS - 1);
+    let index = (msb - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + sub;
+    index.min(HISTOGRAM_BUCKETS - 1)
+}
+
+pub fn get_bucket_lower_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift
+}
+
+//桶内的最大值
+pub fn get_bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    if index == HISTOGRAM_BUCKETS - 1 {
+        return u64::max_value();
+    }
+    get_bucket_lower_bound(index + 1) - 1
+}
+
+//合并多个时间段的直方图，全部是空值时返回None
+pub fn merge_histograms(values: &[Option<Histogram>]) -> Option<Histogram> {
+    let mut result: Option<Histogram> = None;
+    for x in values.iter().flatten() {
+        match result.as_mut() {
+            Some(h) => h.merge(x),
+            None => result = Some(x.clone()),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_bounds() {
+        let mut last_upper = None;
+        for i in 0..HISTOGRAM_BUCKETS - 1 {
+            let lower = get_bucket_lower_bound(i);
+            let upper = get_bucket_upper_bound(i);
+            assert_eq!(get_bucket_index(lower), i);
+            assert_eq!(get_bucket_index(upper), i);
+            if let Some(last_upper) = last_upper {
+                assert_eq!(lower, last_upper + 1);
+            }
+            last_upper = Some(upper);
+        }
+        assert_eq!(get_bucket_index(u64::max_value()), HISTOGRAM_BUCKETS - 1);
+        assert_eq!(get_bucket_index(1000), 35);
+        assert_eq!((get_bucket_lower_bound(35), get_bucket_upper_bound(35)), (896, 1023));
+    }
+
+    #[test]
+    fn test_percentile_and_merge() {
+        let mut h1 = Histogram::new();
+        for v in 1..=100 {
+            h1.record(v);
+        }
+        let mut h2 = Histogram::new();
+        h2.record_n(10_000, 100);
+        assert_eq!(h1.value_at_percentile(50.0), 55);
+        assert_eq!(h1.max(), 111);
+
+        let merged = merge_histograms(&[Some(h1.clone()), None, Some(h2)]).unwrap();
+        assert_eq!(merged.count(), 200);
+        assert_eq!(merged.min(), 1);
+        assert_eq!(merged.value_at_percentile(50.0), h1.value_at_percentile(100.0));
+        assert_eq!(merged.value_at_percentile(99.0), get_bucket_upper_bound(get_bucket_index(10_000)));
+        assert_eq!(merge_histograms(&[None, None]), None);
+
+        let mut buf = vec![];
+        merged.write_to(&mut buf).unwrap();
+        Histogram::write_null(&mut buf).unwrap();
+        assert_eq!(buf.len(), HISTOGRAM_LEN * 2);
+        let mut reader = &buf[..];
+        assert_eq!(Histogram::read_from(&mut reader).unwrap(), Some(merged));
+        assert_eq!(Histogram::read_from(&mut reader).unwrap(), None);
+    }
+}
@@ -18,13 +18,21 @@ pub mod tuple_indexed;
 pub mod file_utils;
 pub mod collections;
 pub mod stopwatch;
+pub mod histogram;
+pub mod parquet_writer;
+pub mod simd;
 
 use byteorder::{WriteBytesExt, ReadBytesExt, NetworkEndian};
 use std::io;
 
 //default file byte order
+//所有文件格式中的多字节数值都按大端(网络字节序)紧凑存储，不做内存对齐，
+//不允许直接写入本机字节序或内存布局，保证不同架构(x86/aarch64)录制的文件可以互相打开
 type FileEndian = NetworkEndian;
 
+//file header property of byte order
+pub const FILE_BYTE_ORDER: &str = "BE";
+
 
 enum_from_primitive! {
     #[derive(Clone, Copy, PartialEq, Debug)]
@@ -35,17 +43,35 @@ enum_from_primitive! {
         INT32,
         UINT32,
         INT64,
-//        FLOAT64
+        FLOAT32,
+        FLOAT64,
+        //1 byte: 0 false, 1 true
+        BOOL,
+        //固定桶的直方图，见 histogram.rs
+        HISTOGRAM,
     }
 }
 
-fn get_unit_len(value_type: ValueType) -> i8{
+fn get_unit_len(value_type: ValueType) -> i16 {
     match value_type {
-        INT16 => 2,
-        UINT16 => 2,
-        INT32 => 4,
-        UINT32 => 4,
-        INT64 => 8,
-//        FLOAT64=> 8
+        ValueType::UNKNOWN => 0,
+        ValueType::INT16 => 2,
+        ValueType::UINT16 => 2,
+        ValueType::INT32 => 4,
+        ValueType::UINT32 => 4,
+        ValueType::INT64 => 8,
+        ValueType::FLOAT32 => 4,
+        ValueType::FLOAT64 => 8,
+        ValueType::BOOL => 1,
+        ValueType::HISTOGRAM => histogram::HISTOGRAM_LEN as i16,
+    }
+}
+
+//parse value type, return error instead of panic on malformed input
+fn parse_value_type(value: i8) -> io::Result<ValueType> {
+    use num::FromPrimitive;
+    match ValueType::from_i8(value) {
+        Some(ValueType::UNKNOWN) | None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid value type: {}", value))),
+        Some(value_type) => Ok(value_type)
     }
 }
\ No newline at end of file
@@ -0,0 +1,325 @@
+
+//最小的Parquet文件写入，用于导出指标数据到 pandas/Spark 等工具
+//只支持扁平的表结构: INT64, DOUBLE, UTF8 三种列类型，可以为空值；每个文件一个row group，每列一个数据页，PLAIN编码不压缩
+//注意: Parquet格式规定使用小端字节序，与本项目的其它文件格式不同，文件元数据使用Thrift compact protocol编码
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use byteorder::{WriteBytesExt, LittleEndian};
+
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+pub enum ParquetColumnData {
+    Int64(Vec<Option<i64>>),
+    Double(Vec<Option<f64>>),
+    Utf8(Vec<Option<String>>),
+}
+
+impl ParquetColumnData {
+    pub fn len(&self) -> usize {
+        match self {
+            ParquetColumnData::Int64(x) => x.len(),
+            ParquetColumnData::Double(x) => x.len(),
+            ParquetColumnData::Utf8(x) => x.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //parquet physical type: INT64=2, DOUBLE=5, BYTE_ARRAY=6
+    fn get_physical_type(&self) -> i32 {
+        match self {
+            ParquetColumnData::Int64(_) => 2,
+            ParquetColumnData::Double(_) => 5,
+            ParquetColumnData::Utf8(_) => 6,
+        }
+    }
+
+    fn is_null(&self, i: usize) -> bool {
+        match self {
+            ParquetColumnData::Int64(x) => x[i].is_none(),
+            ParquetColumnData::Double(x) => x[i].is_none(),
+            ParquetColumnData::Utf8(x) => x[i].is_none(),
+        }
+    }
+
+    //PLAIN编码，只包含非空值
+    fn encode_values(&self, buf: &mut Vec<u8>) {
+        match self {
+            ParquetColumnData::Int64(x) => x.iter().flatten().for_each(|v| buf.write_i64::<LittleEndian>(*v).unwrap()),
+            ParquetColumnData::Double(x) => x.iter().flatten().for_each(|v| buf.write_f64::<LittleEndian>(*v).unwrap()),
+            ParquetColumnData::Utf8(x) => x.iter().flatten().for_each(|v| {
+                buf.write_u32::<LittleEndian>(v.len() as u32).unwrap();
+                buf.extend_from_slice(v.as_bytes());
+            }),
+        }
+    }
+}
+
+pub struct ParquetColumn {
+    pub name: String,
+    pub data: ParquetColumnData,
+}
+
+pub fn write_parquet_file(path: &str, columns: &[ParquetColumn]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_parquet(&mut writer, columns)?;
+    writer.flush()
+}
+
+pub fn write_parquet<W: Write>(writer: &mut W, columns: &[ParquetColumn]) -> io::Result<()> {
+    let num_rows = columns.first().map(|x| x.data.len()).unwrap_or(0);
+    if columns.iter().any(|x| x.data.len() != num_rows) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "all parquet columns must have the same length"));
+    }
+
+    let mut offset = PARQUET_MAGIC.len() as i64;
+    writer.write_all(PARQUET_MAGIC)?;
+
+    //(data_page_offset, chunk_size)
+    let mut chunks = vec![];
+    for column in columns {
+        let page = encode_data_page(&column.data);
+        let mut header = CompactWriter::new();
+        //PageHeader
+        header.i32_field(1, 0); //type: DATA_PAGE
+        header.i32_field(2, page.len() as i32); //uncompressed_page_size
+        header.i32_field(3, page.len() as i32); //compressed_page_size
+        header.struct_begin(5); //data_page_header
+        header.i32_field(1, num_rows as i32); //num_values
+        header.i32_field(2, 0); //encoding: PLAIN
+        header.i32_field(3, 3); //definition_level_encoding: RLE
+        header.i32_field(4, 3); //repetition_level_encoding: RLE
+        header.struct_end();
+        header.stop();
+
+        writer.write_all(&header.buf)?;
+        writer.write_all(&page)?;
+        let chunk_size = (header.buf.len() + page.len()) as i64;
+        chunks.push((offset, chunk_size));
+        offset += chunk_size;
+    }
+
+    //FileMetaData
+    let mut meta = CompactWriter::new();
+    meta.i32_field(1, 1); //version
+    meta.list_begin(2, COMPACT_STRUCT, columns.len() + 1); //schema
+    meta.list_struct_begin();
+    meta.binary_field(4, b"schema");
+    meta.i32_field(5, columns.len() as i32); //num_children
+    meta.list_struct_end();
+    for column in columns {
+        meta.list_struct_begin();
+        meta.i32_field(1, column.data.get_physical_type());
+        meta.i32_field(3, 1); //repetition_type: OPTIONAL
+        meta.binary_field(4, column.name.as_bytes());
+        if let ParquetColumnData::Utf8(_) = column.data {
+            meta.i32_field(6, 0); //converted_type: UTF8
+        }
+        meta.list_struct_end();
+    }
+    meta.i64_field(3, num_rows as i64);
+    meta.list_begin(4, COMPACT_STRUCT, 1); //row_groups
+    meta.list_struct_begin();
+    meta.list_begin(1, COMPACT_STRUCT, columns.len()); //columns
+    for (column, (data_page_offset, chunk_size)) in columns.iter().zip(chunks.iter()) {
+        meta.list_struct_begin();
+        meta.i64_field(2, *data_page_offset); //file_offset
+        meta.struct_begin(3); //meta_data
+        meta.i32_field(1, column.data.get_physical_type());
+        meta.list_begin(2, COMPACT_I32, 2); //encodings: PLAIN, RLE
+        meta.write_i32(0);
+        meta.write_i32(3);
+        meta.list_begin(3, COMPACT_BINARY, 1); //path_in_schema
+        meta.write_binary(column.name.as_bytes());
+        meta.i32_field(4, 0); //codec: UNCOMPRESSED
+        meta.i64_field(5, num_rows as i64); //num_values
+        meta.i64_field(6, *chunk_size); //total_uncompressed_size
+        meta.i64_field(7, *chunk_size); //total_compressed_size
+        meta.i64_field(9, *data_page_offset);
+        meta.struct_end();
+        meta.list_struct_end();
+    }
+    meta.i64_field(2, chunks.iter().map(|x| x.1).sum()); //total_byte_size
+    meta.i64_field(3, num_rows as i64);
+    meta.list_struct_end();
+    meta.binary_field(6, b"flare-profiler"); //created_by
+    meta.stop();
+
+    writer.write_all(&meta.buf)?;
+    writer.write_u32::<LittleEndian>(meta.buf.len() as u32)?;
+    writer.write_all(PARQUET_MAGIC)?;
+    Ok(())
+}
+
+//data page v1: definition levels (4字节长度 + RLE) + values
+fn encode_data_page(data: &ParquetColumnData) -> Vec<u8> {
+    let mut levels = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let level = if data.is_null(i) { 0u8 } else { 1u8 };
+        let mut run = 1;
+        while i + run < data.len() && data.is_null(i + run) == data.is_null(i) {
+            run += 1;
+        }
+        //RLE run: header = run_len << 1, bit width 1 的值占1个字节
+        write_varint(&mut levels, (run as u64) << 1);
+        levels.push(level);
+        i += run;
+    }
+    let mut page = vec![];
+    page.write_u32::<LittleEndian>(levels.len() as u32).unwrap();
+    page.extend_from_slice(&levels);
+    data.encode_values(&mut page);
+    page
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+//thrift compact protocol types
+const COMPACT_I32: u8 = 5;
+const COMPACT_I64: u8 = 6;
+const COMPACT_BINARY: u8 = 8;
+const COMPACT_LIST: u8 = 9;
+const COMPACT_STRUCT: u8 = 12;
+
+struct CompactWriter {
+    buf: Vec<u8>,
+    //每层struct上一个字段的id，字段id按增量编码
+    last_field_ids: Vec<i16>,
+}
+
+impl CompactWriter {
+    fn new() -> CompactWriter {
+        CompactWriter { buf: vec![], last_field_ids: vec![0] }
+    }
+
+    fn field_header(&mut self, id: i16, field_type: u8) {
+        let last_id = self.last_field_ids.last_mut().unwrap();
+        let delta = id - *last_id;
+        if delta > 0 && delta <= 15 {
+            self.buf.push(((delta as u8) << 4) | field_type);
+        } else {
+            self.buf.push(field_type);
+            write_varint(&mut self.buf, zigzag(id as i64));
+        }
+        *last_id = id;
+    }
+
+    fn i32_field(&mut self, id: i16, value: i32) {
+        self.field_header(id, COMPACT_I32);
+        self.write_i32(value);
+    }
+
+    fn i64_field(&mut self, id: i16, value: i64) {
+        self.field_header(id, COMPACT_I64);
+        write_varint(&mut self.buf, zigzag(value));
+    }
+
+    fn binary_field(&mut self, id: i16, value: &[u8]) {
+        self.field_header(id, COMPACT_BINARY);
+        self.write_binary(value);
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        write_varint(&mut self.buf, zigzag(value as i64));
+    }
+
+    fn write_binary(&mut self, value: &[u8]) {
+        write_varint(&mut self.buf, value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn struct_begin(&mut self, id: i16) {
+        self.field_header(id, COMPACT_STRUCT);
+        self.last_field_ids.push(0);
+    }
+
+    fn struct_end(&mut self) {
+        self.stop();
+        self.last_field_ids.pop();
+    }
+
+    fn list_begin(&mut self, id: i16, elem_type: u8, size: usize) {
+        self.field_header(id, COMPACT_LIST);
+        if size < 15 {
+            self.buf.push(((size as u8) << 4) | elem_type);
+        } else {
+            self.buf.push(0xf0 | elem_type);
+            write_varint(&mut self.buf, size as u64);
+        }
+    }
+
+    //list中的struct元素没有字段头
+    fn list_struct_begin(&mut self) {
+        self.last_field_ids.push(0);
+    }
+
+    fn list_struct_end(&mut self) {
+        self.struct_end();
+    }
+
+    fn stop(&mut self) {
+        self.buf.push(0);
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{ReadBytesExt, LittleEndian};
+
+    #[test]
+    fn test_write_parquet_layout() {
+        let columns = vec![
+            ParquetColumn { name: "time".to_string(), data: ParquetColumnData::Int64(vec![Some(1000), Some(1020), Some(1040)]) },
+            ParquetColumn { name: "value".to_string(), data: ParquetColumnData::Double(vec![Some(0.5), None, Some(1.5)]) },
+            ParquetColumn { name: "name".to_string(), data: ParquetColumnData::Utf8(vec![None, Some("a".to_string()), Some("线程".to_string())]) },
+        ];
+        let mut buf = vec![];
+        write_parquet(&mut buf, &columns).unwrap();
+        assert_eq!(&buf[0..4], PARQUET_MAGIC);
+        assert_eq!(&buf[buf.len() - 4..], PARQUET_MAGIC);
+        let meta_len = (&buf[buf.len() - 8..buf.len() - 4]).read_u32::<LittleEndian>().unwrap() as usize;
+        assert!(meta_len > 0 && meta_len < buf.len() - 12);
+
+        //definition levels: run of 1 x 0, run of 2 x 1
+        let page = encode_data_page(&columns[2].data);
+        assert_eq!(&page[0..8], &[4, 0, 0, 0, 2, 0, 4, 1]);
+        assert_eq!(&page[8..13], &[1, 0, 0, 0, b'a']);
+        assert_eq!(page.len(), 13 + 4 + "线程".len());
+
+        let mut columns = columns;
+        columns[0].data = ParquetColumnData::Int64(vec![Some(1)]);
+        assert!(write_parquet(&mut vec![], &columns).is_err());
+    }
+
+    #[test]
+    fn test_compact_protocol() {
+        let mut writer = CompactWriter::new();
+        writer.i32_field(1, -1);
+        writer.i64_field(20, 150);
+        writer.struct_begin(21);
+        writer.binary_field(1, b"ab");
+        writer.struct_end();
+        writer.stop();
+        assert_eq!(writer.buf, vec![0x15, 0x01, 0x06, 0x28, 0xac, 0x02, 0x1c, 0x18, 0x02, b'a', b'b', 0x00, 0x00]);
+    }
+}
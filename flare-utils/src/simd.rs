@@ -0,0 +1,198 @@
+//时序查询降采样的聚合计算：对连续的数值块求和及计数
+//  空值用单独的有效标记数组表示(1为有效，0为空值)，不使用Option，数据可以连续存放；
+//  不使用哨兵值，任意的i64/f64(包括 i64::MIN 及 NaN)都可以作为有效值参与计算
+//  x86_64 支持 AVX2 时每次处理4个值，其它平台及不支持时使用标量实现
+//  标量实现同样按4路累加，浮点数的求和顺序与SIMD一致，不同机器上的查询结果相同
+//  整数求和按补码回绕，不检查溢出
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const LANES: usize = 4;
+
+static SIMD_ENABLED: AtomicBool = AtomicBool::new(true);
+
+//关闭后使用标量实现，用于对比测试及基准测试
+pub fn set_simd_enabled(enabled: bool) {
+    SIMD_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_simd_available() -> bool {
+    if !SIMD_ENABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+//有效值的和及数量，valid与values的长度必须相同，元素只能是0或1
+pub fn sum_count_i64(values: &[i64], valid: &[u8]) -> (i64, usize) {
+    assert_eq!(values.len(), valid.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_simd_available() {
+            return unsafe { avx2::sum_count_i64(values, valid) };
+        }
+    }
+    scalar_sum_count_i64(values, valid)
+}
+
+pub fn sum_count_f64(values: &[f64], valid: &[u8]) -> (f64, usize) {
+    assert_eq!(values.len(), valid.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_simd_available() {
+            return unsafe { avx2::sum_count_f64(values, valid) };
+        }
+    }
+    scalar_sum_count_f64(values, valid)
+}
+
+fn scalar_sum_count_i64(values: &[i64], valid: &[u8]) -> (i64, usize) {
+    let mut sums = [0i64; LANES];
+    let mut count = 0;
+    let chunks = values.chunks_exact(LANES);
+    let rest = chunks.remainder();
+    for (chunk, flags) in chunks.zip(valid.chunks_exact(LANES)) {
+        for i in 0..LANES {
+            if flags[i] != 0 {
+                sums[i] = sums[i].wrapping_add(chunk[i]);
+                count += 1;
+            }
+        }
+    }
+    let mut sum = sums.iter().fold(0i64, |a, b| a.wrapping_add(*b));
+    for (x, _) in rest.iter().zip(&valid[values.len() - rest.len()..]).filter(|x| *x.1 != 0) {
+        sum = sum.wrapping_add(*x);
+        count += 1;
+    }
+    (sum, count)
+}
+
+fn scalar_sum_count_f64(values: &[f64], valid: &[u8]) -> (f64, usize) {
+    let mut sums = [0.0f64; LANES];
+    let mut count = 0;
+    let chunks = values.chunks_exact(LANES);
+    let rest = chunks.remainder();
+    for (chunk, flags) in chunks.zip(valid.chunks_exact(LANES)) {
+        for i in 0..LANES {
+            if flags[i] != 0 {
+                sums[i] += chunk[i];
+                count += 1;
+            }
+        }
+    }
+    let mut sum = (sums[0] + sums[1]) + (sums[2] + sums[3]);
+    for (x, _) in rest.iter().zip(&valid[values.len() - rest.len()..]).filter(|x| *x.1 != 0) {
+        sum += *x;
+        count += 1;
+    }
+    (sum, count)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+    use super::LANES;
+
+    //4个有效标记扩展为4个64位的掩码(有效为-1，空值为0)
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_mask(flags: &[u8]) -> __m256i {
+        let bytes = i32::from_le_bytes([flags[0], flags[1], flags[2], flags[3]]);
+        let ones = _mm256_cvtepu8_epi64(_mm_cvtsi32_si128(bytes));
+        _mm256_sub_epi64(_mm256_setzero_si256(), ones)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_count_i64(values: &[i64], valid: &[u8]) -> (i64, usize) {
+        let mut sums = _mm256_setzero_si256();
+        //有效值的掩码为-1，累减得到有效值数量
+        let mut counts = _mm256_setzero_si256();
+        let chunks = values.chunks_exact(LANES);
+        let rest = chunks.remainder();
+        for (chunk, flags) in chunks.zip(valid.chunks_exact(LANES)) {
+            let v = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            let mask = load_mask(flags);
+            sums = _mm256_add_epi64(sums, _mm256_and_si256(mask, v));
+            counts = _mm256_sub_epi64(counts, mask);
+        }
+        let mut sum = to_i64_lanes(sums).iter().fold(0i64, |a, b| a.wrapping_add(*b));
+        let mut count = to_i64_lanes(counts).iter().sum::<i64>() as usize;
+        for (x, _) in rest.iter().zip(&valid[values.len() - rest.len()..]).filter(|x| *x.1 != 0) {
+            sum = sum.wrapping_add(*x);
+            count += 1;
+        }
+        (sum, count)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_count_f64(values: &[f64], valid: &[u8]) -> (f64, usize) {
+        let mut sums = _mm256_setzero_pd();
+        let mut counts = _mm256_setzero_si256();
+        let chunks = values.chunks_exact(LANES);
+        let rest = chunks.remainder();
+        for (chunk, flags) in chunks.zip(valid.chunks_exact(LANES)) {
+            let v = _mm256_loadu_pd(chunk.as_ptr());
+            let mask = load_mask(flags);
+            sums = _mm256_add_pd(sums, _mm256_and_pd(_mm256_castsi256_pd(mask), v));
+            counts = _mm256_sub_epi64(counts, mask);
+        }
+        let mut lanes = [0.0f64; LANES];
+        _mm256_storeu_pd(lanes.as_mut_ptr(), sums);
+        let mut sum = (lanes[0] + lanes[1]) + (lanes[2] + lanes[3]);
+        let mut count = to_i64_lanes(counts).iter().sum::<i64>() as usize;
+        for (x, _) in rest.iter().zip(&valid[values.len() - rest.len()..]).filter(|x| *x.1 != 0) {
+            sum += *x;
+            count += 1;
+        }
+        (sum, count)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn to_i64_lanes(v: __m256i) -> [i64; LANES] {
+        let mut lanes = [0i64; LANES];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, v);
+        lanes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    //SIMD与标量实现的结果必须一致
+    #[test]
+    fn test_simd_matches_scalar() {
+        let mut rng = rand::thread_rng();
+        for len in 0..67 {
+            let valid: Vec<u8> = (0..len).map(|_| if rng.gen_range(0, 4) == 0 { 0 } else { 1 }).collect();
+            let ints: Vec<i64> = valid.iter().map(|x| if *x == 0 { 0 } else { rng.gen_range(-1_000_000, 1_000_000) }).collect();
+            let floats: Vec<f64> = ints.iter().map(|x| *x as f64 / 7.0).collect();
+            assert_eq!(sum_count_i64(&ints, &valid), scalar_sum_count_i64(&ints, &valid));
+            let (sum, count) = sum_count_f64(&floats, &valid);
+            let (scalar_sum, scalar_count) = scalar_sum_count_f64(&floats, &valid);
+            assert_eq!(count, scalar_count);
+            assert_eq!(sum.to_bits(), scalar_sum.to_bits());
+        }
+    }
+
+    //空值只由有效标记决定，i64::MIN 是有效值
+    #[test]
+    fn test_null_values() {
+        let ints = [0, 3, 0, -5, 7, 0, 0, i64::min_value(), 1];
+        let valid = [0, 1, 0, 1, 1, 0, 0, 1, 1];
+        assert_eq!(sum_count_i64(&ints, &valid), (6i64.wrapping_add(i64::min_value()), 5));
+        assert_eq!(sum_count_i64(&[i64::min_value(); 9], &[1; 9]).1, 9);
+        assert_eq!(sum_count_i64(&[5; 9], &[0; 9]), (0, 0));
+
+        let floats = [std::f64::NAN, 1.5, -2.5, 100.0, 4.0];
+        assert_eq!(sum_count_f64(&floats, &[0, 1, 1, 0, 1]), (3.0, 3));
+        assert_eq!(sum_count_f64(&floats, &[0; 5]), (0.0, 0));
+    }
+}
@@ -1,552 +1,1860 @@
-use std::fs::{File, OpenOptions};
-use std::io::{SeekFrom, ErrorKind, Seek, Write, Read, BufReader};
-use chrono::Local;
-use std::io::Error;
-use std::io;
-use byteorder::{WriteBytesExt, ReadBytesExt, NetworkEndian};
-use std::str::from_utf8;
-use num::FromPrimitive;
-use std::cmp::*;
-
-use super::FileEndian;
-use super::{ValueType, get_unit_len};
-use crate::file_utils::open_file;
-use std::collections::VecDeque;
-
-#[derive(Clone, PartialEq, Debug)]
-pub enum TSValue {
-    int16(i16),
-    int32(i32),
-    int64(i64)
-}
-
-#[derive(Clone, PartialEq, Debug)]
-pub enum TSRangeValue {
-    vec_int16(Vec<i16>),
-    vec_int32(Vec<i32>),
-    vec_int64(Vec<i64>),
-    vec_f32(Vec<f32>),
-}
-
-impl TSRangeValue {
-    pub fn as_int64(&self) -> Option<Vec<i64>> {
-        match self {
-            TSRangeValue::vec_int64(x) => Some(x.clone()),
-            _ => None
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct TSResult {
-    pub begin_time: i64,
-    pub end_time: i64,
-    pub unit_time: i32,
-    pub steps: i32,
-    pub total_cpu_time: i64,
-    pub data: TSRangeValue
-}
-
-#[derive( Debug )]
-pub struct TimeSeriesFile {
-    //file path
-    pub path: String,
-    //data segment start offset
-    pub data_offset: u64,
-
-    // value data type
-    pub value_type: ValueType,
-    // value unit len(bytes)
-    pub unit_len: i8,
-    //sample unit time ms
-    pub unit_time: i32,
-    // data start time ms
-    pub begin_time: i64,
-    // current time ms
-    pub end_time: i64,
-    // sample count
-    pub amount: i32,
-}
-
-pub struct TimeSeriesFileReader {
-    info: TimeSeriesFile,
-    inited: bool,
-
-}
-
-pub struct TimeSeriesFileWriter {
-    info: TimeSeriesFile,
-    inited: bool,
-    last_save_time: i64,
-    last_sample_time: i64,
-
-    //bulk value buffer
-    data_buffer: VecDeque<(i64, TSValue)>,
-    //last write bulk time
-    last_flush_data_time: i64,
-    //write interval
-    data_flush_interval_time: i64,
-    //cache value size limit
-    data_buffer_size_limit: usize,
-
-}
-
-pub trait TimeSeries {
-
-    fn get_header_info(&self) -> &TimeSeriesFile;
-
-    fn get_begin_time(&self) -> i64;
-
-    fn add_value(&mut self, time: i64, value: TSValue) -> Result<u32, Error>;
-
-    fn get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> TSResult;
-
-    fn time_to_step(&self, time: i64) -> u32 {
-        let info = self.get_header_info();
-        let mut steps = (time - info.begin_time) / info.unit_time as i64;
-        if steps < 0 {
-            steps = 0;
-        }
-        steps as u32
-    }
-}
-
-impl TimeSeriesFile {
-
-    fn new(value_type: ValueType, unit_time: i32, path: &str, file: File) -> TimeSeriesFile {
-        TimeSeriesFile{
-            path: path.to_string(),
-            data_offset: 0,
-            unit_time,
-            unit_len: get_unit_len(value_type),
-            value_type,
-            begin_time: 0,
-            end_time: 0,
-            amount: 0,
-        }
-    }
-
-    fn get_file(&self) -> Result<File, Error> {
-        Ok(open_file(&self.path, true)?)
-    }
-
-    pub fn get_range_value(&self, origin_start_time: i64, origin_end_time: i64, unit_time_ms: i32) -> TSResult {
-        let mut data_vec = vec![];
-        // self.begin_time <= start_time <= self.end_time
-        let mut start_time = max(self.begin_time, origin_start_time);
-        start_time = min(start_time, self.end_time);
-
-        // self.begin_time <= end_time <= self.end_time
-        let mut end_time = max(self.begin_time, origin_end_time);
-        end_time = min(end_time, self.end_time);
-
-        //convert time to steps
-        let step1 = (start_time - self.begin_time) / self.unit_time as i64;
-        let step2 = (end_time - self.begin_time) / self.unit_time as i64;
-
-        //convert steps to file offset
-        let offset1 = self.data_offset + self.unit_len as u64 * step1 as u64;
-        let offset2 = self.data_offset + self.unit_len as u64 * step2 as u64;
-
-        //read specify range data
-        match self.get_file() {
-            Ok(mut file) => {
-                file.seek(SeekFrom::Start(offset1));
-                //let mut stream = file.take(offset2 - offset1);
-                //stream.read_i16_into::<FileEndian>(data_vec.as_mut_slice());
-                let bytes = offset2 - offset1;
-                let mut buf_reader = BufReader::with_capacity(1024*100, file);
-                let steps = bytes / self.unit_len as u64;
-
-                match self.value_type {
-                    ValueType::UNKNOWN => {},
-                    ValueType::INT16 => {
-                        for i in 0..steps {
-                            data_vec.push(buf_reader.read_i16::<FileEndian>().unwrap() as i64);
-                        }
-                    },
-                    ValueType::UINT16 => {
-                        for i in 0..steps {
-                            data_vec.push(buf_reader.read_u16::<FileEndian>().unwrap() as i64);
-                        }
-                    },
-                    ValueType::INT32 => {
-                        for i in 0..steps {
-                            data_vec.push(buf_reader.read_i32::<FileEndian>().unwrap() as i64);
-                        }
-                    },
-                    ValueType::UINT32 => {
-                        for i in 0..steps {
-                            data_vec.push(buf_reader.read_u32::<FileEndian>().unwrap() as i64);
-                        }
-                    },
-                    ValueType::INT64 => {
-                        for i in 0..steps {
-                            data_vec.push(buf_reader.read_i64::<FileEndian>().unwrap() as i64);
-                        }
-                    },
-//            ValueType::FLOAT64 => {},
-                }
-            },
-            Err(e) => {
-                println!("open ts file failed, path: {}, error: {}", self.path, e);
-            }
-        }
-
-
-        //convert time unit, merge n source point to one new point
-        let merge_num = (unit_time_ms / self.unit_time) as usize;
-        let mut unit_time_ms = merge_num as i32 * self.unit_time;
-        if merge_num > 1 {
-            let mut new_data_vec = vec![];
-            let size = data_vec.len() / merge_num as usize;
-            //fill data
-            for i in 0..size {
-                new_data_vec.push(ts_sum_int64(&data_vec[i*merge_num..(i+1)*merge_num]));
-            }
-            let total_cpu_time = ts_sum_int64(new_data_vec.as_slice());
-            TSResult {
-                begin_time: start_time,
-                end_time,
-                total_cpu_time,
-                unit_time: unit_time_ms,
-                steps: new_data_vec.len() as i32,
-                data: TSRangeValue::vec_int64(new_data_vec)
-            }
-        }else {
-            let total_cpu_time = ts_sum_int64(data_vec.as_slice());
-            TSResult {
-                begin_time: start_time,
-                end_time,
-                total_cpu_time,
-                unit_time: unit_time_ms,
-                steps: data_vec.len() as i32,
-                data: TSRangeValue::vec_int64(data_vec)
-            }
-        }
-    }
-}
-
-//填充空的数据，使得返回的时序数据范围的一致的
-fn fill_null_data(mut data_vec: Vec<i64>, start_time: i64, end_time: i64, origin_start_time: i64, origin_end_time: i64, unit_time_ms: i32) -> Vec<i64> {
-    let fill_steps_before = (start_time - origin_start_time)/unit_time_ms as i64;
-    let fill_steps_after = (origin_end_time - end_time)/unit_time_ms as i64;
-    if fill_steps_before == 0 && fill_steps_after == 0 {
-        return data_vec;
-    }
-
-    let mut new_data_vec = Vec::with_capacity(data_vec.len()+(fill_steps_before+fill_steps_after) as usize);
-    for i in 0..fill_steps_before {
-        new_data_vec.push(0);
-    }
-
-    new_data_vec.append(&mut data_vec);
-
-    for i in 0..fill_steps_after {
-        new_data_vec.push(0);
-    }
-    new_data_vec
-}
-
-fn ts_avg_int16 (numbers: &[i16]) -> f32 {
-    let mut sum = 0;
-    numbers.iter().for_each(|x| sum += *x as i32);
-    sum as f32 / numbers.len() as f32
-}
-
-fn ts_sum_int16 (numbers: &[i16]) -> i64 {
-    let mut sum = 0;
-    numbers.iter().for_each(|x| sum += *x as i64);
-    sum
-}
-
-fn ts_sum_int64 (numbers: &[i64]) -> i64 {
-    let mut sum = 0;
-    numbers.iter().for_each(|x| sum += *x as i64);
-    sum
-}
-
-//fn average(numbers: &[i32]) -> f32 {
-//    numbers.iter().sum::<i32>() as f32 / numbers.len() as f32
-//}
-//
-//fn median(numbers: &mut [i32]) -> i32 {
-//    numbers.sort();
-//    let mid = numbers.len() / 2;
-//    numbers[mid]
-//}
-
-
-impl TimeSeriesFileReader {
-
-    pub fn new(path: &str) -> Result<TimeSeriesFileReader, Error> {
-        let mut path = path.to_string()+".fts";
-        //let now_time = Local::now().timestamp_millis();
-        match File::open(path.clone()) {
-            Ok(file) => {
-                let info = TimeSeriesFile::new(ValueType::UNKNOWN, 0, &path, file);
-                let mut reader = TimeSeriesFileReader {
-                    info: info,
-                    inited: false
-                };
-                reader.init();
-                Ok(reader)
-            },
-            Err(err) => Err(err)
-        }
-    }
-
-    fn init(&mut self) -> Result<bool, Error> {
-        if !self.inited {
-            let info = &mut self.info;
-
-            //read file header
-            let mut flag_buf = [0 as u8;4];
-            let file = &mut info.get_file()?;
-            file.seek(SeekFrom::Start(0));
-            //TS file header segment: TSHS (4 bytes)
-            file.read_exact(&mut flag_buf[..]);
-            let flag = std::str::from_utf8(&flag_buf[..]).unwrap();
-            if flag != "TSHS" {
-                println!("Invalid time series file, header segment flag not match");
-                return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid time series file (header segment)"));
-            }
-
-            //header len (2 bytes)
-            let header_len = file.read_u16::<FileEndian>().unwrap() as u64;
-            let header_offset = 4 + 2;
-
-            //header data (n bytes)
-            info.value_type = ValueType::from_i8(file.read_i8().unwrap()).unwrap();
-            info.unit_len = file.read_i8().unwrap();
-            info.unit_time = file.read_i32::<FileEndian>().unwrap();
-            info.begin_time = file.read_i64::<FileEndian>().unwrap();
-            info.end_time = file.read_i64::<FileEndian>().unwrap();
-            info.amount = file.read_i32::<FileEndian>().unwrap();
-
-            //data segment flag
-            file.seek(SeekFrom::Start(header_offset + header_len));
-            file.read_exact(&mut flag_buf[..]);
-            let flag = std::str::from_utf8(&flag_buf[..]).unwrap();
-            if flag != "TSDS" {
-                println!("Invalid time series file, data segment flag not match: {}", flag);
-                return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid time series file (data segment)"));
-            }
-
-            //save data segment start offset
-            info.data_offset = header_offset + header_len + 4;
-
-            self.inited = true;
-        }
-        Ok(true)
-    }
-
-}
-
-impl TimeSeries for TimeSeriesFileReader {
-
-    fn get_header_info(&self) -> &TimeSeriesFile {
-        &self.info
-    }
-
-    fn get_begin_time(&self) -> i64 {
-        self.info.begin_time
-    }
-
-    fn add_value(&mut self, time: i64, value: TSValue) -> Result<u32, Error> {
-        Err(Error::new(ErrorKind::PermissionDenied, "can not modify data file in reader mode"))
-    }
-
-    fn get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> TSResult {
-        self.info.get_range_value(start_time, end_time, unit_time_ms)
-    }
-}
-
-
-impl TimeSeriesFileWriter {
-
-    pub fn new(value_type: ValueType, unit_time: i32, begin_time: i64, path: &str) -> Result<TimeSeriesFileWriter, Error> {
-        let data_flush_interval_time = 1000;
-        let data_buffer_size_limit = 1000;
-
-        let mut path = path.to_string()+".fts";
-        let now_time = Local::now().timestamp_millis();
-        let file_rs = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(path.clone());
-        match file_rs {
-            Ok(file) => {
-                let info = TimeSeriesFile::new(value_type, unit_time, &path, file);
-                let mut writer = TimeSeriesFileWriter {
-                    info,
-                    inited: false,
-                    last_save_time: 0,
-                    last_sample_time: 0,
-                    data_buffer: VecDeque::with_capacity(data_buffer_size_limit+2),
-                    data_buffer_size_limit,
-                    last_flush_data_time: 0,
-                    data_flush_interval_time,
-                };
-                writer.init(begin_time);
-                Ok(writer)
-            },
-            Err(err) => Err(err)
-        }
-    }
-
-    fn init(&mut self, time: i64) -> Result<bool, Error> {
-        if !self.inited {
-            let info = &mut self.info;
-            info.begin_time = time;
-            info.end_time = time;
-
-            self.save_header_info();
-
-            self.inited = true;
-        }
-        Ok(true)
-    }
-
-    fn save_header_info(&mut self) {
-        let info = &mut self.info;
-        //encode header
-        let mut header_vec = vec![];
-        header_vec.write_i8(info.value_type as i8);
-        header_vec.write_i8(info.unit_len);
-        header_vec.write_i32::<FileEndian>(info.unit_time);
-        header_vec.write_i64::<FileEndian>(info.begin_time);
-        header_vec.write_i64::<FileEndian>(info.end_time);
-        header_vec.write_i32::<FileEndian>(info.amount);
-
-        //write file header
-        match info.get_file() {
-            Ok(mut file) => {
-                file.seek(SeekFrom::Start(0));
-
-                //TS file header segment: TSHS (4 bytes)
-                file.write_all(b"TSHS");
-
-                //header len (2 bytes)
-                file.write_u16::<FileEndian>(header_vec.len() as u16);
-
-                //header data (n bytes)
-                file.write_all(header_vec.as_slice());
-
-                //data segment flag: TSDS
-                file.write_all(b"TSDS");
-
-                //save data segment start offset
-                info.data_offset = file.seek(SeekFrom::Current(0)).unwrap();
-                //info.data_offset = file.stream_position().unwrap();
-
-                self.last_save_time = self.last_sample_time;
-            },
-            Err(e) =>{
-                println!("save ts file header_info failed, path: {}, error: {}", self.info.path, e);
-            }
-        }
-
-    }
-
-    pub fn flush(&mut self) -> Result<(), Error> {
-        let info = &mut self.info;
-        let file = &mut info.get_file()?;
-
-        while let Some((steps, value)) = self.data_buffer.pop_front() {
-            info.amount = min(info.amount+1, steps as i32 +1);
-            info.end_time = info.begin_time + steps * info.unit_time as i64;
-
-            match value {
-                TSValue::int16(val) => {
-                    if info.value_type != ValueType::INT16 {
-                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::INT16);
-                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
-                    }
-                    let offset = (steps * 2) as u64;
-                    file.seek(SeekFrom::Start(info.data_offset + offset));
-                    file.write_i16::<FileEndian>(val);
-                }
-                TSValue::int32(val) => {
-                    if info.value_type != ValueType::INT32 {
-                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::INT32);
-                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
-                    }
-                    let offset = (steps * 4) as u64;
-                    file.seek(SeekFrom::Start(info.data_offset + offset));
-                    file.write_i32::<FileEndian>(val);
-                }
-                TSValue::int64(val) => {
-                    if info.value_type != ValueType::INT64 {
-                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::INT64);
-                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
-                    }
-                    let offset = (steps * 8) as u64;
-                    file.seek(SeekFrom::Start(info.data_offset + offset));
-                    file.write_i64::<FileEndian>(val);
-                }
-                _ => {
-                    println!("unsupported value type: {:?}", info.value_type);
-                    return Err(io::Error::new(ErrorKind::InvalidInput, "unsupported value type"));
-                }
-            }
-        }
-
-        //save header info periodically
-//        self.last_sample_time = time;
-//        if time - self.last_save_time > 1000 {
-//        }
-        self.save_header_info();
-        let now_time = Local::now().timestamp_millis();
-        self.last_flush_data_time = now_time;
-
-        Ok(())
-    }
-
-}
-
-impl TimeSeries for TimeSeriesFileWriter {
-
-    fn get_header_info(&self) -> &TimeSeriesFile {
-        &self.info
-    }
-
-    fn get_begin_time(&self) -> i64 {
-        self.info.begin_time
-    }
-
-    fn add_value(&mut self, time: i64, value: TSValue) -> Result<u32, Error> {
-
-        let info = &mut self.info;
-        let mut steps = (time - info.begin_time) / info.unit_time as i64;
-        if steps < 0 {
-            steps = 0;
-        }
-        self.data_buffer.push_back((steps, value));
-
-        //flush
-        //TODO call get timestamp_millis() may be frequently, maybe too heavily?
-        let now_time = Local::now().timestamp_millis();
-        let interval = now_time - self.last_flush_data_time;
-        if interval > self.data_flush_interval_time || self.data_buffer.len() >= self.data_buffer_size_limit {
-            //println!("flushing ts file, data_buffer len:{}, write interval: {} ...", self.data_buffer.len(), interval);
-            self.flush();
-        }
-
-        Ok(steps as u32)
-    }
-
-    fn get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> TSResult {
-        self.info.get_range_value(start_time, end_time, unit_time_ms)
-    }
-}
-
-impl Drop for TimeSeriesFileWriter {
-
-    fn drop(&mut self) {
-        self.flush();
-        self.save_header_info();
-    }
-
-}
\ No newline at end of file
+use std::fs::{File, OpenOptions};
+use std::io::{SeekFrom, ErrorKind, Seek, Write, Read, BufReader};
+use chrono::Local;
+use std::io::Error;
+use std::io;
+use byteorder::{WriteBytesExt, ReadBytesExt, NetworkEndian};
+use std::str::from_utf8;
+use num::FromPrimitive;
+use std::cmp::*;
+
+use super::FileEndian;
+use super::{ValueType, get_unit_len};
+use crate::histogram::*;
+use crate::simd::*;
+use crate::file_utils::{open_file, path_with_suffix};
+use std::path::{Path, PathBuf};
+use std::collections::{VecDeque, BTreeMap};
+
+//unit_len saved in header by older versions
+const LEGACY_UNIT_LEN: i16 = 2;
+
+//v1: header没有版本号，unit_len固定为2
+pub const TS_FORMAT_VERSION_V1: i16 = 1;
+//v2: 文件头末尾增加版本号，unit_len按值类型计算
+pub const TS_FORMAT_VERSION_V2: i16 = 2;
+//v3: 增加delta类型，整数gauge合并时取平均值；旧版本的整数gauge按delta读取
+pub const TS_FORMAT_VERSION_V3: i16 = 3;
+pub const TS_FORMAT_VERSION: i16 = TS_FORMAT_VERSION_V3;
+//v1 header len: value_type + unit_len + unit_time + begin_time + end_time + amount
+const TS_HEADER_LEN_V1: u64 = 26;
+//v2 header len: v1 + version, 之后追加的字段是可选的，旧版本读取时忽略
+//optional: metric_kind (1 byte), labels (count + key/value, 每个字符串为 u16长度 + utf8)
+const TS_HEADER_LEN_V2: u64 = 28;
+const TS_HEADER_LEN_WITH_KIND: u64 = TS_HEADER_LEN_V2 + 1;
+//预先聚合的粗粒度时间层级(ms)，与原始文件放在一起: <path>.<unit_time>ms.fts
+pub const TS_TIER_UNIT_TIMES: [i32; 3] = [1_000, 10_000, 60_000];
+//一次查询返回的最大点数，避免查询范围过大时填充过多的空值
+const MAX_RANGE_STEPS: i64 = 1_000_000;
+
+enum_from_primitive! {
+    //gauge: 每个时间单位的瞬时值(如内存用量)，合并时取平均值
+    //counter: 单调递增的累计值(如JIT编译耗时、GC次数)，合并时取最后一个值，可以转换为增量或速率
+    //delta: 每个时间单位的增量(如线程CPU时间)，合并时累加，整数类型有预先聚合的时间层级
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum MetricKind {
+        GAUGE,
+        COUNTER,
+        DELTA,
+    }
+}
+
+impl MetricKind {
+    //未指定类型时的默认值: 整数为增量，其它为瞬时值
+    pub fn default_for(value_type: ValueType) -> MetricKind {
+        match value_type {
+            ValueType::INT16 | ValueType::UINT16 | ValueType::INT32 | ValueType::UINT32 | ValueType::INT64 => MetricKind::DELTA,
+            _ => MetricKind::GAUGE,
+        }
+    }
+}
+
+//range query transform
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TSTransform {
+    NONE,
+    //每个时间单位的增量
+    DELTA,
+    //每秒的增量
+    RATE,
+}
+
+impl TSTransform {
+    pub fn parse(value: &str) -> Option<TSTransform> {
+        match value {
+            "" | "none" => Some(TSTransform::NONE),
+            "delta" => Some(TSTransform::DELTA),
+            "rate" => Some(TSTransform::RATE),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TSValue {
+    int16(i16),
+    int32(i32),
+    int64(i64),
+    float32(f32),
+    float64(f64),
+    bool(bool),
+    histogram(Histogram),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TSRangeValue {
+    vec_int16(Vec<i16>),
+    vec_int32(Vec<i32>),
+    vec_int64(Vec<i64>),
+    vec_f32(Vec<f32>),
+    //None表示没有记录数据(超出记录范围或者中断)
+    vec_opt_int64(Vec<Option<i64>>),
+    //浮点数合并时取平均值，布尔值合并时为true的比例
+    vec_opt_f64(Vec<Option<f64>>),
+    //直方图合并时累加各个桶的计数
+    vec_histogram(Vec<Option<Histogram>>),
+}
+
+impl TSRangeValue {
+    //空值按0返回
+    pub fn as_int64(&self) -> Option<Vec<i64>> {
+        match self {
+            TSRangeValue::vec_int64(x) => Some(x.clone()),
+            TSRangeValue::vec_opt_int64(x) => Some(x.iter().map(|v| v.unwrap_or(0)).collect()),
+            _ => None
+        }
+    }
+
+    pub fn as_opt_int64(&self) -> Option<Vec<Option<i64>>> {
+        match self {
+            TSRangeValue::vec_int64(x) => Some(x.iter().map(|v| Some(*v)).collect()),
+            TSRangeValue::vec_opt_int64(x) => Some(x.clone()),
+            _ => None
+        }
+    }
+
+    pub fn as_histogram(&self) -> Option<&Vec<Option<Histogram>>> {
+        match self {
+            TSRangeValue::vec_histogram(x) => Some(x),
+            _ => None
+        }
+    }
+
+    pub fn as_opt_f64(&self) -> Option<Vec<Option<f64>>> {
+        match self {
+            TSRangeValue::vec_opt_f64(x) => Some(x.clone()),
+            TSRangeValue::vec_opt_int64(x) => Some(x.iter().map(|v| v.map(|v| v as f64)).collect()),
+            _ => None
+        }
+    }
+}
+
+//没有数据的时间段 [begin_time, end_time)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TSGap {
+    pub begin_time: i64,
+    pub end_time: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TSResult {
+    pub begin_time: i64,
+    pub end_time: i64,
+    pub unit_time: i32,
+    pub steps: i32,
+    pub total_cpu_time: i64,
+    pub data: TSRangeValue,
+    pub gaps: Vec<TSGap>,
+}
+
+#[derive( Debug )]
+pub struct TimeSeriesFile {
+    //file path
+    pub path: PathBuf,
+    //data segment start offset
+    pub data_offset: u64,
+
+    // value data type
+    pub value_type: ValueType,
+    // value unit len(bytes)
+    pub unit_len: i16,
+    //sample unit time ms
+    pub unit_time: i32,
+    // data start time ms
+    pub begin_time: i64,
+    // current time ms
+    pub end_time: i64,
+    // sample count, 文件头中为i32，超过 i32::MAX 时保持为 i32::MAX
+    //   只用于判断是否有数据，数据的步数及偏移量由 end_time 按64位计算，不受此值限制
+    pub amount: i32,
+    // file format version
+    pub version: i16,
+    // counter or gauge, 旧文件没有保存时为gauge
+    pub metric_kind: MetricKind,
+    // series labels, 如 thread_id, thread_name, unit
+    pub labels: BTreeMap<String, String>,
+}
+
+pub struct TimeSeriesFileReader {
+    info: TimeSeriesFile,
+    inited: bool,
+    //strict mode: verify header and data segment, return error on malformed file
+    strict: bool,
+    //downsampling tiers, sorted by unit_time
+    tiers: Vec<TimeSeriesFile>,
+}
+
+//聚合层级的写入状态，累计当前时间单位内的数值
+struct TSTierWriter {
+    writer: TimeSeriesFileWriter,
+    step: i64,
+    sum: Option<i64>,
+}
+
+pub struct TimeSeriesFileWriter {
+    info: TimeSeriesFile,
+    inited: bool,
+    last_save_time: i64,
+    last_sample_time: i64,
+
+    //bulk value buffer
+    data_buffer: VecDeque<(i64, TSValue)>,
+    //last written step, 跳过的步数写入空值
+    last_step: i64,
+    //last write bulk time
+    last_flush_data_time: i64,
+    //write interval
+    data_flush_interval_time: i64,
+    //cache value size limit
+    data_buffer_size_limit: usize,
+    //downsampling tiers
+    tiers: Vec<TSTierWriter>,
+}
+
+pub trait TimeSeries {
+
+    fn get_header_info(&self) -> &TimeSeriesFile;
+
+    fn get_begin_time(&self) -> i64;
+
+    fn add_value(&mut self, time: i64, value: TSValue) -> Result<u32, Error>;
+
+    fn get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> TSResult;
+
+    //counter转换为增量或速率，见 TimeSeriesFile::try_get_transformed_range_value
+    fn get_transformed_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32, transform: TSTransform) -> Result<TSResult, Error> {
+        self.get_header_info().try_get_transformed_range_value(start_time, end_time, unit_time_ms, transform)
+    }
+
+    //写入缓冲的数据，只读的时序文件不需要
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn time_to_step(&self, time: i64) -> u32 {
+        let info = self.get_header_info();
+        let mut steps = (time - info.begin_time) / info.unit_time as i64;
+        if steps < 0 {
+            steps = 0;
+        }
+        steps as u32
+    }
+}
+
+impl TimeSeriesFile {
+
+    fn new(value_type: ValueType, unit_time: i32, path: &Path, file: File) -> TimeSeriesFile {
+        TimeSeriesFile{
+            path: path.to_path_buf(),
+            data_offset: 0,
+            unit_time,
+            unit_len: get_unit_len(value_type),
+            value_type,
+            begin_time: 0,
+            end_time: 0,
+            amount: 0,
+            version: TS_FORMAT_VERSION,
+            metric_kind: MetricKind::default_for(value_type),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    fn get_file(&self) -> Result<File, Error> {
+        Ok(open_file(&self.path, true)?)
+    }
+
+    pub fn get_range_value(&self, origin_start_time: i64, origin_end_time: i64, unit_time_ms: i32) -> TSResult {
+        match self.read_range_value(origin_start_time, origin_end_time, unit_time_ms, false) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("read ts file failed, path: {}, error: {}", self.path.display(), e);
+                TSResult {
+                    begin_time: origin_start_time,
+                    end_time: origin_start_time,
+                    total_cpu_time: 0,
+                    unit_time: max(unit_time_ms, self.unit_time),
+                    steps: 0,
+                    data: TSRangeValue::vec_int64(vec![]),
+                    gaps: vec![],
+                }
+            }
+        }
+    }
+
+    //strict mode: return error if the data segment is truncated
+    pub fn try_get_range_value(&self, origin_start_time: i64, origin_end_time: i64, unit_time_ms: i32) -> Result<TSResult, Error> {
+        self.read_range_value(origin_start_time, origin_end_time, unit_time_ms, true)
+    }
+
+    //返回相邻时间单位之间的增量(DELTA)或每秒的增量(RATE)，第一个点的增量使用查询范围之前的一个点计算
+    //counter的值变小时认为发生了重置(如JVM重启)，增量为重置后的值
+    pub fn try_get_transformed_range_value(&self, origin_start_time: i64, origin_end_time: i64, unit_time_ms: i32, transform: TSTransform) -> Result<TSResult, Error> {
+        if transform == TSTransform::NONE {
+            return self.try_get_range_value(origin_start_time, origin_end_time, unit_time_ms);
+        }
+        let merged_unit_time = max(unit_time_ms / max(self.unit_time, 1), 1) as i64 * self.unit_time as i64;
+        let result = self.try_get_range_value(origin_start_time.saturating_sub(merged_unit_time), origin_end_time, unit_time_ms)?;
+        let reset = self.metric_kind == MetricKind::COUNTER;
+        let begin_time = result.begin_time + result.unit_time as i64;
+        let data = match (transform, &result.data) {
+            (TSTransform::DELTA, TSRangeValue::vec_opt_int64(values)) => TSRangeValue::vec_opt_int64(ts_delta(values, reset)),
+            (TSTransform::DELTA, TSRangeValue::vec_opt_f64(values)) => TSRangeValue::vec_opt_f64(ts_delta(values, reset)),
+            (_, data) => {
+                let values = data.as_opt_f64().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("unsupported transform {:?} of value type: {:?}", transform, self.value_type)))?;
+                let secs = result.unit_time as f64 / 1000.0;
+                TSRangeValue::vec_opt_f64(ts_delta(&values, reset).iter().map(|x| x.map(|v| v / secs)).collect())
+            }
+        };
+        let steps = max(result.steps - 1, 0);
+        let gaps = match &data {
+            TSRangeValue::vec_opt_int64(x) => find_gaps(x, begin_time, result.unit_time as i64),
+            TSRangeValue::vec_opt_f64(x) => find_gaps(x, begin_time, result.unit_time as i64),
+            _ => vec![],
+        };
+        Ok(TSResult {
+            begin_time,
+            end_time: result.end_time,
+            unit_time: result.unit_time,
+            steps,
+            total_cpu_time: 0,
+            data,
+            gaps,
+        })
+    }
+
+    fn read_range_value(&self, origin_start_time: i64, origin_end_time: i64, unit_time_ms: i32, strict: bool) -> Result<TSResult, Error> {
+        if self.unit_time <= 0 || self.end_time < self.begin_time {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("invalid ts file header, unit_time: {}, begin_time: {}, end_time: {}",
+                                                                     self.unit_time, self.begin_time, self.end_time)));
+        }
+        let unit_time = self.unit_time as i64;
+
+        //convert time to steps, 超出记录范围的部分返回空值；时间差溢出时返回错误，不能panic
+        let origin_end_time = max(origin_start_time, origin_end_time);
+        let out_of_range = || io::Error::new(ErrorKind::InvalidData, format!("time out of range, start_time: {}, end_time: {}, begin_time: {}, end_time: {}",
+                                                                            origin_start_time, origin_end_time, self.begin_time, self.end_time));
+        let step1 = origin_start_time.checked_sub(self.begin_time).ok_or_else(out_of_range)?.div_euclid(unit_time);
+        let step2 = min(origin_end_time.checked_sub(self.begin_time).ok_or_else(out_of_range)?.div_euclid(unit_time), step1.saturating_add(MAX_RANGE_STEPS));
+        let last_step = self.end_time.checked_sub(self.begin_time).ok_or_else(out_of_range)? / unit_time;
+        let read_step1 = min(max(step1, 0), step2);
+        let read_step2 = max(min(step2, last_step.saturating_add(1)), read_step1);
+
+        let merge_num = max(unit_time_ms / self.unit_time, 1) as usize;
+        let unit_time_ms = merge_num as i32 * self.unit_time;
+        let begin_time = step1.checked_mul(unit_time).and_then(|x| x.checked_add(self.begin_time)).ok_or_else(out_of_range)?;
+        let steps = (step2 - step1) as usize;
+
+        match self.value_type {
+            ValueType::FLOAT32 | ValueType::FLOAT64 if self.metric_kind == MetricKind::GAUGE || self.metric_kind == MetricKind::DELTA => {
+                let data_vec = self.read_gauge_f64_values(steps, step1, read_step1, read_step2, merge_num, self.metric_kind == MetricKind::GAUGE, strict)?;
+                Ok(TSResult {
+                    begin_time,
+                    end_time: origin_end_time,
+                    total_cpu_time: 0,
+                    unit_time: unit_time_ms,
+                    steps: data_vec.len() as i32,
+                    gaps: find_gaps(&data_vec, begin_time, unit_time_ms as i64),
+                    data: TSRangeValue::vec_opt_f64(data_vec)
+                })
+            }
+            ValueType::FLOAT32 | ValueType::FLOAT64 | ValueType::BOOL => {
+                let value_type = self.value_type;
+                let data_vec = self.read_values(steps, step1, read_step1, read_step2, strict, |reader| {
+                    match value_type {
+                        ValueType::FLOAT32 => reader.read_f32::<FileEndian>().map(|x| if x.is_nan() { None } else { Some(x as f64) }),
+                        ValueType::FLOAT64 => reader.read_f64::<FileEndian>().map(|x| if x.is_nan() { None } else { Some(x) }),
+                        _ => reader.read_u8().map(|x| if x > 1 { None } else { Some(x as f64) }),
+                    }
+                })?;
+                //merge n source point to one new point, 布尔值合并时为true的比例
+                let data_vec = match self.metric_kind {
+                    MetricKind::COUNTER => merge_values(data_vec, merge_num, ts_last_opt),
+                    MetricKind::GAUGE => merge_values(data_vec, merge_num, ts_avg_opt_f64),
+                    MetricKind::DELTA => merge_values(data_vec, merge_num, ts_sum_opt_f64),
+                };
+                Ok(TSResult {
+                    begin_time,
+                    end_time: origin_end_time,
+                    total_cpu_time: 0,
+                    unit_time: unit_time_ms,
+                    steps: data_vec.len() as i32,
+                    gaps: find_gaps(&data_vec, begin_time, unit_time_ms as i64),
+                    data: TSRangeValue::vec_opt_f64(data_vec)
+                })
+            }
+            ValueType::HISTOGRAM => {
+                let data_vec = self.read_values(steps, step1, read_step1, read_step2, strict, |reader| Histogram::read_from(reader))?;
+                //merge-on-read: 累加各个桶的计数
+                let data_vec = merge_values(data_vec, merge_num, merge_histograms);
+                Ok(TSResult {
+                    begin_time,
+                    end_time: origin_end_time,
+                    total_cpu_time: 0,
+                    unit_time: unit_time_ms,
+                    steps: data_vec.len() as i32,
+                    gaps: find_gaps(&data_vec, begin_time, unit_time_ms as i64),
+                    data: TSRangeValue::vec_histogram(data_vec)
+                })
+            }
+            _ if self.metric_kind == MetricKind::GAUGE || self.metric_kind == MetricKind::DELTA => {
+                let average = self.metric_kind == MetricKind::GAUGE;
+                let data_vec = self.read_gauge_i64_values(steps, step1, read_step1, read_step2, merge_num, average, strict)?;
+                //只有增量的合计值有意义
+                let total_cpu_time = if average { 0 } else { ts_sum_opt_int64(data_vec.as_slice()).unwrap_or(0) };
+                Ok(TSResult {
+                    begin_time,
+                    end_time: origin_end_time,
+                    total_cpu_time,
+                    unit_time: unit_time_ms,
+                    steps: data_vec.len() as i32,
+                    gaps: find_gaps(&data_vec, begin_time, unit_time_ms as i64),
+                    data: TSRangeValue::vec_opt_int64(data_vec)
+                })
+            }
+            _ => {
+                let value_type = self.value_type;
+                let data_vec = self.read_values(steps, step1, read_step1, read_step2, strict, |reader| {
+                    match value_type {
+                        ValueType::INT16 => reader.read_i16::<FileEndian>().map(|x| if x == i16::min_value() { None } else { Some(x as i64) }),
+                        ValueType::UINT16 => reader.read_u16::<FileEndian>().map(|x| if x == u16::max_value() { None } else { Some(x as i64) }),
+                        ValueType::INT32 => reader.read_i32::<FileEndian>().map(|x| if x == i32::min_value() { None } else { Some(x as i64) }),
+                        ValueType::UINT32 => reader.read_u32::<FileEndian>().map(|x| if x == u32::max_value() { None } else { Some(x as i64) }),
+                        ValueType::INT64 => reader.read_i64::<FileEndian>().map(|x| if x == i64::min_value() { None } else { Some(x) }),
+                        _ => Err(io::Error::new(ErrorKind::InvalidData, "unknown value type")),
+                    }
+                })?;
+                //counter: merge n source point to the last value
+                let data_vec = merge_values(data_vec, merge_num, ts_last_opt);
+                Ok(TSResult {
+                    begin_time,
+                    end_time: origin_end_time,
+                    total_cpu_time: 0,
+                    unit_time: unit_time_ms,
+                    steps: data_vec.len() as i32,
+                    gaps: find_gaps(&data_vec, begin_time, unit_time_ms as i64),
+                    data: TSRangeValue::vec_opt_int64(data_vec)
+                })
+            }
+        }
+    }
+
+    //读取 [step1, step1+steps) 的数据，只有 [read_step1, read_step2) 在记录范围内，其余为空值
+    fn read_values<T, F>(&self, steps: usize, step1: i64, read_step1: i64, read_step2: i64, strict: bool, read_value: F) -> Result<Vec<Option<T>>, Error>
+        where F: Fn(&mut BufReader<File>) -> io::Result<Option<T>> {
+        let mut data_vec = Vec::with_capacity(steps);
+        for _ in step1..read_step1 {
+            data_vec.push(None);
+        }
+
+        if read_step2 > read_step1 {
+            //convert steps to file offset
+            let unit_len = get_unit_len(self.value_type) as u64;
+            let offset1 = self.data_offset + unit_len * read_step1 as u64;
+
+            //read specify range data
+            let mut file = self.get_file()?;
+            file.seek(SeekFrom::Start(offset1))?;
+            let mut buf_reader = BufReader::with_capacity(1024*100, file);
+            for _ in read_step1..read_step2 {
+                match read_value(&mut buf_reader) {
+                    Ok(value) => data_vec.push(value),
+                    //数据未完整写入时，剩余部分作为空值返回
+                    Err(ref e) if e.kind() == ErrorKind::UnexpectedEof && !strict => break,
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+        while data_vec.len() < steps {
+            data_vec.push(None);
+        }
+        Ok(data_vec)
+    }
+
+    //整数gauge/delta的快速路径: 整块读取原始数据，解码为连续的i64及有效标记，按块求和(gauge再除以有效值的数量)，见 simd.rs
+    fn read_gauge_i64_values(&self, steps: usize, step1: i64, read_step1: i64, read_step2: i64, merge_num: usize, average: bool, strict: bool) -> Result<Vec<Option<i64>>, Error> {
+        let bytes = self.read_raw_values(read_step1, read_step2, strict)?;
+        let offset = (read_step1 - step1) as usize;
+        let unit_len = get_unit_len(self.value_type) as usize;
+        let decode = match self.value_type {
+            ValueType::INT16 => |x: &[u8]| { let v = i16::from_be_bytes([x[0], x[1]]); if v == i16::min_value() { None } else { Some(v as i64) } },
+            ValueType::UINT16 => |x: &[u8]| { let v = u16::from_be_bytes([x[0], x[1]]); if v == u16::max_value() { None } else { Some(v as i64) } },
+            ValueType::INT32 => |x: &[u8]| { let v = i32::from_be_bytes([x[0], x[1], x[2], x[3]]); if v == i32::min_value() { None } else { Some(v as i64) } },
+            ValueType::UINT32 => |x: &[u8]| { let v = u32::from_be_bytes([x[0], x[1], x[2], x[3]]); if v == u32::max_value() { None } else { Some(v as i64) } },
+            ValueType::INT64 => |x: &[u8]| { let v = i64::from_be_bytes([x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]]); if v == i64::min_value() { None } else { Some(v) } },
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "unknown value type")),
+        };
+        //不合并时直接解码为结果，不需要中间数组
+        if merge_num <= 1 {
+            return Ok(decode_opt_values(&bytes, unit_len, offset, steps, decode));
+        }
+        let mut values = vec![0i64; steps];
+        let mut valid = vec![0u8; steps];
+        decode_masked_values(&bytes, unit_len, &mut values[offset..], &mut valid[offset..], decode);
+        Ok(values.chunks_exact(merge_num).zip(valid.chunks_exact(merge_num)).map(|(x, flags)| match sum_count_i64(x, flags) {
+            (_, 0) => None,
+            (sum, count) if average => Some(sum / count as i64),
+            (sum, _) => Some(sum)
+        }).collect())
+    }
+
+    //浮点数gauge/delta的快速路径，按块取平均值(delta为合计值)
+    fn read_gauge_f64_values(&self, steps: usize, step1: i64, read_step1: i64, read_step2: i64, merge_num: usize, average: bool, strict: bool) -> Result<Vec<Option<f64>>, Error> {
+        let bytes = self.read_raw_values(read_step1, read_step2, strict)?;
+        let offset = (read_step1 - step1) as usize;
+        let unit_len = get_unit_len(self.value_type) as usize;
+        let decode = match self.value_type {
+            ValueType::FLOAT32 => |x: &[u8]| { let v = f32::from_bits(u32::from_be_bytes([x[0], x[1], x[2], x[3]])); if v.is_nan() { None } else { Some(v as f64) } },
+            _ => |x: &[u8]| { let v = f64::from_bits(u64::from_be_bytes([x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]])); if v.is_nan() { None } else { Some(v) } },
+        };
+        if merge_num <= 1 {
+            return Ok(decode_opt_values(&bytes, unit_len, offset, steps, decode));
+        }
+        let mut values = vec![0.0f64; steps];
+        let mut valid = vec![0u8; steps];
+        decode_masked_values(&bytes, unit_len, &mut values[offset..], &mut valid[offset..], decode);
+        Ok(values.chunks_exact(merge_num).zip(valid.chunks_exact(merge_num)).map(|(x, flags)| match sum_count_f64(x, flags) {
+            (_, 0) => None,
+            (sum, count) if average => Some(sum / count as f64),
+            (sum, _) => Some(sum)
+        }).collect())
+    }
+
+    //一次读取 [read_step1, read_step2) 的原始数据；数据未完整写入时(非strict)只返回完整的值
+    fn read_raw_values(&self, read_step1: i64, read_step2: i64, strict: bool) -> Result<Vec<u8>, Error> {
+        if read_step2 <= read_step1 {
+            return Ok(vec![]);
+        }
+        let unit_len = get_unit_len(self.value_type) as u64;
+        let len = unit_len * (read_step2 - read_step1) as u64;
+        let mut file = self.get_file()?;
+        file.seek(SeekFrom::Start(self.data_offset + unit_len * read_step1 as u64))?;
+        let mut bytes = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < len {
+            if strict {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"));
+            }
+            bytes.truncate(bytes.len() / unit_len as usize * unit_len as usize);
+        }
+        Ok(bytes)
+    }
+}
+
+//前 offset 个及原始数据之后的步长为空值
+fn decode_opt_values<T, F>(bytes: &[u8], unit_len: usize, offset: usize, steps: usize, decode: F) -> Vec<Option<T>>
+    where F: Fn(&[u8]) -> Option<T> {
+    let mut data_vec = Vec::with_capacity(steps);
+    data_vec.resize_with(offset, || None);
+    data_vec.extend(bytes.chunks_exact(unit_len).take(steps - offset).map(decode));
+    data_vec.resize_with(steps, || None);
+    data_vec
+}
+
+//空值不写入values，只把有效标记置为0
+fn decode_masked_values<T, F>(bytes: &[u8], unit_len: usize, values: &mut [T], valid: &mut [u8], decode: F)
+    where F: Fn(&[u8]) -> Option<T> {
+    for ((value, flag), x) in values.iter_mut().zip(valid.iter_mut()).zip(bytes.chunks_exact(unit_len)) {
+        if let Some(v) = decode(x) {
+            *value = v;
+            *flag = 1;
+        }
+    }
+}
+
+//相邻两个值的增量，返回的数量比输入少一个
+fn ts_delta<T>(values: &[Option<T>], reset: bool) -> Vec<Option<T>>
+    where T: Copy + PartialOrd + std::ops::Sub<Output = T> {
+    values.windows(2).map(|x| match (x[0], x[1]) {
+        (Some(prev), Some(value)) => if reset && value < prev { Some(value) } else { Some(value - prev) },
+        _ => None
+    }).collect()
+}
+
+fn merge_values<T, F>(data_vec: Vec<Option<T>>, merge_num: usize, merge: F) -> Vec<Option<T>>
+    where F: Fn(&[Option<T>]) -> Option<T> {
+    if merge_num <= 1 {
+        return data_vec;
+    }
+    let size = data_vec.len() / merge_num;
+    let mut new_data_vec = Vec::with_capacity(size);
+    for i in 0..size {
+        new_data_vec.push(merge(&data_vec[i*merge_num..(i+1)*merge_num]));
+    }
+    new_data_vec
+}
+
+//连续的空值合并为一个时间段
+fn find_gaps<T>(data_vec: &[Option<T>], begin_time: i64, unit_time: i64) -> Vec<TSGap> {
+    let mut gaps = vec![];
+    let mut gap_start = None;
+    for (i, value) in data_vec.iter().enumerate() {
+        match (value, gap_start) {
+            (None, None) => gap_start = Some(i),
+            (Some(_), Some(start)) => {
+                gaps.push(TSGap { begin_time: begin_time + start as i64 * unit_time, end_time: begin_time + i as i64 * unit_time });
+                gap_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = gap_start {
+        gaps.push(TSGap { begin_time: begin_time + start as i64 * unit_time, end_time: begin_time + data_vec.len() as i64 * unit_time });
+    }
+    gaps
+}
+
+fn ts_avg_int16 (numbers: &[i16]) -> f32 {
+    let mut sum = 0;
+    numbers.iter().for_each(|x| sum += *x as i32);
+    sum as f32 / numbers.len() as f32
+}
+
+fn ts_sum_int16 (numbers: &[i16]) -> i64 {
+    let mut sum = 0;
+    numbers.iter().for_each(|x| sum += *x as i64);
+    sum
+}
+
+fn ts_sum_int64 (numbers: &[i64]) -> i64 {
+    let mut sum = 0;
+    numbers.iter().for_each(|x| sum += *x as i64);
+    sum
+}
+
+//全部是空值时返回None
+//最后一个非空值
+fn ts_last_opt<T: Copy> (numbers: &[Option<T>]) -> Option<T> {
+    numbers.iter().rev().find_map(|x| *x)
+}
+
+fn ts_avg_opt_f64 (numbers: &[Option<f64>]) -> Option<f64> {
+    let mut sum = 0.0;
+    let mut count = 0;
+    numbers.iter().for_each(|x| if let Some(x) = x { sum += *x; count += 1; });
+    if count > 0 { Some(sum / count as f64) } else { None }
+}
+
+fn ts_sum_opt_f64 (numbers: &[Option<f64>]) -> Option<f64> {
+    let mut sum = None;
+    numbers.iter().for_each(|x| if let Some(x) = x { sum = Some(sum.unwrap_or(0.0) + *x) });
+    sum
+}
+
+//全部是空值时返回None
+fn ts_sum_opt_int64 (numbers: &[Option<i64>]) -> Option<i64> {
+    let mut sum = None;
+    numbers.iter().for_each(|x| if let Some(x) = x { sum = Some(sum.unwrap_or(0) + *x) });
+    sum
+}
+
+//fn average(numbers: &[i32]) -> f32 {
+//    numbers.iter().sum::<i32>() as f32 / numbers.len() as f32
+//}
+//
+//fn median(numbers: &mut [i32]) -> i32 {
+//    numbers.sort();
+//    let mid = numbers.len() / 2;
+//    numbers[mid]
+//}
+
+
+impl TimeSeriesFileReader {
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<TimeSeriesFileReader, Error> {
+        TimeSeriesFileReader::open(path.as_ref(), false)
+    }
+
+    //bounds-checked reader for untrusted files, e.g. fuzz targets
+    pub fn new_strict<P: AsRef<Path>>(path: P) -> Result<TimeSeriesFileReader, Error> {
+        TimeSeriesFileReader::open(path.as_ref(), true)
+    }
+
+    fn open(path: &Path, strict: bool) -> Result<TimeSeriesFileReader, Error> {
+        let mut reader = TimeSeriesFileReader::open_file(path, strict)?;
+        //聚合层级是可选的，旧文件没有聚合层级
+        for tier_unit_time in TS_TIER_UNIT_TIMES.iter() {
+            let tier_path = get_tier_path(path, *tier_unit_time);
+            if std::fs::metadata(path_with_suffix(&tier_path, ".fts")).is_err() {
+                continue;
+            }
+            match TimeSeriesFileReader::open_file(&tier_path, strict) {
+                Ok(tier_reader) => reader.tiers.push(tier_reader.info),
+                Err(e) => {
+                    println!("open ts tier file failed, path: {}, error: {}", tier_path.display(), e);
+                }
+            }
+        }
+        Ok(reader)
+    }
+
+    fn open_file(path: &Path, strict: bool) -> Result<TimeSeriesFileReader, Error> {
+        let path = path_with_suffix(path, ".fts");
+        //let now_time = Local::now().timestamp_millis();
+        match File::open(&path) {
+            Ok(file) => {
+                let info = TimeSeriesFile::new(ValueType::UNKNOWN, 0, &path, file);
+                let mut reader = TimeSeriesFileReader {
+                    info: info,
+                    inited: false,
+                    strict,
+                    tiers: vec![],
+                };
+                reader.init()?;
+                Ok(reader)
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn try_get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> Result<TSResult, Error> {
+        select_tier(&self.info, self.tiers.iter(), unit_time_ms).try_get_range_value(start_time, end_time, unit_time_ms)
+    }
+
+    pub fn try_get_transformed_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32, transform: TSTransform) -> Result<TSResult, Error> {
+        select_tier(&self.info, self.tiers.iter(), unit_time_ms).try_get_transformed_range_value(start_time, end_time, unit_time_ms, transform)
+    }
+
+    pub fn get_tier_unit_times(&self) -> Vec<i32> {
+        self.tiers.iter().map(|x| x.unit_time).collect()
+    }
+
+    fn init(&mut self) -> Result<bool, Error> {
+        if !self.inited {
+            let strict = self.strict;
+            let info = &mut self.info;
+
+            //read file header
+            let mut flag_buf = [0 as u8;4];
+            let file = &mut File::open(&info.path)?;
+            let file_len = file.seek(SeekFrom::End(0))?;
+            file.seek(SeekFrom::Start(0))?;
+            //TS file header segment: TSHS (4 bytes)
+            file.read_exact(&mut flag_buf[..])?;
+            if &flag_buf != b"TSHS" {
+                println!("Invalid time series file, header segment flag not match");
+                return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid time series file (header segment)"));
+            }
+
+            //header len (2 bytes)
+            let header_len = file.read_u16::<FileEndian>()? as u64;
+            let header_offset = 4 + 2;
+
+            //header data (n bytes)
+            info.value_type = super::parse_value_type(file.read_i8()?)?;
+            info.unit_len = file.read_i8()? as i16;
+            info.unit_time = file.read_i32::<FileEndian>()?;
+            info.begin_time = file.read_i64::<FileEndian>()?;
+            info.end_time = file.read_i64::<FileEndian>()?;
+            info.amount = file.read_i32::<FileEndian>()?;
+            info.version = if header_len > TS_HEADER_LEN_V1 {
+                file.read_i16::<FileEndian>()?
+            } else {
+                TS_FORMAT_VERSION_V1
+            };
+            if info.version < TS_FORMAT_VERSION_V1 || info.version > TS_FORMAT_VERSION {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("Unsupported time series file version: {}, max supported version: {}, please upgrade flare-profiler",
+                                                                         info.version, TS_FORMAT_VERSION)));
+            }
+            if header_len > TS_HEADER_LEN_V2 {
+                let kind = file.read_i8()?;
+                info.metric_kind = MetricKind::from_i8(kind)
+                    .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("invalid metric kind: {}", kind)))?;
+            }
+            //v3之前的整数gauge合并时累加，即增量
+            if info.version < TS_FORMAT_VERSION_V3 && info.metric_kind == MetricKind::GAUGE {
+                info.metric_kind = MetricKind::default_for(info.value_type);
+            }
+            if header_len > TS_HEADER_LEN_WITH_KIND {
+                //标签不能超出文件头范围
+                info.labels = read_labels(&mut Read::by_ref(file).take(header_len - TS_HEADER_LEN_WITH_KIND))?;
+            }
+
+            //data segment flag
+            file.seek(SeekFrom::Start(header_offset + header_len))?;
+            file.read_exact(&mut flag_buf[..])?;
+            if &flag_buf != b"TSDS" {
+                println!("Invalid time series file, data segment flag not match: {}", String::from_utf8_lossy(&flag_buf[..]));
+                return Err(io::Error::new(ErrorKind::InvalidInput, "Invalid time series file (data segment)"));
+            }
+
+            //save data segment start offset
+            info.data_offset = header_offset + header_len + 4;
+
+            //旧版本文件头中的unit_len固定为2，按值类型计算
+            let unit_len = get_unit_len(info.value_type);
+            if strict {
+                if info.unit_len != encode_unit_len(unit_len) && info.unit_len != LEGACY_UNIT_LEN {
+                    return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid time series file, unit_len {} not match value type {:?}", info.unit_len, info.value_type)));
+                }
+                if info.unit_time <= 0 || info.end_time < info.begin_time || info.amount < 0 {
+                    return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid time series file, unit_time: {}, begin_time: {}, end_time: {}, amount: {}",
+                                                                             info.unit_time, info.begin_time, info.end_time, info.amount)));
+                }
+                let steps = info.end_time.checked_sub(info.begin_time).map(|x| x / info.unit_time as i64).ok_or_else(||
+                    io::Error::new(ErrorKind::InvalidData, format!("Invalid time series file, time range overflow, begin_time: {}, end_time: {}", info.begin_time, info.end_time)))?;
+                let data_len = (steps as u64).saturating_mul(unit_len as u64);
+                if info.amount > 0 && info.data_offset.saturating_add(data_len) > file_len {
+                    return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid time series file, data segment is truncated, expect {} bytes but {}",
+                                                                             data_len, file_len - min(file_len, info.data_offset))));
+                }
+            }
+            info.unit_len = unit_len;
+
+            self.inited = true;
+        }
+        Ok(true)
+    }
+
+}
+
+impl TimeSeries for TimeSeriesFileReader {
+
+    fn get_header_info(&self) -> &TimeSeriesFile {
+        &self.info
+    }
+
+    fn get_begin_time(&self) -> i64 {
+        self.info.begin_time
+    }
+
+    fn add_value(&mut self, time: i64, value: TSValue) -> Result<u32, Error> {
+        Err(Error::new(ErrorKind::PermissionDenied, "can not modify data file in reader mode"))
+    }
+
+    fn get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> TSResult {
+        select_tier(&self.info, self.tiers.iter(), unit_time_ms).get_range_value(start_time, end_time, unit_time_ms)
+    }
+
+    fn get_transformed_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32, transform: TSTransform) -> Result<TSResult, Error> {
+        self.try_get_transformed_range_value(start_time, end_time, unit_time_ms, transform)
+    }
+}
+
+fn write_labels(buf: &mut Vec<u8>, labels: &BTreeMap<String, String>) {
+    buf.write_u16::<FileEndian>(labels.len() as u16);
+    for (key, value) in labels {
+        for str in &[key, value] {
+            buf.write_u16::<FileEndian>(str.len() as u16);
+            buf.extend_from_slice(str.as_bytes());
+        }
+    }
+}
+
+fn read_labels<R: Read>(reader: &mut R) -> Result<BTreeMap<String, String>, Error> {
+    let mut labels = BTreeMap::new();
+    let count = reader.read_u16::<FileEndian>()?;
+    for _ in 0..count {
+        let key = read_label_str(reader)?;
+        let value = read_label_str(reader)?;
+        labels.insert(key, value);
+    }
+    Ok(labels)
+}
+
+fn read_label_str<R: Read>(reader: &mut R) -> Result<String, Error> {
+    let len = reader.read_u16::<FileEndian>()? as usize;
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| io::Error::new(ErrorKind::InvalidData, "invalid time series file, label is not utf8"))
+}
+
+//文件头中的unit_len只有1个字节，超过127时写入0，读取时按值类型计算
+fn encode_unit_len(unit_len: i16) -> i16 {
+    if unit_len > i8::max_value() as i16 { 0 } else { unit_len }
+}
+
+//聚合层级保存合计值，只用于整数类型的delta
+fn get_tier_unit_times(info: &TimeSeriesFile) -> Vec<i32> {
+    match info.value_type {
+        ValueType::FLOAT32 | ValueType::FLOAT64 | ValueType::BOOL | ValueType::HISTOGRAM => return vec![],
+        _ => {}
+    }
+    if info.metric_kind != MetricKind::DELTA {
+        return vec![];
+    }
+    let unit_time = info.unit_time;
+    TS_TIER_UNIT_TIMES.iter().filter(|x| **x > unit_time && **x % unit_time == 0).cloned().collect()
+}
+
+fn get_tier_path(path: &Path, tier_unit_time: i32) -> PathBuf {
+    path_with_suffix(path, &format!(".{}ms", tier_unit_time))
+}
+
+//选择能整除查询单位时间的最粗粒度层级，查询的数据量与返回的点数成正比
+fn select_tier<'a, I>(info: &'a TimeSeriesFile, tiers: I, unit_time_ms: i32) -> &'a TimeSeriesFile
+    where I: Iterator<Item=&'a TimeSeriesFile> {
+    let mut selected = info;
+    for tier in tiers {
+        if tier.unit_time > selected.unit_time && tier.unit_time <= unit_time_ms && unit_time_ms % tier.unit_time == 0 {
+            selected = tier;
+        }
+    }
+    selected
+}
+
+//查询单位时间向上取整到聚合层级的整数倍，使宽范围的查询可以使用聚合层级
+pub fn align_unit_time_to_tier(unit_time_ms: i64) -> i64 {
+    let mut aligned = unit_time_ms;
+    for tier_unit_time in TS_TIER_UNIT_TIMES.iter() {
+        let tier_unit_time = *tier_unit_time as i64;
+        if unit_time_ms >= tier_unit_time {
+            aligned = (unit_time_ms + tier_unit_time - 1) / tier_unit_time * tier_unit_time;
+        }
+    }
+    aligned
+}
+
+fn get_ts_value(value: &TSValue) -> i64 {
+    match value {
+        TSValue::int16(v) => *v as i64,
+        TSValue::int32(v) => *v as i64,
+        TSValue::int64(v) => *v,
+        TSValue::float32(v) => *v as i64,
+        TSValue::float64(v) => *v as i64,
+        TSValue::bool(v) => *v as i64,
+        TSValue::histogram(v) => v.count() as i64,
+    }
+}
+
+
+impl TimeSeriesFileWriter {
+
+    pub fn new<P: AsRef<Path>>(value_type: ValueType, unit_time: i32, begin_time: i64, path: P) -> Result<TimeSeriesFileWriter, Error> {
+        TimeSeriesFileWriter::new_with_kind(value_type, MetricKind::default_for(value_type), unit_time, begin_time, path)
+    }
+
+    pub fn new_with_kind<P: AsRef<Path>>(value_type: ValueType, metric_kind: MetricKind, unit_time: i32, begin_time: i64, path: P) -> Result<TimeSeriesFileWriter, Error> {
+        let path = path.as_ref();
+        let mut writer = TimeSeriesFileWriter::new_file(value_type, unit_time, begin_time, path)?;
+        if metric_kind != writer.info.metric_kind {
+            writer.info.metric_kind = metric_kind;
+            writer.save_header_info();
+        }
+        for tier_unit_time in get_tier_unit_times(&writer.info) {
+            writer.tiers.push(TSTierWriter::new(path, tier_unit_time, begin_time)?);
+        }
+        Ok(writer)
+    }
+
+    //打开已存在的文件继续写入，如恢复profiler重启前的录制；旧版本文件先升级到当前格式
+    pub fn open_append<P: AsRef<Path>>(path: P) -> Result<TimeSeriesFileWriter, Error> {
+        let path = path.as_ref();
+        migrate_ts_file(path)?;
+        let reader = TimeSeriesFileReader::open_file(path, true)?;
+        let info = reader.info;
+        let last_step = if info.amount > 0 { (info.end_time - info.begin_time) / info.unit_time as i64 } else { -1 };
+        let mut writer = TimeSeriesFileWriter::with_info(info);
+        writer.inited = true;
+        writer.last_step = last_step;
+
+        let (begin_time, end_time, unit_time) = (writer.info.begin_time, writer.info.end_time, writer.info.unit_time);
+        for tier_unit_time in get_tier_unit_times(&writer.info) {
+            let tier_path = get_tier_path(path, tier_unit_time);
+            let tier = if std::fs::metadata(path_with_suffix(&tier_path, ".fts")).is_ok() {
+                TSTierWriter::open(&tier_path)?
+            } else {
+                //没有聚合层级的文件，使用已有的数据生成
+                let mut tier = TSTierWriter::new(path, tier_unit_time, begin_time)?;
+                tier.writer.set_labels(writer.info.labels.clone())?;
+                if last_step >= 0 {
+                    let result = writer.info.try_get_range_value(begin_time, end_time + unit_time as i64, unit_time)?;
+                    for (i, value) in result.data.as_opt_int64().unwrap_or(vec![]).iter().enumerate() {
+                        if let Some(value) = value {
+                            tier.add_value(result.begin_time + i as i64 * unit_time as i64, *value)?;
+                        }
+                    }
+                }
+                tier
+            };
+            writer.tiers.push(tier);
+        }
+        Ok(writer)
+    }
+
+    //设置序列的标签，文件头长度会变化，必须在写入数据之前设置
+    pub fn set_labels(&mut self, labels: BTreeMap<String, String>) -> Result<(), Error> {
+        if self.last_step >= 0 || !self.data_buffer.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "labels must be set before writing data"));
+        }
+        let mut buf = vec![];
+        write_labels(&mut buf, &labels);
+        if labels.len() > u16::max_value() as usize || buf.len() as u64 + TS_HEADER_LEN_WITH_KIND > u16::max_value() as u64
+            || labels.iter().any(|(k, v)| k.len() > u16::max_value() as usize || v.len() > u16::max_value() as usize) {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "labels are too large"));
+        }
+        for tier in self.tiers.iter_mut() {
+            tier.writer.set_labels(labels.clone())?;
+        }
+        self.info.labels = labels;
+        self.save_header_info();
+        Ok(())
+    }
+
+    //不生成聚合层级
+    fn new_file(value_type: ValueType, unit_time: i32, begin_time: i64, path: &Path) -> Result<TimeSeriesFileWriter, Error> {
+        let path = path_with_suffix(path, ".fts");
+        let now_time = Local::now().timestamp_millis();
+        let file_rs = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path.clone());
+        match file_rs {
+            Ok(file) => {
+                let info = TimeSeriesFile::new(value_type, unit_time, &path, file);
+                let mut writer = TimeSeriesFileWriter::with_info(info);
+                writer.init(begin_time);
+                Ok(writer)
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    fn with_info(info: TimeSeriesFile) -> TimeSeriesFileWriter {
+        let data_flush_interval_time = 1000;
+        let data_buffer_size_limit = 1000;
+        TimeSeriesFileWriter {
+            info,
+            inited: false,
+            last_save_time: 0,
+            last_sample_time: 0,
+            data_buffer: VecDeque::with_capacity(data_buffer_size_limit+2),
+            data_buffer_size_limit,
+            last_flush_data_time: 0,
+            last_step: -1,
+            data_flush_interval_time,
+            tiers: vec![],
+        }
+    }
+
+    fn init(&mut self, time: i64) -> Result<bool, Error> {
+        if !self.inited {
+            let info = &mut self.info;
+            info.begin_time = time;
+            info.end_time = time;
+
+            self.save_header_info();
+
+            self.inited = true;
+        }
+        Ok(true)
+    }
+
+    fn save_header_info(&mut self) {
+        let info = &mut self.info;
+        //encode header
+        let mut header_vec = vec![];
+        header_vec.write_i8(info.value_type as i8);
+        header_vec.write_i8(encode_unit_len(info.unit_len) as i8);
+        header_vec.write_i32::<FileEndian>(info.unit_time);
+        header_vec.write_i64::<FileEndian>(info.begin_time);
+        header_vec.write_i64::<FileEndian>(info.end_time);
+        header_vec.write_i32::<FileEndian>(info.amount);
+        header_vec.write_i16::<FileEndian>(TS_FORMAT_VERSION);
+        header_vec.write_i8(info.metric_kind as i8);
+        //没有标签时不写入
+        if !info.labels.is_empty() {
+            write_labels(&mut header_vec, &info.labels);
+        }
+
+        //write file header
+        match info.get_file() {
+            Ok(mut file) => {
+                file.seek(SeekFrom::Start(0));
+
+                //TS file header segment: TSHS (4 bytes)
+                file.write_all(b"TSHS");
+
+                //header len (2 bytes)
+                file.write_u16::<FileEndian>(header_vec.len() as u16);
+
+                //header data (n bytes)
+                file.write_all(header_vec.as_slice());
+
+                //data segment flag: TSDS
+                file.write_all(b"TSDS");
+
+                //save data segment start offset
+                info.data_offset = file.seek(SeekFrom::Current(0)).unwrap();
+                //info.data_offset = file.stream_position().unwrap();
+
+                self.last_save_time = self.last_sample_time;
+            },
+            Err(e) =>{
+                println!("save ts file header_info failed, path: {}, error: {}", self.info.path.display(), e);
+            }
+        }
+
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let info = &mut self.info;
+        let file = &mut info.get_file()?;
+
+        while let Some((steps, value)) = self.data_buffer.pop_front() {
+            //中间没有数据的步数写入空值，区分没有记录和数值为0
+            if steps > self.last_step + 1 {
+                write_null_values(file, info, self.last_step + 1, steps)?;
+            }
+            self.last_step = max(self.last_step, steps);
+            info.amount = min(info.amount.saturating_add(1), min(steps + 1, i32::max_value() as i64) as i32);
+            info.end_time = info.begin_time + steps * info.unit_time as i64;
+
+            match value {
+                TSValue::int16(val) => {
+                    if info.value_type != ValueType::INT16 {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::INT16);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    let offset = (steps * 2) as u64;
+                    file.seek(SeekFrom::Start(info.data_offset + offset));
+                    file.write_i16::<FileEndian>(val);
+                }
+                TSValue::int32(val) => {
+                    if info.value_type != ValueType::INT32 {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::INT32);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    let offset = (steps * 4) as u64;
+                    file.seek(SeekFrom::Start(info.data_offset + offset));
+                    file.write_i32::<FileEndian>(val);
+                }
+                TSValue::int64(val) => {
+                    if info.value_type != ValueType::INT64 {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::INT64);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    let offset = (steps * 8) as u64;
+                    file.seek(SeekFrom::Start(info.data_offset + offset));
+                    file.write_i64::<FileEndian>(val);
+                }
+                TSValue::float32(val) => {
+                    if info.value_type != ValueType::FLOAT32 {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::FLOAT32);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    let offset = (steps * 4) as u64;
+                    file.seek(SeekFrom::Start(info.data_offset + offset))?;
+                    file.write_f32::<FileEndian>(val)?;
+                }
+                TSValue::float64(val) => {
+                    if info.value_type != ValueType::FLOAT64 {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::FLOAT64);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    let offset = (steps * 8) as u64;
+                    file.seek(SeekFrom::Start(info.data_offset + offset))?;
+                    file.write_f64::<FileEndian>(val)?;
+                }
+                TSValue::bool(val) => {
+                    if info.value_type != ValueType::BOOL {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::BOOL);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    file.seek(SeekFrom::Start(info.data_offset + steps as u64))?;
+                    file.write_u8(val as u8)?;
+                }
+                TSValue::histogram(val) => {
+                    if info.value_type != ValueType::HISTOGRAM {
+                        println!("value type not match, expect {:?} but {:?}", info.value_type, ValueType::HISTOGRAM);
+                        return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+                    }
+                    let offset = steps as u64 * HISTOGRAM_LEN as u64;
+                    file.seek(SeekFrom::Start(info.data_offset + offset))?;
+                    let mut buf = Vec::with_capacity(HISTOGRAM_LEN);
+                    val.write_to(&mut buf)?;
+                    file.write_all(&buf)?;
+                }
+                #[allow(unreachable_patterns)]
+                _ => {
+                    println!("unsupported value type: {:?}", info.value_type);
+                    return Err(io::Error::new(ErrorKind::InvalidInput, "unsupported value type"));
+                }
+            }
+        }
+
+        //save header info periodically
+//        self.last_sample_time = time;
+//        if time - self.last_save_time > 1000 {
+//        }
+        self.save_header_info();
+        let now_time = Local::now().timestamp_millis();
+        self.last_flush_data_time = now_time;
+
+        //写入未结束的聚合值，后续数据到达时覆盖
+        for tier in self.tiers.iter_mut() {
+            if let Some(sum) = tier.sum {
+                let time = tier.writer.info.begin_time + tier.step * tier.writer.info.unit_time as i64;
+                tier.writer.add_value(time, TSValue::int64(sum))?;
+            }
+            tier.writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+}
+
+//写入 [start_step, end_step) 的空值，每种类型使用一个数据中不会出现的值表示空值
+fn write_null_values(file: &mut File, info: &TimeSeriesFile, start_step: i64, end_step: i64) -> Result<(), Error> {
+    let count = (end_step - start_step) as usize;
+    let mut buf = Vec::with_capacity(count * get_unit_len(info.value_type) as usize);
+    for _ in 0..count {
+        match info.value_type {
+            ValueType::INT16 => buf.write_i16::<FileEndian>(i16::min_value())?,
+            ValueType::UINT16 => buf.write_u16::<FileEndian>(u16::max_value())?,
+            ValueType::INT32 => buf.write_i32::<FileEndian>(i32::min_value())?,
+            ValueType::UINT32 => buf.write_u32::<FileEndian>(u32::max_value())?,
+            ValueType::INT64 => buf.write_i64::<FileEndian>(i64::min_value())?,
+            ValueType::FLOAT32 => buf.write_f32::<FileEndian>(std::f32::NAN)?,
+            ValueType::FLOAT64 => buf.write_f64::<FileEndian>(std::f64::NAN)?,
+            ValueType::BOOL => buf.write_u8(0xFF)?,
+            ValueType::HISTOGRAM => Histogram::write_null(&mut buf)?,
+            ValueType::UNKNOWN => return Err(io::Error::new(ErrorKind::InvalidInput, "unknown value type")),
+        }
+    }
+    file.seek(SeekFrom::Start(info.data_offset + start_step as u64 * get_unit_len(info.value_type) as u64))?;
+    file.write_all(&buf)
+}
+
+impl TSTierWriter {
+
+    fn new(path: &Path, tier_unit_time: i32, begin_time: i64) -> Result<TSTierWriter, Error> {
+        //对齐到层级的时间单位
+        let tier_begin_time = begin_time - begin_time.rem_euclid(tier_unit_time as i64);
+        let writer = TimeSeriesFileWriter::new_file(ValueType::INT64, tier_unit_time, tier_begin_time, &get_tier_path(path, tier_unit_time))?;
+        Ok(TSTierWriter {
+            writer,
+            step: -1,
+            sum: None,
+        })
+    }
+
+    //继续累计最后一个时间单位(可能未结束)的合计值
+    fn open(tier_path: &Path) -> Result<TSTierWriter, Error> {
+        let info = TimeSeriesFileReader::open_file(tier_path, true)?.info;
+        let mut tier = TSTierWriter {
+            writer: TimeSeriesFileWriter::with_info(info),
+            step: -1,
+            sum: None,
+        };
+        tier.writer.inited = true;
+        let info = &tier.writer.info;
+        if info.amount > 0 {
+            let last_step = (info.end_time - info.begin_time) / info.unit_time as i64;
+            let result = info.try_get_range_value(info.end_time, info.end_time + info.unit_time as i64, info.unit_time)?;
+            tier.step = last_step;
+            tier.sum = result.data.as_opt_int64().and_then(|x| x.get(0).cloned()).unwrap_or(None);
+            tier.writer.last_step = last_step;
+        }
+        Ok(tier)
+    }
+
+    //累计到聚合层级，进入新的时间单位时写入上一个时间单位的合计值
+    fn add_value(&mut self, time: i64, value: i64) -> Result<(), Error> {
+        let tier_info = &self.writer.info;
+        let tier_step = max((time - tier_info.begin_time) / tier_info.unit_time as i64, 0);
+        if tier_step != self.step {
+            if let Some(sum) = self.sum {
+                let tier_time = tier_info.begin_time + self.step * tier_info.unit_time as i64;
+                self.writer.add_value(tier_time, TSValue::int64(sum))?;
+            }
+            self.step = tier_step;
+            self.sum = None;
+        }
+        self.sum = Some(self.sum.unwrap_or(0) + value);
+        Ok(())
+    }
+}
+
+impl TimeSeries for TimeSeriesFileWriter {
+
+    fn get_header_info(&self) -> &TimeSeriesFile {
+        &self.info
+    }
+
+    fn get_begin_time(&self) -> i64 {
+        self.info.begin_time
+    }
+
+    fn add_value(&mut self, time: i64, value: TSValue) -> Result<u32, Error> {
+
+        let info = &mut self.info;
+        let mut steps = (time - info.begin_time) / info.unit_time as i64;
+        if steps < 0 {
+            steps = 0;
+        }
+
+        let tier_value = get_ts_value(&value);
+        for tier in self.tiers.iter_mut() {
+            tier.add_value(time, tier_value)?;
+        }
+        self.data_buffer.push_back((steps, value));
+
+        //flush
+        //TODO call get timestamp_millis() may be frequently, maybe too heavily?
+        let now_time = Local::now().timestamp_millis();
+        let interval = now_time - self.last_flush_data_time;
+        if interval > self.data_flush_interval_time || self.data_buffer.len() >= self.data_buffer_size_limit {
+            //println!("flushing ts file, data_buffer len:{}, write interval: {} ...", self.data_buffer.len(), interval);
+            self.flush();
+        }
+
+        Ok(steps as u32)
+    }
+
+    fn get_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32) -> TSResult {
+        select_tier(&self.info, self.tiers.iter().map(|x| &x.writer.info), unit_time_ms).get_range_value(start_time, end_time, unit_time_ms)
+    }
+
+    fn get_transformed_range_value(&self, start_time: i64, end_time: i64, unit_time_ms: i32, transform: TSTransform) -> Result<TSResult, Error> {
+        select_tier(&self.info, self.tiers.iter().map(|x| &x.writer.info), unit_time_ms).try_get_transformed_range_value(start_time, end_time, unit_time_ms, transform)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        TimeSeriesFileWriter::flush(self)
+    }
+}
+
+impl Drop for TimeSeriesFileWriter {
+
+    fn drop(&mut self) {
+        self.flush();
+        self.save_header_info();
+    }
+
+}
+
+//升级旧版本的时序文件到当前格式，返回是否做了升级
+pub fn migrate_ts_file<P: AsRef<Path>>(path: P) -> Result<bool, Error> {
+    let path = path.as_ref();
+    let reader = TimeSeriesFileReader::new(path)?;
+    let info = reader.get_header_info();
+    if info.version == TS_FORMAT_VERSION {
+        return Ok(false);
+    }
+
+    //先写入临时文件，完成后再替换原文件
+    let file_path = info.path.clone();
+    let tmp_path = path_with_suffix(path, ".migrating");
+    let _ = std::fs::remove_file(path_with_suffix(&tmp_path, ".fts"));
+    {
+        let mut source = File::open(&file_path)?;
+        let file_len = source.seek(SeekFrom::End(0))?;
+        source.seek(SeekFrom::Start(info.data_offset))?;
+        let mut data = Vec::with_capacity((file_len - min(file_len, info.data_offset)) as usize);
+        source.read_to_end(&mut data)?;
+
+        let mut writer = TimeSeriesFileWriter::new_file(info.value_type, info.unit_time, info.begin_time, &tmp_path)?;
+        writer.info.begin_time = info.begin_time;
+        writer.info.end_time = info.end_time;
+        writer.info.amount = info.amount;
+        writer.info.metric_kind = info.metric_kind;
+        writer.info.labels = info.labels.clone();
+        writer.save_header_info();
+        let mut file = writer.info.get_file()?;
+        file.seek(SeekFrom::Start(writer.info.data_offset))?;
+        file.write_all(&data)?;
+    }
+    std::fs::rename(path_with_suffix(&tmp_path, ".fts"), &file_path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn test_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join("flare-utils-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/{}_{}", dir.to_str().unwrap(), name, std::process::id());
+        let _ = fs::remove_file(format!("{}.fts", path));
+        path
+    }
+
+    #[test]
+    fn test_ts_file_is_big_endian() {
+        let path = test_path("ts_endian");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::INT32, 20, 1_000, &path).unwrap();
+            writer.add_value(1_000, TSValue::int32(0x01020304)).unwrap();
+            writer.add_value(1_020, TSValue::int32(-2)).unwrap();
+        }
+        let bytes = fs::read(format!("{}.fts", path)).unwrap();
+        assert_eq!(&bytes[0..4], b"TSHS");
+        //header len
+        assert_eq!(&bytes[4..6], &[0, 29]);
+        assert_eq!(bytes[6], ValueType::INT32 as u8);
+        //unit_time, begin_time, end_time, amount
+        assert_eq!(&bytes[8..12], &20i32.to_be_bytes());
+        assert_eq!(&bytes[12..20], &1_000i64.to_be_bytes());
+        assert_eq!(&bytes[20..28], &1_020i64.to_be_bytes());
+        assert_eq!(&bytes[28..32], &2i32.to_be_bytes());
+        assert_eq!(&bytes[32..34], &TS_FORMAT_VERSION.to_be_bytes());
+        assert_eq!(bytes[34], MetricKind::DELTA as u8);
+        assert_eq!(&bytes[35..39], b"TSDS");
+        //data segment is packed, no alignment padding
+        assert_eq!(&bytes[39..43], &[1, 2, 3, 4]);
+        assert_eq!(&bytes[43..47], &[0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(bytes.len(), 47);
+    }
+
+    #[test]
+    fn test_ts_file_round_trip() {
+        let cases = vec![
+            (ValueType::INT16, (0..100).map(|x| TSValue::int16((x - 50) * 600)).collect::<Vec<_>>()),
+            (ValueType::INT32, (0..100).map(|x| TSValue::int32((x - 50) * 40_000_000)).collect()),
+            (ValueType::INT64, (0..100).map(|x| TSValue::int64((x as i64 - 50) * 90_000_000_000_000)).collect()),
+        ];
+        for (value_type, values) in cases {
+            let path = test_path(&format!("ts_round_trip_{:?}", value_type));
+            {
+                let mut writer = TimeSeriesFileWriter::new(value_type, 20, 1_000, &path).unwrap();
+                for (i, value) in values.iter().enumerate() {
+                    writer.add_value(1_000 + i as i64 * 20, value.clone()).unwrap();
+                }
+            }
+            let expected: Vec<i64> = values.iter().map(get_ts_value).collect();
+
+            let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+            let info = reader.get_header_info();
+            assert_eq!(info.value_type, value_type);
+            assert_eq!(info.amount, values.len() as i32);
+            let result = reader.try_get_range_value(info.begin_time, info.end_time, 20).unwrap();
+            let data = result.data.as_int64().unwrap();
+            assert!(!data.is_empty());
+            assert_eq!(data.as_slice(), &expected[..data.len()]);
+        }
+    }
+
+    #[test]
+    fn test_migrate_v1_ts_file() {
+        let path = test_path("ts_migrate");
+        //v1 header: no version, unit_len is always 2
+        let mut bytes = vec![];
+        bytes.extend_from_slice(b"TSHS");
+        bytes.write_u16::<FileEndian>(26).unwrap();
+        bytes.write_i8(ValueType::INT32 as i8).unwrap();
+        bytes.write_i8(LEGACY_UNIT_LEN as i8).unwrap();
+        bytes.write_i32::<FileEndian>(20).unwrap();
+        bytes.write_i64::<FileEndian>(1_000).unwrap();
+        bytes.write_i64::<FileEndian>(1_060).unwrap();
+        bytes.write_i32::<FileEndian>(4).unwrap();
+        bytes.extend_from_slice(b"TSDS");
+        for value in &[7, 8, 9, 10] {
+            bytes.write_i32::<FileEndian>(*value).unwrap();
+        }
+        fs::write(format!("{}.fts", path), &bytes).unwrap();
+
+        assert_eq!(TimeSeriesFileReader::new(&path).unwrap().get_header_info().version, TS_FORMAT_VERSION_V1);
+        assert!(migrate_ts_file(&path).unwrap());
+        assert!(!migrate_ts_file(&path).unwrap());
+
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        let info = reader.get_header_info();
+        assert_eq!(info.version, TS_FORMAT_VERSION);
+        assert_eq!((info.begin_time, info.end_time, info.amount), (1_000, 1_060, 4));
+        let result = reader.try_get_range_value(1_000, 1_060, 20).unwrap();
+        assert_eq!(result.data.as_int64().unwrap(), vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn test_reject_newer_ts_file_version() {
+        let path = test_path("ts_newer_version");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::INT32, 20, 1_000, &path).unwrap();
+            writer.add_value(1_000, TSValue::int32(1)).unwrap();
+        }
+        let mut bytes = fs::read(format!("{}.fts", path)).unwrap();
+        bytes[32..34].copy_from_slice(&(TS_FORMAT_VERSION + 1).to_be_bytes());
+        fs::write(format!("{}.fts", path), &bytes).unwrap();
+        assert!(TimeSeriesFileReader::new(&path).is_err());
+    }
+
+    #[test]
+    fn test_range_value_with_gaps() {
+        let path = test_path("ts_gaps");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::INT32, 20, 1_000, &path).unwrap();
+            for &(step, value) in &[(0, 5), (1, 0), (4, 7), (5, 8)] {
+                writer.add_value(1_000 + step * 20, TSValue::int32(value)).unwrap();
+            }
+        }
+        let reader = TimeSeriesFileReader::new(&path).unwrap();
+        //两个步长在记录范围之前，两个步长在记录范围之后
+        let result = reader.try_get_range_value(960, 1_160, 20).unwrap();
+        assert_eq!(result.begin_time, 960);
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![None, None, Some(5), Some(0), None, None, Some(7), Some(8), None, None]);
+        assert_eq!(result.total_cpu_time, 20);
+        assert_eq!(result.gaps, vec![TSGap { begin_time: 960, end_time: 1_000 }, TSGap { begin_time: 1_040, end_time: 1_080 }, TSGap { begin_time: 1_120, end_time: 1_160 }]);
+
+        //合并后全部为空值的点仍然是空值
+        let result = reader.try_get_range_value(960, 1_160, 40).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![None, Some(5), None, Some(15), None]);
+    }
+
+    #[test]
+    fn test_query_downsampling_tiers() {
+        let path = test_path("ts_tiers");
+        for tier_unit_time in TS_TIER_UNIT_TIMES.iter() {
+            let _ = fs::remove_file(path_with_suffix(get_tier_path(Path::new(&path), *tier_unit_time), ".fts"));
+        }
+        //2分钟，每20ms一个点，中间有10秒的中断; 层级按整分钟对齐，开始时间对齐时结果与原始数据聚合一致
+        let begin_time = 1_570_000_020_000;
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::INT32, 20, begin_time, &path).unwrap();
+            for i in 0..6000i64 {
+                if i >= 2000 && i < 2500 {
+                    continue;
+                }
+                writer.add_value(begin_time + i * 20, TSValue::int32((i % 7) as i32)).unwrap();
+            }
+        }
+        let reader = TimeSeriesFileReader::new(&path).unwrap();
+        assert_eq!(reader.get_tier_unit_times(), vec![1_000, 10_000, 60_000]);
+        let raw = reader.get_header_info();
+        let end_time = begin_time + 120_000;
+        for unit_time in &[1_000, 10_000, 60_000, 30_000] {
+            let result = reader.try_get_range_value(begin_time, end_time, *unit_time).unwrap();
+            let expected = raw.try_get_range_value(begin_time, end_time, *unit_time).unwrap();
+            assert_eq!(result.unit_time, *unit_time);
+            assert_eq!(result.data.as_opt_int64(), expected.data.as_opt_int64(), "unit_time: {}", unit_time);
+            assert_eq!(result.gaps, expected.gaps);
+        }
+    }
+
+    #[test]
+    fn test_append_after_reopen() {
+        let path = test_path("ts_append");
+        for tier_unit_time in TS_TIER_UNIT_TIMES.iter() {
+            let _ = fs::remove_file(path_with_suffix(get_tier_path(Path::new(&path), *tier_unit_time), ".fts"));
+        }
+        let begin_time = 1_570_000_020_000;
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::INT32, 20, begin_time, &path).unwrap();
+            for i in 0..1010i64 {
+                writer.add_value(begin_time + i * 20, TSValue::int32(1)).unwrap();
+            }
+        }
+        {
+            //中断后继续写入
+            let mut writer = TimeSeriesFileWriter::open_append(&path).unwrap();
+            assert_eq!(writer.get_begin_time(), begin_time);
+            for i in 1100..2000i64 {
+                writer.add_value(begin_time + i * 20, TSValue::int32(2)).unwrap();
+            }
+        }
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        let info = reader.get_header_info();
+        assert_eq!(info.end_time, begin_time + 1999 * 20);
+        assert_eq!(info.amount, 1910);
+
+        let data = info.try_get_range_value(begin_time, begin_time + 40_000, 20).unwrap().data.as_opt_int64().unwrap();
+        assert_eq!(data.len(), 2000);
+        assert_eq!(data[1009], Some(1));
+        assert!(data[1010..1100].iter().all(|x| x.is_none()));
+        assert_eq!(data[1100], Some(2));
+
+        //最后一个未结束的时间单位在重新打开后继续累计
+        let result = reader.try_get_range_value(begin_time, begin_time + 40_000, 1_000).unwrap();
+        let data = result.data.as_opt_int64().unwrap();
+        assert_eq!(data[20], Some(10));
+        assert_eq!(data[21], None);
+        assert_eq!(data[22], Some(100));
+        assert_eq!(data[39], Some(100));
+    }
+
+    #[test]
+    fn test_float_and_bool_series() {
+        let path = test_path("ts_float");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::FLOAT64, 20, 1_000, &path).unwrap();
+            for (i, value) in [0.25, 0.75, 1.5, -2.5].iter().enumerate() {
+                writer.add_value(1_000 + i as i64 * 20, TSValue::float64(*value)).unwrap();
+            }
+            writer.add_value(1_120, TSValue::float64(0.5)).unwrap();
+        }
+        let bytes = fs::read(format!("{}.fts", path)).unwrap();
+        assert_eq!(&bytes[39..47], &0.25f64.to_be_bytes());
+        let reader = TimeSeriesFileReader::new(&path).unwrap();
+        let result = reader.try_get_range_value(1_000, 1_140, 20).unwrap();
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![Some(0.25), Some(0.75), Some(1.5), Some(-2.5), None, None, Some(0.5)]);
+        assert_eq!(result.gaps, vec![TSGap { begin_time: 1_080, end_time: 1_120 }]);
+        //浮点数合并时取平均值，忽略空值
+        let result = reader.try_get_range_value(1_000, 1_160, 40).unwrap();
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![Some(0.5), Some(-0.5), None, Some(0.5)]);
+
+        let path = test_path("ts_float32");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::FLOAT32, 20, 1_000, &path).unwrap();
+            writer.add_value(1_000, TSValue::float32(0.125)).unwrap();
+            writer.add_value(1_020, TSValue::float32(0.375)).unwrap();
+            writer.add_value(1_040, TSValue::float32(1.0)).unwrap();
+        }
+        let result = TimeSeriesFileReader::new_strict(&path).unwrap().try_get_range_value(1_000, 1_040, 40).unwrap();
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![Some(0.25)]);
+
+        let path = test_path("ts_bool");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::BOOL, 20, 1_000, &path).unwrap();
+            for (i, value) in [true, false, true, true].iter().enumerate() {
+                writer.add_value(1_000 + i as i64 * 20, TSValue::bool(*value)).unwrap();
+            }
+            writer.add_value(1_120, TSValue::bool(false)).unwrap();
+        }
+        let bytes = fs::read(format!("{}.fts", path)).unwrap();
+        assert_eq!(&bytes[39..46], &[1, 0, 1, 1, 0xFF, 0xFF, 0]);
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        let result = reader.try_get_range_value(1_000, 1_140, 20).unwrap();
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![Some(1.0), Some(0.0), Some(1.0), Some(1.0), None, None, Some(0.0)]);
+        //布尔值合并后为true的比例
+        let result = reader.try_get_range_value(1_000, 1_080, 80).unwrap();
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![Some(0.75)]);
+    }
+
+    #[test]
+    fn test_histogram_series() {
+        let path = test_path("ts_histogram");
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::HISTOGRAM, 1000, 10_000, &path).unwrap();
+            for i in 0..4 {
+                let mut histogram = Histogram::new();
+                histogram.record_n(100 * (i + 1), 10);
+                writer.add_value(10_000 + i as i64 * 1000, TSValue::histogram(histogram)).unwrap();
+            }
+            let mut histogram = Histogram::new();
+            histogram.record(5000);
+            writer.add_value(16_000, TSValue::histogram(histogram)).unwrap();
+        }
+        let bytes = fs::read(format!("{}.fts", path)).unwrap();
+        assert_eq!(bytes.len(), 39 + HISTOGRAM_LEN * 7);
+        //unit_len超过1个字节，文件头中写入0
+        assert_eq!(bytes[7], 0);
+
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        let result = reader.try_get_range_value(10_000, 17_000, 1000).unwrap();
+        let values = result.data.as_histogram().unwrap();
+        assert_eq!(values.len(), 7);
+        assert_eq!(values[0].as_ref().unwrap().count(), 10);
+        assert_eq!(values[4], None);
+        assert_eq!(result.gaps, vec![TSGap { begin_time: 14_000, end_time: 16_000 }]);
+
+        //merge-on-read
+        let result = reader.try_get_range_value(10_000, 18_000, 4000).unwrap();
+        let values = result.data.as_histogram().unwrap();
+        assert_eq!(values.len(), 2);
+        let merged = values[0].as_ref().unwrap();
+        assert_eq!(merged.count(), 40);
+        assert_eq!(merged.min(), get_bucket_lower_bound(get_bucket_index(100)));
+        assert_eq!(merged.value_at_percentile(100.0), get_bucket_upper_bound(get_bucket_index(400)));
+        assert_eq!(values[1].as_ref().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_counter_rate_and_delta() {
+        let path = test_path("ts_counter");
+        {
+            let mut writer = TimeSeriesFileWriter::new_with_kind(ValueType::INT64, MetricKind::COUNTER, 500, 10_000, &path).unwrap();
+            //累计值，15_000ms 时计数器重置
+            let values = [0, 100, 300, 600, 1000, 1500, 2100, 2800, 3600, 4500, 50, 150];
+            for (i, value) in values.iter().enumerate() {
+                writer.add_value(10_000 + i as i64 * 500, TSValue::int64(*value)).unwrap();
+            }
+        }
+        //counter没有聚合层级
+        assert!(fs::metadata(format!("{}.1000ms.fts", path)).is_err());
+
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().metric_kind, MetricKind::COUNTER);
+        //合并时取最后一个值
+        let result = reader.try_get_range_value(10_000, 16_000, 1000).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(100), Some(600), Some(1500), Some(2800), Some(4500), Some(150)]);
+
+        let result = reader.try_get_transformed_range_value(11_000, 16_000, 1000, TSTransform::DELTA).unwrap();
+        assert_eq!(result.begin_time, 11_000);
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(500), Some(900), Some(1300), Some(1700), Some(150)]);
+
+        let result = reader.try_get_transformed_range_value(10_000, 12_000, 500, TSTransform::RATE).unwrap();
+        //第一个点在记录之前，没有增量
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![None, Some(200.0), Some(400.0), Some(600.0)]);
+        assert_eq!(result.gaps, vec![TSGap { begin_time: 10_000, end_time: 10_500 }]);
+
+        //gauge不做重置处理
+        let path = test_path("ts_gauge_delta");
+        {
+            let mut writer = TimeSeriesFileWriter::new_with_kind(ValueType::INT32, MetricKind::GAUGE, 1000, 10_000, &path).unwrap();
+            for (i, value) in [5, 3, 8].iter().enumerate() {
+                writer.add_value(10_000 + i as i64 * 1000, TSValue::int32(*value)).unwrap();
+            }
+        }
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().metric_kind, MetricKind::GAUGE);
+        let result = reader.try_get_transformed_range_value(11_000, 13_000, 1000, TSTransform::DELTA).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(-2), Some(5)]);
+    }
+
+    #[test]
+    fn test_gauge_merge_average() {
+        let path = test_path("ts_gauge_average");
+        {
+            let mut writer = TimeSeriesFileWriter::new_with_kind(ValueType::INT64, MetricKind::GAUGE, 500, 10_000, &path).unwrap();
+            for (i, value) in [100, 300, 600, 0].iter().enumerate() {
+                writer.add_value(10_000 + i as i64 * 500, TSValue::int64(*value)).unwrap();
+            }
+            writer.add_value(12_500, TSValue::int64(50)).unwrap();
+        }
+        //gauge的合计值没有意义，没有聚合层级
+        assert!(fs::metadata(format!("{}.1000ms.fts", path)).is_err());
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().metric_kind, MetricKind::GAUGE);
+        let result = reader.try_get_range_value(10_000, 13_000, 1000).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(200), Some(300), Some(50)]);
+        assert_eq!(result.total_cpu_time, 0);
+
+        //v2文件中的整数gauge按增量读取，合并时累加
+        let mut bytes = fs::read(format!("{}.fts", path)).unwrap();
+        bytes[32..34].copy_from_slice(&TS_FORMAT_VERSION_V2.to_be_bytes());
+        fs::write(format!("{}.fts", path), &bytes).unwrap();
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().metric_kind, MetricKind::DELTA);
+        let result = reader.try_get_range_value(10_000, 13_000, 1000).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(400), Some(600), Some(50)]);
+        assert!(migrate_ts_file(&path).unwrap());
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().version, TS_FORMAT_VERSION);
+        assert_eq!(reader.get_header_info().metric_kind, MetricKind::DELTA);
+
+        let path = test_path("ts_float_delta");
+        {
+            let mut writer = TimeSeriesFileWriter::new_with_kind(ValueType::FLOAT64, MetricKind::DELTA, 500, 10_000, &path).unwrap();
+            for (i, value) in [0.5, 1.5, 2.0].iter().enumerate() {
+                writer.add_value(10_000 + i as i64 * 500, TSValue::float64(*value)).unwrap();
+            }
+        }
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        let result = reader.try_get_range_value(10_000, 12_000, 1000).unwrap();
+        assert_eq!(result.data.as_opt_f64().unwrap(), vec![Some(2.0), Some(2.0)]);
+    }
+
+    #[test]
+    fn test_series_labels() {
+        let path = test_path("ts_labels");
+        let mut labels = BTreeMap::new();
+        labels.insert("thread_id".to_string(), "100".to_string());
+        labels.insert("thread_name".to_string(), "工作线程-1".to_string());
+        labels.insert("unit".to_string(), "us".to_string());
+        {
+            let mut writer = TimeSeriesFileWriter::new(ValueType::INT32, 20, 1_000, &path).unwrap();
+            writer.set_labels(labels.clone()).unwrap();
+            writer.add_value(1_000, TSValue::int32(1)).unwrap();
+            writer.add_value(1_020, TSValue::int32(2)).unwrap();
+            writer.flush().unwrap();
+            //写入数据后不能再修改标签
+            assert!(writer.set_labels(BTreeMap::new()).is_err());
+        }
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().labels, labels);
+        let result = reader.try_get_range_value(1_000, 1_040, 20).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(1), Some(2)]);
+
+        //续写时保留标签
+        {
+            let mut writer = TimeSeriesFileWriter::open_append(&path).unwrap();
+            writer.add_value(1_040, TSValue::int32(3)).unwrap();
+        }
+        let reader = TimeSeriesFileReader::new_strict(&path).unwrap();
+        assert_eq!(reader.get_header_info().labels, labels);
+        let result = reader.try_get_range_value(1_000, 1_060, 20).unwrap();
+        assert_eq!(result.data.as_opt_int64().unwrap(), vec![Some(1), Some(2), Some(3)]);
+
+        //截断的标签
+        let mut bytes = fs::read(format!("{}.fts", path)).unwrap();
+        bytes[36] = 0xff;
+        fs::write(format!("{}.fts", path), &bytes).unwrap();
+        assert!(TimeSeriesFileReader::new_strict(&path).is_err());
+    }
+
+    //整块读取及SIMD合并的结果与逐个读取的Option合并一致，包括未完整写入的文件
+    #[test]
+    fn test_gauge_block_merge_matches_scalar() {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        for &value_type in &[ValueType::INT16, ValueType::INT32, ValueType::INT64, ValueType::FLOAT32, ValueType::FLOAT64] {
+            let path = test_path(&format!("ts_block_merge_{:?}", value_type));
+            let mut expected = vec![];
+            {
+                let mut writer = TimeSeriesFileWriter::new(value_type, 10, 1_000, &path).unwrap();
+                let mut step = 0;
+                while expected.len() < 500 {
+                    //随机跳过一些步长，中间为空值
+                    if rng.gen_range(0, 5) == 0 {
+                        step += rng.gen_range(1, 20);
+                        while expected.len() < step as usize {
+                            expected.push(None);
+                        }
+                    }
+                    let n = rng.gen_range(-1000, 1000) as i64;
+                    let value = match value_type {
+                        ValueType::INT16 => TSValue::int16(n as i16),
+                        ValueType::INT32 => TSValue::int32(n as i32),
+                        ValueType::INT64 => TSValue::int64(n * 1_000_000_000),
+                        ValueType::FLOAT32 => TSValue::float32(n as f32 / 4.0),
+                        _ => TSValue::float64(n as f64 / 4.0),
+                    };
+                    writer.add_value(1_000 + step * 10, value).unwrap();
+                    expected.push(Some(n));
+                    step += 1;
+                }
+            }
+            //截掉最后半个值，模拟写入中断
+            let file_path = format!("{}.fts", path);
+            let len = fs::metadata(&file_path).unwrap().len();
+            let file = OpenOptions::new().write(true).open(&file_path).unwrap();
+            file.set_len(len - 1).unwrap();
+            let last = expected.len() - 1;
+            expected[last] = None;
+
+            let reader = TimeSeriesFileReader::new(&path).unwrap();
+            for &merge_num in &[1usize, 3, 4, 7, 64] {
+                assert!(reader.try_get_range_value(950, 1_000 + 520 * 10, merge_num as i32 * 10).is_err());
+                let result = reader.get_range_value(950, 1_000 + 520 * 10, merge_num as i32 * 10);
+                let mut source = vec![None; 5];
+                source.extend(expected.iter().cloned());
+                source.resize(5 + 520, None);
+                match value_type {
+                    ValueType::FLOAT32 | ValueType::FLOAT64 => {
+                        let source: Vec<Option<f64>> = source.iter().map(|x| x.map(|v| match value_type {
+                            ValueType::FLOAT32 => (v as f32 / 4.0) as f64,
+                            _ => v as f64 / 4.0,
+                        })).collect();
+                        let merged = merge_values(source, merge_num, ts_avg_opt_f64);
+                        let data = result.data.as_opt_f64().unwrap();
+                        assert_eq!(data.len(), merged.len());
+                        for (x, y) in data.iter().zip(merged.iter()) {
+                            assert_eq!(x.is_some(), y.is_some());
+                            assert!((x.unwrap_or(0.0) - y.unwrap_or(0.0)).abs() < 1e-9);
+                        }
+                    }
+                    _ => {
+                        let scale = if value_type == ValueType::INT64 { 1_000_000_000 } else { 1 };
+                        let source: Vec<Option<i64>> = source.iter().map(|x| x.map(|v| v * scale)).collect();
+                        let merged = merge_values(source, merge_num, ts_sum_opt_int64);
+                        assert_eq!(result.data.as_opt_int64().unwrap(), merged);
+                        assert_eq!(result.total_cpu_time, ts_sum_opt_int64(&merged).unwrap_or(0));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,560 +1,1014 @@
-// indexed file
-
-use std::fs::{File, OpenOptions};
-use std::io::{SeekFrom, ErrorKind, Seek, Write, Read, BufReader};
-use chrono::Local;
-use std::io::Error;
-use std::io;
-use byteorder::{WriteBytesExt, ReadBytesExt, NetworkEndian};
-use std::str::from_utf8;
-use num::{FromPrimitive, PrimInt};
-use std::collections::{HashMap, VecDeque};
-use std::cmp::*;
-
-use super::FileEndian;
-use super::file_utils::*;
-use super::collections::*;
-use crate::collections::MapUtil::*;
-use super::{ValueType, get_unit_len};
-
-//bulk data handler
-type BulkDataConsumer = fn(Vec<u8>);
-//type BulkDataConsumer = fn(&[u8]);
-
-//Tuple-Indexed file Header Segment: TIHS (4 bytes)
-static TUPLE_INDEXED_HEADER_SEGMENT_FLAG: &str = "TIHS";
-//Tuple-Indexed Data Segment flag: TIDS
-static TUPLE_INDEXED_DATA_SEGMENT_FLAG: &str = "TIDS";
-
-//Tuple-Extra file Header Segment: TEHS (4 bytes)
-static TUPLE_EXTRA_HEADER_SEGMENT_FLAG: &str = "TEHS";
-//Tuple-Extra Data Segment flag: TEDS
-static TUPLE_EXTRA_DATA_SEGMENT_FLAG: &str = "TEDS";
-
-
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
-pub enum TupleValue {
-    int16(i16),
-    uint16(u16),
-    int32(i32),
-    uint32(u32),
-    int64(i64),
-//    float64(f64)
-}
-
-impl TupleValue {
-    pub fn as_int(&self) -> i64 {
-        match self {
-            TupleValue::int16(x) => *x as i64,
-            TupleValue::uint16(x) => *x as i64,
-            TupleValue::int32(x) => *x as i64,
-            TupleValue::uint32(x) => *x as i64,
-            TupleValue::int64(x) => *x,
-        }
-    }
-}
-
-impl std::cmp::Ord for TupleValue {
-
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.as_int().cmp(&other.as_int())
-    }
-}
-
-impl PartialOrd for TupleValue {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.as_int().partial_cmp(&other.as_int())
-    }
-}
-
-fn get_value_type(value: &TupleValue) -> ValueType {
-    match value {
-        TupleValue::int16(_) => ValueType::INT16,
-        TupleValue::uint16(_) => ValueType::UINT16,
-        TupleValue::int32(_) => ValueType::INT32,
-        TupleValue::uint32(_) => ValueType::UINT32,
-        TupleValue::int64(_) => ValueType::INT64,
-//        TupleValue::float64(_) => ValueType::FLOAT64,
-        _ => ValueType::UNKNOWN
-    }
-}
-
-///
-/// save tuple values in indexed file
-#[derive( Debug )]
-pub struct TupleIndexedFile {
-    //state
-    inited: bool,
-    writable: bool,
-    index_map: HashMap<TupleValue, TupleValue>,
-    index_vec: Vec<TupleValue>,
-
-    //indexed file
-    pub indexed_path: String,
-    //data segment start offset
-    pub indexed_data_offset: u64,
-
-    //extra data file
-    extra_path: String,
-    extra_data_offset: u64,
-
-    //bulk value buffer
-    bulk_buffer: VecDeque<(TupleValue, Vec<u8>)>,
-    //last write bulk time
-    last_flush_bulk_time: i64,
-    //write interval
-    bulk_flush_interval_time: i64,
-    //cache bytes
-    bulk_buffer_bytes: usize,
-    //buffer bytes limit
-    bulk_buffer_bytes_limit: usize,
-
-    //header info
-    // index value type
-    pub index_type: ValueType,
-    // bulk data type
-    pub bulk_offset_type: ValueType,
-    // tuple unit len(bytes)
-    pub unit_len: i8,
-    // data start time ms
-    pub begin_time: i64,
-    // current time ms
-    pub end_time: i64,
-    // sample count
-    pub amount: i32,
-}
-
-//init methods
-impl TupleIndexedFile {
-
-    pub fn new_reader(path: &str) -> Result<TupleIndexedFile, io::Error> {
-        let mut tuple_file = TupleIndexedFile::new(path,
-                                                   ValueType::UNKNOWN,
-                                                   ValueType::UNKNOWN,
-                                                   false)?;
-        tuple_file.init_reader()?;
-        Ok(tuple_file)
-    }
-
-    pub fn new_writer(path: &str, index_type: ValueType) -> Result<TupleIndexedFile, io::Error> {
-
-        let mut tuple_file = TupleIndexedFile::new(path,
-                                                   index_type,
-                                                   ValueType::UINT32,
-                                                   true)?;
-        tuple_file.init_writer()?;
-        Ok(tuple_file)
-    }
-
-    fn new(path: &str, index_type: ValueType, bulk_offset_type: ValueType, writable: bool) -> Result<TupleIndexedFile, io::Error> {
-        let indexed_path = path.to_string() + ".fidx";
-        let extra_path = path.to_string() + ".fdata";
-        let unit_len = get_unit_len(index_type) + get_unit_len(bulk_offset_type);
-        let bulk_write_interval_time = 1000;
-        let bulk_buffer_bytes_limit = 100*1024;
-        Ok(TupleIndexedFile {
-            inited: false,
-            writable,
-            index_map: HashMap::new(),
-            index_vec: vec![],
-            indexed_path,
-            indexed_data_offset: 0,
-            extra_path,
-            extra_data_offset: 0,
-            bulk_buffer: VecDeque::with_capacity(256),
-            last_flush_bulk_time: 0,
-            bulk_flush_interval_time: bulk_write_interval_time,
-            bulk_buffer_bytes: 0,
-            bulk_buffer_bytes_limit,
-            index_type,
-            bulk_offset_type,
-            unit_len,
-            begin_time: 0,
-            end_time: 0,
-            amount: 0
-        })
-    }
-
-    fn init_reader(&mut self) -> Result<(), Error> {
-        if !self.inited {
-            self.load_indexed_header_info()?;
-            self.load_extra_header_info()?;
-            self.load_index_map()?;
-            self.inited = true;
-        }
-        Ok(())
-    }
-
-    fn init_writer(&mut self) -> Result<(), Error> {
-        if !self.inited {
-
-            //读取已经存在的文件头信息
-            let mut load = false;
-            let mut indexed_file = self.get_indexed_file()?;
-            if let Ok(len) = indexed_file.seek(SeekFrom::End(0)) {
-                if len > 0 {
-                    if let Err(e) = self.init_reader() {
-                        //load file failed
-                        return Err(e);
-                    }else {
-                        load = true;
-                    }
-                }
-            }
-
-            //文件不存在或者文件大小为0
-            if !load {
-                let now_time = Local::now().timestamp_millis();
-                self.begin_time = now_time;
-                self.end_time = now_time;
-                self.save_indexed_header_info()?;
-                self.save_extra_header_info()?;
-            }
-
-            self.inited = true;
-        }
-        Ok(())
-    }
-
-    fn get_indexed_file(&self) -> Result<File, Error> {
-        Ok(open_file(&self.indexed_path, self.writable)?)
-    }
-
-    fn get_extra_file(&self) -> Result<File, Error> {
-        Ok(open_file(&self.extra_path, self.writable)?)
-    }
-
-    pub fn save_indexed_header_info(&mut self) -> Result<(), Error> {
-
-        let mut header_map = HashMap::new();
-        header_map.insert("desc", "flare profiler indexed file".to_string());
-        header_map.insert("first_el_type", (self.index_type as i8).to_string());
-        header_map.insert("second_el_type", (self.bulk_offset_type as i8).to_string());
-        header_map.insert("unit_len", self.unit_len.to_string());
-        header_map.insert("begin_time", self.begin_time.to_string());
-        header_map.insert("end_time", self.end_time.to_string());
-        header_map.insert("amount", self.amount.to_string()); //变长
-
-        //write file header
-        let mut file = self.get_indexed_file()?;
-        file.seek(SeekFrom::Start(0));
-
-        //save data segment start offset
-        self.indexed_data_offset = write_header_info(&mut file, &mut header_map, TUPLE_INDEXED_HEADER_SEGMENT_FLAG, TUPLE_INDEXED_DATA_SEGMENT_FLAG)?;
-
-        Ok(())
-    }
-
-    fn save_extra_header_info(&mut self) -> Result<(), Error> {
-
-        let mut header_map = HashMap::new();
-        header_map.insert("desc", "flare profiler data file".to_string());
-        header_map.insert("first_el_type", (self.index_type as i8).to_string());
-        header_map.insert("second_el_type", (self.bulk_offset_type as i8).to_string());
-        header_map.insert("unit_len", self.unit_len.to_string());
-        header_map.insert("begin_time", self.begin_time.to_string());
-        header_map.insert("end_time", self.end_time.to_string());
-        header_map.insert("amount", self.amount.to_string()); //变长
-
-        //write file header
-        let mut file = self.get_extra_file()?;
-        file.seek(SeekFrom::Start(0));
-
-        write_header_info(&mut file, &mut header_map, TUPLE_EXTRA_HEADER_SEGMENT_FLAG, TUPLE_EXTRA_DATA_SEGMENT_FLAG)?;
-
-        //save data segment start offset
-        self.extra_data_offset = file.seek(SeekFrom::Current(0)).unwrap();
-        //info.data_offset = file.stream_position().unwrap();
-        Ok(())
-    }
-
-    fn load_indexed_header_info(&mut self) -> Result<(), io::Error> {
-        //read indexed file header
-        let mut file = self.get_indexed_file()?;
-        file.seek(SeekFrom::Start(0));
-
-        let mut header_map: HashMap<String, String> = HashMap::new();
-        self.indexed_data_offset = read_header_info(&mut file, &mut header_map, TUPLE_INDEXED_HEADER_SEGMENT_FLAG, TUPLE_INDEXED_DATA_SEGMENT_FLAG)?;
-        self.index_type = ValueType::from_i8(get_as_i8(&mut header_map, "first_el_type")).unwrap();
-        self.bulk_offset_type = ValueType::from_i8(get_as_i8(&mut header_map, "second_el_type")).unwrap();
-        self.begin_time = get_as_i64(&mut header_map, "begin_time");
-        self.end_time = get_as_i64(&mut header_map, "end_time");
-        self.unit_len = get_as_i8(&mut header_map, "unit_len");
-        self.amount = get_as_i32(&mut header_map, "amount");
-        Ok(())
-    }
-
-    fn load_extra_header_info(&mut self) -> Result<(), io::Error> {
-        //read extra file header
-
-        let mut file = self.get_extra_file()?;
-        file.seek(SeekFrom::Start(0));
-
-        let mut header_map: HashMap<String, String> = HashMap::new();
-        self.extra_data_offset = read_header_info(&mut file, &mut header_map, TUPLE_EXTRA_HEADER_SEGMENT_FLAG, TUPLE_EXTRA_DATA_SEGMENT_FLAG)?;
-        Ok(())
-    }
-
-}
-
-//read & write value methods
-impl TupleIndexedFile {
-
-    pub fn add_value(&mut self, index: TupleValue, bulk_value: &[u8]) -> io::Result<()> {
-        let val_type = get_value_type(&index);
-        if val_type != self.index_type {
-            println!("index type not match, expect '{:?}' but '{:?}'", self.index_type, val_type );
-            return Err(io::Error::new(ErrorKind::InvalidInput, "index type not match"));
-        }
-
-        //put into bulk buffer
-        self.bulk_buffer_bytes += bulk_value.len();
-        self.bulk_buffer.push_back((index.clone(), bulk_value.to_vec()));
-
-        //flush
-        //TODO call get timestamp_millis() may be frequently, maybe too heavily?
-        let now_time = Local::now().timestamp_millis();
-        let interval = now_time - self.last_flush_bulk_time;
-        if interval > self.bulk_flush_interval_time || self.bulk_buffer_bytes >= self.bulk_buffer_bytes_limit {
-            //println!("flushing indexed file, bulk_buffer_bytes:{}, write interval: {} ...", self.bulk_buffer_bytes, interval);
-            self.flush();
-        }
-
-        Ok(())
-    }
-
-    pub fn flush(&mut self) -> io::Result<()> {
-        let mut indexed_file = self.get_indexed_file()?;
-        let mut extra_file = self.get_extra_file()?;
-        while let Some((index, bulk_value)) = self.bulk_buffer.pop_front() {
-            let bulk_offset = extra_file.seek(SeekFrom::End(0)).unwrap();
-            //bulk len  FIXME 数据块长度没有判断，大于64KB会溢出
-            extra_file.write_u16::<FileEndian>(bulk_value.len() as u16)?;
-            //bulk data
-            extra_file.write_all(&bulk_value)?;
-
-            //index value
-            self.write_indexed_value(&mut indexed_file,&index, self.index_type);
-            //bulk offset
-            let bulk_offset_value = TupleValue::uint32(bulk_offset as u32);
-            self.write_indexed_value(&mut indexed_file,&bulk_offset_value, self.bulk_offset_type);
-
-            self.index_vec.push(index.clone());
-            self.index_map.insert(index, bulk_offset_value);
-            self.amount += 1;
-            self.bulk_buffer_bytes -= bulk_value.len();
-        }
-        let now_time = Local::now().timestamp_millis();
-        self.last_flush_bulk_time = now_time;
-        Ok(())
-    }
-
-    fn write_indexed_value(&mut self, indexed_file: &mut File, value: &TupleValue, expect_value_type: ValueType) -> io::Result<()> {
-        let val_type = get_value_type(&value);
-        if val_type != expect_value_type {
-            println!("value type not match, expect '{:?}' but '{:?}'", expect_value_type, val_type );
-            return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
-        }
-
-        indexed_file.seek(SeekFrom::End(0));
-        match value {
-            TupleValue::int16(v) => {
-                indexed_file.write_i16::<FileEndian>(*v);
-            },
-            TupleValue::uint16(v) => {
-                indexed_file.write_u16::<FileEndian>(*v);
-            },
-            TupleValue::int32(v) => {
-                indexed_file.write_i32::<FileEndian>(*v);
-            },
-            TupleValue::uint32(v) => {
-                indexed_file.write_u32::<FileEndian>(*v);
-            },
-            TupleValue::int64(v) => {
-                indexed_file.write_i64::<FileEndian>(*v);
-            },
-//            TupleValue::float64(v) => {
-//                indexed_file.write_f64::<FileEndian>(*v);
-//            },
-//            _ => {
-//                println!("unsupported indexed value: {:?}", value);
-//                return Err(io::Error::new(ErrorKind::InvalidInput, "unsupported indexed value"));
-//            }
-        }
-        Ok(())
-    }
-
-    fn read_indexed_value(reader: &mut Read, index_type: &ValueType) -> io::Result<TupleValue> {
-        match index_type {
-            ValueType::INT16 => {
-                Ok(TupleValue::int16(reader.read_i16::<FileEndian>()?))
-            },
-            ValueType::UINT16 => {
-                Ok(TupleValue::uint16(reader.read_u16::<FileEndian>()?))
-            },
-            ValueType::INT32 => {
-                Ok(TupleValue::int32(reader.read_i32::<FileEndian>()?))
-            },
-            ValueType::UINT32 => {
-                Ok(TupleValue::uint32(reader.read_u32::<FileEndian>()?))
-            },
-            ValueType::INT64 => {
-                Ok(TupleValue::int64(reader.read_i64::<FileEndian>()?))
-            },
-//            ValueType::FLOAT64 => {
-//
-//            },
-            _ => {
-                println!("unsupported read index value: {:?}", index_type);
-                Err(io::Error::new(ErrorKind::InvalidInput, "unsupported read index value"))
-            },
-        }
-    }
-
-    fn load_index_map(&mut self) -> io::Result<()> {
-        let mut file = self.get_indexed_file()?;
-        file.seek(SeekFrom::Start(self.indexed_data_offset));
-
-        let mut reader = BufReader::new(file);
-        loop {
-            if let Ok(index_value) = TupleIndexedFile::read_indexed_value(&mut reader, &self.index_type) {
-                if let Ok(bulk_offset_value) = TupleIndexedFile::read_indexed_value(&mut reader, &self.bulk_offset_type) {
-                    self.index_vec.push(index_value.clone());
-                    self.index_map.insert(index_value, bulk_offset_value);
-                } else {
-                    break;
-                }
-            }else {
-                break;
-            }
-        }
-        Ok(())
-    }
-
-    ///
-    /// read bulk value by index
-    ///
-    pub fn get_value(&mut self, index: &TupleValue) -> io::Result<Vec<u8>> {
-        let mut bulk_offset = 0u32;
-        if let Some(TupleValue::uint32(offset)) = self.index_map.get(index) {
-            bulk_offset = *offset;
-        } else {
-            return Err(io::Error::new(ErrorKind::NotFound, "index not found"));
-        }
-        let mut extra_file = self.get_extra_file()?;
-        let (buf, offset) = TupleIndexedFile::read_bulk_data(&mut extra_file, bulk_offset)?;
-        Ok(buf)
-
-    }
-
-    fn read_bulk_data(extra_file: &mut File, bulk_offset: u32) -> Result<(Vec<u8>,u32), Error> {
-        extra_file.seek(SeekFrom::Start(bulk_offset as u64));
-        let bytes_to_read = extra_file.read_u16::<FileEndian>()? as usize;
-        let mut buf = vec![0u8; bytes_to_read];
-        extra_file.read_exact(&mut buf)?;
-        let new_offset = extra_file.seek(SeekFrom::Current(0)).unwrap();
-        Ok((buf, new_offset as u32))
-    }
-
-    fn read_range_bulk_data(extra_file: &mut File, start_offset: u32, end_offset: u32) -> Result<Vec<Vec<u8>>, Error> {
-        let mut result = Vec::with_capacity(1024);
-        let mut read_pos = start_offset;
-        extra_file.seek(SeekFrom::Start(start_offset as u64));
-        //buf 32K
-        let mut reader = BufReader::with_capacity(32*1024, extra_file);
-        while read_pos <= end_offset {
-            let bytes_to_read = reader.read_u16::<FileEndian>()? as usize;
-            let mut buf = vec![0u8; bytes_to_read];
-            reader.read_exact(&mut buf)?;
-            result.push(buf);
-            read_pos += 2;
-            read_pos += bytes_to_read as u32;
-        }
-        Ok(result)
-    }
-
-    pub fn get_range_value<F>(&mut self, start_index: &TupleValue, end_index: &TupleValue, mut handler: F) -> io::Result<()>
-        where F: FnMut(Vec<u8>) {
-        //TODO 扩大范围，避免边界不完整
-        let new_start_index = self.search_index(start_index).clone();
-        let new_end_index = self.search_index(end_index).clone();
-        let mut start_offset = 0u32;
-        let mut end_offset = 0u32;
-        let mut found = false;
-        if let Some(TupleValue::uint32(offset1)) = self.index_map.get(&new_start_index) {
-            start_offset = *offset1;
-            if let Some(TupleValue::uint32(offset2)) = self.index_map.get(&new_end_index) {
-                end_offset = *offset2;
-                found = true;
-            }
-        }
-
-        if found {
-            let mut extra_file = self.get_extra_file()?;
-            let buf_vec = TupleIndexedFile::read_range_bulk_data(&mut extra_file, start_offset, end_offset)?;
-            for buf in buf_vec {
-                handler(buf);
-            }
-
-//            let mut offset = start_offset;
-//            loop {
-//                let (buf, next_offset) = TupleIndexedFile::read_bulk_data(&mut extra_file, offset)?;
-//                handler(buf);
-//                offset = next_offset;
-//                if offset > end_offset {
-//                    break;
-//                }
-//            }
-
-            Ok(())
-        }else {
-            Err(io::Error::new(ErrorKind::NotFound, "index not found"))
-        }
-    }
-
-    pub fn get_all_entries(&mut self) -> io::Result<Vec<(i64, Vec<u8>)>> {
-        let mut result = Vec::with_capacity(self.index_map.len());
-        let mut extra_file = self.get_extra_file()?;
-        for (k, v) in self.index_map.iter() {
-            //println!("entry: {:?} => {:?}", k, v);
-            result.push((k.as_int(), TupleIndexedFile::read_bulk_data(&mut extra_file, v.as_int() as u32)?.0));
-        }
-        Ok(result)
-    }
-
-    fn search_index(&mut self, start_index: &TupleValue) -> &TupleValue {
-        match self.index_vec.binary_search(start_index) {
-            Ok(index) => &self.index_vec[index],
-            Err(index) => {
-                let index = min(index, self.index_vec.len() - 1);
-                &self.index_vec[index]
-            },
-        }
-    }
-
-    pub fn get_index_pairs(&self, start: usize, end: usize) -> Vec<(i64, i64)> {
-        let mut result = vec![];
-        for (i, idx) in self.index_vec.iter().enumerate() {
-            if i < start {
-                continue;
-            }
-            if i >= end {
-                break;
-            }
-            result.push((idx.as_int(), self.index_map.get(idx).as_ref().unwrap().as_int()));
-        }
-        result
-    }
-
-}
-
-impl Drop for TupleIndexedFile {
-
-    fn drop(&mut self) {
-        if self.writable {
-            self.save_indexed_header_info();
-            self.flush();
-        }
-    }
-
-}
\ No newline at end of file
+// indexed file
+
+use std::fs::{File, OpenOptions};
+use std::io::{SeekFrom, ErrorKind, Seek, Write, Read, BufReader};
+use chrono::Local;
+use std::io::Error;
+use std::io;
+use byteorder::{WriteBytesExt, ReadBytesExt, NetworkEndian};
+use std::str::from_utf8;
+use num::{FromPrimitive, PrimInt};
+use std::collections::{HashMap, VecDeque};
+use std::cmp::*;
+use std::path::{Path, PathBuf};
+
+use super::FileEndian;
+use super::file_utils::*;
+use super::collections::*;
+use crate::collections::MapUtil::*;
+use super::{ValueType, get_unit_len};
+use crate::collections::MapUtil::parse_value;
+
+//bulk data handler
+type BulkDataConsumer = fn(Vec<u8>);
+//type BulkDataConsumer = fn(&[u8]);
+
+//Tuple-Indexed file Header Segment: TIHS (4 bytes)
+static TUPLE_INDEXED_HEADER_SEGMENT_FLAG: &str = "TIHS";
+//Tuple-Indexed Data Segment flag: TIDS
+static TUPLE_INDEXED_DATA_SEGMENT_FLAG: &str = "TIDS";
+
+//Tuple-Extra file Header Segment: TEHS (4 bytes)
+static TUPLE_EXTRA_HEADER_SEGMENT_FLAG: &str = "TEHS";
+//Tuple-Extra Data Segment flag: TEDS
+static TUPLE_EXTRA_DATA_SEGMENT_FLAG: &str = "TEDS";
+
+//v1: uint32 bulk offset, u16 bulk len, data file is limited to 4GB and bulk data to 64KB
+pub static TUPLE_FORMAT_VERSION_V1: &str = "0.1.0";
+//v2: int64 bulk offset, u32 bulk len
+pub static TUPLE_FORMAT_VERSION_V2: &str = "0.2.0";
+//索引分块: 内存中只保存每块第一个索引值，查找时按块从索引文件读取，内存占用与条目数量无关
+//  索引文件中的条目按索引值递增、定长存放，第n块为第 n*INDEX_CHUNK_ENTRIES 个条目开始的连续条目
+pub const INDEX_CHUNK_ENTRIES: usize = 4096;
+//缓存最近读取的索引块数量
+const INDEX_CHUNK_CACHE_SIZE: usize = 4;
+
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TupleValue {
+    int16(i16),
+    uint16(u16),
+    int32(i32),
+    uint32(u32),
+    int64(i64),
+//    float64(f64)
+}
+
+impl TupleValue {
+    pub fn as_int(&self) -> i64 {
+        match self {
+            TupleValue::int16(x) => *x as i64,
+            TupleValue::uint16(x) => *x as i64,
+            TupleValue::int32(x) => *x as i64,
+            TupleValue::uint32(x) => *x as i64,
+            TupleValue::int64(x) => *x,
+        }
+    }
+}
+
+impl std::cmp::Ord for TupleValue {
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_int().cmp(&other.as_int())
+    }
+}
+
+impl PartialOrd for TupleValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.as_int().partial_cmp(&other.as_int())
+    }
+}
+
+fn get_value_type(value: &TupleValue) -> ValueType {
+    match value {
+        TupleValue::int16(_) => ValueType::INT16,
+        TupleValue::uint16(_) => ValueType::UINT16,
+        TupleValue::int32(_) => ValueType::INT32,
+        TupleValue::uint32(_) => ValueType::UINT32,
+        TupleValue::int64(_) => ValueType::INT64,
+//        TupleValue::float64(_) => ValueType::FLOAT64,
+        _ => ValueType::UNKNOWN
+    }
+}
+
+///
+/// save tuple values in indexed file
+#[derive( Debug )]
+pub struct TupleIndexedFile {
+    //state
+    inited: bool,
+    writable: bool,
+    //strict mode: verify index entries and bulk offsets, return error on malformed file
+    strict: bool,
+    //索引文件中的条目数量
+    index_entries: usize,
+    //每个索引块的第一个索引值
+    chunk_first_indexes: Vec<TupleValue>,
+    //最近读取的索引块: (块序号, [(索引值, 数据偏移量)])
+    chunk_cache: VecDeque<(usize, Vec<(TupleValue, TupleValue)>)>,
+
+    //indexed file
+    pub indexed_path: PathBuf,
+    //data segment start offset
+    pub indexed_data_offset: u64,
+
+    //extra data file
+    extra_path: PathBuf,
+    extra_data_offset: u64,
+
+    //bulk value buffer
+    bulk_buffer: VecDeque<(TupleValue, Vec<u8>)>,
+    //last write bulk time
+    last_flush_bulk_time: i64,
+    //write interval
+    bulk_flush_interval_time: i64,
+    //cache bytes
+    bulk_buffer_bytes: usize,
+    //buffer bytes limit
+    bulk_buffer_bytes_limit: usize,
+    //sync data to disk after flush
+    sync_on_flush: bool,
+
+    //header info
+    // index value type
+    pub index_type: ValueType,
+    // bulk data type
+    pub bulk_offset_type: ValueType,
+    // tuple unit len(bytes)
+    pub unit_len: i8,
+    // data start time ms
+    pub begin_time: i64,
+    // current time ms
+    pub end_time: i64,
+    // sample count, 超过 i32::MAX 时保持为 i32::MAX，条目数量由索引文件大小计算
+    pub amount: i32,
+}
+
+//init methods
+impl TupleIndexedFile {
+
+    pub fn new_reader<P: AsRef<Path>>(path: P) -> Result<TupleIndexedFile, io::Error> {
+        let mut tuple_file = TupleIndexedFile::new(path,
+                                                   ValueType::UNKNOWN,
+                                                   ValueType::UNKNOWN,
+                                                   false)?;
+        tuple_file.init_reader()?;
+        Ok(tuple_file)
+    }
+
+    //bounds-checked reader for untrusted files, e.g. fuzz targets
+    pub fn new_strict_reader<P: AsRef<Path>>(path: P) -> Result<TupleIndexedFile, io::Error> {
+        let mut tuple_file = TupleIndexedFile::new(path,
+                                                   ValueType::UNKNOWN,
+                                                   ValueType::UNKNOWN,
+                                                   false)?;
+        tuple_file.strict = true;
+        tuple_file.init_reader()?;
+        Ok(tuple_file)
+    }
+
+    pub fn new_writer<P: AsRef<Path>>(path: P, index_type: ValueType) -> Result<TupleIndexedFile, io::Error> {
+
+        let mut tuple_file = TupleIndexedFile::new(path,
+                                                   index_type,
+                                                   ValueType::INT64,
+                                                   true)?;
+        tuple_file.init_writer()?;
+        Ok(tuple_file)
+    }
+
+    fn new<P: AsRef<Path>>(path: P, index_type: ValueType, bulk_offset_type: ValueType, writable: bool) -> Result<TupleIndexedFile, io::Error> {
+        let indexed_path = path_with_suffix(&path, ".fidx");
+        let extra_path = path_with_suffix(&path, ".fdata");
+        let unit_len = (get_unit_len(index_type) + get_unit_len(bulk_offset_type)) as i8;
+        let bulk_write_interval_time = 1000;
+        let bulk_buffer_bytes_limit = 100*1024;
+        Ok(TupleIndexedFile {
+            inited: false,
+            writable,
+            strict: false,
+            index_entries: 0,
+            chunk_first_indexes: vec![],
+            chunk_cache: VecDeque::with_capacity(INDEX_CHUNK_CACHE_SIZE),
+            indexed_path,
+            indexed_data_offset: 0,
+            extra_path,
+            extra_data_offset: 0,
+            bulk_buffer: VecDeque::with_capacity(256),
+            last_flush_bulk_time: 0,
+            bulk_flush_interval_time: bulk_write_interval_time,
+            bulk_buffer_bytes: 0,
+            bulk_buffer_bytes_limit,
+            sync_on_flush: false,
+            index_type,
+            bulk_offset_type,
+            unit_len,
+            begin_time: 0,
+            end_time: 0,
+            amount: 0
+        })
+    }
+
+    fn init_reader(&mut self) -> Result<(), Error> {
+        if !self.inited {
+            self.load_indexed_header_info()?;
+            self.load_extra_header_info()?;
+            self.load_index_chunks()?;
+            self.inited = true;
+        }
+        Ok(())
+    }
+
+    fn init_writer(&mut self) -> Result<(), Error> {
+        if !self.inited {
+
+            //读取已经存在的文件头信息
+            let mut load = false;
+            let mut indexed_file = self.get_indexed_file()?;
+            if let Ok(len) = indexed_file.seek(SeekFrom::End(0)) {
+                if len > 0 {
+                    if let Err(e) = self.init_reader() {
+                        //load file failed
+                        return Err(e);
+                    }else {
+                        load = true;
+                    }
+                }
+            }
+
+            //文件不存在或者文件大小为0
+            if !load {
+                let now_time = Local::now().timestamp_millis();
+                self.begin_time = now_time;
+                self.end_time = now_time;
+                self.save_indexed_header_info()?;
+                self.save_extra_header_info()?;
+            }
+
+            self.inited = true;
+        }
+        Ok(())
+    }
+
+    fn get_indexed_file(&self) -> Result<File, Error> {
+        Ok(open_file(&self.indexed_path, self.writable)?)
+    }
+
+    fn get_extra_file(&self) -> Result<File, Error> {
+        Ok(open_file(&self.extra_path, self.writable)?)
+    }
+
+    pub fn save_indexed_header_info(&mut self) -> Result<(), Error> {
+
+        let mut header_map = HashMap::new();
+        header_map.insert("ver", self.get_format_version().to_string());
+        header_map.insert("desc", "flare profiler indexed file".to_string());
+        header_map.insert("first_el_type", (self.index_type as i8).to_string());
+        header_map.insert("second_el_type", (self.bulk_offset_type as i8).to_string());
+        header_map.insert("unit_len", self.unit_len.to_string());
+        header_map.insert("begin_time", self.begin_time.to_string());
+        header_map.insert("end_time", self.end_time.to_string());
+        header_map.insert("amount", self.amount.to_string()); //变长
+
+        //write file header
+        let mut file = self.get_indexed_file()?;
+        file.seek(SeekFrom::Start(0));
+
+        //save data segment start offset
+        self.indexed_data_offset = write_header_info(&mut file, &mut header_map, TUPLE_INDEXED_HEADER_SEGMENT_FLAG, TUPLE_INDEXED_DATA_SEGMENT_FLAG)?;
+
+        Ok(())
+    }
+
+    fn save_extra_header_info(&mut self) -> Result<(), Error> {
+
+        let mut header_map = HashMap::new();
+        header_map.insert("ver", self.get_format_version().to_string());
+        header_map.insert("desc", "flare profiler data file".to_string());
+        header_map.insert("first_el_type", (self.index_type as i8).to_string());
+        header_map.insert("second_el_type", (self.bulk_offset_type as i8).to_string());
+        header_map.insert("unit_len", self.unit_len.to_string());
+        header_map.insert("begin_time", self.begin_time.to_string());
+        header_map.insert("end_time", self.end_time.to_string());
+        header_map.insert("amount", self.amount.to_string()); //变长
+
+        //write file header
+        let mut file = self.get_extra_file()?;
+        file.seek(SeekFrom::Start(0));
+
+        write_header_info(&mut file, &mut header_map, TUPLE_EXTRA_HEADER_SEGMENT_FLAG, TUPLE_EXTRA_DATA_SEGMENT_FLAG)?;
+
+        //save data segment start offset
+        self.extra_data_offset = file.seek(SeekFrom::Current(0)).unwrap();
+        //info.data_offset = file.stream_position().unwrap();
+        Ok(())
+    }
+
+    fn load_indexed_header_info(&mut self) -> Result<(), io::Error> {
+        //read indexed file header
+        let mut file = self.get_indexed_file()?;
+        file.seek(SeekFrom::Start(0));
+
+        let mut header_map: HashMap<String, String> = HashMap::new();
+        self.indexed_data_offset = read_header_info(&mut file, &mut header_map, TUPLE_INDEXED_HEADER_SEGMENT_FLAG, TUPLE_INDEXED_DATA_SEGMENT_FLAG)?;
+        check_format_version(&header_map)?;
+        self.index_type = super::parse_value_type(parse_value(&header_map, "first_el_type")?)?;
+        self.bulk_offset_type = super::parse_value_type(parse_value(&header_map, "second_el_type")?)?;
+        self.begin_time = parse_value(&header_map, "begin_time")?;
+        self.end_time = parse_value(&header_map, "end_time")?;
+        self.unit_len = parse_value(&header_map, "unit_len")?;
+        self.amount = parse_value(&header_map, "amount")?;
+        if self.bulk_offset_type != ValueType::UINT32 && self.bulk_offset_type != ValueType::INT64 {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("unsupported bulk offset type: {:?}", self.bulk_offset_type)));
+        }
+        Ok(())
+    }
+
+    fn load_extra_header_info(&mut self) -> Result<(), io::Error> {
+        //read extra file header
+
+        let mut file = self.get_extra_file()?;
+        file.seek(SeekFrom::Start(0));
+
+        let mut header_map: HashMap<String, String> = HashMap::new();
+        self.extra_data_offset = read_header_info(&mut file, &mut header_map, TUPLE_EXTRA_HEADER_SEGMENT_FLAG, TUPLE_EXTRA_DATA_SEGMENT_FLAG)?;
+        check_format_version(&header_map)?;
+        Ok(())
+    }
+
+}
+
+//read & write value methods
+impl TupleIndexedFile {
+
+    //缓冲的数据超过 interval_ms 或者 bytes_limit 时写入文件，sync_on_flush 时写入后同步到磁盘
+    pub fn set_flush_policy(&mut self, interval_ms: i64, bytes_limit: usize, sync_on_flush: bool) {
+        self.bulk_flush_interval_time = interval_ms;
+        self.bulk_buffer_bytes_limit = bytes_limit;
+        self.sync_on_flush = sync_on_flush;
+    }
+
+    //还没有写入文件的数据
+    pub fn get_buffered_bytes(&self) -> usize {
+        self.bulk_buffer_bytes
+    }
+
+    pub fn add_value(&mut self, index: TupleValue, bulk_value: &[u8]) -> io::Result<()> {
+        let val_type = get_value_type(&index);
+        if val_type != self.index_type {
+            println!("index type not match, expect '{:?}' but '{:?}'", self.index_type, val_type );
+            return Err(io::Error::new(ErrorKind::InvalidInput, "index type not match"));
+        }
+
+        //put into bulk buffer
+        self.bulk_buffer_bytes += bulk_value.len();
+        self.bulk_buffer.push_back((index.clone(), bulk_value.to_vec()));
+
+        //flush
+        //TODO call get timestamp_millis() may be frequently, maybe too heavily?
+        let now_time = Local::now().timestamp_millis();
+        let interval = now_time - self.last_flush_bulk_time;
+        if interval > self.bulk_flush_interval_time || self.bulk_buffer_bytes >= self.bulk_buffer_bytes_limit {
+            //println!("flushing indexed file, bulk_buffer_bytes:{}, write interval: {} ...", self.bulk_buffer_bytes, interval);
+            self.flush();
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut indexed_file = self.get_indexed_file()?;
+        let mut extra_file = self.get_extra_file()?;
+        let large_format = self.is_large_format();
+        let sync = self.sync_on_flush && !self.bulk_buffer.is_empty();
+        while let Some((index, bulk_value)) = self.bulk_buffer.pop_front() {
+            let bulk_offset = extra_file.seek(SeekFrom::End(0))?;
+            //v1格式的数据块长度及偏移量有限制，超出时返回错误而不是写入溢出的值
+            let bulk_offset_value = if large_format {
+                if bulk_value.len() > u32::max_value() as usize {
+                    return Err(io::Error::new(ErrorKind::InvalidInput, format!("bulk data is too large: {}", bulk_value.len())));
+                }
+                extra_file.write_u32::<FileEndian>(bulk_value.len() as u32)?;
+                TupleValue::int64(bulk_offset as i64)
+            } else {
+                if bulk_value.len() > u16::max_value() as usize || bulk_offset > u32::max_value() as u64 {
+                    return Err(io::Error::new(ErrorKind::InvalidInput, format!("bulk data exceeds v1 format limits, len: {}, offset: {}", bulk_value.len(), bulk_offset)));
+                }
+                extra_file.write_u16::<FileEndian>(bulk_value.len() as u16)?;
+                TupleValue::uint32(bulk_offset as u32)
+            };
+            //bulk data
+            extra_file.write_all(&bulk_value)?;
+
+            //index value
+            self.write_indexed_value(&mut indexed_file,&index, self.index_type)?;
+            //bulk offset
+            self.write_indexed_value(&mut indexed_file,&bulk_offset_value, self.bulk_offset_type)?;
+
+            self.add_index_entry(index);
+            self.amount = self.amount.saturating_add(1);
+            self.bulk_buffer_bytes -= bulk_value.len();
+        }
+        if sync {
+            //先同步数据文件，索引不会指向不存在的数据
+            extra_file.sync_data()?;
+            indexed_file.sync_data()?;
+        }
+        let now_time = Local::now().timestamp_millis();
+        self.last_flush_bulk_time = now_time;
+        Ok(())
+    }
+
+    fn write_indexed_value(&mut self, indexed_file: &mut File, value: &TupleValue, expect_value_type: ValueType) -> io::Result<()> {
+        let val_type = get_value_type(&value);
+        if val_type != expect_value_type {
+            println!("value type not match, expect '{:?}' but '{:?}'", expect_value_type, val_type );
+            return Err(io::Error::new(ErrorKind::InvalidInput, "value type not match"));
+        }
+
+        indexed_file.seek(SeekFrom::End(0));
+        match value {
+            TupleValue::int16(v) => {
+                indexed_file.write_i16::<FileEndian>(*v);
+            },
+            TupleValue::uint16(v) => {
+                indexed_file.write_u16::<FileEndian>(*v);
+            },
+            TupleValue::int32(v) => {
+                indexed_file.write_i32::<FileEndian>(*v);
+            },
+            TupleValue::uint32(v) => {
+                indexed_file.write_u32::<FileEndian>(*v);
+            },
+            TupleValue::int64(v) => {
+                indexed_file.write_i64::<FileEndian>(*v);
+            },
+//            TupleValue::float64(v) => {
+//                indexed_file.write_f64::<FileEndian>(*v);
+//            },
+//            _ => {
+//                println!("unsupported indexed value: {:?}", value);
+//                return Err(io::Error::new(ErrorKind::InvalidInput, "unsupported indexed value"));
+//            }
+        }
+        Ok(())
+    }
+
+    fn read_indexed_value(reader: &mut Read, index_type: &ValueType) -> io::Result<TupleValue> {
+        match index_type {
+            ValueType::INT16 => {
+                Ok(TupleValue::int16(reader.read_i16::<FileEndian>()?))
+            },
+            ValueType::UINT16 => {
+                Ok(TupleValue::uint16(reader.read_u16::<FileEndian>()?))
+            },
+            ValueType::INT32 => {
+                Ok(TupleValue::int32(reader.read_i32::<FileEndian>()?))
+            },
+            ValueType::UINT32 => {
+                Ok(TupleValue::uint32(reader.read_u32::<FileEndian>()?))
+            },
+            ValueType::INT64 => {
+                Ok(TupleValue::int64(reader.read_i64::<FileEndian>()?))
+            },
+//            ValueType::FLOAT64 => {
+//
+//            },
+            _ => {
+                println!("unsupported read index value: {:?}", index_type);
+                Err(io::Error::new(ErrorKind::InvalidInput, "unsupported read index value"))
+            },
+        }
+    }
+
+    fn get_index_entry_len(&self) -> u64 {
+        (get_unit_len(self.index_type) + get_unit_len(self.bulk_offset_type)) as u64
+    }
+
+    //新写入的条目，最后一块的缓存失效
+    fn add_index_entry(&mut self, index: TupleValue) {
+        let chunk = self.index_entries / INDEX_CHUNK_ENTRIES;
+        if self.index_entries % INDEX_CHUNK_ENTRIES == 0 {
+            self.chunk_first_indexes.push(index);
+        }
+        self.index_entries += 1;
+        self.chunk_cache.retain(|x| x.0 != chunk);
+    }
+
+    //只读取每块的第一个索引值；严格模式顺序校验全部条目，不保存在内存中
+    fn load_index_chunks(&mut self) -> io::Result<()> {
+        let mut file = self.get_indexed_file()?;
+        let indexed_len = file.seek(SeekFrom::End(0))?;
+        let extra_len = self.get_extra_file()?.seek(SeekFrom::End(0))?;
+
+        let entry_len = self.get_index_entry_len();
+        let data_len = indexed_len.saturating_sub(self.indexed_data_offset);
+        if self.strict && data_len % entry_len != 0 {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid indexed file, incomplete entry, data len: {}", data_len)));
+        }
+        //不完整的条目(写入中断)忽略
+        let entries = (data_len / entry_len) as usize;
+        self.index_entries = 0;
+        self.chunk_first_indexes = Vec::with_capacity((entries + INDEX_CHUNK_ENTRIES - 1) / INDEX_CHUNK_ENTRIES);
+        self.chunk_cache.clear();
+
+        if !self.strict {
+            for chunk_start in (0..entries).step_by(INDEX_CHUNK_ENTRIES) {
+                file.seek(SeekFrom::Start(self.indexed_data_offset + chunk_start as u64 * entry_len))?;
+                self.chunk_first_indexes.push(TupleIndexedFile::read_indexed_value(&mut file, &self.index_type)?);
+            }
+            self.index_entries = entries;
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(self.indexed_data_offset))?;
+        let mut reader = BufReader::with_capacity(INDEX_CHUNK_ENTRIES * entry_len as usize, file);
+        let mut last_index: Option<TupleValue> = None;
+        for _ in 0..entries {
+            let index_value = TupleIndexedFile::read_indexed_value(&mut reader, &self.index_type)?;
+            let bulk_offset_value = TupleIndexedFile::read_indexed_value(&mut reader, &self.bulk_offset_type)?;
+            //索引必须递增，数据偏移量必须在数据文件范围内
+            if let Some(last) = &last_index {
+                if *last >= index_value {
+                    return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid indexed file, index is not increasing: {:?}", index_value)));
+                }
+            }
+            let bulk_offset = bulk_offset_value.as_int() as u64;
+            if bulk_offset < self.extra_data_offset || bulk_offset.saturating_add(self.get_bulk_len_size()) > extra_len {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("Invalid indexed file, bulk offset out of range: {}", bulk_offset)));
+            }
+            last_index = Some(index_value.clone());
+            self.add_index_entry(index_value);
+        }
+        Ok(())
+    }
+
+    //从索引文件读取 [start, end) 的条目
+    fn read_index_entries(&self, start: usize, end: usize) -> io::Result<Vec<(TupleValue, TupleValue)>> {
+        let end = min(end, self.index_entries);
+        if start >= end {
+            return Ok(vec![]);
+        }
+        let entry_len = self.get_index_entry_len();
+        let mut file = self.get_indexed_file()?;
+        file.seek(SeekFrom::Start(self.indexed_data_offset + start as u64 * entry_len))?;
+        let mut reader = BufReader::with_capacity(min(end - start, INDEX_CHUNK_ENTRIES) * entry_len as usize, file);
+        let mut entries = Vec::with_capacity(end - start);
+        for _ in start..end {
+            let index_value = TupleIndexedFile::read_indexed_value(&mut reader, &self.index_type)?;
+            let bulk_offset_value = TupleIndexedFile::read_indexed_value(&mut reader, &self.bulk_offset_type)?;
+            entries.push((index_value, bulk_offset_value));
+        }
+        Ok(entries)
+    }
+
+    fn get_index_chunk(&mut self, chunk: usize) -> io::Result<&Vec<(TupleValue, TupleValue)>> {
+        match self.chunk_cache.iter().position(|x| x.0 == chunk) {
+            Some(pos) => {
+                let cached = self.chunk_cache.remove(pos).unwrap();
+                self.chunk_cache.push_back(cached);
+            }
+            None => {
+                let start = chunk * INDEX_CHUNK_ENTRIES;
+                let entries = self.read_index_entries(start, start + INDEX_CHUNK_ENTRIES)?;
+                if self.chunk_cache.len() >= INDEX_CHUNK_CACHE_SIZE {
+                    self.chunk_cache.pop_front();
+                }
+                self.chunk_cache.push_back((chunk, entries));
+            }
+        }
+        Ok(&self.chunk_cache.back().unwrap().1)
+    }
+
+    //第一个不小于index的条目，都小于index时返回最后一个条目
+    fn search_index(&mut self, index: &TupleValue) -> io::Result<Option<(TupleValue, TupleValue)>> {
+        if self.index_entries == 0 {
+            return Ok(None);
+        }
+        //第一个值不小于index的块之前的一块中查找
+        let chunks = self.chunk_first_indexes.len();
+        let next_chunk = self.chunk_first_indexes.partition_point(|x| x < index);
+        if next_chunk == 0 {
+            return Ok(self.get_index_chunk(0)?.first().cloned());
+        }
+        let entries = self.get_index_chunk(next_chunk - 1)?;
+        if let Some(entry) = entries.get(entries.partition_point(|x| x.0 < *index)) {
+            return Ok(Some(entry.clone()));
+        }
+        if next_chunk < chunks {
+            return Ok(self.get_index_chunk(next_chunk)?.first().cloned());
+        }
+        Ok(entries.last().cloned())
+    }
+
+    //常驻内存的索引大小(估算)，包括索引块缓存的上限
+    pub fn get_resident_bytes(&self) -> usize {
+        self.chunk_first_indexes.capacity() * std::mem::size_of::<TupleValue>()
+            + INDEX_CHUNK_CACHE_SIZE * INDEX_CHUNK_ENTRIES * std::mem::size_of::<(TupleValue, TupleValue)>()
+    }
+
+    ///
+    /// read bulk value by index
+    ///
+    pub fn get_value(&mut self, index: &TupleValue) -> io::Result<Vec<u8>> {
+        let bulk_offset = match self.search_index(index)? {
+            Some((found, offset)) if found == *index => offset.as_int() as u64,
+            _ => return Err(io::Error::new(ErrorKind::NotFound, "index not found"))
+        };
+        let mut extra_file = self.get_extra_file()?;
+        let (buf, _) = TupleIndexedFile::read_bulk_data(&mut extra_file, bulk_offset, self.is_large_format())?;
+        Ok(buf)
+
+    }
+
+    pub fn get_format_version(&self) -> &'static str {
+        if self.is_large_format() { TUPLE_FORMAT_VERSION_V2 } else { TUPLE_FORMAT_VERSION_V1 }
+    }
+
+    fn is_large_format(&self) -> bool {
+        self.bulk_offset_type == ValueType::INT64
+    }
+
+    fn get_bulk_len_size(&self) -> u64 {
+        if self.is_large_format() { 4 } else { 2 }
+    }
+
+    fn read_bulk_len(reader: &mut Read, large_format: bool) -> io::Result<usize> {
+        if large_format {
+            Ok(reader.read_u32::<FileEndian>()? as usize)
+        } else {
+            Ok(reader.read_u16::<FileEndian>()? as usize)
+        }
+    }
+
+    fn read_bulk_data(extra_file: &mut File, bulk_offset: u64, large_format: bool) -> Result<(Vec<u8>,u64), Error> {
+        let file_len = extra_file.seek(SeekFrom::End(0))?;
+        extra_file.seek(SeekFrom::Start(bulk_offset))?;
+        let bytes_to_read = TupleIndexedFile::read_bulk_len(extra_file, large_format)?;
+        //避免损坏的长度导致分配过大的内存
+        if bulk_offset + bytes_to_read as u64 > file_len {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "bulk data is truncated"));
+        }
+        let mut buf = vec![0u8; bytes_to_read];
+        extra_file.read_exact(&mut buf)?;
+        let new_offset = extra_file.seek(SeekFrom::Current(0))?;
+        Ok((buf, new_offset))
+    }
+
+    pub fn get_range_value<F>(&mut self, start_index: &TupleValue, end_index: &TupleValue, mut handler: F) -> io::Result<()>
+        where F: FnMut(Vec<u8>) {
+        let range = self.map_range(start_index, end_index)?;
+        for buf in range.iter() {
+            handler(buf.to_vec());
+        }
+        Ok(())
+    }
+
+    //映射索引范围内的批量数据，按顺序返回每个值的切片，不为每个值分配内存
+    //返回的数据不依赖当前对象，读取期间可以使用其它索引文件
+    pub fn map_range(&mut self, start_index: &TupleValue, end_index: &TupleValue) -> io::Result<MappedRange> {
+        //TODO 扩大范围，避免边界不完整
+        let mut start_offset = 0u64;
+        let mut end_offset = 0u64;
+        let mut found = false;
+        if let (Some((_, offset1)), Some((_, offset2))) = (self.search_index(start_index)?, self.search_index(end_index)?) {
+            start_offset = offset1.as_int() as u64;
+            end_offset = offset2.as_int() as u64;
+            found = start_offset <= end_offset;
+        }
+        if !found {
+            return Err(io::Error::new(ErrorKind::NotFound, "index not found"));
+        }
+
+        //映射到文件末尾，最后一个值的长度读取后才知道
+        let mut extra_file = self.get_extra_file()?;
+        let file_len = extra_file.seek(SeekFrom::End(0))?;
+        if start_offset > file_len {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "bulk data is truncated"));
+        }
+        let region = MappedRegion::map(&mut extra_file, start_offset, (file_len - start_offset) as usize)?;
+        let data = region.as_slice();
+        let len_size = self.get_bulk_len_size() as usize;
+        let large_format = self.is_large_format();
+        let mut entries = Vec::with_capacity(1024);
+        let mut pos = 0usize;
+        while pos as u64 <= end_offset - start_offset {
+            if pos + len_size > data.len() {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "bulk data is truncated"));
+            }
+            let bytes_to_read = TupleIndexedFile::read_bulk_len(&mut &data[pos..pos + len_size], large_format)?;
+            pos += len_size;
+            if pos + bytes_to_read > data.len() {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "bulk data is truncated"));
+            }
+            entries.push((pos, bytes_to_read));
+            pos += bytes_to_read;
+        }
+        Ok(MappedRange { region, entries })
+    }
+
+    //按索引顺序返回全部条目，逐块读取索引
+    pub fn get_all_entries(&mut self) -> io::Result<Vec<(i64, Vec<u8>)>> {
+        let mut result = Vec::with_capacity(self.index_entries);
+        let large_format = self.is_large_format();
+        let mut extra_file = self.get_extra_file()?;
+        for start in (0..self.index_entries).step_by(INDEX_CHUNK_ENTRIES) {
+            for (k, v) in self.read_index_entries(start, start + INDEX_CHUNK_ENTRIES)? {
+                result.push((k.as_int(), TupleIndexedFile::read_bulk_data(&mut extra_file, v.as_int() as u64, large_format)?.0));
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn get_index_count(&self) -> usize {
+        self.index_entries
+    }
+
+    //[start, end) 的 (索引值, 数据偏移量)，读取失败时返回已读取的部分
+    pub fn get_index_pairs(&self, start: usize, end: usize) -> Vec<(i64, i64)> {
+        let mut result = vec![];
+        let mut chunk_start = start;
+        while chunk_start < min(end, self.index_entries) {
+            match self.read_index_entries(chunk_start, min(end, chunk_start + INDEX_CHUNK_ENTRIES)) {
+                Ok(entries) => result.extend(entries.iter().map(|(k, v)| (k.as_int(), v.as_int()))),
+                Err(_) => break
+            }
+            chunk_start += INDEX_CHUNK_ENTRIES;
+        }
+        result
+    }
+
+}
+
+//索引范围内映射的批量数据
+pub struct MappedRange {
+    region: MappedRegion,
+    //每个值在映射数据中的(位置, 长度)
+    entries: Vec<(usize, usize)>,
+}
+
+impl MappedRange {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&[u8]> {
+        self.entries.get(i).map(|(pos, len)| &self.region.as_slice()[*pos..*pos + *len])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&[u8]> {
+        let data = self.region.as_slice();
+        self.entries.iter().map(move |(pos, len)| &data[*pos..*pos + *len])
+    }
+}
+
+impl Drop for TupleIndexedFile {
+
+    fn drop(&mut self) {
+        if self.writable {
+            self.save_indexed_header_info();
+            self.flush();
+        }
+    }
+
+}
+fn check_format_version(header_map: &HashMap<String, String>) -> io::Result<()> {
+    match header_map.get("ver") {
+        Some(ver) if ver == TUPLE_FORMAT_VERSION_V1 || ver == TUPLE_FORMAT_VERSION_V2 => Ok(()),
+        Some(ver) => Err(io::Error::new(ErrorKind::InvalidData, format!("Unsupported indexed file version: {}, max supported version: {}, please upgrade flare-profiler",
+                                                                        ver, TUPLE_FORMAT_VERSION_V2))),
+        None => Err(io::Error::new(ErrorKind::InvalidData, "Invalid indexed file, missing version"))
+    }
+}
+
+//升级v1格式的索引文件到当前格式(v2)，返回是否做了升级
+pub fn migrate_tuple_file<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let mut reader = TupleIndexedFile::new_reader(&path)?;
+    if reader.is_large_format() {
+        return Ok(false);
+    }
+
+    //先写入临时文件，完成后再替换原文件
+    let tmp_path = path_with_suffix(&path, ".migrating");
+    let _ = std::fs::remove_file(path_with_suffix(&tmp_path, ".fidx"));
+    let _ = std::fs::remove_file(path_with_suffix(&tmp_path, ".fdata"));
+    {
+        let mut writer = TupleIndexedFile::new_writer(&tmp_path, reader.index_type)?;
+        let mut entries = reader.get_all_entries()?;
+        entries.sort_by_key(|x| x.0);
+        for (index, bulk_value) in entries {
+            let index = match reader.index_type {
+                ValueType::INT16 => TupleValue::int16(index as i16),
+                ValueType::UINT16 => TupleValue::uint16(index as u16),
+                ValueType::INT32 => TupleValue::int32(index as i32),
+                ValueType::UINT32 => TupleValue::uint32(index as u32),
+                _ => TupleValue::int64(index),
+            };
+            writer.add_value(index, &bulk_value)?;
+        }
+        writer.flush()?;
+        writer.begin_time = reader.begin_time;
+        writer.end_time = reader.end_time;
+        writer.amount = reader.amount;
+        writer.save_indexed_header_info()?;
+    }
+    std::fs::rename(path_with_suffix(&tmp_path, ".fidx"), &reader.indexed_path)?;
+    std::fs::rename(path_with_suffix(&tmp_path, ".fdata"), &reader.extra_path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn test_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join("flare-utils-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/{}_{}", dir.to_str().unwrap(), name, std::process::id());
+        let _ = fs::remove_file(format!("{}.fidx", path));
+        let _ = fs::remove_file(format!("{}.fdata", path));
+        path
+    }
+
+    #[test]
+    fn test_tuple_file_is_big_endian() {
+        let path = test_path("tuple_endian");
+        let mut writer = TupleIndexedFile::new_writer(&path, ValueType::UINT32).unwrap();
+        writer.add_value(TupleValue::uint32(0x01020304), b"abc").unwrap();
+        writer.flush().unwrap();
+
+        //index entry: uint32 index + int64 bulk offset, packed
+        let fidx = fs::read(format!("{}.fidx", path)).unwrap();
+        let pos = writer.indexed_data_offset as usize;
+        assert_eq!(&fidx[pos..pos+4], &[1, 2, 3, 4]);
+        assert_eq!(&fidx[pos+4..pos+12], &(writer.extra_data_offset as i64).to_be_bytes());
+        assert_eq!(fidx.len(), pos + 12);
+
+        //bulk entry: uint32 len + data
+        let fdata = fs::read(format!("{}.fdata", path)).unwrap();
+        let pos = writer.extra_data_offset as usize;
+        assert_eq!(&fdata[pos..pos+4], &[0, 0, 0, 3]);
+        assert_eq!(&fdata[pos+4..], b"abc");
+    }
+
+    #[test]
+    fn test_tuple_file_round_trip() {
+        for &bulk_offset_type in &[ValueType::UINT32, ValueType::INT64] {
+            let path = test_path(&format!("tuple_round_trip_{:?}", bulk_offset_type));
+            {
+                let mut writer = TupleIndexedFile::new(&path, ValueType::INT64, bulk_offset_type, true).unwrap();
+                writer.init_writer().unwrap();
+                for i in 0..100i64 {
+                    writer.add_value(TupleValue::int64(i * 1_000_000_007 - 50_000_000_000), format!("value-{}", i).as_bytes()).unwrap();
+                }
+                writer.flush().unwrap();
+            }
+            let mut reader = TupleIndexedFile::new_strict_reader(&path).unwrap();
+            assert_eq!(reader.bulk_offset_type, bulk_offset_type);
+            assert_eq!(reader.get_format_version(), if bulk_offset_type == ValueType::INT64 { TUPLE_FORMAT_VERSION_V2 } else { TUPLE_FORMAT_VERSION_V1 });
+            for i in 0..100i64 {
+                let value = reader.get_value(&TupleValue::int64(i * 1_000_000_007 - 50_000_000_000)).unwrap();
+                assert_eq!(value, format!("value-{}", i).into_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_map_range() {
+        for &bulk_offset_type in &[ValueType::UINT32, ValueType::INT64] {
+            let path = test_path(&format!("tuple_map_range_{:?}", bulk_offset_type));
+            let mut writer = TupleIndexedFile::new(&path, ValueType::UINT32, bulk_offset_type, true).unwrap();
+            writer.init_writer().unwrap();
+            for i in 0..5000u32 {
+                writer.add_value(TupleValue::uint32(i * 2), format!("value-{}", i).as_bytes()).unwrap();
+            }
+            writer.flush().unwrap();
+
+            let mut reader = TupleIndexedFile::new_reader(&path).unwrap();
+            let range = reader.map_range(&TupleValue::uint32(2000), &TupleValue::uint32(7998)).unwrap();
+            assert_eq!(range.len(), 3000);
+            assert_eq!(range.get(0).unwrap(), b"value-1000");
+            for (i, value) in range.iter().enumerate() {
+                assert_eq!(value, format!("value-{}", i + 1000).as_bytes());
+            }
+            #[cfg(unix)]
+            assert!(range.region.is_mapped());
+
+            //与逐个读取的结果一致
+            let mut values = vec![];
+            reader.get_range_value(&TupleValue::uint32(2000), &TupleValue::uint32(7998), |x| values.push(x)).unwrap();
+            assert_eq!(values, range.iter().map(|x| x.to_vec()).collect::<Vec<_>>());
+
+            //截断的数据
+            let fdata = format!("{}.fdata", path);
+            let len = fs::metadata(&fdata).unwrap().len();
+            OpenOptions::new().write(true).open(&fdata).unwrap().set_len(len - 2).unwrap();
+            assert_eq!(reader.map_range(&TupleValue::uint32(0), &TupleValue::uint32(9998)).err().unwrap().kind(), ErrorKind::UnexpectedEof);
+        }
+    }
+
+    //跨多个索引块的查找、范围映射，以及写入时读取最后一块
+    #[test]
+    fn test_chunked_index() {
+        let path = test_path("tuple_chunked_index");
+        let count = INDEX_CHUNK_ENTRIES as u32 * 3 + 10;
+        let mut writer = TupleIndexedFile::new_writer(&path, ValueType::UINT32).unwrap();
+        for i in 0..count {
+            writer.add_value(TupleValue::uint32(i * 2), format!("value-{}", i).as_bytes()).unwrap();
+            if i == INDEX_CHUNK_ENTRIES as u32 - 1 {
+                writer.flush().unwrap();
+                assert_eq!(writer.get_value(&TupleValue::uint32(i * 2)).unwrap(), format!("value-{}", i).into_bytes());
+            }
+        }
+        writer.flush().unwrap();
+        assert_eq!(writer.get_value(&TupleValue::uint32((count - 1) * 2)).unwrap(), format!("value-{}", count - 1).into_bytes());
+
+        for &strict in &[false, true] {
+            let mut reader = if strict { TupleIndexedFile::new_strict_reader(&path).unwrap() } else { TupleIndexedFile::new_reader(&path).unwrap() };
+            assert_eq!(reader.get_index_count(), count as usize);
+            assert_eq!(reader.chunk_first_indexes.len(), 4);
+            assert!(reader.chunk_first_indexes.capacity() < 16);
+            for i in (0..count).step_by(97).chain(vec![INDEX_CHUNK_ENTRIES as u32 - 1, INDEX_CHUNK_ENTRIES as u32, count - 1]) {
+                assert_eq!(reader.get_value(&TupleValue::uint32(i * 2)).unwrap(), format!("value-{}", i).into_bytes());
+            }
+            //不存在的索引
+            assert_eq!(reader.get_value(&TupleValue::uint32(INDEX_CHUNK_ENTRIES as u32 * 2 - 1)).err().unwrap().kind(), ErrorKind::NotFound);
+            assert_eq!(reader.get_value(&TupleValue::uint32(count * 2)).err().unwrap().kind(), ErrorKind::NotFound);
+
+            //范围的边界不存在时使用下一个条目
+            let start = INDEX_CHUNK_ENTRIES as u32 - 5;
+            let range = reader.map_range(&TupleValue::uint32(start * 2 - 1), &TupleValue::uint32((start + INDEX_CHUNK_ENTRIES as u32) * 2 - 1)).unwrap();
+            assert_eq!(range.len(), INDEX_CHUNK_ENTRIES + 1);
+            assert_eq!(range.get(0).unwrap(), format!("value-{}", start).as_bytes());
+            //超出范围时到最后一个条目
+            assert_eq!(reader.map_range(&TupleValue::uint32(0), &TupleValue::uint32(u32::max_value())).unwrap().len(), count as usize);
+
+            let pairs = reader.get_index_pairs(INDEX_CHUNK_ENTRIES - 1, INDEX_CHUNK_ENTRIES + 1);
+            assert_eq!(pairs.iter().map(|x| x.0).collect::<Vec<_>>(), vec![(INDEX_CHUNK_ENTRIES as i64 - 1) * 2, INDEX_CHUNK_ENTRIES as i64 * 2]);
+            let entries = reader.get_all_entries().unwrap();
+            assert_eq!(entries.len(), count as usize);
+            assert!(entries.windows(2).all(|x| x[0].0 < x[1].0));
+        }
+    }
+
+    #[test]
+    fn test_flush_policy() {
+        let path = test_path("tuple_flush_policy");
+        let mut writer = TupleIndexedFile::new_writer(&path, ValueType::UINT32).unwrap();
+        writer.set_flush_policy(60_000, 10, true);
+        writer.add_value(TupleValue::uint32(1), b"abc").unwrap();
+        //第一次写入时间为0，立即写入
+        assert_eq!(writer.get_buffered_bytes(), 0);
+        for i in 2..5u32 {
+            writer.add_value(TupleValue::uint32(i), b"abc").unwrap();
+        }
+        assert_eq!(writer.get_buffered_bytes(), 9);
+        assert_eq!(TupleIndexedFile::new_reader(&path).unwrap().get_all_entries().unwrap().len(), 1);
+        writer.add_value(TupleValue::uint32(5), b"abc").unwrap();
+        assert_eq!(writer.get_buffered_bytes(), 0);
+        assert_eq!(TupleIndexedFile::new_reader(&path).unwrap().get_all_entries().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_reject_unknown_byte_order() {
+        let path = test_path("tuple_byte_order");
+        {
+            let mut writer = TupleIndexedFile::new_writer(&path, ValueType::UINT32).unwrap();
+            writer.add_value(TupleValue::uint32(1), b"abc").unwrap();
+            writer.flush().unwrap();
+        }
+        let fidx_path = format!("{}.fidx", path);
+        let mut fidx = fs::read(&fidx_path).unwrap();
+        let pos = fidx.windows(3).position(|x| x == b"BE\0").unwrap();
+        fidx[pos..pos+2].copy_from_slice(b"LE");
+        fs::write(&fidx_path, &fidx).unwrap();
+        assert!(TupleIndexedFile::new_reader(&path).is_err());
+    }
+
+    #[test]
+    fn test_migrate_v1_tuple_file() {
+        let path = test_path("tuple_migrate");
+        {
+            let mut writer = TupleIndexedFile::new(&path, ValueType::UINT32, ValueType::UINT32, true).unwrap();
+            writer.init_writer().unwrap();
+            for i in 0..50u32 {
+                writer.add_value(TupleValue::uint32(i), format!("value-{}", i).as_bytes()).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        assert!(migrate_tuple_file(&path).unwrap());
+        assert!(!migrate_tuple_file(&path).unwrap());
+
+        let mut reader = TupleIndexedFile::new_strict_reader(&path).unwrap();
+        assert_eq!(reader.get_format_version(), TUPLE_FORMAT_VERSION_V2);
+        let mut values = vec![];
+        reader.get_range_value(&TupleValue::uint32(0), &TupleValue::uint32(49), |x| values.push(x)).unwrap();
+        assert_eq!(values.len(), 50);
+        assert_eq!(values[49], b"value-49".to_vec());
+    }
+}
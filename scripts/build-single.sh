@@ -0,0 +1,44 @@
+#!/bin/bash
+
+#build a single flare_server binary with embedded agent libs, attacher and UI static files,
+#deploy to a production host by copying one file
+
+#cargo params
+#compile with crt-static, making bin file without depends vcruntime dll
+export RUSTFLAGS="-Awarnings -C target-feature=+crt-static"
+
+if [[ "$OSTYPE" == "cygwin" || "$OSTYPE" == "msys" ]]; then
+  CARGO_OPTS="--target x86_64-pc-windows-msvc"
+  TARGET_PATH="x86_64-pc-windows-msvc/release"
+  BIN_NAME="flare_server.exe"
+else
+  CARGO_OPTS=""
+  TARGET_PATH="release"
+  BIN_NAME="flare_server"
+fi
+
+PROJECT_PATH="$(cd "$(dirname $0)/.."; pwd -P )"
+DIST_DIR="$PROJECT_PATH/target/flare-profiler"
+
+#build agent libs and attacher into dist dir
+$PROJECT_PATH/scripts/build-agent.sh $@
+if [[ $? != 0 ]];then
+   echo "exec build-agent.sh failed."
+   exit 1
+fi
+
+#copy simpleui
+mkdir -p $DIST_DIR/res/static
+cp -r $PROJECT_PATH/flare-server/static/simpleui/* $DIST_DIR/res/static/
+
+#build flare-server with embedded files
+echo "build single flare-server binary .."
+cd $PROJECT_PATH/flare-server
+FLARE_EMBED_DIR=$DIST_DIR cargo build $CARGO_OPTS --release --features embedded
+if [[ $? != 0 ]];then
+   echo "build flare server failed."
+   exit 1
+fi
+
+cp $PROJECT_PATH/flare-server/target/$TARGET_PATH/$BIN_NAME $PROJECT_PATH/target/
+echo "single binary: $PROJECT_PATH/target/$BIN_NAME"